@@ -0,0 +1,79 @@
+//! Measures the time taken and memory allocated when parsing some larger source files.
+//!
+//! Run with `cargo bench -p protox-parse`.
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    hint::black_box,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::{Duration, Instant},
+};
+
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+static ALLOCATED_BYTES: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        ALLOCATED_BYTES.fetch_add(layout.size(), Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        ALLOCATED_BYTES.fetch_add(new_size, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+const ITERATIONS: u32 = 200;
+
+const FILES: &[(&str, &str)] = &[
+    (
+        "descriptor.proto",
+        include_str!("../../protox/protobuf/src/google/protobuf/descriptor.proto"),
+    ),
+    (
+        "google_unittest_custom_options.proto",
+        include_str!("../../protox/tests/data/google_unittest_custom_options.proto"),
+    ),
+];
+
+fn main() {
+    println!(
+        "{:<40} {:>12} {:>14} {:>14}",
+        "file", "time/iter", "allocs/iter", "bytes/iter"
+    );
+
+    for &(name, source) in FILES {
+        // Warm up, and check the file actually parses.
+        protox_parse::parse(name, source).unwrap();
+
+        let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+        let allocated_bytes = ALLOCATED_BYTES.load(Ordering::Relaxed);
+        let start = Instant::now();
+        for _ in 0..ITERATIONS {
+            black_box(protox_parse::parse(name, black_box(source)).unwrap());
+        }
+        let elapsed = start.elapsed();
+        let allocations = ALLOCATIONS.load(Ordering::Relaxed) - allocations;
+        let allocated_bytes = ALLOCATED_BYTES.load(Ordering::Relaxed) - allocated_bytes;
+
+        println!(
+            "{:<40} {:>12?} {:>14} {:>14}",
+            name,
+            Duration::from_nanos((elapsed.as_nanos() / u128::from(ITERATIONS)) as u64),
+            allocations / ITERATIONS as usize,
+            allocated_bytes / ITERATIONS as usize,
+        );
+    }
+}
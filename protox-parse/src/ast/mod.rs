@@ -1,4 +1,5 @@
 use std::{
+    borrow::Cow,
     convert::TryFrom,
     fmt::{self, Write},
     ops::Range,
@@ -16,46 +17,46 @@ pub(crate) enum Syntax {
 }
 
 #[derive(Default, Clone, Debug, PartialEq)]
-pub(crate) struct File {
+pub(crate) struct File<'a> {
     pub span: Span,
     pub syntax: Syntax,
-    pub syntax_span: std::option::Option<(Span, Comments)>,
-    pub package: std::option::Option<Package>,
-    pub imports: Vec<Import>,
-    pub options: Vec<Option>,
-    pub items: Vec<FileItem>,
+    pub syntax_span: std::option::Option<(Span, Comments<'a>)>,
+    pub package: std::option::Option<Package<'a>>,
+    pub imports: Vec<Import<'a>>,
+    pub options: Vec<Option<'a>>,
+    pub items: Vec<FileItem<'a>>,
 }
 
 #[derive(Clone, Debug, PartialEq)]
-pub(crate) enum FileItem {
-    Enum(Enum),
-    Message(Message),
-    Extend(Extend),
-    Service(Service),
+pub(crate) enum FileItem<'a> {
+    Enum(Enum<'a>),
+    Message(Message<'a>),
+    Extend(Extend<'a>),
+    Service(Service<'a>),
 }
 
 #[derive(Clone, Default, Debug, PartialEq)]
-pub(crate) struct Comments {
-    pub leading_detached_comments: Vec<std::string::String>,
-    pub leading_comment: std::option::Option<std::string::String>,
-    pub trailing_comment: std::option::Option<std::string::String>,
+pub(crate) struct Comments<'a> {
+    pub leading_detached_comments: Vec<Cow<'a, str>>,
+    pub leading_comment: std::option::Option<Cow<'a, str>>,
+    pub trailing_comment: std::option::Option<Cow<'a, str>>,
 }
 
 #[derive(Clone, Debug, PartialEq)]
-pub(crate) struct Ident {
-    pub value: std::string::String,
+pub(crate) struct Ident<'a> {
+    pub value: Cow<'a, str>,
     pub span: Span,
 }
 
 #[derive(Clone, Debug, PartialEq)]
-pub(crate) struct FullIdent {
-    pub parts: Vec<Ident>,
+pub(crate) struct FullIdent<'a> {
+    pub parts: Vec<Ident<'a>>,
 }
 
 #[derive(Clone, Debug, PartialEq)]
-pub(crate) struct TypeName {
+pub(crate) struct TypeName<'a> {
     pub leading_dot: std::option::Option<Span>,
-    pub name: FullIdent,
+    pub name: FullIdent<'a>,
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -72,30 +73,30 @@ pub(crate) struct Float {
 }
 
 #[derive(Clone, PartialEq)]
-pub(crate) struct String {
-    pub value: Vec<u8>,
+pub(crate) struct String<'a> {
+    pub value: Cow<'a, [u8]>,
     pub span: Span,
 }
 
 #[derive(Clone, Debug, PartialEq)]
-pub(crate) enum OptionValue {
+pub(crate) enum OptionValue<'a> {
     Ident {
         negative: bool,
-        ident: Ident,
+        ident: Ident<'a>,
         span: Span,
     },
     Int(Int),
     Float(Float),
-    String(String),
-    Aggregate(std::string::String, Span),
+    String(String<'a>),
+    Aggregate(Cow<'a, str>, Span),
 }
 
 #[derive(Clone, Debug, PartialEq)]
-pub(crate) struct Import {
+pub(crate) struct Import<'a> {
     pub kind: std::option::Option<(ImportKind, Span)>,
-    pub value: std::string::String,
+    pub value: Cow<'a, str>,
     pub value_span: Span,
-    pub comments: Comments,
+    pub comments: Comments<'a>,
     pub span: Span,
 }
 
@@ -106,53 +107,53 @@ pub(crate) enum ImportKind {
 }
 
 #[derive(Clone, Debug, PartialEq)]
-pub(crate) struct Package {
-    pub name: FullIdent,
-    pub comments: Comments,
+pub(crate) struct Package<'a> {
+    pub name: FullIdent<'a>,
+    pub comments: Comments<'a>,
     pub span: Span,
 }
 
 #[derive(Clone, Debug, PartialEq)]
-pub(crate) struct Option {
-    pub body: OptionBody,
-    pub comments: Comments,
+pub(crate) struct Option<'a> {
+    pub body: OptionBody<'a>,
+    pub comments: Comments<'a>,
     pub span: Span,
 }
 
 #[derive(Clone, Debug, PartialEq)]
-pub(crate) enum OptionNamePart {
-    Ident(Ident),
-    Extension(TypeName, Span),
+pub(crate) enum OptionNamePart<'a> {
+    Ident(Ident<'a>),
+    Extension(TypeName<'a>, Span),
 }
 
 #[derive(Clone, Debug, PartialEq)]
-pub(crate) struct OptionBody {
-    pub name: Vec<OptionNamePart>,
-    pub value: OptionValue,
+pub(crate) struct OptionBody<'a> {
+    pub name: Vec<OptionNamePart<'a>>,
+    pub value: OptionValue<'a>,
 }
 
 #[derive(Clone, Default, Debug, PartialEq)]
-pub(crate) struct OptionList {
-    pub options: Vec<OptionBody>,
+pub(crate) struct OptionList<'a> {
+    pub options: Vec<OptionBody<'a>>,
     pub span: Span,
 }
 
 #[derive(Clone, Debug, PartialEq)]
-pub(crate) struct Message {
-    pub name: Ident,
-    pub body: MessageBody,
-    pub comments: Comments,
+pub(crate) struct Message<'a> {
+    pub name: Ident<'a>,
+    pub body: MessageBody<'a>,
+    pub comments: Comments<'a>,
     pub span: Span,
 }
 
 #[derive(Clone, Debug, PartialEq)]
-pub(crate) struct Field {
+pub(crate) struct Field<'a> {
     pub label: std::option::Option<(FieldLabel, Span)>,
-    pub name: Ident,
-    pub kind: FieldKind,
+    pub name: Ident<'a>,
+    pub kind: FieldKind<'a>,
     pub number: Int,
-    pub options: std::option::Option<OptionList>,
-    pub comments: Comments,
+    pub options: std::option::Option<OptionList<'a>>,
+    pub comments: Comments<'a>,
     pub span: Span,
 }
 
@@ -164,43 +165,43 @@ pub(crate) enum FieldLabel {
 }
 
 #[derive(Debug, Default, Clone, PartialEq)]
-pub(crate) struct MessageBody {
-    pub items: Vec<MessageItem>,
-    pub extensions: Vec<Extensions>,
-    pub options: Vec<Option>,
-    pub reserved: Vec<Reserved>,
+pub(crate) struct MessageBody<'a> {
+    pub items: Vec<MessageItem<'a>>,
+    pub extensions: Vec<Extensions<'a>>,
+    pub options: Vec<Option<'a>>,
+    pub reserved: Vec<Reserved<'a>>,
 }
 
 #[derive(Debug, Clone, PartialEq)]
-pub(crate) enum MessageItem {
-    Field(Field),
-    Enum(Enum),
-    Message(Message),
-    Extend(Extend),
-    Oneof(Oneof),
+pub(crate) enum MessageItem<'a> {
+    Field(Field<'a>),
+    Enum(Enum<'a>),
+    Message(Message<'a>),
+    Extend(Extend<'a>),
+    Oneof(Oneof<'a>),
 }
 
 #[derive(Clone, Debug, PartialEq)]
-pub(crate) enum FieldKind {
+pub(crate) enum FieldKind<'a> {
     Normal {
-        ty: Ty,
+        ty: Ty<'a>,
         ty_span: Span,
     },
     Group {
         ty_span: Span,
-        body: MessageBody,
+        body: MessageBody<'a>,
     },
     Map {
         ty_span: Span,
-        key_ty: Ty,
+        key_ty: Ty<'a>,
         key_ty_span: Span,
-        value_ty: Ty,
+        value_ty: Ty<'a>,
         value_ty_span: Span,
     },
 }
 
 #[derive(Clone, Debug, PartialEq)]
-pub(crate) enum Ty {
+pub(crate) enum Ty<'a> {
     Double,
     Float,
     Int32,
@@ -216,45 +217,45 @@ pub(crate) enum Ty {
     Bool,
     String,
     Bytes,
-    Named(TypeName),
+    Named(TypeName<'a>),
 }
 
 #[derive(Clone, Debug, PartialEq)]
-pub(crate) struct Oneof {
-    pub name: Ident,
-    pub options: Vec<Option>,
-    pub fields: Vec<Field>,
-    pub comments: Comments,
+pub(crate) struct Oneof<'a> {
+    pub name: Ident<'a>,
+    pub options: Vec<Option<'a>>,
+    pub fields: Vec<Field<'a>>,
+    pub comments: Comments<'a>,
     pub span: Span,
 }
 
 #[derive(Clone, Debug, PartialEq)]
-pub(crate) struct Extend {
-    pub extendee: TypeName,
-    pub fields: Vec<Field>,
-    pub comments: Comments,
+pub(crate) struct Extend<'a> {
+    pub extendee: TypeName<'a>,
+    pub fields: Vec<Field<'a>>,
+    pub comments: Comments<'a>,
     pub span: Span,
 }
 
 #[derive(Clone, Debug, PartialEq)]
-pub(crate) struct Reserved {
-    pub kind: ReservedKind,
-    pub comments: Comments,
+pub(crate) struct Reserved<'a> {
+    pub kind: ReservedKind<'a>,
+    pub comments: Comments<'a>,
     pub span: Span,
 }
 
 #[derive(Clone, Debug, PartialEq)]
-pub(crate) struct Extensions {
+pub(crate) struct Extensions<'a> {
     pub ranges: Vec<ReservedRange>,
-    pub options: std::option::Option<OptionList>,
-    pub comments: Comments,
+    pub options: std::option::Option<OptionList<'a>>,
+    pub comments: Comments<'a>,
     pub span: Span,
 }
 
 #[derive(Clone, Debug, PartialEq)]
-pub(crate) enum ReservedKind {
+pub(crate) enum ReservedKind<'a> {
     Ranges(Vec<ReservedRange>),
-    Names(Vec<Ident>),
+    Names(Vec<Ident<'a>>),
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -271,42 +272,43 @@ pub(crate) enum ReservedRangeEnd {
 }
 
 #[derive(Clone, Debug, PartialEq)]
-pub(crate) struct Enum {
-    pub name: Ident,
-    pub options: Vec<Option>,
-    pub values: Vec<EnumValue>,
-    pub reserved: Vec<Reserved>,
-    pub comments: Comments,
+pub(crate) struct Enum<'a> {
+    pub name: Ident<'a>,
+    pub options: Vec<Option<'a>>,
+    pub values: Vec<EnumValue<'a>>,
+    pub reserved: Vec<Reserved<'a>>,
+    pub comments: Comments<'a>,
     pub span: Span,
 }
 
 #[derive(Clone, Debug, PartialEq)]
-pub(crate) struct EnumValue {
-    pub name: Ident,
+pub(crate) struct EnumValue<'a> {
+    pub name: Ident<'a>,
     pub number: Int,
-    pub options: std::option::Option<OptionList>,
-    pub comments: Comments,
+    pub options: std::option::Option<OptionList<'a>>,
+    pub comments: Comments<'a>,
     pub span: Span,
 }
 
 #[derive(Clone, Debug, PartialEq)]
-pub(crate) struct Service {
-    pub name: Ident,
-    pub options: Vec<Option>,
-    pub methods: Vec<Method>,
-    pub comments: Comments,
+pub(crate) struct Service<'a> {
+    pub name: Ident<'a>,
+    pub options: Vec<Option<'a>>,
+    pub methods: Vec<Method<'a>>,
+    pub comments: Comments<'a>,
     pub span: Span,
 }
 
 #[derive(Clone, Debug, PartialEq)]
-pub(crate) struct Method {
-    pub name: Ident,
-    pub input_ty: TypeName,
-    pub output_ty: TypeName,
-    pub options: Vec<Option>,
+pub(crate) struct Method<'a> {
+    pub name: Ident<'a>,
+    pub input_ty: TypeName<'a>,
+    pub output_ty: TypeName<'a>,
+    /// `None` if the method has no body, as opposed to an empty body.
+    pub options: std::option::Option<Vec<Option<'a>>>,
     pub client_streaming: std::option::Option<Span>,
     pub server_streaming: std::option::Option<Span>,
-    pub comments: Comments,
+    pub comments: Comments<'a>,
     pub span: Span,
 }
 
@@ -352,20 +354,29 @@ impl Int {
     }
 }
 
-impl String {
-    pub fn into_utf8(self) -> Result<(std::string::String, Span), Self> {
-        match std::string::String::from_utf8(self.value) {
-            Ok(string) => Ok((string, self.span)),
-            Err(err) => Err(String {
-                value: err.into_bytes(),
-                span: self.span,
-            }),
+impl<'a> String<'a> {
+    pub fn into_utf8(self) -> Result<(Cow<'a, str>, Span), Self> {
+        match self.value {
+            Cow::Borrowed(bytes) => match std::str::from_utf8(bytes) {
+                Ok(string) => Ok((Cow::Borrowed(string), self.span)),
+                Err(_) => Err(String {
+                    value: Cow::Borrowed(bytes),
+                    span: self.span,
+                }),
+            },
+            Cow::Owned(bytes) => match std::string::String::from_utf8(bytes) {
+                Ok(string) => Ok((Cow::Owned(string), self.span)),
+                Err(err) => Err(String {
+                    value: Cow::Owned(err.into_bytes()),
+                    span: self.span,
+                }),
+            },
         }
     }
 }
 
-impl Ident {
-    pub fn new(value: impl Into<std::string::String>, span: Range<usize>) -> Self {
+impl<'a> Ident<'a> {
+    pub fn new(value: impl Into<Cow<'a, str>>, span: Range<usize>) -> Self {
         Ident {
             span,
             value: value.into(),
@@ -373,13 +384,13 @@ impl Ident {
     }
 }
 
-impl FullIdent {
+impl FullIdent<'_> {
     pub fn span(&self) -> Span {
         self.parts.first().unwrap().span.start..self.parts.last().unwrap().span.end
     }
 }
 
-impl TypeName {
+impl TypeName<'_> {
     pub fn span(&self) -> Span {
         if let Some(leading_dot) = &self.leading_dot {
             join_span(leading_dot.clone(), self.name.span())
@@ -389,7 +400,7 @@ impl TypeName {
     }
 }
 
-impl Ty {
+impl Ty<'_> {
     pub fn proto_ty(&self) -> std::option::Option<field_descriptor_proto::Type> {
         match self {
             Ty::Double => Some(field_descriptor_proto::Type::Double),
@@ -419,7 +430,7 @@ impl Ty {
     }
 }
 
-impl OptionNamePart {
+impl OptionNamePart<'_> {
     pub fn span(&self) -> Span {
         match self {
             OptionNamePart::Ident(ident) => ident.span.clone(),
@@ -428,7 +439,7 @@ impl OptionNamePart {
     }
 }
 
-impl OptionBody {
+impl OptionBody<'_> {
     pub fn has_name(&self, name: &str) -> bool {
         matches!(self.name.as_slice(), [OptionNamePart::Ident(ident)] if ident.value == name)
     }
@@ -446,7 +457,7 @@ impl OptionBody {
     }
 }
 
-impl OptionValue {
+impl OptionValue<'_> {
     pub fn span(&self) -> Span {
         match self {
             OptionValue::Ident { span, .. } => span.clone(),
@@ -530,14 +541,14 @@ impl ReservedRange {
     }
 }
 
-impl From<Ident> for FullIdent {
-    fn from(part: Ident) -> Self {
+impl<'a> From<Ident<'a>> for FullIdent<'a> {
+    fn from(part: Ident<'a>) -> Self {
         FullIdent { parts: vec![part] }
     }
 }
 
-impl From<Vec<Ident>> for FullIdent {
-    fn from(parts: Vec<Ident>) -> Self {
+impl<'a> From<Vec<Ident<'a>>> for FullIdent<'a> {
+    fn from(parts: Vec<Ident<'a>>) -> Self {
         debug_assert!(!parts.is_empty());
         FullIdent { parts }
     }
@@ -552,13 +563,13 @@ impl fmt::Display for Syntax {
     }
 }
 
-impl fmt::Display for Ident {
+impl fmt::Display for Ident<'_> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "{}", self.value)
     }
 }
 
-impl fmt::Display for FullIdent {
+impl fmt::Display for FullIdent<'_> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "{}", self.parts[0])?;
         for part in &self.parts[1..] {
@@ -568,7 +579,7 @@ impl fmt::Display for FullIdent {
     }
 }
 
-impl fmt::Display for TypeName {
+impl fmt::Display for TypeName<'_> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         if self.leading_dot.is_some() {
             write!(f, ".")?;
@@ -577,7 +588,7 @@ impl fmt::Display for TypeName {
     }
 }
 
-impl fmt::Display for OptionValue {
+impl fmt::Display for OptionValue<'_> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             OptionValue::Ident {
@@ -617,7 +628,7 @@ impl fmt::Display for Float {
     }
 }
 
-impl fmt::Debug for String {
+impl fmt::Debug for String<'_> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let value = self.to_string();
         f.debug_struct("String")
@@ -627,9 +638,9 @@ impl fmt::Debug for String {
     }
 }
 
-impl fmt::Display for String {
+impl fmt::Display for String<'_> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        for &ch in self.value.as_slice() {
+        for &ch in self.value.iter() {
             match ch {
                 b'\t' => f.write_str("\\t")?,
                 b'\r' => f.write_str("\\r")?,
@@ -656,14 +667,14 @@ fn option_span() {
                 TypeName {
                     leading_dot: Some(1..2),
                     name: FullIdent::from(Ident {
-                        value: "ext".to_owned(),
+                        value: "ext".into(),
                         span: 2..5,
                     }),
                 },
                 0..6,
             ),
             OptionNamePart::Ident(Ident {
-                value: "ident".to_owned(),
+                value: "ident".into(),
                 span: 7..12,
             }),
         ],
@@ -1,3 +1,20 @@
+//! Case conversions used for protobuf names.
+//!
+//! [`to_json_name()`] and [`to_pascal_case()`] match the conversions used by protoc to generate
+//! JSON names, and the names of map entry messages and group fields. [`to_snake_case()`] and
+//! [`to_upper_camel_case()`] match the conversions used by `prost-build` to generate Rust field
+//! and type names, before escaping any names which are Rust keywords.
+//!
+//! # Examples
+//!
+//! ```
+//! # use protox_parse::case::{to_json_name, to_pascal_case, to_snake_case, to_upper_camel_case};
+//! assert_eq!(to_json_name("foo_bar_2"), "fooBar2");
+//! assert_eq!(to_pascal_case("foo_bar"), "FooBar");
+//! assert_eq!(to_snake_case("HTTPServerV2"), "http_server_v2");
+//! assert_eq!(to_upper_camel_case("http_server"), "HttpServer");
+//! ```
+
 pub(crate) fn is_valid_ident(s: &str) -> bool {
     !s.is_empty()
         && s.as_bytes()[0].is_ascii_alphabetic()
@@ -14,7 +31,51 @@ pub(crate) fn is_valid_group_name(s: &str) -> bool {
             .all(|&ch| ch.is_ascii_alphanumeric() || ch == b'_')
 }
 
-pub(crate) fn to_pascal_case(name: &str) -> String {
+/// Converts a field name to the default JSON name generated by protoc.
+///
+/// Underscores are removed, and the character following each underscore is converted to
+/// uppercase. All other characters are unchanged.
+///
+/// # Examples
+///
+/// ```
+/// # use protox_parse::case::to_json_name;
+/// assert_eq!(to_json_name("foo_bar"), "fooBar");
+/// assert_eq!(to_json_name("_foo__bar_"), "FooBar");
+/// assert_eq!(to_json_name("FOO_BAR"), "FOOBAR");
+/// ```
+pub fn to_json_name(name: &str) -> String {
+    let mut result = String::with_capacity(name.len());
+    let mut uppercase_next = false;
+
+    for ch in name.chars() {
+        if ch == '_' {
+            uppercase_next = true;
+        } else if uppercase_next {
+            result.push(ch.to_ascii_uppercase());
+            uppercase_next = false;
+        } else {
+            result.push(ch);
+        }
+    }
+
+    result
+}
+
+/// Converts a name to pascal case in the same way as protoc.
+///
+/// This is used to derive the name of the entry message of a map field, and the name of the
+/// message defined by a group field. It is equivalent to [`to_json_name()`], except that the first
+/// character is also converted to uppercase.
+///
+/// # Examples
+///
+/// ```
+/// # use protox_parse::case::to_pascal_case;
+/// assert_eq!(to_pascal_case("map_field"), "MapField");
+/// assert_eq!(to_pascal_case("fooBAR"), "FooBAR");
+/// ```
+pub fn to_pascal_case(name: &str) -> String {
     let mut result = String::with_capacity(name.len());
     let mut uppercase_next = true;
 
@@ -31,3 +92,100 @@ pub(crate) fn to_pascal_case(name: &str) -> String {
 
     result
 }
+
+/// Converts a name to snake case in the same way as `prost-build` does for field and module names.
+///
+/// The name is split into words at underscores and other non-alphanumeric characters, between a
+/// lowercase character and a following uppercase character, and before the last character of a run
+/// of uppercase characters followed by a lowercase character. Each word is then converted to
+/// lowercase.
+///
+/// # Examples
+///
+/// ```
+/// # use protox_parse::case::to_snake_case;
+/// assert_eq!(to_snake_case("fooBar"), "foo_bar");
+/// assert_eq!(to_snake_case("XMLHttpRequest"), "xml_http_request");
+/// assert_eq!(to_snake_case("__foo__bar"), "foo_bar");
+/// ```
+pub fn to_snake_case(name: &str) -> String {
+    let mut result = String::with_capacity(name.len() + 4);
+    for word in words(name) {
+        if !result.is_empty() {
+            result.push('_');
+        }
+        result.extend(word.chars().flat_map(char::to_lowercase));
+    }
+    result
+}
+
+/// Converts a name to upper camel case in the same way as `prost-build` does for message, enum
+/// and enum value names.
+///
+/// The name is split into words as described for [`to_snake_case()`], then the first character of
+/// each word is converted to uppercase and the remaining characters to lowercase.
+///
+/// # Examples
+///
+/// ```
+/// # use protox_parse::case::to_upper_camel_case;
+/// assert_eq!(to_upper_camel_case("foo_bar"), "FooBar");
+/// assert_eq!(to_upper_camel_case("FOO_BAR"), "FooBar");
+/// assert_eq!(to_upper_camel_case("XMLHttpRequest"), "XmlHttpRequest");
+/// ```
+pub fn to_upper_camel_case(name: &str) -> String {
+    let mut result = String::with_capacity(name.len());
+    for word in words(name) {
+        let mut chars = word.chars();
+        if let Some(first) = chars.next() {
+            result.extend(first.to_uppercase());
+            result.extend(chars.flat_map(char::to_lowercase));
+        }
+    }
+    result
+}
+
+/// Splits a name into words, using the same rules as the `heck` crate.
+fn words(name: &str) -> Vec<&str> {
+    #[derive(Clone, Copy, PartialEq)]
+    enum Mode {
+        Boundary,
+        Lowercase,
+        Uppercase,
+    }
+
+    let mut words = Vec::new();
+    for part in name.split(|ch: char| !ch.is_alphanumeric()) {
+        let mut chars = part.char_indices().peekable();
+        let mut start = 0;
+        let mut mode = Mode::Boundary;
+
+        while let Some((index, ch)) = chars.next() {
+            let Some(&(next_index, next)) = chars.peek() else {
+                words.push(&part[start..]);
+                break;
+            };
+
+            let next_mode = if ch.is_lowercase() {
+                Mode::Lowercase
+            } else if ch.is_uppercase() {
+                Mode::Uppercase
+            } else {
+                mode
+            };
+
+            if next_mode == Mode::Lowercase && next.is_uppercase() {
+                words.push(&part[start..next_index]);
+                start = next_index;
+                mode = Mode::Boundary;
+            } else if mode == Mode::Uppercase && ch.is_uppercase() && next.is_lowercase() {
+                words.push(&part[start..index]);
+                start = index;
+                mode = Mode::Boundary;
+            } else {
+                mode = next_mode;
+            }
+        }
+    }
+    words
+}
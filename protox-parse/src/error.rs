@@ -4,7 +4,10 @@ use logos::Span;
 use miette::{Diagnostic, NamedSource, SourceCode};
 use thiserror::Error;
 
-use crate::MAX_MESSAGE_FIELD_NUMBER;
+use crate::{
+    fix::{with_trailing_whitespace, Edit, Fix},
+    MAX_MESSAGE_FIELD_NUMBER,
+};
 
 /// An error that may occur while parsing a protobuf source file.
 #[derive(Error, Diagnostic)]
@@ -130,6 +133,12 @@ pub(crate) enum ParseErrorKind {
         #[label("defined here")]
         span: Span,
     },
+    #[error("{kind} range end number must be greater than start number")]
+    InvalidRangeEnd {
+        kind: &'static str,
+        #[label("defined here")]
+        span: Span,
+    },
     #[error("{kind} fields may not have default values")]
     InvalidDefault {
         kind: &'static str,
@@ -205,11 +214,32 @@ pub(crate) enum ParseErrorKind {
         #[label("defined here")]
         span: Span,
     },
+    #[error("'map_entry' should not be set explicitly")]
+    #[diagnostic(help("use 'map<KeyType, ValueType>' instead"))]
+    MapEntryOptionSet {
+        #[label("set here")]
+        span: Span,
+    },
     #[error("a oneof must have at least one field")]
     EmptyOneof {
         #[label("defined here")]
         span: Span,
     },
+    #[error("oneofs cannot be nested")]
+    #[diagnostic(help("move the fields of the inner oneof into the outer oneof"))]
+    NestedOneof {
+        #[label("defined here")]
+        span: Span,
+    },
+    #[error("messages are nested more than {limit} levels deep")]
+    #[diagnostic(help(
+        "reduce the nesting of message and group definitions, or of message values in options"
+    ))]
+    NestingTooDeep {
+        limit: u32,
+        #[label("defined here")]
+        span: Span,
+    },
     #[error("file is too large")]
     #[diagnostic(help("the maximum file length is 2,147,483,647 bytes"))]
     FileTooLarge,
@@ -237,9 +267,27 @@ impl ParseError {
         self.source_code.name()
     }
 
+    /// Gets machine-applicable fixes for this error, and any related errors in the same file.
+    ///
+    /// Fixes are available for errors with a single obvious resolution, such as a proto2 field missing its label or
+    /// a label which is not allowed. The edits of every fix refer to offsets in the original source code.
+    pub fn fixes(&self) -> Vec<Fix> {
+        let source = self.source_code.inner();
+        std::iter::once(&*self.kind)
+            .chain(&self.related)
+            .filter_map(|kind| kind.fix(source))
+            .collect()
+    }
+
     /// Gets the primary source code span associated with this error, if any.
     pub fn span(&self) -> Option<Range<usize>> {
-        match &*self.kind {
+        self.kind.span()
+    }
+}
+
+impl ParseErrorKind {
+    fn span(&self) -> Option<Span> {
+        match self {
             ParseErrorKind::InvalidToken { span } => Some(span.clone()),
             ParseErrorKind::IntegerOutOfRange { span } => Some(span.clone()),
             ParseErrorKind::InvalidStringCharacters { span } => Some(span.clone()),
@@ -260,6 +308,7 @@ impl ParseError {
             ParseErrorKind::NegativeIdentOutsideDefault { span } => Some(span.clone()),
             ParseErrorKind::InvalidMessageNumber { span } => Some(span.clone()),
             ParseErrorKind::InvalidEnumNumber { span } => Some(span.clone()),
+            ParseErrorKind::InvalidRangeEnd { span, .. } => Some(span.clone()),
             ParseErrorKind::InvalidDefault { span, .. } => Some(span.clone()),
             ParseErrorKind::Proto3DefaultValue { span } => Some(span.clone()),
             ParseErrorKind::InvalidExtendFieldKind { span, .. } => Some(span.clone()),
@@ -273,10 +322,49 @@ impl ParseError {
             ParseErrorKind::InvalidMapFieldKeyType { span } => Some(span.clone()),
             ParseErrorKind::ValueInvalidType { span, .. } => Some(span.clone()),
             ParseErrorKind::IntegerValueOutOfRange { span, .. } => Some(span.clone()),
+            ParseErrorKind::MapEntryOptionSet { span } => Some(span.clone()),
             ParseErrorKind::EmptyOneof { span } => Some(span.clone()),
+            ParseErrorKind::NestedOneof { span } => Some(span.clone()),
+            ParseErrorKind::NestingTooDeep { span, .. } => Some(span.clone()),
             ParseErrorKind::FileTooLarge => None,
         }
     }
+
+    /// Gets the offset used to order errors by position. Errors without a span, such as reaching the end of the
+    /// file, are ordered last.
+    fn offset(&self) -> usize {
+        match self {
+            ParseErrorKind::DuplicatePackage { second, .. } => second.start,
+            kind => kind.span().map_or(usize::MAX, |span| span.start),
+        }
+    }
+
+    fn fix(&self, source: &str) -> Option<Fix> {
+        match self {
+            ParseErrorKind::Proto2FieldMissingLabel { span } => Some(Fix::new(
+                "add 'optional' label",
+                vec![Edit::insert(span.start, "optional ")],
+            )),
+            ParseErrorKind::MapFieldWithLabel { span }
+            | ParseErrorKind::OneofFieldWithLabel { span }
+            | ParseErrorKind::Proto3RequiredField { span } => Some(Fix::new(
+                "remove label",
+                vec![Edit::delete(with_trailing_whitespace(source, span.clone()))],
+            )),
+            ParseErrorKind::RequiredExtendField { span } => Some(Fix::new(
+                "replace 'required' with 'optional'",
+                vec![Edit::new(span.clone(), "optional")],
+            )),
+            ParseErrorKind::DuplicatePackage { second, .. } => Some(Fix::new(
+                "remove duplicate package",
+                vec![Edit::delete(with_trailing_whitespace(
+                    source,
+                    second.clone(),
+                ))],
+            )),
+            _ => None,
+        }
+    }
 }
 
 impl fmt::Debug for ParseError {
@@ -299,3 +387,40 @@ impl fmt::Debug for ParseError {
         write!(f, "{}", self)
     }
 }
+
+/// Removes errors which are likely to be caused by an earlier error: those whose span overlaps an earlier error, and
+/// invalid tokens immediately following another invalid token.
+pub(crate) fn dedupe_cascading(errors: &mut Vec<ParseErrorKind>) {
+    let mut spans: Vec<Span> = Vec::with_capacity(errors.len());
+    let mut previous_invalid_token: Option<Span> = None;
+    errors.retain(|err| {
+        let Some(span) = err.span() else {
+            return true;
+        };
+
+        let is_invalid_token = matches!(err, ParseErrorKind::InvalidToken { .. });
+        let follows_invalid_token = is_invalid_token
+            && previous_invalid_token
+                .as_ref()
+                .is_some_and(|previous| previous.end == span.start);
+        if is_invalid_token {
+            previous_invalid_token = Some(span.clone());
+        }
+
+        if follows_invalid_token
+            || spans
+                .iter()
+                .any(|other| other.start < span.end && span.start < other.end)
+        {
+            return false;
+        }
+        spans.push(span);
+        true
+    });
+}
+
+/// Orders errors by the position of their primary span. The sort is stable, so errors at the same position keep the
+/// order they were reported in.
+pub(crate) fn sort_by_span(errors: &mut [ParseErrorKind]) {
+    errors.sort_by_key(ParseErrorKind::offset);
+}
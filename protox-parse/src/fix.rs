@@ -0,0 +1,114 @@
+//! Machine-applicable fixes for errors.
+//!
+//! See the documentation for [`ParseError::fixes()`](crate::ParseError::fixes()) for details.
+
+use std::ops::Range;
+
+/// A suggested change to a source file which resolves an error.
+///
+/// A fix consists of a short description, suitable for display in an editor, and one or more
+/// [`Edit`]s to the file in which the error occurred.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fix {
+    message: String,
+    edits: Vec<Edit>,
+}
+
+/// A replacement of a span of source code with new text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Edit {
+    span: Range<usize>,
+    replacement: String,
+}
+
+impl Fix {
+    /// Creates a new fix from a description and a list of edits.
+    ///
+    /// The edits must not overlap.
+    pub fn new(message: impl Into<String>, edits: Vec<Edit>) -> Self {
+        Fix {
+            message: message.into(),
+            edits,
+        }
+    }
+
+    /// Gets a short description of the fix, such as `"add 'optional' label"`.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    /// Gets the edits to apply to the source file.
+    pub fn edits(&self) -> &[Edit] {
+        &self.edits
+    }
+
+    /// Applies the edits to the source code of the file, returning the fixed source.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use protox_parse::parse;
+    /// let source = "syntax = 'proto2';\nmessage Foo {\n  int32 bar = 1;\n}\n";
+    /// let err = parse("foo.proto", source).unwrap_err();
+    /// let fixes = err.fixes();
+    ///
+    /// assert_eq!(fixes[0].message(), "add 'optional' label");
+    /// assert_eq!(
+    ///     fixes[0].apply(source),
+    ///     "syntax = 'proto2';\nmessage Foo {\n  optional int32 bar = 1;\n}\n",
+    /// );
+    /// ```
+    pub fn apply(&self, source: &str) -> String {
+        let mut edits: Vec<&Edit> = self.edits.iter().collect();
+        edits.sort_by_key(|edit| edit.span.start);
+
+        let mut result = String::with_capacity(source.len());
+        let mut offset = 0;
+        for edit in edits {
+            result.push_str(&source[offset..edit.span.start]);
+            result.push_str(&edit.replacement);
+            offset = edit.span.end;
+        }
+        result.push_str(&source[offset..]);
+        result
+    }
+}
+
+impl Edit {
+    /// Creates an edit which replaces the given span with new text.
+    pub fn new(span: Range<usize>, replacement: impl Into<String>) -> Self {
+        Edit {
+            span,
+            replacement: replacement.into(),
+        }
+    }
+
+    /// Creates an edit which inserts text at the given offset.
+    pub fn insert(offset: usize, text: impl Into<String>) -> Self {
+        Edit::new(offset..offset, text)
+    }
+
+    /// Creates an edit which deletes the given span.
+    pub fn delete(span: Range<usize>) -> Self {
+        Edit::new(span, "")
+    }
+
+    /// Gets the byte offsets of the source code to replace.
+    pub fn span(&self) -> Range<usize> {
+        self.span.clone()
+    }
+
+    /// Gets the text to replace the span with.
+    pub fn replacement(&self) -> &str {
+        &self.replacement
+    }
+}
+
+/// Extends the span of a token to remove with any whitespace following it on the same line.
+pub(crate) fn with_trailing_whitespace(source: &str, span: Range<usize>) -> Range<usize> {
+    let trailing = source[span.end..]
+        .bytes()
+        .take_while(|&b| b == b' ' || b == b'\t')
+        .count();
+    span.start..span.end + trailing
+}
@@ -0,0 +1,70 @@
+//! Formatting of floating-point default values, matching `SimpleDtoa` and `SimpleFtoa` in protoc.
+
+/// Formats a `double` default value using the shortest of 15 or 17 significant digits which round-trips.
+pub(crate) fn format_double(value: f64) -> String {
+    if let Some(special) = format_special(value) {
+        return special;
+    }
+
+    let short = format_general(value, f64::DIGITS as usize);
+    if short.parse::<f64>() == Ok(value) {
+        short
+    } else {
+        format_general(value, f64::DIGITS as usize + 2)
+    }
+}
+
+/// Formats a `float` default value using the shortest of 6 or 9 significant digits which round-trips.
+pub(crate) fn format_float(value: f32) -> String {
+    if let Some(special) = format_special(value.into()) {
+        return special;
+    }
+
+    let short = format_general(value.into(), f32::DIGITS as usize);
+    if short.parse::<f32>() == Ok(value) {
+        short
+    } else {
+        format_general(value.into(), f32::DIGITS as usize + 3)
+    }
+}
+
+fn format_special(value: f64) -> Option<String> {
+    if value.is_nan() {
+        Some("nan".to_owned())
+    } else if value == f64::INFINITY {
+        Some("inf".to_owned())
+    } else if value == f64::NEG_INFINITY {
+        Some("-inf".to_owned())
+    } else {
+        None
+    }
+}
+
+/// Formats a finite value like the C `%.*g` format specifier.
+fn format_general(value: f64, precision: usize) -> String {
+    // Round to the requested number of significant digits first, since this may change the exponent.
+    let scientific = format!("{:.*e}", precision - 1, value);
+    let (mantissa, exponent) = scientific.split_once('e').unwrap();
+    let exponent: i32 = exponent.parse().unwrap();
+
+    if exponent < -4 || exponent >= precision as i32 {
+        format!(
+            "{}e{}{:02}",
+            trim_fraction(mantissa),
+            if exponent < 0 { '-' } else { '+' },
+            exponent.unsigned_abs()
+        )
+    } else {
+        let decimals = (precision as i32 - 1 - exponent) as usize;
+        trim_fraction(&format!("{:.*}", decimals, value)).to_owned()
+    }
+}
+
+/// Removes trailing zeros after the decimal point, and the decimal point itself if nothing follows it.
+fn trim_fraction(number: &str) -> &str {
+    if number.contains('.') {
+        number.trim_end_matches('0').trim_end_matches('.')
+    } else {
+        number
+    }
+}
@@ -1,3 +1,5 @@
+use std::{borrow::Cow, collections::HashSet};
+
 use logos::Span;
 use prost_types::{
     descriptor_proto, enum_descriptor_proto, field_descriptor_proto, source_code_info::Location,
@@ -10,24 +12,24 @@ use prost_types::{
 
 use self::lines::LineResolver;
 use crate::{
-    ast, case::to_pascal_case, error::ParseErrorKind, index_to_i32, tag, MAX_MESSAGE_FIELD_NUMBER,
+    ast, case::to_pascal_case, error::ParseErrorKind, index_to_i32, tag, value::ParsedOption,
+    MAX_MESSAGE_FIELD_NUMBER,
 };
 
+mod float;
 mod lines;
 
 /// Convert the AST to a FileDescriptorProto, performing basic checks and generate group and map messages, and synthetic oneofs.
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "trace", name = "generate_descriptor", skip_all)
+)]
 pub(crate) fn generate_file(
     ast: ast::File,
     name: &str,
     source: &str,
 ) -> Result<FileDescriptorProto, Vec<ParseErrorKind>> {
-    let mut ctx = Context {
-        syntax: ast.syntax,
-        errors: vec![],
-        path: vec![],
-        locations: vec![],
-        lines: LineResolver::new(source),
-    };
+    let mut ctx = Context::new(ast.syntax, source);
 
     let file = ctx.generate_file_descriptor(name, ast);
 
@@ -45,12 +47,36 @@ pub(crate) fn generate_file(
     }
 }
 
-struct Context {
+/// Convert the AST to a FileDescriptorProto, and collect the values of all options and default values.
+pub(crate) fn generate_option_values(
+    ast: ast::File,
+    name: &str,
+    source: &str,
+) -> Result<Vec<ParsedOption>, Vec<ParseErrorKind>> {
+    let mut ctx = Context::new(ast.syntax, source);
+    ctx.option_values = Some(vec![]);
+
+    ctx.generate_file_descriptor(name, ast);
+
+    if ctx.errors.is_empty() {
+        Ok(ctx
+            .option_values
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(path, name_span, value)| ParsedOption::new(path, name_span, value, source))
+            .collect())
+    } else {
+        Err(ctx.errors)
+    }
+}
+
+struct Context<'a> {
     syntax: ast::Syntax,
     errors: Vec<ParseErrorKind>,
     path: Vec<i32>,
     locations: Vec<Location>,
     lines: LineResolver,
+    option_values: Option<Vec<(Vec<i32>, Span, ast::OptionValue<'a>)>>,
 }
 
 enum FieldScope {
@@ -59,8 +85,19 @@ enum FieldScope {
     Extend,
 }
 
-impl Context {
-    fn generate_file_descriptor(&mut self, name: &str, ast: ast::File) -> FileDescriptorProto {
+impl<'a> Context<'a> {
+    fn new(syntax: ast::Syntax, source: &str) -> Self {
+        Context {
+            syntax,
+            errors: vec![],
+            path: vec![],
+            locations: vec![],
+            lines: LineResolver::new(source),
+            option_values: None,
+        }
+    }
+
+    fn generate_file_descriptor(&mut self, name: &str, ast: ast::File<'a>) -> FileDescriptorProto {
         self.add_span(ast.span);
 
         let package = if let Some(package) = ast.package {
@@ -82,7 +119,7 @@ impl Context {
                 import.comments,
             );
 
-            dependency.push(import.value);
+            dependency.push(import.value.into_owned());
             match import.kind {
                 Some((ast::ImportKind::Public, span)) => {
                     self.add_span_for(
@@ -177,10 +214,10 @@ impl Context {
         }
     }
 
-    fn generate_message_descriptor(&mut self, ast: ast::Message) -> DescriptorProto {
+    fn generate_message_descriptor(&mut self, ast: ast::Message<'a>) -> DescriptorProto {
         self.add_comments(ast.span, ast.comments);
 
-        let name = Some(ast.name.value.to_string());
+        let name = Some(ast.name.value.into_owned());
         self.add_span_for(&[tag::message::NAME], ast.name.span);
 
         DescriptorProto {
@@ -189,7 +226,7 @@ impl Context {
         }
     }
 
-    fn generate_message_body_descriptor(&mut self, ast: ast::MessageBody) -> DescriptorProto {
+    fn generate_message_body_descriptor(&mut self, ast: ast::MessageBody<'a>) -> DescriptorProto {
         let mut field = Vec::new();
         let mut extension = Vec::new();
         let mut nested_type = Vec::new();
@@ -285,13 +322,32 @@ impl Context {
                     self.add_comments(reserved.span, reserved.comments);
                     for name in names {
                         self.add_span_for(&[index_to_i32(reserved_name.len())], name.span);
-                        reserved_name.push(name.value);
+                        reserved_name.push(name.value.into_owned());
                     }
                     self.path.pop();
                 }
             }
         }
 
+        // Like protoc, prefix synthetic oneof names with 'X' until they don't conflict with any field or
+        // real oneof in the message.
+        let mut names: HashSet<String> = field
+            .iter()
+            .map(|field| field.name().to_owned())
+            .chain(
+                oneof_decl[..real_oneof_count]
+                    .iter()
+                    .map(|oneof| oneof.name().to_owned()),
+            )
+            .collect();
+        for oneof in &mut oneof_decl[real_oneof_count..] {
+            let name = oneof.name.get_or_insert_with(String::new);
+            while names.contains(name.as_str()) {
+                name.insert(0, 'X');
+            }
+            names.insert(name.clone());
+        }
+
         self.path.push(tag::message::EXTENSION_RANGE);
         for extensions in ast.extensions {
             self.add_comments(extensions.span.clone(), extensions.comments);
@@ -308,6 +364,14 @@ impl Context {
         }
         self.path.pop();
 
+        for option in &ast.options {
+            if option.body.has_name("map_entry") {
+                self.errors.push(ParseErrorKind::MapEntryOptionSet {
+                    span: option.body.span(),
+                });
+            }
+        }
+
         self.path.push(tag::message::OPTIONS);
         let options = self.generate_options(ast.options);
         self.path.pop();
@@ -332,7 +396,7 @@ impl Context {
     #[allow(clippy::too_many_arguments)]
     fn generate_field_descriptor(
         &mut self,
-        mut ast: ast::Field,
+        mut ast: ast::Field<'a>,
         field_index: usize,
         field_tag: i32,
         message_tag: i32,
@@ -400,7 +464,7 @@ impl Context {
                 ty: ast::Ty::Named(ty),
                 ..
             } => {
-                name = ast.name.value;
+                name = ast.name.value.into_owned();
                 label = self.generate_field_label(ast.label, ast.span.clone(), scope);
                 r#type = None;
                 type_name = Some(ty.to_string());
@@ -410,16 +474,18 @@ impl Context {
                 if let Some(o) = default_value_option {
                     default_value = Some(o.value.to_token_string());
                     self.add_span_for(&[tag::field::DEFAULT_VALUE], o.value.span());
+                    self.add_option_value(&[tag::field::DEFAULT_VALUE], &o);
                 }
             }
             ast::FieldKind::Normal { ty, ty_span } => {
-                name = ast.name.value;
+                name = ast.name.value.into_owned();
                 label = self.generate_field_label(ast.label, ast.span.clone(), scope);
                 r#type = ty.proto_ty();
                 type_name = None;
 
                 if let Some(o) = default_value_option {
                     self.add_span_for(&[tag::field::DEFAULT_VALUE], o.value.span());
+                    self.add_option_value(&[tag::field::DEFAULT_VALUE], &o);
                     default_value = self.generate_field_default_value(r#type, o.value);
                 }
 
@@ -430,7 +496,7 @@ impl Context {
                 name = ast.name.value.to_ascii_lowercase();
                 label = self.generate_field_label(ast.label, ast.span.clone(), scope);
                 r#type = Some(field_descriptor_proto::Type::Group);
-                type_name = Some(ast.name.value);
+                type_name = Some(ast.name.value.into_owned());
 
                 if self.syntax != ast::Syntax::Proto2 {
                     self.errors.push(ParseErrorKind::Proto3GroupField {
@@ -468,7 +534,7 @@ impl Context {
                 value_ty,
                 ..
             } => {
-                name = ast.name.value;
+                name = ast.name.value.into_owned();
                 label = Some(field_descriptor_proto::Label::Repeated);
                 r#type = Some(field_descriptor_proto::Type::Message);
                 type_name = Some(to_pascal_case(&name) + "Entry");
@@ -623,16 +689,18 @@ impl Context {
     fn generate_field_default_value(
         &mut self,
         ty: Option<field_descriptor_proto::Type>,
-        value: ast::OptionValue,
+        value: ast::OptionValue<'a>,
     ) -> Option<String> {
         use field_descriptor_proto::Type;
 
         match (ty, value) {
-            (Some(Type::Double | Type::Float), value) => {
+            (Some(ty @ (Type::Double | Type::Float)), value) => {
                 if let Some(float) = value.as_f64() {
-                    let mut string = float.to_string();
-                    string.make_ascii_lowercase();
-                    Some(string)
+                    if ty == Type::Double {
+                        Some(float::format_double(float))
+                    } else {
+                        Some(float::format_float(float as f32))
+                    }
                 } else {
                     self.errors.push(ParseErrorKind::ValueInvalidType {
                         expected: "a floating-point number".to_owned(),
@@ -746,10 +814,10 @@ impl Context {
         }
     }
 
-    fn generate_string_option_value(&mut self, value: ast::OptionValue) -> Option<String> {
+    fn generate_string_option_value(&mut self, value: ast::OptionValue<'a>) -> Option<String> {
         match value {
             ast::OptionValue::String(string) => {
-                if let Ok(string) = String::from_utf8(string.value) {
+                if let Ok(string) = String::from_utf8(string.value.into_owned()) {
                     Some(string)
                 } else {
                     self.errors
@@ -779,6 +847,22 @@ impl Context {
         }
     }
 
+    /// Checks that the inclusive end of a range is not less than its start.
+    fn check_range_end(
+        &mut self,
+        kind: &'static str,
+        start: Option<i32>,
+        end: Option<i32>,
+        span: Span,
+    ) {
+        if let (Some(start), Some(end)) = (start, end) {
+            if end < start {
+                self.errors
+                    .push(ParseErrorKind::InvalidRangeEnd { kind, span });
+            }
+        }
+    }
+
     fn generate_message_reserved_range(
         &mut self,
         range: ast::ReservedRange,
@@ -788,6 +872,7 @@ impl Context {
         self.add_span_for(&[tag::message::reserved_range::START], range.start_span());
         self.add_span_for(&[tag::message::reserved_range::END], range.end_span());
 
+        let span = range.span();
         let start = self.generate_message_number(range.start);
         let end = match range.end {
             ast::ReservedRangeEnd::None => start,
@@ -798,6 +883,7 @@ impl Context {
                 MAX_MESSAGE_FIELD_NUMBER
             }),
         };
+        self.check_range_end("reserved", start, end, span);
 
         descriptor_proto::ReservedRange {
             start,
@@ -809,7 +895,7 @@ impl Context {
         &mut self,
         range: ast::ReservedRange,
         is_message_set: bool,
-        options: Option<ast::OptionList>,
+        options: Option<ast::OptionList<'a>>,
     ) -> descriptor_proto::ExtensionRange {
         self.add_span(range.span());
         self.add_span_for(&[tag::message::extension_range::START], range.start_span());
@@ -819,6 +905,7 @@ impl Context {
         let options = self.generate_options_list(options);
         self.path.pop();
 
+        let span = range.span();
         let start = self.generate_message_number(range.start);
         let end = match range.end {
             ast::ReservedRangeEnd::None => start,
@@ -829,6 +916,7 @@ impl Context {
                 MAX_MESSAGE_FIELD_NUMBER
             }),
         };
+        self.check_range_end("extension", start, end, span);
 
         descriptor_proto::ExtensionRange {
             start,
@@ -842,7 +930,7 @@ impl Context {
     #[allow(clippy::too_many_arguments)]
     fn generate_oneof_descriptor(
         &mut self,
-        oneof: ast::Oneof,
+        oneof: ast::Oneof<'a>,
         oneof_index: usize,
         oneof_tag: i32,
         message_tag: i32,
@@ -881,7 +969,7 @@ impl Context {
         }
 
         OneofDescriptorProto {
-            name: Some(oneof.name.value),
+            name: Some(oneof.name.value.into_owned()),
             options: options.map(|uninterpreted_option| OneofOptions {
                 uninterpreted_option,
             }),
@@ -890,7 +978,7 @@ impl Context {
 
     fn generate_extend_descriptors(
         &mut self,
-        ast: ast::Extend,
+        ast: ast::Extend<'a>,
         extension_tag: i32,
         extensions: &mut Vec<FieldDescriptorProto>,
         message_tag: i32,
@@ -922,11 +1010,11 @@ impl Context {
         }
     }
 
-    fn generate_enum_descriptor(&mut self, ast: ast::Enum) -> EnumDescriptorProto {
+    fn generate_enum_descriptor(&mut self, ast: ast::Enum<'a>) -> EnumDescriptorProto {
         self.add_comments(ast.span, ast.comments);
         self.add_span_for(&[tag::enum_::NAME], ast.name.span);
 
-        let name = Some(ast.name.value);
+        let name = Some(ast.name.value.into_owned());
         let mut value = Vec::new();
         let mut reserved_range = Vec::new();
         let mut reserved_name = Vec::new();
@@ -955,7 +1043,7 @@ impl Context {
                     self.add_comments(reserved.span, reserved.comments);
                     for name in names {
                         self.add_span_for(&[index_to_i32(reserved_name.len())], name.span);
-                        reserved_name.push(name.value);
+                        reserved_name.push(name.value.into_owned());
                     }
                     self.path.pop();
                 }
@@ -978,10 +1066,13 @@ impl Context {
         }
     }
 
-    fn generate_enum_value_descriptor(&mut self, ast: ast::EnumValue) -> EnumValueDescriptorProto {
+    fn generate_enum_value_descriptor(
+        &mut self,
+        ast: ast::EnumValue<'a>,
+    ) -> EnumValueDescriptorProto {
         self.add_comments(ast.span, ast.comments);
         self.add_span_for(&[tag::enum_value::NAME], ast.name.span);
-        let name = Some(ast.name.value);
+        let name = Some(ast.name.value.into_owned());
 
         self.add_span_for(&[tag::enum_value::NUMBER], ast.number.span.clone());
         let number = self.generate_enum_number(ast.number);
@@ -1019,20 +1110,22 @@ impl Context {
         self.add_span_for(&[tag::enum_::reserved_range::START], range.start_span());
         self.add_span_for(&[tag::enum_::reserved_range::END], range.end_span());
 
+        let span = range.span();
         let start = self.generate_enum_number(range.start);
         let end = match range.end {
             ast::ReservedRangeEnd::None => start,
             ast::ReservedRangeEnd::Int(value) => self.generate_enum_number(value),
             ast::ReservedRangeEnd::Max(_) => Some(i32::MAX),
         };
+        self.check_range_end("reserved", start, end, span);
 
         enum_descriptor_proto::EnumReservedRange { start, end }
     }
 
-    fn generate_service_descriptor(&mut self, service: ast::Service) -> ServiceDescriptorProto {
+    fn generate_service_descriptor(&mut self, service: ast::Service<'a>) -> ServiceDescriptorProto {
         self.add_comments(service.span, service.comments);
         self.add_span_for(&[tag::service::NAME], service.name.span);
-        let name = Some(service.name.value);
+        let name = Some(service.name.value.into_owned());
         let mut method = Vec::new();
 
         self.path.push(tag::service::METHOD);
@@ -1057,10 +1150,10 @@ impl Context {
         }
     }
 
-    fn generate_method_descriptor(&mut self, ast: ast::Method) -> MethodDescriptorProto {
+    fn generate_method_descriptor(&mut self, ast: ast::Method<'a>) -> MethodDescriptorProto {
         self.add_comments(ast.span, ast.comments);
         self.add_span_for(&[tag::method::NAME], ast.name.span);
-        let name = Some(ast.name.value);
+        let name = Some(ast.name.value.into_owned());
 
         self.add_span_for(&[tag::method::INPUT_TYPE], ast.input_ty.span());
         let input_type = ast.input_ty.to_string();
@@ -1085,24 +1178,25 @@ impl Context {
             self.add_span_for(&[tag::method::SERVER_STREAMING], span);
         }
 
+        // Like protoc, methods with a body always have options, even if the body is empty.
         self.path.push(tag::method::OPTIONS);
-        let options = self.generate_options(ast.options);
+        let options = ast.options.map(|options| MethodOptions {
+            uninterpreted_option: self.generate_options(options).unwrap_or_default(),
+            ..Default::default()
+        });
         self.path.pop();
 
         MethodDescriptorProto {
             name,
             input_type: Some(input_type),
             output_type: Some(output_type),
-            options: options.map(|uninterpreted_option| MethodOptions {
-                uninterpreted_option,
-                ..Default::default()
-            }),
+            options,
             client_streaming,
             server_streaming,
         }
     }
 
-    fn generate_options(&mut self, ast: Vec<ast::Option>) -> Option<Vec<UninterpretedOption>> {
+    fn generate_options(&mut self, ast: Vec<ast::Option<'a>>) -> Option<Vec<UninterpretedOption>> {
         let mut options = Vec::new();
 
         for option_ast in ast {
@@ -1112,6 +1206,10 @@ impl Context {
                 option_ast.span,
                 option_ast.comments,
             );
+            self.add_option_value(
+                &[tag::UNINTERPRETED_OPTION, index_to_i32(options.len())],
+                &option_ast.body,
+            );
             options.push(self.generate_option(option_ast.body));
         }
 
@@ -1124,7 +1222,7 @@ impl Context {
 
     fn generate_options_list(
         &mut self,
-        ast: Option<ast::OptionList>,
+        ast: Option<ast::OptionList<'a>>,
     ) -> Option<Vec<UninterpretedOption>> {
         let mut options = Vec::new();
 
@@ -1136,6 +1234,10 @@ impl Context {
                     &[tag::UNINTERPRETED_OPTION, index_to_i32(options.len())],
                     option_ast.span(),
                 );
+                self.add_option_value(
+                    &[tag::UNINTERPRETED_OPTION, index_to_i32(options.len())],
+                    &option_ast,
+                );
                 options.push(self.generate_option(option_ast));
             }
         }
@@ -1147,12 +1249,12 @@ impl Context {
         }
     }
 
-    fn generate_option(&mut self, ast: ast::OptionBody) -> UninterpretedOption {
+    fn generate_option(&mut self, ast: ast::OptionBody<'a>) -> UninterpretedOption {
         let mut name = Vec::new();
         for part in ast.name {
             match part {
                 ast::OptionNamePart::Ident(ident) => name.push(uninterpreted_option::NamePart {
-                    name_part: ident.value,
+                    name_part: ident.value.into_owned(),
                     is_extension: false,
                 }),
                 ast::OptionNamePart::Extension(extension, _) => {
@@ -1171,7 +1273,7 @@ impl Context {
                 ..
             } => UninterpretedOption {
                 name,
-                identifier_value: Some(ident.value),
+                identifier_value: Some(ident.value.into_owned()),
                 ..Default::default()
             },
             ast::OptionValue::Ident {
@@ -1216,12 +1318,12 @@ impl Context {
             },
             ast::OptionValue::String(string) => UninterpretedOption {
                 name,
-                string_value: Some(string.value),
+                string_value: Some(string.value.into_owned()),
                 ..Default::default()
             },
             ast::OptionValue::Aggregate(message, _) => UninterpretedOption {
                 name,
-                aggregate_value: Some(message),
+                aggregate_value: Some(message.into_owned()),
                 ..Default::default()
             },
         }
@@ -1241,9 +1343,13 @@ impl Context {
         self.locations.push(Location {
             path: self.path.clone(),
             span,
-            leading_comments: comments.leading_comment,
-            trailing_comments: comments.trailing_comment,
-            leading_detached_comments: comments.leading_detached_comments,
+            leading_comments: comments.leading_comment.map(Cow::into_owned),
+            trailing_comments: comments.trailing_comment.map(Cow::into_owned),
+            leading_detached_comments: comments
+                .leading_detached_comments
+                .into_iter()
+                .map(Cow::into_owned)
+                .collect(),
         });
     }
 
@@ -1259,12 +1365,23 @@ impl Context {
         self.pop_path(path_items.len());
     }
 
+    fn add_option_value(&mut self, path_items: &[i32], option: &ast::OptionBody<'a>) {
+        if let Some(option_values) = &mut self.option_values {
+            let mut path = self.path.clone();
+            path.extend_from_slice(path_items);
+            option_values.push((path, option.name_span(), option.value.clone()));
+        }
+    }
+
     fn pop_path(&mut self, n: usize) {
         self.path.truncate(self.path.len() - n);
     }
 }
 
-fn take_option(options: &mut Option<ast::OptionList>, name: &str) -> Option<ast::OptionBody> {
+fn take_option<'a>(
+    options: &mut Option<ast::OptionList<'a>>,
+    name: &str,
+) -> Option<ast::OptionBody<'a>> {
     if let Some(options) = options {
         if let Some(index) = options.options.iter().position(|o| o.has_name(name)) {
             return Some(options.options.remove(index));
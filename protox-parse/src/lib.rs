@@ -5,18 +5,25 @@
 #![deny(unsafe_code)]
 #![doc(html_root_url = "https://docs.rs/protox-parse/0.7.0/")]
 
+use std::{fmt, sync::Arc};
+
 use logos::Span;
 use prost_types::FileDescriptorProto;
 
 pub use self::error::ParseError;
 
+pub mod case;
+pub mod fix;
+pub mod resolve;
+pub mod tag;
+pub mod tokens;
+pub mod value;
+
 mod ast;
-mod case;
 mod error;
 mod generate;
 mod lex;
 mod parse;
-mod tag;
 #[cfg(test)]
 mod tests;
 
@@ -74,22 +81,270 @@ const MAX_MESSAGE_FIELD_NUMBER: i32 = 536_870_911;
 /// })
 /// ```
 pub fn parse(name: &str, source: &str) -> Result<FileDescriptorProto, ParseError> {
-    if source.len() > MAX_FILE_LEN {
-        return Err(ParseError::new(
-            vec![error::ParseErrorKind::FileTooLarge],
-            name,
-            String::default(),
-        ));
+    ParseOptions::new().parse(name, source)
+}
+
+/// Parses a single protobuf source file, and returns the values of all options and field defaults
+/// it contains.
+///
+/// String values are returned with both their decoded bytes and the original literal text, which is
+/// not preserved by [`parse()`].
+///
+/// # Examples
+///
+/// ```
+/// # use protox_parse::{parse_option_values, value::OptionValue};
+/// let source = r#"
+///     message Foo {
+///         optional bytes foo = 1 [default = "\xFF", (my_option) = -5];
+///     }
+/// "#;
+/// let values = parse_option_values("foo.proto", source).unwrap();
+///
+/// assert_eq!(values[0].name(), "default");
+/// assert_eq!(values[0].path(), &[4, 0, 2, 0, 7]);
+/// assert_eq!(values[0].value(), &OptionValue::String {
+///     value: vec![0xFF],
+///     raw: r#""\xFF""#.to_owned(),
+/// });
+/// assert_eq!(values[1].name(), "(my_option)");
+/// assert_eq!(values[1].path(), &[4, 0, 2, 0, 8, 999, 0]);
+/// assert_eq!(values[1].value(), &OptionValue::Int { negative: true, value: 5 });
+/// ```
+pub fn parse_option_values(
+    name: &str,
+    source: &str,
+) -> Result<Vec<value::ParsedOption>, ParseError> {
+    ParseOptions::new().parse_option_values(name, source)
+}
+
+/// Options for parsing protobuf source files.
+///
+/// # Examples
+///
+/// ```
+/// # use protox_parse::ParseOptions;
+/// let source = "message A { message B { message C {} } }";
+///
+/// assert!(ParseOptions::new().parse("foo.proto", source).is_ok());
+/// assert!(ParseOptions::new().max_nesting_depth(2).parse("foo.proto", source).is_err());
+/// ```
+#[derive(Clone)]
+pub struct ParseOptions {
+    max_nesting_depth: u32,
+    comment_attachment: CommentAttachment,
+    type_resolver: Option<Arc<dyn resolve::TypeResolver + Send + Sync>>,
+    max_errors: Option<usize>,
+    dedupe_cascading: bool,
+    sort_by_span: bool,
+}
+
+/// Controls how comments are attached to declarations in the generated source code info.
+///
+/// # Examples
+///
+/// ```
+/// # use protox_parse::{CommentAttachment, ParseOptions};
+/// let source = "message Foo { /* the field */ optional int32 a = 1; }";
+/// let field_location = |options: &ParseOptions| {
+///     let file = options.parse("foo.proto", source).unwrap();
+///     file.source_code_info.unwrap().location.into_iter()
+///         .find(|location| location.path == [4, 0, 2, 0])
+///         .unwrap()
+/// };
+///
+/// let location = field_location(&ParseOptions::new());
+/// assert_eq!(location.leading_comments, None);
+/// assert_eq!(location.leading_detached_comments, [" the field "]);
+///
+/// let location = field_location(ParseOptions::new().comment_attachment(CommentAttachment::Greedy));
+/// assert_eq!(location.leading_comments.as_deref(), Some(" the field "));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CommentAttachment {
+    /// Attach comments the same way as `protoc`.
+    ///
+    /// When a declaration starts on the same line as the previous token, or the same line as the previous token's
+    /// trailing comment, a single comment in between is ambiguous. `protoc` records such a comment as detached, or
+    /// discards it entirely if it is followed by a closing brace.
+    #[default]
+    Protoc,
+    /// Attach ambiguous comments to the nearest declaration.
+    ///
+    /// A comment on the same line as the following declaration becomes its leading comment, and a comment before a
+    /// closing brace on the same line as the previous declaration becomes its trailing comment. All other comments
+    /// are attached as with [`CommentAttachment::Protoc`].
+    Greedy,
+}
+
+impl ParseOptions {
+    /// Creates a new [`ParseOptions`] with default settings.
+    pub fn new() -> Self {
+        ParseOptions {
+            max_nesting_depth: DEFAULT_MAX_NESTING_DEPTH,
+            comment_attachment: CommentAttachment::default(),
+            type_resolver: None,
+            max_errors: None,
+            dedupe_cascading: false,
+            sort_by_span: false,
+        }
+    }
+
+    /// Sets the maximum depth to which message and group definitions, and message values in options, may be nested.
+    ///
+    /// Files exceeding this depth fail to parse with an error, instead of risking a stack overflow. The
+    /// default is 100.
+    pub fn max_nesting_depth(&mut self, depth: u32) -> &mut Self {
+        self.max_nesting_depth = depth;
+        self
+    }
+
+    /// Sets how comments are attached to declarations in the generated source code info.
+    ///
+    /// The default is [`CommentAttachment::Protoc`].
+    pub fn comment_attachment(&mut self, attachment: CommentAttachment) -> &mut Self {
+        self.comment_attachment = attachment;
+        self
+    }
+
+    /// Sets a [`TypeResolver`](resolve::TypeResolver) used to determine the types of fields with named types.
+    ///
+    /// By default, these fields only have their `type_name` set. See the [`resolve`] module for details.
+    pub fn type_resolver(
+        &mut self,
+        resolver: impl resolve::TypeResolver + Send + Sync + 'static,
+    ) -> &mut Self {
+        self.type_resolver = Some(Arc::new(resolver));
+        self
+    }
+
+    /// Sets the maximum number of errors reported when a file fails to parse.
+    ///
+    /// Any further errors are discarded, after removing cascading errors and sorting if those options are enabled.
+    /// At least one error is always reported. By default, there is no limit.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use protox_parse::ParseOptions;
+    /// let source = "message Foo { optional int32 a = 0; optional int32 b = 0; optional int32 c = 0; }";
+    ///
+    /// let err = ParseOptions::new().parse("foo.proto", source).unwrap_err();
+    /// assert_eq!(miette::Diagnostic::related(&err).unwrap().count(), 2);
+    ///
+    /// let err = ParseOptions::new().max_errors(2).parse("foo.proto", source).unwrap_err();
+    /// assert_eq!(miette::Diagnostic::related(&err).unwrap().count(), 1);
+    /// ```
+    pub fn max_errors(&mut self, max: usize) -> &mut Self {
+        self.max_errors = Some(max);
+        self
+    }
+
+    /// Sets whether to remove errors which are likely to be caused by an earlier error.
+    ///
+    /// If enabled, an error is dropped if its span overlaps that of an error reported before it, such as an
+    /// unexpected string token containing an invalid escape sequence, and runs of adjacent invalid tokens are
+    /// reported once. The default is `false`.
+    pub fn dedupe_cascading(&mut self, yes: bool) -> &mut Self {
+        self.dedupe_cascading = yes;
+        self
+    }
+
+    /// Sets whether to sort errors by their position in the file.
+    ///
+    /// By default, errors are reported in the order they were found, which differs from their position when, for
+    /// example, an invalid field number is found after a syntax error later in the file. Errors without a position
+    /// are sorted last.
+    pub fn sort_by_span(&mut self, yes: bool) -> &mut Self {
+        self.sort_by_span = yes;
+        self
     }
 
-    let ast = parse::parse_file(source)
-        .map_err(|errors| ParseError::new(errors, name, source.to_owned()))?;
+    /// Parses a single protobuf source file into a [`FileDescriptorProto`].
+    ///
+    /// See [`parse()`] for details.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "debug", skip(self, source))
+    )]
+    pub fn parse(&self, name: &str, source: &str) -> Result<FileDescriptorProto, ParseError> {
+        let ast = self.parse_ast(name, source)?;
 
-    generate::generate_file(ast, name, source)
-        .map_err(|errors| ParseError::new(errors, name, source.to_owned()))
+        let mut file = generate::generate_file(ast, name, source)
+            .map_err(|errors| self.error(errors, name, source))?;
+        if let Some(resolver) = &self.type_resolver {
+            resolve::resolve_types(&mut file, resolver.as_ref());
+        }
+        Ok(file)
+    }
+
+    /// Parses a single protobuf source file, and returns the values of all options and field defaults.
+    ///
+    /// See [`parse_option_values()`] for details.
+    pub fn parse_option_values(
+        &self,
+        name: &str,
+        source: &str,
+    ) -> Result<Vec<value::ParsedOption>, ParseError> {
+        let ast = self.parse_ast(name, source)?;
+
+        generate::generate_option_values(ast, name, source)
+            .map_err(|errors| self.error(errors, name, source))
+    }
+
+    fn parse_ast<'a>(&self, name: &str, source: &'a str) -> Result<ast::File<'a>, ParseError> {
+        if source.len() > MAX_FILE_LEN {
+            return Err(ParseError::new(
+                vec![error::ParseErrorKind::FileTooLarge],
+                name,
+                String::default(),
+            ));
+        }
+
+        parse::parse_file(source, self.max_nesting_depth, self.comment_attachment)
+            .map_err(|errors| self.error(errors, name, source))
+    }
+
+    fn error(
+        &self,
+        mut errors: Vec<error::ParseErrorKind>,
+        name: &str,
+        source: &str,
+    ) -> ParseError {
+        if self.dedupe_cascading {
+            error::dedupe_cascading(&mut errors);
+        }
+        if self.sort_by_span {
+            error::sort_by_span(&mut errors);
+        }
+        if let Some(max) = self.max_errors {
+            errors.truncate(max.max(1));
+        }
+        ParseError::new(errors, name, source.to_owned())
+    }
+}
+
+impl fmt::Debug for ParseOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ParseOptions")
+            .field("max_nesting_depth", &self.max_nesting_depth)
+            .field("comment_attachment", &self.comment_attachment)
+            .field("type_resolver", &self.type_resolver.is_some())
+            .field("max_errors", &self.max_errors)
+            .field("dedupe_cascading", &self.dedupe_cascading)
+            .field("sort_by_span", &self.sort_by_span)
+            .finish()
+    }
+}
+
+impl Default for ParseOptions {
+    fn default() -> Self {
+        ParseOptions::new()
+    }
 }
 
 const MAX_FILE_LEN: usize = i32::MAX as usize;
+const DEFAULT_MAX_NESTING_DEPTH: u32 = 100;
 
 fn index_to_i32(index: usize) -> i32 {
     // We enforce that all files parsed are at most i32::MAX bytes long. Therefore the indices of any
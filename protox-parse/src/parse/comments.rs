@@ -1,34 +1,34 @@
 use std::{borrow::Cow, mem::take};
 
 #[derive(Default, Clone)]
-pub(super) struct Comments {
-    leading_detached: Vec<String>,
-    leading: Option<String>,
-    trailing: Option<String>,
+pub(super) struct Comments<'a> {
+    leading_detached: Vec<Cow<'a, str>>,
+    leading: Option<Cow<'a, str>>,
+    trailing: Option<Cow<'a, str>>,
 
     is_trailing: bool,
     is_line_comment: bool,
 }
 
-impl Comments {
-    pub fn new() -> Comments {
+impl<'a> Comments<'a> {
+    pub fn new() -> Comments<'a> {
         Comments::default()
     }
 
-    pub fn block_comment(&mut self, comment: Cow<str>) {
+    pub fn block_comment(&mut self, comment: Cow<'a, str>) {
         if self.leading.is_some() {
             self.flush();
         }
-        self.leading = Some(comment.into_owned());
+        self.leading = Some(comment);
     }
 
-    pub fn line_comment(&mut self, comment: Cow<str>) {
+    pub fn line_comment(&mut self, comment: Cow<'a, str>) {
         if self.leading.is_some() && !self.is_line_comment {
             self.flush();
         }
         match &mut self.leading {
-            None => self.leading = Some(comment.into_owned()),
-            Some(current) => current.push_str(comment.as_ref()),
+            None => self.leading = Some(comment),
+            Some(current) => current.to_mut().push_str(comment.as_ref()),
         }
         self.is_line_comment = true;
     }
@@ -48,6 +48,23 @@ impl Comments {
         self.is_line_comment = false;
     }
 
+    /// Detaches the only pending comment from both the previous and next token.
+    ///
+    /// This is used when the next token is on the same line as the previous token or its trailing comment, in which
+    /// case it is unclear which token a single comment belongs to.
+    pub fn detach_single(&mut self) {
+        let count = self.leading_detached.len()
+            + usize::from(self.trailing.is_some())
+            + usize::from(self.leading.is_some());
+        if count == 1 {
+            if let Some(trailing) = self.trailing.take() {
+                self.leading_detached.insert(0, trailing);
+            }
+            self.is_trailing = false;
+            self.flush();
+        }
+    }
+
     pub fn reset(&mut self) {
         self.leading = None;
         self.leading_detached.clear();
@@ -57,11 +74,11 @@ impl Comments {
         self.is_line_comment = false;
     }
 
-    pub fn take_leading(&mut self) -> (Vec<String>, Option<String>) {
+    pub fn take_leading(&mut self) -> (Vec<Cow<'a, str>>, Option<Cow<'a, str>>) {
         (take(&mut self.leading_detached), take(&mut self.leading))
     }
 
-    pub fn take_trailing(&mut self) -> Option<String> {
+    pub fn take_trailing(&mut self) -> Option<Cow<'a, str>> {
         take(&mut self.trailing)
     }
 }
@@ -1,4 +1,5 @@
 use std::{
+    borrow::Cow,
     fmt::{self, Write},
     iter::once,
     path::Path,
@@ -13,14 +14,24 @@ use crate::{
     error::ParseErrorKind,
     join_span,
     lex::{EqFloat, Token},
+    CommentAttachment,
 };
 
 mod comments;
 #[cfg(test)]
 mod tests;
 
-pub(crate) fn parse_file(source: &str) -> Result<ast::File, Vec<ParseErrorKind>> {
-    let mut parser = Parser::new(source);
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "trace", name = "parse_syntax", skip_all)
+)]
+pub(crate) fn parse_file<'a>(
+    source: &'a str,
+    max_nesting_depth: u32,
+    comment_attachment: CommentAttachment,
+) -> Result<ast::File<'a>, Vec<ParseErrorKind>> {
+    let mut parser = Parser::new(source, max_nesting_depth);
+    parser.comment_attachment = comment_attachment;
     match parser.parse_file() {
         Ok(ast) if parser.lexer.extras.errors.is_empty() => Ok(ast),
         _ => Err(parser.lexer.extras.errors),
@@ -30,7 +41,12 @@ pub(crate) fn parse_file(source: &str) -> Result<ast::File, Vec<ParseErrorKind>>
 struct Parser<'a> {
     lexer: Lexer<'a, Token<'a>>,
     peek: Option<Result<(Token<'a>, Span), ()>>,
-    comments: Comments,
+    comments: Comments<'a>,
+    comment_attachment: CommentAttachment,
+    /// The end of the last token which was not a comment or newline.
+    prev_end: Option<usize>,
+    depth: u32,
+    max_depth: u32,
 }
 
 #[derive(Debug, Clone)]
@@ -39,27 +55,31 @@ enum ExpectedToken {
     Ident,
 }
 
-enum Statement {
+enum Statement<'a> {
     Empty(Span),
-    Package(ast::Package),
-    Import(ast::Import),
-    Option(ast::Option),
-    Message(ast::Message),
-    Enum(ast::Enum),
-    Service(ast::Service),
-    Extend(ast::Extend),
+    Package(ast::Package<'a>),
+    Import(ast::Import<'a>),
+    Option(ast::Option<'a>),
+    Message(ast::Message<'a>),
+    Enum(ast::Enum<'a>),
+    Service(ast::Service<'a>),
+    Extend(ast::Extend<'a>),
 }
 
 impl<'a> Parser<'a> {
-    fn new(source: &'a str) -> Self {
+    fn new(source: &'a str, max_depth: u32) -> Self {
         Parser {
             lexer: Token::lexer(source),
             comments: Comments::new(),
+            comment_attachment: CommentAttachment::Protoc,
+            prev_end: None,
             peek: None,
+            depth: 0,
+            max_depth,
         }
     }
 
-    fn parse_file(&mut self) -> Result<ast::File, ()> {
+    fn parse_file(&mut self) -> Result<ast::File<'a>, ()> {
         let mut file_span = self.lexer.source().len()..0;
 
         let mut syntax = ast::Syntax::default();
@@ -77,7 +97,7 @@ impl<'a> Parser<'a> {
             None => (),
         }
 
-        let mut package: Option<ast::Package> = None;
+        let mut package: Option<ast::Package<'a>> = None;
         let mut imports = Vec::new();
         let mut options = Vec::new();
         let mut items = Vec::new();
@@ -133,7 +153,7 @@ impl<'a> Parser<'a> {
         })
     }
 
-    fn parse_syntax(&mut self) -> Result<(ast::Syntax, Span, ast::Comments), ()> {
+    fn parse_syntax(&mut self) -> Result<(ast::Syntax, Span, ast::Comments<'a>), ()> {
         let leading_comments = self.parse_leading_comments();
 
         let start = self.expect_eq(Token::SYNTAX)?;
@@ -142,7 +162,7 @@ impl<'a> Parser<'a> {
         let syntax = match self.peek()? {
             Some((Token::StringLiteral(_), _)) => {
                 let value = self.parse_string()?;
-                match value.value.as_slice() {
+                match value.value.as_ref() {
                     b"proto2" => ast::Syntax::Proto2,
                     b"proto3" => ast::Syntax::Proto3,
                     bytes => {
@@ -164,7 +184,7 @@ impl<'a> Parser<'a> {
         Ok((syntax, join_span(start, end), comments))
     }
 
-    fn parse_statement(&mut self) -> Result<Option<Statement>, ()> {
+    fn parse_statement(&mut self) -> Result<Option<Statement<'a>>, ()> {
         match self.peek()? {
             Some((Token::Semicolon, span)) => {
                 self.bump();
@@ -184,7 +204,7 @@ impl<'a> Parser<'a> {
         }
     }
 
-    fn parse_package(&mut self) -> Result<ast::Package, ()> {
+    fn parse_package(&mut self) -> Result<ast::Package<'a>, ()> {
         let leading_comments = self.parse_leading_comments();
 
         let start = self.expect_eq(Token::PACKAGE)?;
@@ -202,7 +222,7 @@ impl<'a> Parser<'a> {
         })
     }
 
-    fn parse_import(&mut self) -> Result<ast::Import, ()> {
+    fn parse_import(&mut self) -> Result<ast::Import<'a>, ()> {
         let leading_comments = self.parse_leading_comments();
 
         let start = self.expect_eq(Token::IMPORT)?;
@@ -240,7 +260,7 @@ impl<'a> Parser<'a> {
         })
     }
 
-    fn parse_message(&mut self) -> Result<ast::Message, ()> {
+    fn parse_message(&mut self) -> Result<ast::Message<'a>, ()> {
         let leading_comments = self.parse_leading_comments();
 
         let start = self.expect_eq(Token::MESSAGE)?;
@@ -250,7 +270,7 @@ impl<'a> Parser<'a> {
         self.expect_eq(Token::LeftBrace)?;
         let comments = self.parse_trailing_comment(leading_comments);
 
-        let (body, end) = self.parse_message_body()?;
+        let (body, end) = self.parse_message_body(name.span.clone())?;
 
         Ok(ast::Message {
             name,
@@ -260,7 +280,23 @@ impl<'a> Parser<'a> {
         })
     }
 
-    fn parse_message_body(&mut self) -> Result<(ast::MessageBody, Span), ()> {
+    fn parse_message_body(&mut self, name_span: Span) -> Result<(ast::MessageBody<'a>, Span), ()> {
+        // Message bodies are parsed recursively, so limit the depth to avoid overflowing the stack.
+        if self.depth >= self.max_depth {
+            self.add_error(ParseErrorKind::NestingTooDeep {
+                limit: self.max_depth,
+                span: name_span,
+            });
+            return Err(());
+        }
+
+        self.depth += 1;
+        let result = self.parse_message_body_items();
+        self.depth -= 1;
+        result
+    }
+
+    fn parse_message_body_items(&mut self) -> Result<(ast::MessageBody<'a>, Span), ()> {
         let mut items = Vec::new();
         let mut options = Vec::new();
         let mut reserved = Vec::new();
@@ -304,7 +340,7 @@ impl<'a> Parser<'a> {
         ))
     }
 
-    fn parse_field(&mut self) -> Result<ast::Field, ()> {
+    fn parse_field(&mut self) -> Result<ast::Field<'a>, ()> {
         let leading_comments = self.parse_leading_comments();
 
         let (label, start) = match self.peek()? {
@@ -333,10 +369,10 @@ impl<'a> Parser<'a> {
 
     fn parse_map(
         &mut self,
-        leading_comments: (Vec<String>, Option<String>),
+        leading_comments: (Vec<Cow<'a, str>>, Option<Cow<'a, str>>),
         start: Span,
         label: Option<(ast::FieldLabel, Span)>,
-    ) -> Result<ast::Field, ()> {
+    ) -> Result<ast::Field<'a>, ()> {
         let ty_start = self.expect_eq(Token::MAP)?;
 
         self.expect_eq(Token::LeftAngleBracket)?;
@@ -380,10 +416,10 @@ impl<'a> Parser<'a> {
 
     fn parse_group(
         &mut self,
-        leading_comments: (Vec<String>, Option<String>),
+        leading_comments: (Vec<Cow<'a, str>>, Option<Cow<'a, str>>),
         start: Span,
         label: Option<(ast::FieldLabel, Span)>,
-    ) -> Result<ast::Field, ()> {
+    ) -> Result<ast::Field<'a>, ()> {
         let ty_span = self.expect_eq(Token::GROUP)?;
 
         let name = self.parse_ident()?;
@@ -407,7 +443,7 @@ impl<'a> Parser<'a> {
 
         let comments = self.parse_trailing_comment(leading_comments);
 
-        let (body, end) = self.parse_message_body()?;
+        let (body, end) = self.parse_message_body(name.span.clone())?;
 
         Ok(ast::Field {
             label,
@@ -422,10 +458,10 @@ impl<'a> Parser<'a> {
 
     fn parse_normal_field(
         &mut self,
-        leading_comments: (Vec<String>, Option<String>),
+        leading_comments: (Vec<Cow<'a, str>>, Option<Cow<'a, str>>),
         start: Span,
         label: Option<(ast::FieldLabel, Span)>,
-    ) -> Result<ast::Field, ()> {
+    ) -> Result<ast::Field<'a>, ()> {
         let (ty, ty_span) = self.parse_field_type(&[ExpectedToken::Ident])?;
 
         let name = self.parse_ident()?;
@@ -454,7 +490,7 @@ impl<'a> Parser<'a> {
         })
     }
 
-    fn parse_extend(&mut self) -> Result<ast::Extend, ()> {
+    fn parse_extend(&mut self) -> Result<ast::Extend<'a>, ()> {
         let leading_comments = self.parse_leading_comments();
 
         let start = self.expect_eq(Token::EXTEND)?;
@@ -485,7 +521,7 @@ impl<'a> Parser<'a> {
         })
     }
 
-    fn parse_service(&mut self) -> Result<ast::Service, ()> {
+    fn parse_service(&mut self) -> Result<ast::Service<'a>, ()> {
         let leading_comments = self.parse_leading_comments();
 
         let start = self.expect_eq(Token::SERVICE)?;
@@ -524,7 +560,7 @@ impl<'a> Parser<'a> {
         })
     }
 
-    fn parse_service_rpc(&mut self) -> Result<ast::Method, ()> {
+    fn parse_service_rpc(&mut self) -> Result<ast::Method<'a>, ()> {
         let leading_comments = self.parse_leading_comments();
 
         let start = self.expect_eq(Token::RPC)?;
@@ -555,7 +591,7 @@ impl<'a> Parser<'a> {
 
         self.expect_eq(Token::RightParen)?;
 
-        let mut options = Vec::new();
+        let mut options = None;
         let end = match self.peek_skip_comments()? {
             Some((Token::Semicolon, span)) => {
                 self.bump();
@@ -563,6 +599,7 @@ impl<'a> Parser<'a> {
             }
             Some((Token::LeftBrace, _)) => {
                 self.bump();
+                let options = options.get_or_insert_with(Vec::new);
                 loop {
                     match self.peek()? {
                         Some((Token::OPTION, _)) => {
@@ -594,7 +631,7 @@ impl<'a> Parser<'a> {
         })
     }
 
-    fn parse_enum(&mut self) -> Result<ast::Enum, ()> {
+    fn parse_enum(&mut self) -> Result<ast::Enum<'a>, ()> {
         let leading_comments = self.parse_leading_comments();
 
         let start = self.expect_eq(Token::ENUM)?;
@@ -638,7 +675,7 @@ impl<'a> Parser<'a> {
         })
     }
 
-    fn parse_enum_value(&mut self) -> Result<ast::EnumValue, ()> {
+    fn parse_enum_value(&mut self) -> Result<ast::EnumValue<'a>, ()> {
         let leading_comments = self.parse_leading_comments();
 
         let name = self.parse_ident()?;
@@ -665,7 +702,7 @@ impl<'a> Parser<'a> {
         })
     }
 
-    fn parse_oneof(&mut self) -> Result<ast::Oneof, ()> {
+    fn parse_oneof(&mut self) -> Result<ast::Oneof<'a>, ()> {
         let leading_comments = self.parse_leading_comments();
         let start = self.expect_eq(Token::ONEOF)?;
 
@@ -685,6 +722,10 @@ impl<'a> Parser<'a> {
                     continue;
                 }
                 Some((Token::RightBrace, _)) => break self.bump(),
+                Some((Token::ONEOF, span)) if self.is_oneof_definition() => {
+                    self.add_error(ParseErrorKind::NestedOneof { span });
+                    return Err(());
+                }
                 Some((Token::Dot | Token::Ident(_), _)) => fields.push(self.parse_field()?),
                 _ => self.unexpected_token("a message field, option or '}'")?,
             }
@@ -699,7 +740,26 @@ impl<'a> Parser<'a> {
         })
     }
 
-    fn parse_field_type(&mut self, terminators: &[ExpectedToken]) -> Result<(ast::Ty, Span), ()> {
+    /// Returns true if the peeked `oneof` keyword starts a oneof definition, rather than a field whose type is named
+    /// `oneof`.
+    fn is_oneof_definition(&self) -> bool {
+        let mut lexer = Lexer::<Token>::new(self.lexer.remainder());
+        let mut tokens = std::iter::from_fn(|| lexer.next()).filter(|token| {
+            !matches!(
+                token,
+                Ok(Token::LineComment(_) | Token::BlockComment(_) | Token::Newline)
+            )
+        });
+        matches!(
+            (tokens.next(), tokens.next()),
+            (Some(Ok(Token::Ident(_))), Some(Ok(Token::LeftBrace)))
+        )
+    }
+
+    fn parse_field_type(
+        &mut self,
+        terminators: &[ExpectedToken],
+    ) -> Result<(ast::Ty<'a>, Span), ()> {
         let scalar_ty = match self.peek()? {
             Some((Token::DOUBLE, span)) => (ast::Ty::Double, span),
             Some((Token::FLOAT, span)) => (ast::Ty::Float, span),
@@ -728,7 +788,7 @@ impl<'a> Parser<'a> {
         Ok(scalar_ty)
     }
 
-    fn parse_reserved(&mut self) -> Result<ast::Reserved, ()> {
+    fn parse_reserved(&mut self) -> Result<ast::Reserved<'a>, ()> {
         let leading_comments = self.parse_leading_comments();
         let start = self.expect_eq(Token::RESERVED)?;
 
@@ -756,7 +816,7 @@ impl<'a> Parser<'a> {
         }
     }
 
-    fn parse_extensions(&mut self) -> Result<ast::Extensions, ()> {
+    fn parse_extensions(&mut self) -> Result<ast::Extensions<'a>, ()> {
         let leading_comments = self.parse_leading_comments();
         let start = self.expect_eq(Token::EXTENSIONS)?;
 
@@ -781,7 +841,7 @@ impl<'a> Parser<'a> {
         })
     }
 
-    fn parse_reserved_names(&mut self) -> Result<(Vec<ast::Ident>, Span), ()> {
+    fn parse_reserved_names(&mut self) -> Result<(Vec<ast::Ident<'a>>, Span), ()> {
         let mut names = vec![self.parse_ident_string()?];
 
         let end = loop {
@@ -798,25 +858,25 @@ impl<'a> Parser<'a> {
         Ok((names, end))
     }
 
-    fn parse_ident_string(&mut self) -> Result<ast::Ident, ()> {
+    fn parse_ident_string(&mut self) -> Result<ast::Ident<'a>, ()> {
         let (value, span) = self.parse_utf8_string()?;
         if !is_valid_ident(&value) {
             self.add_error(ParseErrorKind::InvalidIdentifier { span: span.clone() })
         }
-        Ok(ast::Ident { value, span })
+        Ok(ast::Ident::new(value, span))
     }
 
     fn parse_reserved_ranges(
         &mut self,
         terminators: &[ExpectedToken],
     ) -> Result<Vec<ast::ReservedRange>, ()> {
-        let mut ranges = vec![self.parse_reserved_range()?];
+        let mut ranges = vec![self.parse_reserved_range(terminators)?];
 
         loop {
             match self.peek_skip_comments()? {
                 Some((Token::Comma, _)) => {
                     self.bump();
-                    ranges.push(self.parse_reserved_range()?);
+                    ranges.push(self.parse_reserved_range(terminators)?);
                     continue;
                 }
                 Some((tok, _)) if terminators.iter().any(|e| e.matches(&tok)) => break,
@@ -829,7 +889,10 @@ impl<'a> Parser<'a> {
         Ok(ranges)
     }
 
-    fn parse_reserved_range(&mut self) -> Result<ast::ReservedRange, ()> {
+    fn parse_reserved_range(
+        &mut self,
+        terminators: &[ExpectedToken],
+    ) -> Result<ast::ReservedRange, ()> {
         let start = self.parse_int()?;
 
         let end = match self.peek_skip_comments()? {
@@ -846,14 +909,21 @@ impl<'a> Parser<'a> {
                     _ => self.unexpected_token("an integer or 'max'")?,
                 }
             }
-            Some((Token::Comma | Token::Semicolon, _)) => ast::ReservedRangeEnd::None,
-            _ => self.unexpected_token("'to', ',' or ';'")?,
+            Some((Token::Comma, _)) => ast::ReservedRangeEnd::None,
+            Some((tok, _)) if terminators.iter().any(|e| e.matches(&tok)) => {
+                ast::ReservedRangeEnd::None
+            }
+            _ => self.unexpected_token(fmt_expected(
+                [ExpectedToken::Token(Token::TO), ExpectedToken::COMMA]
+                    .into_iter()
+                    .chain(terminators.iter().cloned()),
+            ))?,
         };
 
         Ok(ast::ReservedRange { start, end })
     }
 
-    fn parse_options_list(&mut self) -> Result<ast::OptionList, ()> {
+    fn parse_options_list(&mut self) -> Result<ast::OptionList<'a>, ()> {
         let start = self.expect_eq(Token::LeftBracket)?;
 
         let mut options = vec![self.parse_option_body()?];
@@ -874,7 +944,7 @@ impl<'a> Parser<'a> {
         })
     }
 
-    fn parse_option(&mut self) -> Result<ast::Option, ()> {
+    fn parse_option(&mut self) -> Result<ast::Option<'a>, ()> {
         let leading_comments = self.parse_leading_comments();
         let start = self.expect_eq(Token::OPTION)?;
 
@@ -890,7 +960,7 @@ impl<'a> Parser<'a> {
         })
     }
 
-    fn parse_option_body(&mut self) -> Result<ast::OptionBody, ()> {
+    fn parse_option_body(&mut self) -> Result<ast::OptionBody<'a>, ()> {
         let mut name = vec![self.parse_option_name_part()?];
 
         loop {
@@ -956,7 +1026,7 @@ impl<'a> Parser<'a> {
                 self.bump();
                 let value = self.parse_text_format_message()?;
                 let end = self.expect_eq(Token::RightBrace)?;
-                ast::OptionValue::Aggregate(value, join_span(start, end))
+                ast::OptionValue::Aggregate(value.into(), join_span(start, end))
             }
             _ => self.unexpected_token("a constant")?,
         };
@@ -964,7 +1034,7 @@ impl<'a> Parser<'a> {
         Ok(ast::OptionBody { name, value })
     }
 
-    fn parse_option_name_part(&mut self) -> Result<ast::OptionNamePart, ()> {
+    fn parse_option_name_part(&mut self) -> Result<ast::OptionNamePart<'a>, ()> {
         match self.peek()? {
             Some((Token::Ident(_), _)) => Ok(ast::OptionNamePart::Ident(self.parse_ident()?)),
             Some((Token::LeftParen, start)) => {
@@ -981,17 +1051,32 @@ impl<'a> Parser<'a> {
         let mut result = String::new();
 
         let mut brace_level = 0u32;
+        // Messages may also be delimited by angle brackets, which count towards the nesting depth.
+        let mut angle_level = 0u32;
 
         debug_assert!(self.peek.is_none());
         self.lexer.extras.text_format_mode = true;
-        while let Some((tok, _)) = self.peek()? {
-            if tok == Token::LeftBrace {
-                brace_level += 1;
-            } else if tok == Token::RightBrace {
-                match brace_level.checked_sub(1) {
+        while let Some((tok, span)) = self.peek()? {
+            match tok {
+                Token::LeftBrace => brace_level += 1,
+                Token::LeftAngleBracket => angle_level += 1,
+                Token::RightBrace => match brace_level.checked_sub(1) {
                     None => break,
                     Some(new_brace_level) => brace_level = new_brace_level,
-                }
+                },
+                Token::RightAngleBracket => angle_level = angle_level.saturating_sub(1),
+                _ => (),
+            }
+
+            // The value is parsed recursively when options are interpreted, so limit the depth to avoid overflowing
+            // the stack. The opening brace of the value itself has already been consumed.
+            if brace_level + angle_level >= self.max_depth {
+                self.lexer.extras.text_format_mode = false;
+                self.add_error(ParseErrorKind::NestingTooDeep {
+                    limit: self.max_depth,
+                    span,
+                });
+                return Err(());
             }
 
             if !result.is_empty() {
@@ -1005,7 +1090,7 @@ impl<'a> Parser<'a> {
         Ok(result)
     }
 
-    fn parse_type_name(&mut self, terminators: &[ExpectedToken]) -> Result<ast::TypeName, ()> {
+    fn parse_type_name(&mut self, terminators: &[ExpectedToken]) -> Result<ast::TypeName<'a>, ()> {
         let leading_dot = match self.peek()? {
             Some((Token::Dot, span)) => {
                 self.bump();
@@ -1020,7 +1105,10 @@ impl<'a> Parser<'a> {
         Ok(ast::TypeName { name, leading_dot })
     }
 
-    fn parse_full_ident(&mut self, terminators: &[ExpectedToken]) -> Result<ast::FullIdent, ()> {
+    fn parse_full_ident(
+        &mut self,
+        terminators: &[ExpectedToken],
+    ) -> Result<ast::FullIdent<'a>, ()> {
         let mut result = vec![self.parse_ident()?];
 
         loop {
@@ -1040,7 +1128,7 @@ impl<'a> Parser<'a> {
         }
     }
 
-    fn parse_ident(&mut self) -> Result<ast::Ident, ()> {
+    fn parse_ident(&mut self) -> Result<ast::Ident<'a>, ()> {
         self.expect(
             |tok, span| match tok {
                 Token::Ident(value) => Some(ast::Ident::new(value, span)),
@@ -1078,7 +1166,7 @@ impl<'a> Parser<'a> {
         }
     }
 
-    fn parse_utf8_string(&mut self) -> Result<(String, Span), ()> {
+    fn parse_utf8_string(&mut self) -> Result<(Cow<'a, str>, Span), ()> {
         let bytes = self.parse_string()?;
 
         match bytes.into_utf8() {
@@ -1088,43 +1176,43 @@ impl<'a> Parser<'a> {
                     span: bytes.span.clone(),
                 });
                 Ok((
-                    String::from_utf8_lossy(&bytes.value).into_owned(),
+                    String::from_utf8_lossy(&bytes.value).into_owned().into(),
                     bytes.span,
                 ))
             }
         }
     }
 
-    fn parse_string(&mut self) -> Result<ast::String, ()> {
+    fn parse_string(&mut self) -> Result<ast::String<'a>, ()> {
         let mut result = match self.peek_skip_comments()? {
             Some((Token::StringLiteral(value), span)) => {
                 self.bump();
-                Ok(ast::String {
-                    value: value.into_owned(),
-                    span,
-                })
+                Ok(ast::String { value, span })
             }
             _ => self.unexpected_token("a string literal")?,
         }?;
 
         while let Some((Token::StringLiteral(value), span)) = self.peek_skip_comments()? {
             self.bump();
-            result.value.extend(value.as_ref());
+            result.value.to_mut().extend(value.as_ref());
             result.span = join_span(result.span.clone(), span);
         }
 
         Ok(result)
     }
 
-    fn parse_leading_comments(&mut self) -> (Vec<String>, Option<String>) {
+    fn parse_leading_comments(&mut self) -> (Vec<Cow<'a, str>>, Option<Cow<'a, str>>) {
         let _ = self.peek();
         self.comments.take_leading()
     }
 
     fn parse_trailing_comment(
         &mut self,
-        (leading_detached_comments, leading_comment): (Vec<String>, std::option::Option<String>),
-    ) -> ast::Comments {
+        (leading_detached_comments, leading_comment): (
+            Vec<Cow<'a, str>>,
+            std::option::Option<Cow<'a, str>>,
+        ),
+    ) -> ast::Comments<'a> {
         let _ = self.peek();
         let trailing_comment = self.comments.take_trailing();
 
@@ -1144,7 +1232,7 @@ impl<'a> Parser<'a> {
 
     fn expect<T>(
         &mut self,
-        mut f: impl FnMut(Token, Span) -> Option<T>,
+        mut f: impl FnMut(Token<'a>, Span) -> Option<T>,
         expected: impl ToString,
     ) -> Result<T, ()> {
         if let Some((tok, span)) = self.peek_skip_comments()? {
@@ -1180,12 +1268,17 @@ impl<'a> Parser<'a> {
             .expect("called bump without peek returning Ok()");
         match tok {
             Token::LineComment(_) | Token::BlockComment(_) | Token::Newline => (),
-            _ => self.comments.reset(),
+            _ => {
+                self.comments.reset();
+                self.prev_end = Some(span.end);
+            }
         }
         span
     }
 
     fn peek(&mut self) -> Result<Option<(Token<'a>, Span)>, ()> {
+        // The end of a block comment on the same line as the previous token.
+        let mut trailing_end = None;
         if self.lexer.span() != Span::default() {
             match self.peek_comments()? {
                 Some((Token::LineComment(comment), _)) => {
@@ -1193,13 +1286,14 @@ impl<'a> Parser<'a> {
                     self.comments.line_comment(comment);
                     self.comments.flush();
                 }
-                Some((Token::BlockComment(comment), _)) => {
+                Some((Token::BlockComment(comment), span)) => {
                     self.bump();
+                    self.comments.block_comment(comment);
                     if let Some((Token::Newline, _)) = self.peek_comments()? {
                         self.bump();
-                        self.comments.block_comment(comment);
+                        self.comments.flush();
                     } else {
-                        return self.peek_skip_comments();
+                        trailing_end = Some(span.end);
                     }
                 }
                 Some((Token::Newline, _)) => {
@@ -1227,16 +1321,36 @@ impl<'a> Parser<'a> {
                     self.comments.flush();
                     self.comments.detach();
                 }
-                tok @ (None
-                | Some((Token::RightBrace | Token::RightBracket | Token::RightParen, _))) => {
-                    self.comments.flush();
+                tok => {
+                    if matches!(
+                        tok,
+                        None | Some((
+                            Token::RightBrace | Token::RightBracket | Token::RightParen,
+                            _
+                        ))
+                    ) {
+                        self.comments.flush();
+                    }
+                    if let Some((_, span)) = &tok {
+                        if self.comment_attachment == CommentAttachment::Protoc
+                            && [self.prev_end, trailing_end]
+                                .into_iter()
+                                .flatten()
+                                .any(|end| self.is_same_line(end, span.start))
+                        {
+                            self.comments.detach_single();
+                        }
+                    }
                     return Ok(tok);
                 }
-                tok => return Ok(tok),
             }
         }
     }
 
+    fn is_same_line(&self, start: usize, end: usize) -> bool {
+        !self.lexer.source()[start..end].contains('\n')
+    }
+
     fn peek_skip_comments(&mut self) -> Result<Option<(Token<'a>, Span)>, ()> {
         loop {
             match self.peek_comments()? {
@@ -1325,7 +1439,7 @@ impl fmt::Display for ExpectedToken {
     }
 }
 
-impl Statement {
+impl Statement<'_> {
     fn span(&self) -> Span {
         match self {
             Statement::Empty(span) => span.clone(),
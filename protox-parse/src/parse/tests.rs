@@ -4,7 +4,7 @@ use super::*;
 
 macro_rules! case {
     ($method:ident($source:expr)) => {{
-        let mut parser = Parser::new($source);
+        let mut parser = Parser::new($source, crate::DEFAULT_MAX_NESTING_DEPTH);
         let result = parser.$method();
         assert_debug_snapshot!(if parser.lexer.extras.errors.is_empty() {
             Ok(result.unwrap())
@@ -320,6 +320,9 @@ pub fn parse_message() {
     case!(parse_message("message Foo { reserved 'a' }"));
     case!(parse_message("message Foo { extensions 5 to }"));
     case!(parse_message("message Foo { optional .a.b, }"));
+    case!(parse_message(
+        "message Foo { extensions 5 [declaration = { number: 5 }]; }"
+    ));
 }
 
 #[test]
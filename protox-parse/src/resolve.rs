@@ -0,0 +1,108 @@
+//! Resolution of the named types of fields.
+//!
+//! A single file does not contain enough information to tell whether a named field type, such as `Foo` in
+//! `optional Foo foo = 1;`, refers to a message or an enum. Like `protoc`, the parser therefore only sets the
+//! `type_name` of such fields, exactly as written, and leaves the `type` unset. Setting a [`TypeResolver`] with
+//! [`ParseOptions::type_resolver`](crate::ParseOptions::type_resolver) allows the parser to fill in both fields
+//! from an external source of symbols, such as previously compiled files.
+//!
+//! # Examples
+//!
+//! ```
+//! # use prost_types::field_descriptor_proto::Type;
+//! # use protox_parse::ParseOptions;
+//! let source = "
+//!     package pkg;
+//!     message Foo {
+//!         optional Bar bar = 1;
+//!         optional Unknown unknown = 2;
+//!     }
+//! ";
+//!
+//! let file = ParseOptions::new()
+//!     .type_resolver(|scope: &str, name: &str| match (scope, name) {
+//!         ("pkg.Foo", "Bar") => Some(("pkg.Bar".to_owned(), Type::Enum)),
+//!         _ => None,
+//!     })
+//!     .parse("foo.proto", source)
+//!     .unwrap();
+//!
+//! let fields = &file.message_type[0].field;
+//! assert_eq!(fields[0].type_name(), ".pkg.Bar");
+//! assert_eq!(fields[0].r#type(), Type::Enum);
+//! assert_eq!(fields[1].type_name(), "Unknown");
+//! assert_eq!(fields[1].r#type, None);
+//! ```
+
+use prost_types::{
+    field_descriptor_proto::Type, DescriptorProto, FieldDescriptorProto, FileDescriptorProto,
+};
+
+/// A source of symbols used to resolve the named types of fields.
+///
+/// See the [module documentation](self) for details. This trait is implemented for closures with the same signature
+/// as [`resolve_type`](TypeResolver::resolve_type).
+pub trait TypeResolver {
+    /// Resolves the type `name`, as written in the source, of a field declared in `scope`.
+    ///
+    /// The scope is the fully-qualified name, without a leading `.`, of the message containing the field, or the
+    /// package of the file for extensions declared at the top level. It is empty if the file has no package.
+    ///
+    /// Returns the fully-qualified name of the type and whether it is a [`Type::Message`] or [`Type::Enum`], or `None`
+    /// if the type is unknown, in which case the field is left unresolved.
+    fn resolve_type(&self, scope: &str, name: &str) -> Option<(String, Type)>;
+}
+
+impl<F> TypeResolver for F
+where
+    F: Fn(&str, &str) -> Option<(String, Type)>,
+{
+    fn resolve_type(&self, scope: &str, name: &str) -> Option<(String, Type)> {
+        self(scope, name)
+    }
+}
+
+/// Sets the type of every field in the file whose type can be resolved by `resolver`.
+pub(crate) fn resolve_types(file: &mut FileDescriptorProto, resolver: &dyn TypeResolver) {
+    let package = file.package().to_owned();
+    resolve_fields(&mut file.extension, &package, resolver);
+    for message in &mut file.message_type {
+        resolve_message(message, &package, resolver);
+    }
+}
+
+fn resolve_message(message: &mut DescriptorProto, scope: &str, resolver: &dyn TypeResolver) {
+    let scope = if scope.is_empty() {
+        message.name().to_owned()
+    } else {
+        format!("{}.{}", scope, message.name())
+    };
+
+    resolve_fields(&mut message.field, &scope, resolver);
+    resolve_fields(&mut message.extension, &scope, resolver);
+    for nested in &mut message.nested_type {
+        resolve_message(nested, &scope, resolver);
+    }
+}
+
+fn resolve_fields(fields: &mut [FieldDescriptorProto], scope: &str, resolver: &dyn TypeResolver) {
+    for field in fields {
+        if field.r#type.is_some() {
+            continue;
+        }
+        let Some(type_name) = &field.type_name else {
+            continue;
+        };
+
+        if let Some((name, ty @ (Type::Message | Type::Enum))) =
+            resolver.resolve_type(scope, type_name)
+        {
+            field.type_name = Some(if name.starts_with('.') {
+                name
+            } else {
+                format!(".{}", name)
+            });
+            field.set_type(ty);
+        }
+    }
+}
@@ -1,89 +1,129 @@
-pub(crate) const UNINTERPRETED_OPTION: i32 = 999;
-
-pub(crate) mod file {
-    pub(crate) const PACKAGE: i32 = 2;
-    pub(crate) const DEPENDENCY: i32 = 3;
-    pub(crate) const PUBLIC_DEPENDENCY: i32 = 10;
-    pub(crate) const WEAK_DEPENDENCY: i32 = 11;
-    pub(crate) const MESSAGE_TYPE: i32 = 4;
-    pub(crate) const ENUM_TYPE: i32 = 5;
-    pub(crate) const SERVICE: i32 = 6;
-    pub(crate) const EXTENSION: i32 = 7;
-    pub(crate) const OPTIONS: i32 = 8;
-    pub(crate) const SYNTAX: i32 = 12;
+//! Field numbers of the messages defined in `google/protobuf/descriptor.proto`.
+//!
+//! These are useful for building the paths of [`SourceCodeInfo`](prost_types::SourceCodeInfo) locations. Each
+//! module contains the field numbers of one message, and each constant is named after the field it identifies.
+
+#![allow(missing_docs)]
+
+/// The field number of the `uninterpreted_option` field of each options message.
+pub const UNINTERPRETED_OPTION: i32 = 999;
+
+/// Field numbers of `google.protobuf.FileDescriptorSet`.
+pub mod file_descriptor_set {
+    pub const FILE: i32 = 1;
+}
+
+/// Field numbers of `google.protobuf.FileDescriptorProto`.
+pub mod file {
+    pub const NAME: i32 = 1;
+    pub const PACKAGE: i32 = 2;
+    pub const DEPENDENCY: i32 = 3;
+    pub const PUBLIC_DEPENDENCY: i32 = 10;
+    pub const WEAK_DEPENDENCY: i32 = 11;
+    pub const MESSAGE_TYPE: i32 = 4;
+    pub const ENUM_TYPE: i32 = 5;
+    pub const SERVICE: i32 = 6;
+    pub const EXTENSION: i32 = 7;
+    pub const OPTIONS: i32 = 8;
+    pub const SOURCE_CODE_INFO: i32 = 9;
+    pub const SYNTAX: i32 = 12;
 }
 
-pub(crate) mod message {
-    pub(crate) const NAME: i32 = 1;
-    pub(crate) const FIELD: i32 = 2;
-    pub(crate) const EXTENSION: i32 = 6;
-    pub(crate) const NESTED_TYPE: i32 = 3;
-    pub(crate) const ENUM_TYPE: i32 = 4;
-    pub(crate) const EXTENSION_RANGE: i32 = 5;
-    pub(crate) const OPTIONS: i32 = 7;
-    pub(crate) const ONEOF_DECL: i32 = 8;
-    pub(crate) const RESERVED_RANGE: i32 = 9;
-    pub(crate) const RESERVED_NAME: i32 = 10;
-
-    pub(crate) mod extension_range {
-        pub(crate) const START: i32 = 1;
-        pub(crate) const END: i32 = 2;
-        pub(crate) const OPTIONS: i32 = 3;
+/// Field numbers of `google.protobuf.DescriptorProto`.
+pub mod message {
+    pub const NAME: i32 = 1;
+    pub const FIELD: i32 = 2;
+    pub const EXTENSION: i32 = 6;
+    pub const NESTED_TYPE: i32 = 3;
+    pub const ENUM_TYPE: i32 = 4;
+    pub const EXTENSION_RANGE: i32 = 5;
+    pub const OPTIONS: i32 = 7;
+    pub const ONEOF_DECL: i32 = 8;
+    pub const RESERVED_RANGE: i32 = 9;
+    pub const RESERVED_NAME: i32 = 10;
+
+    /// Field numbers of `google.protobuf.DescriptorProto.ExtensionRange`.
+    pub mod extension_range {
+        pub const START: i32 = 1;
+        pub const END: i32 = 2;
+        pub const OPTIONS: i32 = 3;
     }
 
-    pub(crate) mod reserved_range {
-        pub(crate) const START: i32 = 1;
-        pub(crate) const END: i32 = 2;
+    /// Field numbers of `google.protobuf.DescriptorProto.ReservedRange`.
+    pub mod reserved_range {
+        pub const START: i32 = 1;
+        pub const END: i32 = 2;
     }
 }
 
-pub(crate) mod field {
-    pub(crate) const NAME: i32 = 1;
-    pub(crate) const EXTENDEE: i32 = 2;
-    pub(crate) const NUMBER: i32 = 3;
-    pub(crate) const LABEL: i32 = 4;
-    pub(crate) const TYPE: i32 = 5;
-    pub(crate) const TYPE_NAME: i32 = 6;
-    pub(crate) const DEFAULT_VALUE: i32 = 7;
-    pub(crate) const JSON_NAME: i32 = 10;
-    pub(crate) const OPTIONS: i32 = 8;
+/// Field numbers of `google.protobuf.FieldDescriptorProto`.
+pub mod field {
+    pub const NAME: i32 = 1;
+    pub const EXTENDEE: i32 = 2;
+    pub const NUMBER: i32 = 3;
+    pub const LABEL: i32 = 4;
+    pub const TYPE: i32 = 5;
+    pub const TYPE_NAME: i32 = 6;
+    pub const DEFAULT_VALUE: i32 = 7;
+    pub const JSON_NAME: i32 = 10;
+    pub const OPTIONS: i32 = 8;
 }
 
-pub(crate) mod oneof {
-    pub(crate) const NAME: i32 = 1;
-    pub(crate) const OPTIONS: i32 = 2;
+/// Field numbers of `google.protobuf.OneofDescriptorProto`.
+pub mod oneof {
+    pub const NAME: i32 = 1;
+    pub const OPTIONS: i32 = 2;
 }
 
-pub(crate) mod enum_ {
-    pub(crate) const NAME: i32 = 1;
-    pub(crate) const VALUE: i32 = 2;
-    pub(crate) const OPTIONS: i32 = 3;
-    pub(crate) const RESERVED_RANGE: i32 = 4;
-    pub(crate) const RESERVED_NAME: i32 = 5;
+/// Field numbers of `google.protobuf.EnumDescriptorProto`.
+pub mod enum_ {
+    pub const NAME: i32 = 1;
+    pub const VALUE: i32 = 2;
+    pub const OPTIONS: i32 = 3;
+    pub const RESERVED_RANGE: i32 = 4;
+    pub const RESERVED_NAME: i32 = 5;
 
-    pub(crate) mod reserved_range {
-        pub(crate) const START: i32 = 1;
-        pub(crate) const END: i32 = 2;
+    /// Field numbers of `google.protobuf.EnumDescriptorProto.EnumReservedRange`.
+    pub mod reserved_range {
+        pub const START: i32 = 1;
+        pub const END: i32 = 2;
     }
 }
 
-pub(crate) mod enum_value {
-    pub(crate) const NAME: i32 = 1;
-    pub(crate) const NUMBER: i32 = 2;
-    pub(crate) const OPTIONS: i32 = 3;
+/// Field numbers of `google.protobuf.EnumValueDescriptorProto`.
+pub mod enum_value {
+    pub const NAME: i32 = 1;
+    pub const NUMBER: i32 = 2;
+    pub const OPTIONS: i32 = 3;
+}
+
+/// Field numbers of `google.protobuf.ServiceDescriptorProto`.
+pub mod service {
+    pub const NAME: i32 = 1;
+    pub const METHOD: i32 = 2;
+    pub const OPTIONS: i32 = 3;
+}
+
+/// Field numbers of `google.protobuf.MethodDescriptorProto`.
+pub mod method {
+    pub const NAME: i32 = 1;
+    pub const INPUT_TYPE: i32 = 2;
+    pub const OUTPUT_TYPE: i32 = 3;
+    pub const OPTIONS: i32 = 4;
+    pub const CLIENT_STREAMING: i32 = 5;
+    pub const SERVER_STREAMING: i32 = 6;
 }
 
-pub(crate) mod service {
-    pub(crate) const NAME: i32 = 1;
-    pub(crate) const METHOD: i32 = 2;
-    pub(crate) const OPTIONS: i32 = 3;
+/// Field numbers of `google.protobuf.FieldOptions`.
+pub mod field_options {
+    pub const CTYPE: i32 = 1;
+    pub const PACKED: i32 = 2;
+    pub const LAZY: i32 = 5;
+    pub const JSTYPE: i32 = 6;
 }
 
-pub(crate) mod method {
-    pub(crate) const NAME: i32 = 1;
-    pub(crate) const INPUT_TYPE: i32 = 2;
-    pub(crate) const OUTPUT_TYPE: i32 = 3;
-    pub(crate) const OPTIONS: i32 = 4;
-    pub(crate) const CLIENT_STREAMING: i32 = 5;
-    pub(crate) const SERVER_STREAMING: i32 = 6;
+/// Field numbers of `google.protobuf.ExtensionRangeOptions`.
+pub mod extension_range_options {
+    pub const DECLARATION: i32 = 2;
+    pub const VERIFICATION: i32 = 3;
 }
@@ -1,7 +1,10 @@
 use insta::assert_debug_snapshot;
 use prost_types::FileDescriptorProto;
 
-use crate::error::ParseErrorKind::{self, *};
+use crate::{
+    error::ParseErrorKind::{self, *},
+    fix::Edit,
+};
 
 fn parse(source: &str) -> Result<FileDescriptorProto, Vec<ParseErrorKind>> {
     crate::parse("test.proto", source).map_err(|err| err.into_inner())
@@ -196,6 +199,28 @@ fn message_reserved_range_extrema() {
         ),
         Err(vec![InvalidMessageNumber { span: 48..57 }]),
     );
+    assert_eq!(
+        parse(
+            r#"message Message {
+                reserved 5 to 1;
+            }"#
+        ),
+        Err(vec![InvalidRangeEnd {
+            kind: "reserved",
+            span: 43..49
+        }]),
+    );
+    assert_eq!(
+        parse(
+            r#"message Message {
+                extensions 5 to 1;
+            }"#
+        ),
+        Err(vec![InvalidRangeEnd {
+            kind: "extension",
+            span: 45..51
+        }]),
+    );
     assert_debug_snapshot!(parse(
         r#"message Message {
             reserved 1 to 536870911;
@@ -258,6 +283,33 @@ fn proto3_group_field() {
     );
 }
 
+#[test]
+fn invalid_group_field() {
+    assert_eq!(
+        parse("message Message { optional group foo = 1 {} }"),
+        Err(vec![InvalidGroupName { span: 33..36 }]),
+    );
+    assert_eq!(
+        parse("message Message { optional group Foo = 1 [default = 1] {} }"),
+        Err(vec![InvalidDefault {
+            kind: "group",
+            span: 42..53,
+        }]),
+    );
+}
+
+#[test]
+fn map_entry_option_set_explicitly() {
+    assert_eq!(
+        parse("message FooEntry { option map_entry = true; }"),
+        Err(vec![MapEntryOptionSet { span: 26..42 }]),
+    );
+    assert_eq!(
+        parse("message Foo { option (map_entry) = true; option map_entry = false; }"),
+        Err(vec![MapEntryOptionSet { span: 48..65 }]),
+    );
+}
+
 #[test]
 fn proto3_required_field() {
     assert_eq!(
@@ -396,6 +448,18 @@ fn enum_reserved_range_extrema() {
             InvalidEnumNumber { span: 136..146 }
         ]),
     );
+    assert_eq!(
+        parse(
+            r#"enum Extreme {
+                ZERO = 0;
+                reserved 1 to 1, 3 to -3;
+            }"#
+        ),
+        Err(vec![InvalidRangeEnd {
+            kind: "reserved",
+            span: 74..81
+        }]),
+    );
     assert_debug_snapshot!(parse(
         r#"
         syntax = "proto3";
@@ -767,3 +831,578 @@ fn options() {
         }]),
     );
 }
+
+#[test]
+fn nesting_too_deep() {
+    fn nested_messages(depth: usize) -> String {
+        "message M {".repeat(depth) + &"}".repeat(depth)
+    }
+
+    assert!(parse(&nested_messages(100)).is_ok());
+    assert_eq!(
+        parse(&nested_messages(101)),
+        Err(vec![NestingTooDeep {
+            limit: 100,
+            span: 1108..1109,
+        }]),
+    );
+    assert_eq!(
+        crate::ParseOptions::new()
+            .max_nesting_depth(1)
+            .parse("test.proto", "message Foo { optional group Bar = 1 {} }")
+            .map_err(|err| err.into_inner()),
+        Err(vec![NestingTooDeep {
+            limit: 1,
+            span: 29..32,
+        }]),
+    );
+
+    // Message values of options are also parsed recursively when the option is interpreted.
+    fn nested_option_value(depth: usize, open: &str, close: &str) -> String {
+        format!(
+            "option (foo) = {{ {}}};",
+            format!("a {} ", open).repeat(depth - 1) + &close.repeat(depth - 1)
+        )
+    }
+
+    assert!(parse(&nested_option_value(100, "{", "}")).is_ok());
+    assert_eq!(
+        parse(&nested_option_value(101, "{", "}")),
+        Err(vec![NestingTooDeep {
+            limit: 100,
+            span: 415..416,
+        }]),
+    );
+    assert!(parse(&nested_option_value(100, "<", ">")).is_ok());
+    assert_eq!(
+        parse(&nested_option_value(101, "<", ">")),
+        Err(vec![NestingTooDeep {
+            limit: 100,
+            span: 415..416,
+        }]),
+    );
+}
+
+#[test]
+fn tokenize() {
+    use crate::tokens::{tokenize, TokenKind};
+
+    let mut tokens = tokenize("test.proto", "foo 0x1F 1.5 \"\\777\" /* a */ $ 'x");
+    let kinds: Vec<_> = tokens
+        .by_ref()
+        .map(|token| (token.kind().clone(), token.span()))
+        .collect();
+    assert_eq!(
+        kinds,
+        vec![
+            (TokenKind::Ident("foo"), 0..3),
+            (TokenKind::Int(31), 4..8),
+            (TokenKind::Float(1.5), 9..12),
+            (TokenKind::String(b"".as_ref().into()), 13..19),
+            (TokenKind::BlockComment(" a ".into()), 20..27),
+            (TokenKind::Invalid, 28..29),
+            (TokenKind::String(b"x".as_ref().into()), 30..32),
+        ]
+    );
+
+    let error = tokens.take_error().unwrap();
+    assert_eq!(error.file(), "test.proto");
+    assert_eq!(
+        error.into_inner(),
+        vec![
+            InvalidStringEscape { span: 14..18 },
+            InvalidToken { span: 28..29 },
+            UnexpectedEof {
+                expected: "string terminator".to_owned()
+            },
+        ]
+    );
+    assert!(tokens.take_error().is_none());
+}
+
+#[test]
+fn option_values() {
+    use crate::value::OptionValue;
+
+    let values: Vec<_> = crate::parse_option_values(
+        "test.proto",
+        r#"
+        option (a) = { foo: 1 };
+        message Foo {
+            optional bytes b = 1 [default = "\377" '\x00'];
+            optional float f = 2 [default = -inf, (c).d = 1.5];
+        }
+        "#,
+    )
+    .unwrap()
+    .into_iter()
+    .map(|value| {
+        (
+            value.path().to_owned(),
+            value.name().to_owned(),
+            value.value().clone(),
+        )
+    })
+    .collect();
+
+    assert_eq!(
+        values,
+        vec![
+            (
+                vec![4, 0, 2, 0, 7],
+                "default".to_owned(),
+                OptionValue::String {
+                    value: vec![0xFF, 0x00],
+                    raw: r#""\377" '\x00'"#.to_owned(),
+                },
+            ),
+            (
+                vec![4, 0, 2, 1, 7],
+                "default".to_owned(),
+                OptionValue::Ident {
+                    negative: true,
+                    ident: "inf".to_owned(),
+                },
+            ),
+            (
+                vec![4, 0, 2, 1, 8, 999, 0],
+                "(c).d".to_owned(),
+                OptionValue::Float(1.5),
+            ),
+            (
+                vec![8, 999, 0],
+                "(a)".to_owned(),
+                OptionValue::Message("foo : 1".to_owned()),
+            ),
+        ]
+    );
+}
+
+#[test]
+fn fixes() {
+    let source = "syntax = 'proto3';
+message Foo {
+    required int32 a = 1;
+    repeated map<int32, int32> b = 2;
+    oneof c {
+        optional int32 d = 3;
+    }
+}
+extend Foo { required int32 e = 4; }
+";
+    let error = crate::parse("test.proto", source).unwrap_err();
+    let fixes = error.fixes();
+    assert_eq!(
+        fixes.iter().map(|fix| fix.message()).collect::<Vec<_>>(),
+        [
+            "remove label",
+            "remove label",
+            "remove label",
+            "replace 'required' with 'optional'",
+        ]
+    );
+
+    let mut fixed = source.to_owned();
+    for fix in fixes.iter().rev() {
+        fixed = fix.apply(&fixed);
+    }
+    assert_eq!(
+        fixed,
+        "syntax = 'proto3';
+message Foo {
+    int32 a = 1;
+    map<int32, int32> b = 2;
+    oneof c {
+        int32 d = 3;
+    }
+}
+extend Foo { optional int32 e = 4; }
+"
+    );
+
+    let source = "package foo; package bar;";
+    let error = crate::parse("test.proto", source).unwrap_err();
+    assert_eq!(error.fixes()[0].message(), "remove duplicate package");
+    assert_eq!(error.fixes()[0].apply(source), "package foo; ");
+
+    let error = crate::parse("test.proto", "message Foo { int32 a = 1; }").unwrap_err();
+    assert_eq!(error.fixes()[0].edits(), [Edit::insert(14, "optional ")]);
+    assert!(crate::parse("test.proto", "message {}")
+        .unwrap_err()
+        .fixes()
+        .is_empty());
+}
+
+#[test]
+fn comment_attachment() {
+    let source = "syntax = 'proto2';
+message Foo {
+    optional int32 a = 1; /* a */ }
+message Bar { /* b */ optional int32 b = 1;
+    optional int32 c = 2; /* c */ /* d */
+    optional int32 d = 3;
+}";
+    let comments = |attachment| {
+        let file = crate::ParseOptions::new()
+            .comment_attachment(attachment)
+            .parse("test.proto", source)
+            .unwrap();
+        file.source_code_info
+            .unwrap()
+            .location
+            .into_iter()
+            .filter(|location| {
+                location.leading_comments.is_some()
+                    || location.trailing_comments.is_some()
+                    || !location.leading_detached_comments.is_empty()
+            })
+            .map(|location| {
+                (
+                    location.path,
+                    location.leading_detached_comments,
+                    location.leading_comments,
+                    location.trailing_comments,
+                )
+            })
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(
+        comments(crate::CommentAttachment::Protoc),
+        [
+            (vec![4, 1, 2, 0], vec![" b ".to_owned()], None, None),
+            (vec![4, 1, 2, 1], vec![], None, Some(" c ".to_owned())),
+            (vec![4, 1, 2, 2], vec![], Some(" d ".to_owned()), None),
+        ]
+    );
+    assert_eq!(
+        comments(crate::CommentAttachment::Greedy),
+        [
+            (vec![4, 0, 2, 0], vec![], None, Some(" a ".to_owned())),
+            (vec![4, 1, 2, 0], vec![], Some(" b ".to_owned()), None),
+            (vec![4, 1, 2, 1], vec![], None, Some(" c ".to_owned())),
+            (vec![4, 1, 2, 2], vec![], Some(" d ".to_owned()), None),
+        ]
+    );
+}
+
+#[test]
+fn case_conversions() {
+    use crate::case::*;
+
+    assert_eq!(to_json_name("foo_bar_baz"), "fooBarBaz");
+    assert_eq!(to_json_name("foo__bar"), "fooBar");
+    assert_eq!(to_json_name("_foo"), "Foo");
+    assert_eq!(to_json_name("foo_1"), "foo1");
+    assert_eq!(to_pascal_case("foo_bar"), "FooBar");
+    assert_eq!(to_pascal_case("fooBar"), "FooBar");
+
+    assert_eq!(to_snake_case("FooBar"), "foo_bar");
+    assert_eq!(to_snake_case("HTTPServer"), "http_server");
+    assert_eq!(to_snake_case("foo2Bar"), "foo2_bar");
+    assert_eq!(to_snake_case("Foo_"), "foo");
+    assert_eq!(to_snake_case(""), "");
+    assert_eq!(to_upper_camel_case("foo_bar_1"), "FooBar1");
+    assert_eq!(to_upper_camel_case("FOO_BAR"), "FooBar");
+    assert_eq!(to_upper_camel_case("fooBAR"), "FooBar");
+}
+
+proptest::proptest! {
+    #[test]
+    fn prop_case_conversions_match_heck(s in "[A-Za-z0-9_]{0,24}") {
+        use heck::{ToSnakeCase, ToUpperCamelCase};
+
+        proptest::prop_assert_eq!(crate::case::to_snake_case(&s), s.to_snake_case());
+        proptest::prop_assert_eq!(crate::case::to_upper_camel_case(&s), s.to_upper_camel_case());
+    }
+}
+
+/// Generates sequences of keywords, punctuation and literals, which reach far more of the parser than random strings.
+fn token_soup() -> impl proptest::strategy::Strategy<Value = String> {
+    use proptest::prelude::*;
+
+    let token = prop_oneof![
+        Just("syntax"),
+        Just("edition"),
+        Just("package"),
+        Just("import"),
+        Just("public"),
+        Just("weak"),
+        Just("option"),
+        Just("message"),
+        Just("enum"),
+        Just("service"),
+        Just("rpc"),
+        Just("returns"),
+        Just("stream"),
+        Just("extend"),
+        Just("extensions"),
+        Just("reserved"),
+        Just("to"),
+        Just("max"),
+        Just("oneof"),
+        Just("map"),
+        Just("group"),
+        Just("optional"),
+        Just("required"),
+        Just("repeated"),
+        Just("int32"),
+        Just("string"),
+        Just("bytes"),
+        Just("float"),
+        Just("bool"),
+        Just("true"),
+        Just("inf"),
+        Just("nan"),
+        Just("default"),
+        Just("json_name"),
+        Just("Foo"),
+        Just("foo"),
+        Just("'proto2'"),
+        Just("'proto3'"),
+        Just("\"2023\""),
+        Just("'\\x1'"),
+        Just("'\\777'"),
+        Just("'\\u12'"),
+        Just("'\\U0010ffff'"),
+        Just("\"\\xzz\""),
+        Just("="),
+        Just(";"),
+        Just("{"),
+        Just("}"),
+        Just("["),
+        Just("]"),
+        Just("("),
+        Just(")"),
+        Just("<"),
+        Just(">"),
+        Just(","),
+        Just("."),
+        Just("-"),
+        Just(":"),
+        Just("/"),
+        Just("\n"),
+        Just("0"),
+        Just("1"),
+        Just("2147483647"),
+        Just("2147483648"),
+        Just("536870911"),
+        Just("536870912"),
+        Just("18446744073709551615"),
+        Just("18446744073709551616"),
+        Just("0x"),
+        Just("0x1F"),
+        Just("07"),
+        Just("08"),
+        Just("1e"),
+        Just("1e5"),
+        Just("1.5e-3"),
+        Just(".5"),
+        Just("5."),
+        Just("// c\n"),
+        Just("/* c */"),
+        Just("/*"),
+        Just("#"),
+    ];
+    proptest::collection::vec(token, 0..64).prop_map(|tokens| tokens.join(" "))
+}
+
+proptest::proptest! {
+    #[test]
+    fn prop_parse_token_soup(source in token_soup()) {
+        // Malformed input must be reported as an error, rather than panicking.
+        let _ = crate::parse("test.proto", &source);
+    }
+}
+
+#[test]
+fn enum_descriptor() {
+    use prost_types::{
+        enum_descriptor_proto::EnumReservedRange, uninterpreted_option::NamePart,
+        EnumDescriptorProto, EnumOptions, EnumValueDescriptorProto, EnumValueOptions,
+        UninterpretedOption,
+    };
+
+    // Options are not interpreted by the parser.
+    let option = |name: &str| UninterpretedOption {
+        name: vec![NamePart {
+            name_part: name.to_owned(),
+            is_extension: false,
+        }],
+        identifier_value: Some("true".to_owned()),
+        ..Default::default()
+    };
+
+    let file = parse(
+        r#"
+        enum Enum {
+            option allow_alias = true;
+            A = 0 [deprecated = true];
+            B = 0;
+            C = -1;
+            reserved 2, 5 to 10, 20 to max;
+            reserved "X", "Y";
+        }
+        "#,
+    )
+    .unwrap();
+
+    assert_eq!(
+        file.enum_type[0],
+        EnumDescriptorProto {
+            name: Some("Enum".to_owned()),
+            value: vec![
+                EnumValueDescriptorProto {
+                    name: Some("A".to_owned()),
+                    number: Some(0),
+                    options: Some(EnumValueOptions {
+                        uninterpreted_option: vec![option("deprecated")],
+                        ..Default::default()
+                    }),
+                },
+                EnumValueDescriptorProto {
+                    name: Some("B".to_owned()),
+                    number: Some(0),
+                    options: None,
+                },
+                EnumValueDescriptorProto {
+                    name: Some("C".to_owned()),
+                    number: Some(-1),
+                    options: None,
+                },
+            ],
+            options: Some(EnumOptions {
+                uninterpreted_option: vec![option("allow_alias")],
+                ..Default::default()
+            }),
+            reserved_range: vec![
+                EnumReservedRange {
+                    start: Some(2),
+                    end: Some(2),
+                },
+                EnumReservedRange {
+                    start: Some(5),
+                    end: Some(10),
+                },
+                EnumReservedRange {
+                    start: Some(20),
+                    end: Some(i32::MAX),
+                },
+            ],
+            reserved_name: vec!["X".to_owned(), "Y".to_owned()],
+        }
+    );
+}
+
+#[test]
+fn type_resolver() {
+    use prost_types::field_descriptor_proto::Type;
+
+    use crate::ParseOptions;
+
+    let source = r#"
+        syntax = "proto2";
+        package pkg;
+
+        message Outer {
+            message Inner {
+                optional Enum e = 1;
+            }
+            optional Inner inner = 1;
+            map<string, .other.Value> values = 2;
+            optional group G = 3 {}
+            optional int32 i = 4;
+        }
+
+        extend Outer {
+            optional Missing missing = 100;
+        }
+    "#;
+
+    let file = ParseOptions::new()
+        .type_resolver(|scope: &str, name: &str| match (scope, name) {
+            ("pkg.Outer.Inner", "Enum") => Some(("pkg.Enum".to_owned(), Type::Enum)),
+            ("pkg.Outer", "Inner") => Some((".pkg.Outer.Inner".to_owned(), Type::Message)),
+            ("pkg.Outer.ValuesEntry", ".other.Value") => {
+                Some((".other.Value".to_owned(), Type::Message))
+            }
+            ("pkg", "Missing") => None,
+            (scope, name) => panic!("unexpected type '{}' in scope '{}'", name, scope),
+        })
+        .parse("test.proto", source)
+        .unwrap();
+
+    let outer = &file.message_type[0];
+    assert_eq!(outer.nested_type[0].field[0].type_name(), ".pkg.Enum");
+    assert_eq!(outer.nested_type[0].field[0].r#type(), Type::Enum);
+    assert_eq!(outer.field[0].type_name(), ".pkg.Outer.Inner");
+    assert_eq!(outer.field[0].r#type(), Type::Message);
+    assert_eq!(outer.field[1].type_name(), "ValuesEntry");
+    assert_eq!(outer.nested_type[1].field[1].type_name(), ".other.Value");
+    assert_eq!(outer.nested_type[1].field[1].r#type(), Type::Message);
+    assert_eq!(outer.field[2].type_name(), "G");
+    assert_eq!(outer.field[2].r#type(), Type::Group);
+    assert_eq!(file.extension[0].type_name(), "Missing");
+    assert_eq!(file.extension[0].r#type, None);
+
+    assert_eq!(parse(source).unwrap().message_type[0].field[0].r#type, None);
+}
+
+#[test]
+fn error_limits() {
+    let parse_with = |options: &mut crate::ParseOptions, source: &str| {
+        options
+            .parse("test.proto", source)
+            .map_err(|err| err.into_inner())
+    };
+
+    let source = r#"message Foo { optional int32 a = 1 "abc\q"; optional string b = 2; $$$ }"#;
+    assert_eq!(
+        parse_with(&mut crate::ParseOptions::new(), source),
+        Err(vec![
+            InvalidStringEscape { span: 39..40 },
+            UnexpectedToken {
+                expected: "';' or '['".to_owned(),
+                found: "\"abcq\"".to_owned(),
+                span: 35..42,
+            },
+            InvalidToken { span: 67..68 },
+            InvalidToken { span: 68..69 },
+            InvalidToken { span: 69..70 },
+        ]),
+    );
+    assert_eq!(
+        parse_with(crate::ParseOptions::new().dedupe_cascading(true), source),
+        Err(vec![
+            InvalidStringEscape { span: 39..40 },
+            InvalidToken { span: 67..68 },
+        ]),
+    );
+    assert_eq!(
+        parse_with(crate::ParseOptions::new().max_errors(2), source),
+        Err(vec![
+            InvalidStringEscape { span: 39..40 },
+            UnexpectedToken {
+                expected: "';' or '['".to_owned(),
+                found: "\"abcq\"".to_owned(),
+                span: 35..42,
+            },
+        ]),
+    );
+
+    let source = "syntax = 'proto3'; message Foo { required int32 a = 1 [default = 1]; }";
+    assert_eq!(
+        parse_with(&mut crate::ParseOptions::new(), source),
+        Err(vec![
+            Proto3DefaultValue { span: 55..66 },
+            Proto3RequiredField { span: 33..41 },
+        ]),
+    );
+    assert_eq!(
+        parse_with(
+            crate::ParseOptions::new().sort_by_span(true).max_errors(0),
+            source
+        ),
+        Err(vec![Proto3RequiredField { span: 33..41 }]),
+    );
+}
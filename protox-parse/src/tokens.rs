@@ -0,0 +1,189 @@
+//! A public interface to the protobuf lexer.
+//!
+//! See the documentation for [`tokenize()`] for details.
+
+use std::{borrow::Cow, fmt, ops::Range};
+
+use logos::{Lexer, Logos};
+
+use crate::{
+    error::{ParseError, ParseErrorKind},
+    lex,
+};
+
+/// Splits a protobuf source file into [`Token`]s, without parsing it.
+///
+/// This uses the same lexer as [`parse()`](crate::parse()), so string escapes, numeric literals
+/// and comments are handled exactly as they are in full compilation. Whitespace and newlines are
+/// skipped.
+///
+/// Invalid input does not stop iteration. Unrecognized characters are returned as tokens of kind
+/// [`TokenKind::Invalid`], and any errors encountered so far can be retrieved with
+/// [`Tokens::take_error()`].
+///
+/// # Examples
+///
+/// ```
+/// # use protox_parse::tokens::{tokenize, TokenKind};
+/// let source = "optional bytes b = 1 [default = '\\xFF']; // comment";
+/// let tokens: Vec<_> = tokenize("foo.proto", source).map(|t| t.kind().clone()).collect();
+///
+/// assert_eq!(tokens, vec![
+///     TokenKind::Ident("optional"),
+///     TokenKind::Ident("bytes"),
+///     TokenKind::Ident("b"),
+///     TokenKind::Punct('='),
+///     TokenKind::Int(1),
+///     TokenKind::Punct('['),
+///     TokenKind::Ident("default"),
+///     TokenKind::Punct('='),
+///     TokenKind::String(b"\xFF".as_ref().into()),
+///     TokenKind::Punct(']'),
+///     TokenKind::Punct(';'),
+///     TokenKind::LineComment(" comment".into()),
+/// ]);
+/// ```
+pub fn tokenize<'a>(name: &str, source: &'a str) -> Tokens<'a> {
+    Tokens {
+        name: name.to_owned(),
+        lexer: lex::Token::lexer(source),
+    }
+}
+
+/// An iterator over the tokens of a protobuf source file, created by [`tokenize()`].
+pub struct Tokens<'a> {
+    name: String,
+    lexer: Lexer<'a, lex::Token<'a>>,
+}
+
+/// A single token of a protobuf source file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Token<'a> {
+    kind: TokenKind<'a>,
+    span: Range<usize>,
+}
+
+/// The kind and value of a [`Token`].
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum TokenKind<'a> {
+    /// An identifier or keyword, such as `message` or `foo_bar`.
+    Ident(&'a str),
+    /// A non-negative integer literal, in decimal, octal or hexadecimal form.
+    Int(u64),
+    /// A non-negative floating-point literal.
+    Float(f64),
+    /// A string literal, with escape sequences decoded. The original text is available from the
+    /// token's [span](Token::span).
+    String(Cow<'a, [u8]>),
+    /// A punctuation character, such as `{`, `=` or `;`.
+    Punct(char),
+    /// A `//` comment, excluding the leading slashes and trailing newline.
+    LineComment(Cow<'a, str>),
+    /// A `/* */` comment, excluding the delimiters.
+    BlockComment(Cow<'a, str>),
+    /// Input which is not a valid token.
+    Invalid,
+}
+
+impl<'a> Tokens<'a> {
+    /// Takes the errors encountered by the lexer so far, if there were any.
+    pub fn take_error(&mut self) -> Option<ParseError> {
+        if self.lexer.extras.errors.is_empty() {
+            None
+        } else {
+            let errors = std::mem::take(&mut self.lexer.extras.errors);
+            Some(ParseError::new(
+                errors,
+                &self.name,
+                self.lexer.source().to_owned(),
+            ))
+        }
+    }
+}
+
+impl<'a> Iterator for Tokens<'a> {
+    type Item = Token<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let kind = match self.lexer.next()? {
+                Ok(lex::Token::Newline) => continue,
+                Ok(token) => TokenKind::from_lex(token),
+                Err(()) => {
+                    self.lexer.extras.errors.push(ParseErrorKind::InvalidToken {
+                        span: self.lexer.span(),
+                    });
+                    TokenKind::Invalid
+                }
+            };
+
+            // Comment tokens may include trailing whitespace, which is excluded from the span.
+            let start = self.lexer.span().start;
+            let end = start + self.lexer.slice().trim_end().len();
+            return Some(Token {
+                kind,
+                span: start..end,
+            });
+        }
+    }
+}
+
+impl<'a> fmt::Debug for Tokens<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Tokens")
+            .field("name", &self.name)
+            .field("span", &self.lexer.span())
+            .finish_non_exhaustive()
+    }
+}
+
+impl<'a> Token<'a> {
+    /// Gets the kind and value of this token.
+    pub fn kind(&self) -> &TokenKind<'a> {
+        &self.kind
+    }
+
+    /// Gets the byte range of this token in the source file.
+    pub fn span(&self) -> Range<usize> {
+        self.span.clone()
+    }
+}
+
+impl<'a> TokenKind<'a> {
+    fn from_lex(token: lex::Token<'a>) -> Self {
+        match token {
+            lex::Token::Ident(value) => TokenKind::Ident(value),
+            lex::Token::IntLiteral(value) => TokenKind::Int(value),
+            lex::Token::FloatLiteral(value) => TokenKind::Float(value.0),
+            lex::Token::StringLiteral(value) => TokenKind::String(value),
+            lex::Token::LineComment(value) => TokenKind::LineComment(match value {
+                Cow::Borrowed(value) => Cow::Borrowed(value.strip_suffix('\n').unwrap_or(value)),
+                Cow::Owned(mut value) => {
+                    if value.ends_with('\n') {
+                        value.pop();
+                    }
+                    Cow::Owned(value)
+                }
+            }),
+            lex::Token::BlockComment(value) => TokenKind::BlockComment(value),
+            lex::Token::Dot => TokenKind::Punct('.'),
+            lex::Token::Minus => TokenKind::Punct('-'),
+            lex::Token::Plus => TokenKind::Punct('+'),
+            lex::Token::LeftParen => TokenKind::Punct('('),
+            lex::Token::RightParen => TokenKind::Punct(')'),
+            lex::Token::LeftBrace => TokenKind::Punct('{'),
+            lex::Token::RightBrace => TokenKind::Punct('}'),
+            lex::Token::LeftBracket => TokenKind::Punct('['),
+            lex::Token::RightBracket => TokenKind::Punct(']'),
+            lex::Token::LeftAngleBracket => TokenKind::Punct('<'),
+            lex::Token::RightAngleBracket => TokenKind::Punct('>'),
+            lex::Token::Comma => TokenKind::Punct(','),
+            lex::Token::Equals => TokenKind::Punct('='),
+            lex::Token::Colon => TokenKind::Punct(':'),
+            lex::Token::Semicolon => TokenKind::Punct(';'),
+            lex::Token::ForwardSlash => TokenKind::Punct('/'),
+            lex::Token::Newline => unreachable!("newlines are skipped"),
+        }
+    }
+}
@@ -0,0 +1,115 @@
+//! Structured values of options and field defaults.
+//!
+//! See the documentation for [`parse_option_values()`](crate::parse_option_values()) for details.
+
+use std::ops::Range;
+
+use logos::Span;
+
+use crate::ast;
+
+/// An option or default value as written in a protobuf source file.
+///
+/// Unlike the [`UninterpretedOption`](prost_types::UninterpretedOption) and
+/// [`default_value`](prost_types::FieldDescriptorProto::default_value) fields of the generated
+/// descriptor, this preserves the kind of literal used.
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum OptionValue {
+    /// An integer literal, such as `-5` or `0x2A`.
+    Int {
+        /// Whether the literal is preceded by a minus sign.
+        negative: bool,
+        /// The magnitude of the integer.
+        value: u64,
+    },
+    /// A floating-point literal, such as `1.5e3`.
+    Float(f64),
+    /// A string literal.
+    String {
+        /// The string contents, with escape sequences decoded.
+        value: Vec<u8>,
+        /// The literal as written in the source, including quotes and escape sequences. Adjacent
+        /// literals which are concatenated together are included in full.
+        raw: String,
+    },
+    /// An identifier, such as `SPEED` or `-inf`.
+    Ident {
+        /// Whether the identifier is preceded by a minus sign.
+        negative: bool,
+        /// The identifier.
+        ident: String,
+    },
+    /// A message value in the protobuf text format, without its enclosing braces.
+    Message(String),
+}
+
+/// An option or default value parsed from a source file, returned by
+/// [`parse_option_values()`](crate::parse_option_values()).
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParsedOption {
+    path: Vec<i32>,
+    name: String,
+    value: OptionValue,
+    span: Range<usize>,
+}
+
+impl ParsedOption {
+    pub(crate) fn new(
+        path: Vec<i32>,
+        name_span: Span,
+        value: ast::OptionValue,
+        source: &str,
+    ) -> Self {
+        let span = value.span();
+        let value = match value {
+            ast::OptionValue::Int(int) => OptionValue::Int {
+                negative: int.negative,
+                value: int.value,
+            },
+            ast::OptionValue::Float(float) => OptionValue::Float(float.value),
+            ast::OptionValue::String(string) => OptionValue::String {
+                value: string.value.into_owned(),
+                raw: source[string.span].to_owned(),
+            },
+            ast::OptionValue::Ident {
+                negative, ident, ..
+            } => OptionValue::Ident {
+                negative,
+                ident: ident.value.into_owned(),
+            },
+            ast::OptionValue::Aggregate(message, _) => OptionValue::Message(message.into_owned()),
+        };
+
+        ParsedOption {
+            path,
+            name: source[name_span].to_owned(),
+            value,
+            span,
+        }
+    }
+
+    /// Gets the path to this value in the generated [`FileDescriptorProto`](prost_types::FileDescriptorProto).
+    ///
+    /// This uses the same format as [`Location::path`](prost_types::source_code_info::Location::path).
+    /// For options, it points to an entry in the `uninterpreted_option` field of the options
+    /// message, and for default values it points to the field's `default_value`.
+    pub fn path(&self) -> &[i32] {
+        &self.path
+    }
+
+    /// Gets the name of the option as written in the source, for example `(my.ext).field` or `default`.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Gets the value of the option.
+    pub fn value(&self) -> &OptionValue {
+        &self.value
+    }
+
+    /// Gets the byte range of the value in the source file.
+    pub fn span(&self) -> Range<usize> {
+        self.span.clone()
+    }
+}
@@ -0,0 +1,702 @@
+//! A builder API for constructing protobuf files programmatically.
+//!
+//! Tools which synthesize protobuf definitions, for example from a database or API schema, can use [`FileBuilder`]
+//! to describe a file instead of generating source text by hand. The builder renders the file as `.proto` source with
+//! [`FileBuilder::to_source`], and the rendered source is parsed and checked exactly like a file read from disk. Any
+//! errors, and the source code info in the resulting descriptors, refer to spans within the rendered source.
+//!
+//! [`FileBuilder`] implements [`FileResolver`], so one or more builders can be passed to a [`Compiler`](crate::Compiler)
+//! to produce checked file descriptors.
+//!
+//! Names, types and option values are written to the source as-is. In particular, string option values must include
+//! quotes, and custom option names must be wrapped in parentheses, as in `"(my.option)"`.
+//!
+//! # Examples
+//!
+//! ```
+//! # use protox::{builder::FileBuilder, file::{ChainFileResolver, GoogleFileResolver}, Compiler};
+//! # use prost_types::field_descriptor_proto::Label;
+//! # fn main() -> Result<(), protox::Error> {
+//! let mut file = FileBuilder::new("greeter.proto");
+//! file.syntax("proto3").package("greeter");
+//!
+//! let request = file.add_message("HelloRequest");
+//! request.add_field("name", "string", 1);
+//! request.add_field("tags", "string", 2).label(Label::Repeated);
+//!
+//! file.add_message("HelloReply").add_field("message", "string", 1);
+//! file.add_service("Greeter")
+//!     .add_method("SayHello", "HelloRequest", "HelloReply");
+//!
+//! assert_eq!(file.to_source(), r#"syntax = "proto3";
+//!
+//! package greeter;
+//!
+//! message HelloRequest {
+//!   string name = 1;
+//!   repeated string tags = 2;
+//! }
+//!
+//! message HelloReply {
+//!   string message = 1;
+//! }
+//!
+//! service Greeter {
+//!   rpc SayHello(HelloRequest) returns (HelloReply);
+//! }
+//! "#);
+//!
+//! let mut resolver = ChainFileResolver::new();
+//! resolver.add(file);
+//! resolver.add(GoogleFileResolver::new());
+//!
+//! let mut compiler = Compiler::with_file_resolver(resolver);
+//! compiler.open_file("greeter.proto")?;
+//! assert!(compiler.descriptor_pool().get_service_by_name("greeter.Greeter").is_some());
+//! # Ok(())
+//! # }
+//! ```
+
+use std::{
+    fmt::{self, Write},
+    ops::Range,
+};
+
+use prost_types::field_descriptor_proto::Label;
+
+use crate::{
+    file::{File, FileResolver},
+    Error,
+};
+
+#[cfg(test)]
+mod tests;
+
+const INDENT: &str = "  ";
+
+/// A builder for a protobuf source file.
+///
+/// See the [module documentation](self) for an example.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FileBuilder {
+    name: String,
+    syntax: Option<String>,
+    package: Option<String>,
+    imports: Vec<String>,
+    options: Vec<(String, String)>,
+    definitions: Vec<Definition>,
+}
+
+/// A builder for a message definition, created by [`FileBuilder::add_message`] or
+/// [`MessageBuilder::add_message`].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MessageBuilder {
+    name: String,
+    comment: Option<String>,
+    options: Vec<(String, String)>,
+    members: Vec<Member>,
+    reserved_ranges: Vec<Range<i32>>,
+    reserved_names: Vec<String>,
+}
+
+/// A builder for a message field, created by [`MessageBuilder::add_field`] or [`OneofBuilder::add_field`].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FieldBuilder {
+    name: String,
+    ty: String,
+    number: i32,
+    label: Option<Label>,
+    comment: Option<String>,
+    options: Vec<(String, String)>,
+}
+
+/// A builder for a oneof, created by [`MessageBuilder::add_oneof`].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct OneofBuilder {
+    name: String,
+    comment: Option<String>,
+    fields: Vec<FieldBuilder>,
+}
+
+/// A builder for an enum definition, created by [`FileBuilder::add_enum`] or [`MessageBuilder::add_enum`].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct EnumBuilder {
+    name: String,
+    comment: Option<String>,
+    options: Vec<(String, String)>,
+    values: Vec<EnumValueBuilder>,
+}
+
+/// A builder for an enum value, created by [`EnumBuilder::add_value`].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct EnumValueBuilder {
+    name: String,
+    number: i32,
+    comment: Option<String>,
+    options: Vec<(String, String)>,
+}
+
+/// A builder for a service definition, created by [`FileBuilder::add_service`].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ServiceBuilder {
+    name: String,
+    comment: Option<String>,
+    options: Vec<(String, String)>,
+    methods: Vec<MethodBuilder>,
+}
+
+/// A builder for a service method, created by [`ServiceBuilder::add_method`].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MethodBuilder {
+    name: String,
+    input_type: String,
+    output_type: String,
+    client_streaming: bool,
+    server_streaming: bool,
+    comment: Option<String>,
+    options: Vec<(String, String)>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Definition {
+    Message(MessageBuilder),
+    Enum(EnumBuilder),
+    Service(ServiceBuilder),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Member {
+    Field(FieldBuilder),
+    Oneof(OneofBuilder),
+    Message(MessageBuilder),
+    Enum(EnumBuilder),
+}
+
+impl FileBuilder {
+    /// Creates a new, empty file with the given name.
+    pub fn new(name: impl Into<String>) -> Self {
+        FileBuilder {
+            name: name.into(),
+            ..Default::default()
+        }
+    }
+
+    /// The name of this file.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Sets the syntax of this file, for example `"proto3"`. If not set, the file has no `syntax` statement, and so
+    /// is interpreted as `proto2`.
+    pub fn syntax(&mut self, syntax: impl Into<String>) -> &mut Self {
+        self.syntax = Some(syntax.into());
+        self
+    }
+
+    /// Sets the package of this file.
+    pub fn package(&mut self, package: impl Into<String>) -> &mut Self {
+        self.package = Some(package.into());
+        self
+    }
+
+    /// Adds an import of the file with the given name.
+    pub fn import(&mut self, name: impl Into<String>) -> &mut Self {
+        self.imports.push(name.into());
+        self
+    }
+
+    /// Sets a file option.
+    pub fn option(&mut self, name: impl Into<String>, value: impl Into<String>) -> &mut Self {
+        self.options.push((name.into(), value.into()));
+        self
+    }
+
+    /// Adds a top-level message, and returns a builder for it.
+    pub fn add_message(&mut self, name: impl Into<String>) -> &mut MessageBuilder {
+        self.definitions
+            .push(Definition::Message(MessageBuilder::new(name.into())));
+        match self.definitions.last_mut() {
+            Some(Definition::Message(message)) => message,
+            _ => unreachable!(),
+        }
+    }
+
+    /// Adds a top-level enum, and returns a builder for it.
+    pub fn add_enum(&mut self, name: impl Into<String>) -> &mut EnumBuilder {
+        self.definitions
+            .push(Definition::Enum(EnumBuilder::new(name.into())));
+        match self.definitions.last_mut() {
+            Some(Definition::Enum(enum_)) => enum_,
+            _ => unreachable!(),
+        }
+    }
+
+    /// Adds a service, and returns a builder for it.
+    pub fn add_service(&mut self, name: impl Into<String>) -> &mut ServiceBuilder {
+        self.definitions.push(Definition::Service(ServiceBuilder {
+            name: name.into(),
+            ..Default::default()
+        }));
+        match self.definitions.last_mut() {
+            Some(Definition::Service(service)) => service,
+            _ => unreachable!(),
+        }
+    }
+
+    /// Renders this file as protobuf source text.
+    pub fn to_source(&self) -> String {
+        self.to_string()
+    }
+
+    /// Renders this file as source text, and parses it.
+    ///
+    /// The returned [`File`] has not yet been checked. To check it along with its imports, pass this builder to a
+    /// [`Compiler`](crate::Compiler) as a [`FileResolver`].
+    pub fn build(&self) -> Result<File, Error> {
+        File::from_source(&self.name, &self.to_source())
+    }
+}
+
+impl FileResolver for FileBuilder {
+    fn open_file(&self, name: &str) -> Result<File, Error> {
+        if name == self.name {
+            self.build()
+        } else {
+            Err(Error::file_not_found(name))
+        }
+    }
+
+    fn description(&self) -> String {
+        format!("builder for '{}'", self.name)
+    }
+}
+
+impl fmt::Display for FileBuilder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut sections = Vec::new();
+
+        if let Some(syntax) = &self.syntax {
+            sections.push(format!("syntax = \"{}\";\n", syntax));
+        }
+        if let Some(package) = &self.package {
+            sections.push(format!("package {};\n", package));
+        }
+        if !self.imports.is_empty() {
+            let mut imports = String::new();
+            for import in &self.imports {
+                writeln!(imports, "import \"{}\";", import)?;
+            }
+            sections.push(imports);
+        }
+        if !self.options.is_empty() {
+            let mut options = String::new();
+            for (name, value) in &self.options {
+                writeln!(options, "option {} = {};", name, value)?;
+            }
+            sections.push(options);
+        }
+        for definition in &self.definitions {
+            let mut out = String::new();
+            match definition {
+                Definition::Message(message) => message.write(&mut out, 0)?,
+                Definition::Enum(enum_) => enum_.write(&mut out, 0)?,
+                Definition::Service(service) => service.write(&mut out)?,
+            }
+            sections.push(out);
+        }
+
+        for (i, section) in sections.iter().enumerate() {
+            if i != 0 {
+                f.write_char('\n')?;
+            }
+            f.write_str(section)?;
+        }
+        Ok(())
+    }
+}
+
+impl MessageBuilder {
+    fn new(name: String) -> Self {
+        MessageBuilder {
+            name,
+            ..Default::default()
+        }
+    }
+
+    /// Sets a comment to be attached to this message.
+    pub fn comment(&mut self, comment: impl Into<String>) -> &mut Self {
+        self.comment = Some(comment.into());
+        self
+    }
+
+    /// Sets a message option.
+    pub fn option(&mut self, name: impl Into<String>, value: impl Into<String>) -> &mut Self {
+        self.options.push((name.into(), value.into()));
+        self
+    }
+
+    /// Adds a field, and returns a builder for it.
+    ///
+    /// The type is written to the source as-is, so it may be a scalar type such as `"int32"`, a message or enum
+    /// name, or a map type such as `"map<string, int32>"`.
+    pub fn add_field(
+        &mut self,
+        name: impl Into<String>,
+        ty: impl Into<String>,
+        number: i32,
+    ) -> &mut FieldBuilder {
+        self.members.push(Member::Field(FieldBuilder::new(
+            name.into(),
+            ty.into(),
+            number,
+        )));
+        match self.members.last_mut() {
+            Some(Member::Field(field)) => field,
+            _ => unreachable!(),
+        }
+    }
+
+    /// Adds a oneof, and returns a builder for it.
+    pub fn add_oneof(&mut self, name: impl Into<String>) -> &mut OneofBuilder {
+        self.members.push(Member::Oneof(OneofBuilder {
+            name: name.into(),
+            ..Default::default()
+        }));
+        match self.members.last_mut() {
+            Some(Member::Oneof(oneof)) => oneof,
+            _ => unreachable!(),
+        }
+    }
+
+    /// Adds a nested message, and returns a builder for it.
+    pub fn add_message(&mut self, name: impl Into<String>) -> &mut MessageBuilder {
+        self.members
+            .push(Member::Message(MessageBuilder::new(name.into())));
+        match self.members.last_mut() {
+            Some(Member::Message(message)) => message,
+            _ => unreachable!(),
+        }
+    }
+
+    /// Adds a nested enum, and returns a builder for it.
+    pub fn add_enum(&mut self, name: impl Into<String>) -> &mut EnumBuilder {
+        self.members
+            .push(Member::Enum(EnumBuilder::new(name.into())));
+        match self.members.last_mut() {
+            Some(Member::Enum(enum_)) => enum_,
+            _ => unreachable!(),
+        }
+    }
+
+    /// Reserves a range of field numbers. The end of the range is exclusive.
+    pub fn reserved_range(&mut self, range: Range<i32>) -> &mut Self {
+        self.reserved_ranges.push(range);
+        self
+    }
+
+    /// Reserves a field name.
+    pub fn reserved_name(&mut self, name: impl Into<String>) -> &mut Self {
+        self.reserved_names.push(name.into());
+        self
+    }
+
+    fn write(&self, out: &mut String, depth: usize) -> fmt::Result {
+        write_comment(out, self.comment.as_deref(), depth)?;
+        writeln!(out, "{}message {} {{", indent(depth), self.name)?;
+        for (name, value) in &self.options {
+            writeln!(out, "{}option {} = {};", indent(depth + 1), name, value)?;
+        }
+        for member in &self.members {
+            match member {
+                Member::Field(field) => field.write(out, depth + 1)?,
+                Member::Oneof(oneof) => oneof.write(out, depth + 1)?,
+                Member::Message(message) => message.write(out, depth + 1)?,
+                Member::Enum(enum_) => enum_.write(out, depth + 1)?,
+            }
+        }
+        if !self.reserved_ranges.is_empty() {
+            let ranges: Vec<String> = self
+                .reserved_ranges
+                .iter()
+                .map(|range| {
+                    if range.end - 1 == range.start {
+                        range.start.to_string()
+                    } else {
+                        format!("{} to {}", range.start, range.end - 1)
+                    }
+                })
+                .collect();
+            writeln!(out, "{}reserved {};", indent(depth + 1), ranges.join(", "))?;
+        }
+        if !self.reserved_names.is_empty() {
+            let names: Vec<String> = self
+                .reserved_names
+                .iter()
+                .map(|name| format!("\"{}\"", name))
+                .collect();
+            writeln!(out, "{}reserved {};", indent(depth + 1), names.join(", "))?;
+        }
+        writeln!(out, "{}}}", indent(depth))
+    }
+}
+
+impl FieldBuilder {
+    fn new(name: String, ty: String, number: i32) -> Self {
+        FieldBuilder {
+            name,
+            ty,
+            number,
+            ..Default::default()
+        }
+    }
+
+    /// Sets the label of this field. Fields in `proto2` files other than map fields and oneof members must have a
+    /// label.
+    pub fn label(&mut self, label: Label) -> &mut Self {
+        self.label = Some(label);
+        self
+    }
+
+    /// Sets a comment to be attached to this field.
+    pub fn comment(&mut self, comment: impl Into<String>) -> &mut Self {
+        self.comment = Some(comment.into());
+        self
+    }
+
+    /// Sets a field option, such as `default` or `json_name`.
+    pub fn option(&mut self, name: impl Into<String>, value: impl Into<String>) -> &mut Self {
+        self.options.push((name.into(), value.into()));
+        self
+    }
+
+    fn write(&self, out: &mut String, depth: usize) -> fmt::Result {
+        write_comment(out, self.comment.as_deref(), depth)?;
+        out.push_str(&indent(depth));
+        match self.label {
+            Some(Label::Optional) => out.push_str("optional "),
+            Some(Label::Required) => out.push_str("required "),
+            Some(Label::Repeated) => out.push_str("repeated "),
+            None => (),
+        }
+        write!(out, "{} {} = {}", self.ty, self.name, self.number)?;
+        write_compact_options(out, &self.options)?;
+        out.push_str(";\n");
+        Ok(())
+    }
+}
+
+impl OneofBuilder {
+    /// Sets a comment to be attached to this oneof.
+    pub fn comment(&mut self, comment: impl Into<String>) -> &mut Self {
+        self.comment = Some(comment.into());
+        self
+    }
+
+    /// Adds a field to this oneof, and returns a builder for it.
+    pub fn add_field(
+        &mut self,
+        name: impl Into<String>,
+        ty: impl Into<String>,
+        number: i32,
+    ) -> &mut FieldBuilder {
+        self.fields
+            .push(FieldBuilder::new(name.into(), ty.into(), number));
+        self.fields.last_mut().unwrap()
+    }
+
+    fn write(&self, out: &mut String, depth: usize) -> fmt::Result {
+        write_comment(out, self.comment.as_deref(), depth)?;
+        writeln!(out, "{}oneof {} {{", indent(depth), self.name)?;
+        for field in &self.fields {
+            field.write(out, depth + 1)?;
+        }
+        writeln!(out, "{}}}", indent(depth))
+    }
+}
+
+impl EnumBuilder {
+    fn new(name: String) -> Self {
+        EnumBuilder {
+            name,
+            ..Default::default()
+        }
+    }
+
+    /// Sets a comment to be attached to this enum.
+    pub fn comment(&mut self, comment: impl Into<String>) -> &mut Self {
+        self.comment = Some(comment.into());
+        self
+    }
+
+    /// Sets an enum option.
+    pub fn option(&mut self, name: impl Into<String>, value: impl Into<String>) -> &mut Self {
+        self.options.push((name.into(), value.into()));
+        self
+    }
+
+    /// Adds a value, and returns a builder for it.
+    pub fn add_value(&mut self, name: impl Into<String>, number: i32) -> &mut EnumValueBuilder {
+        self.values.push(EnumValueBuilder {
+            name: name.into(),
+            number,
+            ..Default::default()
+        });
+        self.values.last_mut().unwrap()
+    }
+
+    fn write(&self, out: &mut String, depth: usize) -> fmt::Result {
+        write_comment(out, self.comment.as_deref(), depth)?;
+        writeln!(out, "{}enum {} {{", indent(depth), self.name)?;
+        for (name, value) in &self.options {
+            writeln!(out, "{}option {} = {};", indent(depth + 1), name, value)?;
+        }
+        for value in &self.values {
+            write_comment(out, value.comment.as_deref(), depth + 1)?;
+            write!(
+                out,
+                "{}{} = {}",
+                indent(depth + 1),
+                value.name,
+                value.number
+            )?;
+            write_compact_options(out, &value.options)?;
+            out.push_str(";\n");
+        }
+        writeln!(out, "{}}}", indent(depth))
+    }
+}
+
+impl EnumValueBuilder {
+    /// Sets a comment to be attached to this enum value.
+    pub fn comment(&mut self, comment: impl Into<String>) -> &mut Self {
+        self.comment = Some(comment.into());
+        self
+    }
+
+    /// Sets an enum value option.
+    pub fn option(&mut self, name: impl Into<String>, value: impl Into<String>) -> &mut Self {
+        self.options.push((name.into(), value.into()));
+        self
+    }
+}
+
+impl ServiceBuilder {
+    /// Sets a comment to be attached to this service.
+    pub fn comment(&mut self, comment: impl Into<String>) -> &mut Self {
+        self.comment = Some(comment.into());
+        self
+    }
+
+    /// Sets a service option.
+    pub fn option(&mut self, name: impl Into<String>, value: impl Into<String>) -> &mut Self {
+        self.options.push((name.into(), value.into()));
+        self
+    }
+
+    /// Adds a method, and returns a builder for it.
+    pub fn add_method(
+        &mut self,
+        name: impl Into<String>,
+        input_type: impl Into<String>,
+        output_type: impl Into<String>,
+    ) -> &mut MethodBuilder {
+        self.methods.push(MethodBuilder {
+            name: name.into(),
+            input_type: input_type.into(),
+            output_type: output_type.into(),
+            ..Default::default()
+        });
+        self.methods.last_mut().unwrap()
+    }
+
+    fn write(&self, out: &mut String) -> fmt::Result {
+        write_comment(out, self.comment.as_deref(), 0)?;
+        writeln!(out, "service {} {{", self.name)?;
+        for (name, value) in &self.options {
+            writeln!(out, "{}option {} = {};", INDENT, name, value)?;
+        }
+        for method in &self.methods {
+            method.write(out)?;
+        }
+        out.push_str("}\n");
+        Ok(())
+    }
+}
+
+impl MethodBuilder {
+    /// Sets whether the client sends a stream of requests.
+    pub fn client_streaming(&mut self, yes: bool) -> &mut Self {
+        self.client_streaming = yes;
+        self
+    }
+
+    /// Sets whether the server sends a stream of responses.
+    pub fn server_streaming(&mut self, yes: bool) -> &mut Self {
+        self.server_streaming = yes;
+        self
+    }
+
+    /// Sets a comment to be attached to this method.
+    pub fn comment(&mut self, comment: impl Into<String>) -> &mut Self {
+        self.comment = Some(comment.into());
+        self
+    }
+
+    /// Sets a method option.
+    pub fn option(&mut self, name: impl Into<String>, value: impl Into<String>) -> &mut Self {
+        self.options.push((name.into(), value.into()));
+        self
+    }
+
+    fn write(&self, out: &mut String) -> fmt::Result {
+        write_comment(out, self.comment.as_deref(), 1)?;
+        write!(
+            out,
+            "{}rpc {}({}{}) returns ({}{})",
+            INDENT,
+            self.name,
+            if self.client_streaming { "stream " } else { "" },
+            self.input_type,
+            if self.server_streaming { "stream " } else { "" },
+            self.output_type,
+        )?;
+        if self.options.is_empty() {
+            out.push_str(";\n");
+        } else {
+            out.push_str(" {\n");
+            for (name, value) in &self.options {
+                writeln!(out, "{}option {} = {};", indent(2), name, value)?;
+            }
+            writeln!(out, "{}}}", INDENT)?;
+        }
+        Ok(())
+    }
+}
+
+fn indent(depth: usize) -> String {
+    INDENT.repeat(depth)
+}
+
+fn write_comment(out: &mut String, comment: Option<&str>, depth: usize) -> fmt::Result {
+    if let Some(comment) = comment {
+        for line in comment.lines() {
+            if line.is_empty() {
+                writeln!(out, "{}//", indent(depth))?;
+            } else {
+                writeln!(out, "{}// {}", indent(depth), line)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn write_compact_options(out: &mut String, options: &[(String, String)]) -> fmt::Result {
+    if !options.is_empty() {
+        let options: Vec<String> = options
+            .iter()
+            .map(|(name, value)| format!("{} = {}", name, value))
+            .collect();
+        write!(out, " [{}]", options.join(", "))?;
+    }
+    Ok(())
+}
@@ -0,0 +1,181 @@
+use prost_types::field_descriptor_proto::Label;
+
+use super::FileBuilder;
+use crate::{
+    file::{ChainFileResolver, GoogleFileResolver},
+    Compiler,
+};
+
+#[test]
+fn render_all_elements() {
+    let mut file = FileBuilder::new("foo.proto");
+    file.package("foo")
+        .import("google/protobuf/descriptor.proto")
+        .option("java_package", "\"com.example.foo\"")
+        .option("optimize_for", "SPEED");
+
+    let message = file.add_message("Foo");
+    message.comment("A message.\n\nWith two paragraphs.");
+    message.option("deprecated", "true");
+    message
+        .add_field("id", "int32", 1)
+        .label(Label::Required)
+        .comment("The id.");
+    message
+        .add_field("name", "string", 2)
+        .label(Label::Optional)
+        .option("default", "\"bar\"")
+        .option("json_name", "\"fooName\"");
+    message.add_field("counts", "map<string, int32>", 3);
+    let oneof = message.add_oneof("kind");
+    oneof.add_field("text", "string", 4);
+    oneof.add_field("nested", "Nested", 5);
+    message
+        .add_message("Nested")
+        .add_field("values", "Kind", 1)
+        .label(Label::Repeated)
+        .option("packed", "true");
+    message
+        .add_enum("Kind")
+        .add_value("KIND_UNSPECIFIED", 0)
+        .comment("The default.");
+    message
+        .reserved_range(10..11)
+        .reserved_range(20..30)
+        .reserved_name("old");
+
+    let enum_ = file.add_enum("Status");
+    enum_.option("allow_alias", "true");
+    enum_.add_value("STATUS_UNSPECIFIED", 0);
+    enum_
+        .add_value("STATUS_UNKNOWN", 0)
+        .option("deprecated", "true");
+
+    let service = file.add_service("FooService");
+    service.comment("A service.").option("deprecated", "true");
+    service
+        .add_method("Watch", "Foo", "Foo")
+        .client_streaming(true)
+        .server_streaming(true)
+        .option("idempotency_level", "NO_SIDE_EFFECTS");
+
+    assert_eq!(
+        file.to_source(),
+        r#"package foo;
+
+import "google/protobuf/descriptor.proto";
+
+option java_package = "com.example.foo";
+option optimize_for = SPEED;
+
+// A message.
+//
+// With two paragraphs.
+message Foo {
+  option deprecated = true;
+  // The id.
+  required int32 id = 1;
+  optional string name = 2 [default = "bar", json_name = "fooName"];
+  map<string, int32> counts = 3;
+  oneof kind {
+    string text = 4;
+    Nested nested = 5;
+  }
+  message Nested {
+    repeated Kind values = 1 [packed = true];
+  }
+  enum Kind {
+    // The default.
+    KIND_UNSPECIFIED = 0;
+  }
+  reserved 10, 20 to 29;
+  reserved "old";
+}
+
+enum Status {
+  option allow_alias = true;
+  STATUS_UNSPECIFIED = 0;
+  STATUS_UNKNOWN = 0 [deprecated = true];
+}
+
+// A service.
+service FooService {
+  option deprecated = true;
+  rpc Watch(stream Foo) returns (stream Foo) {
+    option idempotency_level = NO_SIDE_EFFECTS;
+  }
+}
+"#
+    );
+
+    let mut resolver = ChainFileResolver::new();
+    resolver.add(file);
+    resolver.add(GoogleFileResolver::new());
+
+    let mut compiler = Compiler::with_file_resolver(resolver);
+    compiler.include_source_info(true);
+    compiler.open_file("foo.proto").unwrap();
+
+    let pool = compiler.descriptor_pool();
+    let message = pool.get_message_by_name("foo.Foo").unwrap();
+    assert_eq!(message.fields().len(), 5);
+    assert!(message.get_field_by_name("counts").unwrap().is_map());
+    assert_eq!(message.oneofs().len(), 1);
+    assert_eq!(
+        message
+            .parent_file()
+            .file_descriptor_proto()
+            .source_code_info
+            .as_ref()
+            .unwrap()
+            .location
+            .iter()
+            .find(|location| location.path == [4, 0, 2, 0])
+            .unwrap()
+            .leading_comments(),
+        " The id.\n"
+    );
+}
+
+#[test]
+fn multiple_builders() {
+    let mut dep = FileBuilder::new("dep.proto");
+    dep.syntax("proto3").package("dep");
+    dep.add_message("Bar");
+
+    let mut root = FileBuilder::new("root.proto");
+    root.syntax("proto3").import("dep.proto");
+    root.add_message("Foo").add_field("bar", "dep.Bar", 1);
+
+    let mut resolver = ChainFileResolver::new();
+    resolver.add(root);
+    resolver.add(dep);
+
+    let mut compiler = Compiler::with_file_resolver(resolver);
+    compiler.open_file("root.proto").unwrap();
+
+    let field = compiler
+        .descriptor_pool()
+        .get_message_by_name("Foo")
+        .unwrap()
+        .get_field_by_name("bar")
+        .unwrap();
+    assert_eq!(field.kind().as_message().unwrap().full_name(), "dep.Bar");
+}
+
+#[test]
+fn check_error() {
+    let mut file = FileBuilder::new("foo.proto");
+    file.syntax("proto3");
+    file.add_message("Foo").add_field("bar", "Bar", 1);
+
+    let mut compiler = Compiler::with_file_resolver(file);
+    let err = compiler.open_file("foo.proto").unwrap_err();
+    assert_eq!(err.to_string(), "name 'Bar' is not defined");
+}
+
+#[test]
+fn empty_file() {
+    assert_eq!(FileBuilder::new("foo.proto").to_source(), "");
+    assert!(FileBuilder::new("foo.proto").build().is_ok());
+}
@@ -0,0 +1,318 @@
+//! Custom checks run while compiling files.
+//!
+//! A [`CustomCheck`] registered with [`Compiler::add_check`](crate::Compiler::add_check) is run on every file added
+//! with [`open_file`](crate::Compiler::open_file), after the file has been checked and added to the descriptor pool.
+//! This allows projects to enforce their own rules, such as requiring every field to be documented, within the same
+//! compile pass. Problems are reported through a [`Diagnostics`] sink using the path of the offending element, so
+//! they are rendered with source spans like any other error.
+//!
+//! # Examples
+//!
+//! ```
+//! # use protox::{check::{CustomCheck, Diagnostics}, file::{File, FileResolver}, Compiler};
+//! # use prost_reflect::FileDescriptor;
+//! # struct Resolver;
+//! # impl FileResolver for Resolver {
+//! #     fn open_file(&self, name: &str) -> Result<File, protox::Error> {
+//! #         File::from_source(name, "message Foo {\n  // The bar.\n  optional int32 bar = 1;\n  optional int32 baz = 2;\n}\n")
+//! #     }
+//! # }
+//! struct FieldsCommented;
+//!
+//! impl CustomCheck for FieldsCommented {
+//!     fn check_file(&self, file: &FileDescriptor, diagnostics: &mut Diagnostics<'_>) {
+//!         for message in file.messages() {
+//!             for field in message.fields() {
+//!                 let commented = field
+//!                     .parent_file()
+//!                     .file_descriptor_proto()
+//!                     .source_code_info
+//!                     .iter()
+//!                     .flat_map(|info| &info.location)
+//!                     .any(|location| location.path == field.path() && location.leading_comments.is_some());
+//!                 if !commented {
+//!                     diagnostics.warning(field.path(), format!("field '{}' is not documented", field.name()));
+//!                 }
+//!             }
+//!         }
+//!     }
+//! }
+//!
+//! let mut compiler = Compiler::with_file_resolver(Resolver);
+//! compiler.include_source_info(true).add_check(FieldsCommented);
+//! compiler.open_file("foo.proto")?;
+//!
+//! assert_eq!(compiler.check_warnings().len(), 1);
+//! assert_eq!(compiler.check_warnings()[0].to_string(), "field 'baz' is not documented");
+//! # Ok::<(), protox::Error>(())
+//! ```
+
+use std::fmt;
+
+use miette::{Diagnostic, LabeledSpan, NamedSource, Severity, SourceCode, SourceSpan};
+use prost_reflect::FileDescriptor;
+use prost_types::FileDescriptorProto;
+
+use crate::{compile::find_span, Error, UnusedImport};
+
+/// A user-defined check run on each file passed to [`Compiler::open_file`](crate::Compiler::open_file).
+///
+/// See the [module documentation](self) for an example. Like [`FileResolver`](crate::file::FileResolver), checks must
+/// be [`Send`] and [`Sync`].
+pub trait CustomCheck: Send + Sync {
+    /// Checks a file, reporting any problems to `diagnostics`.
+    ///
+    /// The file has already been successfully checked by the compiler, so all type names are resolved. If any
+    /// errors are reported, the file fails to compile with the first of them.
+    fn check_file(&self, file: &FileDescriptor, diagnostics: &mut Diagnostics<'_>);
+}
+
+/// Collects the problems reported by a [`CustomCheck`].
+pub struct Diagnostics<'a> {
+    file: &'a FileDescriptor,
+    source: Option<&'a str>,
+    reported: Vec<CheckDiagnostic>,
+}
+
+/// An error or warning reported by a [`CustomCheck`].
+pub struct CheckDiagnostic {
+    severity: Severity,
+    message: String,
+    help: Option<String>,
+    span: Option<SourceSpan>,
+    source_code: NamedSource<String>,
+}
+
+/// The result of checking a single file with [`Compiler::check_file_only`](crate::Compiler::check_file_only).
+pub struct FileDiagnostics {
+    file: FileDescriptorProto,
+    warnings: Vec<CheckDiagnostic>,
+    unused_imports: Vec<UnusedImport>,
+    errors: Vec<Error>,
+}
+
+impl<'a> Diagnostics<'a> {
+    pub(crate) fn new(file: &'a FileDescriptor, source: Option<&'a str>) -> Self {
+        Diagnostics {
+            file,
+            source,
+            reported: Vec::new(),
+        }
+    }
+
+    /// Reports an error, which causes the file to fail to compile.
+    ///
+    /// The `path` identifies the element the error applies to, in the same format as
+    /// [`Location::path`](prost_types::source_code_info::Location::path). The paths of descriptors can be found using
+    /// methods such as [`MessageDescriptor::path`](prost_reflect::MessageDescriptor::path). If the file has no source
+    /// code info for the path, the most specific enclosing element is used.
+    pub fn error(&mut self, path: &[i32], message: impl Into<String>) -> &mut CheckDiagnostic {
+        self.report(Severity::Error, path, message.into())
+    }
+
+    /// Reports a warning, which is available from [`Compiler::check_warnings`](crate::Compiler::check_warnings).
+    ///
+    /// See [`error`](Diagnostics::error) for the format of `path`.
+    pub fn warning(&mut self, path: &[i32], message: impl Into<String>) -> &mut CheckDiagnostic {
+        self.report(Severity::Warning, path, message.into())
+    }
+
+    fn report(
+        &mut self,
+        severity: Severity,
+        path: &[i32],
+        message: String,
+    ) -> &mut CheckDiagnostic {
+        let span = self
+            .source
+            .and_then(|source| find_span(self.file.file_descriptor_proto(), source, path));
+        self.reported.push(CheckDiagnostic {
+            severity,
+            message,
+            help: None,
+            span,
+            source_code: NamedSource::new(
+                self.file.name(),
+                self.source.unwrap_or_default().to_owned(),
+            ),
+        });
+        self.reported.last_mut().unwrap()
+    }
+
+    pub(crate) fn into_inner(self) -> Vec<CheckDiagnostic> {
+        self.reported
+    }
+}
+
+impl CheckDiagnostic {
+    /// Creates a warning which applies to a whole file, rather than an element within it.
+    pub(crate) fn file_warning(file: &str, message: impl Into<String>) -> Self {
+        CheckDiagnostic {
+            severity: Severity::Warning,
+            message: message.into(),
+            help: None,
+            span: None,
+            source_code: NamedSource::new(file, String::new()),
+        }
+    }
+
+    /// Creates a warning for the element at `path` in a file which has not yet been added to a pool.
+    pub(crate) fn warning_at(
+        file: &FileDescriptorProto,
+        source: Option<&str>,
+        path: &[i32],
+        message: impl Into<String>,
+    ) -> Self {
+        CheckDiagnostic {
+            severity: Severity::Warning,
+            message: message.into(),
+            help: None,
+            span: source.and_then(|source| find_span(file, source, path)),
+            source_code: NamedSource::new(file.name(), source.unwrap_or_default().to_owned()),
+        }
+    }
+
+    /// Sets a message suggesting how to fix the problem.
+    pub fn set_help(&mut self, help: impl Into<String>) -> &mut Self {
+        self.help = Some(help.into());
+        self
+    }
+
+    /// Returns `true` if this is an error, rather than a warning.
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+
+    /// Returns the name of the file in which the problem was reported.
+    pub fn file(&self) -> &str {
+        self.source_code.name()
+    }
+
+    /// Returns the source code span of the element the problem was reported on, if known.
+    pub fn span(&self) -> Option<SourceSpan> {
+        self.span
+    }
+
+    pub(crate) fn into_parts(
+        self,
+    ) -> (
+        String,
+        Option<String>,
+        Option<SourceSpan>,
+        NamedSource<String>,
+    ) {
+        (self.message, self.help, self.span, self.source_code)
+    }
+}
+
+impl FileDiagnostics {
+    pub(crate) fn new(
+        file: FileDescriptorProto,
+        warnings: Vec<CheckDiagnostic>,
+        unused_imports: Vec<UnusedImport>,
+        errors: Vec<Error>,
+    ) -> Self {
+        FileDiagnostics {
+            file,
+            warnings,
+            unused_imports,
+            errors,
+        }
+    }
+
+    /// Returns the checked file, with all type names resolved.
+    pub fn file_descriptor_proto(&self) -> &FileDescriptorProto {
+        &self.file
+    }
+
+    /// Returns the warnings reported while checking the file, in the same format as
+    /// [`Compiler::check_warnings`](crate::Compiler::check_warnings).
+    pub fn warnings(&self) -> &[CheckDiagnostic] {
+        &self.warnings
+    }
+
+    /// Returns the unused imports of the file, in the same format as
+    /// [`Compiler::unused_imports`](crate::Compiler::unused_imports).
+    pub fn unused_imports(&self) -> &[UnusedImport] {
+        &self.unused_imports
+    }
+
+    /// Returns the errors recorded while checking the file in [`CheckMode::Lenient`](crate::CheckMode::Lenient) mode.
+    pub fn errors(&self) -> &[Error] {
+        &self.errors
+    }
+}
+
+impl fmt::Debug for FileDiagnostics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FileDiagnostics")
+            .field("file", &self.file.name())
+            .field("warnings", &self.warnings)
+            .field("unused_imports", &self.unused_imports)
+            .field("errors", &self.errors)
+            .finish()
+    }
+}
+
+impl fmt::Debug for Diagnostics<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Diagnostics")
+            .field("file", &self.file.name())
+            .field("reported", &self.reported)
+            .finish_non_exhaustive()
+    }
+}
+
+impl fmt::Display for CheckDiagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl fmt::Debug for CheckDiagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:", self.file())?;
+        if let Some(span) = &self.span {
+            if let Ok(span_contents) = self.source_code.read_span(span, 0, 0) {
+                write!(
+                    f,
+                    "{}:{}: ",
+                    span_contents.line() + 1,
+                    span_contents.column() + 1
+                )?;
+            }
+        }
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for CheckDiagnostic {}
+
+impl Diagnostic for CheckDiagnostic {
+    fn severity(&self) -> Option<Severity> {
+        Some(self.severity)
+    }
+
+    fn help<'a>(&'a self) -> Option<Box<dyn fmt::Display + 'a>> {
+        self.help
+            .as_ref()
+            .map(|help| Box::new(help) as Box<dyn fmt::Display>)
+    }
+
+    fn source_code(&self) -> Option<&dyn SourceCode> {
+        if self.span.is_some() {
+            Some(&self.source_code)
+        } else {
+            None
+        }
+    }
+
+    fn labels(&self) -> Option<Box<dyn Iterator<Item = LabeledSpan> + '_>> {
+        self.span.map(|span| {
+            Box::new(std::iter::once(LabeledSpan::new_with_span(
+                Some("defined here".to_owned()),
+                span,
+            ))) as Box<dyn Iterator<Item = LabeledSpan>>
+        })
+    }
+}
@@ -0,0 +1,344 @@
+//! Re-encoding of files in exactly the same byte order as `protoc`, for [`Compiler::byte_compatible`].
+//!
+//! The descriptor fields generated by the parser are already in the same order as `protoc`, but two parts of the
+//! encoding differ:
+//!
+//! * Source code info locations are sorted by path, whereas `protoc` records them in the order it parses the
+//!   elements they refer to.
+//! * Custom options are encoded by field number along with the standard options. `protoc` stores them as unknown
+//!   fields, so they are written after all standard options, with one entry for each option statement in the order
+//!   they appear in the source.
+//!
+//! [`Compiler::byte_compatible`]: super::Compiler::byte_compatible
+
+use std::{cmp::Ordering, collections::HashMap};
+
+use prost::{
+    encoding::{self, WireType},
+    Message,
+};
+use prost_reflect::{
+    DynamicMessage, ExtensionDescriptor, FieldDescriptor, FileDescriptor, ReflectMessage, Value,
+};
+use prost_types::{source_code_info::Location, FileDescriptorProto, SourceCodeInfo};
+use protox_parse::tag;
+
+/// Encodes the file in the same byte order as `protoc`.
+///
+/// `encoded` is the encoding of the file with any changes for the output, such as stripped options, or `None` to use
+/// the file as it is in the pool.
+pub(crate) fn encode(file: &FileDescriptor, encoded: Option<Vec<u8>>) -> Vec<u8> {
+    let encoded = encoded.unwrap_or_else(|| file.encode_to_vec());
+    let file_desc = file
+        .parent_pool()
+        .get_message_by_name("google.protobuf.FileDescriptorProto")
+        .unwrap_or_else(|| FileDescriptorProto::default().descriptor());
+    let mut file_msg = DynamicMessage::decode(file_desc, encoded.as_slice()).unwrap();
+
+    let mut locations = Vec::new();
+    if file_msg.has_field_by_name("source_code_info") {
+        if let Some(Value::Message(source_code_info)) =
+            file_msg.get_field_by_name_mut("source_code_info")
+        {
+            let mut info: SourceCodeInfo = source_code_info.transcode_to().unwrap();
+            sort_locations(&mut info.location);
+            source_code_info.clear();
+            source_code_info.transcode_from(&info).unwrap();
+            locations = info.location;
+        }
+    }
+
+    // Sorted by path, to find the option statements which set each option.
+    let mut statements: Vec<(&[i32], usize)> = locations
+        .iter()
+        .enumerate()
+        .map(|(index, location)| (location.path.as_slice(), index))
+        .collect();
+    statements.sort_unstable();
+
+    let mut buf = Vec::with_capacity(encoded.len());
+    encode_message(&file_msg, &mut Vec::new(), &statements, &mut buf);
+    buf
+}
+
+/// Sorts locations into the order `protoc` records them in.
+///
+/// `protoc` adds a location when it starts parsing an element, so locations are mostly in order of their starting
+/// position, with each location before those nested inside it. There are two exceptions:
+///
+/// * The `extendee` of each extension field is recorded as part of the field, after the field itself.
+/// * The nested message of a group field is recorded after the field's name, number and options, followed by the
+///   `type_name` of the field and then the body of the group.
+fn sort_locations(locations: &mut Vec<Location>) {
+    let start = |location: &Location| (location.span[0], location.span[1]);
+
+    let mut by_path: HashMap<&[i32], Vec<usize>> = HashMap::new();
+    for (index, location) in locations.iter().enumerate() {
+        by_path.entry(&location.path).or_default().push(index);
+    }
+
+    // Each location is sorted no earlier than the location it is nested in. If several locations have the parent's
+    // path, such as the `extend` blocks of a file, the last one starting before the location is used.
+    let mut by_depth: Vec<usize> = (0..locations.len()).collect();
+    by_depth.sort_by_key(|&index| locations[index].path.len());
+    let mut position: Vec<(i32, i32)> = locations.iter().map(start).collect();
+    for &index in &by_depth {
+        let path = &locations[index].path;
+        let parent = (0..path.len())
+            .rev()
+            .find_map(|len| by_path.get(&path[..len]))
+            .and_then(|parents| {
+                parents
+                    .iter()
+                    .rev()
+                    .find(|&&parent| start(&locations[parent]) <= start(&locations[index]))
+                    .or(parents.first())
+            });
+        if let Some(&parent) = parent {
+            position[index] = position[index].max(position[parent]);
+        }
+    }
+
+    let mut order: Vec<usize> = (0..locations.len()).collect();
+    order.sort_by(|&l, &r| {
+        position[l]
+            .cmp(&position[r])
+            .then_with(|| locations[l].path.cmp(&locations[r].path))
+    });
+
+    for (field, group) in group_fields(locations) {
+        let moved: Vec<usize> = [
+            Some(group),
+            find_child(&by_path, locations, group, tag::message::NAME),
+            find_child(&by_path, locations, field, tag::field::TYPE_NAME),
+        ]
+        .into_iter()
+        .flatten()
+        .collect();
+        order.retain(|index| !moved.contains(index));
+        let field_path = &locations[field].path;
+        let end = order
+            .iter()
+            .rposition(|&index| locations[index].path.starts_with(field_path))
+            .map_or(order.len(), |end| end + 1);
+        order.splice(end..end, moved);
+    }
+
+    let mut sorted: Vec<_> = locations.drain(..).map(Some).collect();
+    locations.extend(order.into_iter().map(|index| sorted[index].take().unwrap()));
+}
+
+/// Finds each group field, and the location of the message it declares, which has the same span as the field.
+fn group_fields(locations: &[Location]) -> Vec<(usize, usize)> {
+    let is_field = |path: &[i32]| {
+        matches!(path, [.., tag::message::FIELD | tag::message::EXTENSION, _] if path.len() > 2)
+            || matches!(path, [tag::file::EXTENSION, _])
+    };
+    let is_message = |path: &[i32]| {
+        matches!(path, [.., tag::message::NESTED_TYPE, _] if path.len() > 2)
+            || matches!(path, [tag::file::MESSAGE_TYPE, _])
+    };
+
+    let mut messages: HashMap<&[i32], usize> = HashMap::new();
+    for (index, location) in locations.iter().enumerate() {
+        if is_message(&location.path) {
+            messages.entry(&location.span).or_insert(index);
+        }
+    }
+    locations
+        .iter()
+        .enumerate()
+        .filter(|(_, location)| is_field(&location.path))
+        .filter_map(|(field, location)| Some((field, *messages.get(location.span.as_slice())?)))
+        .collect()
+}
+
+fn find_child(
+    by_path: &HashMap<&[i32], Vec<usize>>,
+    locations: &[Location],
+    parent: usize,
+    tag: i32,
+) -> Option<usize> {
+    let mut path = locations[parent].path.clone();
+    path.push(tag);
+    by_path.get(path.as_slice())?.first().copied()
+}
+
+/// An entry to encode in a message, with the index of the location which sorts it, if any.
+enum Entry<'a> {
+    Field(FieldDescriptor, &'a Value),
+    Extension(ExtensionDescriptor, Value, Option<usize>),
+}
+
+impl Entry<'_> {
+    fn number(&self) -> u32 {
+        match self {
+            Entry::Field(field, _) => field.number(),
+            Entry::Extension(extension, _, _) => extension.number(),
+        }
+    }
+}
+
+/// Encodes a message, with any custom options in the order of the statements which set them.
+fn encode_message(
+    message: &DynamicMessage,
+    path: &mut Vec<i32>,
+    statements: &[(&[i32], usize)],
+    buf: &mut Vec<u8>,
+) {
+    let is_options = message
+        .descriptor()
+        .get_field_by_name("uninterpreted_option")
+        .is_some();
+
+    let mut entries: Vec<Entry> = message
+        .fields()
+        .map(|(field, value)| Entry::Field(field, value))
+        .collect();
+    for (extension, value) in message.extensions() {
+        if is_options {
+            path.push(extension.number() as i32);
+            split_option(&extension, value, path, statements, &mut entries);
+            path.pop();
+        } else {
+            entries.push(Entry::Extension(extension, value.clone(), None));
+        }
+    }
+    entries.sort_by(|l, r| match (l, r) {
+        (Entry::Extension(_, _, Some(l)), Entry::Extension(_, _, Some(r))) => l.cmp(r),
+        (Entry::Extension(_, _, Some(_)), _) => Ordering::Greater,
+        (_, Entry::Extension(_, _, Some(_))) => Ordering::Less,
+        _ => l.number().cmp(&r.number()),
+    });
+
+    for entry in entries {
+        match entry {
+            Entry::Field(field, value) => {
+                encode_field(message, &field, value, path, statements, buf)
+            }
+            Entry::Extension(extension, value, _) => {
+                let mut single = DynamicMessage::new(message.descriptor());
+                single.set_extension(&extension, value);
+                buf.extend_from_slice(&single.encode_to_vec());
+            }
+        }
+    }
+    for unknown in message.unknown_fields() {
+        unknown.encode(buf);
+    }
+}
+
+fn encode_field(
+    message: &DynamicMessage,
+    field: &FieldDescriptor,
+    value: &Value,
+    path: &mut Vec<i32>,
+    statements: &[(&[i32], usize)],
+    buf: &mut Vec<u8>,
+) {
+    path.push(field.number() as i32);
+    match value {
+        Value::Message(child) if !field.is_group() => {
+            encode_nested(field.number(), child, path, statements, buf);
+        }
+        Value::List(values)
+            if !field.is_group() && values.iter().all(|value| value.as_message().is_some()) =>
+        {
+            for (index, value) in values.iter().enumerate() {
+                path.push(index as i32);
+                encode_nested(
+                    field.number(),
+                    value.as_message().unwrap(),
+                    path,
+                    statements,
+                    buf,
+                );
+                path.pop();
+            }
+        }
+        _ => {
+            let mut single = DynamicMessage::new(message.descriptor());
+            single.set_field(field, value.clone());
+            buf.extend_from_slice(&single.encode_to_vec());
+        }
+    }
+    path.pop();
+}
+
+fn encode_nested(
+    number: u32,
+    message: &DynamicMessage,
+    path: &mut Vec<i32>,
+    statements: &[(&[i32], usize)],
+    buf: &mut Vec<u8>,
+) {
+    let mut nested = Vec::new();
+    encode_message(message, path, statements, &mut nested);
+    encoding::encode_key(number, WireType::LengthDelimited, buf);
+    encoding::encode_varint(nested.len() as u64, buf);
+    buf.extend_from_slice(&nested);
+}
+
+/// Splits the value of a custom option into the parts set by each option statement, in the order they are recorded.
+///
+/// If the statements cannot be determined, for example because the file has no source info, the whole value is
+/// added after the options set by statements.
+fn split_option(
+    extension: &ExtensionDescriptor,
+    value: &Value,
+    path: &[i32],
+    statements: &[(&[i32], usize)],
+    entries: &mut Vec<Entry>,
+) {
+    let start = statements.partition_point(|(statement, _)| *statement < path);
+    let mut parts: Vec<(usize, Value)> = Vec::new();
+    let mut covered: Vec<&[i32]> = Vec::new();
+    for &(statement, index) in &statements[start..] {
+        if !statement.starts_with(path) {
+            break;
+        }
+        // Skip locations within the value of an earlier statement, such as the fields of an aggregate value.
+        if covered.iter().any(|prefix| statement.starts_with(prefix)) {
+            continue;
+        }
+        covered.push(statement);
+        match extract(value, &statement[path.len()..]) {
+            Some(part) => parts.push((index, part)),
+            None => {
+                parts.clear();
+                break;
+            }
+        }
+    }
+
+    if parts.is_empty() {
+        entries.push(Entry::Extension(extension.clone(), value.clone(), None));
+    } else {
+        entries.extend(
+            parts
+                .into_iter()
+                .map(|(index, part)| Entry::Extension(extension.clone(), part, Some(index))),
+        );
+    }
+}
+
+/// Gets the part of an option value set by a statement with the given path relative to the option.
+fn extract(value: &Value, path: &[i32]) -> Option<Value> {
+    let Some((&first, rest)) = path.split_first() else {
+        return Some(value.clone());
+    };
+    match value {
+        Value::List(values) => Some(Value::List(vec![extract(
+            values.get(usize::try_from(first).ok()?)?,
+            rest,
+        )?])),
+        Value::Message(message) => {
+            let field = message.descriptor().get_field(u32::try_from(first).ok()?)?;
+            let part = extract(&message.get_field(&field), rest)?;
+            let mut branch = DynamicMessage::new(message.descriptor());
+            branch.set_field(&field, part);
+            Some(Value::Message(branch))
+        }
+        _ => None,
+    }
+}
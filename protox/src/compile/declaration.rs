@@ -0,0 +1,357 @@
+use std::collections::{HashMap, HashSet};
+
+use miette::NamedSource;
+use prost_reflect::{
+    DynamicMessage, ExtensionDescriptor, FileDescriptor, Kind, MessageDescriptor, Value,
+};
+use protox_parse::tag;
+
+use super::options::{decode_file, find_span, has_option_field};
+use crate::error::{Error, ErrorKind};
+
+const EXTENSION_RANGE_OPTIONS: &str = "google.protobuf.ExtensionRangeOptions";
+
+/// The value of `google.protobuf.ExtensionRangeOptions.VerificationState.DECLARATION`.
+const VERIFICATION_DECLARATION: i32 = 0;
+/// The value of `google.protobuf.ExtensionRangeOptions.VerificationState.UNVERIFIED`.
+const VERIFICATION_UNVERIFIED: i32 = 1;
+
+/// Checks the extension declarations of the extension ranges in the file, and that extensions declared in the file
+/// match the declarations of the message they extend.
+pub(crate) fn check_extension_declarations(
+    file: &FileDescriptor,
+    source: Option<&str>,
+) -> Result<(), Error> {
+    if !has_option_field(file.parent_pool(), EXTENSION_RANGE_OPTIONS, "declaration") {
+        return Ok(());
+    }
+    let Some(file_msg) = decode_file(file) else {
+        return Ok(());
+    };
+
+    let error = |path: &[i32], message: String| {
+        Error::from_kind(ErrorKind::InvalidExtensionDeclaration {
+            message,
+            span: source.and_then(|source| find_span(file.file_descriptor_proto(), source, path)),
+            source_code: NamedSource::new(file.name(), source.unwrap_or_default().to_owned()),
+        })
+    };
+
+    let mut messages: Vec<(Vec<i32>, &DynamicMessage)> =
+        children(&file_msg, tag::file::MESSAGE_TYPE)
+            .enumerate()
+            .map(|(index, message)| (vec![tag::file::MESSAGE_TYPE, index as i32], message))
+            .collect();
+    while let Some((path, message)) = messages.pop() {
+        check_ranges(message, &path).map_err(|(path, message)| error(&path, message))?;
+        for (index, nested) in children(message, tag::message::NESTED_TYPE).enumerate() {
+            let mut nested_path = path.clone();
+            nested_path.extend([tag::message::NESTED_TYPE, index as i32]);
+            messages.push((nested_path, nested));
+        }
+    }
+
+    let mut extendee_files = HashMap::new();
+    for extension in all_extensions(file) {
+        let extendee = extension.containing_message();
+        let extendee_file = extendee_files
+            .entry(extendee.parent_file().name().to_owned())
+            .or_insert_with(|| decode_file(&extendee.parent_file()));
+        let Some(extendee_file) = extendee_file else {
+            continue;
+        };
+        let Some(extendee_msg) = find_message(extendee_file, &extendee) else {
+            continue;
+        };
+
+        check_extension(&extension, &extendee, extendee_msg)
+            .map_err(|message| error(extension.path(), message))?;
+    }
+
+    Ok(())
+}
+
+/// Validates the declarations of each extension range in a message.
+fn check_ranges(message: &DynamicMessage, path: &[i32]) -> Result<(), (Vec<i32>, String)> {
+    let mut numbers = HashSet::new();
+    let mut names = HashSet::new();
+    for (index, range) in children(message, tag::message::EXTENSION_RANGE).enumerate() {
+        let Some(options) = child(range, tag::message::extension_range::OPTIONS) else {
+            continue;
+        };
+        let mut options_path = path.to_vec();
+        options_path.extend([
+            tag::message::EXTENSION_RANGE,
+            index as i32,
+            tag::message::extension_range::OPTIONS,
+        ]);
+
+        let declarations: Vec<_> =
+            children(options, tag::extension_range_options::DECLARATION).collect();
+        if declarations.is_empty() {
+            continue;
+        }
+        if verification(options) == Some(VERIFICATION_UNVERIFIED) {
+            options_path.push(tag::extension_range_options::VERIFICATION);
+            return Err((
+                options_path,
+                "cannot mark the extension range as UNVERIFIED when it has extensions declared"
+                    .to_owned(),
+            ));
+        }
+
+        let start = get_i32(range, tag::message::extension_range::START);
+        let end = get_i32(range, tag::message::extension_range::END);
+        options_path.push(tag::extension_range_options::DECLARATION);
+        for (index, declaration) in declarations.into_iter().enumerate() {
+            let declaration = Declaration::new(declaration);
+            if !(start..end).contains(&declaration.number) {
+                return Err((
+                    options_path,
+                    format!(
+                        "extension declaration number {} is not in the extension range",
+                        declaration.number
+                    ),
+                ));
+            }
+            if !numbers.insert(declaration.number) {
+                return Err((
+                    options_path,
+                    format!(
+                        "extension declaration number {} is declared multiple times",
+                        declaration.number
+                    ),
+                ));
+            }
+
+            if !declaration.reserved
+                && (declaration.full_name.is_empty() || declaration.ty.is_empty())
+            {
+                return Err((
+                    options_path,
+                    format!(
+                        "extension declaration #{} should have both 'full_name' and 'type' set",
+                        index
+                    ),
+                ));
+            }
+            if !declaration.full_name.is_empty() {
+                if !declaration.full_name.starts_with('.') {
+                    return Err((
+                        options_path,
+                        format!(
+                            "extension declaration name '{}' must be fully-qualified with a leading '.'",
+                            declaration.full_name
+                        ),
+                    ));
+                }
+                if !names.insert(declaration.full_name.clone()) {
+                    return Err((
+                        options_path,
+                        format!(
+                            "extension name '{}' is declared multiple times",
+                            declaration.full_name
+                        ),
+                    ));
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Checks an extension against the declarations of the range of its extendee which contains its number.
+fn check_extension(
+    extension: &ExtensionDescriptor,
+    extendee: &MessageDescriptor,
+    extendee_msg: &DynamicMessage,
+) -> Result<(), String> {
+    let number = extension.number() as i32;
+    let Some(options) = children(extendee_msg, tag::message::EXTENSION_RANGE)
+        .find(|range| {
+            (get_i32(range, tag::message::extension_range::START)
+                ..get_i32(range, tag::message::extension_range::END))
+                .contains(&number)
+        })
+        .and_then(|range| child(range, tag::message::extension_range::OPTIONS))
+    else {
+        return Ok(());
+    };
+
+    let mut declarations = children(options, tag::extension_range_options::DECLARATION).peekable();
+    let is_verified =
+        declarations.peek().is_some() || verification(options) == Some(VERIFICATION_DECLARATION);
+    let Some(declaration) = declarations
+        .map(Declaration::new)
+        .find(|declaration| declaration.number == number)
+    else {
+        if is_verified {
+            return Err(format!(
+                "missing extension declaration for field '{}' with number {} in extendee message '{}'",
+                extension.full_name(),
+                number,
+                extendee.full_name()
+            ));
+        }
+        return Ok(());
+    };
+
+    if declaration.reserved {
+        return Err(format!(
+            "cannot use number {} for extension field '{}', as it is reserved in the extension declarations for message '{}'",
+            number,
+            extension.full_name(),
+            extendee.full_name()
+        ));
+    }
+
+    let full_name = format!(".{}", extension.full_name());
+    if !declaration.full_name.is_empty() && declaration.full_name != full_name {
+        return Err(format!(
+            "extension field {} of '{}' is expected to have name '{}', not '{}'",
+            number,
+            extendee.full_name(),
+            declaration.full_name,
+            full_name
+        ));
+    }
+
+    let ty = type_name(extension);
+    if !declaration.ty.is_empty() && declaration.ty != ty {
+        return Err(format!(
+            "extension field {} of '{}' is expected to be type '{}', not '{}'",
+            number,
+            extendee.full_name(),
+            declaration.ty,
+            ty
+        ));
+    }
+
+    if declaration.repeated != extension.is_list() {
+        return Err(format!(
+            "extension field {} of '{}' is expected to be {}",
+            number,
+            extendee.full_name(),
+            if declaration.repeated {
+                "repeated"
+            } else {
+                "optional"
+            }
+        ));
+    }
+
+    Ok(())
+}
+
+struct Declaration {
+    number: i32,
+    full_name: String,
+    ty: String,
+    reserved: bool,
+    repeated: bool,
+}
+
+impl Declaration {
+    fn new(message: &DynamicMessage) -> Self {
+        let get_str = |name| {
+            message
+                .get_field_by_name(name)
+                .and_then(|value| value.as_str().map(ToOwned::to_owned))
+                .unwrap_or_default()
+        };
+        let get_bool = |name| {
+            message
+                .get_field_by_name(name)
+                .and_then(|value| value.as_bool())
+                .unwrap_or_default()
+        };
+
+        Declaration {
+            number: message
+                .get_field_by_name("number")
+                .and_then(|value| value.as_i32())
+                .unwrap_or_default(),
+            full_name: get_str("full_name"),
+            ty: get_str("type"),
+            reserved: get_bool("reserved"),
+            repeated: get_bool("repeated"),
+        }
+    }
+}
+
+/// Formats the type of an extension in the same way as the `type` field of a declaration.
+fn type_name(extension: &ExtensionDescriptor) -> String {
+    match extension.kind() {
+        Kind::Message(message) => format!(".{}", message.full_name()),
+        Kind::Enum(enum_) => format!(".{}", enum_.full_name()),
+        Kind::Double => "double".to_owned(),
+        Kind::Float => "float".to_owned(),
+        Kind::Int32 => "int32".to_owned(),
+        Kind::Int64 => "int64".to_owned(),
+        Kind::Uint32 => "uint32".to_owned(),
+        Kind::Uint64 => "uint64".to_owned(),
+        Kind::Sint32 => "sint32".to_owned(),
+        Kind::Sint64 => "sint64".to_owned(),
+        Kind::Fixed32 => "fixed32".to_owned(),
+        Kind::Fixed64 => "fixed64".to_owned(),
+        Kind::Sfixed32 => "sfixed32".to_owned(),
+        Kind::Sfixed64 => "sfixed64".to_owned(),
+        Kind::Bool => "bool".to_owned(),
+        Kind::String => "string".to_owned(),
+        Kind::Bytes => "bytes".to_owned(),
+    }
+}
+
+/// Gets all extensions declared in a file, including those nested in messages.
+fn all_extensions(file: &FileDescriptor) -> Vec<ExtensionDescriptor> {
+    let mut extensions: Vec<_> = file.extensions().collect();
+    let mut messages: Vec<_> = file.messages().collect();
+    while let Some(message) = messages.pop() {
+        extensions.extend(message.child_extensions());
+        messages.extend(message.child_messages());
+    }
+    extensions
+}
+
+/// Finds the dynamic representation of a message in its decoded file.
+fn find_message<'a>(
+    file_msg: &'a DynamicMessage,
+    message: &MessageDescriptor,
+) -> Option<&'a DynamicMessage> {
+    let mut current = file_msg;
+    for part in message.path().chunks(2) {
+        current = children(current, part[0]).nth(part[1] as usize)?;
+    }
+    Some(current)
+}
+
+fn verification(options: &DynamicMessage) -> Option<i32> {
+    options
+        .fields()
+        .find(|(field, _)| field.number() == tag::extension_range_options::VERIFICATION as u32)
+        .and_then(|(_, value)| value.as_enum_number())
+}
+
+fn get_i32(message: &DynamicMessage, number: i32) -> i32 {
+    message
+        .get_field_by_number(number as u32)
+        .and_then(|value| value.as_i32())
+        .unwrap_or_default()
+}
+
+fn child(message: &DynamicMessage, number: i32) -> Option<&DynamicMessage> {
+    message
+        .fields()
+        .find(|(field, _)| field.number() == number as u32)
+        .and_then(|(_, value)| value.as_message())
+}
+
+fn children(message: &DynamicMessage, number: i32) -> impl Iterator<Item = &DynamicMessage> {
+    message
+        .fields()
+        .find(|(field, _)| field.number() == number as u32)
+        .and_then(|(_, value)| value.as_list())
+        .unwrap_or_default()
+        .iter()
+        .filter_map(Value::as_message)
+}
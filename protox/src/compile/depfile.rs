@@ -0,0 +1,35 @@
+use std::{fmt::Write, path::Path};
+
+/// Formats a Makefile rule listing `dependencies` as prerequisites of every output.
+///
+/// This is the format used by `protoc --dependency_out`, and understood by `make`, `ninja` and other build tools.
+pub(crate) fn format_depfile<'a>(
+    outputs: &[&Path],
+    dependencies: impl IntoIterator<Item = &'a Path>,
+) -> String {
+    let mut depfile = String::new();
+    for (i, output) in outputs.iter().enumerate() {
+        if i != 0 {
+            depfile.push(' ');
+        }
+        write_escaped(&mut depfile, output);
+    }
+    depfile.push(':');
+    for dependency in dependencies {
+        depfile.push_str(" \\\n  ");
+        write_escaped(&mut depfile, dependency);
+    }
+    depfile.push('\n');
+    depfile
+}
+
+/// Escapes characters which have a special meaning in Makefile rules.
+fn write_escaped(depfile: &mut String, path: &Path) {
+    for ch in path.display().to_string().chars() {
+        match ch {
+            ' ' | '#' => write!(depfile, "\\{}", ch).unwrap(),
+            '$' => depfile.push_str("$$"),
+            _ => depfile.push(ch),
+        }
+    }
+}
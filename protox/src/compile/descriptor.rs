@@ -0,0 +1,380 @@
+use miette::NamedSource;
+use prost_reflect::{FileDescriptor, Kind};
+use prost_types::{
+    field_descriptor_proto::{Label, Type},
+    DescriptorProto, EnumDescriptorProto, FieldDescriptorProto, FileDescriptorProto,
+};
+
+use crate::error::{Error, ErrorKind};
+
+/// Checks the syntax of a pre-compiled file.
+///
+/// This is checked before the file is added to the descriptor pool, which does not handle unknown syntaxes.
+pub(crate) fn check_syntax(file: &FileDescriptorProto) -> Result<(), Error> {
+    match file.syntax() {
+        "" | "proto2" | "proto3" => Ok(()),
+        syntax => Err(error(file, format!("unknown syntax '{}'", syntax))),
+    }
+}
+
+/// Checks the rules enforced by the parser for source files, which are not checked by the descriptor pool.
+///
+/// This only reports errors in pre-compiled files, which have no source to find spans in.
+pub(crate) fn check_descriptor(file: &FileDescriptorProto) -> Result<(), Error> {
+    let is_proto3 = file.syntax() == "proto3";
+    if !file.package().is_empty() && !file.package().split('.').all(is_valid_name) {
+        return Err(error(
+            file,
+            format!("package name '{}' is not valid", file.package()),
+        ));
+    }
+
+    let mut messages: Vec<(String, &DescriptorProto)> = file
+        .message_type
+        .iter()
+        .map(|message| (qualify(file.package(), message.name()), message))
+        .collect();
+    let mut enums: Vec<(String, &EnumDescriptorProto)> = file
+        .enum_type
+        .iter()
+        .map(|enum_| (qualify(file.package(), enum_.name()), enum_))
+        .collect();
+    for extension in &file.extension {
+        check_field(file, file.package(), extension, true, is_proto3)?;
+    }
+    for service in &file.service {
+        let name = qualify(file.package(), service.name());
+        check_name(file, "service", &name, service.name())?;
+        for method in &service.method {
+            check_name(
+                file,
+                "method",
+                &qualify(&name, method.name()),
+                method.name(),
+            )?;
+        }
+    }
+
+    while let Some((name, message)) = messages.pop() {
+        check_name(file, "message", &name, message.name())?;
+        if is_proto3 && !message.extension_range.is_empty() {
+            return Err(error(
+                file,
+                format!(
+                    "extension ranges are not allowed in proto3 message '{}'",
+                    name
+                ),
+            ));
+        }
+        for range in &message.reserved_range {
+            check_range(
+                file,
+                "reserved",
+                &name,
+                range.start(),
+                range.end().saturating_sub(1),
+            )?;
+        }
+        for range in &message.extension_range {
+            check_range(
+                file,
+                "extension",
+                &name,
+                range.start(),
+                range.end().saturating_sub(1),
+            )?;
+        }
+
+        for field in &message.field {
+            check_field(file, &name, field, false, is_proto3)?;
+            if message
+                .reserved_name
+                .iter()
+                .any(|reserved| reserved == field.name())
+            {
+                return Err(error(
+                    file,
+                    format!(
+                        "field name '{}' is reserved in message '{}'",
+                        field.name(),
+                        name
+                    ),
+                ));
+            }
+        }
+        for extension in &message.extension {
+            check_field(file, &name, extension, true, is_proto3)?;
+        }
+        for oneof in &message.oneof_decl {
+            check_name(file, "oneof", &qualify(&name, oneof.name()), oneof.name())?;
+        }
+        for reserved in &message.reserved_name {
+            check_reserved_name(file, &name, reserved)?;
+        }
+
+        messages.extend(
+            message
+                .nested_type
+                .iter()
+                .map(|nested| (qualify(&name, nested.name()), nested)),
+        );
+        enums.extend(
+            message
+                .enum_type
+                .iter()
+                .map(|enum_| (qualify(&name, enum_.name()), enum_)),
+        );
+    }
+
+    for (name, enum_) in enums {
+        check_name(file, "enum", &name, enum_.name())?;
+        for range in &enum_.reserved_range {
+            check_range(file, "reserved", &name, range.start(), range.end())?;
+        }
+        for value in &enum_.value {
+            check_name(
+                file,
+                "enum value",
+                &qualify(&name, value.name()),
+                value.name(),
+            )?;
+            if enum_
+                .reserved_name
+                .iter()
+                .any(|reserved| reserved == value.name())
+            {
+                return Err(error(
+                    file,
+                    format!(
+                        "enum value name '{}' is reserved in enum '{}'",
+                        value.name(),
+                        name
+                    ),
+                ));
+            }
+        }
+        for reserved in &enum_.reserved_name {
+            check_reserved_name(file, &name, reserved)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// The type of a field in a pre-compiled file which refers to a message or enum.
+pub(crate) struct DeclaredType {
+    name: String,
+    is_extension: bool,
+    ty: Type,
+}
+
+/// Collects the types of the fields in a pre-compiled file which refer to a message or enum.
+///
+/// The descriptor pool replaces these with the kind of the type each field refers to, so they must be collected
+/// before the file is added, and checked afterwards by [`check_field_types`].
+pub(crate) fn declared_types(file: &FileDescriptorProto) -> Vec<DeclaredType> {
+    let mut types = Vec::new();
+    let mut add = |scope: &str, fields: &[FieldDescriptorProto], is_extension: bool| {
+        for field in fields {
+            if let Some(ty @ (Type::Message | Type::Group | Type::Enum)) =
+                field.r#type.and_then(|ty| Type::try_from(ty).ok())
+            {
+                types.push(DeclaredType {
+                    name: qualify(scope, field.name()),
+                    is_extension,
+                    ty,
+                });
+            }
+        }
+    };
+
+    add(file.package(), &file.extension, true);
+    let mut messages: Vec<(String, &DescriptorProto)> = file
+        .message_type
+        .iter()
+        .map(|message| (qualify(file.package(), message.name()), message))
+        .collect();
+    while let Some((name, message)) = messages.pop() {
+        add(&name, &message.field, false);
+        add(&name, &message.extension, true);
+        messages.extend(
+            message
+                .nested_type
+                .iter()
+                .map(|nested| (qualify(&name, nested.name()), nested)),
+        );
+    }
+    types
+}
+
+/// Checks that each field in a pre-compiled file refers to a type of the kind it was declared with.
+pub(crate) fn check_field_types(
+    file: &FileDescriptor,
+    declared: &[DeclaredType],
+) -> Result<(), Error> {
+    let pool = file.parent_pool();
+    for declared in declared {
+        let kind = if declared.is_extension {
+            pool.get_extension_by_name(&declared.name)
+                .map(|extension| extension.kind())
+        } else {
+            declared.name.rsplit_once('.').and_then(|(message, field)| {
+                pool.get_message_by_name(message)?
+                    .get_field_by_name(field)
+                    .map(|field| field.kind())
+            })
+        };
+
+        let mismatch = match (declared.ty, kind) {
+            (Type::Message | Type::Group, Some(Kind::Enum(enum_))) => {
+                Some((enum_.full_name().to_owned(), "a message"))
+            }
+            (Type::Enum, Some(Kind::Message(message))) => {
+                Some((message.full_name().to_owned(), "an enum"))
+            }
+            _ => None,
+        };
+        if let Some((target, expected)) = mismatch {
+            return Err(error(
+                file.file_descriptor_proto(),
+                format!(
+                    "field '{}' refers to '{}', which is not {} type",
+                    declared.name, target, expected
+                ),
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn check_field(
+    file: &FileDescriptorProto,
+    scope: &str,
+    field: &FieldDescriptorProto,
+    is_extension: bool,
+    is_proto3: bool,
+) -> Result<(), Error> {
+    let name = qualify(scope, field.name());
+    check_name(file, "field", &name, field.name())?;
+    if field.r#type.is_none() && field.type_name.is_none() {
+        return Err(error(file, format!("field '{}' has no type", name)));
+    }
+
+    let label = field.label.and_then(|label| Label::try_from(label).ok());
+    if field.default_value.is_some() {
+        if label == Some(Label::Repeated) {
+            return Err(error(
+                file,
+                format!("repeated field '{}' cannot have a default value", name),
+            ));
+        }
+        if is_proto3 {
+            return Err(error(
+                file,
+                format!("default value of field '{}' is not allowed in proto3", name),
+            ));
+        }
+    }
+    if label == Some(Label::Required) {
+        if is_extension {
+            return Err(error(
+                file,
+                format!("extension field '{}' may not be required", name),
+            ));
+        }
+        if is_proto3 {
+            return Err(error(
+                file,
+                format!("required field '{}' is not allowed in proto3", name),
+            ));
+        }
+    }
+    if is_proto3 && field.r#type == Some(Type::Group as i32) {
+        return Err(error(
+            file,
+            format!("group field '{}' is not allowed in proto3", name),
+        ));
+    }
+    // Extensions are not in a oneof, but protoc still sets `proto3_optional` for optional extensions in proto3 files.
+    if field.proto3_optional() && (!is_proto3 || (!is_extension && field.oneof_index.is_none())) {
+        return Err(error(
+            file,
+            format!(
+                "field '{}' with 'proto3_optional' set must be in a oneof of a proto3 message",
+                name
+            ),
+        ));
+    }
+    Ok(())
+}
+
+fn check_name(
+    file: &FileDescriptorProto,
+    kind: &str,
+    full_name: &str,
+    name: &str,
+) -> Result<(), Error> {
+    if is_valid_name(name) {
+        Ok(())
+    } else {
+        Err(error(
+            file,
+            format!("{} name '{}' is not valid", kind, full_name),
+        ))
+    }
+}
+
+fn check_reserved_name(file: &FileDescriptorProto, scope: &str, name: &str) -> Result<(), Error> {
+    if is_valid_name(name) {
+        Ok(())
+    } else {
+        Err(error(
+            file,
+            format!("reserved name '{}' in '{}' is not valid", name, scope),
+        ))
+    }
+}
+
+fn check_range(
+    file: &FileDescriptorProto,
+    kind: &str,
+    name: &str,
+    start: i32,
+    end: i32,
+) -> Result<(), Error> {
+    if end < start {
+        return Err(error(
+            file,
+            format!(
+                "{} range end number must be greater than start number in '{}'",
+                kind, name
+            ),
+        ));
+    }
+    Ok(())
+}
+
+/// Returns `true` if `name` is a valid name for a definition. Like protoc, names of pre-compiled definitions may
+/// start with a digit.
+fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .bytes()
+            .all(|ch| ch.is_ascii_alphanumeric() || ch == b'_')
+}
+
+fn qualify(scope: &str, name: &str) -> String {
+    if scope.is_empty() {
+        name.to_owned()
+    } else {
+        format!("{}.{}", scope, name)
+    }
+}
+
+fn error(file: &FileDescriptorProto, message: String) -> Error {
+    Error::from_kind(ErrorKind::InvalidDescriptor {
+        message,
+        span: None,
+        source_code: NamedSource::new(file.name(), String::new()),
+    })
+}
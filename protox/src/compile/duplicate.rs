@@ -0,0 +1,139 @@
+use std::collections::HashMap;
+
+use miette::NamedSource;
+use prost_reflect::{DescriptorPool, FileDescriptor};
+use prost_types::FileDescriptorProto;
+use protox_parse::tag;
+
+use super::options::find_span;
+use crate::error::{Error, ErrorKind, NameDefinition};
+
+/// A package-level name defined by a file which is already defined by another file in the pool.
+pub(crate) struct DuplicateName {
+    name: String,
+    path: Vec<i32>,
+    existing: FileDescriptor,
+    existing_path: Vec<i32>,
+}
+
+/// Finds the first package-level name defined by `file` which is already defined by a file in the pool.
+///
+/// The pool would also reject the file, but its error can only point at the definition in the file being added. This
+/// is checked beforehand so that the error can include the location of both definitions, even if the other file was
+/// added from a file descriptor set.
+pub(crate) fn find_duplicate_name(
+    pool: &DescriptorPool,
+    file: &FileDescriptorProto,
+) -> Option<DuplicateName> {
+    let names = file
+        .message_type
+        .iter()
+        .enumerate()
+        .map(|(index, message)| (tag::file::MESSAGE_TYPE, index, message.name()))
+        .chain(
+            file.enum_type
+                .iter()
+                .enumerate()
+                .map(|(index, enum_)| (tag::file::ENUM_TYPE, index, enum_.name())),
+        )
+        .chain(
+            file.service
+                .iter()
+                .enumerate()
+                .map(|(index, service)| (tag::file::SERVICE, index, service.name())),
+        )
+        .chain(
+            file.extension
+                .iter()
+                .enumerate()
+                .map(|(index, extension)| (tag::file::EXTENSION, index, extension.name())),
+        );
+
+    for (tag, index, name) in names {
+        let name = if file.package().is_empty() {
+            name.to_owned()
+        } else {
+            format!("{}.{}", file.package(), name)
+        };
+        let existing = if let Some(message) = pool.get_message_by_name(&name) {
+            Some((message.parent_file(), message.path().to_vec()))
+        } else if let Some(enum_) = pool.get_enum_by_name(&name) {
+            Some((enum_.parent_file(), enum_.path().to_vec()))
+        } else if let Some(service) = pool.get_service_by_name(&name) {
+            Some((service.parent_file(), service.path().to_vec()))
+        } else {
+            pool.get_extension_by_name(&name)
+                .map(|extension| (extension.parent_file(), extension.path().to_vec()))
+        };
+
+        if let Some((existing, mut existing_path)) = existing {
+            existing_path.push(tag::message::NAME);
+            return Some(DuplicateName {
+                name,
+                path: vec![tag, index as i32, tag::message::NAME],
+                existing,
+                existing_path,
+            });
+        }
+    }
+
+    None
+}
+
+impl DuplicateName {
+    /// Gets the name of the file which first defined the name.
+    pub fn existing_file(&self) -> &str {
+        self.existing.name()
+    }
+
+    /// Returns `true` if `file` has the same contents as the file which first defined the name, apart from its name.
+    ///
+    /// If the sources of both files are available they are compared directly, otherwise the file descriptors are
+    /// compared, ignoring source code info.
+    pub fn is_identical(
+        &self,
+        file: &FileDescriptorProto,
+        source: Option<&str>,
+        sources: &HashMap<String, String>,
+    ) -> bool {
+        if let (Some(source), Some(existing_source)) = (source, sources.get(self.existing.name())) {
+            return source == existing_source;
+        }
+
+        let normalize = |file: &FileDescriptorProto| FileDescriptorProto {
+            name: None,
+            source_code_info: None,
+            ..file.clone()
+        };
+        normalize(file) == normalize(self.existing.file_descriptor_proto())
+    }
+
+    pub fn into_error(
+        self,
+        file: &FileDescriptorProto,
+        source: Option<&str>,
+        sources: &HashMap<String, String>,
+    ) -> Error {
+        let existing_source = sources.get(self.existing.name()).map(String::as_str);
+        Error::from_kind(ErrorKind::DuplicateName {
+            span: source.and_then(|source| find_span(file, source, &self.path)),
+            source_code: NamedSource::new(file.name(), source.unwrap_or_default().to_owned()),
+            definition: vec![NameDefinition {
+                name: self.name.clone(),
+                span: existing_source.and_then(|source| {
+                    find_span(
+                        self.existing.file_descriptor_proto(),
+                        source,
+                        &self.existing_path,
+                    )
+                }),
+                source_code: NamedSource::new(
+                    self.existing.name(),
+                    existing_source.unwrap_or_default().to_owned(),
+                ),
+            }],
+            existing_file: self.existing.name().to_owned(),
+            name: self.name,
+        })
+    }
+}
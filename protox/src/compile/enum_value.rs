@@ -0,0 +1,246 @@
+use std::collections::HashMap;
+
+use miette::NamedSource;
+use prost_reflect::{EnumDescriptor, EnumValueDescriptor, FileDescriptor, Syntax};
+use prost_types::{DescriptorProto, EnumDescriptorProto, FileDescriptorProto};
+use protox_parse::tag;
+
+use super::options::find_span;
+use crate::{
+    check::{CheckDiagnostic, Diagnostics},
+    error::{Error, ErrorKind},
+};
+
+/// Checks that enum values do not conflict with the values or names of other enums in the same scope.
+///
+/// Like C++, enum values are siblings of their type rather than children of it, so the values of two enums declared
+/// in the same message or package must have distinct names. This is checked before the file is added to the pool so
+/// that the error can explain the scoping rule.
+pub(crate) fn check_enum_value_scopes(
+    file: &FileDescriptorProto,
+    source: Option<&str>,
+) -> Result<(), Error> {
+    let mut scopes = vec![(
+        file.package().to_owned(),
+        vec![tag::file::ENUM_TYPE],
+        &file.enum_type,
+    )];
+    let mut messages: Vec<(String, Vec<i32>, &DescriptorProto)> = file
+        .message_type
+        .iter()
+        .enumerate()
+        .map(|(index, message)| {
+            (
+                join(file.package(), message.name()),
+                vec![tag::file::MESSAGE_TYPE, index as i32],
+                message,
+            )
+        })
+        .collect();
+    while let Some((name, path, message)) = messages.pop() {
+        let mut enum_path = path.clone();
+        enum_path.push(tag::message::ENUM_TYPE);
+        for (index, nested) in message.nested_type.iter().enumerate() {
+            let mut nested_path = path.clone();
+            nested_path.extend([tag::message::NESTED_TYPE, index as i32]);
+            messages.push((join(&name, nested.name()), nested_path, nested));
+        }
+        scopes.push((name, enum_path, &message.enum_type));
+    }
+
+    for (scope, path, enums) in scopes {
+        check_scope(file, source, &scope, &path, enums)?;
+    }
+    Ok(())
+}
+
+fn check_scope(
+    file: &FileDescriptorProto,
+    source: Option<&str>,
+    scope: &str,
+    path: &[i32],
+    enums: &[EnumDescriptorProto],
+) -> Result<(), Error> {
+    let enum_path = |index: usize| {
+        let mut path = path.to_vec();
+        path.push(index as i32);
+        path
+    };
+
+    // Maps each name to the index of the enum defining it, whether it is the name of a value, and the path of its
+    // definition.
+    let mut names: HashMap<&str, (usize, bool, Vec<i32>)> = HashMap::new();
+    for (index, enum_) in enums.iter().enumerate() {
+        let mut path = enum_path(index);
+        path.push(tag::enum_value::NAME);
+        names.entry(enum_.name()).or_insert((index, false, path));
+    }
+
+    for (index, enum_) in enums.iter().enumerate() {
+        for (value_index, value) in enum_.value.iter().enumerate() {
+            let mut path = enum_path(index);
+            path.extend([tag::enum_::VALUE, value_index as i32, tag::enum_value::NAME]);
+            match names.get(value.name()) {
+                // Duplicate values within one enum are reported when the file is added to the pool.
+                Some((first_index, is_value, first_path)) if *first_index != index || !is_value => {
+                    let span =
+                        |path: &[i32]| source.and_then(|source| find_span(file, source, path));
+                    return Err(Error::from_kind(ErrorKind::EnumValueScopeConflict {
+                        name: join(scope, value.name()),
+                        value: value.name().to_owned(),
+                        enum_name: enum_.name().to_owned(),
+                        scope: if scope.is_empty() {
+                            "the root package".to_owned()
+                        } else {
+                            format!("'{}'", scope)
+                        },
+                        first: span(first_path),
+                        second: span(&path),
+                        source_code: NamedSource::new(
+                            file.name(),
+                            source.unwrap_or_default().to_owned(),
+                        ),
+                    }));
+                }
+                Some(_) => (),
+                None => {
+                    names.insert(value.name(), (index, true, path));
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Checks that the values of each enum are distinct after removing the enum name prefix and ignoring case, as protoc
+/// does, since they would otherwise conflict in languages which use these names.
+///
+/// Like protoc, conflicts are only errors in proto3 files, and are returned as warnings in proto2 files. If
+/// `allow_conflicts` is set, they are always returned as warnings.
+pub(crate) fn check_enum_value_names(
+    file: &FileDescriptor,
+    source: Option<&str>,
+    allow_conflicts: bool,
+) -> Result<Vec<CheckDiagnostic>, Error> {
+    let mut diagnostics = Diagnostics::new(file, source);
+    let allow_conflicts = allow_conflicts || file.syntax() != Syntax::Proto3;
+
+    let mut enums: Vec<EnumDescriptor> = file.enums().collect();
+    let mut messages: Vec<_> = file.messages().collect();
+    while let Some(message) = messages.pop() {
+        enums.extend(message.child_enums());
+        messages.extend(message.child_messages());
+    }
+
+    for enum_ty in enums {
+        let prefix: String = enum_ty
+            .name()
+            .chars()
+            .filter(|&ch| ch != '_')
+            .map(|ch| ch.to_ascii_lowercase())
+            .collect();
+
+        let mut names: HashMap<String, EnumValueDescriptor> = HashMap::new();
+        for value in enum_ty.values() {
+            let name = to_pascal_case(strip_prefix(value.name(), &prefix));
+            let Some(existing) = names.get(&name) else {
+                names.insert(name, value);
+                continue;
+            };
+            if existing.number() == value.number() {
+                continue;
+            }
+
+            let mut path = value.path().to_vec();
+            path.push(tag::enum_value::NAME);
+            if allow_conflicts {
+                diagnostics
+                    .warning(&path, conflict_message(&value, existing))
+                    .set_help(CONFLICT_HELP);
+            } else {
+                let mut first_path = existing.path().to_vec();
+                first_path.push(tag::enum_value::NAME);
+                let span = |path: &[i32]| {
+                    source.and_then(|source| find_span(file.file_descriptor_proto(), source, path))
+                };
+                return Err(Error::from_kind(ErrorKind::EnumValueNameConflict {
+                    message: conflict_message(&value, existing),
+                    first: span(&first_path),
+                    second: span(&path),
+                    source_code: NamedSource::new(
+                        file.name(),
+                        source.unwrap_or_default().to_owned(),
+                    ),
+                }));
+            }
+        }
+    }
+
+    Ok(diagnostics.into_inner())
+}
+
+const CONFLICT_HELP: &str =
+    "if you are using allow_alias, assign the same number to each enum value name";
+
+fn conflict_message(value: &EnumValueDescriptor, existing: &EnumValueDescriptor) -> String {
+    format!(
+        "enum value '{}' has the same name as '{}' if you ignore case and strip out the enum name prefix",
+        value.name(),
+        existing.name()
+    )
+}
+
+/// Removes the name of an enum from the start of a value name, ignoring case and underscores, in the same way as
+/// protoc. `prefix` is the enum name in lowercase with underscores removed.
+fn strip_prefix<'a>(name: &'a str, prefix: &str) -> &'a str {
+    let mut prefix = prefix.bytes().peekable();
+    let mut index = 0;
+    let bytes = name.as_bytes();
+    while index < bytes.len() {
+        let Some(&expected) = prefix.peek() else {
+            break;
+        };
+        if bytes[index] != b'_' {
+            if bytes[index].to_ascii_lowercase() != expected {
+                return name;
+            }
+            prefix.next();
+        }
+        index += 1;
+    }
+    if prefix.peek().is_some() {
+        return name;
+    }
+
+    let rest = name[index..].trim_start_matches('_');
+    if rest.is_empty() {
+        name
+    } else {
+        rest
+    }
+}
+
+fn to_pascal_case(name: &str) -> String {
+    let mut result = String::with_capacity(name.len());
+    let mut uppercase_next = true;
+    for ch in name.chars() {
+        if ch == '_' {
+            uppercase_next = true;
+        } else if uppercase_next {
+            result.push(ch.to_ascii_uppercase());
+            uppercase_next = false;
+        } else {
+            result.push(ch.to_ascii_lowercase());
+        }
+    }
+    result
+}
+
+fn join(scope: &str, name: &str) -> String {
+    if scope.is_empty() {
+        name.to_owned()
+    } else {
+        format!("{}.{}", scope, name)
+    }
+}
@@ -0,0 +1,101 @@
+use std::collections::HashMap;
+
+use miette::NamedSource;
+use prost_reflect::{ExtensionDescriptor, FileDescriptor};
+use protox_parse::tag;
+
+use super::options::find_span;
+use crate::error::{Error, ErrorKind, ExtensionNumberContext};
+
+/// Checks that the extensions declared in the file do not use a number already used by another extension of the same
+/// message.
+///
+/// The pool does not check this for extensions, and the other extension may be declared in any file previously added
+/// to the pool, even if neither file imports the other. The error points at the numbers of both extensions, and at the
+/// extension range of the extended message which contains the number. The sources of previously compiled files are
+/// used to find the spans of definitions in other files.
+pub(crate) fn check_extension_numbers(
+    file: &FileDescriptor,
+    source: Option<&str>,
+    sources: &HashMap<String, String>,
+) -> Result<(), Error> {
+    let mut messages: Vec<_> = file.messages().collect();
+    let mut extensions: Vec<_> = file.extensions().collect();
+    while let Some(message) = messages.pop() {
+        messages.extend(message.child_messages());
+        extensions.extend(message.child_extensions());
+    }
+
+    let source_of = |other: &FileDescriptor| {
+        if other == file {
+            source
+        } else {
+            sources.get(other.name()).map(String::as_str)
+        }
+    };
+    let number_span = |extension: &ExtensionDescriptor| {
+        let mut path = extension.path().to_vec();
+        path.push(tag::field::NUMBER);
+        let parent_file = extension.parent_file();
+        source_of(&parent_file)
+            .and_then(|source| find_span(parent_file.file_descriptor_proto(), source, &path))
+    };
+
+    for extension in extensions {
+        let extendee = extension.containing_message();
+        let Some(existing) = extendee
+            .extensions()
+            .find(|other| other.number() == extension.number() && *other != extension)
+        else {
+            continue;
+        };
+        // Each pair is reported once, on the extension which was added to the pool last.
+        if existing.parent_file() == *file && existing.path() > extension.path() {
+            continue;
+        }
+
+        let existing_file = existing.parent_file();
+        let mut context = vec![ExtensionNumberContext::Definition {
+            name: existing.full_name().to_owned(),
+            span: number_span(&existing),
+            source_code: NamedSource::new(
+                existing_file.name(),
+                source_of(&existing_file).unwrap_or_default().to_owned(),
+            ),
+        }];
+
+        let extendee_file = extendee.parent_file();
+        let extendee_source = source_of(&extendee_file);
+        let ranges = &extendee.descriptor_proto().extension_range;
+        if let Some(index) = ranges.iter().position(|range| {
+            range.start() as u32 <= extension.number() && extension.number() < range.end() as u32
+        }) {
+            let mut path = extendee.path().to_vec();
+            path.extend([tag::message::EXTENSION_RANGE, index as i32]);
+            context.push(ExtensionNumberContext::Range {
+                message: extendee.full_name().to_owned(),
+                start: ranges[index].start(),
+                end: ranges[index].end() - 1,
+                span: extendee_source.and_then(|source| {
+                    find_span(extendee_file.file_descriptor_proto(), source, &path)
+                }),
+                source_code: NamedSource::new(
+                    extendee_file.name(),
+                    extendee_source.unwrap_or_default().to_owned(),
+                ),
+            });
+        }
+
+        return Err(Error::from_kind(ErrorKind::DuplicateExtensionNumber {
+            number: extension.number(),
+            extendee: extendee.full_name().to_owned(),
+            existing: existing.full_name().to_owned(),
+            existing_file: existing_file.name().to_owned(),
+            span: number_span(&extension),
+            source_code: NamedSource::new(file.name(), source.unwrap_or_default().to_owned()),
+            context,
+        }));
+    }
+
+    Ok(())
+}
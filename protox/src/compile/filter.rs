@@ -0,0 +1,513 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+
+use prost_reflect::{
+    DescriptorPool, DynamicMessage, EnumDescriptor, ExtensionDescriptor, FileDescriptor,
+    MessageDescriptor, ServiceDescriptor,
+};
+use prost_types::FileDescriptorProto;
+use protox_parse::tag;
+
+use super::{find_unused_imports, unused::public_closure};
+use crate::{
+    error::ErrorKind,
+    symbol::{join_name, type_references, Symbol, SymbolKind, SymbolTable},
+    Error,
+};
+
+/// Selects the packages and definitions to include in the output of
+/// [`Compiler::filtered_file_descriptor_set`](crate::Compiler::filtered_file_descriptor_set).
+///
+/// Like `buf build --type`, the output is restricted to the files defining the selected names, along with the files
+/// they depend on. If [`prune_types`](DescriptorFilter::prune_types) is set, definitions which are not reachable from
+/// the selected names are also removed from those files.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DescriptorFilter {
+    names: Vec<String>,
+    prune_types: bool,
+    keep_custom_options: bool,
+}
+
+impl DescriptorFilter {
+    /// Creates a filter which does not select any names.
+    pub fn new() -> Self {
+        DescriptorFilter::default()
+    }
+
+    /// Includes the definition with the given fully-qualified name, which may be a package, message, enum, service,
+    /// extension or any other named definition.
+    ///
+    /// For a package, every file in the package or one of its sub-packages is included. Otherwise, the file which
+    /// declares the definition is included.
+    pub fn include(&mut self, name: impl Into<String>) -> &mut Self {
+        let name = name.into();
+        self.names.push(match name.strip_prefix('.') {
+            Some(name) => name.to_owned(),
+            None => name,
+        });
+        self
+    }
+
+    /// Gets the fully-qualified names selected by this filter.
+    pub fn names(&self) -> impl Iterator<Item = &'_ str> {
+        self.names.iter().map(String::as_str)
+    }
+
+    /// Set whether to remove messages, enums, services and extensions which are not reachable from the selected
+    /// names. Defaults to `false`.
+    ///
+    /// When set, only the top-level definitions containing a selected name are kept, along with the types they
+    /// transitively reference through field types, extendees and method input and output types. A selected package
+    /// keeps every definition in it. Nested definitions are kept or removed along with the top-level message
+    /// containing them. Imports which are no longer needed by the remaining definitions are removed, and files
+    /// left without any definitions are omitted, unless they are needed to forward a public import.
+    pub fn prune_types(&mut self, prune_types: bool) -> &mut Self {
+        self.prune_types = prune_types;
+        self
+    }
+
+    /// Set whether to keep the extensions used as custom options by the definitions which remain after
+    /// [pruning](DescriptorFilter::prune_types), along with the types they reference. Defaults to `false`, in which
+    /// case custom option definitions are removed unless they are otherwise reachable.
+    ///
+    /// This has no effect unless [`prune_types`](DescriptorFilter::prune_types) is set.
+    pub fn keep_custom_options(&mut self, keep_custom_options: bool) -> &mut Self {
+        self.keep_custom_options = keep_custom_options;
+        self
+    }
+}
+
+/// A file to include in a filtered file descriptor set.
+pub(crate) struct FilteredFile {
+    pub file: FileDescriptor,
+    /// The names of the imports of the file which are also included.
+    pub imports: HashSet<String>,
+    /// The fully-qualified names of the top-level definitions of the file to keep, if types are pruned.
+    pub definitions: Option<HashSet<String>>,
+}
+
+/// A message, enum, service or extension declared at the top level of a file.
+enum Definition {
+    Message(MessageDescriptor),
+    Enum(EnumDescriptor),
+    Service(ServiceDescriptor),
+    Extension(ExtensionDescriptor),
+}
+
+/// Finds the files declaring the names selected by the filter, along with the files they transitively depend on, in
+/// the order they were added to the pool.
+///
+/// Imports which are not used by a file are not followed, and are removed from the output. Public imports are
+/// always kept, since they may be used by the files importing the file. If types are pruned, only the imports needed
+/// by the reachable definitions are followed instead.
+pub(crate) fn filter_files(
+    pool: &DescriptorPool,
+    filter: &DescriptorFilter,
+) -> Result<Vec<FilteredFile>, Error> {
+    let symbols = SymbolTable::new(pool);
+    let mut root_files = BTreeSet::new();
+    let mut root_definitions = BTreeSet::new();
+    for name in filter.names() {
+        match symbols.get(name) {
+            Some(symbol) if symbol.kind() == SymbolKind::Package => {
+                let prefix = format!("{}.", name);
+                for file in pool.files().filter(|file| {
+                    file.package_name() == name || file.package_name().starts_with(&prefix)
+                }) {
+                    root_definitions.extend(
+                        Definition::all(&file).map(|definition| definition.full_name().to_owned()),
+                    );
+                    root_files.insert(file.name().to_owned());
+                }
+            }
+            Some(symbol) => {
+                root_definitions.insert(root_definition(pool, symbol));
+                root_files.insert(symbol.file().to_owned());
+            }
+            None => {
+                return Err(Error::from_kind(ErrorKind::UndefinedFilterName {
+                    name: name.to_owned(),
+                }))
+            }
+        }
+    }
+
+    if filter.prune_types {
+        return Ok(prune(pool, root_definitions, filter.keep_custom_options));
+    }
+
+    let mut included: HashSet<String> = HashSet::new();
+    let mut stack: Vec<String> = root_files.into_iter().collect();
+    while let Some(name) = stack.pop() {
+        if !included.insert(name.clone()) {
+            continue;
+        }
+        let file = pool
+            .get_file_by_name(&name)
+            .expect("file should be in the pool");
+        for import in used_imports(&file) {
+            if !included.contains(&import) {
+                stack.push(import);
+            }
+        }
+    }
+
+    Ok(pool
+        .files()
+        .filter(|file| included.contains(file.name()))
+        .map(|file| FilteredFile {
+            imports: used_imports(&file),
+            definitions: None,
+            file,
+        })
+        .collect())
+}
+
+/// Finds the top-level definitions reachable from the given roots, and the imports needed by the files declaring
+/// them.
+fn prune(
+    pool: &DescriptorPool,
+    roots: BTreeSet<String>,
+    keep_custom_options: bool,
+) -> Vec<FilteredFile> {
+    let definitions: HashMap<String, Definition> = pool
+        .files()
+        .flat_map(|file| Definition::all(&file).collect::<Vec<_>>())
+        .map(|definition| (definition.full_name().to_owned(), definition))
+        .collect();
+
+    // Maps each top-level definition to the top-level definitions declaring the types it references.
+    let mut references: HashMap<String, BTreeSet<String>> = HashMap::new();
+    for reference in type_references(pool) {
+        let target = reference.target();
+        references
+            .entry(containing_definition(
+                pool,
+                reference.file(),
+                reference.referrer(),
+            ))
+            .or_default()
+            .insert(containing_definition(
+                pool,
+                target.file(),
+                target.full_name(),
+            ));
+    }
+
+    // Maps each file with retained definitions to the names of those definitions, and the files they reference.
+    let mut retained: BTreeMap<String, (HashSet<String>, BTreeSet<String>)> = BTreeMap::new();
+    let mut visited: HashSet<String> = HashSet::new();
+    let mut stack: Vec<String> = roots.into_iter().collect();
+    while let Some(name) = stack.pop() {
+        if !visited.insert(name.clone()) {
+            continue;
+        }
+
+        let definition = &definitions[&name];
+        let file = definition.parent_file();
+        let mut targets = references.get(&name).cloned().unwrap_or_default();
+        if keep_custom_options {
+            definition.for_each_options(&mut |options| {
+                targets.extend(option_definitions(pool, &options));
+            });
+            if !retained.contains_key(file.name()) {
+                targets.extend(option_definitions(pool, &file.options()));
+            }
+        }
+
+        let (names, files) = retained.entry(file.name().to_owned()).or_default();
+        names.insert(name);
+        for target in &targets {
+            let target_file = definitions[target].parent_file();
+            if target_file != file {
+                files.insert(target_file.name().to_owned());
+            }
+        }
+        stack.extend(targets);
+    }
+
+    // Finds the import each file uses to see the files it references, which may forward them with a public import.
+    let mut included: HashSet<String> = retained.keys().cloned().collect();
+    let mut imports: HashMap<String, HashSet<String>> = HashMap::new();
+    let mut stack: Vec<(FileDescriptor, String, bool)> = retained
+        .iter()
+        .flat_map(|(name, (_, files))| {
+            let file = pool
+                .get_file_by_name(name)
+                .expect("file should be in the pool");
+            files
+                .iter()
+                .map(move |target| (file.clone(), target.clone(), false))
+        })
+        .collect();
+    let mut visited: HashSet<(String, String, bool)> = HashSet::new();
+    while let Some((file, target, public_only)) = stack.pop() {
+        if !visited.insert((file.name().to_owned(), target.clone(), public_only)) {
+            continue;
+        }
+
+        let dependencies: Vec<FileDescriptor> = if public_only {
+            file.public_dependencies().collect()
+        } else {
+            file.dependencies().collect()
+        };
+        let dependency = dependencies
+            .into_iter()
+            .find(|dependency| public_closure(dependency).contains(&target))
+            .expect("referenced file should be visible to the referencing file");
+
+        imports
+            .entry(file.name().to_owned())
+            .or_default()
+            .insert(dependency.name().to_owned());
+        if dependency.name() != target {
+            included.insert(dependency.name().to_owned());
+            stack.push((dependency, target, true));
+        }
+    }
+
+    pool.files()
+        .filter(|file| included.contains(file.name()))
+        .map(|file| FilteredFile {
+            imports: imports.remove(file.name()).unwrap_or_default(),
+            definitions: Some(
+                retained
+                    .remove(file.name())
+                    .map(|(names, _)| names)
+                    .unwrap_or_default(),
+            ),
+            file,
+        })
+        .collect()
+}
+
+/// Gets the name of the top-level definition containing a symbol selected by a filter.
+fn root_definition(pool: &DescriptorPool, symbol: &Symbol) -> String {
+    if symbol.kind() == SymbolKind::EnumValue {
+        // Enum values are siblings of their enum, so their names do not identify the enum.
+        let enum_ = pool
+            .all_enums()
+            .find(|enum_| {
+                enum_
+                    .values()
+                    .any(|value| value.full_name() == symbol.full_name())
+            })
+            .expect("enum value should be in the pool");
+        containing_definition(pool, symbol.file(), enum_.full_name())
+    } else {
+        containing_definition(pool, symbol.file(), symbol.full_name())
+    }
+}
+
+/// Gets the name of the top-level definition containing the definition with the given name, declared in `file`.
+fn containing_definition(pool: &DescriptorPool, file: &str, full_name: &str) -> String {
+    let package = pool
+        .get_file_by_name(file)
+        .expect("file should be in the pool")
+        .package_name()
+        .to_owned();
+    let rest = if package.is_empty() {
+        full_name
+    } else {
+        &full_name[package.len() + 1..]
+    };
+    let name = rest.split_once('.').map_or(rest, |(name, _)| name);
+    join_name(&package, name)
+}
+
+/// Gets the names of the top-level definitions containing the extensions set in an options message.
+fn option_definitions<'a>(
+    pool: &'a DescriptorPool,
+    options: &'a DynamicMessage,
+) -> impl Iterator<Item = String> + 'a {
+    options.extensions().map(|(extension, _)| {
+        containing_definition(pool, extension.parent_file().name(), extension.full_name())
+    })
+}
+
+impl Definition {
+    fn all(file: &FileDescriptor) -> impl Iterator<Item = Definition> + '_ {
+        file.messages()
+            .map(Definition::Message)
+            .chain(file.enums().map(Definition::Enum))
+            .chain(file.services().map(Definition::Service))
+            .chain(file.extensions().map(Definition::Extension))
+    }
+
+    fn full_name(&self) -> &str {
+        match self {
+            Definition::Message(message) => message.full_name(),
+            Definition::Enum(enum_) => enum_.full_name(),
+            Definition::Service(service) => service.full_name(),
+            Definition::Extension(extension) => extension.full_name(),
+        }
+    }
+
+    fn parent_file(&self) -> FileDescriptor {
+        match self {
+            Definition::Message(message) => message.parent_file(),
+            Definition::Enum(enum_) => enum_.parent_file(),
+            Definition::Service(service) => service.parent_file(),
+            Definition::Extension(extension) => extension.parent_file(),
+        }
+    }
+
+    /// Calls `f` with the options of this definition and every element declared within it.
+    fn for_each_options(&self, f: &mut impl FnMut(DynamicMessage)) {
+        fn for_each_enum_options(enum_: &EnumDescriptor, f: &mut impl FnMut(DynamicMessage)) {
+            f(enum_.options());
+            enum_.values().for_each(|value| f(value.options()));
+        }
+
+        match self {
+            Definition::Message(message) => {
+                let mut messages = vec![message.clone()];
+                while let Some(message) = messages.pop() {
+                    f(message.options());
+                    message.fields().for_each(|field| f(field.options()));
+                    message.oneofs().for_each(|oneof| f(oneof.options()));
+                    message
+                        .child_extensions()
+                        .for_each(|extension| f(extension.options()));
+                    message
+                        .child_enums()
+                        .for_each(|enum_| for_each_enum_options(&enum_, f));
+                    messages.extend(message.child_messages());
+                }
+            }
+            Definition::Enum(enum_) => for_each_enum_options(enum_, f),
+            Definition::Service(service) => {
+                f(service.options());
+                service.methods().for_each(|method| f(method.options()));
+            }
+            Definition::Extension(extension) => f(extension.options()),
+        }
+    }
+}
+
+fn used_imports(file: &FileDescriptor) -> HashSet<String> {
+    let unused: HashSet<String> = find_unused_imports(file, None)
+        .into_iter()
+        .map(|import| import.name().to_owned())
+        .collect();
+    file.dependencies()
+        .map(|dependency| dependency.name().to_owned())
+        .filter(|name| !unused.contains(name))
+        .collect()
+}
+
+/// Removes the imports of a file for which `keep` returns `false`, updating the indices of public and weak imports,
+/// and the locations of the imports in the source code info.
+pub(crate) fn remove_imports(file: &mut FileDescriptorProto, keep: impl Fn(&str) -> bool) {
+    let dependencies = retain_indexed(&mut file.dependency, |name| keep(name));
+    if dependencies.iter().all(Option::is_some) {
+        return;
+    }
+
+    let public_dependencies = remap_dependency_list(&mut file.public_dependency, &dependencies);
+    let weak_dependencies = remap_dependency_list(&mut file.weak_dependency, &dependencies);
+    remap_locations(file, tag::file::DEPENDENCY, &dependencies);
+    remap_locations(file, tag::file::PUBLIC_DEPENDENCY, &public_dependencies);
+    remap_locations(file, tag::file::WEAK_DEPENDENCY, &weak_dependencies);
+}
+
+/// Removes the top-level messages, enums, services and extensions of a file for which `keep` returns `false`, along
+/// with their locations in the source code info.
+pub(crate) fn remove_definitions(file: &mut FileDescriptorProto, keep: impl Fn(&str) -> bool) {
+    let package = file.package().to_owned();
+    let keep = |name: &str| keep(&join_name(&package, name));
+
+    let messages = retain_indexed(&mut file.message_type, |message| keep(message.name()));
+    let enums = retain_indexed(&mut file.enum_type, |enum_| keep(enum_.name()));
+    let services = retain_indexed(&mut file.service, |service| keep(service.name()));
+    let extensions = retain_indexed(&mut file.extension, |extension| keep(extension.name()));
+
+    // Each `extend` block has a location with the path of the extension list, which is removed if none of the
+    // extensions within it are kept.
+    if let Some(source_code_info) = &mut file.source_code_info {
+        let kept_spans: Vec<_> = source_code_info
+            .location
+            .iter()
+            .filter(|location| match location.path.as_slice() {
+                [tag::file::EXTENSION, index] => new_index(&extensions, *index).is_some(),
+                _ => false,
+            })
+            .filter_map(|location| span_bounds(&location.span))
+            .collect();
+        source_code_info.location.retain(|location| {
+            if location.path != [tag::file::EXTENSION] {
+                return true;
+            }
+            span_bounds(&location.span).map_or(true, |(start, end)| {
+                kept_spans
+                    .iter()
+                    .any(|&(kept_start, kept_end)| start <= kept_start && kept_end <= end)
+            })
+        });
+    }
+
+    remap_locations(file, tag::file::MESSAGE_TYPE, &messages);
+    remap_locations(file, tag::file::ENUM_TYPE, &enums);
+    remap_locations(file, tag::file::SERVICE, &services);
+    remap_locations(file, tag::file::EXTENSION, &extensions);
+}
+
+/// Removes the entries of a list for which `keep` returns `false`. Returns the new index of each of its previous
+/// entries, or `None` if it was removed.
+fn retain_indexed<T>(list: &mut Vec<T>, mut keep: impl FnMut(&T) -> bool) -> Vec<Option<i32>> {
+    let mut indices = Vec::with_capacity(list.len());
+    let mut next = 0;
+    list.retain(|item| {
+        if keep(item) {
+            indices.push(Some(next));
+            next += 1;
+            true
+        } else {
+            indices.push(None);
+            false
+        }
+    });
+    indices
+}
+
+/// Updates a list of import indices, such as `public_dependency`, removing those which are not kept. Returns the new
+/// index of each of its previous entries.
+fn remap_dependency_list(list: &mut Vec<i32>, dependencies: &[Option<i32>]) -> Vec<Option<i32>> {
+    let indices = retain_indexed(list, |&index| new_index(dependencies, index).is_some());
+    for index in list {
+        *index = new_index(dependencies, *index).expect("index should be kept");
+    }
+    indices
+}
+
+/// Removes or renumbers the source code info locations of the elements of the file's list with the given tag.
+fn remap_locations(file: &mut FileDescriptorProto, tag: i32, indices: &[Option<i32>]) {
+    if let Some(source_code_info) = &mut file.source_code_info {
+        source_code_info
+            .location
+            .retain_mut(|location| match location.path.as_mut_slice() {
+                [path_tag, index, ..] if *path_tag == tag => match new_index(indices, *index) {
+                    Some(new) => {
+                        *index = new;
+                        true
+                    }
+                    None => false,
+                },
+                _ => true,
+            });
+    }
+}
+
+fn new_index(indices: &[Option<i32>], index: i32) -> Option<i32> {
+    usize::try_from(index).ok().and_then(|i| *indices.get(i)?)
+}
+
+/// Gets the start and end of a source code info span as (line, column) pairs.
+fn span_bounds(span: &[i32]) -> Option<((i32, i32), (i32, i32))> {
+    match *span {
+        [start_line, start_column, end_line, end_column] => {
+            Some(((start_line, start_column), (end_line, end_column)))
+        }
+        [line, start_column, end_column] => Some(((line, start_column), (line, end_column))),
+        _ => None,
+    }
+}
@@ -0,0 +1,165 @@
+use std::collections::HashMap;
+
+use miette::NamedSource;
+use prost_types::{DescriptorProto, FieldDescriptorProto, FileDescriptorProto};
+use protox_parse::{case::to_json_name, tag};
+
+use super::options::find_span;
+use crate::error::{Error, ErrorKind};
+
+/// Checks that the JSON names of the fields in each message of the file are unique, and that custom JSON names are
+/// valid.
+///
+/// This matches the checks made by protoc. Conflicts between two default JSON names are checked first, followed by
+/// conflicts involving at least one custom name set by the `json_name` option.
+pub(crate) fn check_json_names(
+    file: &FileDescriptorProto,
+    source: Option<&str>,
+) -> Result<(), Error> {
+    let mut messages: Vec<(Vec<i32>, &DescriptorProto)> = file
+        .message_type
+        .iter()
+        .enumerate()
+        .map(|(index, message)| (vec![tag::file::MESSAGE_TYPE, index as i32], message))
+        .collect();
+    while let Some((path, message)) = messages.pop() {
+        check_message(message, &path, false)
+            .and_then(|()| check_message(message, &path, true))
+            .map_err(|err| err.into_error(file, source))?;
+
+        for (index, nested) in message.nested_type.iter().enumerate() {
+            let mut nested_path = path.clone();
+            nested_path.extend([tag::message::NESTED_TYPE, index as i32]);
+            messages.push((nested_path, nested));
+        }
+    }
+    Ok(())
+}
+
+struct JsonName<'a> {
+    field: &'a FieldDescriptorProto,
+    path: Vec<i32>,
+    name: String,
+    is_custom: bool,
+}
+
+impl<'a> JsonName<'a> {
+    fn new(field: &'a FieldDescriptorProto, path: Vec<i32>, use_custom_name: bool) -> Self {
+        // Descriptors produced by a compiler always have `json_name` set, so it is only custom if it differs from the
+        // default.
+        let default = to_json_name(field.name());
+        match &field.json_name {
+            Some(name) if use_custom_name && *name != default => JsonName {
+                field,
+                path,
+                name: name.clone(),
+                is_custom: true,
+            },
+            _ => JsonName {
+                field,
+                path,
+                name: default,
+                is_custom: false,
+            },
+        }
+    }
+
+    fn kind(&self) -> &'static str {
+        if self.is_custom {
+            "custom"
+        } else {
+            "default"
+        }
+    }
+}
+
+enum JsonNameError {
+    Invalid {
+        message: String,
+        path: Vec<i32>,
+    },
+    Conflict {
+        message: String,
+        first: Vec<i32>,
+        second: Vec<i32>,
+    },
+}
+
+impl JsonNameError {
+    fn into_error(self, file: &FileDescriptorProto, source: Option<&str>) -> Error {
+        let span = |path: &[i32]| source.and_then(|source| find_span(file, source, path));
+        let source_code = NamedSource::new(file.name(), source.unwrap_or_default().to_owned());
+        match self {
+            JsonNameError::Invalid { message, path } => {
+                Error::from_kind(ErrorKind::InvalidJsonName {
+                    message,
+                    span: span(&path),
+                    source_code,
+                })
+            }
+            JsonNameError::Conflict {
+                message,
+                first,
+                second,
+            } => Error::from_kind(ErrorKind::DuplicateJsonName {
+                message,
+                first: span(&first),
+                second: span(&second),
+                source_code,
+            }),
+        }
+    }
+}
+
+fn check_message(
+    message: &DescriptorProto,
+    path: &[i32],
+    use_custom_names: bool,
+) -> Result<(), JsonNameError> {
+    let mut names: HashMap<String, JsonName> = HashMap::with_capacity(message.field.len());
+    for (index, field) in message.field.iter().enumerate() {
+        let mut field_path = path.to_vec();
+        field_path.extend([tag::message::FIELD, index as i32]);
+        let json_name = JsonName::new(field, field_path, use_custom_names);
+
+        if json_name.is_custom && json_name.name.starts_with('[') && json_name.name.ends_with(']') {
+            let mut path = json_name.path.clone();
+            path.push(tag::field::JSON_NAME);
+            return Err(JsonNameError::Invalid {
+                message: format!(
+                    "the custom JSON name of field '{}' ('{}') is invalid: JSON names may not start with '[' and end with ']'",
+                    field.name(),
+                    json_name.name
+                ),
+                path,
+            });
+        }
+
+        match names.get(&json_name.name) {
+            None => {
+                names.insert(json_name.name.clone(), json_name);
+            }
+            // Conflicts between default names are reported by the first pass.
+            Some(existing) if use_custom_names && !existing.is_custom && !json_name.is_custom => {}
+            Some(existing) => {
+                let mut first = existing.path.clone();
+                first.push(tag::field::NAME);
+                let mut second = json_name.path.clone();
+                second.push(tag::field::NAME);
+                return Err(JsonNameError::Conflict {
+                    message: format!(
+                        "the {} JSON name of field '{}' ('{}') conflicts with the {} JSON name of field '{}'",
+                        json_name.kind(),
+                        field.name(),
+                        json_name.name,
+                        existing.kind(),
+                        existing.field.name()
+                    ),
+                    first,
+                    second,
+                });
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,264 @@
+use prost_types::{
+    DescriptorProto, EnumDescriptorProto, FieldDescriptorProto, FileDescriptorProto,
+    ServiceDescriptorProto, SourceCodeInfo,
+};
+use protox_parse::tag;
+
+/// The part of a file descriptor removed by [`prune`].
+struct Pruned {
+    path: Vec<i32>,
+    /// Whether an element of a repeated field was removed, so later elements have moved down by one.
+    shifted: bool,
+}
+
+impl Pruned {
+    fn element(tag: i32, index: i32) -> Option<Self> {
+        Some(Pruned {
+            path: vec![tag, index],
+            shifted: true,
+        })
+    }
+
+    fn options(tag: i32) -> Option<Self> {
+        Some(Pruned {
+            path: vec![tag],
+            shifted: false,
+        })
+    }
+}
+
+/// Removes the innermost element of `file` containing the location at `path`, so that an error reported there no
+/// longer applies.
+///
+/// If the path is within the options of an element, only the options are removed. Otherwise the enclosing
+/// definition is removed, along with its locations in the source code info. Returns `false` if the path does not
+/// refer to an element which can be removed, such as the package name.
+pub(crate) fn prune(file: &mut FileDescriptorProto, path: &[i32]) -> bool {
+    let pruned = match path {
+        [tag::file::DEPENDENCY, index, ..] => prune_dependency(file, *index),
+        [tag::file::MESSAGE_TYPE, rest @ ..] => prune_in(
+            &mut file.message_type,
+            tag::file::MESSAGE_TYPE,
+            rest,
+            prune_message,
+        ),
+        [tag::file::ENUM_TYPE, rest @ ..] => {
+            prune_in(&mut file.enum_type, tag::file::ENUM_TYPE, rest, prune_enum)
+        }
+        [tag::file::SERVICE, rest @ ..] => {
+            prune_in(&mut file.service, tag::file::SERVICE, rest, prune_service)
+        }
+        [tag::file::EXTENSION, rest @ ..] => {
+            prune_in(&mut file.extension, tag::file::EXTENSION, rest, prune_field)
+        }
+        [tag::file::OPTIONS, ..] => file
+            .options
+            .take()
+            .and_then(|_| Pruned::options(tag::file::OPTIONS)),
+        _ => None,
+    };
+
+    match pruned {
+        Some(pruned) => {
+            if let Some(source_code_info) = &mut file.source_code_info {
+                remove_locations(source_code_info, &pruned);
+            }
+            true
+        }
+        None => false,
+    }
+}
+
+/// Removes the element of `items` at the start of `path`, or a part of it if `prune` succeeds on the rest of the path.
+fn prune_in<T>(
+    items: &mut Vec<T>,
+    tag: i32,
+    path: &[i32],
+    prune: impl FnOnce(&mut T, &[i32]) -> Option<Pruned>,
+) -> Option<Pruned> {
+    let (&index, rest) = path.split_first()?;
+    let item = items.get_mut(usize::try_from(index).ok()?)?;
+    if let Some(mut pruned) = prune(item, rest) {
+        pruned.path.splice(0..0, [tag, index]);
+        return Some(pruned);
+    }
+
+    items.remove(index as usize);
+    Pruned::element(tag, index)
+}
+
+fn prune_dependency(file: &mut FileDescriptorProto, index: i32) -> Option<Pruned> {
+    if index < 0 || index as usize >= file.dependency.len() {
+        return None;
+    }
+    file.dependency.remove(index as usize);
+
+    for indices in [&mut file.public_dependency, &mut file.weak_dependency] {
+        indices.retain(|&i| i != index);
+        for i in indices.iter_mut().filter(|i| **i > index) {
+            *i -= 1;
+        }
+    }
+    Pruned::element(tag::file::DEPENDENCY, index)
+}
+
+fn prune_message(message: &mut DescriptorProto, path: &[i32]) -> Option<Pruned> {
+    match path {
+        [tag::message::FIELD, rest @ ..] => {
+            prune_in(&mut message.field, tag::message::FIELD, rest, prune_field)
+        }
+        [tag::message::NESTED_TYPE, rest @ ..] => prune_in(
+            &mut message.nested_type,
+            tag::message::NESTED_TYPE,
+            rest,
+            prune_message,
+        ),
+        [tag::message::ENUM_TYPE, rest @ ..] => prune_in(
+            &mut message.enum_type,
+            tag::message::ENUM_TYPE,
+            rest,
+            prune_enum,
+        ),
+        [tag::message::EXTENSION_RANGE, rest @ ..] => prune_in(
+            &mut message.extension_range,
+            tag::message::EXTENSION_RANGE,
+            rest,
+            |range, path| match path {
+                [tag::message::extension_range::OPTIONS, ..] => range
+                    .options
+                    .take()
+                    .and_then(|_| Pruned::options(tag::message::extension_range::OPTIONS)),
+                _ => None,
+            },
+        ),
+        [tag::message::EXTENSION, rest @ ..] => prune_in(
+            &mut message.extension,
+            tag::message::EXTENSION,
+            rest,
+            prune_field,
+        ),
+        [tag::message::OPTIONS, ..] => message
+            .options
+            .take()
+            .and_then(|_| Pruned::options(tag::message::OPTIONS)),
+        [tag::message::ONEOF_DECL, rest @ ..] => {
+            let pruned = prune_in(
+                &mut message.oneof_decl,
+                tag::message::ONEOF_DECL,
+                rest,
+                |oneof, path| match path {
+                    [tag::oneof::OPTIONS, ..] => oneof
+                        .options
+                        .take()
+                        .and_then(|_| Pruned::options(tag::oneof::OPTIONS)),
+                    _ => None,
+                },
+            )?;
+            if pruned.shifted {
+                // The fields of a removed oneof become ordinary fields.
+                let index = pruned.path[1];
+                for field in &mut message.field {
+                    match field.oneof_index {
+                        Some(i) if i == index => {
+                            field.oneof_index = None;
+                            field.proto3_optional = None;
+                        }
+                        Some(i) if i > index => field.oneof_index = Some(i - 1),
+                        _ => (),
+                    }
+                }
+            }
+            Some(pruned)
+        }
+        [tag::message::RESERVED_RANGE, index, ..] => {
+            let index = *index;
+            if index < 0 || index as usize >= message.reserved_range.len() {
+                return None;
+            }
+            message.reserved_range.remove(index as usize);
+            Pruned::element(tag::message::RESERVED_RANGE, index)
+        }
+        _ => None,
+    }
+}
+
+fn prune_field(field: &mut FieldDescriptorProto, path: &[i32]) -> Option<Pruned> {
+    match path {
+        [tag::field::OPTIONS, ..] => field
+            .options
+            .take()
+            .and_then(|_| Pruned::options(tag::field::OPTIONS)),
+        _ => None,
+    }
+}
+
+fn prune_enum(enum_: &mut EnumDescriptorProto, path: &[i32]) -> Option<Pruned> {
+    match path {
+        [tag::enum_::VALUE, rest @ ..] => prune_in(
+            &mut enum_.value,
+            tag::enum_::VALUE,
+            rest,
+            |value, path| match path {
+                [tag::enum_value::OPTIONS, ..] => value
+                    .options
+                    .take()
+                    .and_then(|_| Pruned::options(tag::enum_value::OPTIONS)),
+                _ => None,
+            },
+        ),
+        [tag::enum_::OPTIONS, ..] => enum_
+            .options
+            .take()
+            .and_then(|_| Pruned::options(tag::enum_::OPTIONS)),
+        [tag::enum_::RESERVED_RANGE, index, ..] => {
+            let index = *index;
+            if index < 0 || index as usize >= enum_.reserved_range.len() {
+                return None;
+            }
+            enum_.reserved_range.remove(index as usize);
+            Pruned::element(tag::enum_::RESERVED_RANGE, index)
+        }
+        _ => None,
+    }
+}
+
+fn prune_service(service: &mut ServiceDescriptorProto, path: &[i32]) -> Option<Pruned> {
+    match path {
+        [tag::service::METHOD, rest @ ..] => prune_in(
+            &mut service.method,
+            tag::service::METHOD,
+            rest,
+            |method, path| match path {
+                [tag::method::OPTIONS, ..] => method
+                    .options
+                    .take()
+                    .and_then(|_| Pruned::options(tag::method::OPTIONS)),
+                _ => None,
+            },
+        ),
+        [tag::service::OPTIONS, ..] => service
+            .options
+            .take()
+            .and_then(|_| Pruned::options(tag::service::OPTIONS)),
+        _ => None,
+    }
+}
+
+/// Removes the locations of a pruned element, and renumbers the locations of the elements after it.
+fn remove_locations(source_code_info: &mut SourceCodeInfo, pruned: &Pruned) {
+    source_code_info
+        .location
+        .retain(|location| !location.path.starts_with(&pruned.path));
+
+    if pruned.shifted {
+        let (index, parent) = pruned.path.split_last().unwrap();
+        for location in &mut source_code_info.location {
+            if location.path.len() > parent.len() && location.path.starts_with(parent) {
+                let i = &mut location.path[parent.len()];
+                if *i > *index {
+                    *i -= 1;
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,105 @@
+use miette::{Diagnostic, NamedSource, SourceSpan};
+use prost_reflect::DescriptorError;
+use prost_types::{DescriptorProto, FileDescriptorProto};
+use protox_parse::tag;
+
+use super::options::find_span;
+use crate::error::{Error, ErrorKind};
+
+/// Adds a span to an error from the descriptor pool about the key or value type of a map field.
+///
+/// Map entry messages are generated by the parser, so they have no source locations, and the descriptor pool cannot
+/// point at the type which failed to resolve. This finds the map field whose key or value type matches the name in
+/// the error instead, and points at that type within the field's `map<K, V>` type.
+pub(crate) fn find_invalid_map_type(
+    file: &FileDescriptorProto,
+    source: &str,
+    err: &DescriptorError,
+) -> Option<Error> {
+    if err
+        .labels()
+        .is_some_and(|mut labels| labels.next().is_some())
+    {
+        return None;
+    }
+
+    let message = err.to_string();
+    let name = message.split('\'').nth(1)?;
+
+    let mut path = vec![tag::file::MESSAGE_TYPE, 0];
+    for (index, message_type) in file.message_type.iter().enumerate() {
+        path[1] = index as i32;
+        if let Some(span) = find_in_message(file, source, message_type, &mut path, name) {
+            return Some(Error::from_kind(ErrorKind::InvalidMapType {
+                message,
+                help: err.help().map(|help| help.to_string()),
+                span: Some(span),
+                source_code: NamedSource::new(file.name(), source.to_owned()),
+            }));
+        }
+    }
+    None
+}
+
+fn find_in_message(
+    file: &FileDescriptorProto,
+    source: &str,
+    message: &DescriptorProto,
+    path: &mut Vec<i32>,
+    name: &str,
+) -> Option<SourceSpan> {
+    for (index, field) in message.field.iter().enumerate() {
+        let Some(entry) = map_entry(message, field.type_name()) else {
+            continue;
+        };
+        let is_key = match &entry.field[..] {
+            [key, _] if key.type_name() == name => true,
+            [_, value] if value.type_name() == name => false,
+            _ => continue,
+        };
+
+        path.extend([tag::message::FIELD, index as i32, tag::field::TYPE_NAME]);
+        let span = find_span(file, source, path);
+        path.truncate(path.len() - 3);
+        return span.map(|span| narrow_span(source, span, name, is_key));
+    }
+
+    for (index, nested) in message.nested_type.iter().enumerate() {
+        path.extend([tag::message::NESTED_TYPE, index as i32]);
+        let span = find_in_message(file, source, nested, path, name);
+        path.truncate(path.len() - 2);
+        if span.is_some() {
+            return span;
+        }
+    }
+    None
+}
+
+/// Gets the map entry message nested in `message` which a field with the given type name refers to.
+fn map_entry<'a>(message: &'a DescriptorProto, type_name: &str) -> Option<&'a DescriptorProto> {
+    let entry_name = type_name.rsplit('.').next()?;
+    message.nested_type.iter().find(|nested| {
+        nested.name() == entry_name
+            && nested
+                .options
+                .as_ref()
+                .is_some_and(|options| options.map_entry())
+    })
+}
+
+/// Narrows the span of a `map<K, V>` type to the key or value type.
+fn narrow_span(source: &str, span: SourceSpan, name: &str, is_key: bool) -> SourceSpan {
+    let text = &source[span.offset()..][..span.len()];
+    let (Some(open), Some(comma)) = (text.find('<'), text.find(',')) else {
+        return span;
+    };
+    let range = if is_key {
+        open + 1..comma
+    } else {
+        comma + 1..text.len()
+    };
+    match text[range.clone()].find(name) {
+        Some(start) => (span.offset() + range.start + start, name.len()).into(),
+        None => span,
+    }
+}
@@ -0,0 +1,73 @@
+use miette::NamedSource;
+use prost_reflect::{Cardinality, FileDescriptor, Kind, MessageDescriptor, Syntax};
+use protox_parse::tag;
+
+use super::options::find_span;
+use crate::error::{Error, ErrorKind};
+
+/// Checks the restrictions on messages with the `message_set_wire_format` option declared in the file, and on
+/// extensions of such messages.
+pub(crate) fn check_message_sets(file: &FileDescriptor, source: Option<&str>) -> Result<(), Error> {
+    let error = |path: &[i32], tag: i32, message: &'static str| {
+        let mut path = path.to_vec();
+        path.push(tag);
+        Error::from_kind(ErrorKind::InvalidMessageSet {
+            message,
+            span: source.and_then(|source| find_span(file.file_descriptor_proto(), source, &path)),
+            source_code: NamedSource::new(file.name(), source.unwrap_or_default().to_owned()),
+        })
+    };
+
+    let mut messages: Vec<_> = file.messages().collect();
+    let mut extensions: Vec<_> = file.extensions().collect();
+    while let Some(message) = messages.pop() {
+        if is_message_set(&message) {
+            if file.syntax() == Syntax::Proto3 {
+                return Err(error(
+                    message.path(),
+                    tag::field::NAME,
+                    "message sets are not supported in proto3",
+                ));
+            }
+            if let Some(field) = message.fields().next() {
+                return Err(error(
+                    field.path(),
+                    tag::field::NAME,
+                    "message sets cannot have fields, only extensions",
+                ));
+            }
+        }
+
+        messages.extend(message.child_messages());
+        extensions.extend(message.child_extensions());
+    }
+
+    for extension in extensions {
+        if is_message_set(&extension.containing_message())
+            && (!matches!(extension.kind(), Kind::Message(_))
+                || extension.is_group()
+                || extension.cardinality() != Cardinality::Optional)
+        {
+            // Like protoc, report the error on the type of the extension.
+            let tag = match extension.kind() {
+                Kind::Message(_) | Kind::Enum(_) if !extension.is_group() => tag::field::TYPE_NAME,
+                _ => tag::field::TYPE,
+            };
+            return Err(error(
+                extension.path(),
+                tag,
+                "extensions of message sets must be optional messages",
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+fn is_message_set(message: &MessageDescriptor) -> bool {
+    message
+        .descriptor_proto()
+        .options
+        .as_ref()
+        .is_some_and(|options| options.message_set_wire_format())
+}
@@ -1,23 +1,157 @@
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     fmt::{self, Write},
+    fs,
+    io::{self, Write as _},
+    iter, mem,
+    ops::Range,
     path::{Path, PathBuf},
+    time::Instant,
 };
 
-use prost::Message;
-use prost_reflect::{DescriptorPool, DynamicMessage, ReflectMessage, Value};
-use prost_types::{FileDescriptorProto, FileDescriptorSet};
+use bytes::{Buf, Bytes};
+use prost::{
+    encoding::{self, DecodeContext, WireType},
+    Message,
+};
+use prost_reflect::{
+    DescriptorError, DescriptorPool, DynamicMessage, FileDescriptor, ReflectMessage, Value,
+};
+use prost_types::FileDescriptorProto;
+use protox_parse::tag;
 
 use crate::{
-    error::{Error, ErrorKind},
-    file::{check_shadow, path_to_file_name, File, FileMetadata, FileResolver},
+    check::{CheckDiagnostic, CustomCheck, Diagnostics, FileDiagnostics},
+    error::{Error, ErrorKind, Searched},
+    file::{
+        check_shadow, create_temp, path_to_file_name, ChainFileResolver, DescriptorSetFileResolver,
+        File, FileMetadata, FileResolver, ImportResolution, InputDigest,
+    },
+    format::{DescriptorSet, OutputFormat},
+    stats::{CompileReport, MemoryUsage, Stats},
+    symbol::{self, Location, SymbolTable, SyntheticOrigin, TypeReference},
 };
 
+mod byte_compat;
+mod declaration;
+mod depfile;
+mod descriptor;
+mod duplicate;
+mod enum_value;
+mod extension_number;
+mod filter;
+mod json_name;
+mod lenient;
+mod map_field;
+mod message_set;
+mod observer;
+mod oneof;
+mod options;
+mod placeholder;
+mod protoc_args;
+mod references;
+mod standard_options;
+mod syntax;
 #[cfg(test)]
 mod tests;
+mod unused;
+
+/// The maximum length of the key and length prefix of an encoded file.
+const MAX_HEADER_LEN: usize = 11;
+
+/// A predicate deciding whether an imported file is included in the output.
+type ImportFilter = Box<dyn Fn(&str) -> bool + Send + Sync>;
+
+/// Controls how [`Compiler::open_file`] handles a path to a file which is shadowed by a file with the same name in an
+/// earlier include path.
+///
+/// See [`Compiler::shadow_policy`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ShadowPolicy {
+    /// Shadowed files are an error, like in `protoc`.
+    #[default]
+    Error,
+    /// The file found first in the include paths is used, and a warning is reported in
+    /// [`check_warnings`](Compiler::check_warnings).
+    WarnAndUseFirst,
+    /// The file found first in the include paths is used if it has the same contents as the shadowed file. Otherwise,
+    /// it is an error.
+    AllowIfIdenticalContent,
+}
+
+/// A version of `protoc` whose validation rules a [`Compiler`] can reproduce.
+///
+/// Each level is named after the last version of `protoc` it matches, and later levels compare greater than earlier
+/// ones. See [`Compiler::compatibility_level`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[non_exhaustive]
+pub enum CompatibilityLevel {
+    /// `protoc` 3.12 to 3.14, run without `--experimental_allow_proto3_optional`. Proto3 `optional` fields are
+    /// rejected, and the other checks are the same as for [`Protoc21`](CompatibilityLevel::Protoc21).
+    Protoc3_12,
+    /// `protoc` 3.15 to 21.x. Proto3 `optional` fields are wrapped in synthetic oneofs, and options are not checked
+    /// against their `targets`.
+    Protoc21,
+    /// `protoc` 22 to 25, which enforce the `targets` of options. Editions were experimental in these versions, and
+    /// are not supported by protox, so this currently applies the same checks as [`Latest`](CompatibilityLevel::Latest).
+    Protoc25,
+    /// The latest version of `protoc`.
+    #[default]
+    Latest,
+}
+
+impl CompatibilityLevel {
+    fn allows_proto3_optional(self) -> bool {
+        self >= CompatibilityLevel::Protoc21
+    }
+
+    fn checks_option_targets(self) -> bool {
+        self >= CompatibilityLevel::Protoc25
+    }
+}
+
+/// Controls whether errors found while checking a file stop it from being compiled.
+///
+/// See [`Compiler::check_mode`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum CheckMode {
+    /// The first error found in a file is returned, and the file is not added to the compiler.
+    #[default]
+    Strict,
+    /// Errors are recorded in [`check_errors`](Compiler::check_errors), and the file is added with any invalid
+    /// definitions removed.
+    Lenient,
+}
+
+/// Controls how pre-compiled files, such as those added with [`Compiler::add_file_descriptor_set`], are checked.
+///
+/// See [`Compiler::descriptor_verification`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum DescriptorVerification {
+    /// Pre-compiled files are checked with the same rules as source files. Errors have no span, since there is no
+    /// source to point to.
+    #[default]
+    Strict,
+    /// Pre-compiled files are assumed to be valid, and are only checked when they are added to the descriptor pool.
+    TrustInput,
+}
+
+pub use self::{filter::DescriptorFilter, observer::CompilerObserver, unused::UnusedImport};
+pub(crate) use self::{
+    options::find_span,
+    references::{find_location, source_references, tokens, SourceReference},
+    standard_options::is_packable,
+    unused::{find_unused_imports, removal_fix},
+};
 
 /// Options for compiling protobuf files.
 ///
+/// A `Compiler` is [`Send`] and [`Sync`], so it may be moved to another thread, or shared behind a mutex. To compile
+/// several independent sets of files concurrently, create a `Compiler` for each one.
+///
 /// # Examples
 ///
 /// ```
@@ -50,7 +184,53 @@ pub struct Compiler {
     resolver: Box<dyn FileResolver>,
     files: HashMap<String, FileMetadata>,
     include_imports: bool,
+    import_filter: Option<ImportFilter>,
     include_source_info: bool,
+    retain_options: bool,
+    byte_compatible: bool,
+    deny_unused_imports: bool,
+    unused_imports: Vec<UnusedImport>,
+    trace_imports: bool,
+    import_trace: Vec<ImportResolution>,
+    input_digests: Vec<InputDigest>,
+    checks: Vec<Box<dyn CustomCheck>>,
+    observers: Vec<Box<dyn CompilerObserver>>,
+    check_warnings: Vec<CheckDiagnostic>,
+    includes: Vec<PathBuf>,
+    hermetic: bool,
+    interpret_options: bool,
+    uninterpreted_options: HashMap<String, Vec<options::UninterpretedOptions>>,
+    sources: HashMap<String, String>,
+    retain_sources: bool,
+    import_rewrites: Vec<(String, String)>,
+    allow_enum_value_name_conflicts: bool,
+    allow_identical_redefinitions: bool,
+    aliases: HashMap<String, String>,
+    shadow_policy: ShadowPolicy,
+    compatibility_level: CompatibilityLevel,
+    report: Option<CompileReport>,
+    lossy_utf8: bool,
+    allow_missing_weak_imports: bool,
+    allow_missing_imports: bool,
+    missing_imports: HashSet<String>,
+    check_mode: CheckMode,
+    descriptor_verification: DescriptorVerification,
+    check_errors: Vec<Error>,
+    references: HashMap<String, Vec<Location>>,
+}
+
+/// The compiled state of a [`Compiler`], saved by [`Compiler::snapshot`] to be restored by [`Compiler::restore`].
+#[derive(Debug, Clone)]
+pub struct CompilerSnapshot {
+    pool: DescriptorPool,
+    files: HashMap<String, FileMetadata>,
+    unused_imports: usize,
+    import_trace: usize,
+    input_digests: usize,
+    check_warnings: usize,
+    check_errors: usize,
+    missing_imports: HashSet<String>,
+    report: Option<CompileReport>,
 }
 
 impl Compiler {
@@ -67,13 +247,19 @@ impl Compiler {
 
         let mut resolver = ChainFileResolver::new();
 
-        for include in includes {
-            resolver.add(IncludeFileResolver::new(include.as_ref().to_owned()));
+        let includes: Vec<PathBuf> = includes
+            .into_iter()
+            .map(|include| include.as_ref().to_owned())
+            .collect();
+        for include in &includes {
+            resolver.add(IncludeFileResolver::new(include.clone()));
         }
 
         resolver.add(GoogleFileResolver::new());
 
-        Ok(Compiler::with_file_resolver(resolver))
+        let mut compiler = Compiler::with_file_resolver(resolver);
+        compiler.includes = includes;
+        Ok(compiler)
     }
 
     /// Creates a new [`Compiler`] with a custom [`FileResolver`] for looking up imported files.
@@ -86,8 +272,199 @@ impl Compiler {
             resolver: Box::new(resolver),
             files: HashMap::new(),
             include_imports: false,
+            import_filter: None,
             include_source_info: false,
+            retain_options: false,
+            byte_compatible: false,
+            deny_unused_imports: false,
+            unused_imports: Vec::new(),
+            trace_imports: false,
+            import_trace: Vec::new(),
+            input_digests: Vec::new(),
+            checks: Vec::new(),
+            observers: Vec::new(),
+            check_warnings: Vec::new(),
+            includes: Vec::new(),
+            hermetic: false,
+            interpret_options: true,
+            uninterpreted_options: HashMap::new(),
+            sources: HashMap::new(),
+            retain_sources: true,
+            import_rewrites: Vec::new(),
+            allow_enum_value_name_conflicts: false,
+            allow_identical_redefinitions: false,
+            aliases: HashMap::new(),
+            shadow_policy: ShadowPolicy::Error,
+            compatibility_level: CompatibilityLevel::Latest,
+            report: None,
+            lossy_utf8: false,
+            allow_missing_weak_imports: false,
+            allow_missing_imports: false,
+            missing_imports: HashSet::new(),
+            check_mode: CheckMode::Strict,
+            descriptor_verification: DescriptorVerification::Strict,
+            check_errors: Vec::new(),
+            references: HashMap::new(),
+        }
+    }
+
+    /// Creates a new [`Compiler`] configured from `protoc` command line arguments, and compiles the input files.
+    ///
+    /// This understands the commonly used subset of `protoc` flags, allowing protox to be used by scripts and build
+    /// rules which already construct a `protoc` command line. The arguments should not include the program name.
+    ///
+    /// - `-I<PATH>`, `-I <PATH>`, `--proto_path=<PATH>` and `--proto_path <PATH>` add include paths. If none are
+    ///   given, the current directory is used.
+    /// - `--descriptor_set_in=<FILES>` registers encoded file descriptor sets, as by
+    ///   [`add_encoded_file_descriptor_set`](Compiler::add_encoded_file_descriptor_set).
+    /// - `--include_imports`, `--include_source_info` and `--retain_options` set the corresponding options.
+    /// - All other arguments not starting with `-` are input files, passed to [`open_file`](Compiler::open_file).
+    ///
+    /// Like `protoc`, the values of `--proto_path` and `--descriptor_set_in` may list several paths, separated by
+    /// `:` on Unix or `;` on Windows.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if an argument is not recognized, including output flags such as `--descriptor_set_out`, if
+    /// a descriptor set cannot be read, or if any input file fails to compile.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use std::fs;
+    /// # use protox::Compiler;
+    /// # fn main() -> Result<(), protox::Error> {
+    /// # let tempdir = tempfile::TempDir::new().unwrap();
+    /// # std::env::set_current_dir(&tempdir).unwrap();
+    /// # fs::create_dir("protos").unwrap();
+    /// fs::write("protos/bar.proto", "message Bar { }").unwrap();
+    ///
+    /// let args = ["-Iprotos", "--include_imports", "bar.proto"].map(String::from);
+    /// let compiler = Compiler::from_protoc_args(&args)?;
+    ///
+    /// assert_eq!(compiler.file_descriptor_set().file[0].message_type[0].name(), "Bar");
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn from_protoc_args(args: &[String]) -> Result<Self, Error> {
+        let args = protoc_args::ProtocArgs::parse(args)?;
+
+        let mut compiler = Compiler::new(&args.includes)?;
+        compiler
+            .include_imports(args.include_imports)
+            .include_source_info(args.include_source_info)
+            .retain_options(args.retain_options);
+        for path in &args.descriptor_sets {
+            let buf = fs::read(path).map_err(|err| {
+                Error::from_kind(ErrorKind::OpenFile {
+                    name: path.display().to_string(),
+                    path: path.clone(),
+                    err,
+                })
+            })?;
+            compiler.add_encoded_file_descriptor_set(buf.as_slice())?;
         }
+        compiler.open_files(&args.files)?;
+        Ok(compiler)
+    }
+
+    /// Creates a new [`Compiler`] with include paths inferred from the imports of the given files, and compiles them.
+    ///
+    /// This is useful when the layout of include paths is not known in advance. See
+    /// [`discover_includes`](crate::file::discover_includes) for how the include paths are chosen.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the include paths cannot be determined, or if any file fails to compile.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use std::fs;
+    /// # use protox::Compiler;
+    /// # fn main() -> Result<(), protox::Error> {
+    /// # let tempdir = tempfile::TempDir::new().unwrap();
+    /// # std::env::set_current_dir(&tempdir).unwrap();
+    /// fs::create_dir_all("proto/foo").unwrap();
+    /// fs::write("proto/foo/bar.proto", "package foo; message Bar {}").unwrap();
+    /// fs::write("proto/foo/root.proto", "import 'foo/bar.proto'; message Root { optional foo.Bar bar = 1; }").unwrap();
+    ///
+    /// let compiler = Compiler::with_discovered_includes(["proto/foo/root.proto"])?;
+    ///
+    /// assert_eq!(compiler.file_descriptor_set().file[0].name(), "foo/root.proto");
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn with_discovered_includes(
+        files: impl IntoIterator<Item = impl AsRef<Path>>,
+    ) -> Result<Self, Error> {
+        let files: Vec<PathBuf> = files
+            .into_iter()
+            .map(|file| file.as_ref().to_owned())
+            .collect();
+
+        let mut compiler = Compiler::new(crate::file::discover_includes(&files)?)?;
+        compiler.open_files(&files)?;
+        Ok(compiler)
+    }
+
+    /// Registers pre-compiled files, which are used in preference to the [`FileResolver`] when opening files.
+    ///
+    /// This allows a project which vendors its own versions of common dependencies, such as the well-known types in
+    /// `google/protobuf/*.proto` or `validate/validate.proto`, to supply them once as binary descriptors. Any import
+    /// of a file in the set resolves to the registered descriptor, regardless of the include paths. If several sets
+    /// contain a file with the same name, the one added most recently is used.
+    ///
+    /// Files are only looked up when they are first opened, so this should be called before
+    /// [`open_file`](Compiler::open_file).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use prost_types::{DescriptorProto, FileDescriptorProto, FileDescriptorSet};
+    /// # use protox::{file::{File, FileResolver}, Compiler};
+    /// # struct Resolver;
+    /// # impl FileResolver for Resolver {
+    /// #     fn open_file(&self, name: &str) -> Result<File, protox::Error> {
+    /// #         File::from_source(name, "import 'vendor/dep.proto'; message Root { optional Dep dep = 1; }")
+    /// #     }
+    /// # }
+    /// # fn main() -> Result<(), protox::Error> {
+    /// let vendored = FileDescriptorSet {
+    ///     file: vec![FileDescriptorProto {
+    ///         name: Some("vendor/dep.proto".to_owned()),
+    ///         message_type: vec![DescriptorProto {
+    ///             name: Some("Dep".to_owned()),
+    ///             ..Default::default()
+    ///         }],
+    ///         ..Default::default()
+    ///     }],
+    /// };
+    ///
+    /// let mut compiler = Compiler::with_file_resolver(Resolver);
+    /// compiler.add_file_descriptor_set(vendored);
+    /// compiler.open_file("root.proto")?;
+    /// assert!(compiler.descriptor_pool().get_message_by_name("Dep").is_some());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn add_file_descriptor_set(&mut self, set: prost_types::FileDescriptorSet) -> &mut Self {
+        self.add_predeclared_files(DescriptorSetFileResolver::new(set));
+        self
+    }
+
+    /// Registers pre-compiled files from an encoded [`FileDescriptorSet`](prost_types::FileDescriptorSet).
+    ///
+    /// See [`add_file_descriptor_set`](Compiler::add_file_descriptor_set). Unlike that method, extension options
+    /// set in the files are preserved.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the bytes are not a valid encoded file descriptor set.
+    pub fn add_encoded_file_descriptor_set(&mut self, buf: impl Buf) -> Result<&mut Self, Error> {
+        let resolver = DescriptorSetFileResolver::decode(buf).map_err(Error::new)?;
+        self.add_predeclared_files(resolver);
+        Ok(self)
     }
 
     /// Sets whether the output `FileDescriptorSet` should include source info.
@@ -109,13 +486,520 @@ impl Compiler {
         self
     }
 
+    /// Sets a filter on the imported files included in the output when [`include_imports`](Compiler::include_imports)
+    /// is set.
+    ///
+    /// The filter is called with the name of each imported file, and the file is only included if it returns `true`.
+    /// Files added with [`open_file`](Compiler::open_file) are always included. Replaces any filter set previously,
+    /// including by [`exclude_google_imports`](Compiler::exclude_google_imports).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use protox::{file::{File, FileResolver}, Compiler};
+    /// # struct Resolver;
+    /// # impl FileResolver for Resolver {
+    /// #     fn open_file(&self, name: &str) -> Result<File, protox::Error> {
+    /// #         match name {
+    /// #             "root.proto" => File::from_source(name, "import 'vendor/dep.proto'; import 'dep.proto';"),
+    /// #             _ => File::from_source(name, ""),
+    /// #         }
+    /// #     }
+    /// # }
+    /// # fn main() -> Result<(), protox::Error> {
+    /// let mut compiler = Compiler::with_file_resolver(Resolver);
+    /// compiler
+    ///     .include_imports(true)
+    ///     .filter_imports(|name| !name.starts_with("vendor/"))
+    ///     .open_file("root.proto")?;
+    ///
+    /// let files = compiler.file_descriptor_set().file;
+    /// assert_eq!(files[0].name(), "dep.proto");
+    /// assert_eq!(files[1].name(), "root.proto");
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn filter_imports(
+        &mut self,
+        filter: impl Fn(&str) -> bool + Send + Sync + 'static,
+    ) -> &mut Self {
+        self.import_filter = Some(Box::new(filter));
+        self
+    }
+
+    /// Sets whether to exclude imported `google/protobuf/*.proto` files, such as the well-known types, from the output
+    /// when [`include_imports`](Compiler::include_imports) is set.
+    ///
+    /// This is useful when the consumer of the output already links the well-known types. It replaces any filter set by
+    /// [`filter_imports`](Compiler::filter_imports).
+    pub fn exclude_google_imports(&mut self, yes: bool) -> &mut Self {
+        if yes {
+            self.filter_imports(|name| !name.starts_with("google/protobuf/"))
+        } else {
+            self.import_filter = None;
+            self
+        }
+    }
+
+    /// Sets whether the output `FileDescriptorSet` should include options with source retention.
+    ///
+    /// Options whose definition is annotated with `retention = RETENTION_SOURCE` are only meaningful to tools
+    /// processing the source files, so by default they are removed from the output of
+    /// [`file_descriptor_set`](Compiler::file_descriptor_set) and
+    /// [`encode_file_descriptor_set`](Compiler::encode_file_descriptor_set), matching `protoc`. They are always
+    /// available through [`descriptor_pool`](Compiler::descriptor_pool).
+    ///
+    /// Option retention is only supported if the imported version of `google/protobuf/descriptor.proto` defines the
+    /// `retention` field of `FieldOptions`.
+    pub fn retain_options(&mut self, yes: bool) -> &mut Self {
+        self.retain_options = yes;
+        self
+    }
+
+    /// Sets whether the output is encoded byte-for-byte as `protoc` would encode it.
+    ///
+    /// By default, the output of [`encode_file_descriptor_set`](Compiler::encode_file_descriptor_set) contains the
+    /// same descriptors as `protoc`, but not always in the same order: source code info locations are sorted by path,
+    /// and custom options are encoded by field number. If this option is set, locations are instead ordered as `protoc`
+    /// records them while parsing, and custom options are written after the standard options, with one entry for each
+    /// option statement in source order. This is useful for tools which hash or compare descriptor bytes, at the cost
+    /// of re-encoding each file in the output.
+    ///
+    /// Custom options without a source code info location, such as those of a file added with
+    /// [`add_file_descriptor_set`](Compiler::add_file_descriptor_set) without source info, are written in order of
+    /// field number after the other options.
+    pub fn byte_compatible(&mut self, yes: bool) -> &mut Self {
+        self.byte_compatible = yes;
+        self
+    }
+
+    /// Sets whether files with unused imports should fail to compile.
+    ///
+    /// An import is unused if the imported file does not declare any type or option extension referenced by the
+    /// importing file. Regardless of this option, unused imports in files added with [`open_file`](Compiler::open_file)
+    /// are reported by [`unused_imports`](Compiler::unused_imports).
+    pub fn deny_unused_imports(&mut self, yes: bool) -> &mut Self {
+        self.deny_unused_imports = yes;
+        self
+    }
+
+    /// Sets whether to record how each file name is resolved.
+    ///
+    /// If set, every file opened through the [`FileResolver`] is recorded in [`import_trace`](Compiler::import_trace),
+    /// including which resolvers were consulted and which one found the file. This is useful to diagnose imports
+    /// which resolve to a file shadowed by an earlier include path.
+    ///
+    /// If the `tracing` feature is enabled, a debug event describing the resolution of each file is emitted regardless
+    /// of this option.
+    pub fn trace_imports(&mut self, yes: bool) -> &mut Self {
+        self.trace_imports = yes;
+        self
+    }
+
+    /// Sets whether compilation is restricted to files inside the include paths.
+    ///
+    /// In hermetic mode, every file name, including the names in `import` statements, must be a relative path without
+    /// `.` or `..` components, so that imports cannot refer to absolute paths or escape the include paths. For a
+    /// [`Compiler`] created with [`new`](Compiler::new), it is also an error to read a file whose real path, after
+    /// following symbolic links, is not inside any of the include paths.
+    ///
+    /// Files supplied by a custom [`FileResolver`] are trusted, and only have their names checked.
+    pub fn hermetic(&mut self, yes: bool) -> &mut Self {
+        self.hermetic = yes;
+        self
+    }
+
+    /// Sets whether options are interpreted.
+    ///
+    /// By default, options are resolved and validated against their definitions in the same way as `protoc`. If this
+    /// is disabled, each option is instead left in the `uninterpreted_option` field of its options message in the
+    /// output of [`file_descriptor_set`](Compiler::file_descriptor_set) and
+    /// [`encode_file_descriptor_set`](Compiler::encode_file_descriptor_set), as in the state of a descriptor before
+    /// `protoc` interprets its options. Source code info locations refer to the `uninterpreted_option` entries. This
+    /// is useful for code generators which resolve options themselves.
+    ///
+    /// Since options are not resolved, unknown options are not reported and options such as `packed` have no effect
+    /// on the files in [`descriptor_pool`](Compiler::descriptor_pool), which do not include uninterpreted options.
+    /// Imports which are only used for option extensions are reported by
+    /// [`unused_imports`](Compiler::unused_imports).
+    pub fn interpret_options(&mut self, yes: bool) -> &mut Self {
+        self.interpret_options = yes;
+        self
+    }
+
+    /// Sets whether the source text of compiled files is kept after they are compiled.
+    ///
+    /// By default, the source of each file is kept so that errors involving two files, such as a name defined in both,
+    /// can point at the location in each file, and so that [`allow_identical_redefinitions`] can compare files by
+    /// their source. For very large compilations, disabling this reduces memory usage, which can be checked with
+    /// [`memory_usage`](Compiler::memory_usage). Errors then only point at the file being compiled, and redefinitions
+    /// are compared by their file descriptors instead.
+    ///
+    /// [`allow_identical_redefinitions`]: Compiler::allow_identical_redefinitions
+    pub fn retain_sources(&mut self, yes: bool) -> &mut Self {
+        self.retain_sources = yes;
+        if !yes {
+            self.sources = HashMap::new();
+        }
+        self
+    }
+
+    /// Sets whether source files which are not valid UTF-8 are compiled anyway. Defaults to `false`.
+    ///
+    /// By default, opening such a file is an error which reports the byte offset of the first invalid sequence. If
+    /// this is set, each invalid sequence is instead replaced with U+FFFD, and a warning is added to
+    /// [`check_warnings`](Compiler::check_warnings). Invalid sequences usually only occur in comments or string
+    /// literals, so this allows any other errors in the file to be reported. Note that string literals containing
+    /// invalid sequences will not have the same value as when compiled by `protoc`.
+    pub fn lossy_utf8(&mut self, yes: bool) -> &mut Self {
+        self.lossy_utf8 = yes;
+        self
+    }
+
+    /// Sets whether enum values whose names conflict after removing the enum name prefix are allowed.
+    ///
+    /// Like `protoc`, by default it is an error for two values of a proto3 enum with different numbers to have the same
+    /// name once the name of the enum is removed from the start of each, ignoring case and underscores, and the rest is
+    /// converted to `PascalCase`. For example, in an enum `Foo`, the values `FOO_BAR` and `Bar` conflict. Conflicts in
+    /// proto2 enums are always reported as warnings in [`check_warnings`](Compiler::check_warnings). If this is set,
+    /// conflicts in proto3 enums are reported as warnings too.
+    pub fn allow_enum_value_name_conflicts(&mut self, yes: bool) -> &mut Self {
+        self.allow_enum_value_name_conflicts = yes;
+        self
+    }
+
+    /// Sets whether a file may redefine the names of another file with identical contents.
+    ///
+    /// By default, it is an error for two files to define the same name, even if they are copies of each other, such
+    /// as when a vendored file is reachable under two different import paths. If this is set, a file whose contents
+    /// match a previously compiled file is instead treated as another name for it: its definitions are not added
+    /// again, and imports of it refer to the first file in the output.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use protox::{file::{File, FileResolver}, Compiler};
+    /// # struct Resolver;
+    /// # impl FileResolver for Resolver {
+    /// #     fn open_file(&self, name: &str) -> Result<File, protox::Error> {
+    /// #         match name {
+    /// #             "a/common.proto" | "b/common.proto" => File::from_source(name, "package common; message Common {}"),
+    /// #             "root.proto" => File::from_source(name, "import 'b/common.proto'; message Root { optional common.Common c = 1; }"),
+    /// #             _ => Err(protox::Error::file_not_found(name)),
+    /// #         }
+    /// #     }
+    /// # }
+    /// # fn main() -> Result<(), protox::Error> {
+    /// let mut compiler = Compiler::with_file_resolver(Resolver);
+    /// compiler
+    ///     .allow_identical_redefinitions(true)
+    ///     .open_files(["a/common.proto", "root.proto"])?;
+    ///
+    /// let files = compiler.file_descriptor_set().file;
+    /// assert_eq!(files[1].dependency, ["a/common.proto"]);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn allow_identical_redefinitions(&mut self, yes: bool) -> &mut Self {
+        self.allow_identical_redefinitions = yes;
+        self
+    }
+
+    /// Sets whether `import weak` statements may refer to files which cannot be found. Defaults to `false`.
+    ///
+    /// Like `protoc`, if this is set, a missing weak import is replaced with an empty placeholder file, and a warning
+    /// is added to [`check_warnings`](Compiler::check_warnings). The import is kept in the `dependency` and
+    /// `weak_dependency` fields of the importing file, but the placeholder is never included in the output. Since the
+    /// placeholder defines no names, any reference to a type from the missing file is reported as undefined, and
+    /// importing the missing file without `weak` is still an error.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use protox::{file::{File, FileResolver}, Compiler};
+    /// # struct Resolver;
+    /// # impl FileResolver for Resolver {
+    /// #     fn open_file(&self, name: &str) -> Result<File, protox::Error> {
+    /// #         match name {
+    /// #             "root.proto" => File::from_source(name, "import weak 'missing.proto'; message Root {}"),
+    /// #             _ => Err(protox::Error::file_not_found(name)),
+    /// #         }
+    /// #     }
+    /// # }
+    /// # fn main() -> Result<(), protox::Error> {
+    /// let mut compiler = Compiler::with_file_resolver(Resolver);
+    /// compiler
+    ///     .allow_missing_weak_imports(true)
+    ///     .include_imports(true)
+    ///     .open_file("root.proto")?;
+    ///
+    /// let files = compiler.file_descriptor_set().file;
+    /// assert_eq!(files.len(), 1);
+    /// assert_eq!(files[0].dependency, ["missing.proto"]);
+    /// assert_eq!(files[0].weak_dependency, [0]);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn allow_missing_weak_imports(&mut self, yes: bool) -> &mut Self {
+        self.allow_missing_weak_imports = yes;
+        self
+    }
+
+    /// Sets whether imports may refer to files which cannot be found. Defaults to `false`.
+    ///
+    /// This is intended for editors and other tools which analyze a file while its dependencies may be unavailable.
+    /// If set, each missing import is replaced with a placeholder file, and a warning is added to
+    /// [`check_warnings`](Compiler::check_warnings). Type names in the importing file which cannot be resolved are
+    /// defined as placeholder messages, or enums if they are used by a field with a default value, and each reference
+    /// to one is reported as a warning instead of an error. Like missing weak imports, the import is kept in the
+    /// importing file, but placeholder files are never included in the output.
+    ///
+    /// Placeholder types are only created for the first file to import a missing file, and all the types in a
+    /// placeholder file must be in the same package. Any other unresolved references are removed from the importing
+    /// file, along with the field or method making them, and also reported as warnings.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use protox::{file::{File, FileResolver}, Compiler};
+    /// # struct Resolver;
+    /// # impl FileResolver for Resolver {
+    /// #     fn open_file(&self, name: &str) -> Result<File, protox::Error> {
+    /// #         match name {
+    /// #             "root.proto" => File::from_source(name, "import 'missing.proto'; message Root { optional foo.Foo foo = 1; }"),
+    /// #             _ => Err(protox::Error::file_not_found(name)),
+    /// #         }
+    /// #     }
+    /// # }
+    /// # fn main() -> Result<(), protox::Error> {
+    /// let mut compiler = Compiler::with_file_resolver(Resolver);
+    /// compiler
+    ///     .allow_missing_imports(true)
+    ///     .open_file("root.proto")?;
+    ///
+    /// let files = compiler.file_descriptor_set().file;
+    /// assert_eq!(files.len(), 1);
+    /// assert_eq!(files[0].message_type[0].field[0].type_name(), ".foo.Foo");
+    /// assert_eq!(compiler.check_warnings().len(), 2);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn allow_missing_imports(&mut self, yes: bool) -> &mut Self {
+        self.allow_missing_imports = yes;
+        self
+    }
+
+    /// Sets how paths to files which are shadowed in the include paths are handled. Defaults to [`ShadowPolicy::Error`].
+    ///
+    /// When [`open_file`](Compiler::open_file) is passed a path inside an include path, it is compiled using the name
+    /// of the file relative to that include path. If an earlier include path also contains a file with that name, the
+    /// path is shadowed, because imports of that name would resolve to the other file. This commonly happens in
+    /// monorepos where the same file is reachable from several include paths, and the policy can be relaxed to accept
+    /// such files.
+    pub fn shadow_policy(&mut self, policy: ShadowPolicy) -> &mut Self {
+        self.shadow_policy = policy;
+        self
+    }
+
+    /// Sets the version of `protoc` whose validation rules are followed. Defaults to [`CompatibilityLevel::Latest`].
+    ///
+    /// Earlier levels skip the checks added in later versions of `protoc`, and reject proto3 `optional` fields if the
+    /// version required `--experimental_allow_proto3_optional`, so golden tests recorded with a pinned version of
+    /// `protoc` can be reproduced.
+    ///
+    /// Some rules apply at every level. Files declaring an `edition` are not supported, and conflicting JSON names
+    /// are always rejected, since the descriptor pool requires the JSON names of the fields of a message to be unique.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use protox::{file::{File, FileResolver}, CompatibilityLevel, Compiler};
+    /// # struct Resolver;
+    /// # impl FileResolver for Resolver {
+    /// #     fn open_file(&self, name: &str) -> Result<File, protox::Error> {
+    /// #         File::from_source(name, "
+    /// syntax = 'proto3';
+    ///
+    /// message Foo {
+    ///   optional int32 limit = 1;
+    /// }
+    /// #         ")
+    /// #     }
+    /// # }
+    /// let mut compiler = Compiler::with_file_resolver(Resolver);
+    /// compiler.compatibility_level(CompatibilityLevel::Protoc3_12);
+    /// assert!(compiler.open_file("foo.proto").is_err());
+    ///
+    /// let mut compiler = Compiler::with_file_resolver(Resolver);
+    /// compiler.compatibility_level(CompatibilityLevel::Protoc21);
+    /// compiler.open_file("foo.proto")?;
+    /// # Ok::<(), protox::Error>(())
+    /// ```
+    pub fn compatibility_level(&mut self, level: CompatibilityLevel) -> &mut Self {
+        self.compatibility_level = level;
+        self
+    }
+
+    /// Sets whether errors found while checking a file stop it from being compiled. Defaults to
+    /// [`CheckMode::Strict`].
+    ///
+    /// In [`CheckMode::Lenient`] mode, errors found after a file has been parsed are recorded in
+    /// [`check_errors`](Compiler::check_errors) instead of being returned, and the rest of the file is compiled as
+    /// usual. Definitions which cannot be added to the descriptor pool, such as fields referring to an unknown type,
+    /// are removed along with their source locations, or just their options if the error is in an option. This allows
+    /// tools such as language servers to provide completion and navigation for a file while it is being edited.
+    ///
+    /// Parse errors, unresolved imports and names already defined by another file are still returned.
+    pub fn check_mode(&mut self, mode: CheckMode) -> &mut Self {
+        self.check_mode = mode;
+        self
+    }
+
+    /// Sets how pre-compiled files are checked. Defaults to [`DescriptorVerification::Strict`].
+    ///
+    /// Files added with [`add_file_descriptor_set`](Compiler::add_file_descriptor_set) or opened from a
+    /// [`DescriptorSetFileResolver`] were not parsed by protox, so the rules the parser enforces, such as valid names
+    /// and the restrictions of proto3, have not been checked. In [`DescriptorVerification::Strict`] mode, these files
+    /// are checked with the same rules as source files, so that malformed descriptors are reported rather than
+    /// causing problems for later consumers. Since there is no source, the errors do not have a span.
+    ///
+    /// [`DescriptorVerification::TrustInput`] skips these checks, which may be useful for large sets produced by
+    /// `protoc`. Pre-compiled files which are not valid may then cause errors or panics later on.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use prost_types::{field_descriptor_proto::{Label, Type}, DescriptorProto, FieldDescriptorProto, FileDescriptorProto, FileDescriptorSet};
+    /// # use protox::{file::{File, FileResolver}, Compiler, DescriptorVerification};
+    /// # struct Resolver;
+    /// # impl FileResolver for Resolver {
+    /// #     fn open_file(&self, name: &str) -> Result<File, protox::Error> {
+    /// #         File::from_source(name, "syntax = 'proto3'; import 'vendor/dep.proto'; message Root { Dep dep = 1; }")
+    /// #     }
+    /// # }
+    /// let vendored = FileDescriptorSet {
+    ///     file: vec![FileDescriptorProto {
+    ///         name: Some("vendor/dep.proto".to_owned()),
+    ///         syntax: Some("proto3".to_owned()),
+    ///         message_type: vec![DescriptorProto {
+    ///             name: Some("Dep".to_owned()),
+    ///             field: vec![FieldDescriptorProto {
+    ///                 name: Some("id".to_owned()),
+    ///                 number: Some(1),
+    ///                 label: Some(Label::Required as i32),
+    ///                 r#type: Some(Type::Int32 as i32),
+    ///                 ..Default::default()
+    ///             }],
+    ///             ..Default::default()
+    ///         }],
+    ///         ..Default::default()
+    ///     }],
+    /// };
+    ///
+    /// let mut compiler = Compiler::with_file_resolver(Resolver);
+    /// compiler.add_file_descriptor_set(vendored.clone());
+    /// let err = compiler.open_file("root.proto").unwrap_err();
+    /// assert_eq!(err.to_string(), "required field 'Dep.id' is not allowed in proto3");
+    ///
+    /// let mut compiler = Compiler::with_file_resolver(Resolver);
+    /// compiler
+    ///     .descriptor_verification(DescriptorVerification::TrustInput)
+    ///     .add_file_descriptor_set(vendored);
+    /// assert!(compiler.open_file("root.proto").is_ok());
+    /// ```
+    pub fn descriptor_verification(&mut self, mode: DescriptorVerification) -> &mut Self {
+        self.descriptor_verification = mode;
+        self
+    }
+
+    /// Adds a rule which translates file names starting with `from_prefix` to start with `to_prefix` before they are
+    /// looked up.
+    ///
+    /// This allows sources to import files by a different name to their location in the include paths, similar to
+    /// `protoc`'s `--proto_path=virtual=real` mappings or the `import_prefix` and `strip_import_prefix` attributes of
+    /// Bazel's `proto_library`. For example, with a rewrite from `company` to `third_party/company`, the import
+    /// `company/foo.proto` is resolved by opening `third_party/company/foo.proto`.
+    ///
+    /// Prefixes match whole path components, and an empty `from_prefix` matches every file. If several prefixes match,
+    /// the longest one is used. The file keeps the name it was imported with in the output, so that the imports of
+    /// other files refer to it correctly.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use protox::{file::{File, FileResolver}, Compiler};
+    /// # struct Resolver;
+    /// # impl FileResolver for Resolver {
+    /// #     fn open_file(&self, name: &str) -> Result<File, protox::Error> {
+    /// #         match name {
+    /// #             "root.proto" => File::from_source(name, "import 'company/foo.proto'; message Root { optional Foo foo = 1; }"),
+    /// #             "third_party/company/foo.proto" => File::from_source(name, "message Foo {}"),
+    /// #             _ => Err(protox::Error::file_not_found(name)),
+    /// #         }
+    /// #     }
+    /// # }
+    /// # fn main() -> Result<(), protox::Error> {
+    /// let mut compiler = Compiler::with_file_resolver(Resolver);
+    /// compiler
+    ///     .add_import_rewrite("company", "third_party/company")
+    ///     .include_imports(true)
+    ///     .open_file("root.proto")?;
+    ///
+    /// let files = compiler.file_descriptor_set().file;
+    /// assert_eq!(files[0].name(), "company/foo.proto");
+    /// assert_eq!(compiler.files().next().unwrap().name(), "company/foo.proto");
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn add_import_rewrite(
+        &mut self,
+        from_prefix: impl Into<String>,
+        to_prefix: impl Into<String>,
+    ) -> &mut Self {
+        let trim = |prefix: String| prefix.trim_end_matches('/').to_owned();
+        self.import_rewrites
+            .push((trim(from_prefix.into()), trim(to_prefix.into())));
+        self
+    }
+
+    /// Registers a [`CustomCheck`] to run on each file added with [`open_file`](Compiler::open_file).
+    ///
+    /// Checks are run in the order they were added, after the file has been checked by the compiler. If a check
+    /// reports an error, the file fails to compile. Warnings are available from
+    /// [`check_warnings`](Compiler::check_warnings). Imported files are not checked unless they are also opened.
+    pub fn add_check(&mut self, check: impl CustomCheck + 'static) -> &mut Self {
+        self.checks.push(Box::new(check));
+        self
+    }
+
+    /// Registers a [`CompilerObserver`] to be notified as each file is read, parsed and checked, and of any errors.
+    ///
+    /// Observers are notified in the order they were added, for imported files as well as those opened directly.
+    pub fn add_observer(&mut self, observer: impl CompilerObserver + 'static) -> &mut Self {
+        self.observers.push(Box::new(observer));
+        self
+    }
+
     /// Compiles the file at the given path, and adds it to this `Compiler` instance.
     ///
     /// If the path is absolute, or relative to the current directory, it must reside under one of the
     /// include paths. Otherwise, it is looked up relative to the given include paths in the same way as
-    /// `import` statements.
+    /// `import` statements. Paths are compared after normalizing `.` and `..` components, and relative paths are
+    /// compared with absolute include paths relative to the current directory. If the path still does not match an
+    /// include path, symbolic links are resolved, so a file reached through a link or a different mapping of the same
+    /// drive is found. To compile a file under a name which does not match its path, use
+    /// [`add_file_with_name()`][Compiler::add_file_with_name()].
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "debug", skip_all, fields(path = %path.as_ref().display()))
+    )]
     pub fn open_file(&mut self, path: impl AsRef<Path>) -> Result<&mut Self, Error> {
-        let path = path.as_ref();
+        let result = self.open_path(path.as_ref());
+        self.observe_error(result)?;
+        Ok(self)
+    }
+
+    fn open_path(&mut self, path: &Path) -> Result<(), Error> {
         let (name, is_resolved) = if let Some(name) = self.resolver.resolve_path(path) {
             (name, true)
         } else if let Some(name) = path_to_file_name(path) {
@@ -123,118 +1007,641 @@ impl Compiler {
         } else {
             return Err(Error::from_kind(ErrorKind::FileNotIncluded {
                 path: path.to_owned(),
+                searched: None,
             }));
         };
 
-        if let Some(file_metadata) = self.files.get_mut(&name) {
+        if let Some(file_metadata) = self.files.get(&name) {
             if is_resolved {
-                check_shadow(&name, file_metadata.path(), path)?;
+                let actual_path = file_metadata.path().map(Path::to_owned);
+                self.check_shadow(&name, actual_path.as_deref(), path)?;
             }
-            file_metadata.is_import = false;
-            return Ok(self);
+            self.files.get_mut(&name).unwrap().is_import = false;
+            return Ok(());
         }
 
-        let file = self.resolver.open_file(&name).map_err(|err| {
+        let file = self.resolve_file(&name, None).map_err(|err| {
             if err.is_file_not_found() {
                 Error::from_kind(ErrorKind::FileNotIncluded {
                     path: path.to_owned(),
+                    searched: err.searched().map(|searched| Searched(searched.to_vec())),
                 })
             } else {
                 err
             }
         })?;
         if is_resolved {
-            check_shadow(&name, file.path(), path)?;
-        }
-
-        let mut import_stack = vec![name.clone()];
-        for (i, import) in file.descriptor.dependency.iter().enumerate() {
-            self.add_import(import, &mut import_stack)
-                .map_err(|e| e.into_import_error(&file, i))?;
+            self.check_shadow(&name, file.path(), path)?;
         }
-        drop(import_stack);
 
-        let path = self.check_file(file)?;
-        self.files.insert(
-            name.clone(),
-            FileMetadata {
-                name,
-                path,
-                is_import: false,
-            },
-        );
-        Ok(self)
+        self.add_file(name, file)
     }
 
-    /// Compiles the given files, and adds them to this `Compiler` instance.
+    /// Compiles the file at the given path, and adds it to this `Compiler` instance with the name `name`.
     ///
-    /// See [`open_file()`][Compiler::open_file()].
-    pub fn open_files(
+    /// The path is not matched against the include paths, so the file may be anywhere on the file system, and is
+    /// referred to by `name` in the output, in diagnostics and by the `import` statements of other files. Its imports
+    /// are resolved in the same way as for [`open_file()`][Compiler::open_file()]. If a file with the same name has
+    /// already been added, it is not read again.
+    ///
+    /// In [`hermetic`](Compiler::hermetic) mode, the name must still be a valid relative path, and the file must be
+    /// inside one of the include paths.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use protox::Compiler;
+    /// # let tempdir = tempfile::TempDir::new().unwrap();
+    /// let path = tempdir.path().join("foo.proto");
+    /// std::fs::write(&path, "package foo; message Foo {}").unwrap();
+    ///
+    /// let mut compiler = Compiler::new(["."])?;
+    /// compiler.add_file_with_name(&path, "foo/foo.proto")?;
+    /// assert_eq!(compiler.files().next().unwrap().name(), "foo/foo.proto");
+    /// # Ok::<(), protox::Error>(())
+    /// ```
+    pub fn add_file_with_name(
         &mut self,
-        paths: impl IntoIterator<Item = impl AsRef<Path>>,
+        path: impl AsRef<Path>,
+        name: &str,
     ) -> Result<&mut Self, Error> {
-        for path in paths {
-            self.open_file(path)?;
+        if let Some(file_metadata) = self.files.get_mut(name) {
+            file_metadata.is_import = false;
+            return Ok(self);
         }
 
+        let path = path.as_ref();
+        let result = self.check_file_name(name).and_then(|()| {
+            if self.hermetic {
+                self.check_hermetic_path(name, path)?;
+            }
+            let file = File::open(name, path);
+            let file = self.recover_utf8(name, file)?;
+            self.on_file_read(&file);
+            self.add_file(name.to_owned(), file)
+        });
+        self.observe_error(result)?;
         Ok(self)
     }
 
-    /// Converts all added files into an instance of [`FileDescriptorSet`](prost_types::FileDescriptorSet).
+    /// Compiles a file read from `reader`, such as the standard input of the process, and adds it to this `Compiler`
+    /// instance with the given name.
     ///
-    /// Files are sorted topologically, with dependency files ordered before the files that import them.
-    pub fn file_descriptor_set(&self) -> prost_types::FileDescriptorSet {
-        let file = self
-            .pool
-            .files()
-            .filter(|f| self.include_imports || !self.files[f.name()].is_import)
-            .map(|f| {
-                if self.include_source_info {
-                    f.file_descriptor_proto().clone()
-                } else {
-                    prost_types::FileDescriptorProto {
-                        source_code_info: None,
-                        ..f.file_descriptor_proto().clone()
-                    }
-                }
-            })
-            .collect();
+    /// The file is not looked up using the file resolver, so it can be compiled without writing it to a temporary
+    /// file. Its imports are resolved in the same way as for [`open_file()`][Compiler::open_file()], and diagnostics
+    /// refer to the file by `name`. If a file with the same name has already been added, it is not read again.
+    ///
+    /// # Errors
+    ///
+    /// In addition to the errors returned by [`open_file()`][Compiler::open_file()], returns an error if reading
+    /// fails, or if more than 2,147,483,647 bytes are read.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use protox::Compiler;
+    /// let mut compiler = Compiler::new(["."])?;
+    /// compiler.add_reader("foo.proto", "message Foo {}".as_bytes())?;
+    /// assert!(compiler.descriptor_pool().get_message_by_name("Foo").is_some());
+    /// # Ok::<(), protox::Error>(())
+    /// ```
+    pub fn add_reader(&mut self, name: &str, reader: impl io::Read) -> Result<&mut Self, Error> {
+        if let Some(file_metadata) = self.files.get_mut(name) {
+            file_metadata.is_import = false;
+            return Ok(self);
+        }
 
-        prost_types::FileDescriptorSet { file }
+        let result = self.check_file_name(name).and_then(|()| {
+            let file = File::from_reader(name, reader);
+            let file = self.recover_utf8(name, file)?;
+            self.on_file_read(&file);
+            self.add_file(name.to_owned(), file)
+        });
+        self.observe_error(result)?;
+        Ok(self)
     }
 
-    /// Converts all added files into an instance of [`FileDescriptorSet`](prost_types::FileDescriptorSet) and encodes it.
-    ///
-    /// This is equivalent to `file_descriptor_set()?.encode_to_vec()`, with the exception that extension
-    /// options are included.
-    pub fn encode_file_descriptor_set(&self) -> Vec<u8> {
-        if self.include_imports && self.include_source_info {
-            // Avoid reflection if possible.
-            return self.pool.encode_to_vec();
+    fn add_file(&mut self, name: String, file: File) -> Result<(), Error> {
+        self.add_imports(&file, &mut vec![name.clone()])?;
+
+        let start = self.start_timer();
+        let (path, source) = self.check_file(file)?;
+        if self.aliases.contains_key(&name) {
+            self.record_check_time(&name, start);
+            self.files.insert(
+                name.clone(),
+                FileMetadata {
+                    name,
+                    path,
+                    is_import: false,
+                },
+            );
+            return Ok(());
         }
 
-        let file_desc = FileDescriptorProto::default().descriptor();
+        let file_descriptor = self.pool.get_file_by_name(&name).unwrap();
+        let mut diagnostics = Diagnostics::new(&file_descriptor, source.as_deref());
+        for check in &self.checks {
+            check.check_file(&file_descriptor, &mut diagnostics);
+        }
+        let (errors, warnings): (Vec<_>, Vec<_>) = diagnostics
+            .into_inner()
+            .into_iter()
+            .partition(CheckDiagnostic::is_error);
+        if let Some(error) = errors.into_iter().next() {
+            self.recover(Err(error.into()))?;
+        }
+        self.check_warnings.extend(warnings);
 
-        let files = self
-            .pool
-            .files()
-            .filter(|f| self.include_imports || !self.files[f.name()].is_import)
-            .map(|f| {
-                let file_buf = f.encode_to_vec();
-
-                let mut file_msg =
-                    DynamicMessage::decode(file_desc.clone(), file_buf.as_slice()).unwrap();
-                if !self.include_source_info {
-                    file_msg.clear_field_by_name("source_code_info");
-                }
+        let mut unused_imports = unused::find_unused_imports(&file_descriptor, source.as_deref());
+        unused_imports.retain(|import| !self.missing_imports.contains(import.name()));
+        if self.deny_unused_imports {
+            if let Some(unused_import) = unused_imports.into_iter().next() {
+                self.recover(Err(unused_import.into()))?;
+            }
+        } else {
+            self.unused_imports.extend(unused_imports);
+        }
 
-                Value::Message(file_msg)
+        self.record_check_time(&name, start);
+        for observer in &self.observers {
+            observer.on_file_checked(&file_descriptor);
+        }
+        self.files.insert(
+            name.clone(),
+            FileMetadata {
+                name,
+                path,
+                is_import: false,
+            },
+        );
+        Ok(())
+    }
+
+    /// Checks the file with the given name against the files already compiled, without adding it to this `Compiler`
+    /// instance.
+    ///
+    /// The file is looked up using the file resolver in the same way as an `import` statement, and checked in the
+    /// same way as by [`open_file()`][Compiler::open_file()]. Afterwards, the state of the compiler is restored, so
+    /// the file can be checked again after it changes, for example to validate an editor buffer provided through an
+    /// [`OverlayFileResolver`](crate::file::OverlayFileResolver). Imports which have not already been compiled are
+    /// compiled as well, and are also discarded, so checking is cheapest once the dependencies of the file have been
+    /// added with [`open_file()`][Compiler::open_file()].
+    ///
+    /// # Errors
+    ///
+    /// Returns the first error found in the file, or any of its imports which had not already been compiled. It is
+    /// also an error if a file with the same name has already been compiled.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use protox::{file::{File, FileResolver}, Compiler};
+    /// # struct Resolver;
+    /// # impl FileResolver for Resolver {
+    /// #     fn open_file(&self, name: &str) -> Result<File, protox::Error> {
+    /// #         match name {
+    /// #             "dep.proto" => File::from_source(name, "message Dep {}"),
+    /// #             _ => File::from_source(name, "import 'dep.proto';\nmessage Foo { optional Dep dep = 1; }"),
+    /// #         }
+    /// #     }
+    /// # }
+    /// let mut compiler = Compiler::with_file_resolver(Resolver);
+    /// compiler.open_file("dep.proto")?;
+    ///
+    /// let diagnostics = compiler.check_file_only("foo.proto")?;
+    /// assert_eq!(diagnostics.file_descriptor_proto().message_type[0].field[0].type_name(), ".Dep");
+    /// assert!(compiler.descriptor_pool().get_message_by_name("Foo").is_none());
+    /// # Ok::<(), protox::Error>(())
+    /// ```
+    pub fn check_file_only(&mut self, name: &str) -> Result<FileDiagnostics, Error> {
+        if self.files.contains_key(name) {
+            return Err(Error::from_kind(ErrorKind::FileAlreadyCompiled {
+                name: name.to_owned(),
+            }));
+        }
+
+        let snapshot = self.snapshot();
+        let result = self.resolve_file(name, None).and_then(|file| {
+            self.add_file(name.to_owned(), file)?;
+            let file_name = self.aliases.get(name).map_or(name, String::as_str);
+            Ok(self
+                .pool
+                .get_file_by_name(file_name)
+                .unwrap()
+                .file_descriptor_proto()
+                .clone())
+        });
+
+        let warnings = self.check_warnings.split_off(snapshot.check_warnings);
+        let unused_imports = self.unused_imports.split_off(snapshot.unused_imports);
+        let errors = self.check_errors.split_off(snapshot.check_errors);
+        self.restore(&snapshot);
+
+        Ok(FileDiagnostics::new(
+            self.observe_error(result)?,
+            warnings,
+            unused_imports,
+            errors,
+        ))
+    }
+
+    /// Saves the files compiled so far, so that files compiled afterwards can be discarded with
+    /// [`restore()`][Compiler::restore()].
+    ///
+    /// This allows a tool such as a language server to run speculative analysis, for example compiling an edited
+    /// buffer along with the files which import it, and then return to the authoritative state. Saving a snapshot is
+    /// cheap, since the descriptor pool is shared until another file is added.
+    ///
+    /// A snapshot only records the compiled files, along with the diagnostics, unused imports and other results
+    /// collected for them. Options set on the compiler, and resolvers, checks and observers added to it, are not
+    /// affected by restoring a snapshot.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use protox::{file::{File, FileResolver}, Compiler};
+    /// # struct Resolver;
+    /// # impl FileResolver for Resolver {
+    /// #     fn open_file(&self, name: &str) -> Result<File, protox::Error> {
+    /// #         match name {
+    /// #             "dep.proto" => File::from_source(name, "message Dep {}"),
+    /// #             _ => File::from_source(name, "import 'dep.proto';\nmessage Foo { optional Dep dep = 1; }"),
+    /// #         }
+    /// #     }
+    /// # }
+    /// let mut compiler = Compiler::with_file_resolver(Resolver);
+    /// compiler.open_file("dep.proto")?;
+    ///
+    /// let snapshot = compiler.snapshot();
+    /// compiler.open_file("foo.proto")?;
+    /// assert!(compiler.descriptor_pool().get_message_by_name("Foo").is_some());
+    ///
+    /// compiler.restore(&snapshot);
+    /// assert!(compiler.descriptor_pool().get_message_by_name("Foo").is_none());
+    /// assert!(compiler.descriptor_pool().get_message_by_name("Dep").is_some());
+    /// # Ok::<(), protox::Error>(())
+    /// ```
+    pub fn snapshot(&self) -> CompilerSnapshot {
+        CompilerSnapshot {
+            pool: self.pool.clone(),
+            files: self.files.clone(),
+            unused_imports: self.unused_imports.len(),
+            import_trace: self.import_trace.len(),
+            input_digests: self.input_digests.len(),
+            check_warnings: self.check_warnings.len(),
+            check_errors: self.check_errors.len(),
+            missing_imports: self.missing_imports.clone(),
+            report: self.report.clone(),
+        }
+    }
+
+    /// Discards all files compiled since `snapshot` was saved by [`snapshot()`][Compiler::snapshot()], along with
+    /// their diagnostics and other results.
+    ///
+    /// The same snapshot may be restored several times, to repeatedly run analysis starting from the same state.
+    /// Since only the changes made after the snapshot are discarded, it must have been saved from this compiler, and
+    /// not before an older snapshot was restored.
+    pub fn restore(&mut self, snapshot: &CompilerSnapshot) -> &mut Self {
+        self.unused_imports.truncate(snapshot.unused_imports);
+        self.import_trace.truncate(snapshot.import_trace);
+        self.input_digests.truncate(snapshot.input_digests);
+        self.check_warnings.truncate(snapshot.check_warnings);
+        self.check_errors.truncate(snapshot.check_errors);
+        self.files.clone_from(&snapshot.files);
+        self.sources
+            .retain(|name, _| snapshot.files.contains_key(name));
+        self.aliases
+            .retain(|name, _| snapshot.files.contains_key(name));
+        self.uninterpreted_options
+            .retain(|name, _| snapshot.files.contains_key(name));
+        self.references.retain(|_, locations| {
+            locations.retain(|location| snapshot.files.contains_key(location.file()));
+            !locations.is_empty()
+        });
+        self.missing_imports.clone_from(&snapshot.missing_imports);
+        self.report.clone_from(&snapshot.report);
+        self.pool = snapshot.pool.clone();
+        self
+    }
+
+    /// Compiles the given files, and adds them to this `Compiler` instance.
+    ///
+    /// See [`open_file()`][Compiler::open_file()].
+    pub fn open_files(
+        &mut self,
+        paths: impl IntoIterator<Item = impl AsRef<Path>>,
+    ) -> Result<&mut Self, Error> {
+        for path in paths {
+            self.open_file(path)?;
+        }
+
+        Ok(self)
+    }
+
+    /// Compiles the given files in the same way as [`open_files()`][Compiler::open_files()], and returns the
+    /// resulting [`FileDescriptorSet`](prost_types::FileDescriptorSet) along with a [`CompileReport`] recording where
+    /// the time was spent.
+    ///
+    /// The report includes, for each file opened, the time taken by the [`FileResolver`] to read it, the time taken to
+    /// parse it, and the time taken to check it and add it to the descriptor pool.
+    pub fn compile_with_report(
+        &mut self,
+        paths: impl IntoIterator<Item = impl AsRef<Path>>,
+    ) -> Result<(prost_types::FileDescriptorSet, CompileReport), Error> {
+        let start = Instant::now();
+        self.report = Some(CompileReport::default());
+        let result = self.open_files(paths).map(|_| ());
+        let mut report = self.report.take().unwrap();
+        result?;
+
+        let file_descriptor_set = self.file_descriptor_set();
+        report.set_total_time(start.elapsed());
+        Ok((file_descriptor_set, report))
+    }
+
+    /// Converts all added files into an instance of [`FileDescriptorSet`](prost_types::FileDescriptorSet).
+    ///
+    /// Files are sorted topologically, with dependency files ordered before the files that import them.
+    pub fn file_descriptor_set(&self) -> prost_types::FileDescriptorSet {
+        let file = self
+            .pool
+            .files()
+            .filter(|f| self.is_output_file(f))
+            .map(|f| self.output_file_proto(&f))
+            .collect();
+
+        prost_types::FileDescriptorSet { file }
+    }
+
+    /// Converts the files declaring the packages and definitions selected by `filter`, along with the files they
+    /// depend on, into an instance of [`FileDescriptorSet`](prost_types::FileDescriptorSet).
+    ///
+    /// Unlike [`file_descriptor_set`](Compiler::file_descriptor_set), the output is always self-contained, so the
+    /// [`include_imports`](Compiler::include_imports) option is ignored. Imports which are not used by a file are
+    /// not followed, and are removed from the output, so files only needed by unrelated definitions are omitted. Use
+    /// [`DescriptorFilter::prune_types`] to also remove definitions which are not reachable from the selected names.
+    /// Files are sorted topologically, as for [`file_descriptor_set`](Compiler::file_descriptor_set).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a name selected by `filter` is not defined by any file added to this compiler.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use protox::{file::{File, FileResolver}, Compiler, DescriptorFilter};
+    /// # struct Resolver;
+    /// # impl FileResolver for Resolver {
+    /// #     fn open_file(&self, name: &str) -> Result<File, protox::Error> {
+    /// #         match name {
+    /// #             "a.proto" => File::from_source(name, "package a; message A {}"),
+    /// #             "b.proto" => File::from_source(name, "package b; import 'a.proto'; message B { optional a.A a = 1; }"),
+    /// #             _ => File::from_source(name, "package c; message C {}"),
+    /// #         }
+    /// #     }
+    /// # }
+    /// let mut compiler = Compiler::with_file_resolver(Resolver);
+    /// compiler.open_files(["b.proto", "c.proto"])?;
+    ///
+    /// let file_descriptor_set = compiler.filtered_file_descriptor_set(DescriptorFilter::new().include("b.B"))?;
+    /// let names: Vec<_> = file_descriptor_set.file.iter().map(|file| file.name()).collect();
+    /// assert_eq!(names, ["a.proto", "b.proto"]);
+    /// # Ok::<(), protox::Error>(())
+    /// ```
+    pub fn filtered_file_descriptor_set(
+        &self,
+        filter: &DescriptorFilter,
+    ) -> Result<prost_types::FileDescriptorSet, Error> {
+        let file = filter::filter_files(&self.pool, filter)?
+            .into_iter()
+            .map(|filtered| {
+                let mut file = self.output_file_proto(&filtered.file);
+                filter::remove_imports(&mut file, |name| filtered.imports.contains(name));
+                if let Some(definitions) = &filtered.definitions {
+                    filter::remove_definitions(&mut file, |name| definitions.contains(name));
+                }
+                file
             })
             .collect();
 
-        let mut file_descriptor_set = FileDescriptorSet::default().transcode_to_dynamic();
-        file_descriptor_set.set_field_by_name("file", Value::List(files));
-        file_descriptor_set.encode_to_vec()
+        Ok(prost_types::FileDescriptorSet { file })
+    }
+
+    /// Converts all added files into an instance of [`FileDescriptorSet`](prost_types::FileDescriptorSet) and encodes it.
+    ///
+    /// This is equivalent to `file_descriptor_set()?.encode_to_vec()`, with the exception that extension
+    /// options are included.
+    pub fn encode_file_descriptor_set(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        self.encode_file_descriptor_set_to(&mut buf)
+            .expect("writing to a Vec cannot fail");
+        buf
+    }
+
+    /// Encodes all added files into a [`FileDescriptorSet`](prost_types::FileDescriptorSet), writing it to `writer`.
+    ///
+    /// The output is identical to [`encode_file_descriptor_set`](Compiler::encode_file_descriptor_set), but files are
+    /// encoded and written one at a time, so the whole set is never held in memory at once. This reduces peak memory
+    /// usage for very large compilations. The writer is not buffered, so writing directly to a file or socket should
+    /// use a [`BufWriter`](io::BufWriter).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use protox::{file::{File, FileResolver}, Compiler};
+    /// # struct Resolver;
+    /// # impl FileResolver for Resolver {
+    /// #     fn open_file(&self, name: &str) -> Result<File, protox::Error> {
+    /// #         File::from_source(name, "message Foo {}")
+    /// #     }
+    /// # }
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let mut compiler = Compiler::with_file_resolver(Resolver);
+    /// compiler.open_file("root.proto")?;
+    ///
+    /// let mut output = Vec::new();
+    /// compiler.encode_file_descriptor_set_to(&mut output)?;
+    /// assert_eq!(output, compiler.encode_file_descriptor_set());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn encode_file_descriptor_set_to(&self, mut writer: impl io::Write) -> io::Result<()> {
+        let mut header = Vec::with_capacity(MAX_HEADER_LEN);
+        // Reused for each file which does not need to be re-encoded.
+        let mut buf = Vec::new();
+        for file in self.pool.files().filter(|f| self.is_output_file(f)) {
+            let output;
+            let file_buf = match self.encode_output_file(&file) {
+                Some(encoded) => {
+                    output = encoded;
+                    &output
+                }
+                None => {
+                    buf.clear();
+                    file.encode(&mut buf).expect("vec should have capacity");
+                    &buf
+                }
+            };
+
+            // Skip the source code info field without decoding the rest of the file.
+            let ranges: Vec<Range<usize>> = if self.include_source_info {
+                iter::once(0..file_buf.len()).collect()
+            } else {
+                ranges_without_field(file_buf, tag::file::SOURCE_CODE_INFO as u32)
+            };
+            let len: usize = ranges.iter().map(|range| range.len()).sum();
+
+            header.clear();
+            encoding::encode_key(
+                tag::file_descriptor_set::FILE as u32,
+                WireType::LengthDelimited,
+                &mut header,
+            );
+            encoding::encode_varint(len as u64, &mut header);
+            writer.write_all(&header)?;
+            for range in ranges {
+                writer.write_all(&file_buf[range])?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Encodes a single compiled file as a [`FileDescriptorProto`].
+    ///
+    /// The encoding is identical to that of the file within [`encode_file_descriptor_set`](Compiler::encode_file_descriptor_set),
+    /// so it includes extension options and respects the [`include_source_info`](Compiler::include_source_info) option.
+    /// Unlike the set, any compiled file may be encoded, including imports which are not part of the output. This is
+    /// useful for embedding the descriptor of each file separately, for example in generated code.
+    ///
+    /// Returns `None` if no file with the given name has been compiled.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use prost::Message;
+    /// # use protox::{file::{File, FileResolver}, Compiler};
+    /// # struct Resolver;
+    /// # impl FileResolver for Resolver {
+    /// #     fn open_file(&self, name: &str) -> Result<File, protox::Error> {
+    /// #         File::from_source(name, "message Foo {}")
+    /// #     }
+    /// # }
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let mut compiler = Compiler::with_file_resolver(Resolver);
+    /// compiler.open_file("root.proto")?;
+    ///
+    /// let encoded = compiler.encode_file("root.proto").unwrap();
+    /// assert_eq!(encoded, compiler.file_descriptor_set().file[0].encode_to_vec());
+    /// assert_eq!(compiler.encode_file("missing.proto"), None);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn encode_file(&self, name: &str) -> Option<Vec<u8>> {
+        let file = self.pool.get_file_by_name(name)?;
+        let buf = self
+            .encode_output_file(&file)
+            .unwrap_or_else(|| file.encode_to_vec());
+
+        if self.include_source_info {
+            Some(buf)
+        } else {
+            Some(
+                ranges_without_field(&buf, tag::file::SOURCE_CODE_INFO as u32)
+                    .into_iter()
+                    .flat_map(|range| &buf[range])
+                    .copied()
+                    .collect(),
+            )
+        }
+    }
+
+    /// Encodes all added files into a [`FileDescriptorSet`](prost_types::FileDescriptorSet) and writes it to the given path.
+    ///
+    /// The output is identical to [`encode_file_descriptor_set`](Compiler::encode_file_descriptor_set), so it includes
+    /// extension options and respects the [`include_imports`](Compiler::include_imports) and
+    /// [`include_source_info`](Compiler::include_source_info) options, in the same way as `protoc --descriptor_set_out`.
+    ///
+    /// The file is written atomically: the data is written to a temporary file in the same directory, which is then
+    /// renamed over the destination. Readers will therefore never observe a partially written file.
+    pub fn write_file_descriptor_set(&self, path: impl AsRef<Path>) -> Result<(), Error> {
+        write_atomic(path.as_ref(), |writer| {
+            self.encode_file_descriptor_set_to(writer)
+        })
+    }
+
+    /// Writes all added files to the given path in the same way as
+    /// [`write_file_descriptor_set`](Compiler::write_file_descriptor_set), using the given format.
+    ///
+    /// See [`descriptor_set`](Compiler::descriptor_set) for how custom options are written in the text format and JSON.
+    pub fn write_file_descriptor_set_as(
+        &self,
+        path: impl AsRef<Path>,
+        format: OutputFormat,
+    ) -> Result<(), Error> {
+        let output = match format {
+            OutputFormat::Binary => return self.write_file_descriptor_set(path),
+            OutputFormat::Text => self.descriptor_set().to_text_format(),
+            #[cfg(feature = "serde")]
+            OutputFormat::Json => self.descriptor_set().to_json(),
+        };
+        write_atomic(path.as_ref(), |writer| writer.write_all(output.as_bytes()))
+    }
+
+    /// Converts all added files into a [`DescriptorSet`], which can be formatted in the protobuf text format or as
+    /// JSON.
+    ///
+    /// The descriptors are the same as those encoded by
+    /// [`encode_file_descriptor_set`](Compiler::encode_file_descriptor_set). Custom options are written using the
+    /// names of their extensions, such as `[my.option]`.
+    pub fn descriptor_set(&self) -> DescriptorSet {
+        DescriptorSet::decode(&self.pool, &self.encode_file_descriptor_set())
+            .expect("encoded file descriptor set should be valid")
+    }
+
+    /// Formats a Makefile-style depfile, with a rule making each of the given output paths depend on every file read
+    /// during compilation.
+    ///
+    /// This is the same format as `protoc --dependency_out`, and is understood by `make`, `ninja` and other build tools.
+    /// Imported files are always listed, regardless of the [`include_imports`](Compiler::include_imports) option,
+    /// including those opened by a custom [`FileResolver`] which reports a [`path`](File::path). Files without a path,
+    /// such as the built-in copies of the well-known types, are omitted.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use std::fs;
+    /// # use protox::Compiler;
+    /// # fn main() -> Result<(), protox::Error> {
+    /// # let tempdir = tempfile::TempDir::new().unwrap();
+    /// # std::env::set_current_dir(&tempdir).unwrap();
+    /// fs::write("dep.proto", "message Dep {}").unwrap();
+    /// fs::write("root.proto", "import 'dep.proto'; message Root { optional Dep dep = 1; }").unwrap();
+    ///
+    /// let depfile = Compiler::new(["."])?
+    ///     .open_file("root.proto")?
+    ///     .depfile(["out dir/root.bin"]);
+    ///
+    /// assert_eq!(depfile, "out\\ dir/root.bin: \\\n  ./dep.proto \\\n  ./root.proto\n");
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn depfile(&self, outputs: impl IntoIterator<Item = impl AsRef<Path>>) -> String {
+        let outputs: Vec<_> = outputs.into_iter().collect();
+        let outputs: Vec<&Path> = outputs.iter().map(|output| output.as_ref()).collect();
+        depfile::format_depfile(&outputs, self.dependency_paths())
+    }
+
+    /// Writes a depfile, as returned by [`depfile`](Compiler::depfile), to the given path.
+    ///
+    /// Like [`write_file_descriptor_set`](Compiler::write_file_descriptor_set), the file is written atomically.
+    pub fn write_depfile(
+        &self,
+        path: impl AsRef<Path>,
+        outputs: impl IntoIterator<Item = impl AsRef<Path>>,
+    ) -> Result<(), Error> {
+        let depfile = self.depfile(outputs);
+        write_atomic(path.as_ref(), |writer| writer.write_all(depfile.as_bytes()))
     }
 
     /// Gets a copy of the [`DescriptorPool`] containing all referenced files.
@@ -249,6 +1656,556 @@ impl Compiler {
         self.pool.files().map(|f| &self.files[f.name()])
     }
 
+    /// Gets the names of all files which import the given file, either directly or transitively.
+    ///
+    /// The files will appear in topological order. The given file itself is not included, and if it has not been
+    /// compiled, the result is empty.
+    ///
+    /// This can be used to determine which files need to be compiled again when a file changes.
+    pub fn dependents(&self, file_name: &str) -> Vec<&str> {
+        let mut affected = HashSet::from([file_name]);
+        let mut dependents = Vec::new();
+        for file in self.pool.files() {
+            if file
+                .dependencies()
+                .any(|dependency| affected.contains(dependency.name()))
+            {
+                let name = self.files[file.name()].name();
+                affected.insert(name);
+                dependents.push(name);
+            }
+        }
+        dependents
+    }
+
+    /// Gets the unused imports found in files added with [`open_file`](Compiler::open_file).
+    ///
+    /// These mirror the warnings emitted by `protoc`, and each one provides the span of source code to delete in order
+    /// to remove the import.
+    pub fn unused_imports(&self) -> &[UnusedImport] {
+        &self.unused_imports
+    }
+
+    /// Gets the warnings reported by [`CustomCheck`]s registered with [`add_check`](Compiler::add_check).
+    ///
+    /// This also includes enum value name conflicts, if they are allowed by
+    /// [`allow_enum_value_name_conflicts`](Compiler::allow_enum_value_name_conflicts), and shadowed files, if they are
+    /// allowed by [`shadow_policy`](Compiler::shadow_policy).
+    pub fn check_warnings(&self) -> &[CheckDiagnostic] {
+        &self.check_warnings
+    }
+
+    /// Gets the errors recorded while checking files in [`CheckMode::Lenient`] mode, in the order they were found.
+    ///
+    /// See [`check_mode`](Compiler::check_mode).
+    pub fn check_errors(&self) -> &[Error] {
+        &self.check_errors
+    }
+
+    /// Computes size and complexity metrics for the files which would be included in the output of
+    /// [`file_descriptor_set`](Compiler::file_descriptor_set).
+    ///
+    /// Sizes are measured before source code info and source-retention options are removed.
+    pub fn stats(&self) -> Stats {
+        Stats::new(self.pool.files().filter(|f| self.is_output_file(f)))
+    }
+
+    /// Estimates the memory used by the files added to this [`Compiler`].
+    ///
+    /// Unlike [`stats`](Compiler::stats), this includes every file, whether or not it would be included in the
+    /// output, along with the source text kept for error reporting. See also
+    /// [`retain_sources`](Compiler::retain_sources).
+    pub fn memory_usage(&self) -> MemoryUsage {
+        MemoryUsage::new(
+            self.pool.files(),
+            self.sources
+                .iter()
+                .map(|(name, source)| (name.as_str(), source.as_str())),
+        )
+    }
+
+    /// Gets a record of how each file name was resolved, in the order the files were opened.
+    ///
+    /// This is only populated if the [`trace_imports`](Compiler::trace_imports) option is set. Files which were already
+    /// added to this `Compiler` are not resolved again, and so only appear once.
+    pub fn import_trace(&self) -> &[ImportResolution] {
+        &self.import_trace
+    }
+
+    /// Gets the SHA-256 hashes of the contents of each file read, in the order the files were read.
+    ///
+    /// This includes each file read by the [`FileResolver`], including imports, and each file added with
+    /// [`add_reader`](Compiler::add_reader) or [`add_file_with_name`](Compiler::add_file_with_name), along with the
+    /// path it was read from. This can be used to attest exactly which inputs a build used, or as the key of an
+    /// external cache.
+    ///
+    /// Source files are hashed as UTF-8 text, and pre-compiled files by their encoded
+    /// [`FileDescriptorProto`](prost_types::FileDescriptorProto). Files which were already added to this `Compiler`
+    /// are not read again, and so only appear once.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use protox::{file::{File, FileResolver}, Compiler};
+    /// # struct Resolver;
+    /// # impl FileResolver for Resolver {
+    /// #     fn open_file(&self, name: &str) -> Result<File, protox::Error> {
+    /// #         File::from_source(name, "")
+    /// #     }
+    /// # }
+    /// let mut compiler = Compiler::with_file_resolver(Resolver);
+    /// compiler.open_file("empty.proto")?;
+    ///
+    /// let digests = compiler.input_digests();
+    /// assert_eq!(digests[0].name(), "empty.proto");
+    /// assert_eq!(
+    ///     digests[0].digest(),
+    ///     "sha256:e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+    /// );
+    /// # Ok::<(), protox::Error>(())
+    /// ```
+    pub fn input_digests(&self) -> &[InputDigest] {
+        &self.input_digests
+    }
+
+    /// Gets the options of a message, field, oneof, enum, enum value, extension, service or method, identified by its
+    /// fully-qualified name with or without a leading period.
+    ///
+    /// The options are decoded using the compiled files, so any custom options defined by extensions in them are
+    /// available through [`DynamicMessage::get_extension`]. Imported files are included regardless of the
+    /// [`include_imports`](Compiler::include_imports) option. If [`interpret_options`](Compiler::interpret_options) is
+    /// disabled, options are left in the `uninterpreted_option` field instead.
+    ///
+    /// Returns `None` if no element with the given name exists. Note that, like in `protoc`, enum values are siblings
+    /// of their enum type, so the value `FOO` of `pkg.Enum` has the name `pkg.FOO`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use protox::{file::{ChainFileResolver, File, FileResolver, GoogleFileResolver}, Compiler};
+    /// # use prost_reflect::Value;
+    /// # struct Resolver;
+    /// # impl FileResolver for Resolver {
+    /// #     fn open_file(&self, name: &str) -> Result<File, protox::Error> {
+    /// #         match name {
+    /// #             "root.proto" => File::from_source(name, "
+    /// package pkg;
+    /// import 'google/protobuf/descriptor.proto';
+    ///
+    /// extend google.protobuf.FieldOptions {
+    ///     optional string routing = 50000;
+    /// }
+    ///
+    /// message Msg {
+    ///     optional int32 field = 1 [deprecated = true, (routing) = 'shard'];
+    /// }
+    /// #             "),
+    /// #             _ => Err(protox::Error::file_not_found(name)),
+    /// #         }
+    /// #     }
+    /// # }
+    /// # fn main() -> Result<(), protox::Error> {
+    /// # let mut resolver = ChainFileResolver::new();
+    /// # resolver.add(Resolver);
+    /// # resolver.add(GoogleFileResolver::new());
+    /// let mut compiler = Compiler::with_file_resolver(resolver);
+    /// compiler.open_file("root.proto")?;
+    ///
+    /// let options = compiler.options_of(".pkg.Msg.field").unwrap();
+    /// let routing = compiler.descriptor_pool().get_extension_by_name("pkg.routing").unwrap();
+    /// assert_eq!(*options.get_extension(&routing), Value::String("shard".to_owned()));
+    ///
+    /// assert_eq!(compiler.option_value("pkg.Msg.field", "(pkg.routing)"), Some(Value::String("shard".to_owned())));
+    /// assert_eq!(compiler.option_value("pkg.Msg.field", "deprecated"), Some(Value::Bool(true)));
+    /// assert_eq!(compiler.option_value("pkg.Msg", "deprecated"), None);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn options_of(&self, full_name: &str) -> Option<DynamicMessage> {
+        symbol::find_options(&self.pool, full_name)
+    }
+
+    /// Gets the value of an option set on the element with the given fully-qualified name.
+    ///
+    /// The option may be the name of a field of the options message, such as `deprecated`, or the fully-qualified
+    /// name of an extension, optionally surrounded by parentheses as in an option statement. Returns `None` if the
+    /// element does not exist, or the option is unknown or not set on it. See [`options_of`](Compiler::options_of).
+    pub fn option_value(&self, full_name: &str, option: &str) -> Option<Value> {
+        symbol::find_option_value(&self.options_of(full_name)?, option)
+    }
+
+    /// Builds a [`SymbolTable`] of every name defined in all imported files.
+    ///
+    /// As with [`type_references`](Compiler::type_references), imported files are included regardless of the
+    /// [`include_imports`](Compiler::include_imports) option.
+    pub fn symbol_table(&self) -> SymbolTable {
+        SymbolTable::new(&self.pool)
+    }
+
+    /// Gets the resolved type references made by fields, extensions and methods in all imported files.
+    ///
+    /// Each reference identifies the message or enum that the element's type name resolved to, and the file
+    /// declaring it. References are grouped by file in topological order, and include imported files regardless
+    /// of the [`include_imports`](Compiler::include_imports) option.
+    pub fn type_references(&self) -> Vec<TypeReference> {
+        symbol::type_references(&self.pool)
+    }
+
+    /// Gets every name in the source of the compiled files which resolved to the definition with the given
+    /// fully-qualified name.
+    ///
+    /// This includes the types of fields and extensions, the extendees of extensions, the input and output types of
+    /// methods, and the extension and field names used in options. The locations are recorded while each file is
+    /// checked, so they are available even if sources are not [retained](Compiler::retain_sources), but files added
+    /// without source, such as by [`add_file_descriptor_set`](Compiler::add_file_descriptor_set), have no
+    /// locations. Locations are grouped by file in the order the files were added, and ordered by position within
+    /// each file.
+    pub fn references(&self, full_name: &str) -> Vec<Location> {
+        let full_name = full_name.strip_prefix('.').unwrap_or(full_name);
+        self.references.get(full_name).cloned().unwrap_or_default()
+    }
+
+    /// Gets the map entry messages and synthetic oneofs generated for fields in all imported files, each linked to
+    /// the field it was generated for.
+    ///
+    /// As in `protoc`, these definitions have no locations in the source code info, so editors can use this to map
+    /// them back to the originating field. Imported files are included regardless of the
+    /// [`include_imports`](Compiler::include_imports) option.
+    pub fn synthetic_origins(&self) -> Vec<SyntheticOrigin> {
+        symbol::synthetic_origins(&self.pool)
+    }
+
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "debug", name = "resolve", skip(self))
+    )]
+    fn resolve_file(&mut self, name: &str, importer: Option<&str>) -> Result<File, Error> {
+        self.check_file_name(name)?;
+
+        let resolved_name = self.rewrite_import(name);
+        let start = self.start_timer();
+        let result = if !self.trace_imports && !cfg!(feature = "tracing") {
+            self.resolver.open_file(&resolved_name)
+        } else {
+            let mut trace = ImportResolution::new(&resolved_name, importer);
+            let result = self.resolver.open_file_traced(&resolved_name, &mut trace);
+            #[cfg(feature = "tracing")]
+            tracing::debug!(path = ?trace.path(), "resolved {}", trace);
+            if self.trace_imports {
+                self.import_trace.push(trace);
+            }
+            result
+        };
+        let mut file = self.recover_utf8(name, result)?;
+        if let (Some(report), Some(start)) = (&mut self.report, start) {
+            // The file is parsed by the resolver, so the parse time is subtracted to get the resolver's latency.
+            let timings = report.file_mut(name);
+            timings.add_resolve_time(start.elapsed().saturating_sub(file.parse_time));
+            timings.add_parse_time(file.parse_time);
+        }
+        if resolved_name != name {
+            file.set_name(name);
+        }
+        self.on_file_read(&file);
+
+        if self.hermetic {
+            if let Some(path) = file.path() {
+                self.check_hermetic_path(name, path)?;
+            }
+        }
+        Ok(file)
+    }
+
+    /// In hermetic mode, checks that a file name is in canonical form.
+    fn check_file_name(&self, name: &str) -> Result<(), Error> {
+        if self.hermetic
+            && (name.contains('\\') || path_to_file_name(Path::new(name)).as_deref() != Some(name))
+        {
+            return Err(Error::from_kind(ErrorKind::InvalidFileName {
+                name: name.to_owned(),
+            }));
+        }
+        Ok(())
+    }
+
+    /// If lossy UTF-8 decoding is enabled and `result` failed because the file is not valid UTF-8, parses the file
+    /// with invalid sequences replaced and records a warning.
+    fn recover_utf8(&mut self, name: &str, result: Result<File, Error>) -> Result<File, Error> {
+        match result {
+            Err(err) if self.lossy_utf8 && err.is_invalid_utf8() => {
+                self.check_warnings.push(CheckDiagnostic::file_warning(
+                    name,
+                    format!("{}; invalid sequences were replaced with U+FFFD", err),
+                ));
+                err.into_lossy_file()
+            }
+            result => result,
+        }
+    }
+
+    /// Applies the longest matching rule added by [`add_import_rewrite`](Compiler::add_import_rewrite) to a file name.
+    fn rewrite_import(&self, name: &str) -> String {
+        let mut rewritten = None;
+        let mut longest = 0;
+        for (from, to) in &self.import_rewrites {
+            let rest = if from.is_empty() {
+                Some(name)
+            } else {
+                name.strip_prefix(from.as_str())
+                    .filter(|rest| rest.is_empty() || rest.starts_with('/'))
+                    .map(|rest| rest.trim_start_matches('/'))
+            };
+            if let Some(rest) = rest {
+                if rewritten.is_none() || from.len() > longest {
+                    rewritten = Some(match (to.is_empty(), rest.is_empty()) {
+                        (true, _) => rest.to_owned(),
+                        (false, true) => to.clone(),
+                        (false, false) => format!("{}/{}", to, rest),
+                    });
+                    longest = from.len();
+                }
+            }
+        }
+        rewritten.unwrap_or_else(|| name.to_owned())
+    }
+
+    /// Checks that a file read from the file system is inside one of the include paths, after resolving symbolic links.
+    fn check_hermetic_path(&self, name: &str, path: &Path) -> Result<(), Error> {
+        if self.includes.is_empty() {
+            return Ok(());
+        }
+
+        let outside_includes = || {
+            Error::from_kind(ErrorKind::FileOutsideIncludes {
+                name: name.to_owned(),
+                path: path.to_owned(),
+            })
+        };
+        let real_path = fs::canonicalize(path).map_err(|_| outside_includes())?;
+        if self
+            .includes
+            .iter()
+            .filter_map(|include| fs::canonicalize(include).ok())
+            .any(|include| real_path.starts_with(include))
+        {
+            Ok(())
+        } else {
+            Err(outside_includes())
+        }
+    }
+
+    fn add_predeclared_files(&mut self, files: DescriptorSetFileResolver) {
+        let mut resolver = ChainFileResolver::new();
+        resolver.add(files);
+        resolver.add(mem::replace(
+            &mut self.resolver,
+            Box::new(ChainFileResolver::new()),
+        ));
+        self.resolver = Box::new(resolver);
+    }
+
+    /// Checks whether the file at `path` is shadowed by `actual_path`, handling it according to the shadow policy.
+    fn check_shadow(
+        &mut self,
+        name: &str,
+        actual_path: Option<&Path>,
+        path: &Path,
+    ) -> Result<(), Error> {
+        let err = match check_shadow(name, actual_path, path) {
+            Ok(()) => return Ok(()),
+            Err(err) => err,
+        };
+
+        match self.shadow_policy {
+            ShadowPolicy::Error => Err(err),
+            ShadowPolicy::WarnAndUseFirst => {
+                let mut warning = CheckDiagnostic::file_warning(name, err.to_string());
+                if let Some(actual_path) = actual_path {
+                    warning.set_help(format!("using '{}' instead", actual_path.display()));
+                }
+                self.check_warnings.push(warning);
+                Ok(())
+            }
+            ShadowPolicy::AllowIfIdenticalContent => {
+                match (actual_path.map(fs::read), fs::read(path)) {
+                    (Some(Ok(actual)), Ok(expected)) if actual == expected => Ok(()),
+                    _ => Err(err),
+                }
+            }
+        }
+    }
+
+    /// Returns `true` if the file should be included in the output.
+    fn is_output_file(&self, file: &FileDescriptor) -> bool {
+        if !self.files[file.name()].is_import {
+            return true;
+        }
+        self.include_imports
+            && !self.missing_imports.contains(file.name())
+            && self
+                .import_filter
+                .as_ref()
+                .map_or(true, |filter| filter(file.name()))
+    }
+
+    /// Encodes a file for output, if it differs from the file in the pool.
+    fn encode_output_file(&self, file: &FileDescriptor) -> Option<Vec<u8>> {
+        let encoded = if let Some(uninterpreted) = self.uninterpreted_options.get(file.name()) {
+            Some(options::restore_uninterpreted_options(file, uninterpreted))
+        } else if self.retain_options {
+            None
+        } else {
+            options::strip_source_retention_options(file)
+        };
+        if self.byte_compatible {
+            Some(byte_compat::encode(file, encoded))
+        } else {
+            encoded
+        }
+    }
+
+    /// Converts a file into the [`FileDescriptorProto`] written to the output, respecting the options which control
+    /// source info and option retention.
+    fn output_file_proto(&self, file: &FileDescriptor) -> FileDescriptorProto {
+        let file = match self.encode_output_file(file) {
+            Some(stripped) => FileDescriptorProto::decode(stripped.as_slice()).unwrap(),
+            None => file.file_descriptor_proto().clone(),
+        };
+        if self.include_source_info {
+            file
+        } else {
+            FileDescriptorProto {
+                source_code_info: None,
+                ..file
+            }
+        }
+    }
+
+    /// Gets the source of a file added to this compiler, if it was retained.
+    pub(crate) fn source(&self, name: &str) -> Option<&str> {
+        self.sources.get(name).map(String::as_str)
+    }
+
+    /// Gets the paths of all files read from the file system, in topological order.
+    pub(crate) fn dependency_paths(&self) -> impl Iterator<Item = &'_ Path> {
+        self.files().filter_map(|file| file.path())
+    }
+
+    /// Starts timing a compilation step, if called from [`compile_with_report`](Compiler::compile_with_report).
+    fn start_timer(&self) -> Option<Instant> {
+        self.report.as_ref().map(|_| Instant::now())
+    }
+
+    fn record_check_time(&mut self, name: &str, start: Option<Instant>) {
+        if let (Some(report), Some(start)) = (&mut self.report, start) {
+            report.file_mut(name).add_check_time(start.elapsed());
+        }
+    }
+
+    /// Adds all imports of `file`, whose name is at the top of `import_stack`.
+    fn add_imports(&mut self, file: &File, import_stack: &mut Vec<String>) -> Result<(), Error> {
+        for (i, import) in file.descriptor.dependency.iter().enumerate() {
+            let is_weak = file.descriptor.weak_dependency.contains(&(i as i32));
+            let allow_missing =
+                self.allow_missing_imports || (self.allow_missing_weak_imports && is_weak);
+            if !allow_missing && self.missing_imports.contains(import) {
+                return Err(Error::file_not_found(import).into_import_error(file, i));
+            }
+
+            match self.add_import(import, import_stack) {
+                Err(err)
+                    if allow_missing && err.is_file_not_found() && err.file() == Some(import) =>
+                {
+                    self.add_missing_import(file.descriptor.name(), import, is_weak);
+                }
+                result => result.map_err(|e| e.into_import_error(file, i))?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Records an import which could not be found. The placeholder file is added to the pool when the importing file is
+    /// checked.
+    fn add_missing_import(&mut self, importer: &str, name: &str, is_weak: bool) {
+        self.files.insert(
+            name.to_owned(),
+            FileMetadata {
+                name: name.to_owned(),
+                path: None,
+                is_import: true,
+            },
+        );
+        self.missing_imports.insert(name.to_owned());
+        self.check_warnings.push(CheckDiagnostic::file_warning(
+            importer,
+            if is_weak {
+                format!("weak import '{}' not found", name)
+            } else {
+                format!("import '{}' not found", name)
+            },
+        ));
+    }
+
+    /// Adds placeholder files for the imports of a file which could not be found, and reports a warning for each
+    /// reference to a placeholder type.
+    fn add_placeholder_files(
+        &mut self,
+        descriptor: &FileDescriptorProto,
+        source: Option<&str>,
+    ) -> Result<(), Error> {
+        let missing: Vec<&str> = descriptor
+            .dependency
+            .iter()
+            .map(String::as_str)
+            .filter(|name| {
+                self.missing_imports.contains(*name) && self.pool.get_file_by_name(name).is_none()
+            })
+            .collect();
+        if missing.is_empty() {
+            return Ok(());
+        }
+
+        let references = if self.allow_missing_imports {
+            let (files, references) =
+                placeholder::placeholder_files(&self.pool, descriptor, &missing);
+            match self.pool.add_file_descriptor_protos(files) {
+                Ok(()) => references,
+                // The placeholder types conflict with other definitions, so leave the references unresolved.
+                Err(_) => Vec::new(),
+            }
+        } else {
+            Vec::new()
+        };
+
+        for name in missing {
+            if self.pool.get_file_by_name(name).is_none() {
+                self.pool.add_file_descriptor_proto(FileDescriptorProto {
+                    name: Some(name.to_owned()),
+                    ..Default::default()
+                })?;
+            }
+        }
+
+        for reference in references {
+            let mut warning = CheckDiagnostic::warning_at(
+                descriptor,
+                source,
+                &reference.path,
+                format!("name '{}' is not defined", reference.name),
+            );
+            warning.set_help(format!(
+                "using a placeholder for '{}' in missing import '{}'",
+                reference.full_name, reference.file
+            ));
+            self.check_warnings.push(warning);
+        }
+        Ok(())
+    }
+
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "debug", name = "import", skip(self, import_stack))
+    )]
     fn add_import(&mut self, file_name: &str, import_stack: &mut Vec<String>) -> Result<(), Error> {
         if import_stack.iter().any(|name| name == file_name) {
             let mut cycle = String::new();
@@ -260,6 +2217,7 @@ impl Compiler {
             return Err(Error::from_kind(ErrorKind::CircularImport {
                 name: file_name.to_owned(),
                 cycle,
+                imports: Vec::new(),
             }));
         }
 
@@ -267,16 +2225,20 @@ impl Compiler {
             return Ok(());
         }
 
-        let file = self.resolver.open_file(file_name)?;
+        let file = self.resolve_file(file_name, import_stack.last().map(String::as_str))?;
 
         import_stack.push(file_name.to_owned());
-        for (i, import) in file.descriptor.dependency.iter().enumerate() {
-            self.add_import(import, import_stack)
-                .map_err(|e| e.into_import_error(&file, i))?;
-        }
+        self.add_imports(&file, import_stack)?;
         import_stack.pop();
 
-        let path = self.check_file(file)?;
+        let start = self.start_timer();
+        let (path, _) = self.check_file(file)?;
+        self.record_check_time(file_name, start);
+        if let Some(file_descriptor) = self.pool.get_file_by_name(file_name) {
+            for observer in &self.observers {
+                observer.on_file_checked(&file_descriptor);
+            }
+        }
         self.files.insert(
             file_name.to_owned(),
             FileMetadata {
@@ -288,36 +2250,366 @@ impl Compiler {
         Ok(())
     }
 
-    fn check_file(
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "debug",
+            name = "check",
+            skip_all,
+            fields(name = file.descriptor.name())
+        )
+    )]
+    fn check_file(&mut self, file: File) -> Result<(Option<PathBuf>, Option<String>), Error> {
+        // Some checks run after the file has been added to the pool, so the pool is rebuilt without it if they fail.
+        // Otherwise the file would remain in the pool without being recorded in `files`.
+        let file_count = self.pool.files().len();
+        let result = self.add_checked_file(file);
+        if result.is_err() && self.pool.files().len() != file_count {
+            self.truncate_pool(file_count);
+        }
+        result
+    }
+
+    /// Rebuilds the pool with only the first `len` files.
+    ///
+    /// Cloning the pool before each file is added would copy it again when the file is added, so it is only rebuilt
+    /// on this error path.
+    fn truncate_pool(&mut self, len: usize) {
+        let mut pool = DescriptorPool::new();
+        for file in self.pool.files().take(len) {
+            pool.decode_file_descriptor_proto(file.encode_to_vec().as_slice())
+                .expect("file was previously added to the pool");
+        }
+        self.pool = pool;
+    }
+
+    fn add_checked_file(
         &mut self,
-        File {
+        mut file: File,
+    ) -> Result<(Option<PathBuf>, Option<String>), Error> {
+        if !self.aliases.is_empty() {
+            file.rename_dependencies(&self.aliases);
+        }
+        let File {
             path,
             source,
             descriptor,
             encoded,
-        }: File,
-    ) -> Result<Option<PathBuf>, Error> {
-        if let Some(encoded) = &encoded {
-            self.pool.decode_file_descriptor_proto(encoded.clone())
+            ..
+        } = file;
+
+        let name = descriptor.name().to_owned();
+        let verify =
+            source.is_some() || self.descriptor_verification == DescriptorVerification::Strict;
+        let mut declared_types = Vec::new();
+        if verify {
+            if source.is_none() {
+                descriptor::check_syntax(&descriptor)?;
+                self.recover(descriptor::check_descriptor(&descriptor))?;
+                declared_types = descriptor::declared_types(&descriptor);
+            }
+            self.recover(json_name::check_json_names(&descriptor, source.as_deref()))?;
+            self.recover(enum_value::check_enum_value_scopes(
+                &descriptor,
+                source.as_deref(),
+            ))?;
+        }
+
+        if let Some(duplicate) = duplicate::find_duplicate_name(&self.pool, &descriptor) {
+            if self.allow_identical_redefinitions
+                && duplicate.is_identical(&descriptor, source.as_deref(), &self.sources)
+            {
+                self.aliases
+                    .insert(name, duplicate.existing_file().to_owned());
+                return Ok((path, source));
+            }
+            return Err(duplicate.into_error(&descriptor, source.as_deref(), &self.sources));
+        }
+
+        let mut encoded = encoded;
+        let mut uninterpreted = Vec::new();
+        if !self.interpret_options {
+            let mut file_msg = match &encoded {
+                Some(encoded) => DynamicMessage::decode(
+                    FileDescriptorProto::default().descriptor(),
+                    encoded.clone(),
+                )
+                .map_err(Error::new)?,
+                None => descriptor.transcode_to_dynamic(),
+            };
+            uninterpreted = options::take_uninterpreted_options(&mut file_msg);
+            if !uninterpreted.is_empty() {
+                encoded = Some(file_msg.encode_to_vec().into());
+            }
+        }
+
+        self.add_placeholder_files(&descriptor, source.as_deref())?;
+        let tolerate_unresolved = self.allow_missing_imports
+            && descriptor
+                .dependency
+                .iter()
+                .any(|dependency| self.missing_imports.contains(dependency));
+        if self.check_mode == CheckMode::Lenient || tolerate_unresolved {
+            self.add_pruned_file(
+                &name,
+                descriptor,
+                encoded,
+                source.as_deref(),
+                tolerate_unresolved,
+            )?;
         } else {
-            self.pool.add_file_descriptor_proto(descriptor)
+            if let Some(encoded) = &encoded {
+                self.pool.decode_file_descriptor_proto(encoded.clone())
+            } else {
+                self.pool.add_file_descriptor_proto(descriptor)
+            }
+            .map_err(|err| descriptor_error(&name, source.as_deref(), err))?;
         }
-        .map_err(|mut err| {
-            if let Some(source) = source {
-                err = err.with_source_code(&source);
+
+        let file = self.pool.get_file_by_name(&name).unwrap();
+        if verify {
+            self.recover(descriptor::check_field_types(&file, &declared_types))?;
+            if self.compatibility_level.checks_option_targets() {
+                self.recover(options::check_option_targets(&file, source.as_deref()))?;
             }
-            err
-        })?;
+            self.recover(message_set::check_message_sets(&file, source.as_deref()))?;
+            self.recover(standard_options::check_standard_options(
+                &file,
+                source.as_deref(),
+            ))?;
+            self.recover(oneof::check_oneofs(&file, source.as_deref()))?;
+            self.recover(declaration::check_extension_declarations(
+                &file,
+                source.as_deref(),
+            ))?;
+            self.recover(extension_number::check_extension_numbers(
+                &file,
+                source.as_deref(),
+                &self.sources,
+            ))?;
+            self.recover(syntax::check_syntax_mixing(
+                &file,
+                source.as_deref(),
+                &self.sources,
+            ))?;
+            if !self.compatibility_level.allows_proto3_optional() {
+                self.recover(syntax::check_no_proto3_optional(&file, source.as_deref()))?;
+            }
+            match enum_value::check_enum_value_names(
+                &file,
+                source.as_deref(),
+                self.allow_enum_value_name_conflicts,
+            ) {
+                Ok(warnings) => self.check_warnings.extend(warnings),
+                Err(err) => self.recover(Err(err))?,
+            }
+        }
+
+        if !uninterpreted.is_empty() {
+            self.uninterpreted_options
+                .insert(name.clone(), uninterpreted);
+        }
+
+        if let Some(source) = &source {
+            for reference in source_references(&file, source).references {
+                self.references
+                    .entry(reference.target)
+                    .or_default()
+                    .push(Location {
+                        file: name.clone(),
+                        span: reference.span.into(),
+                        kind: reference.kind,
+                    });
+            }
+        }
+
+        if let (true, Some(source)) = (self.retain_sources, &source) {
+            self.sources.insert(name, source.clone());
+        }
+
+        Ok((path, source))
+    }
+
+    /// Adds a file to the pool in [`CheckMode::Lenient`] mode, removing the element at the location of each error
+    /// until it is valid.
+    ///
+    /// If `tolerate_unresolved` is set, elements referring to types which are not defined are also removed, and
+    /// reported as warnings instead of errors.
+    fn add_pruned_file(
+        &mut self,
+        name: &str,
+        mut descriptor: FileDescriptorProto,
+        encoded: Option<Bytes>,
+        source: Option<&str>,
+        tolerate_unresolved: bool,
+    ) -> Result<(), Error> {
+        if let Some(encoded) = &encoded {
+            // Options may have been modified in the encoded form, so start from it.
+            descriptor = FileDescriptorProto::decode(encoded.clone()).map_err(Error::new)?;
+        }
+
+        loop {
+            // If adding a file fails, its extensions are not removed from the extended messages, so each attempt is
+            // made on a copy of the pool.
+            let mut pool = self.pool.clone();
+            let err = match pool.add_file_descriptor_proto(descriptor.clone()) {
+                Ok(_) => {
+                    self.pool = pool;
+                    return Ok(());
+                }
+                Err(err) => err,
+            };
+            let path = err.path().unwrap_or_default().to_vec();
+            if tolerate_unresolved && placeholder::is_type_reference(&path) {
+                let mut warning =
+                    CheckDiagnostic::warning_at(&descriptor, source, &path, err.to_string());
+                if lenient::prune(&mut descriptor, &path) {
+                    warning.set_help("the definition has been removed");
+                    self.check_warnings.push(warning);
+                    continue;
+                }
+            }
+
+            let err = descriptor_error(name, source, err);
+            if self.check_mode != CheckMode::Lenient || !lenient::prune(&mut descriptor, &path) {
+                return Err(err);
+            }
+            self.add_check_error(err);
+        }
+    }
+
+    /// In [`CheckMode::Lenient`] mode, records an error and continues.
+    fn recover(&mut self, result: Result<(), Error>) -> Result<(), Error> {
+        match result {
+            Err(err) if self.check_mode == CheckMode::Lenient => {
+                self.add_check_error(err);
+                Ok(())
+            }
+            result => result,
+        }
+    }
+
+    fn add_check_error(&mut self, err: Error) {
+        for observer in &self.observers {
+            observer.on_error(&err);
+        }
+        self.check_errors.push(err);
+    }
+
+    /// Records the digest of a file which has been read and parsed, and notifies observers.
+    fn on_file_read(&mut self, file: &File) {
+        self.input_digests.push(InputDigest::new(file));
+        for observer in &self.observers {
+            observer.on_file_resolved(file.name(), file.path());
+            observer.on_file_parsed(&file.descriptor);
+        }
+    }
+
+    /// Notifies observers of an error which caused compilation to fail.
+    fn observe_error<T>(&self, result: Result<T, Error>) -> Result<T, Error> {
+        if let Err(err) = &result {
+            for observer in &self.observers {
+                observer.on_error(err);
+            }
+        }
+        result
+    }
+}
+
+/// Converts an error adding a file to the pool, adding spans from the source if available.
+fn descriptor_error(name: &str, source: Option<&str>, err: DescriptorError) -> Error {
+    match source {
+        // The descriptor has been moved into the pool, so parse the source again to add spans to the error.
+        Some(source) => {
+            let err = err.with_source_code(source);
+            protox_parse::parse(name, source)
+                .ok()
+                .and_then(|file| {
+                    options::find_duplicate_option(&file, source, &err)
+                        .or_else(|| map_field::find_invalid_map_type(&file, source, &err))
+                })
+                .unwrap_or_else(|| err.into())
+        }
+        None => Error::from(err),
+    }
+}
+
+/// Gets the ranges of an encoded message which remain after removing every occurrence of the field `tag`.
+pub(crate) fn ranges_without_field(buf: &[u8], tag: u32) -> Vec<Range<usize>> {
+    let mut ranges = Vec::new();
+    let mut rest = buf;
+    let mut start = 0;
+    while !rest.is_empty() {
+        let field_start = buf.len() - rest.len();
+        let (field_tag, wire_type) = encoding::decode_key(&mut rest).unwrap();
+        encoding::skip_field(wire_type, field_tag, &mut rest, DecodeContext::default()).unwrap();
+        if field_tag == tag {
+            if start != field_start {
+                ranges.push(start..field_start);
+            }
+            start = buf.len() - rest.len();
+        }
+    }
+    if start != buf.len() {
+        ranges.push(start..buf.len());
+    }
+    ranges
+}
+
+/// Writes contents to a temporary file next to `path`, and then renames it into place.
+fn write_atomic(
+    path: &Path,
+    write: impl FnOnce(&mut io::BufWriter<fs::File>) -> io::Result<()>,
+) -> Result<(), Error> {
+    let write_error = |err: io::Error| {
+        Error::from_kind(ErrorKind::WriteFile {
+            path: path.to_owned(),
+            err,
+        })
+    };
 
-        Ok(path)
+    let (file, temp_path) = create_temp(path, |temp_path| {
+        fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(temp_path)
+    })
+    .map_err(write_error)?;
+    let result = (|| {
+        let mut writer = io::BufWriter::new(file);
+        write(&mut writer)?;
+        let file = writer
+            .into_inner()
+            .map_err(io::IntoInnerError::into_error)?;
+        file.sync_all()?;
+        drop(file);
+        fs::rename(&temp_path, path)
+    })();
+    if let Err(err) = result {
+        let _ = fs::remove_file(&temp_path);
+        return Err(write_error(err));
     }
+    Ok(())
 }
 
 impl fmt::Debug for Compiler {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("Compiler")
             .field("include_imports", &self.include_imports)
+            .field("filter_imports", &self.import_filter.is_some())
             .field("include_source_info", &self.include_source_info)
+            .field("retain_options", &self.retain_options)
+            .field("deny_unused_imports", &self.deny_unused_imports)
+            .field("trace_imports", &self.trace_imports)
+            .field("retain_sources", &self.retain_sources)
+            .field("shadow_policy", &self.shadow_policy)
+            .field("compatibility_level", &self.compatibility_level)
+            .field("lossy_utf8", &self.lossy_utf8)
+            .field(
+                "allow_missing_weak_imports",
+                &self.allow_missing_weak_imports,
+            )
+            .field("allow_missing_imports", &self.allow_missing_imports)
             .finish_non_exhaustive()
     }
 }
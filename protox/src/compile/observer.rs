@@ -0,0 +1,71 @@
+use std::path::Path;
+
+use prost_reflect::FileDescriptor;
+use prost_types::FileDescriptorProto;
+
+use crate::Error;
+
+/// Receives events as a [`Compiler`](crate::Compiler) compiles files.
+///
+/// Observers registered with [`Compiler::add_observer`](crate::Compiler::add_observer) are notified in the order they
+/// were added, for every file compiled, including imports. This can be used to report progress, log the files read,
+/// or collect the parsed descriptor of each file before it is checked. All methods have empty default
+/// implementations, so an observer only needs to implement the events it is interested in. Like
+/// [`CustomCheck`](crate::check::CustomCheck), observers must be [`Send`] and [`Sync`].
+///
+/// # Examples
+///
+/// ```
+/// # use std::sync::{Arc, Mutex};
+/// # use prost_reflect::FileDescriptor;
+/// # use protox::{file::{File, FileResolver}, Compiler, CompilerObserver};
+/// # struct Resolver;
+/// # impl FileResolver for Resolver {
+/// #     fn open_file(&self, name: &str) -> Result<File, protox::Error> {
+/// #         match name {
+/// #             "root.proto" => File::from_source(name, "import 'dep.proto'; message Root { optional Dep dep = 1; }"),
+/// #             _ => File::from_source(name, "message Dep {}"),
+/// #         }
+/// #     }
+/// # }
+/// struct Progress {
+///     checked: Arc<Mutex<Vec<String>>>,
+/// }
+///
+/// impl CompilerObserver for Progress {
+///     fn on_file_checked(&self, file: &FileDescriptor) {
+///         self.checked.lock().unwrap().push(file.name().to_owned());
+///     }
+/// }
+///
+/// let checked = Arc::new(Mutex::new(Vec::new()));
+/// let mut compiler = Compiler::with_file_resolver(Resolver);
+/// compiler.add_observer(Progress { checked: checked.clone() });
+/// compiler.open_file("root.proto")?;
+///
+/// assert_eq!(*checked.lock().unwrap(), ["dep.proto", "root.proto"]);
+/// # Ok::<(), protox::Error>(())
+/// ```
+pub trait CompilerObserver: Send + Sync {
+    /// Called when a file has been read, either by the [`FileResolver`](crate::file::FileResolver) or from a reader
+    /// passed to [`Compiler::add_reader`](crate::Compiler::add_reader). `path` is the location of the file on the
+    /// file system, if known.
+    fn on_file_resolved(&self, _name: &str, _path: Option<&Path>) {}
+
+    /// Called with the descriptor of a file as it was parsed, before it is checked. Type names have not been
+    /// resolved, and options have not been interpreted.
+    ///
+    /// For a file opened from a [`DescriptorSetFileResolver`](crate::file::DescriptorSetFileResolver), this is the
+    /// descriptor as decoded from the set.
+    fn on_file_parsed(&self, _file: &FileDescriptorProto) {}
+
+    /// Called when a file has been checked and added to the descriptor pool. For a file passed to
+    /// [`Compiler::open_file`](crate::Compiler::open_file), this is after any [`CustomCheck`](crate::check::CustomCheck)s
+    /// have run.
+    fn on_file_checked(&self, _file: &FileDescriptor) {}
+
+    /// Called with each error found while compiling files, including errors which are recorded in
+    /// [`Compiler::check_errors`](crate::Compiler::check_errors) in [`CheckMode::Lenient`](crate::CheckMode::Lenient)
+    /// mode.
+    fn on_error(&self, _error: &Error) {}
+}
@@ -0,0 +1,82 @@
+use miette::NamedSource;
+use prost_reflect::{Cardinality, FileDescriptor};
+use protox_parse::tag;
+
+use super::options::find_span;
+use crate::error::{Error, ErrorKind};
+
+/// Checks that every oneof declared in the file has at least one field, and that its fields are optional, non-map
+/// fields defined consecutively.
+///
+/// The parser already enforces these rules for source files, so this only reports errors in pre-compiled files.
+pub(crate) fn check_oneofs(file: &FileDescriptor, source: Option<&str>) -> Result<(), Error> {
+    let error = |path: &[i32], tag: i32, message: String| {
+        let mut path = path.to_vec();
+        path.push(tag);
+        Error::from_kind(ErrorKind::InvalidOneof {
+            message,
+            span: source.and_then(|source| find_span(file.file_descriptor_proto(), source, &path)),
+            source_code: NamedSource::new(file.name(), source.unwrap_or_default().to_owned()),
+        })
+    };
+
+    let mut messages: Vec<_> = file.messages().collect();
+    while let Some(message) = messages.pop() {
+        for oneof in message.oneofs() {
+            if oneof.fields().len() == 0 {
+                return Err(error(
+                    oneof.path(),
+                    tag::oneof::NAME,
+                    format!("oneof '{}' must have at least one field", oneof.name()),
+                ));
+            }
+
+            for field in oneof.fields() {
+                if field.is_map() {
+                    return Err(error(
+                        field.path(),
+                        tag::oneof::NAME,
+                        format!("map field '{}' is not allowed in a oneof", field.name()),
+                    ));
+                }
+                if field.cardinality() != Cardinality::Optional {
+                    return Err(error(
+                        field.path(),
+                        tag::field::LABEL,
+                        format!("field '{}' in a oneof must be optional", field.name()),
+                    ));
+                }
+            }
+        }
+
+        // Like protoc, no other field may be defined between the fields of a oneof. This depends on the order the
+        // fields are declared in, rather than their numbers.
+        let mut seen = Vec::new();
+        let mut previous = None;
+        for (index, field) in message.descriptor_proto().field.iter().enumerate() {
+            let oneof = field.oneof_index;
+            if let Some(oneof) = oneof {
+                if previous != Some(oneof) {
+                    if seen.contains(&oneof) {
+                        let mut path = message.path().to_vec();
+                        path.extend([tag::message::FIELD, index as i32]);
+                        return Err(error(
+                            &path,
+                            tag::oneof::NAME,
+                            format!(
+                                "fields in oneof '{}' must be defined consecutively",
+                                message.descriptor_proto().oneof_decl[oneof as usize].name()
+                            ),
+                        ));
+                    }
+                    seen.push(oneof);
+                }
+            }
+            previous = oneof;
+        }
+
+        messages.extend(message.child_messages());
+    }
+
+    Ok(())
+}
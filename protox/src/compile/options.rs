@@ -0,0 +1,406 @@
+use miette::Diagnostic;
+use miette::{NamedSource, SourceSpan};
+use prost::Message;
+use prost_reflect::{
+    DescriptorError, DescriptorPool, DynamicMessage, FileDescriptor, ReflectMessage, Value,
+};
+use prost_types::{FileDescriptorProto, UninterpretedOption};
+use protox_parse::tag;
+
+use crate::error::{location_span, Error, ErrorKind};
+
+pub(crate) const FIELD_OPTIONS: &str = "google.protobuf.FieldOptions";
+
+/// The value of `google.protobuf.FieldOptions.OptionRetention.RETENTION_SOURCE`.
+const RETENTION_SOURCE: i32 = 2;
+
+/// Gets the `google.protobuf.FieldOptions.OptionTargetType` value and description for the options of each kind of
+/// descriptor.
+fn target_type(message_name: &str) -> Option<(i32, &'static str)> {
+    match message_name {
+        "google.protobuf.FileDescriptorProto" => Some((1, "file")),
+        "google.protobuf.DescriptorProto.ExtensionRange" => Some((2, "extension range")),
+        "google.protobuf.DescriptorProto" => Some((3, "message")),
+        "google.protobuf.FieldDescriptorProto" => Some((4, "field")),
+        "google.protobuf.OneofDescriptorProto" => Some((5, "oneof")),
+        "google.protobuf.EnumDescriptorProto" => Some((6, "enum")),
+        "google.protobuf.EnumValueDescriptorProto" => Some((7, "enum entry")),
+        "google.protobuf.ServiceDescriptorProto" => Some((8, "service")),
+        "google.protobuf.MethodDescriptorProto" => Some((9, "method")),
+        _ => None,
+    }
+}
+
+/// Decodes a file using the definition of `descriptor.proto` in its pool, so that option extensions, and any options
+/// not known to `prost-types`, are preserved.
+pub(crate) fn decode_file(file: &FileDescriptor) -> Option<DynamicMessage> {
+    let file_desc = file
+        .parent_pool()
+        .get_message_by_name("google.protobuf.FileDescriptorProto")?;
+    Some(DynamicMessage::decode(file_desc, file.encode_to_vec().as_slice()).unwrap())
+}
+
+/// Returns true if the pool's version of `descriptor.proto` defines the given field of an options message.
+///
+/// If not, no option in the pool can have set it.
+pub(crate) fn has_option_field(pool: &DescriptorPool, options: &str, name: &str) -> bool {
+    pool.get_message_by_name(options)
+        .is_some_and(|options| options.get_field_by_name(name).is_some())
+}
+
+/// Checks that every option set in the file is allowed on the element it is applied to, according to the `targets`
+/// option of its field definition.
+pub(crate) fn check_option_targets(
+    file: &FileDescriptor,
+    source: Option<&str>,
+) -> Result<(), Error> {
+    if !has_option_field(file.parent_pool(), FIELD_OPTIONS, "targets") {
+        return Ok(());
+    }
+    let Some(file_msg) = decode_file(file) else {
+        return Ok(());
+    };
+
+    let mut path = Vec::new();
+    match check_descriptor(&file_msg, &mut path) {
+        Ok(()) => Ok(()),
+        Err((name, target)) => {
+            let span =
+                source.and_then(|source| find_span(file.file_descriptor_proto(), source, &path));
+            Err(Error::from_kind(ErrorKind::InvalidOptionTarget {
+                name,
+                target,
+                span,
+                source_code: NamedSource::new(file.name(), source.unwrap_or_default().to_owned()),
+            }))
+        }
+    }
+}
+
+/// Recursively checks the options of a descriptor and its children. On error, `path` is left pointing at the option.
+fn check_descriptor(
+    message: &DynamicMessage,
+    path: &mut Vec<i32>,
+) -> Result<(), (String, &'static str)> {
+    let target = target_type(message.descriptor().full_name());
+    for (field, value) in message.fields() {
+        path.push(field.number() as i32);
+        match (value, target) {
+            (Value::Message(options), Some(target)) if field.name() == "options" => {
+                check_options(options, target, path, &mut String::new())?
+            }
+            (Value::Message(child), _) => check_descriptor(child, path)?,
+            (Value::List(children), _) => {
+                for (index, child) in children.iter().enumerate() {
+                    if let Value::Message(child) = child {
+                        path.push(index as i32);
+                        check_descriptor(child, path)?;
+                        path.pop();
+                    }
+                }
+            }
+            _ => (),
+        }
+        path.pop();
+    }
+    Ok(())
+}
+
+/// Checks that each option set in `options`, including fields of message-typed options, may be applied to `target`.
+fn check_options(
+    options: &DynamicMessage,
+    target: (i32, &'static str),
+    path: &mut Vec<i32>,
+    name: &mut String,
+) -> Result<(), (String, &'static str)> {
+    let fields = options.fields().map(|(field, value)| {
+        (
+            field.number(),
+            field.name().to_owned(),
+            field.options(),
+            value,
+        )
+    });
+    let extensions = options.extensions().map(|(extension, value)| {
+        (
+            extension.number(),
+            format!("({})", extension.full_name()),
+            extension.options(),
+            value,
+        )
+    });
+
+    for (number, part, field_options, value) in fields.chain(extensions) {
+        let name_len = name.len();
+        if !name.is_empty() {
+            name.push('.');
+        }
+        name.push_str(&part);
+        path.push(number as i32);
+
+        if let Some(targets) = field_options.get_field_by_name("targets") {
+            if let Value::List(targets) = targets.as_ref() {
+                if !targets.is_empty()
+                    && !targets.iter().any(|t| t.as_enum_number() == Some(target.0))
+                {
+                    return Err((name.clone(), target.1));
+                }
+            }
+        }
+        if let Value::Message(value) = value {
+            check_options(value, target, path, name)?;
+        }
+
+        path.pop();
+        name.truncate(name_len);
+    }
+    Ok(())
+}
+
+/// Finds the span of the most specific location enclosing the given path.
+pub(crate) fn find_span(
+    file: &FileDescriptorProto,
+    source: &str,
+    path: &[i32],
+) -> Option<SourceSpan> {
+    let locations = &file.source_code_info.as_ref()?.location;
+    (0..=path.len()).rev().find_map(|len| {
+        let location = locations
+            .iter()
+            .find(|location| location.path == path[..len])?;
+        location_span(source, &location.span)
+    })
+}
+
+/// Builds an error pointing at both settings of an option, if the descriptor pool reported an option as being set more
+/// than once.
+///
+/// An option conflicts with an earlier option if they have the same name, or if it sets a message value which the
+/// earlier option already set a field of, for example `(foo).bar = 1` followed by `(foo) = { baz: 2 }`.
+pub(crate) fn find_duplicate_option(
+    file: &FileDescriptorProto,
+    source: &str,
+    err: &DescriptorError,
+) -> Option<Error> {
+    if !err.to_string().ends_with("has already been set") {
+        return None;
+    }
+    let offset = err.labels()?.next()?.offset();
+
+    let mut file_msg = file.transcode_to_dynamic();
+    for (mut path, options) in take_uninterpreted_options(&mut file_msg) {
+        let names: Vec<Vec<String>> = match &options {
+            Value::List(options) => options
+                .iter()
+                .filter_map(|option| option.as_message()?.transcode_to().ok())
+                .map(|option: UninterpretedOption| option_name_parts(&option))
+                .collect(),
+            _ => continue,
+        };
+
+        path.push(tag::UNINTERPRETED_OPTION);
+        for (second_index, second_name) in names.iter().enumerate() {
+            path.push(second_index as i32);
+            let second = find_span(file, source, &path);
+            path.pop();
+            if second.map(|span| span.offset()) != Some(offset) {
+                continue;
+            }
+
+            let Some(first_index) = names[..second_index]
+                .iter()
+                .position(|first_name| first_name.starts_with(second_name))
+            else {
+                continue;
+            };
+            path.push(first_index as i32);
+            let first = find_span(file, source, &path);
+
+            return Some(Error::from_kind(ErrorKind::DuplicateOption {
+                name: second_name.join("."),
+                first,
+                second,
+                source_code: NamedSource::new(file.name(), source.to_owned()),
+            }));
+        }
+        path.pop();
+    }
+
+    None
+}
+
+/// Formats each part of the name of an uninterpreted option, with extension names enclosed in parentheses.
+fn option_name_parts(option: &UninterpretedOption) -> Vec<String> {
+    option
+        .name
+        .iter()
+        .map(|part| {
+            if part.is_extension {
+                format!("({})", part.name_part)
+            } else {
+                part.name_part.clone()
+            }
+        })
+        .collect()
+}
+
+/// The `uninterpreted_option` entries removed from one options message of a file, along with its path.
+pub(crate) type UninterpretedOptions = (Vec<i32>, Value);
+
+/// Removes all `uninterpreted_option` entries from the file, returning each list removed along with the path of its
+/// options message.
+pub(crate) fn take_uninterpreted_options(file: &mut DynamicMessage) -> Vec<UninterpretedOptions> {
+    let mut taken = Vec::new();
+    take_uninterpreted(file, &mut Vec::new(), &mut taken);
+    taken
+}
+
+/// Removes the `uninterpreted_option` entries from a message and its children, returning true if the message is
+/// left empty and should be removed.
+fn take_uninterpreted(
+    message: &mut DynamicMessage,
+    path: &mut Vec<i32>,
+    taken: &mut Vec<UninterpretedOptions>,
+) -> bool {
+    let mut is_options = false;
+    if let Some(field) = message
+        .descriptor()
+        .get_field_by_name("uninterpreted_option")
+    {
+        if let Some(options) = message.take_field(&field) {
+            taken.push((path.clone(), options));
+            is_options = true;
+        }
+    }
+
+    let mut empty_fields = Vec::new();
+    for (field, value) in message.fields_mut() {
+        path.push(field.number() as i32);
+        match value {
+            Value::Message(child) => {
+                let is_empty = take_uninterpreted(child, path, taken);
+                if is_empty {
+                    empty_fields.push(field);
+                }
+            }
+            Value::List(children) => {
+                for (index, child) in children.iter_mut().enumerate() {
+                    if let Value::Message(child) = child {
+                        path.push(index as i32);
+                        take_uninterpreted(child, path, taken);
+                        path.pop();
+                    }
+                }
+            }
+            _ => (),
+        }
+        path.pop();
+    }
+    // Options messages which only contained uninterpreted options are removed, so that defaults are unaffected.
+    for field in empty_fields {
+        message.clear_field(&field);
+    }
+
+    is_options && message.fields().next().is_none()
+}
+
+/// Encodes the file, restoring the `uninterpreted_option` entries previously removed by
+/// [`take_uninterpreted_options`].
+pub(crate) fn restore_uninterpreted_options(
+    file: &FileDescriptor,
+    uninterpreted: &[UninterpretedOptions],
+) -> Vec<u8> {
+    let file_desc = FileDescriptorProto::default().descriptor();
+    let mut file_msg = DynamicMessage::decode(file_desc, file.encode_to_vec().as_slice()).unwrap();
+    for (path, options) in uninterpreted {
+        if let Some(message) = message_at_path(&mut file_msg, path) {
+            message.set_field_by_name("uninterpreted_option", options.clone());
+        }
+    }
+    file_msg.encode_to_vec()
+}
+
+fn message_at_path<'a>(
+    message: &'a mut DynamicMessage,
+    path: &[i32],
+) -> Option<&'a mut DynamicMessage> {
+    let Some((&number, rest)) = path.split_first() else {
+        return Some(message);
+    };
+    let field = message.descriptor().get_field(number as u32)?;
+    match message.get_field_mut(&field) {
+        Value::Message(child) => message_at_path(child, rest),
+        Value::List(children) => {
+            let (&index, rest) = rest.split_first()?;
+            match children.get_mut(index as usize)? {
+                Value::Message(child) => message_at_path(child, rest),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Encodes the file, removing any options whose definition has `retention = RETENTION_SOURCE`.
+///
+/// Returns `None` if the file has no such options.
+pub(crate) fn strip_source_retention_options(file: &FileDescriptor) -> Option<Vec<u8>> {
+    if !has_option_field(file.parent_pool(), FIELD_OPTIONS, "retention") {
+        return None;
+    }
+    let mut file_msg = decode_file(file)?;
+    if strip_message(&mut file_msg) {
+        Some(file_msg.encode_to_vec())
+    } else {
+        None
+    }
+}
+
+/// Removes all fields and extensions with source retention from the message and its children, returning true if any
+/// were removed.
+fn strip_message(message: &mut DynamicMessage) -> bool {
+    let fields: Vec<_> = message
+        .fields()
+        .filter(|(field, _)| is_source_retention(&field.options()))
+        .map(|(field, _)| field)
+        .collect();
+    let extensions: Vec<_> = message
+        .extensions()
+        .filter(|(extension, _)| is_source_retention(&extension.options()))
+        .map(|(extension, _)| extension)
+        .collect();
+    let mut stripped = !fields.is_empty() || !extensions.is_empty();
+    for field in fields {
+        message.clear_field(&field);
+    }
+    for extension in extensions {
+        message.clear_extension(&extension);
+    }
+
+    for (_, value) in message.fields_mut() {
+        stripped |= strip_value(value);
+    }
+    for (_, value) in message.extensions_mut() {
+        stripped |= strip_value(value);
+    }
+    stripped
+}
+
+fn strip_value(value: &mut Value) -> bool {
+    match value {
+        Value::Message(message) => strip_message(message),
+        Value::List(values) => values
+            .iter_mut()
+            .fold(false, |stripped, value| strip_value(value) | stripped),
+        Value::Map(values) => values
+            .values_mut()
+            .fold(false, |stripped, value| strip_value(value) | stripped),
+        _ => false,
+    }
+}
+
+fn is_source_retention(field_options: &DynamicMessage) -> bool {
+    field_options
+        .get_field_by_name("retention")
+        .is_some_and(|retention| retention.as_enum_number() == Some(RETENTION_SOURCE))
+}
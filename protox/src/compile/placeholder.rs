@@ -0,0 +1,271 @@
+use std::collections::BTreeMap;
+
+use prost_reflect::DescriptorPool;
+use prost_types::{
+    field_descriptor_proto::Type, DescriptorProto, EnumDescriptorProto, EnumValueDescriptorProto,
+    FieldDescriptorProto, FileDescriptorProto,
+};
+use protox_parse::tag;
+
+use crate::symbol::SymbolTable;
+
+/// The largest field number, plus one.
+const MAX_FIELD_NUMBER: i32 = 536_870_912;
+
+/// A reference to a type which is not defined, and the placeholder created for it.
+pub(crate) struct UnresolvedReference {
+    /// The path of the type name in the file making the reference.
+    pub path: Vec<i32>,
+    /// The type name, as written.
+    pub name: String,
+    /// The fully-qualified name of the placeholder.
+    pub full_name: String,
+    /// The name of the placeholder file defining it.
+    pub file: String,
+}
+
+/// Builds a placeholder file for each import of `file` which was not found, defining the types referenced by `file`
+/// which cannot be resolved.
+///
+/// All types in a file must have the same package, so the types are grouped by package and each group is defined in
+/// a different placeholder file. If there are more packages than missing imports, the references to the remaining
+/// types are left unresolved. Types are assumed to be messages, unless they are used as the type of a field with a
+/// default value.
+pub(crate) fn placeholder_files(
+    pool: &DescriptorPool,
+    file: &FileDescriptorProto,
+    missing: &[&str],
+) -> (Vec<FileDescriptorProto>, Vec<UnresolvedReference>) {
+    let mut symbols = SymbolTable::new(pool);
+    symbols.add_file_proto(file);
+
+    let mut collector = Collector {
+        symbols: &symbols,
+        package: file.package(),
+        types: BTreeMap::new(),
+        references: Vec::new(),
+    };
+    collector.collect_file(file);
+    let Collector {
+        types,
+        mut references,
+        ..
+    } = collector;
+
+    // Packages are assigned to missing imports in the order they are first referenced.
+    let mut packages: Vec<(&str, Vec<(&str, &Placeholder)>)> = Vec::new();
+    for reference in &references {
+        let (package, _) = split_name(&reference.full_name);
+        if !packages.iter().any(|(p, _)| *p == package) {
+            packages.push((package, Vec::new()));
+        }
+    }
+    for (full_name, placeholder) in &types {
+        let (package, name) = split_name(full_name);
+        let (_, types) = packages.iter_mut().find(|(p, _)| *p == package).unwrap();
+        types.push((name, placeholder));
+    }
+
+    let mut files = Vec::new();
+    let mut file_names = BTreeMap::new();
+    for (&file_name, (package, types)) in missing.iter().zip(packages) {
+        let mut placeholder_file = FileDescriptorProto {
+            name: Some(file_name.to_owned()),
+            package: (!package.is_empty()).then(|| package.to_owned()),
+            ..Default::default()
+        };
+        for (name, placeholder) in types {
+            placeholder.add_to(&mut placeholder_file, name);
+        }
+
+        file_names.insert(package.to_owned(), file_name);
+        files.push(placeholder_file);
+    }
+
+    references.retain_mut(|reference| {
+        let (package, _) = split_name(&reference.full_name);
+        match file_names.get(package) {
+            Some(file_name) => {
+                reference.file = (*file_name).to_owned();
+                true
+            }
+            None => false,
+        }
+    });
+    (files, references)
+}
+
+/// Splits a fully-qualified name into its package and type name.
+fn split_name(full_name: &str) -> (&str, &str) {
+    full_name.rsplit_once('.').unwrap_or(("", full_name))
+}
+
+#[derive(Default)]
+struct Placeholder {
+    enum_values: Vec<String>,
+    is_enum: bool,
+    is_extendee: bool,
+}
+
+impl Placeholder {
+    fn add_to(&self, file: &mut FileDescriptorProto, name: &str) {
+        if self.is_enum {
+            let mut values = self.enum_values.clone();
+            if values.is_empty() {
+                values.push(format!("{}_UNSPECIFIED", name.to_ascii_uppercase()));
+            }
+            file.enum_type.push(EnumDescriptorProto {
+                name: Some(name.to_owned()),
+                value: values
+                    .into_iter()
+                    .enumerate()
+                    .map(|(number, name)| EnumValueDescriptorProto {
+                        name: Some(name),
+                        number: Some(number as i32),
+                        ..Default::default()
+                    })
+                    .collect(),
+                ..Default::default()
+            });
+        } else {
+            let mut message = DescriptorProto {
+                name: Some(name.to_owned()),
+                ..Default::default()
+            };
+            if self.is_extendee {
+                message
+                    .extension_range
+                    .push(prost_types::descriptor_proto::ExtensionRange {
+                        start: Some(1),
+                        end: Some(MAX_FIELD_NUMBER),
+                        ..Default::default()
+                    });
+            }
+            file.message_type.push(message);
+        }
+    }
+}
+
+struct Collector<'a> {
+    symbols: &'a SymbolTable,
+    package: &'a str,
+    types: BTreeMap<String, Placeholder>,
+    references: Vec<UnresolvedReference>,
+}
+
+impl Collector<'_> {
+    fn collect_file(&mut self, file: &FileDescriptorProto) {
+        let package = file.package();
+        for (i, message) in file.message_type.iter().enumerate() {
+            self.collect_message(&[tag::file::MESSAGE_TYPE, i as i32], package, message);
+        }
+        for (i, extension) in file.extension.iter().enumerate() {
+            self.collect_field(&[tag::file::EXTENSION, i as i32], package, extension);
+        }
+        for (i, service) in file.service.iter().enumerate() {
+            for (j, method) in service.method.iter().enumerate() {
+                let path = [tag::file::SERVICE, i as i32, tag::service::METHOD, j as i32];
+                if let Some(name) = &method.input_type {
+                    self.collect_type(&path, tag::method::INPUT_TYPE, package, name);
+                }
+                if let Some(name) = &method.output_type {
+                    self.collect_type(&path, tag::method::OUTPUT_TYPE, package, name);
+                }
+            }
+        }
+    }
+
+    fn collect_message(&mut self, path: &[i32], scope: &str, message: &DescriptorProto) {
+        let scope = if scope.is_empty() {
+            message.name().to_owned()
+        } else {
+            format!("{}.{}", scope, message.name())
+        };
+
+        for (i, field) in message.field.iter().enumerate() {
+            self.collect_field(
+                &[path, &[tag::message::FIELD, i as i32]].concat(),
+                &scope,
+                field,
+            );
+        }
+        for (i, extension) in message.extension.iter().enumerate() {
+            self.collect_field(
+                &[path, &[tag::message::EXTENSION, i as i32]].concat(),
+                &scope,
+                extension,
+            );
+        }
+        for (i, nested) in message.nested_type.iter().enumerate() {
+            self.collect_message(
+                &[path, &[tag::message::NESTED_TYPE, i as i32]].concat(),
+                &scope,
+                nested,
+            );
+        }
+    }
+
+    fn collect_field(&mut self, path: &[i32], scope: &str, field: &FieldDescriptorProto) {
+        if let Some(name) = &field.type_name {
+            if let Some(placeholder) = self.collect_type(path, tag::field::TYPE_NAME, scope, name) {
+                if field.r#type == Some(Type::Enum as i32) || field.default_value.is_some() {
+                    placeholder.is_enum = true;
+                }
+                if let Some(default_value) = &field.default_value {
+                    if !placeholder.enum_values.contains(default_value) {
+                        placeholder.enum_values.push(default_value.clone());
+                    }
+                }
+            }
+        }
+        if let Some(name) = &field.extendee {
+            if let Some(placeholder) = self.collect_type(path, tag::field::EXTENDEE, scope, name) {
+                placeholder.is_extendee = true;
+            }
+        }
+    }
+
+    fn collect_type(
+        &mut self,
+        path: &[i32],
+        tag: i32,
+        scope: &str,
+        name: &str,
+    ) -> Option<&mut Placeholder> {
+        if self.symbols.resolve_type_name(scope, name).is_some() {
+            return None;
+        }
+
+        let full_name = self.symbols.unresolved_type_name(self.package, scope, name);
+        self.references.push(UnresolvedReference {
+            path: [path, &[tag]].concat(),
+            name: name.to_owned(),
+            full_name: full_name.clone(),
+            file: String::new(),
+        });
+        Some(self.types.entry(full_name).or_default())
+    }
+}
+
+/// Returns `true` if `path` is the location of the type name, extendee, or method input or output type of an element
+/// in a file.
+pub(crate) fn is_type_reference(path: &[i32]) -> bool {
+    fn is_in_message(path: &[i32]) -> bool {
+        match path {
+            [tag::message::FIELD | tag::message::EXTENSION, _, tag::field::TYPE_NAME | tag::field::EXTENDEE] => {
+                true
+            }
+            [tag::message::NESTED_TYPE, _, rest @ ..] => is_in_message(rest),
+            _ => false,
+        }
+    }
+
+    match path {
+        [tag::file::MESSAGE_TYPE, _, rest @ ..] => is_in_message(rest),
+        [tag::file::EXTENSION, _, tag::field::TYPE_NAME | tag::field::EXTENDEE] => true,
+        [tag::file::SERVICE, _, tag::service::METHOD, _, tag::method::INPUT_TYPE | tag::method::OUTPUT_TYPE] => {
+            true
+        }
+        _ => false,
+    }
+}
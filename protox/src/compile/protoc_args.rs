@@ -0,0 +1,93 @@
+use std::{env, path::PathBuf};
+
+use crate::error::{Error, ErrorKind};
+
+/// The subset of `protoc` command line arguments understood by
+/// [`Compiler::from_protoc_args`](crate::Compiler::from_protoc_args).
+#[derive(Debug, Default, PartialEq, Eq)]
+pub(crate) struct ProtocArgs {
+    pub includes: Vec<PathBuf>,
+    pub descriptor_sets: Vec<PathBuf>,
+    pub files: Vec<PathBuf>,
+    pub include_imports: bool,
+    pub include_source_info: bool,
+    pub retain_options: bool,
+}
+
+impl ProtocArgs {
+    /// Parses arguments in the same way as `protoc`, excluding the program name.
+    ///
+    /// Values may be given either in the same argument (`-Ipath`, `--proto_path=path`) or in the following one
+    /// (`-I path`, `--proto_path path`). Like `protoc`, the values of `--proto_path` and `--descriptor_set_in` may
+    /// contain several paths separated by the platform's path list separator (`:` on Unix and `;` on Windows).
+    pub fn parse(args: &[String]) -> Result<Self, Error> {
+        let mut result = ProtocArgs::default();
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            if !arg.starts_with('-') {
+                result.files.push(PathBuf::from(arg));
+                continue;
+            }
+
+            let (flag, value) = if let Some(value) = arg.strip_prefix("-I") {
+                ("-I", (!value.is_empty()).then_some(value))
+            } else if arg.starts_with("--") {
+                match arg.split_once('=') {
+                    Some((flag, value)) => (flag, Some(value)),
+                    None => (arg.as_str(), None),
+                }
+            } else {
+                return Err(unknown_argument(arg));
+            };
+
+            match flag {
+                "-I" | "--proto_path" => {
+                    let value = flag_value(flag, value, &mut args)?;
+                    result.includes.extend(env::split_paths(value));
+                }
+                "--descriptor_set_in" => {
+                    let value = flag_value(flag, value, &mut args)?;
+                    result.descriptor_sets.extend(env::split_paths(value));
+                }
+                "--include_imports" | "--include_source_info" | "--retain_options" => {
+                    if value.is_some() {
+                        return Err(unknown_argument(arg));
+                    }
+                    match flag {
+                        "--include_imports" => result.include_imports = true,
+                        "--include_source_info" => result.include_source_info = true,
+                        _ => result.retain_options = true,
+                    }
+                }
+                _ => return Err(unknown_argument(arg)),
+            }
+        }
+
+        // protoc searches the current directory if no include paths are given.
+        if result.includes.is_empty() {
+            result.includes.push(PathBuf::from("."));
+        }
+        Ok(result)
+    }
+}
+
+fn flag_value<'a>(
+    flag: &str,
+    value: Option<&'a str>,
+    args: &mut impl Iterator<Item = &'a String>,
+) -> Result<&'a str, Error> {
+    match value {
+        Some(value) => Ok(value),
+        None => args.next().map(String::as_str).ok_or_else(|| {
+            Error::from_kind(ErrorKind::MissingArgumentValue {
+                flag: flag.to_owned(),
+            })
+        }),
+    }
+}
+
+fn unknown_argument(arg: &str) -> Error {
+    Error::from_kind(ErrorKind::UnknownArgument {
+        arg: arg.to_owned(),
+    })
+}
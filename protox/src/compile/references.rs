@@ -0,0 +1,429 @@
+use std::{collections::BTreeMap, ops::Range};
+
+use prost_reflect::{FileDescriptor, Kind, MessageDescriptor};
+use prost_types::{DescriptorProto, FileDescriptorProto};
+use protox_parse::{
+    tag,
+    tokens::{tokenize, TokenKind},
+};
+
+use crate::{
+    error::location_span,
+    symbol::{join_name, ReferenceKind},
+};
+
+/// A name in a source file which resolved to a definition.
+pub(crate) struct SourceReference {
+    /// The span of the name as written, excluding the parentheses around an extension name in an option.
+    pub span: Range<usize>,
+    /// The fully-qualified name of the scope the name was resolved in.
+    pub scope: String,
+    /// The fully-qualified name of the definition the name resolved to.
+    pub target: String,
+    pub kind: ReferenceKind,
+}
+
+/// An option set to an aggregate value in the text format, such as `option (foo) = { bar: 1 };`.
+pub(crate) struct AggregateOption {
+    /// The span of the value.
+    pub span: Range<usize>,
+    /// The type of the value.
+    pub message: MessageDescriptor,
+}
+
+/// The names in a file which resolved to a definition, ordered by position.
+pub(crate) struct FileReferences {
+    pub references: Vec<SourceReference>,
+    pub aggregates: Vec<AggregateOption>,
+}
+
+/// Finds the spans of the type names, extendees, method input and output types, and option names in a file which has
+/// been added to the pool, using its source code info.
+///
+/// The pool only records the definition each name resolved to, so the span of each name is found from the location
+/// of the element it belongs to. Map value types and the parts of option names are found by lexing the source of
+/// the location.
+pub(crate) fn source_references(file: &FileDescriptor, source: &str) -> FileReferences {
+    let mut collector = Collector {
+        file,
+        proto: file.file_descriptor_proto(),
+        source,
+        references: BTreeMap::new(),
+        aggregates: Vec::new(),
+    };
+    collector.collect_types();
+    collector.collect_options();
+    FileReferences {
+        references: collector.references.into_values().collect(),
+        aggregates: collector.aggregates,
+    }
+}
+
+struct Collector<'a> {
+    file: &'a FileDescriptor,
+    proto: &'a FileDescriptorProto,
+    source: &'a str,
+    /// The references found so far, keyed by their start offset. An option may have several locations with the same
+    /// span, so this also removes duplicates.
+    references: BTreeMap<usize, SourceReference>,
+    aggregates: Vec<AggregateOption>,
+}
+
+impl Collector<'_> {
+    fn collect_types(&mut self) {
+        let file = self.file;
+        let mut messages: Vec<_> = file.messages().collect();
+        let mut extensions: Vec<_> = file.extensions().collect();
+        while let Some(message) = messages.pop() {
+            messages.extend(message.child_messages());
+            extensions.extend(message.child_extensions());
+
+            for field in message.fields() {
+                // The type of a group is its own declaration, which is never qualified.
+                if field.is_group() {
+                    continue;
+                }
+                let span = self.find_location(&[field.path(), &[tag::field::TYPE_NAME]].concat());
+                match field.kind() {
+                    Kind::Message(entry) if field.is_map() => {
+                        let span = span.and_then(|span| self.map_value_span(span));
+                        let value = entry.map_entry_value_field();
+                        self.push_type(
+                            span,
+                            message.full_name(),
+                            value.kind(),
+                            ReferenceKind::FieldType,
+                        );
+                    }
+                    kind => {
+                        self.push_type(span, message.full_name(), kind, ReferenceKind::FieldType)
+                    }
+                }
+            }
+        }
+
+        for extension in extensions {
+            let scope = match extension.parent_message() {
+                Some(message) => message.full_name().to_owned(),
+                None => file.package_name().to_owned(),
+            };
+            let span = self.find_location(&[extension.path(), &[tag::field::EXTENDEE]].concat());
+            self.push_type(
+                span,
+                &scope,
+                Kind::Message(extension.containing_message()),
+                ReferenceKind::Extendee,
+            );
+            if !extension.is_group() {
+                let span =
+                    self.find_location(&[extension.path(), &[tag::field::TYPE_NAME]].concat());
+                self.push_type(span, &scope, extension.kind(), ReferenceKind::FieldType);
+            }
+        }
+
+        for service in file.services() {
+            for method in service.methods() {
+                for (tag, target, kind) in [
+                    (
+                        tag::method::INPUT_TYPE,
+                        method.input(),
+                        ReferenceKind::MethodInput,
+                    ),
+                    (
+                        tag::method::OUTPUT_TYPE,
+                        method.output(),
+                        ReferenceKind::MethodOutput,
+                    ),
+                ] {
+                    let span = self.find_location(&[method.path(), &[tag]].concat());
+                    self.push_type(span, file.package_name(), Kind::Message(target), kind);
+                }
+            }
+        }
+    }
+
+    /// Collects the extension and field names used in option statements.
+    fn collect_options(&mut self) {
+        let Some(source_code_info) = &self.proto.source_code_info else {
+            return;
+        };
+
+        for location in &source_code_info.location {
+            let Some((options, index, scope)) = options_path(self.proto, &location.path) else {
+                continue;
+            };
+            let numbers = &location.path[index..];
+            let (Some(mut message), Some(span)) = (
+                self.file.parent_pool().get_message_by_name(options),
+                location_span(self.source, &location.span),
+            ) else {
+                continue;
+            };
+            if numbers.is_empty() {
+                continue;
+            }
+
+            let span = span.offset()..span.offset() + span.len();
+            let Some((parts, value)) = option_name_parts(self.file.name(), self.source, span)
+            else {
+                continue;
+            };
+            if parts.len() != numbers.len() {
+                continue;
+            }
+
+            let mut kind = None;
+            for (&number, NamePart { span, is_extension }) in numbers.iter().zip(parts) {
+                let (target, next) = if is_extension {
+                    let Some(extension) = message
+                        .extensions()
+                        .find(|extension| extension.number() == number as u32)
+                    else {
+                        break;
+                    };
+                    (extension.full_name().to_owned(), extension.kind())
+                } else {
+                    let Some(field) = message.get_field(number as u32) else {
+                        break;
+                    };
+                    (field.full_name().to_owned(), field.kind())
+                };
+                self.references.insert(
+                    span.start,
+                    SourceReference {
+                        span,
+                        scope: scope.clone(),
+                        target,
+                        kind: if is_extension {
+                            ReferenceKind::OptionExtension
+                        } else {
+                            ReferenceKind::OptionField
+                        },
+                    },
+                );
+
+                kind = Some(next);
+                match &kind {
+                    Some(Kind::Message(next)) => message = next.clone(),
+                    _ => break,
+                }
+            }
+
+            if let (true, Some(Kind::Message(message))) =
+                (self.source[value.clone()].starts_with('{'), kind)
+            {
+                if !self
+                    .aggregates
+                    .iter()
+                    .any(|aggregate| aggregate.span == value)
+                {
+                    self.aggregates.push(AggregateOption {
+                        span: value,
+                        message,
+                    });
+                }
+            }
+        }
+    }
+
+    fn push_type(
+        &mut self,
+        span: Option<Range<usize>>,
+        scope: &str,
+        target: Kind,
+        kind: ReferenceKind,
+    ) {
+        let target = match target {
+            Kind::Message(message) => message.full_name().to_owned(),
+            Kind::Enum(enum_) => enum_.full_name().to_owned(),
+            _ => return,
+        };
+        if let Some(span) = span {
+            self.references.insert(
+                span.start,
+                SourceReference {
+                    span,
+                    scope: scope.to_owned(),
+                    target,
+                    kind,
+                },
+            );
+        }
+    }
+
+    fn find_location(&self, path: &[i32]) -> Option<Range<usize>> {
+        find_location(self.proto, self.source, path)
+    }
+
+    /// Finds the span of the value type within the type of a map field, such as `Bar` in `map<string, Bar>`.
+    fn map_value_span(&self, span: Range<usize>) -> Option<Range<usize>> {
+        let tokens: Vec<_> = tokens(self.file.name(), self.source, span).collect();
+        let comma = tokens
+            .iter()
+            .position(|(kind, _)| *kind == TokenKind::Punct(','))?;
+        let ((last, _), value) = tokens[comma + 1..].split_last()?;
+        if *last != TokenKind::Punct('>') {
+            return None;
+        }
+        Some(value.first()?.1.start..value.last()?.1.end)
+    }
+}
+
+/// Finds the span of the location with exactly the given path.
+pub(crate) fn find_location(
+    file: &FileDescriptorProto,
+    source: &str,
+    path: &[i32],
+) -> Option<Range<usize>> {
+    let location = file
+        .source_code_info
+        .as_ref()?
+        .location
+        .iter()
+        .find(|location| location.path == path)?;
+    let span = location_span(source, &location.span)?;
+    Some(span.offset()..span.offset() + span.len())
+}
+
+/// Gets the tokens within a span of the source, excluding comments, with spans relative to the whole source.
+pub(crate) fn tokens<'a>(
+    file: &str,
+    source: &'a str,
+    span: Range<usize>,
+) -> impl Iterator<Item = (TokenKind<'a>, Range<usize>)> + 'a {
+    let offset = span.start;
+    tokenize(file, &source[span]).filter_map(move |token| {
+        let span = token.span();
+        match token.kind() {
+            TokenKind::LineComment(_) | TokenKind::BlockComment(_) => None,
+            kind => Some((kind.clone(), span.start + offset..span.end + offset)),
+        }
+    })
+}
+
+/// A part of an option name.
+struct NamePart {
+    span: Range<usize>,
+    is_extension: bool,
+}
+
+/// Splits an option, such as `option (foo.bar).baz = 1;` or `(foo) = 1` within brackets, into the span of each part
+/// of its name, followed by the span of its value.
+fn option_name_parts(
+    file: &str,
+    source: &str,
+    span: Range<usize>,
+) -> Option<(Vec<NamePart>, Range<usize>)> {
+    let end = span.end;
+    let mut tokens = tokens(file, source, span).peekable();
+    if tokens
+        .peek()
+        .is_some_and(|(kind, _)| *kind == TokenKind::Ident("option"))
+    {
+        tokens.next();
+    }
+
+    let mut parts = Vec::new();
+    loop {
+        match tokens.next()? {
+            (TokenKind::Punct('('), _) => {
+                let mut name: Option<Range<usize>> = None;
+                for (kind, span) in tokens.by_ref() {
+                    if kind == TokenKind::Punct(')') {
+                        break;
+                    }
+                    let start = name.map_or(span.start, |name| name.start);
+                    name = Some(start..span.end);
+                }
+                parts.push(NamePart {
+                    span: name?,
+                    is_extension: true,
+                });
+            }
+            (TokenKind::Ident(_), span) => parts.push(NamePart {
+                span,
+                is_extension: false,
+            }),
+            _ => return None,
+        }
+
+        match tokens.next()? {
+            (TokenKind::Punct('.'), _) => continue,
+            (TokenKind::Punct('='), _) => break,
+            _ => return None,
+        }
+    }
+
+    let (_, value) = tokens.next()?;
+    Some((parts, value.start..end))
+}
+
+/// For the location of an option, finds the name of the options message it sets, the index in the path of the
+/// numbers identifying each part of the option name, and the scope extension names are resolved in.
+fn options_path(file: &FileDescriptorProto, path: &[i32]) -> Option<(&'static str, usize, String)> {
+    let package = file.package();
+    match *path {
+        [tag::file::OPTIONS, ..] => Some(("google.protobuf.FileOptions", 1, package.to_owned())),
+        [tag::file::MESSAGE_TYPE, i, ref rest @ ..] => {
+            let message = file.message_type.get(usize::try_from(i).ok()?)?;
+            message_options_path(message, join_name(package, message.name()), rest, 2)
+        }
+        [tag::file::ENUM_TYPE, _, ref rest @ ..] => enum_options_path(rest, 2, package.to_owned()),
+        [tag::file::SERVICE, i, ref rest @ ..] => {
+            let service = file.service.get(usize::try_from(i).ok()?)?;
+            let scope = join_name(package, service.name());
+            match *rest {
+                [tag::service::OPTIONS, ..] => Some(("google.protobuf.ServiceOptions", 3, scope)),
+                [tag::service::METHOD, _, tag::method::OPTIONS, ..] => {
+                    Some(("google.protobuf.MethodOptions", 5, scope))
+                }
+                _ => None,
+            }
+        }
+        [tag::file::EXTENSION, _, tag::field::OPTIONS, ..] => {
+            Some(("google.protobuf.FieldOptions", 3, package.to_owned()))
+        }
+        _ => None,
+    }
+}
+
+fn message_options_path(
+    message: &DescriptorProto,
+    scope: String,
+    path: &[i32],
+    offset: usize,
+) -> Option<(&'static str, usize, String)> {
+    match *path {
+        [tag::message::OPTIONS, ..] => Some(("google.protobuf.MessageOptions", offset + 1, scope)),
+        [tag::message::FIELD | tag::message::EXTENSION, _, tag::field::OPTIONS, ..] => {
+            Some(("google.protobuf.FieldOptions", offset + 3, scope))
+        }
+        [tag::message::ONEOF_DECL, _, tag::oneof::OPTIONS, ..] => {
+            Some(("google.protobuf.OneofOptions", offset + 3, scope))
+        }
+        [tag::message::EXTENSION_RANGE, _, tag::message::extension_range::OPTIONS, ..] => {
+            Some(("google.protobuf.ExtensionRangeOptions", offset + 3, scope))
+        }
+        [tag::message::NESTED_TYPE, i, ref rest @ ..] => {
+            let nested = message.nested_type.get(usize::try_from(i).ok()?)?;
+            let scope = join_name(&scope, nested.name());
+            message_options_path(nested, scope, rest, offset + 2)
+        }
+        [tag::message::ENUM_TYPE, _, ref rest @ ..] => enum_options_path(rest, offset + 2, scope),
+        _ => None,
+    }
+}
+
+fn enum_options_path(
+    path: &[i32],
+    offset: usize,
+    scope: String,
+) -> Option<(&'static str, usize, String)> {
+    match *path {
+        [tag::enum_::OPTIONS, ..] => Some(("google.protobuf.EnumOptions", offset + 1, scope)),
+        [tag::enum_::VALUE, _, tag::enum_value::OPTIONS, ..] => {
+            Some(("google.protobuf.EnumValueOptions", offset + 3, scope))
+        }
+        _ => None,
+    }
+}
@@ -0,0 +1,164 @@
+use miette::NamedSource;
+use prost_reflect::{FileDescriptor, Kind};
+use prost_types::{
+    field_descriptor_proto::Label,
+    field_options::{CType, JsType},
+    file_options::OptimizeMode,
+    FieldDescriptorProto,
+};
+use protox_parse::tag;
+
+use super::options::find_span;
+use crate::error::{Error, ErrorKind};
+
+/// Checks the values of standard options in the file which `protoc` restricts beyond their type, such as `jstype`
+/// only being allowed on 64-bit integer fields, `ctype` only on string and bytes fields, and `packed = true` only on
+/// repeated primitive fields.
+///
+/// Like the other checks, this applies to pre-compiled files as well as source files, so invalid options in an
+/// imported file descriptor set are reported rather than passed through to the output.
+pub(crate) fn check_standard_options(
+    file: &FileDescriptor,
+    source: Option<&str>,
+) -> Result<(), Error> {
+    let error = |path: Vec<i32>, message: String| {
+        Error::from_kind(ErrorKind::InvalidOptionValue {
+            message,
+            span: source.and_then(|source| find_span(file.file_descriptor_proto(), source, &path)),
+            source_code: NamedSource::new(file.name(), source.unwrap_or_default().to_owned()),
+        })
+    };
+
+    if !is_lite(file) {
+        for (index, dependency) in file.dependencies().enumerate() {
+            if is_lite(&dependency) {
+                return Err(error(
+                    vec![tag::file::DEPENDENCY, index as i32],
+                    format!(
+                        "files which do not use 'optimize_for = LITE_RUNTIME' cannot import '{}', which does",
+                        dependency.name()
+                    ),
+                ));
+            }
+        }
+    }
+
+    let check_field = |path: &[i32],
+                       proto: &FieldDescriptorProto,
+                       kind: Kind,
+                       is_extension: bool| {
+        let Some(options) = &proto.options else {
+            return Ok(());
+        };
+        let option_path = |tag: i32| [path, &[tag::field::OPTIONS, tag]].concat();
+
+        if options.ctype.is_some() {
+            if !matches!(kind, Kind::String | Kind::Bytes) {
+                return Err(error(
+                    option_path(tag::field_options::CTYPE),
+                    format!(
+                        "ctype is only allowed on string or bytes fields, but '{}' has type '{:?}'",
+                        proto.name(),
+                        kind,
+                    ),
+                ));
+            }
+            if is_extension && options.ctype() == CType::Cord {
+                return Err(error(
+                    option_path(tag::field_options::CTYPE),
+                    format!(
+                        "extension '{}' specifies 'ctype = CORD', which is not supported for extensions",
+                        proto.name(),
+                    ),
+                ));
+            }
+        }
+        if options.packed() {
+            if proto.label() != Label::Repeated {
+                return Err(error(
+                    option_path(tag::field_options::PACKED),
+                    format!(
+                        "'packed = true' can only be specified for repeated fields, but '{}' is not repeated",
+                        proto.name(),
+                    ),
+                ));
+            }
+            if !is_packable(&kind) {
+                return Err(error(
+                    option_path(tag::field_options::PACKED),
+                    format!(
+                        "'packed = true' can only be specified for repeated primitive fields, but '{}' has type '{:?}'",
+                        proto.name(),
+                        kind,
+                    ),
+                ));
+            }
+        }
+        if options.jstype() != JsType::JsNormal && !is_64_bit_integer(&kind) {
+            return Err(error(
+                option_path(tag::field_options::JSTYPE),
+                format!(
+                    "jstype is only allowed on int64, uint64, sint64, fixed64 or sfixed64 fields, but '{}' has type '{:?}'",
+                    proto.name(),
+                    kind,
+                ),
+            ));
+        }
+        if options.lazy() && !matches!(kind, Kind::Message(_)) {
+            return Err(error(
+                option_path(tag::field_options::LAZY),
+                format!(
+                    "'lazy = true' can only be specified for message fields, but '{}' has type '{:?}'",
+                    proto.name(),
+                    kind,
+                ),
+            ));
+        }
+        Ok(())
+    };
+
+    let mut messages: Vec<_> = file.messages().collect();
+    let mut extensions: Vec<_> = file.extensions().collect();
+    while let Some(message) = messages.pop() {
+        for field in message.fields() {
+            check_field(
+                field.path(),
+                field.field_descriptor_proto(),
+                field.kind(),
+                false,
+            )?;
+        }
+
+        messages.extend(message.child_messages());
+        extensions.extend(message.child_extensions());
+    }
+    for extension in extensions {
+        check_field(
+            extension.path(),
+            extension.field_descriptor_proto(),
+            extension.kind(),
+            true,
+        )?;
+    }
+
+    Ok(())
+}
+
+fn is_lite(file: &FileDescriptor) -> bool {
+    file.file_descriptor_proto()
+        .options
+        .as_ref()
+        .is_some_and(|options| options.optimize_for() == OptimizeMode::LiteRuntime)
+}
+
+/// Returns `true` if repeated fields of this kind can use packed encoding, which is any scalar numeric type or enum.
+pub(crate) fn is_packable(kind: &Kind) -> bool {
+    !matches!(kind, Kind::String | Kind::Bytes | Kind::Message(_))
+}
+
+fn is_64_bit_integer(kind: &Kind) -> bool {
+    matches!(
+        kind,
+        Kind::Int64 | Kind::Uint64 | Kind::Sint64 | Kind::Fixed64 | Kind::Sfixed64
+    )
+}
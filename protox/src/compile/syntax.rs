@@ -0,0 +1,164 @@
+use std::collections::HashMap;
+
+use miette::NamedSource;
+use prost_reflect::{EnumDescriptor, FieldDescriptor, FileDescriptor, Kind, Syntax};
+use protox_parse::tag;
+
+use super::options::find_span;
+use crate::error::{EnumDefinition, Error, ErrorKind};
+
+/// The messages in `descriptor.proto` which proto3 files may extend, to define custom options.
+const OPTIONS_MESSAGES: &[&str] = &[
+    "FileOptions",
+    "MessageOptions",
+    "FieldOptions",
+    "OneofOptions",
+    "ExtensionRangeOptions",
+    "EnumOptions",
+    "EnumValueOptions",
+    "ServiceOptions",
+    "MethodOptions",
+];
+
+/// The packages `descriptor.proto` may be declared in. Like `protoc`, the `proto2` package used by some internal
+/// copies of `descriptor.proto` is also accepted.
+const DESCRIPTOR_PACKAGES: &[&str] = &["google.protobuf", "proto2"];
+
+/// Checks that a proto3 file only uses definitions from proto2 files in the ways protoc allows.
+///
+/// Fields of proto3 messages may not have a closed enum type, since proto3 messages must preserve unknown enum
+/// values, and proto3 files may only declare extensions of the options messages in `descriptor.proto`. The sources of
+/// previously compiled files are used to point at the definition of the enum.
+pub(crate) fn check_syntax_mixing(
+    file: &FileDescriptor,
+    source: Option<&str>,
+    sources: &HashMap<String, String>,
+) -> Result<(), Error> {
+    if file.syntax() != Syntax::Proto3 {
+        return Ok(());
+    }
+
+    let span = |path: &[i32], tag: i32| {
+        let mut path = path.to_vec();
+        path.push(tag);
+        source.and_then(|source| find_span(file.file_descriptor_proto(), source, &path))
+    };
+    let source_code = || NamedSource::new(file.name(), source.unwrap_or_default().to_owned());
+
+    let mut messages: Vec<_> = file.messages().collect();
+    let mut extensions: Vec<_> = file.extensions().collect();
+    while let Some(message) = messages.pop() {
+        if !message.is_map_entry() {
+            for field in message.fields() {
+                if let Some(enum_ty) = closed_enum_type(&field) {
+                    return Err(Error::from_kind(ErrorKind::ClosedEnumInProto3 {
+                        enum_name: enum_ty.full_name().to_owned(),
+                        field: field.name().to_owned(),
+                        message: message.full_name().to_owned(),
+                        span: span(field.path(), tag::field::TYPE_NAME),
+                        source_code: source_code(),
+                        definition: vec![enum_definition(&enum_ty, sources)],
+                    }));
+                }
+            }
+        }
+
+        messages.extend(message.child_messages());
+        extensions.extend(message.child_extensions());
+    }
+
+    for extension in extensions {
+        let extendee = extension.containing_message();
+        if !is_options_message(extendee.full_name()) {
+            return Err(Error::from_kind(ErrorKind::InvalidProto3Extension {
+                extendee: extendee.full_name().to_owned(),
+                span: span(extension.path(), tag::field::EXTENDEE),
+                source_code: source_code(),
+            }));
+        }
+    }
+
+    Ok(())
+}
+
+/// Checks that a proto3 file does not declare `optional` fields, which `protoc` 3.12 to 3.14 only accepted with
+/// `--experimental_allow_proto3_optional`.
+pub(crate) fn check_no_proto3_optional(
+    file: &FileDescriptor,
+    source: Option<&str>,
+) -> Result<(), Error> {
+    let mut messages: Vec<_> = file.messages().collect();
+    let mut fields: Vec<_> = file
+        .extensions()
+        .map(|extension| {
+            (
+                extension.field_descriptor_proto().clone(),
+                extension.path().to_vec(),
+            )
+        })
+        .collect();
+    while let Some(message) = messages.pop() {
+        fields.extend(message.fields().map(|field| {
+            (
+                field.field_descriptor_proto().clone(),
+                field.path().to_vec(),
+            )
+        }));
+        fields.extend(message.child_extensions().map(|extension| {
+            (
+                extension.field_descriptor_proto().clone(),
+                extension.path().to_vec(),
+            )
+        }));
+        messages.extend(message.child_messages());
+    }
+
+    match fields
+        .into_iter()
+        .find(|(field, _)| field.proto3_optional())
+    {
+        Some((field, mut path)) => {
+            path.push(tag::field::LABEL);
+            Err(Error::from_kind(ErrorKind::Proto3OptionalNotAllowed {
+                field: field.name().to_owned(),
+                span: source
+                    .and_then(|source| find_span(file.file_descriptor_proto(), source, &path)),
+                source_code: NamedSource::new(file.name(), source.unwrap_or_default().to_owned()),
+            }))
+        }
+        None => Ok(()),
+    }
+}
+
+fn is_options_message(full_name: &str) -> bool {
+    match full_name.rsplit_once('.') {
+        Some((package, name)) => {
+            DESCRIPTOR_PACKAGES.contains(&package) && OPTIONS_MESSAGES.contains(&name)
+        }
+        None => false,
+    }
+}
+
+/// Gets the enum type of a field, or the value type of a map field, if it is defined in a proto2 file.
+fn closed_enum_type(field: &FieldDescriptor) -> Option<EnumDescriptor> {
+    let kind = match field.kind() {
+        Kind::Message(entry) if field.is_map() => entry.map_entry_value_field().kind(),
+        kind => kind,
+    };
+    match kind {
+        Kind::Enum(enum_ty) if enum_ty.parent_file().syntax() == Syntax::Proto2 => Some(enum_ty),
+        _ => None,
+    }
+}
+
+fn enum_definition(enum_ty: &EnumDescriptor, sources: &HashMap<String, String>) -> EnumDefinition {
+    let file = enum_ty.parent_file();
+    let source = sources.get(file.name()).map(String::as_str);
+    let mut path = enum_ty.path().to_vec();
+    path.push(tag::enum_::NAME);
+    EnumDefinition {
+        name: enum_ty.full_name().to_owned(),
+        span: source.and_then(|source| find_span(file.file_descriptor_proto(), source, &path)),
+        source_code: NamedSource::new(file.name(), source.unwrap_or_default().to_owned()),
+    }
+}
@@ -1,4 +1,4 @@
-use std::{fs, iter::once};
+use std::{env, fs, iter::once};
 
 use tempfile::TempDir;
 
@@ -79,8 +79,8 @@ fn test_compile_error(
 
     match (err.kind(), &expected_err) {
         (
-            ErrorKind::FileNotIncluded { path: lpath },
-            ErrorKind::FileNotIncluded { path: rpath },
+            ErrorKind::FileNotIncluded { path: lpath, .. },
+            ErrorKind::FileNotIncluded { path: rpath, .. },
         ) => assert_eq!(lpath, rpath),
         (err, _) => panic!("unexpected error: {}", err),
     }
@@ -139,14 +139,7 @@ fn abs_include_abs_subdir_file() {
 fn abs_include_dot_file() {
     let dir = TempDir::new().unwrap();
     with_current_dir(&dir, || {
-        test_compile_error(
-            dir.path(),
-            Path::new(".").join("foo.proto"),
-            "foo.proto",
-            ErrorKind::FileNotIncluded {
-                path: Path::new(".").join("foo.proto"),
-            },
-        )
+        test_compile_success(dir.path(), Path::new(".").join("foo.proto"), "foo.proto")
     })
 }
 
@@ -154,13 +147,10 @@ fn abs_include_dot_file() {
 fn abs_include_dot_subdir_file() {
     let dir = TempDir::new().unwrap();
     with_current_dir(&dir, || {
-        test_compile_error(
+        test_compile_success(
             dir.path(),
             Path::new(".").join("dir").join("foo.proto"),
             "dir/foo.proto",
-            ErrorKind::FileNotIncluded {
-                path: Path::new(".").join("dir").join("foo.proto"),
-            },
         )
     })
 }
@@ -181,13 +171,10 @@ fn abs_subdir_include_simple_subdir_file() {
 fn abs_subdir_include_rel_file() {
     let dir = TempDir::new().unwrap();
     with_current_dir(&dir, || {
-        test_compile_error(
+        test_compile_success(
             dir.path().join("include"),
             Path::new("include").join("foo.proto"),
             "foo.proto",
-            ErrorKind::FileNotIncluded {
-                path: Path::new("include").join("foo.proto"),
-            },
         );
     });
 }
@@ -196,13 +183,10 @@ fn abs_subdir_include_rel_file() {
 fn abs_subdir_include_rel_subdir_file() {
     let dir = TempDir::new().unwrap();
     with_current_dir(&dir, || {
-        test_compile_error(
+        test_compile_success(
             dir.path().join("include"),
             Path::new("include").join("dir").join("foo.proto"),
             "dir/foo.proto",
-            ErrorKind::FileNotIncluded {
-                path: Path::new("include").join("dir").join("foo.proto"),
-            },
         );
     });
 }
@@ -227,13 +211,10 @@ fn abs_subdir_include_abs_subdir_file() {
 fn abs_subdir_include_dot_file() {
     let dir = TempDir::new().unwrap();
     with_current_dir(&dir, || {
-        test_compile_error(
+        test_compile_success(
             dir.path().join("include"),
             Path::new(".").join("include").join("foo.proto"),
             "foo.proto",
-            ErrorKind::FileNotIncluded {
-                path: Path::new(".").join("include").join("foo.proto"),
-            },
         );
     });
 }
@@ -242,13 +223,10 @@ fn abs_subdir_include_dot_file() {
 fn abs_subdir_include_dot_subdir_file() {
     let dir = TempDir::new().unwrap();
     with_current_dir(&dir, || {
-        test_compile_error(
+        test_compile_success(
             dir.path().join("include"),
             Path::new(".").join("include").join("dir").join("foo.proto"),
             "dir/foo.proto",
-            ErrorKind::FileNotIncluded {
-                path: Path::new(".").join("include").join("dir").join("foo.proto"),
-            },
         );
     });
 }
@@ -256,7 +234,7 @@ fn abs_subdir_include_dot_subdir_file() {
 #[test]
 fn abs_include_complex_file() {
     let dir = TempDir::new().unwrap();
-    test_compile_error(
+    test_compile_success(
         &dir,
         dir.path()
             .join("dir")
@@ -264,21 +242,13 @@ fn abs_include_complex_file() {
             .join("dir")
             .join("foo.proto"),
         "dir/foo.proto",
-        ErrorKind::FileNotIncluded {
-            path: dir
-                .path()
-                .join("dir")
-                .join("..")
-                .join("dir")
-                .join("foo.proto"),
-        },
     );
 }
 
 #[test]
 fn abs_subdir_include_complex_file() {
     let dir = TempDir::new().unwrap();
-    test_compile_error(
+    test_compile_success(
         dir.path().join("include"),
         dir.path()
             .join("include")
@@ -286,14 +256,6 @@ fn abs_subdir_include_complex_file() {
             .join("include")
             .join("foo.proto"),
         "foo.proto",
-        ErrorKind::FileNotIncluded {
-            path: dir
-                .path()
-                .join("include")
-                .join("..")
-                .join("include")
-                .join("foo.proto"),
-        },
     );
 }
 
@@ -347,6 +309,7 @@ fn rel_subdir_include_abs_file() {
             "foo.proto",
             ErrorKind::FileNotIncluded {
                 path: dir.path().join("foo.proto"),
+                searched: None,
             },
         );
     });
@@ -362,6 +325,7 @@ fn rel_subdir_include_abs_subdir_file() {
             "dir/foo.proto",
             ErrorKind::FileNotIncluded {
                 path: dir.path().join("dir").join("foo.proto"),
+                searched: None,
             },
         );
     });
@@ -395,19 +359,13 @@ fn rel_subdir_include_dot_subdir_file() {
 fn rel_subdir_include_complex_file() {
     let dir = TempDir::new().unwrap();
     with_current_dir(&dir, || {
-        test_compile_error(
+        test_compile_success(
             "include",
             Path::new("include")
                 .join("..")
                 .join("include")
                 .join("foo.proto"),
             "foo.proto",
-            ErrorKind::FileNotIncluded {
-                path: Path::new("include")
-                    .join("..")
-                    .join("include")
-                    .join("foo.proto"),
-            },
         );
     });
 }
@@ -448,14 +406,7 @@ fn dot_include_rel_subdir_file() {
 fn dot_include_abs_file() {
     let dir = TempDir::new().unwrap();
     with_current_dir(&dir, || {
-        test_compile_error(
-            ".",
-            dir.path().join("foo.proto"),
-            "foo.proto",
-            ErrorKind::FileNotIncluded {
-                path: dir.path().join("foo.proto"),
-            },
-        );
+        test_compile_success(".", dir.path().join("foo.proto"), "foo.proto");
     });
 }
 
@@ -463,13 +414,10 @@ fn dot_include_abs_file() {
 fn dot_include_abs_subdir_file() {
     let dir = TempDir::new().unwrap();
     with_current_dir(&dir, || {
-        test_compile_error(
+        test_compile_success(
             ".",
             dir.path().join("dir").join("foo.proto"),
             "dir/foo.proto",
-            ErrorKind::FileNotIncluded {
-                path: dir.path().join("dir").join("foo.proto"),
-            },
         );
     });
 }
@@ -548,6 +496,7 @@ fn dot_subdir_include_abs_file() {
             "dir/foo.proto",
             ErrorKind::FileNotIncluded {
                 path: dir.path().join("include").join("foo.proto"),
+                searched: None,
             },
         );
     });
@@ -557,13 +506,10 @@ fn dot_subdir_include_abs_file() {
 fn dot_subdir_include_abs_subdir_file() {
     let dir = TempDir::new().unwrap();
     with_current_dir(&dir, || {
-        test_compile_error(
+        test_compile_success(
             Path::new(".").join("include"),
             dir.path().join("include").join("dir").join("foo.proto"),
             "dir/foo.proto",
-            ErrorKind::FileNotIncluded {
-                path: dir.path().join("include").join("dir").join("foo.proto"),
-            },
         );
     });
 }
@@ -596,19 +542,13 @@ fn dot_subdir_include_dot_subdir_file() {
 fn dot_subdir_include_complex_file() {
     let dir = TempDir::new().unwrap();
     with_current_dir(&dir, || {
-        test_compile_error(
+        test_compile_success(
             Path::new(".").join("include"),
             Path::new("include")
                 .join("..")
                 .join("include")
                 .join("foo.proto"),
             "foo.proto",
-            ErrorKind::FileNotIncluded {
-                path: Path::new("include")
-                    .join("..")
-                    .join("include")
-                    .join("foo.proto"),
-            },
         );
     });
 }
@@ -629,6 +569,59 @@ fn complex_include_complex_file() {
     });
 }
 
+#[test]
+fn abs_subdir_include_escaping_file() {
+    let dir = TempDir::new().unwrap();
+    std::fs::write(dir.path().join("foo.proto"), EMPTY).unwrap();
+    test_compile_error(
+        dir.path().join("include"),
+        dir.path().join("include").join("..").join("foo.proto"),
+        "foo.proto",
+        ErrorKind::FileNotIncluded {
+            path: dir.path().join("include").join("..").join("foo.proto"),
+            searched: None,
+        },
+    );
+}
+
+#[test]
+fn add_file_with_name() {
+    let dir = TempDir::new().unwrap();
+    fs::create_dir_all(dir.path().join("include")).unwrap();
+    std::fs::write(
+        dir.path().join("include").join("dep.proto"),
+        "package dep; message Dep {}",
+    )
+    .unwrap();
+    std::fs::write(
+        dir.path().join("root.proto"),
+        "import 'dep.proto'; package root; message Root { optional dep.Dep dep = 1; }",
+    )
+    .unwrap();
+
+    let mut compiler = Compiler::new([dir.path().join("include")]).unwrap();
+    compiler
+        .add_file_with_name(dir.path().join("root.proto"), "root/root.proto")
+        .unwrap();
+
+    assert_eq!(
+        compiler.files["root/root.proto"].path(),
+        Some(dir.path().join("root.proto").as_path())
+    );
+    assert!(!compiler.files["root/root.proto"].is_import);
+    assert!(compiler.files["dep.proto"].is_import);
+    assert!(compiler
+        .descriptor_pool()
+        .get_message_by_name("root.Root")
+        .is_some());
+
+    compiler.hermetic(true);
+    let err = compiler
+        .add_file_with_name(dir.path().join("root.proto"), "other.proto")
+        .unwrap_err();
+    assert!(matches!(err.kind(), ErrorKind::FileOutsideIncludes { .. }));
+}
+
 #[test]
 fn invalid_file() {
     let dir = TempDir::new().unwrap();
@@ -639,8 +632,15 @@ fn invalid_file() {
     let err = compiler.open_file("foo.proto").unwrap_err();
 
     match err.kind() {
-        ErrorKind::FileInvalidUtf8 { name } => {
+        ErrorKind::FileInvalidUtf8 {
+            name, path, offset, ..
+        } => {
             assert_eq!(name, "foo.proto");
+            assert_eq!(
+                path.as_deref(),
+                Some(dir.path().join("foo.proto").as_path())
+            );
+            assert_eq!(*offset, 0);
         }
         kind => panic!("unexpected error: {}", kind),
     }
@@ -760,7 +760,7 @@ fn shadow_invalid_file() {
         .unwrap_err();
 
     match err.kind() {
-        ErrorKind::FileInvalidUtf8 { name } => {
+        ErrorKind::FileInvalidUtf8 { name, .. } => {
             assert_eq!(name, "foo.proto");
         }
         kind => panic!("unexpected error: {}", kind),
@@ -794,6 +794,58 @@ fn shadow_already_imported_file() {
     }
 }
 
+#[test]
+fn shadow_policy() {
+    let dir = TempDir::new().unwrap();
+
+    fs::create_dir_all(dir.path().join("include1")).unwrap();
+    std::fs::write(dir.path().join("include1").join("foo.proto"), EMPTY).unwrap();
+    std::fs::write(dir.path().join("include1").join("bar.proto"), EMPTY).unwrap();
+
+    fs::create_dir_all(dir.path().join("include2")).unwrap();
+    std::fs::write(dir.path().join("include2").join("foo.proto"), EMPTY).unwrap();
+    std::fs::write(
+        dir.path().join("include2").join("bar.proto"),
+        "message Bar {}",
+    )
+    .unwrap();
+
+    let mut compiler =
+        Compiler::new([dir.path().join("include1"), dir.path().join("include2")]).unwrap();
+    compiler.shadow_policy(ShadowPolicy::AllowIfIdenticalContent);
+    compiler
+        .open_file(dir.path().join("include2").join("foo.proto"))
+        .unwrap();
+    let err = compiler
+        .open_file(dir.path().join("include2").join("bar.proto"))
+        .unwrap_err();
+    assert!(matches!(err.kind(), ErrorKind::FileShadowed { .. }));
+    assert!(compiler.check_warnings().is_empty());
+
+    let mut compiler =
+        Compiler::new([dir.path().join("include1"), dir.path().join("include2")]).unwrap();
+    compiler.shadow_policy(ShadowPolicy::WarnAndUseFirst);
+    compiler
+        .open_file(dir.path().join("include2").join("bar.proto"))
+        .unwrap();
+    assert_eq!(
+        compiler.files().next().unwrap().path(),
+        Some(dir.path().join("include1").join("bar.proto").as_ref())
+    );
+
+    let warnings = compiler.check_warnings();
+    assert_eq!(warnings.len(), 1);
+    assert_eq!(warnings[0].file(), "bar.proto");
+    assert_eq!(
+        warnings[0].to_string(),
+        format!(
+            "path '{}' is shadowed by '{}' in the include paths",
+            dir.path().join("include2").join("bar.proto").display(),
+            dir.path().join("include1").join("bar.proto").display()
+        )
+    );
+}
+
 #[test]
 fn import_files() {
     let dir = TempDir::new().unwrap();
@@ -883,9 +935,31 @@ fn import_cycle() {
     let err = compiler.open_file("root.proto").unwrap_err();
 
     match err.kind() {
-        ErrorKind::CircularImport { name, cycle } => {
+        ErrorKind::CircularImport {
+            name,
+            cycle,
+            imports,
+        } => {
             assert_eq!(name, "root.proto");
-            assert_eq!(cycle, "root.proto -> dep.proto -> dep2.proto -> root.proto")
+            assert_eq!(cycle, "root.proto -> dep.proto -> dep2.proto -> root.proto");
+            let imports: Vec<_> = imports
+                .iter()
+                .map(|import| {
+                    (
+                        import.source_code.name(),
+                        import.import.as_str(),
+                        import.span.map(|span| (span.offset(), span.len())),
+                    )
+                })
+                .collect();
+            assert_eq!(
+                imports,
+                [
+                    ("root.proto", "dep.proto", Some((0, 19))),
+                    ("dep.proto", "dep2.proto", Some((0, 20))),
+                    ("dep2.proto", "root.proto", Some((0, 20))),
+                ]
+            );
         }
         kind => panic!("unexpected error: {}", kind),
     }
@@ -902,9 +976,16 @@ fn import_cycle_short() {
     let err = compiler.open_file("root.proto").unwrap_err();
 
     match err.kind() {
-        ErrorKind::CircularImport { name, cycle } => {
+        ErrorKind::CircularImport {
+            name,
+            cycle,
+            imports,
+        } => {
             assert_eq!(name, "dep.proto");
-            assert_eq!(cycle, "root.proto -> dep.proto -> dep.proto")
+            assert_eq!(cycle, "root.proto -> dep.proto -> dep.proto");
+            assert_eq!(imports.len(), 1);
+            assert_eq!(imports[0].source_code.name(), "dep.proto");
+            assert_eq!(imports[0].import, "dep.proto");
         }
         kind => panic!("unexpected error: {}", kind),
     }
@@ -920,7 +1001,7 @@ fn import_cycle_nested() {
     let err = compiler.open_file("root.proto").unwrap_err();
 
     match err.kind() {
-        ErrorKind::CircularImport { name, cycle } => {
+        ErrorKind::CircularImport { name, cycle, .. } => {
             assert_eq!(name, "root.proto");
             assert_eq!(cycle, "root.proto -> root.proto")
         }
@@ -1006,3 +1087,55 @@ fn open_file_case_insensitive() {
         "foo.proto",
     );
 }
+
+#[test]
+fn parse_protoc_args() {
+    use protoc_args::ProtocArgs;
+
+    fn parse(args: &[&str]) -> Result<ProtocArgs, Error> {
+        ProtocArgs::parse(&args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>())
+    }
+
+    let include_list = env::join_paths(["c", "d"]).unwrap();
+    let args = parse(&[
+        "-Ia",
+        "-I",
+        "b",
+        &format!("--proto_path={}", include_list.to_str().unwrap()),
+        "--proto_path",
+        "e",
+        "--descriptor_set_in=deps.bin",
+        "--include_imports",
+        "--include_source_info",
+        "foo.proto",
+        "bar/baz.proto",
+    ])
+    .unwrap();
+    assert_eq!(
+        args,
+        ProtocArgs {
+            includes: ["a", "b", "c", "d", "e"].map(PathBuf::from).to_vec(),
+            descriptor_sets: vec![PathBuf::from("deps.bin")],
+            files: ["foo.proto", "bar/baz.proto"].map(PathBuf::from).to_vec(),
+            include_imports: true,
+            include_source_info: true,
+            retain_options: false,
+        }
+    );
+
+    assert_eq!(
+        parse(&["foo.proto"]).unwrap().includes,
+        vec![PathBuf::from(".")]
+    );
+
+    let err = parse(&["--descriptor_set_out=out.bin", "foo.proto"]).unwrap_err();
+    assert!(
+        matches!(err.kind(), ErrorKind::UnknownArgument { arg } if arg == "--descriptor_set_out=out.bin")
+    );
+    let err = parse(&["--include_imports=true"]).unwrap_err();
+    assert!(matches!(err.kind(), ErrorKind::UnknownArgument { .. }));
+    let err = parse(&["-o", "out.bin"]).unwrap_err();
+    assert!(matches!(err.kind(), ErrorKind::UnknownArgument { arg } if arg == "-o"));
+    let err = parse(&["foo.proto", "-I"]).unwrap_err();
+    assert_eq!(err.to_string(), "missing value for protoc flag '-I'");
+}
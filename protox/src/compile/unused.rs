@@ -0,0 +1,216 @@
+use std::{collections::HashSet, fmt};
+
+use miette::{Diagnostic, NamedSource, SourceCode, SourceSpan};
+use prost_reflect::{DynamicMessage, FileDescriptor, MessageDescriptor};
+use protox_parse::fix::{Edit, Fix};
+use thiserror::Error;
+
+use crate::{error::location_span, symbol::file_type_references};
+
+/// A warning that an imported file does not declare any name used by the importing file.
+///
+/// See [`Compiler::unused_imports`](crate::Compiler::unused_imports).
+#[derive(Error, Diagnostic)]
+#[error("import '{name}' is unused")]
+#[diagnostic(severity(Warning), help("remove this import"))]
+pub struct UnusedImport {
+    name: String,
+    #[label("unused import")]
+    span: Option<SourceSpan>,
+    removal_span: Option<SourceSpan>,
+    #[source_code]
+    source_code: NamedSource<String>,
+}
+
+impl UnusedImport {
+    /// Returns the name of the file containing the unused import.
+    pub fn file(&self) -> &str {
+        self.source_code.name()
+    }
+
+    /// Returns the name of the imported file.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the source code span of the `import` statement, if known.
+    pub fn span(&self) -> Option<SourceSpan> {
+        self.span
+    }
+
+    /// Returns the span of source code which should be deleted to remove the import, if known.
+    ///
+    /// If the `import` statement is the only thing on its line, this includes the whole line.
+    pub fn removal_span(&self) -> Option<SourceSpan> {
+        self.removal_span
+    }
+
+    /// Returns a fix which removes the import, if its location is known.
+    pub fn fix(&self) -> Option<Fix> {
+        self.removal_span.map(removal_fix)
+    }
+
+    pub(crate) fn into_parts(
+        self,
+    ) -> (
+        String,
+        Option<SourceSpan>,
+        Option<SourceSpan>,
+        NamedSource<String>,
+    ) {
+        (self.name, self.span, self.removal_span, self.source_code)
+    }
+}
+
+pub(crate) fn removal_fix(span: SourceSpan) -> Fix {
+    Fix::new(
+        "remove unused import",
+        vec![Edit::delete(span.offset()..span.offset() + span.len())],
+    )
+}
+
+impl fmt::Debug for UnusedImport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:", self.file())?;
+        if let Some(span) = &self.span {
+            if let Ok(span_contents) = self.source_code.read_span(span, 0, 0) {
+                write!(
+                    f,
+                    "{}:{}: ",
+                    span_contents.line() + 1,
+                    span_contents.column() + 1
+                )?;
+            }
+        }
+        write!(f, "{}", self)
+    }
+}
+
+/// Finds imports of the given file which are not needed to resolve any type or option name.
+///
+/// Public imports are never reported, since they may be used by files importing this one.
+pub(crate) fn find_unused_imports(
+    file: &FileDescriptor,
+    source: Option<&str>,
+) -> Vec<UnusedImport> {
+    let used = used_files(file);
+
+    let file_proto = file.file_descriptor_proto();
+    let mut unused = Vec::new();
+    for (index, dependency) in file.dependencies().enumerate() {
+        if file_proto.public_dependency.contains(&(index as i32)) {
+            continue;
+        }
+        if public_closure(&dependency)
+            .iter()
+            .any(|name| used.contains(name))
+        {
+            continue;
+        }
+
+        let span = source.and_then(|source| {
+            let location = file_proto
+                .source_code_info
+                .as_ref()?
+                .location
+                .iter()
+                .find(|location| location.path == [3, index as i32])?;
+            location_span(source, &location.span)
+        });
+        let removal_span = source
+            .zip(span)
+            .map(|(source, span)| expand_to_line(source, span));
+
+        unused.push(UnusedImport {
+            name: dependency.name().to_owned(),
+            span,
+            removal_span,
+            source_code: NamedSource::new(file.name(), source.unwrap_or_default().to_owned()),
+        });
+    }
+    unused
+}
+
+/// Gets the names of all files declaring a type or option extension referenced by this file.
+fn used_files(file: &FileDescriptor) -> HashSet<String> {
+    let mut used: HashSet<String> = file_type_references(file)
+        .into_iter()
+        .map(|reference| reference.target.file)
+        .collect();
+
+    let mut add_options = |options: DynamicMessage| {
+        for (extension, _) in options.extensions() {
+            used.insert(extension.parent_file().name().to_owned());
+        }
+    };
+
+    add_options(file.options());
+    let mut messages: Vec<MessageDescriptor> = file.messages().collect();
+    while let Some(message) = messages.pop() {
+        add_options(message.options());
+        message
+            .fields()
+            .for_each(|field| add_options(field.options()));
+        message
+            .oneofs()
+            .for_each(|oneof| add_options(oneof.options()));
+        message
+            .child_extensions()
+            .for_each(|extension| add_options(extension.options()));
+        for enum_ in message.child_enums() {
+            add_options(enum_.options());
+            enum_
+                .values()
+                .for_each(|value| add_options(value.options()));
+        }
+        messages.extend(message.child_messages());
+    }
+    for enum_ in file.enums() {
+        add_options(enum_.options());
+        enum_
+            .values()
+            .for_each(|value| add_options(value.options()));
+    }
+    file.extensions()
+        .for_each(|extension| add_options(extension.options()));
+    for service in file.services() {
+        add_options(service.options());
+        service
+            .methods()
+            .for_each(|method| add_options(method.options()));
+    }
+
+    used
+}
+
+/// Gets the names of a file and all files it transitively imports publicly.
+pub(crate) fn public_closure(file: &FileDescriptor) -> Vec<String> {
+    let mut names = vec![file.name().to_owned()];
+    let mut stack = vec![file.clone()];
+    while let Some(file) = stack.pop() {
+        for dependency in file.public_dependencies() {
+            if !names.iter().any(|name| name == dependency.name()) {
+                names.push(dependency.name().to_owned());
+                stack.push(dependency);
+            }
+        }
+    }
+    names
+}
+
+/// Expands a span to cover its whole line, if the rest of the line is whitespace.
+fn expand_to_line(source: &str, span: SourceSpan) -> SourceSpan {
+    let start = span.offset();
+    let end = start + span.len();
+
+    let line_start = source[..start].rfind('\n').map_or(0, |index| index + 1);
+    let line_end = source[end..]
+        .find('\n')
+        .map_or(source.len(), |index| end + index + 1);
+
+    if source[line_start..start].trim().is_empty() && source[end..line_end].trim().is_empty() {
+        SourceSpan::new(line_start.into(), line_end - line_start)
+    } else {
+        span
+    }
+}
@@ -0,0 +1,1080 @@
+use std::{
+    collections::HashMap,
+    fmt::{self, Write},
+    ops::Range,
+};
+
+use prost_reflect::{DynamicMessage, FileDescriptor, Kind, ReflectMessage, Value};
+use prost_types::{
+    field_descriptor_proto::{Label, Type},
+    source_code_info::Location,
+    DescriptorProto, EnumDescriptorProto, FieldDescriptorProto, FileDescriptorProto,
+    ServiceDescriptorProto,
+};
+use protox_parse::{case::to_json_name, tag};
+
+const INDENT: &str = "  ";
+
+/// The largest field number, which is written as `max` in reserved and extension ranges.
+const MAX_FIELD_NUMBER: i32 = 536_870_911;
+
+/// Renders a file descriptor as protobuf source text.
+///
+/// The file should be taken from a pool built by a [`Compiler`](crate::Compiler), so that type names are resolved and
+/// options are interpreted.
+///
+/// The output is valid `.proto` source which compiles to an equivalent descriptor. All options are written out,
+/// including custom options whose extensions are defined in the file's pool. If the descriptor includes source code
+/// info, its comments are attached to the corresponding elements.
+///
+/// Type names are written in their fully-qualified form, such as `.foo.Bar`, so the output does not depend on the
+/// scoping rules used to resolve relative names.
+///
+/// # Examples
+///
+/// ```
+/// let set = protox::compile_str("foo.proto", "
+///     syntax = 'proto3';
+///     package foo;
+///
+///     // A message.
+///     message Foo {
+///         repeated Foo children = 1 [deprecated = true];
+///         map<string, int32> counts = 2;
+///     }
+/// ", [] as [&str; 0]).unwrap();
+/// let pool = protox::prost_reflect::DescriptorPool::from_file_descriptor_set(set).unwrap();
+///
+/// assert_eq!(protox::decompile(&pool.get_file_by_name("foo.proto").unwrap()), r#"syntax = "proto3";
+///
+/// package foo;
+///
+/// // A message.
+/// message Foo {
+///   repeated .foo.Foo children = 1 [deprecated = true];
+///   map<string, int32> counts = 2;
+/// }
+/// "#);
+/// ```
+pub fn decompile(file: &FileDescriptor) -> String {
+    decompile_with_options(file.file_descriptor_proto(), option_messages(file))
+}
+
+/// Renders a file descriptor as protobuf source text. Custom options are not preserved by [`FileDescriptorProto`], so
+/// only standard options are written.
+pub(crate) fn decompile_proto(file: &FileDescriptorProto) -> String {
+    decompile_with_options(file, HashMap::new())
+}
+
+fn decompile_with_options(
+    file: &FileDescriptorProto,
+    options: HashMap<Vec<i32>, DynamicMessage>,
+) -> String {
+    let mut writer = Writer::new(file, options);
+    writer
+        .write_file(file)
+        .expect("writing to a string cannot fail");
+    writer.out
+}
+
+struct Writer<'a> {
+    out: String,
+    locations: HashMap<Vec<i32>, Vec<&'a Location>>,
+    /// The decoded options of each element, including extension options, keyed by the path of the element.
+    options: HashMap<Vec<i32>, DynamicMessage>,
+    path: Vec<i32>,
+    depth: usize,
+    syntax: &'a str,
+}
+
+impl<'a> Writer<'a> {
+    fn new(file: &'a FileDescriptorProto, options: HashMap<Vec<i32>, DynamicMessage>) -> Self {
+        let mut locations: HashMap<Vec<i32>, Vec<&Location>> = HashMap::new();
+        for location in file.source_code_info.iter().flat_map(|info| &info.location) {
+            locations
+                .entry(location.path.clone())
+                .or_default()
+                .push(location);
+        }
+
+        Writer {
+            out: String::new(),
+            locations,
+            options,
+            path: Vec::new(),
+            depth: 0,
+            syntax: file.syntax(),
+        }
+    }
+
+    fn write_file(&mut self, file: &'a FileDescriptorProto) -> fmt::Result {
+        self.path.push(tag::file::SYNTAX);
+        self.write_leading_comments()?;
+        match file.syntax() {
+            "" => write!(self.out, "syntax = \"proto2\";")?,
+            syntax => write!(self.out, "syntax = {};", quote(syntax))?,
+        }
+        self.write_trailing_comments()?;
+        self.path.pop();
+
+        if let Some(package) = &file.package {
+            self.out.push('\n');
+            self.path.push(tag::file::PACKAGE);
+            self.write_leading_comments()?;
+            write!(self.out, "package {};", package)?;
+            self.write_trailing_comments()?;
+            self.path.pop();
+        }
+
+        if !file.dependency.is_empty() {
+            self.out.push('\n');
+            for (index, dependency) in file.dependency.iter().enumerate() {
+                let modifier = if file.public_dependency.contains(&(index as i32)) {
+                    "public "
+                } else if file.weak_dependency.contains(&(index as i32)) {
+                    "weak "
+                } else {
+                    ""
+                };
+
+                self.path.extend([tag::file::DEPENDENCY, index as i32]);
+                self.write_leading_comments()?;
+                self.write_indent()?;
+                write!(self.out, "import {}{};", modifier, quote(dependency))?;
+                self.write_trailing_comments()?;
+                self.path.truncate(self.path.len() - 2);
+            }
+        }
+
+        if let Some(options) = &file.options {
+            let options = self.option_values(&[], options);
+            if !options.is_empty() {
+                self.out.push('\n');
+                self.write_options(tag::file::OPTIONS, &options)?;
+            }
+        }
+
+        // Extension groups are declared by their extend block, so blocks are written as late as possible before the
+        // next group to preserve the order of messages.
+        let synthetic = synthetic_messages(&[], &file.extension, &file.message_type);
+        let mut blocks = self
+            .extension_blocks(tag::file::EXTENSION, &file.extension)
+            .into_iter()
+            .peekable();
+        for (index, message) in file.message_type.iter().enumerate() {
+            if let Some(&Declaration::Extension(extension)) = synthetic.get(message.name()) {
+                while let Some((location, block)) =
+                    blocks.next_if(|(_, block)| block.start <= extension)
+                {
+                    self.out.push('\n');
+                    self.write_extension_block(
+                        tag::file::EXTENSION,
+                        location,
+                        block,
+                        &file.extension,
+                        &file.message_type,
+                    )?;
+                }
+            } else {
+                self.out.push('\n');
+                self.path.extend([tag::file::MESSAGE_TYPE, index as i32]);
+                self.write_message(message, None)?;
+                self.path.truncate(self.path.len() - 2);
+            }
+        }
+        for (location, block) in blocks {
+            self.out.push('\n');
+            self.write_extension_block(
+                tag::file::EXTENSION,
+                location,
+                block,
+                &file.extension,
+                &file.message_type,
+            )?;
+        }
+
+        for (index, enum_) in file.enum_type.iter().enumerate() {
+            self.out.push('\n');
+            self.path.extend([tag::file::ENUM_TYPE, index as i32]);
+            self.write_enum(enum_)?;
+            self.path.truncate(self.path.len() - 2);
+        }
+
+        for (index, service) in file.service.iter().enumerate() {
+            self.out.push('\n');
+            self.path.extend([tag::file::SERVICE, index as i32]);
+            self.write_service(service)?;
+            self.path.truncate(self.path.len() - 2);
+        }
+
+        Ok(())
+    }
+
+    /// Writes a message definition. If `group` is set, the message is written as the body of a group field.
+    fn write_message(
+        &mut self,
+        message: &'a DescriptorProto,
+        group: Option<(&'a FieldDescriptorProto, Vec<i32>)>,
+    ) -> fmt::Result {
+        if let Some((field, field_path)) = group {
+            self.write_field_start(field, &[], &field_path)?;
+            write!(self.out, " {{")?;
+        } else {
+            self.write_leading_comments()?;
+            self.write_indent()?;
+            write!(self.out, "message {} {{", message.name())?;
+        }
+        self.depth += 1;
+        self.write_trailing_comments()?;
+
+        if let Some(options) = &message.options {
+            let options = self.option_values(&self.path, options);
+            self.write_options(tag::message::OPTIONS, &options)?;
+        }
+
+        // Map entries and groups are declared by their field or extend block. To preserve the order of both fields
+        // and nested messages, fields and extensions are written as late as possible before the next nested message.
+        let synthetic =
+            synthetic_messages(&message.field, &message.extension, &message.nested_type);
+        let mut next_field = 0;
+        let mut blocks = self
+            .extension_blocks(tag::message::EXTENSION, &message.extension)
+            .into_iter()
+            .peekable();
+        for (index, nested) in message.nested_type.iter().enumerate() {
+            match synthetic.get(nested.name()) {
+                Some(&Declaration::Field(field)) => {
+                    while next_field <= field {
+                        next_field = self.write_member(message, next_field)?;
+                    }
+                }
+                Some(&Declaration::Extension(extension)) => {
+                    while let Some((location, block)) =
+                        blocks.next_if(|(_, block)| block.start <= extension)
+                    {
+                        self.write_extension_block(
+                            tag::message::EXTENSION,
+                            location,
+                            block,
+                            &message.extension,
+                            &message.nested_type,
+                        )?;
+                    }
+                }
+                None => {
+                    self.path.extend([tag::message::NESTED_TYPE, index as i32]);
+                    self.write_message(nested, None)?;
+                    self.path.truncate(self.path.len() - 2);
+                }
+            }
+        }
+        while next_field < message.field.len() {
+            next_field = self.write_member(message, next_field)?;
+        }
+
+        for (index, enum_) in message.enum_type.iter().enumerate() {
+            self.path.extend([tag::message::ENUM_TYPE, index as i32]);
+            self.write_enum(enum_)?;
+            self.path.truncate(self.path.len() - 2);
+        }
+
+        for (location, block) in blocks {
+            self.write_extension_block(
+                tag::message::EXTENSION,
+                location,
+                block,
+                &message.extension,
+                &message.nested_type,
+            )?;
+        }
+
+        // The `max` keyword has a larger value in message sets.
+        let max = if message
+            .options
+            .as_ref()
+            .is_some_and(|options| options.message_set_wire_format())
+        {
+            i32::MAX - 1
+        } else {
+            MAX_FIELD_NUMBER
+        };
+        let ranges = &message.extension_range;
+        for (location, statement) in
+            self.statements(tag::message::EXTENSION_RANGE, ranges.len(), false)
+        {
+            let items: Vec<String> = ranges[statement.clone()]
+                .iter()
+                .map(|range| format_range(range.start(), range.end() - 1, max))
+                .collect();
+            self.write_leading_comments_for(location)?;
+            self.write_indent()?;
+            write!(self.out, "extensions {}", items.join(", "))?;
+            if let Some(options) = &ranges[statement.start].options {
+                let path = [
+                    &self.path[..],
+                    &[tag::message::EXTENSION_RANGE, statement.start as i32],
+                ]
+                .concat();
+                let options = self.option_values(&path, options);
+                self.write_compact_options(&options)?;
+            }
+            self.out.push(';');
+            self.write_trailing_comments_for(location)?;
+        }
+
+        let ranges: Vec<String> = message
+            .reserved_range
+            .iter()
+            .map(|range| format_range(range.start(), range.end() - 1, max))
+            .collect();
+        self.write_reserved(tag::message::RESERVED_RANGE, &ranges)?;
+        let names: Vec<String> = message
+            .reserved_name
+            .iter()
+            .map(|name| quote(name))
+            .collect();
+        self.write_reserved(tag::message::RESERVED_NAME, &names)?;
+
+        self.depth -= 1;
+        self.write_line(format_args!("}}"))
+    }
+
+    /// Writes the field at `index`, or the oneof containing it, and returns the index of the next field to write.
+    fn write_member(
+        &mut self,
+        message: &'a DescriptorProto,
+        index: usize,
+    ) -> Result<usize, fmt::Error> {
+        let field = &message.field[index];
+        match field.oneof_index {
+            Some(oneof_index) if !field.proto3_optional() => {
+                let oneof = &message.oneof_decl[oneof_index as usize];
+                self.path.extend([tag::message::ONEOF_DECL, oneof_index]);
+                self.write_leading_comments()?;
+                self.write_indent()?;
+                write!(self.out, "oneof {} {{", oneof.name())?;
+                self.depth += 1;
+                self.write_trailing_comments()?;
+                if let Some(options) = &oneof.options {
+                    let options = self.option_values(&self.path, options);
+                    self.write_options(tag::oneof::OPTIONS, &options)?;
+                }
+                self.path.truncate(self.path.len() - 2);
+
+                let mut end = index;
+                while end < message.field.len()
+                    && message.field[end].oneof_index == Some(oneof_index)
+                {
+                    self.path.extend([tag::message::FIELD, end as i32]);
+                    self.write_field(&message.field[end], &message.nested_type)?;
+                    self.path.truncate(self.path.len() - 2);
+                    end += 1;
+                }
+                self.depth -= 1;
+                self.write_line(format_args!("}}"))?;
+                Ok(end)
+            }
+            _ => {
+                self.path.extend([tag::message::FIELD, index as i32]);
+                self.write_field(field, &message.nested_type)?;
+                self.path.truncate(self.path.len() - 2);
+                Ok(index + 1)
+            }
+        }
+    }
+
+    /// Splits extensions into the extend blocks which declared them.
+    fn extension_blocks(
+        &mut self,
+        tag: i32,
+        extensions: &[FieldDescriptorProto],
+    ) -> Vec<(Option<&'a Location>, Range<usize>)> {
+        let mut blocks = Vec::new();
+        for (location, statement) in self.statements(tag, extensions.len(), true) {
+            let mut start = statement.start;
+            for index in statement.clone() {
+                if extensions[index].extendee() != extensions[start].extendee() {
+                    blocks.push((location, start..index));
+                    start = index;
+                }
+            }
+            blocks.push((location, start..statement.end));
+        }
+        blocks
+    }
+
+    fn write_extension_block(
+        &mut self,
+        tag: i32,
+        location: Option<&'a Location>,
+        block: Range<usize>,
+        extensions: &'a [FieldDescriptorProto],
+        scope: &'a [DescriptorProto],
+    ) -> fmt::Result {
+        self.write_leading_comments_for(location)?;
+        self.write_indent()?;
+        write!(self.out, "extend {} {{", extensions[block.start].extendee())?;
+        self.depth += 1;
+        self.write_trailing_comments_for(location)?;
+        for index in block {
+            self.path.extend([tag, index as i32]);
+            self.write_field(&extensions[index], scope)?;
+            self.path.truncate(self.path.len() - 2);
+        }
+        self.depth -= 1;
+        self.write_line(format_args!("}}"))
+    }
+
+    /// Writes a field, looking up group and map entry types in `scope`.
+    fn write_field(
+        &mut self,
+        field: &'a FieldDescriptorProto,
+        scope: &'a [DescriptorProto],
+    ) -> fmt::Result {
+        if field.r#type() == Type::Group {
+            if let Some(index) = find_nested_index(scope, field.type_name()) {
+                // Comments for a group are attached to its message, rather than the field.
+                let group_field_path = self.path.clone();
+                let field_path = self.path.split_off(self.path.len() - 2);
+                let tag = if self.path.is_empty() {
+                    tag::file::MESSAGE_TYPE
+                } else {
+                    tag::message::NESTED_TYPE
+                };
+                self.path.extend([tag, index as i32]);
+                self.write_message(&scope[index], Some((field, group_field_path)))?;
+                self.path.truncate(self.path.len() - 2);
+                self.path.extend(field_path);
+                return Ok(());
+            }
+        }
+
+        let path = self.path.clone();
+        self.write_field_start(field, scope, &path)?;
+        self.out.push(';');
+        self.write_trailing_comments()?;
+        Ok(())
+    }
+
+    /// Writes a field declaration up to its options. `path` is the path of the field, which differs from the current
+    /// path for groups.
+    fn write_field_start(
+        &mut self,
+        field: &'a FieldDescriptorProto,
+        scope: &'a [DescriptorProto],
+        path: &[i32],
+    ) -> fmt::Result {
+        self.write_leading_comments()?;
+        self.write_indent()?;
+
+        let map_entry = find_nested(scope, field.type_name())
+            .filter(|entry| entry.options.as_ref().is_some_and(|o| o.map_entry()));
+        if let Some(entry) = map_entry {
+            write!(
+                self.out,
+                "map<{}, {}>",
+                field_type(&entry.field[0]),
+                field_type(&entry.field[1])
+            )?;
+        } else {
+            match field.label() {
+                Label::Repeated => self.out.push_str("repeated "),
+                Label::Required => self.out.push_str("required "),
+                Label::Optional
+                    if self.syntax == "proto3" && field.proto3_optional()
+                        || (self.syntax == "proto2" || self.syntax.is_empty())
+                            && field.oneof_index.is_none() =>
+                {
+                    self.out.push_str("optional ")
+                }
+                Label::Optional => (),
+            }
+            if field.r#type() == Type::Group {
+                self.out.push_str("group");
+            } else {
+                self.out.push_str(&field_type(field));
+            }
+        }
+
+        let name = if field.r#type() == Type::Group {
+            field.type_name().rsplit('.').next().unwrap_or_default()
+        } else {
+            field.name()
+        };
+        write!(self.out, " {} = {}", name, field.number())?;
+
+        let mut options = Vec::new();
+        if let Some(value) = format_default_value(field) {
+            options.push(OptionValue {
+                path: Vec::new(),
+                name: "default".to_owned(),
+                value,
+            });
+        }
+        if let Some(json_name) = &field.json_name {
+            if field.extendee.is_none() && *json_name != to_json_name(field.name()) {
+                options.push(OptionValue {
+                    path: Vec::new(),
+                    name: "json_name".to_owned(),
+                    value: quote(json_name),
+                });
+            }
+        }
+        if let Some(field_options) = &field.options {
+            options.extend(self.option_values(path, field_options));
+        }
+        self.write_compact_options(&options)
+    }
+
+    fn write_enum(&mut self, enum_: &'a EnumDescriptorProto) -> fmt::Result {
+        self.write_leading_comments()?;
+        self.write_indent()?;
+        write!(self.out, "enum {} {{", enum_.name())?;
+        self.depth += 1;
+        self.write_trailing_comments()?;
+
+        if let Some(options) = &enum_.options {
+            let options = self.option_values(&self.path, options);
+            self.write_options(tag::enum_::OPTIONS, &options)?;
+        }
+
+        for (index, value) in enum_.value.iter().enumerate() {
+            self.path.extend([tag::enum_::VALUE, index as i32]);
+            self.write_leading_comments()?;
+            self.write_indent()?;
+            write!(self.out, "{} = {}", value.name(), value.number())?;
+            if let Some(options) = &value.options {
+                let options = self.option_values(&self.path, options);
+                self.write_compact_options(&options)?;
+            }
+            self.out.push(';');
+            self.write_trailing_comments()?;
+            self.path.truncate(self.path.len() - 2);
+        }
+
+        let ranges: Vec<String> = enum_
+            .reserved_range
+            .iter()
+            .map(|range| format_range(range.start(), range.end(), i32::MAX))
+            .collect();
+        self.write_reserved(tag::enum_::RESERVED_RANGE, &ranges)?;
+        let names: Vec<String> = enum_.reserved_name.iter().map(|name| quote(name)).collect();
+        self.write_reserved(tag::enum_::RESERVED_NAME, &names)?;
+
+        self.depth -= 1;
+        self.write_line(format_args!("}}"))
+    }
+
+    fn write_service(&mut self, service: &'a ServiceDescriptorProto) -> fmt::Result {
+        self.write_leading_comments()?;
+        self.write_indent()?;
+        write!(self.out, "service {} {{", service.name())?;
+        self.depth += 1;
+        self.write_trailing_comments()?;
+
+        if let Some(options) = &service.options {
+            let options = self.option_values(&self.path, options);
+            self.write_options(tag::service::OPTIONS, &options)?;
+        }
+
+        for (index, method) in service.method.iter().enumerate() {
+            self.path.extend([tag::service::METHOD, index as i32]);
+            self.write_leading_comments()?;
+            self.write_indent()?;
+            write!(
+                self.out,
+                "rpc {}({}{}) returns ({}{})",
+                method.name(),
+                if method.client_streaming() {
+                    "stream "
+                } else {
+                    ""
+                },
+                method.input_type(),
+                if method.server_streaming() {
+                    "stream "
+                } else {
+                    ""
+                },
+                method.output_type(),
+            )?;
+            match &method.options {
+                Some(options) => {
+                    self.out.push_str(" {");
+                    self.depth += 1;
+                    self.write_trailing_comments()?;
+                    let options = self.option_values(&self.path, options);
+                    self.write_options(tag::method::OPTIONS, &options)?;
+                    self.depth -= 1;
+                    self.write_line(format_args!("}}"))?;
+                }
+                None => {
+                    self.out.push(';');
+                    self.write_trailing_comments()?;
+                }
+            }
+            self.path.truncate(self.path.len() - 2);
+        }
+
+        self.depth -= 1;
+        self.write_line(format_args!("}}"))
+    }
+
+    fn write_reserved(&mut self, tag: i32, items: &[String]) -> fmt::Result {
+        for (location, statement) in self.statements(tag, items.len(), true) {
+            self.write_leading_comments_for(location)?;
+            self.write_indent()?;
+            write!(self.out, "reserved {};", items[statement].join(", "))?;
+            self.write_trailing_comments_for(location)?;
+        }
+        Ok(())
+    }
+
+    /// Splits the elements of a repeated field of the current element into the statements which declared them.
+    ///
+    /// Each statement has a location whose path is that of the field, and whose span contains the locations of its
+    /// elements. Without source code info, all elements are written in one statement if `merge` is set, and each in
+    /// its own statement otherwise.
+    fn statements(
+        &mut self,
+        tag: i32,
+        count: usize,
+        merge: bool,
+    ) -> Vec<(Option<&'a Location>, Range<usize>)> {
+        self.path.push(tag);
+        let statements = self.locations.get(&self.path).cloned().unwrap_or_default();
+
+        let mut result: Vec<(Option<usize>, Range<usize>)> = Vec::new();
+        for index in 0..count {
+            self.path.push(index as i32);
+            let statement = self
+                .location()
+                .and_then(|location| span_start(&location.span))
+                .and_then(|start| {
+                    statements
+                        .iter()
+                        .position(|statement| span_contains(&statement.span, start))
+                });
+            self.path.pop();
+
+            match result.last_mut() {
+                Some((last, range)) if *last == statement && (statement.is_some() || merge) => {
+                    range.end = index + 1;
+                }
+                _ => result.push((statement, index..index + 1)),
+            }
+        }
+        self.path.pop();
+
+        result
+            .into_iter()
+            .map(|(statement, range)| (statement.map(|index| statements[index]), range))
+            .collect()
+    }
+
+    /// Gets the options of the element at `path`, falling back to the standard options in the descriptor.
+    fn option_values<T: ReflectMessage>(&self, path: &[i32], options: &T) -> Vec<OptionValue> {
+        match self.options.get(path) {
+            Some(options) => format_options(options),
+            None => format_options(&options.transcode_to_dynamic()),
+        }
+    }
+
+    /// Writes option statements with their comments. `tag` is the field number of the options in the current element.
+    fn write_options(&mut self, tag: i32, options: &[OptionValue]) -> fmt::Result {
+        for option in options {
+            let len = self.path.len();
+            self.path.push(tag);
+            self.path.extend(&option.path);
+            self.write_leading_comments()?;
+            self.write_indent()?;
+            write!(self.out, "option {} = {};", option.name, option.value)?;
+            self.write_trailing_comments()?;
+            self.path.truncate(len);
+        }
+        Ok(())
+    }
+
+    fn write_compact_options(&mut self, options: &[OptionValue]) -> fmt::Result {
+        if !options.is_empty() {
+            let options: Vec<String> = options
+                .iter()
+                .map(|option| format!("{} = {}", option.name, option.value))
+                .collect();
+            write!(self.out, " [{}]", options.join(", "))?;
+        }
+        Ok(())
+    }
+
+    fn location(&self) -> Option<&'a Location> {
+        self.locations
+            .get(&self.path)
+            .and_then(|locations| locations.first().copied())
+    }
+
+    fn write_leading_comments(&mut self) -> fmt::Result {
+        self.write_leading_comments_for(self.location())
+    }
+
+    fn write_leading_comments_for(&mut self, location: Option<&'a Location>) -> fmt::Result {
+        let Some(location) = location else {
+            return Ok(());
+        };
+
+        for comment in &location.leading_detached_comments {
+            self.write_comment(comment)?;
+            self.out.push('\n');
+        }
+        if let Some(comment) = &location.leading_comments {
+            self.write_comment(comment)?;
+        }
+        Ok(())
+    }
+
+    /// Finishes the current line, and writes the trailing comments for the current element.
+    ///
+    /// A single-line comment is written on the same line. Otherwise, it is written on the following lines and
+    /// terminated by a blank line, so it is not attached to the next element instead.
+    fn write_trailing_comments(&mut self) -> fmt::Result {
+        self.write_trailing_comments_for(self.location())
+    }
+
+    fn write_trailing_comments_for(&mut self, location: Option<&'a Location>) -> fmt::Result {
+        let comment = location.and_then(|location| location.trailing_comments.as_deref());
+        match comment {
+            Some(comment) if !comment.trim_end_matches('\n').contains('\n') => {
+                self.out.push(' ');
+                self.write_comment_text(comment)?;
+                self.out.push('\n');
+                Ok(())
+            }
+            Some(comment) => {
+                self.out.push('\n');
+                self.write_comment(comment)?;
+                self.out.push('\n');
+                Ok(())
+            }
+            None => {
+                self.out.push('\n');
+                Ok(())
+            }
+        }
+    }
+
+    fn write_comment(&mut self, comment: &str) -> fmt::Result {
+        if comment.ends_with('\n') {
+            for line in comment_lines(comment) {
+                self.write_indent()?;
+                writeln!(self.out, "//{}", line)?;
+            }
+        } else {
+            self.write_indent()?;
+            self.write_comment_text(comment)?;
+            self.out.push('\n');
+        }
+        Ok(())
+    }
+
+    /// Writes a comment without indentation or a final newline. Comments ending with a newline were written as line
+    /// comments, otherwise they were block comments.
+    fn write_comment_text(&mut self, comment: &str) -> fmt::Result {
+        match comment.strip_suffix('\n') {
+            Some(line) => write!(self.out, "//{}", line),
+            None => write!(self.out, "/*{}*/", comment),
+        }
+    }
+
+    fn write_line(&mut self, line: fmt::Arguments) -> fmt::Result {
+        self.write_indent()?;
+        self.out.write_fmt(line)?;
+        self.out.push('\n');
+        Ok(())
+    }
+
+    fn write_indent(&mut self) -> fmt::Result {
+        for _ in 0..self.depth {
+            self.out.push_str(INDENT);
+        }
+        Ok(())
+    }
+}
+
+/// Gets the start position of a span, as a line and column.
+fn span_start(span: &[i32]) -> Option<(i32, i32)> {
+    match *span {
+        [line, col, ..] => Some((line, col)),
+        _ => None,
+    }
+}
+
+/// Returns whether a span contains the given position.
+fn span_contains(span: &[i32], position: (i32, i32)) -> bool {
+    let (start, end) = match *span {
+        [line, start_col, end_col] => ((line, start_col), (line, end_col)),
+        [start_line, start_col, end_line, end_col] => {
+            ((start_line, start_col), (end_line, end_col))
+        }
+        _ => return false,
+    };
+    start <= position && position < end
+}
+
+fn comment_lines(comment: &str) -> std::str::Split<'_, char> {
+    comment.strip_suffix('\n').unwrap_or(comment).split('\n')
+}
+
+/// The field or extension which declares a map entry or group message.
+enum Declaration {
+    Field(usize),
+    Extension(usize),
+}
+
+/// Gets the names of messages in `scope` which are declared by a map field or group, and the index of that field.
+fn synthetic_messages<'a>(
+    fields: &[FieldDescriptorProto],
+    extensions: &[FieldDescriptorProto],
+    scope: &'a [DescriptorProto],
+) -> HashMap<&'a str, Declaration> {
+    let mut synthetic = HashMap::new();
+    for (index, field) in fields.iter().enumerate() {
+        if let Some(nested) = find_nested(scope, field.type_name()) {
+            let is_map_entry = nested.options.as_ref().is_some_and(|o| o.map_entry());
+            if field.r#type() == Type::Group || is_map_entry {
+                synthetic.insert(nested.name(), Declaration::Field(index));
+            }
+        }
+    }
+    for (index, extension) in extensions.iter().enumerate() {
+        if extension.r#type() == Type::Group {
+            if let Some(nested) = find_nested(scope, extension.type_name()) {
+                synthetic.insert(nested.name(), Declaration::Extension(index));
+            }
+        }
+    }
+    synthetic
+}
+
+/// Finds a message in `scope` whose name is the last component of `type_name`.
+fn find_nested<'a>(scope: &'a [DescriptorProto], type_name: &str) -> Option<&'a DescriptorProto> {
+    find_nested_index(scope, type_name).map(|index| &scope[index])
+}
+
+fn find_nested_index(scope: &[DescriptorProto], type_name: &str) -> Option<usize> {
+    let (_, name) = type_name.rsplit_once('.')?;
+    scope.iter().position(|message| message.name() == name)
+}
+
+fn field_type(field: &FieldDescriptorProto) -> String {
+    match field.r#type() {
+        Type::Double => "double".to_owned(),
+        Type::Float => "float".to_owned(),
+        Type::Int64 => "int64".to_owned(),
+        Type::Uint64 => "uint64".to_owned(),
+        Type::Int32 => "int32".to_owned(),
+        Type::Fixed64 => "fixed64".to_owned(),
+        Type::Fixed32 => "fixed32".to_owned(),
+        Type::Bool => "bool".to_owned(),
+        Type::String => "string".to_owned(),
+        Type::Bytes => "bytes".to_owned(),
+        Type::Uint32 => "uint32".to_owned(),
+        Type::Sfixed32 => "sfixed32".to_owned(),
+        Type::Sfixed64 => "sfixed64".to_owned(),
+        Type::Sint32 => "sint32".to_owned(),
+        Type::Sint64 => "sint64".to_owned(),
+        Type::Group | Type::Message | Type::Enum => field.type_name().to_owned(),
+    }
+}
+
+/// Formats the default value of a field as it is written in source, if it has one.
+pub(crate) fn format_default_value(field: &FieldDescriptorProto) -> Option<String> {
+    let default_value = field.default_value.as_ref()?;
+    Some(match field.r#type() {
+        Type::String => quote(default_value),
+        Type::Bytes => format!("\"{}\"", default_value),
+        _ => default_value.clone(),
+    })
+}
+
+fn format_range(start: i32, end: i32, max: i32) -> String {
+    if start == end {
+        start.to_string()
+    } else if end == max {
+        format!("{} to max", start)
+    } else {
+        format!("{} to {}", start, end)
+    }
+}
+
+/// Decodes the options of every element in a file, including extension options, keyed by the path of the element.
+///
+/// The options can only be decoded if the file's pool includes `google/protobuf/descriptor.proto`, which is always the
+/// case for files using custom options.
+fn option_messages(file: &FileDescriptor) -> HashMap<Vec<i32>, DynamicMessage> {
+    let mut options = HashMap::new();
+    let desc = match file
+        .parent_pool()
+        .get_message_by_name("google.protobuf.FileDescriptorProto")
+    {
+        Some(desc) => desc,
+        None => return options,
+    };
+    if let Ok(message) = DynamicMessage::decode(desc, file.encode_to_vec().as_slice()) {
+        add_option_messages(&message, &mut Vec::new(), &mut options);
+    }
+    options
+}
+
+fn add_option_messages(
+    message: &DynamicMessage,
+    path: &mut Vec<i32>,
+    options: &mut HashMap<Vec<i32>, DynamicMessage>,
+) {
+    for (field, value) in message.fields() {
+        if field.name() == "source_code_info" {
+            continue;
+        }
+
+        match value {
+            Value::Message(value) if field.name() == "options" => {
+                options.insert(path.clone(), value.clone());
+            }
+            Value::Message(value) => {
+                path.push(field.number() as i32);
+                add_option_messages(value, path, options);
+                path.pop();
+            }
+            Value::List(values) => {
+                for (index, value) in values.iter().enumerate() {
+                    if let Value::Message(value) = value {
+                        path.extend([field.number() as i32, index as i32]);
+                        add_option_messages(value, path, options);
+                        path.truncate(path.len() - 2);
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+/// An option set in an options message.
+struct OptionValue {
+    /// The path of the option within the options message, used to find its comments.
+    path: Vec<i32>,
+    name: String,
+    value: String,
+}
+
+/// Gets the name and formatted value of each option set in an options message, including extension options.
+fn format_options(options: &DynamicMessage) -> Vec<OptionValue> {
+    let fields = options
+        .fields()
+        .filter(|(field, _)| field.name() != "uninterpreted_option")
+        .map(|(field, value)| (field.number(), field.name().to_owned(), field.kind(), value));
+    let extensions = options.extensions().map(|(extension, value)| {
+        (
+            extension.number(),
+            format!("({})", extension.full_name()),
+            extension.kind(),
+            value,
+        )
+    });
+
+    let mut result = Vec::new();
+    for (number, name, kind, value) in fields.chain(extensions) {
+        match value {
+            Value::List(values) => {
+                for (index, value) in values.iter().enumerate() {
+                    result.push(OptionValue {
+                        path: vec![number as i32, index as i32],
+                        name: name.clone(),
+                        value: format_value(&kind, value),
+                    });
+                }
+            }
+            value => result.push(OptionValue {
+                path: vec![number as i32],
+                name,
+                value: format_value(&kind, value),
+            }),
+        }
+    }
+    result
+}
+
+fn format_value(kind: &Kind, value: &Value) -> String {
+    match value {
+        Value::Bool(value) => value.to_string(),
+        Value::I32(value) => value.to_string(),
+        Value::I64(value) => value.to_string(),
+        Value::U32(value) => value.to_string(),
+        Value::U64(value) => value.to_string(),
+        Value::F32(value) => format_float((*value).into()),
+        Value::F64(value) => format_float(*value),
+        Value::String(value) => quote(value),
+        Value::Bytes(value) => quote_bytes(value),
+        Value::EnumNumber(number) => match kind.as_enum().and_then(|e| e.get_value(*number)) {
+            Some(value) => value.name().to_owned(),
+            None => number.to_string(),
+        },
+        Value::Message(message) => format_message(message),
+        Value::List(_) | Value::Map(_) => unreachable!("options cannot be lists or maps"),
+    }
+}
+
+fn format_message(message: &DynamicMessage) -> String {
+    let text = message.to_text_format();
+    if text.is_empty() {
+        "{}".to_owned()
+    } else {
+        format!("{{ {} }}", text)
+    }
+}
+
+fn format_float(value: f64) -> String {
+    if value.is_nan() {
+        "nan".to_owned()
+    } else if value.is_infinite() {
+        if value > 0.0 { "inf" } else { "-inf" }.to_owned()
+    } else {
+        value.to_string()
+    }
+}
+
+/// Formats a string as a protobuf string literal.
+fn quote(value: &str) -> String {
+    let mut result = String::with_capacity(value.len() + 2);
+    result.push('"');
+    for ch in value.chars() {
+        match ch {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            '\n' => result.push_str("\\n"),
+            '\r' => result.push_str("\\r"),
+            '\t' => result.push_str("\\t"),
+            ch if ch.is_ascii_control() => write!(result, "\\{:03o}", ch as u8).unwrap(),
+            ch => result.push(ch),
+        }
+    }
+    result.push('"');
+    result
+}
+
+/// Formats bytes as a protobuf string literal, escaping any non-printable bytes.
+fn quote_bytes(value: &[u8]) -> String {
+    let mut result = String::with_capacity(value.len() + 2);
+    result.push('"');
+    for &byte in value {
+        match byte {
+            b'"' => result.push_str("\\\""),
+            b'\\' => result.push_str("\\\\"),
+            b'\n' => result.push_str("\\n"),
+            b'\r' => result.push_str("\\r"),
+            b'\t' => result.push_str("\\t"),
+            0x20..=0x7e => result.push(byte as char),
+            _ => write!(result, "\\{:03o}", byte).unwrap(),
+        }
+    }
+    result.push('"');
+    result
+}
@@ -0,0 +1,121 @@
+//! Helpers for embedding compiled file descriptor sets in a crate.
+//!
+//! A build script compiles the protobuf files and writes the encoded file descriptor set to `OUT_DIR` with
+//! [`compile_to_out_dir`], or [`write_to_out_dir`] for a [`Compiler`] with custom options. The crate then embeds
+//! the output with [`include_file_descriptor_set!`](crate::include_file_descriptor_set), or decodes it into a
+//! [`DescriptorPool`](prost_reflect::DescriptorPool) on first use with
+//! [`include_descriptor_pool!`](crate::include_descriptor_pool).
+//!
+//! # Examples
+//!
+//! In `build.rs`:
+//!
+//! ```no_run
+//! fn main() -> Result<(), protox::Error> {
+//!     protox::embed::compile_to_out_dir(["root.proto"], ["."], "file_descriptor_set.bin")?;
+//!     Ok(())
+//! }
+//! ```
+//!
+//! In the crate:
+//!
+//! ```ignore
+//! let pool = protox::include_descriptor_pool!("file_descriptor_set.bin");
+//! let message = pool.get_message_by_name("package.Message").unwrap();
+//! ```
+
+use std::{
+    env,
+    path::{Path, PathBuf},
+};
+
+use crate::{Compiler, Error};
+
+/// Compiles a set of protobuf files, and writes the encoded file descriptor set to `file_name` in `OUT_DIR`.
+///
+/// Imported files are included in the output, but source code info is not. See [`write_to_out_dir`] for details of
+/// how the file is written. Returns the path of the written file.
+///
+/// # Errors
+///
+/// Returns an error if the files fail to compile, if the `OUT_DIR` environment variable is not set, or if the
+/// output cannot be written.
+pub fn compile_to_out_dir(
+    files: impl IntoIterator<Item = impl AsRef<Path>>,
+    includes: impl IntoIterator<Item = impl AsRef<Path>>,
+    file_name: impl AsRef<Path>,
+) -> Result<PathBuf, Error> {
+    let mut compiler = Compiler::new(includes)?;
+    compiler.include_imports(true).open_files(files)?;
+    write_to_out_dir(&compiler, file_name)
+}
+
+/// Writes the encoded file descriptor set of a compiler to `file_name` in `OUT_DIR`.
+///
+/// The output is the same as [`Compiler::write_file_descriptor_set`], and is written atomically. For each file read
+/// from the file system, a `cargo:rerun-if-changed` instruction is printed, so the build script is only run again when
+/// one of them changes. Returns the path of the written file.
+///
+/// # Errors
+///
+/// Returns an error if the `OUT_DIR` environment variable is not set, or if the output cannot be written.
+pub fn write_to_out_dir(
+    compiler: &Compiler,
+    file_name: impl AsRef<Path>,
+) -> Result<PathBuf, Error> {
+    let out_dir = env::var_os("OUT_DIR")
+        .ok_or_else(|| Error::new("OUT_DIR is not set, this must be called from a build script"))?;
+    let path = Path::new(&out_dir).join(file_name);
+    compiler.write_file_descriptor_set(&path)?;
+
+    for dependency in compiler.dependency_paths() {
+        println!("cargo:rerun-if-changed={}", dependency.display());
+    }
+    Ok(path)
+}
+
+/// Embeds an encoded file descriptor set written to `OUT_DIR` by a build script, as a `&'static [u8; N]`.
+///
+/// The argument is the file name passed to [`compile_to_out_dir`] or [`write_to_out_dir`].
+///
+/// # Examples
+///
+/// ```ignore
+/// const FILE_DESCRIPTOR_SET: &[u8] = protox::include_file_descriptor_set!("file_descriptor_set.bin");
+/// ```
+#[macro_export]
+macro_rules! include_file_descriptor_set {
+    ($file_name:literal) => {
+        ::core::include_bytes!(::core::concat!(::core::env!("OUT_DIR"), "/", $file_name))
+    };
+}
+
+/// Embeds an encoded file descriptor set written to `OUT_DIR` by a build script, and decodes it into a
+/// `&'static DescriptorPool` the first time the expression is evaluated.
+///
+/// The argument is the file name passed to [`compile_to_out_dir`] or [`write_to_out_dir`].
+///
+/// # Panics
+///
+/// Panics if the embedded file is not a valid file descriptor set.
+///
+/// # Examples
+///
+/// ```ignore
+/// fn pool() -> &'static protox::prost_reflect::DescriptorPool {
+///     protox::include_descriptor_pool!("file_descriptor_set.bin")
+/// }
+/// ```
+#[macro_export]
+macro_rules! include_descriptor_pool {
+    ($file_name:literal) => {{
+        static POOL: ::std::sync::OnceLock<$crate::prost_reflect::DescriptorPool> =
+            ::std::sync::OnceLock::new();
+        POOL.get_or_init(|| {
+            $crate::prost_reflect::DescriptorPool::decode(
+                &$crate::include_file_descriptor_set!($file_name)[..],
+            )
+            .expect("failed to decode embedded file descriptor set")
+        })
+    }};
+}
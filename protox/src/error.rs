@@ -1,11 +1,19 @@
-use std::{fmt, io, path::PathBuf};
+use std::{
+    fmt, io,
+    path::{Path, PathBuf},
+    str::Utf8Error,
+};
 
-use miette::{Diagnostic, NamedSource, SourceCode, SourceOffset, SourceSpan};
+use miette::{Diagnostic, NamedSource, SourceCode, SourceSpan};
 use prost_reflect::DescriptorError;
-use protox_parse::ParseError;
+use protox_parse::{fix::Fix, ParseError};
 use thiserror::Error;
 
-use crate::file::File;
+use crate::{
+    check::CheckDiagnostic,
+    compile::{removal_fix, UnusedImport},
+    file::File,
+};
 
 /// An error that can occur when compiling protobuf files.
 #[derive(Diagnostic, Error)]
@@ -30,13 +38,44 @@ pub(crate) enum ErrorKind {
         #[source]
         err: io::Error,
     },
+    #[error("error reading file '{path}'")]
+    ReadFile {
+        name: String,
+        path: PathBuf,
+        #[source]
+        err: io::Error,
+    },
+    #[error("error writing file '{path}'")]
+    WriteFile {
+        path: PathBuf,
+        #[source]
+        err: io::Error,
+    },
     #[error("file '{name}' is too large")]
     #[diagnostic(help("the maximum file length is 2,147,483,647 bytes"))]
     FileTooLarge { name: String },
-    #[error("file '{name}' is not valid utf-8")]
-    FileInvalidUtf8 { name: String },
+    #[error("file '{name}' is not valid utf-8 at byte offset {offset}")]
+    FileInvalidUtf8 {
+        name: String,
+        path: Option<PathBuf>,
+        offset: usize,
+        #[label("invalid utf-8 sequence")]
+        span: Option<SourceSpan>,
+        /// The contents of the file, with invalid sequences replaced by U+FFFD.
+        #[source_code]
+        source_code: NamedSource<String>,
+    },
+    #[error("file '{name}' has already been compiled")]
+    #[diagnostic(help(
+        "only files which have not been added to the compiler can be checked without adding them"
+    ))]
+    FileAlreadyCompiled { name: String },
     #[error("file '{name}' not found")]
-    FileNotFound { name: String },
+    FileNotFound {
+        name: String,
+        #[help]
+        searched: Option<Searched>,
+    },
     #[error("import '{name}' not found")]
     ImportNotFound {
         #[label("imported here")]
@@ -44,11 +83,225 @@ pub(crate) enum ErrorKind {
         #[source_code]
         source_code: NamedSource<String>,
         name: String,
+        #[help]
+        searched: Option<Searched>,
+    },
+    #[error("import '{name}' is unused")]
+    #[diagnostic(help("remove this import"))]
+    UnusedImport {
+        #[label("unused import")]
+        span: Option<SourceSpan>,
+        removal_span: Option<SourceSpan>,
+        #[source_code]
+        source_code: NamedSource<String>,
+        name: String,
+    },
+    #[error("option '{name}' cannot be set on an entity of type '{target}'")]
+    InvalidOptionTarget {
+        #[label("option set here")]
+        span: Option<SourceSpan>,
+        #[source_code]
+        source_code: NamedSource<String>,
+        name: String,
+        target: &'static str,
+    },
+    #[error("{message}")]
+    InvalidExtensionDeclaration {
+        #[label("defined here")]
+        span: Option<SourceSpan>,
+        #[source_code]
+        source_code: NamedSource<String>,
+        message: String,
+    },
+    #[error("{message}")]
+    InvalidOptionValue {
+        #[label("set here")]
+        span: Option<SourceSpan>,
+        #[source_code]
+        source_code: NamedSource<String>,
+        message: String,
+    },
+    #[error("{message}")]
+    InvalidMessageSet {
+        #[label("defined here")]
+        span: Option<SourceSpan>,
+        #[source_code]
+        source_code: NamedSource<String>,
+        message: &'static str,
+    },
+    #[error("{message}")]
+    InvalidOneof {
+        #[label("defined here")]
+        span: Option<SourceSpan>,
+        #[source_code]
+        source_code: NamedSource<String>,
+        message: String,
+    },
+    #[error("{message}")]
+    InvalidDescriptor {
+        #[label("defined here")]
+        span: Option<SourceSpan>,
+        #[source_code]
+        source_code: NamedSource<String>,
+        message: String,
+    },
+    #[error("{message}")]
+    InvalidMapType {
+        #[label("found here")]
+        span: Option<SourceSpan>,
+        #[source_code]
+        source_code: NamedSource<String>,
+        message: String,
+        #[help]
+        help: Option<String>,
+    },
+    #[error("{message}")]
+    InvalidJsonName {
+        #[label("defined here")]
+        span: Option<SourceSpan>,
+        #[source_code]
+        source_code: NamedSource<String>,
+        message: String,
+    },
+    #[error("{message}")]
+    DuplicateJsonName {
+        #[label("first defined here")]
+        first: Option<SourceSpan>,
+        #[label("defined again here")]
+        second: Option<SourceSpan>,
+        #[source_code]
+        source_code: NamedSource<String>,
+        message: String,
+    },
+    #[error("option field '{name}' has already been set")]
+    DuplicateOption {
+        name: String,
+        #[label("first set here")]
+        first: Option<SourceSpan>,
+        #[label("set again here")]
+        second: Option<SourceSpan>,
+        #[source_code]
+        source_code: NamedSource<String>,
+    },
+    #[error("name '{name}' is defined twice")]
+    #[diagnostic(help(
+        "enum values use C++ scoping rules, meaning that enum values are siblings of their type, not children of it, so '{value}' must be unique within {scope}, not just within '{enum_name}'"
+    ))]
+    EnumValueScopeConflict {
+        name: String,
+        value: String,
+        enum_name: String,
+        scope: String,
+        #[label("first defined here")]
+        first: Option<SourceSpan>,
+        #[label("defined again here")]
+        second: Option<SourceSpan>,
+        #[source_code]
+        source_code: NamedSource<String>,
+    },
+    #[error("{message}")]
+    #[diagnostic(help(
+        "if you are using allow_alias, assign the same number to each enum value name"
+    ))]
+    EnumValueNameConflict {
+        #[label("first defined here")]
+        first: Option<SourceSpan>,
+        #[label("defined again here")]
+        second: Option<SourceSpan>,
+        #[source_code]
+        source_code: NamedSource<String>,
+        message: String,
+    },
+    #[error("name '{name}' is already defined in file '{existing_file}'")]
+    #[diagnostic(help(
+        "each fully-qualified name may only be defined once across all compiled files; if both files have the same contents, consider importing one of them instead"
+    ))]
+    DuplicateName {
+        name: String,
+        existing_file: String,
+        #[label("defined again here")]
+        span: Option<SourceSpan>,
+        #[source_code]
+        source_code: NamedSource<String>,
+        #[related]
+        definition: Vec<NameDefinition>,
+    },
+    #[error("{message}")]
+    CustomCheck {
+        #[label("defined here")]
+        span: Option<SourceSpan>,
+        #[source_code]
+        source_code: NamedSource<String>,
+        message: String,
+        #[help]
+        help: Option<String>,
+    },
+    #[error("enum '{enum_name}' is not a proto3 enum, but is used by field '{field}' of proto3 message '{message}'")]
+    #[diagnostic(help(
+        "enums defined in proto2 files are closed, so they cannot be used in proto3 messages, which must preserve unknown enum values"
+    ))]
+    ClosedEnumInProto3 {
+        enum_name: String,
+        field: String,
+        message: String,
+        #[label("used here")]
+        span: Option<SourceSpan>,
+        #[source_code]
+        source_code: NamedSource<String>,
+        #[related]
+        definition: Vec<EnumDefinition>,
+    },
+    #[error("extending '{extendee}' is not allowed in proto3")]
+    #[diagnostic(help(
+        "extensions in proto3 files are only allowed for defining custom options; to extend other messages, declare the extension in a file using proto2 syntax or editions"
+    ))]
+    InvalidProto3Extension {
+        extendee: String,
+        #[label("extended here")]
+        span: Option<SourceSpan>,
+        #[source_code]
+        source_code: NamedSource<String>,
+    },
+    #[error("field '{field}' is a proto3 optional field, which requires --experimental_allow_proto3_optional")]
+    #[diagnostic(help(
+        "protoc 3.12 to 3.14 only accept proto3 optional fields with --experimental_allow_proto3_optional; use CompatibilityLevel::Protoc21 or later to allow them"
+    ))]
+    Proto3OptionalNotAllowed {
+        field: String,
+        #[label("declared optional here")]
+        span: Option<SourceSpan>,
+        #[source_code]
+        source_code: NamedSource<String>,
+    },
+    #[error("extension number '{number}' of '{extendee}' is already used by extension '{existing}' in file '{existing_file}'")]
+    #[diagnostic(help(
+        "each extension of a message must have a different number, even if the extensions are declared in unrelated files"
+    ))]
+    DuplicateExtensionNumber {
+        number: u32,
+        extendee: String,
+        existing: String,
+        existing_file: String,
+        #[label("used again here")]
+        span: Option<SourceSpan>,
+        #[source_code]
+        source_code: NamedSource<String>,
+        #[related]
+        context: Vec<ExtensionNumberContext>,
     },
     #[error("import cycle detected: {cycle}")]
-    CircularImport { name: String, cycle: String },
+    CircularImport {
+        name: String,
+        cycle: String,
+        #[related]
+        imports: Vec<CycleImport>,
+    },
     #[error("file '{path}' is not in any include path")]
-    FileNotIncluded { path: PathBuf },
+    FileNotIncluded {
+        path: PathBuf,
+        #[help]
+        searched: Option<Searched>,
+    },
     #[error("path '{path}' is shadowed by '{shadow}' in the include paths")]
     #[diagnostic(help("either pass '{}' as the input file, or re-order the include paths so that '{}' comes first", shadow.display(), path.display()))]
     FileShadowed {
@@ -56,10 +309,140 @@ pub(crate) enum ErrorKind {
         path: PathBuf,
         shadow: PathBuf,
     },
+    #[error("file name '{name}' must be a relative path without '.' or '..' components")]
+    InvalidFileName { name: String },
+    #[error("file '{name}' resolves to '{path}', which is outside the include paths", path = path.display())]
+    #[diagnostic(help(
+        "in hermetic mode, files may not be read through symbolic links leading outside the include paths"
+    ))]
+    FileOutsideIncludes { name: String, path: PathBuf },
+    #[error("path '{path}' to file '{name}' is a symbolic link, which is not followed", path = path.display())]
+    SymlinkNotFollowed { name: String, path: PathBuf },
+    #[error("file '{name}' does not match the case of '{actual}' on the file system")]
+    #[diagnostic(help("change the name to '{actual}'"))]
+    FileNameCaseMismatch { name: String, actual: String },
+    #[error("digest '{digest}' of module '{module}' is invalid")]
+    #[diagnostic(help("digests must be of the form 'sha256:<hex>'"))]
+    InvalidModuleDigest { module: String, digest: String },
+    #[error("module '{module}' has digest '{actual}', but expected '{expected}'")]
+    ModuleDigestMismatch {
+        module: String,
+        expected: String,
+        actual: String,
+    },
+    #[error("file '{name}' is provided by both module '{first}' and module '{second}'")]
+    DuplicateModuleFile {
+        name: String,
+        first: String,
+        second: String,
+    },
+    #[error("import '{import}' in '{name}' could refer to '{}' or '{}'", first.display(), second.display())]
+    #[diagnostic(help("pass the include path containing the intended file explicitly"))]
+    AmbiguousImport {
+        name: String,
+        import: String,
+        first: PathBuf,
+        second: PathBuf,
+    },
+    #[error("file '{}' is imported as both '{first}' and '{second}'", path.display())]
+    #[diagnostic(help(
+        "a file must be imported by the same name relative to its include path everywhere"
+    ))]
+    InconsistentImportName {
+        path: PathBuf,
+        first: String,
+        second: String,
+    },
+    #[error("unknown protoc argument '{arg}'")]
+    #[diagnostic(help(
+        "the supported arguments are -I, --proto_path, --descriptor_set_in, --include_imports, --include_source_info, --retain_options and input files"
+    ))]
+    UnknownArgument { arg: String },
+    #[error("missing value for protoc flag '{flag}'")]
+    MissingArgumentValue { flag: String },
+    #[error("cannot rename '{name}' to '{new_name}': {reason}")]
+    InvalidRename {
+        name: String,
+        new_name: String,
+        reason: String,
+    },
+    #[error("cannot filter descriptors by '{name}': it is not defined")]
+    UndefinedFilterName { name: String },
     #[error(transparent)]
     Custom(Box<dyn std::error::Error + Send + Sync>),
 }
 
+/// The resolvers which were searched for a file that could not be found, in order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Searched(pub Vec<String>);
+
+impl fmt::Display for Searched {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "searched {}", self.0.join(", "))
+    }
+}
+
+/// The definition of an enum referenced by an error in another file.
+#[derive(Debug, Diagnostic, Error)]
+#[error("enum '{name}' is defined in proto2 file '{}'", source_code.name())]
+#[diagnostic(severity(Advice))]
+pub(crate) struct EnumDefinition {
+    pub name: String,
+    #[label("defined here")]
+    pub span: Option<SourceSpan>,
+    #[source_code]
+    pub source_code: NamedSource<String>,
+}
+
+/// The definitions related to an extension number which is used by more than one extension of a message.
+#[derive(Debug, Diagnostic, Error)]
+pub(crate) enum ExtensionNumberContext {
+    #[error("extension '{name}' is defined in file '{}'", source_code.name())]
+    #[diagnostic(severity(Advice))]
+    Definition {
+        name: String,
+        #[label("first used here")]
+        span: Option<SourceSpan>,
+        #[source_code]
+        source_code: NamedSource<String>,
+    },
+    #[error("'{message}' declares extensions {start} to {end} in file '{}'", source_code.name())]
+    #[diagnostic(severity(Advice))]
+    Range {
+        message: String,
+        start: i32,
+        end: i32,
+        #[label("extension range declared here")]
+        span: Option<SourceSpan>,
+        #[source_code]
+        source_code: NamedSource<String>,
+    },
+}
+
+/// The first definition of a name which is defined again in another file.
+#[derive(Debug, Diagnostic, Error)]
+#[error("'{name}' is first defined in file '{}'", source_code.name())]
+#[diagnostic(severity(Advice))]
+pub(crate) struct NameDefinition {
+    pub name: String,
+    #[label("first defined here")]
+    pub span: Option<SourceSpan>,
+    #[source_code]
+    pub source_code: NamedSource<String>,
+}
+
+/// An import statement which forms part of an import cycle.
+#[derive(Debug, Diagnostic, Error)]
+#[error("'{}' imports '{import}'", source_code.name())]
+#[diagnostic(severity(Advice))]
+pub(crate) struct CycleImport {
+    pub import: String,
+    #[label("imported here")]
+    pub span: Option<SourceSpan>,
+    #[source_code]
+    pub source_code: NamedSource<String>,
+}
+
 impl Error {
     /// Creates an instance of [`struct@Error`] with an arbitrary payload.
     pub fn new<E>(error: E) -> Self
@@ -75,23 +458,94 @@ impl Error {
     pub fn file_not_found(name: &str) -> Self {
         Error::from_kind(ErrorKind::FileNotFound {
             name: name.to_owned(),
+            searched: None,
+        })
+    }
+
+    /// Creates a file not found error which lists the resolvers that were searched for the file.
+    pub(crate) fn file_not_found_in(name: &str, searched: Vec<String>) -> Self {
+        Error::from_kind(ErrorKind::FileNotFound {
+            name: name.to_owned(),
+            searched: (!searched.is_empty()).then_some(Searched(searched)),
         })
     }
 
+    /// If this is a file not found error listing the resolvers searched for the file, returns them.
+    pub(crate) fn searched(&self) -> Option<&[String]> {
+        match &*self.kind {
+            ErrorKind::FileNotFound { searched, .. }
+            | ErrorKind::ImportNotFound { searched, .. }
+            | ErrorKind::FileNotIncluded { searched, .. } => {
+                searched.as_ref().map(|searched| searched.0.as_slice())
+            }
+            _ => None,
+        }
+    }
+
     /// The file in which this error occurred, if available.
     pub fn file(&self) -> Option<&str> {
         match &*self.kind {
             ErrorKind::Parse { err } => Some(err.file()),
             ErrorKind::Check { err } => err.file(),
             ErrorKind::OpenFile { name, .. }
+            | ErrorKind::ReadFile { name, .. }
             | ErrorKind::FileTooLarge { name }
-            | ErrorKind::FileInvalidUtf8 { name }
-            | ErrorKind::FileNotFound { name }
+            | ErrorKind::FileInvalidUtf8 { name, .. }
+            | ErrorKind::FileNotFound { name, .. }
+            | ErrorKind::FileAlreadyCompiled { name }
             | ErrorKind::CircularImport { name, .. }
-            | ErrorKind::FileShadowed { name, .. } => Some(name),
-            ErrorKind::FileNotIncluded { .. } => None,
+            | ErrorKind::FileShadowed { name, .. }
+            | ErrorKind::InvalidFileName { name }
+            | ErrorKind::FileOutsideIncludes { name, .. }
+            | ErrorKind::SymlinkNotFollowed { name, .. }
+            | ErrorKind::FileNameCaseMismatch { name, .. }
+            | ErrorKind::DuplicateModuleFile { name, .. }
+            | ErrorKind::AmbiguousImport { name, .. } => Some(name),
+            ErrorKind::FileNotIncluded { .. }
+            | ErrorKind::InconsistentImportName { .. }
+            | ErrorKind::WriteFile { .. }
+            | ErrorKind::InvalidModuleDigest { .. }
+            | ErrorKind::ModuleDigestMismatch { .. }
+            | ErrorKind::UnknownArgument { .. }
+            | ErrorKind::MissingArgumentValue { .. }
+            | ErrorKind::InvalidRename { .. }
+            | ErrorKind::UndefinedFilterName { .. } => None,
             ErrorKind::Custom(_) => None,
-            ErrorKind::ImportNotFound { source_code, .. } => Some(source_code.name()),
+            ErrorKind::ImportNotFound { source_code, .. }
+            | ErrorKind::UnusedImport { source_code, .. }
+            | ErrorKind::InvalidOptionTarget { source_code, .. }
+            | ErrorKind::InvalidExtensionDeclaration { source_code, .. }
+            | ErrorKind::InvalidOptionValue { source_code, .. }
+            | ErrorKind::InvalidMessageSet { source_code, .. }
+            | ErrorKind::InvalidOneof { source_code, .. }
+            | ErrorKind::InvalidDescriptor { source_code, .. }
+            | ErrorKind::InvalidMapType { source_code, .. }
+            | ErrorKind::InvalidJsonName { source_code, .. }
+            | ErrorKind::DuplicateJsonName { source_code, .. }
+            | ErrorKind::DuplicateOption { source_code, .. }
+            | ErrorKind::EnumValueScopeConflict { source_code, .. }
+            | ErrorKind::EnumValueNameConflict { source_code, .. }
+            | ErrorKind::DuplicateName { source_code, .. }
+            | ErrorKind::CustomCheck { source_code, .. }
+            | ErrorKind::ClosedEnumInProto3 { source_code, .. }
+            | ErrorKind::InvalidProto3Extension { source_code, .. }
+            | ErrorKind::Proto3OptionalNotAllowed { source_code, .. }
+            | ErrorKind::DuplicateExtensionNumber { source_code, .. } => Some(source_code.name()),
+        }
+    }
+
+    /// Gets machine-applicable fixes for this error, if any.
+    ///
+    /// Fixes are available for some parse errors (see [`ParseError::fixes()`]) and for unused imports. Errors reported
+    /// while checking the semantics of a file do not currently provide fixes.
+    pub fn fixes(&self) -> Vec<Fix> {
+        match &*self.kind {
+            ErrorKind::Parse { err } => err.fixes(),
+            ErrorKind::UnusedImport {
+                removal_span: Some(span),
+                ..
+            } => vec![removal_fix(*span)],
+            _ => Vec::new(),
         }
     }
 
@@ -126,15 +580,55 @@ impl Error {
         )
     }
 
-    /// Returns true if this error is caused by an IO error while opening a file.
+    /// Returns true if this error is caused by an IO error while opening, reading or writing a file.
     pub fn is_io(&self) -> bool {
         match &*self.kind {
-            ErrorKind::OpenFile { .. } => true,
+            ErrorKind::OpenFile { .. }
+            | ErrorKind::ReadFile { .. }
+            | ErrorKind::WriteFile { .. } => true,
             ErrorKind::Custom(err) if err.downcast_ref::<io::Error>().is_some() => true,
             _ => false,
         }
     }
 
+    pub(crate) fn invalid_utf8(
+        name: &str,
+        path: Option<&Path>,
+        bytes: &[u8],
+        err: Utf8Error,
+    ) -> Self {
+        let offset = err.valid_up_to();
+        Error::from_kind(ErrorKind::FileInvalidUtf8 {
+            name: name.to_owned(),
+            path: path.map(Path::to_owned),
+            offset,
+            span: Some((offset, char::REPLACEMENT_CHARACTER.len_utf8()).into()),
+            source_code: NamedSource::new(name, String::from_utf8_lossy(bytes).into_owned()),
+        })
+    }
+
+    pub(crate) fn is_invalid_utf8(&self) -> bool {
+        matches!(&*self.kind, ErrorKind::FileInvalidUtf8 { .. })
+    }
+
+    /// If this error was caused by a file which is not valid UTF-8, parses the file with invalid sequences replaced
+    /// by U+FFFD. Otherwise, returns this error unchanged.
+    pub(crate) fn into_lossy_file(self) -> Result<File, Self> {
+        match *self.kind {
+            ErrorKind::FileInvalidUtf8 {
+                name,
+                path,
+                source_code,
+                ..
+            } => {
+                let mut file = File::from_source(&name, source_code.inner())?;
+                file.path = path;
+                Ok(file)
+            }
+            _ => Err(self),
+        }
+    }
+
     pub(crate) fn into_import_error(self, file: &File, import_idx: usize) -> Self {
         fn find_span(file: &File, import_idx: usize) -> Option<SourceSpan> {
             if let Some(sci) = &file.descriptor.source_code_info {
@@ -144,13 +638,7 @@ impl Error {
                             if location.span.len() != 3 {
                                 continue;
                             }
-                            let start_line = location.span[0] as usize + 1;
-                            let start_col = location.span[1] as usize + 1;
-                            let end_col = location.span[2] as usize + 1;
-                            return Some(SourceSpan::new(
-                                SourceOffset::from_location(source, start_line, start_col),
-                                end_col - start_col,
-                            ));
+                            return location_span(source, &location.span);
                         }
                     }
                 }
@@ -158,7 +646,7 @@ impl Error {
             None
         }
         match *self.kind {
-            ErrorKind::FileNotFound { name } => {
+            ErrorKind::FileNotFound { name, searched } => {
                 let source_code: NamedSource<String> =
                     NamedSource::new(file.name(), file.source().unwrap_or_default().to_owned());
                 let span = find_span(file, import_idx);
@@ -166,6 +654,36 @@ impl Error {
                     span,
                     source_code,
                     name,
+                    searched,
+                })
+            }
+            ErrorKind::CircularImport {
+                name,
+                cycle,
+                mut imports,
+            } => {
+                // Each file in the cycle adds its import while the error is propagated, until the file which
+                // starts the cycle is reached.
+                if imports
+                    .first()
+                    .map_or(true, |import| import.source_code.name() != name)
+                {
+                    imports.insert(
+                        0,
+                        CycleImport {
+                            import: file.descriptor.dependency[import_idx].clone(),
+                            span: find_span(file, import_idx),
+                            source_code: NamedSource::new(
+                                file.name(),
+                                file.source().unwrap_or_default().to_owned(),
+                            ),
+                        },
+                    );
+                }
+                Error::from_kind(ErrorKind::CircularImport {
+                    name,
+                    cycle,
+                    imports,
                 })
             }
             _ => self,
@@ -173,6 +691,60 @@ impl Error {
     }
 }
 
+/// Converts a span from a [`Location`](prost_types::source_code_info::Location) into a byte range
+/// of the given source.
+pub(crate) fn location_span(source: &str, span: &[i32]) -> Option<SourceSpan> {
+    let (start_line, start_col, end_line, end_col) = match *span {
+        [start_line, start_col, end_col] => (start_line, start_col, start_line, end_col),
+        [start_line, start_col, end_line, end_col] => (start_line, start_col, end_line, end_col),
+        _ => return None,
+    };
+
+    let line_offset = |line: i32| -> Option<usize> {
+        let line = usize::try_from(line).ok()?;
+        if line == 0 {
+            Some(0)
+        } else {
+            source
+                .match_indices('\n')
+                .nth(line - 1)
+                .map(|(index, _)| index + 1)
+        }
+    };
+
+    let start = line_offset(start_line)? + usize::try_from(start_col).ok()?;
+    let end = line_offset(end_line)? + usize::try_from(end_col).ok()?;
+    if start > end || end > source.len() {
+        return None;
+    }
+
+    Some(SourceSpan::new(start.into(), end - start))
+}
+
+impl From<UnusedImport> for Error {
+    fn from(err: UnusedImport) -> Self {
+        let (name, span, removal_span, source_code) = err.into_parts();
+        Error::from_kind(ErrorKind::UnusedImport {
+            span,
+            removal_span,
+            source_code,
+            name,
+        })
+    }
+}
+
+impl From<CheckDiagnostic> for Error {
+    fn from(err: CheckDiagnostic) -> Self {
+        let (message, help, span, source_code) = err.into_parts();
+        Error::from_kind(ErrorKind::CustomCheck {
+            span,
+            source_code,
+            message,
+            help,
+        })
+    }
+}
+
 impl From<DescriptorError> for Error {
     fn from(err: DescriptorError) -> Self {
         Error::from_kind(ErrorKind::Check { err })
@@ -196,15 +768,100 @@ impl fmt::Debug for Error {
         match &*self.kind {
             ErrorKind::Parse { err } => err.fmt(f),
             ErrorKind::Check { err } => err.fmt(f),
-            ErrorKind::OpenFile { err, .. } => write!(f, "{}: {}", self, err),
+            ErrorKind::OpenFile { err, .. }
+            | ErrorKind::ReadFile { err, .. }
+            | ErrorKind::WriteFile { err, .. } => {
+                write!(f, "{}: {}", self, err)
+            }
             ErrorKind::FileTooLarge { .. }
-            | ErrorKind::FileInvalidUtf8 { .. }
             | ErrorKind::FileNotFound { .. }
+            | ErrorKind::FileAlreadyCompiled { .. }
             | ErrorKind::CircularImport { .. }
             | ErrorKind::FileNotIncluded { .. }
-            | ErrorKind::FileShadowed { .. } => write!(f, "{}", self),
+            | ErrorKind::FileShadowed { .. }
+            | ErrorKind::InvalidFileName { .. }
+            | ErrorKind::FileOutsideIncludes { .. }
+            | ErrorKind::SymlinkNotFollowed { .. }
+            | ErrorKind::FileNameCaseMismatch { .. }
+            | ErrorKind::InvalidModuleDigest { .. }
+            | ErrorKind::ModuleDigestMismatch { .. }
+            | ErrorKind::DuplicateModuleFile { .. }
+            | ErrorKind::AmbiguousImport { .. }
+            | ErrorKind::InconsistentImportName { .. }
+            | ErrorKind::UnknownArgument { .. }
+            | ErrorKind::MissingArgumentValue { .. }
+            | ErrorKind::InvalidRename { .. }
+            | ErrorKind::UndefinedFilterName { .. } => write!(f, "{}", self),
             ErrorKind::Custom(err) => err.fmt(f),
-            ErrorKind::ImportNotFound {
+            ErrorKind::FileInvalidUtf8 {
+                span, source_code, ..
+            }
+            | ErrorKind::ImportNotFound {
+                span, source_code, ..
+            }
+            | ErrorKind::UnusedImport {
+                span, source_code, ..
+            }
+            | ErrorKind::InvalidOptionTarget {
+                span, source_code, ..
+            }
+            | ErrorKind::InvalidExtensionDeclaration {
+                span, source_code, ..
+            }
+            | ErrorKind::InvalidOptionValue {
+                span, source_code, ..
+            }
+            | ErrorKind::InvalidMessageSet {
+                span, source_code, ..
+            }
+            | ErrorKind::InvalidOneof {
+                span, source_code, ..
+            }
+            | ErrorKind::InvalidDescriptor {
+                span, source_code, ..
+            }
+            | ErrorKind::InvalidMapType {
+                span, source_code, ..
+            }
+            | ErrorKind::InvalidJsonName {
+                span, source_code, ..
+            }
+            | ErrorKind::DuplicateJsonName {
+                second: span,
+                source_code,
+                ..
+            }
+            | ErrorKind::DuplicateOption {
+                second: span,
+                source_code,
+                ..
+            }
+            | ErrorKind::EnumValueScopeConflict {
+                second: span,
+                source_code,
+                ..
+            }
+            | ErrorKind::EnumValueNameConflict {
+                second: span,
+                source_code,
+                ..
+            }
+            | ErrorKind::DuplicateName {
+                span, source_code, ..
+            }
+            | ErrorKind::CustomCheck {
+                span, source_code, ..
+            }
+            | ErrorKind::ClosedEnumInProto3 {
+                span, source_code, ..
+            }
+            | ErrorKind::InvalidProto3Extension {
+                span, source_code, ..
+            }
+            | ErrorKind::Proto3OptionalNotAllowed {
+                span, source_code, ..
+            }
+            | ErrorKind::DuplicateExtensionNumber {
                 span, source_code, ..
             } => {
                 write!(f, "{}:", source_code.name())?;
@@ -0,0 +1,546 @@
+//! Resolution of the [features](https://protobuf.dev/editions/features/) which control how each element behaves.
+//!
+//! Editions replace the `syntax` of a file with a set of features, such as whether fields track presence or whether
+//! enums are open. Each edition has default values for every feature. An element inherits the features of the
+//! element it is declared in, and may override them with its `features` option:
+//!
+//! * a file starts with the defaults of its edition,
+//! * messages, enums, services and extensions declared in the file scope inherit from the file,
+//! * nested messages, enums and extensions inherit from their message,
+//! * fields inherit from their oneof if they are in one, or from their message otherwise,
+//! * enum values inherit from their enum, and methods from their service.
+//!
+//! Files using `proto2` or `proto3` syntax use the defaults of the corresponding legacy edition. Their fields also
+//! have the features `protoc` infers from the `required` label, groups and the `packed` option. For example, a
+//! `required` field has [`FieldPresence::LegacyRequired`], and a proto3 field with `[packed = false]` has
+//! [`RepeatedFieldEncoding::Expanded`].
+//!
+//! The core features are always available from [`FeatureSet`]. The `features` options, and features defined as
+//! extensions of `google.protobuf.FeatureSet`, require a version of `descriptor.proto` which defines them. If the
+//! pool contains one, [`FeatureSet::message`] gets the full resolved set, including custom features, with their
+//! defaults taken from the `edition_defaults` option of each feature field.
+//!
+//! A [`FeatureResolver`] resolves the defaults and the features of a file once, so the elements of the file can be
+//! resolved without repeating that work.
+//!
+//! # Limitations
+//!
+//! The parser does not support the `edition` declaration, and [`prost_reflect`] only represents `proto2` and `proto3`
+//! files, so [`Edition`] only has the two legacy editions. Resolution still follows the rules of editions, so
+//! `features` options set in a `proto2` or `proto3` file are applied.
+//!
+//! # Examples
+//!
+//! ```
+//! # use protox::{file::{File, FileResolver}, features::{FeatureResolver, FieldPresence, RepeatedFieldEncoding}, Compiler};
+//! # struct Resolver;
+//! # impl FileResolver for Resolver {
+//! #     fn open_file(&self, name: &str) -> Result<File, protox::Error> {
+//! #         File::from_source(name, "
+//! syntax = 'proto2';
+//!
+//! message Foo {
+//!   required int32 id = 1;
+//!   repeated int32 values = 2 [packed = true];
+//! }
+//! #         ")
+//! #     }
+//! # }
+//! let mut compiler = Compiler::with_file_resolver(Resolver);
+//! compiler.open_file("foo.proto")?;
+//!
+//! let message = compiler.descriptor_pool().get_message_by_name("Foo").unwrap();
+//! let resolver = FeatureResolver::new(&message.parent_file());
+//! let id = resolver.for_field(&message.get_field_by_name("id").unwrap());
+//! assert_eq!(id.field_presence(), FieldPresence::LegacyRequired);
+//!
+//! let values = resolver.for_field(&message.get_field_by_name("values").unwrap());
+//! assert_eq!(values.repeated_field_encoding(), RepeatedFieldEncoding::Packed);
+//! # Ok::<(), protox::Error>(())
+//! ```
+
+use prost::Message;
+use prost_reflect::{
+    DescriptorPool, DynamicMessage, EnumDescriptor, EnumValueDescriptor, ExtensionDescriptor,
+    FieldDescriptor, FileDescriptor, Kind, MessageDescriptor, MethodDescriptor, OneofDescriptor,
+    ReflectMessage, ServiceDescriptor, Syntax, Value,
+};
+use prost_types::{
+    field_descriptor_proto::{Label, Type},
+    FieldDescriptorProto,
+};
+
+#[cfg(test)]
+mod tests;
+
+/// The full name of the message defining the features.
+const FEATURE_SET: &str = "google.protobuf.FeatureSet";
+
+/// An edition of the protobuf language, which determines the default value of each feature.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[non_exhaustive]
+pub enum Edition {
+    /// The legacy edition of files with `syntax = "proto2"`.
+    Proto2,
+    /// The legacy edition of files with `syntax = "proto3"`.
+    Proto3,
+}
+
+/// Whether a field tracks presence.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FieldPresence {
+    /// The field tracks whether it has been set.
+    Explicit,
+    /// The field does not track presence, and is not serialized if it has its default value.
+    Implicit,
+    /// The field is a proto2 `required` field.
+    LegacyRequired,
+}
+
+/// Whether an enum accepts unknown values.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EnumType {
+    /// Unknown values are stored in the field.
+    Open,
+    /// Unknown values are stored in the unknown field set.
+    Closed,
+}
+
+/// How repeated fields of scalar types are encoded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RepeatedFieldEncoding {
+    /// The values are encoded in a single length-delimited record.
+    Packed,
+    /// Each value is encoded in a separate record.
+    Expanded,
+}
+
+/// Whether string fields are checked to contain valid UTF-8 when parsed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Utf8Validation {
+    /// Invalid UTF-8 is rejected.
+    Verify,
+    /// Strings are not validated.
+    None,
+}
+
+/// How message fields are encoded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MessageEncoding {
+    /// Messages are encoded as length-delimited records.
+    LengthPrefixed,
+    /// Messages are encoded between start and end group tags.
+    Delimited,
+}
+
+/// Whether the JSON mapping is checked to be well-defined.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum JsonFormat {
+    /// Definitions which conflict in JSON, such as fields with the same JSON name, are rejected.
+    Allow,
+    /// Conflicts in the JSON mapping are allowed.
+    LegacyBestEffort,
+}
+
+/// The resolved features of an element.
+///
+/// See the [module documentation](self) for details.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FeatureSet {
+    field_presence: FieldPresence,
+    enum_type: EnumType,
+    repeated_field_encoding: RepeatedFieldEncoding,
+    utf8_validation: Utf8Validation,
+    message_encoding: MessageEncoding,
+    json_format: JsonFormat,
+    message: Option<DynamicMessage>,
+}
+
+/// Resolves the features of the elements declared in a file.
+///
+/// The defaults of the file's edition and the features of the file are resolved when the resolver is created, and
+/// each element is resolved from them by applying the options of the elements it is declared in.
+#[derive(Debug, Clone)]
+pub struct FeatureResolver {
+    file: FileDescriptor,
+    edition: Edition,
+    features: FeatureSet,
+}
+
+impl Edition {
+    /// Gets the edition of a file.
+    pub fn of(file: &FileDescriptor) -> Self {
+        match file.syntax() {
+            Syntax::Proto3 => Edition::Proto3,
+            _ => Edition::Proto2,
+        }
+    }
+
+    /// Gets the value of the edition in the `google.protobuf.Edition` enum.
+    pub fn number(self) -> i32 {
+        match self {
+            Edition::Proto2 => 998,
+            Edition::Proto3 => 999,
+        }
+    }
+}
+
+impl FieldPresence {
+    fn from_number(number: i32) -> Option<Self> {
+        match number {
+            1 => Some(FieldPresence::Explicit),
+            2 => Some(FieldPresence::Implicit),
+            3 => Some(FieldPresence::LegacyRequired),
+            _ => None,
+        }
+    }
+}
+
+impl EnumType {
+    fn from_number(number: i32) -> Option<Self> {
+        match number {
+            1 => Some(EnumType::Open),
+            2 => Some(EnumType::Closed),
+            _ => None,
+        }
+    }
+}
+
+impl RepeatedFieldEncoding {
+    fn from_number(number: i32) -> Option<Self> {
+        match number {
+            1 => Some(RepeatedFieldEncoding::Packed),
+            2 => Some(RepeatedFieldEncoding::Expanded),
+            _ => None,
+        }
+    }
+}
+
+impl Utf8Validation {
+    fn from_number(number: i32) -> Option<Self> {
+        match number {
+            2 => Some(Utf8Validation::Verify),
+            3 => Some(Utf8Validation::None),
+            _ => None,
+        }
+    }
+}
+
+impl MessageEncoding {
+    fn from_number(number: i32) -> Option<Self> {
+        match number {
+            1 => Some(MessageEncoding::LengthPrefixed),
+            2 => Some(MessageEncoding::Delimited),
+            _ => None,
+        }
+    }
+}
+
+impl JsonFormat {
+    fn from_number(number: i32) -> Option<Self> {
+        match number {
+            1 => Some(JsonFormat::Allow),
+            2 => Some(JsonFormat::LegacyBestEffort),
+            _ => None,
+        }
+    }
+}
+
+impl FeatureSet {
+    /// Gets the default features of an edition.
+    ///
+    /// If `pool` defines `google.protobuf.FeatureSet`, the defaults are taken from the `edition_defaults` option of
+    /// each of its fields, and of the fields of each message extending it.
+    pub fn defaults(pool: &DescriptorPool, edition: Edition) -> Self {
+        let mut features = match edition {
+            Edition::Proto2 => FeatureSet {
+                field_presence: FieldPresence::Explicit,
+                enum_type: EnumType::Closed,
+                repeated_field_encoding: RepeatedFieldEncoding::Expanded,
+                utf8_validation: Utf8Validation::None,
+                message_encoding: MessageEncoding::LengthPrefixed,
+                json_format: JsonFormat::LegacyBestEffort,
+                message: None,
+            },
+            Edition::Proto3 => FeatureSet {
+                field_presence: FieldPresence::Implicit,
+                enum_type: EnumType::Open,
+                repeated_field_encoding: RepeatedFieldEncoding::Packed,
+                utf8_validation: Utf8Validation::Verify,
+                message_encoding: MessageEncoding::LengthPrefixed,
+                json_format: JsonFormat::Allow,
+                message: None,
+            },
+        };
+
+        if let Some(descriptor) = pool.get_message_by_name(FEATURE_SET) {
+            let mut message = DynamicMessage::new(descriptor.clone());
+            set_defaults(&mut message, edition);
+            for extension in pool.all_extensions() {
+                if extension.containing_message() != descriptor {
+                    continue;
+                }
+                if let Kind::Message(custom) = extension.kind() {
+                    let mut value = DynamicMessage::new(custom);
+                    set_defaults(&mut value, edition);
+                    message.set_extension(&extension, Value::Message(value));
+                }
+            }
+            features.apply_core(&message);
+            features.message = Some(message);
+        }
+
+        features
+    }
+
+    /// Gets the `field_presence` feature.
+    pub fn field_presence(&self) -> FieldPresence {
+        self.field_presence
+    }
+
+    /// Gets the `enum_type` feature.
+    pub fn enum_type(&self) -> EnumType {
+        self.enum_type
+    }
+
+    /// Gets the `repeated_field_encoding` feature.
+    pub fn repeated_field_encoding(&self) -> RepeatedFieldEncoding {
+        self.repeated_field_encoding
+    }
+
+    /// Gets the `utf8_validation` feature.
+    pub fn utf8_validation(&self) -> Utf8Validation {
+        self.utf8_validation
+    }
+
+    /// Gets the `message_encoding` feature.
+    pub fn message_encoding(&self) -> MessageEncoding {
+        self.message_encoding
+    }
+
+    /// Gets the `json_format` feature.
+    pub fn json_format(&self) -> JsonFormat {
+        self.json_format
+    }
+
+    /// Gets the resolved `google.protobuf.FeatureSet` message, including custom features set as extensions.
+    ///
+    /// This is only available if the pool defines `google.protobuf.FeatureSet`.
+    pub fn message(&self) -> Option<&DynamicMessage> {
+        self.message.as_ref()
+    }
+
+    /// Merges the `features` option of an element into the inherited features.
+    fn apply_options(&mut self, options: &DynamicMessage) {
+        let Some(field) = options.descriptor().get_field_by_name("features") else {
+            return;
+        };
+        if !options.has_field(&field) {
+            return;
+        }
+        let Some(overrides) = options.get_field(&field).as_message().cloned() else {
+            return;
+        };
+
+        self.apply_core(&overrides);
+        if let Some(message) = &mut self.message {
+            if message.merge(overrides.encode_to_vec().as_slice()).is_err() {
+                debug_assert!(false, "failed to merge features");
+            }
+        }
+    }
+
+    /// Overrides the core features which are set in a `google.protobuf.FeatureSet` message.
+    fn apply_core(&mut self, features: &DynamicMessage) {
+        let get = |name: &str| {
+            let field = features.descriptor().get_field_by_name(name)?;
+            if features.has_field(&field) {
+                features.get_field(&field).as_enum_number()
+            } else {
+                None
+            }
+        };
+
+        if let Some(value) = get("field_presence").and_then(FieldPresence::from_number) {
+            self.field_presence = value;
+        }
+        if let Some(value) = get("enum_type").and_then(EnumType::from_number) {
+            self.enum_type = value;
+        }
+        if let Some(value) =
+            get("repeated_field_encoding").and_then(RepeatedFieldEncoding::from_number)
+        {
+            self.repeated_field_encoding = value;
+        }
+        if let Some(value) = get("utf8_validation").and_then(Utf8Validation::from_number) {
+            self.utf8_validation = value;
+        }
+        if let Some(value) = get("message_encoding").and_then(MessageEncoding::from_number) {
+            self.message_encoding = value;
+        }
+        if let Some(value) = get("json_format").and_then(JsonFormat::from_number) {
+            self.json_format = value;
+        }
+    }
+
+    /// Applies the features implied by the definition of a field in a `proto2` or `proto3` file, as `protoc` does.
+    fn infer_legacy(&mut self, field: &FieldDescriptorProto, edition: Edition) {
+        if field.label() == Label::Required {
+            self.set_enum("field_presence", 3);
+            self.field_presence = FieldPresence::LegacyRequired;
+        }
+        if field.r#type() == Type::Group {
+            self.set_enum("message_encoding", 2);
+            self.message_encoding = MessageEncoding::Delimited;
+        }
+        match field.options.as_ref().and_then(|options| options.packed) {
+            Some(true) => {
+                self.set_enum("repeated_field_encoding", 1);
+                self.repeated_field_encoding = RepeatedFieldEncoding::Packed;
+            }
+            Some(false) if edition == Edition::Proto3 => {
+                self.set_enum("repeated_field_encoding", 2);
+                self.repeated_field_encoding = RepeatedFieldEncoding::Expanded;
+            }
+            _ => (),
+        }
+    }
+
+    /// Sets a core feature in the resolved message, if there is one.
+    fn set_enum(&mut self, name: &str, number: i32) {
+        if let Some(message) = &mut self.message {
+            if let Some(field) = message.descriptor().get_field_by_name(name) {
+                message.set_field(&field, Value::EnumNumber(number));
+            }
+        }
+    }
+}
+
+impl FeatureResolver {
+    /// Creates a resolver for the elements of a file.
+    pub fn new(file: &FileDescriptor) -> Self {
+        let edition = Edition::of(file);
+        let mut features = FeatureSet::defaults(file.parent_pool(), edition);
+        features.apply_options(&file.options());
+        FeatureResolver {
+            file: file.clone(),
+            edition,
+            features,
+        }
+    }
+
+    /// Gets the resolved features of the file.
+    pub fn for_file(&self) -> &FeatureSet {
+        &self.features
+    }
+
+    /// Resolves the features of a message declared in the file.
+    pub fn for_message(&self, message: &MessageDescriptor) -> FeatureSet {
+        debug_assert_eq!(message.parent_file(), self.file);
+        let mut features = match message.parent_message() {
+            Some(parent) => self.for_message(&parent),
+            None => self.features.clone(),
+        };
+        features.apply_options(&message.options());
+        features
+    }
+
+    /// Resolves the features of a field declared in the file, including the features inferred from its label, type
+    /// and `packed` option.
+    pub fn for_field(&self, field: &FieldDescriptor) -> FeatureSet {
+        let mut features = match field.containing_oneof() {
+            Some(oneof) => self.for_oneof(&oneof),
+            None => self.for_message(field.parent_message()),
+        };
+        features.infer_legacy(field.field_descriptor_proto(), self.edition);
+        features.apply_options(&field.options());
+        features
+    }
+
+    /// Resolves the features of an extension declared in the file, including the features inferred from its label,
+    /// type and `packed` option.
+    pub fn for_extension(&self, extension: &ExtensionDescriptor) -> FeatureSet {
+        debug_assert_eq!(extension.parent_file(), self.file);
+        let mut features = match extension.parent_message() {
+            Some(parent) => self.for_message(&parent),
+            None => self.features.clone(),
+        };
+        features.infer_legacy(extension.field_descriptor_proto(), self.edition);
+        features.apply_options(&extension.options());
+        features
+    }
+
+    /// Resolves the features of a oneof declared in the file.
+    pub fn for_oneof(&self, oneof: &OneofDescriptor) -> FeatureSet {
+        let mut features = self.for_message(oneof.parent_message());
+        features.apply_options(&oneof.options());
+        features
+    }
+
+    /// Resolves the features of an enum declared in the file.
+    pub fn for_enum(&self, enum_: &EnumDescriptor) -> FeatureSet {
+        debug_assert_eq!(enum_.parent_file(), self.file);
+        let mut features = match enum_.parent_message() {
+            Some(parent) => self.for_message(&parent),
+            None => self.features.clone(),
+        };
+        features.apply_options(&enum_.options());
+        features
+    }
+
+    /// Resolves the features of an enum value declared in the file.
+    pub fn for_enum_value(&self, value: &EnumValueDescriptor) -> FeatureSet {
+        let mut features = self.for_enum(value.parent_enum());
+        features.apply_options(&value.options());
+        features
+    }
+
+    /// Resolves the features of a service declared in the file.
+    pub fn for_service(&self, service: &ServiceDescriptor) -> FeatureSet {
+        debug_assert_eq!(service.parent_file(), self.file);
+        let mut features = self.features.clone();
+        features.apply_options(&service.options());
+        features
+    }
+
+    /// Resolves the features of a method declared in the file.
+    pub fn for_method(&self, method: &MethodDescriptor) -> FeatureSet {
+        let mut features = self.for_service(method.parent_service());
+        features.apply_options(&method.options());
+        features
+    }
+}
+
+/// Sets each field of a feature message to the value given by its `edition_defaults` option for an edition.
+fn set_defaults(message: &mut DynamicMessage, edition: Edition) {
+    for field in message.descriptor().fields() {
+        let options = field.options();
+        let Some(Value::List(defaults)) = options
+            .get_field_by_name("edition_defaults")
+            .as_deref()
+            .cloned()
+        else {
+            continue;
+        };
+
+        // The default of the latest edition which is not after the requested edition applies.
+        let value = defaults
+            .iter()
+            .filter_map(Value::as_message)
+            .filter_map(|default| {
+                let default_edition = default.get_field_by_name("edition")?.as_enum_number()?;
+                let value = default.get_field_by_name("value")?.as_str()?.to_owned();
+                Some((default_edition, value))
+            })
+            .filter(|(default_edition, _)| *default_edition <= edition.number())
+            .max_by_key(|(default_edition, _)| *default_edition);
+        let Some((_, value)) = value else {
+            continue;
+        };
+
+        let text = format!("{}: {}", field.name(), value);
+        if let Ok(default) = DynamicMessage::parse_text_format(message.descriptor(), &text) {
+            message.set_field(&field, default.get_field(&field).into_owned());
+        }
+    }
+}
@@ -0,0 +1,412 @@
+use std::collections::HashMap;
+
+use prost_reflect::{DescriptorPool, MessageDescriptor, Value};
+
+use super::{
+    Edition, EnumType, FeatureResolver, FeatureSet, FieldPresence, JsonFormat, MessageEncoding,
+    RepeatedFieldEncoding, Utf8Validation,
+};
+use crate::{
+    file::{ChainFileResolver, File, FileResolver, GoogleFileResolver},
+    Compiler, Error,
+};
+
+const DESCRIPTOR: &str = include_str!(concat!(
+    env!("CARGO_MANIFEST_DIR"),
+    "/protobuf/src/google/protobuf/descriptor.proto"
+));
+
+/// A subset of the feature definitions from newer versions of `descriptor.proto`.
+const FEATURE_SET: &str = r#"
+enum Edition {
+  EDITION_UNKNOWN = 0;
+  EDITION_LEGACY = 900;
+  EDITION_PROTO2 = 998;
+  EDITION_PROTO3 = 999;
+  EDITION_2023 = 1000;
+  EDITION_2024 = 1001;
+}
+
+message FeatureSet {
+  enum FieldPresence {
+    FIELD_PRESENCE_UNKNOWN = 0;
+    EXPLICIT = 1;
+    IMPLICIT = 2;
+    LEGACY_REQUIRED = 3;
+  }
+  optional FieldPresence field_presence = 1 [
+    edition_defaults = { edition: EDITION_LEGACY, value: "EXPLICIT" },
+    edition_defaults = { edition: EDITION_PROTO3, value: "IMPLICIT" },
+    edition_defaults = { edition: EDITION_2023, value: "EXPLICIT" }
+  ];
+
+  enum EnumType {
+    ENUM_TYPE_UNKNOWN = 0;
+    OPEN = 1;
+    CLOSED = 2;
+  }
+  optional EnumType enum_type = 2 [
+    edition_defaults = { edition: EDITION_LEGACY, value: "CLOSED" },
+    edition_defaults = { edition: EDITION_PROTO3, value: "OPEN" }
+  ];
+
+  enum RepeatedFieldEncoding {
+    REPEATED_FIELD_ENCODING_UNKNOWN = 0;
+    PACKED = 1;
+    EXPANDED = 2;
+  }
+  optional RepeatedFieldEncoding repeated_field_encoding = 3 [
+    edition_defaults = { edition: EDITION_LEGACY, value: "EXPANDED" },
+    edition_defaults = { edition: EDITION_PROTO3, value: "PACKED" }
+  ];
+
+  extensions 1000 to max;
+}
+"#;
+
+const CUSTOM_FEATURES: &str = r#"
+syntax = "proto2";
+package custom;
+import "google/protobuf/descriptor.proto";
+
+message CustomFeatures {
+  optional bool legacy_names = 1 [
+    edition_defaults = { edition: EDITION_LEGACY, value: "true" },
+    edition_defaults = { edition: EDITION_PROTO3, value: "false" }
+  ];
+  optional int32 level = 2 [
+    edition_defaults = { edition: EDITION_LEGACY, value: "1" }
+  ];
+}
+
+extend google.protobuf.FeatureSet {
+  optional CustomFeatures custom = 1000;
+}
+"#;
+
+struct TestFileResolver {
+    files: HashMap<&'static str, String>,
+}
+
+impl FileResolver for TestFileResolver {
+    fn open_file(&self, name: &str) -> Result<File, Error> {
+        match self.files.get(name) {
+            Some(source) => File::from_source(name, source),
+            None => Err(Error::file_not_found(name)),
+        }
+    }
+}
+
+/// Adds the feature definitions to `descriptor.proto`.
+fn descriptor_with_features() -> String {
+    let mut source = DESCRIPTOR.to_owned();
+    for (message, number) in [
+        ("FileOptions", 50),
+        ("MessageOptions", 12),
+        ("OneofOptions", 1),
+        ("EnumOptions", 7),
+        ("EnumValueOptions", 2),
+        ("ServiceOptions", 34),
+        ("MethodOptions", 35),
+    ] {
+        let start = format!("message {} {{", message);
+        source = source.replace(
+            &start,
+            &format!("{}\n  optional FeatureSet features = {};", start, number),
+        );
+    }
+    source = source.replace(
+        "message FieldOptions {",
+        "message FieldOptions {
+  optional FeatureSet features = 21;
+  message EditionDefault {
+    optional Edition edition = 3;
+    optional string value = 2;
+  }
+  repeated EditionDefault edition_defaults = 20;
+",
+    );
+    source.push_str(FEATURE_SET);
+    source
+}
+
+fn compile(files: &[(&'static str, &str)], name: &str) -> DescriptorPool {
+    let mut resolver = ChainFileResolver::new();
+    resolver.add(TestFileResolver {
+        files: files
+            .iter()
+            .map(|&(name, source)| (name, source.to_owned()))
+            .collect(),
+    });
+    resolver.add(GoogleFileResolver::new());
+
+    let mut compiler = Compiler::with_file_resolver(resolver);
+    compiler.open_file(name).unwrap();
+    compiler.descriptor_pool()
+}
+
+fn message(pool: &DescriptorPool, name: &str) -> MessageDescriptor {
+    pool.get_message_by_name(name).unwrap()
+}
+
+#[test]
+fn legacy_defaults() {
+    let pool = DescriptorPool::new();
+
+    let proto2 = FeatureSet::defaults(&pool, Edition::Proto2);
+    assert_eq!(proto2.field_presence(), FieldPresence::Explicit);
+    assert_eq!(proto2.enum_type(), EnumType::Closed);
+    assert_eq!(
+        proto2.repeated_field_encoding(),
+        RepeatedFieldEncoding::Expanded
+    );
+    assert_eq!(proto2.utf8_validation(), Utf8Validation::None);
+    assert_eq!(proto2.message_encoding(), MessageEncoding::LengthPrefixed);
+    assert_eq!(proto2.json_format(), JsonFormat::LegacyBestEffort);
+    assert!(proto2.message().is_none());
+
+    let proto3 = FeatureSet::defaults(&pool, Edition::Proto3);
+    assert_eq!(proto3.field_presence(), FieldPresence::Implicit);
+    assert_eq!(proto3.enum_type(), EnumType::Open);
+    assert_eq!(
+        proto3.repeated_field_encoding(),
+        RepeatedFieldEncoding::Packed
+    );
+    assert_eq!(proto3.utf8_validation(), Utf8Validation::Verify);
+    assert_eq!(proto3.json_format(), JsonFormat::Allow);
+}
+
+#[test]
+fn legacy_inference() {
+    let pool = compile(
+        &[(
+            "root.proto",
+            "syntax = 'proto2';
+            message Foo {
+              required int32 a = 1;
+              optional group B = 2 {}
+              repeated int32 c = 3 [packed = true];
+              repeated int32 d = 4;
+              oneof o { int32 e = 5; }
+            }
+            enum Bar { BAR = 0; }",
+        )],
+        "root.proto",
+    );
+    let foo = message(&pool, "Foo");
+    let resolver = FeatureResolver::new(&foo.parent_file());
+    let field = |name: &str| resolver.for_field(&foo.get_field_by_name(name).unwrap());
+
+    assert_eq!(field("a").field_presence(), FieldPresence::LegacyRequired);
+    assert_eq!(field("b").message_encoding(), MessageEncoding::Delimited);
+    assert_eq!(field("b").field_presence(), FieldPresence::Explicit);
+    assert_eq!(
+        field("c").repeated_field_encoding(),
+        RepeatedFieldEncoding::Packed
+    );
+    assert_eq!(
+        field("d").repeated_field_encoding(),
+        RepeatedFieldEncoding::Expanded
+    );
+    assert_eq!(field("e").field_presence(), FieldPresence::Explicit);
+    assert_eq!(
+        resolver
+            .for_enum(&pool.get_enum_by_name("Bar").unwrap())
+            .enum_type(),
+        EnumType::Closed
+    );
+
+    let pool = compile(
+        &[(
+            "root.proto",
+            "syntax = 'proto3';
+            message Foo {
+              repeated int32 a = 1;
+              repeated int32 b = 2 [packed = false];
+            }",
+        )],
+        "root.proto",
+    );
+    let foo = message(&pool, "Foo");
+    let resolver = FeatureResolver::new(&foo.parent_file());
+    let field = |name: &str| resolver.for_field(&foo.get_field_by_name(name).unwrap());
+
+    assert_eq!(field("a").field_presence(), FieldPresence::Implicit);
+    assert_eq!(
+        field("a").repeated_field_encoding(),
+        RepeatedFieldEncoding::Packed
+    );
+    assert_eq!(
+        field("b").repeated_field_encoding(),
+        RepeatedFieldEncoding::Expanded
+    );
+}
+
+#[test]
+fn inherit_and_override() {
+    let descriptor = descriptor_with_features();
+    let pool = compile(
+        &[
+            ("google/protobuf/descriptor.proto", &descriptor),
+            (
+                "root.proto",
+                "syntax = 'proto2';
+                import 'google/protobuf/descriptor.proto';
+                option features.enum_type = OPEN;
+                message Foo {
+                  option features.field_presence = IMPLICIT;
+                  optional int32 a = 1;
+                  optional int32 b = 2 [features.field_presence = EXPLICIT];
+                  oneof o {
+                    option features.repeated_field_encoding = PACKED;
+                    int32 c = 3;
+                  }
+                  message Nested {
+                    optional int32 d = 1;
+                  }
+                  enum Bar {
+                    option features.enum_type = CLOSED;
+                    BAR = 0;
+                  }
+                }
+                enum Baz { BAZ = 0; }",
+            ),
+        ],
+        "root.proto",
+    );
+
+    let resolver = FeatureResolver::new(&pool.get_file_by_name("root.proto").unwrap());
+    let file = resolver.for_file();
+    assert_eq!(file.enum_type(), EnumType::Open);
+    assert_eq!(file.field_presence(), FieldPresence::Explicit);
+    let file = file.message().unwrap();
+    assert_eq!(
+        file.get_field_by_name("enum_type")
+            .unwrap()
+            .as_enum_number(),
+        Some(1)
+    );
+
+    let foo = message(&pool, "Foo");
+    let field = |name: &str| resolver.for_field(&foo.get_field_by_name(name).unwrap());
+    assert_eq!(field("a").field_presence(), FieldPresence::Implicit);
+    assert_eq!(field("b").field_presence(), FieldPresence::Explicit);
+    assert_eq!(field("c").field_presence(), FieldPresence::Implicit);
+    assert_eq!(
+        field("c").repeated_field_encoding(),
+        RepeatedFieldEncoding::Packed
+    );
+    assert_eq!(
+        field("a").repeated_field_encoding(),
+        RepeatedFieldEncoding::Expanded
+    );
+
+    let nested = message(&pool, "Foo.Nested");
+    let d = resolver.for_field(&nested.get_field_by_name("d").unwrap());
+    assert_eq!(d.field_presence(), FieldPresence::Implicit);
+    assert_eq!(d.enum_type(), EnumType::Open);
+
+    let bar = pool.get_enum_by_name("Foo.Bar").unwrap();
+    assert_eq!(resolver.for_enum(&bar).enum_type(), EnumType::Closed);
+    assert_eq!(
+        resolver
+            .for_enum_value(&bar.get_value_by_name("BAR").unwrap())
+            .enum_type(),
+        EnumType::Closed
+    );
+    let baz = pool.get_enum_by_name("Baz").unwrap();
+    assert_eq!(resolver.for_enum(&baz).enum_type(), EnumType::Open);
+}
+
+#[test]
+fn custom_features() {
+    let descriptor = descriptor_with_features();
+    let files = [
+        ("google/protobuf/descriptor.proto", descriptor.as_str()),
+        ("custom.proto", CUSTOM_FEATURES),
+        (
+            "root.proto",
+            "syntax = 'proto3';
+            import 'custom.proto';
+            message Foo {
+              option features.(custom.custom).level = 3;
+              int32 a = 1;
+            }
+            service Service {
+              rpc Method(Foo) returns (Foo) {
+                option features.(custom.custom).legacy_names = true;
+              }
+            }",
+        ),
+    ];
+    let pool = compile(&files, "root.proto");
+    let extension = pool.get_extension_by_name("custom.custom").unwrap();
+    let custom = |features: &FeatureSet| {
+        let value = features.message().unwrap().get_extension(&extension);
+        let value = value.as_message().unwrap();
+        (
+            value.get_field_by_name("legacy_names").unwrap().as_bool(),
+            value.get_field_by_name("level").unwrap().as_i32(),
+        )
+    };
+
+    assert_eq!(
+        custom(&FeatureSet::defaults(&pool, Edition::Proto2)),
+        (Some(true), Some(1))
+    );
+    assert_eq!(
+        custom(&FeatureSet::defaults(&pool, Edition::Proto3)),
+        (Some(false), Some(1))
+    );
+
+    let foo = message(&pool, "Foo");
+    let resolver = FeatureResolver::new(&foo.parent_file());
+    assert_eq!(custom(&resolver.for_message(&foo)), (Some(false), Some(3)));
+    let a = resolver.for_field(&foo.get_field_by_name("a").unwrap());
+    assert_eq!(custom(&a), (Some(false), Some(3)));
+    assert_eq!(a.field_presence(), FieldPresence::Implicit);
+
+    let service = pool.get_service_by_name("Service").unwrap();
+    assert_eq!(
+        custom(&resolver.for_service(&service)),
+        (Some(false), Some(1))
+    );
+    let method = service.methods().next().unwrap();
+    assert_eq!(custom(&resolver.for_method(&method)), (Some(true), Some(1)));
+    assert!(matches!(
+        resolver
+            .for_method(&method)
+            .message()
+            .unwrap()
+            .get_field_by_name("field_presence")
+            .as_deref(),
+        Some(Value::EnumNumber(2))
+    ));
+}
+
+#[test]
+fn bundled_descriptor() {
+    let pool = compile(
+        &[(
+            "root.proto",
+            "syntax = 'proto3';
+            import 'google/protobuf/descriptor.proto';
+            message Foo {
+              repeated int32 a = 1 [features.repeated_field_encoding = EXPANDED];
+            }",
+        )],
+        "root.proto",
+    );
+
+    let foo = message(&pool, "Foo");
+    let resolver = FeatureResolver::new(&foo.parent_file());
+    let file = resolver.for_file().message().unwrap();
+    assert!(matches!(
+        file.get_field_by_name("field_presence").as_deref(),
+        Some(Value::EnumNumber(2))
+    ));
+
+    let a = resolver.for_field(&foo.get_field_by_name("a").unwrap());
+    assert_eq!(a.repeated_field_encoding(), RepeatedFieldEncoding::Expanded);
+    assert_eq!(a.utf8_validation(), Utf8Validation::Verify);
+}
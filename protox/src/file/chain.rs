@@ -1,14 +1,20 @@
 use std::{fmt, path::Path};
 
-use super::{File, FileResolver};
+use super::{File, FileResolver, ImportResolution};
 use crate::Error;
 
 /// An implementation of [`FileResolver`] which chains together several other resolvers.
 ///
-/// When opening files, each resolver is searched in turn until the file is found.
+/// When opening files, each resolver is searched in turn until the file is found. If no resolver has the file, the
+/// returned error lists the label of each resolver that was searched.
 #[derive(Default)]
 pub struct ChainFileResolver {
-    resolvers: Vec<Box<dyn FileResolver>>,
+    resolvers: Vec<Entry>,
+}
+
+struct Entry {
+    label: Option<String>,
+    resolver: Box<dyn FileResolver>,
 }
 
 impl ChainFileResolver {
@@ -24,14 +30,101 @@ impl ChainFileResolver {
     where
         F: FileResolver + 'static,
     {
-        self.resolvers.push(Box::new(resolver))
+        self.resolvers.push(Entry {
+            label: None,
+            resolver: Box::new(resolver),
+        })
+    }
+
+    /// Inserts a new resolver at position `index`, so that it is searched before the resolver currently at that
+    /// position and all those after it.
+    ///
+    /// An index of `0` gives the new resolver the highest priority.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is greater than the number of resolvers.
+    pub fn insert<F>(&mut self, index: usize, resolver: F)
+    where
+        F: FileResolver + 'static,
+    {
+        self.resolvers.insert(
+            index,
+            Entry {
+                label: None,
+                resolver: Box::new(resolver),
+            },
+        )
+    }
+
+    /// Removes and returns the resolver at position `index`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of bounds.
+    pub fn remove(&mut self, index: usize) -> Box<dyn FileResolver> {
+        self.resolvers.remove(index).resolver
+    }
+
+    /// Returns the number of resolvers in this chain.
+    pub fn len(&self) -> usize {
+        self.resolvers.len()
+    }
+
+    /// Returns `true` if this chain contains no resolvers.
+    pub fn is_empty(&self) -> bool {
+        self.resolvers.is_empty()
+    }
+
+    /// Gets an iterator over the resolvers in this chain, in the order they are searched.
+    pub fn iter(&self) -> impl ExactSizeIterator<Item = &dyn FileResolver> + '_ {
+        self.resolvers.iter().map(|entry| &*entry.resolver)
+    }
+
+    /// Gets the label of the resolver at position `index`, or `None` if it is out of bounds.
+    ///
+    /// Unless set with [`set_label`](ChainFileResolver::set_label), this is the resolver's
+    /// [`description`](FileResolver::description).
+    pub fn label(&self, index: usize) -> Option<String> {
+        self.resolvers.get(index).map(Entry::label)
+    }
+
+    /// Sets the label used to identify the resolver at position `index` in "file not found" errors.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of bounds.
+    pub fn set_label(&mut self, index: usize, label: impl Into<String>) {
+        self.resolvers[index].label = Some(label.into());
+    }
+
+    fn not_found(&self, name: &str, errors: Vec<Error>) -> Error {
+        let searched = self
+            .resolvers
+            .iter()
+            .zip(errors)
+            .flat_map(|(entry, err)| match (&entry.label, err.searched()) {
+                (None, Some(searched)) => searched.to_vec(),
+                _ => vec![entry.label()],
+            })
+            .collect();
+        Error::file_not_found_in(name, searched)
+    }
+}
+
+impl Entry {
+    fn label(&self) -> String {
+        match &self.label {
+            Some(label) => label.clone(),
+            None => self.resolver.description(),
+        }
     }
 }
 
 impl FileResolver for ChainFileResolver {
     fn resolve_path(&self, path: &Path) -> Option<String> {
-        for resolver in &self.resolvers {
-            if let Some(name) = resolver.resolve_path(path) {
+        for entry in &self.resolvers {
+            if let Some(name) = entry.resolver.resolve_path(path) {
                 return Some(name);
             }
         }
@@ -40,20 +133,43 @@ impl FileResolver for ChainFileResolver {
     }
 
     fn open_file(&self, name: &str) -> Result<File, Error> {
-        for resolver in &self.resolvers {
-            match resolver.open_file(name) {
+        let mut errors = Vec::with_capacity(self.resolvers.len());
+        for entry in &self.resolvers {
+            match entry.resolver.open_file(name) {
+                Ok(file) => return Ok(file),
+                Err(err) if err.is_file_not_found() => errors.push(err),
+                Err(err) => return Err(err),
+            }
+        }
+
+        Err(self.not_found(name, errors))
+    }
+
+    fn description(&self) -> String {
+        "chain".to_owned()
+    }
+
+    fn open_file_traced(&self, name: &str, trace: &mut ImportResolution) -> Result<File, Error> {
+        let mut errors = Vec::with_capacity(self.resolvers.len());
+        for entry in &self.resolvers {
+            match entry.resolver.open_file_traced(name, trace) {
                 Ok(file) => return Ok(file),
-                Err(err) if err.is_file_not_found() => continue,
+                Err(err) if err.is_file_not_found() => errors.push(err),
                 Err(err) => return Err(err),
             }
         }
 
-        Err(Error::file_not_found(name))
+        Err(self.not_found(name, errors))
     }
 }
 
 impl fmt::Debug for ChainFileResolver {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        f.debug_struct("ChainFileResolver").finish_non_exhaustive()
+        f.debug_struct("ChainFileResolver")
+            .field(
+                "resolvers",
+                &self.resolvers.iter().map(Entry::label).collect::<Vec<_>>(),
+            )
+            .finish()
     }
 }
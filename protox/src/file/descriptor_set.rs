@@ -1,3 +1,5 @@
+use std::time::Duration;
+
 use bytes::{Buf, Bytes};
 use prost::{
     encoding::{check_wire_type, decode_key, decode_varint, skip_field, DecodeContext, WireType},
@@ -74,12 +76,17 @@ impl FileResolver for DescriptorSetFileResolver {
                     source: None,
                     descriptor: file.file.clone(),
                     encoded: file.encoded.clone(),
+                    parse_time: Duration::ZERO,
                 });
             }
         }
 
         Err(Error::file_not_found(name))
     }
+
+    fn description(&self) -> String {
+        "file descriptor set".to_owned()
+    }
 }
 
 impl FileDescriptor {
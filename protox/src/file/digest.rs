@@ -0,0 +1,68 @@
+use std::{
+    fmt::Write,
+    path::{Path, PathBuf},
+};
+
+use prost::Message;
+use sha2::{Digest, Sha256};
+
+use super::File;
+
+/// The SHA-256 hash of the contents of a file read by a [`Compiler`](crate::Compiler).
+///
+/// See [`Compiler::input_digests`](crate::Compiler::input_digests).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InputDigest {
+    name: String,
+    path: Option<PathBuf>,
+    sha256: [u8; 32],
+}
+
+impl InputDigest {
+    /// Hashes the source text of the file, or its encoded descriptor if it has no source.
+    pub(crate) fn new(file: &File) -> Self {
+        let sha256 = match (&file.source, &file.encoded) {
+            (Some(source), _) => sha256(source.as_bytes()),
+            (None, Some(encoded)) => sha256(encoded),
+            (None, None) => sha256(&file.descriptor.encode_to_vec()),
+        };
+        InputDigest {
+            name: file.name().to_owned(),
+            path: file.path().map(ToOwned::to_owned),
+            sha256,
+        }
+    }
+
+    /// Gets the name of the file.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Gets the path the file was read from, if it was read from the file system.
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// Gets the SHA-256 hash of the file contents.
+    pub fn sha256(&self) -> &[u8; 32] {
+        &self.sha256
+    }
+
+    /// Gets the hash formatted as `sha256:<hex>`.
+    pub fn digest(&self) -> String {
+        format!("sha256:{}", to_hex(&self.sha256))
+    }
+}
+
+pub(crate) fn to_hex(bytes: &[u8]) -> String {
+    let mut hex = String::with_capacity(bytes.len() * 2);
+    for byte in bytes {
+        write!(hex, "{:02x}", byte).unwrap();
+    }
+    hex
+}
+
+/// Computes the SHA-256 hash of the data.
+pub(crate) fn sha256(data: &[u8]) -> [u8; 32] {
+    Sha256::digest(data).into()
+}
@@ -0,0 +1,180 @@
+use std::{
+    collections::{hash_map, HashMap, HashSet, VecDeque},
+    path::{Path, PathBuf},
+};
+
+use super::{File, FileResolver, GoogleFileResolver};
+use crate::{error::ErrorKind, Error};
+
+/// Infers the include paths needed to compile a set of files, by finding the files they import on the file system.
+///
+/// Each import is first looked up in the include paths found so far, and then in the files provided by
+/// [`GoogleFileResolver`]. Otherwise, the directory of the importing file and each of its parent directories are
+/// searched for a file with the imported name, and the directory containing it is added to the include paths. For
+/// relative paths, only directories within the current directory are searched. Finally, the directory of each input
+/// file which is not within any of the include paths is added, so the result is the minimal set of include paths
+/// needed to compile the files. The include paths are returned in the order they were found, which is the order they
+/// should be searched.
+///
+/// Use [`Compiler::with_discovered_includes`](crate::Compiler::with_discovered_includes) to compile the files with
+/// the discovered include paths.
+///
+/// # Errors
+///
+/// Returns an error if a file cannot be parsed, or an import is not found in any parent directory. It is also an
+/// error if an import is found in more than one parent directory, or a file is imported by different names, since
+/// the intended include path is ambiguous.
+///
+/// # Examples
+///
+/// ```
+/// # use std::fs;
+/// # let tempdir = tempfile::TempDir::new().unwrap();
+/// # std::env::set_current_dir(&tempdir).unwrap();
+/// fs::create_dir_all("proto/foo").unwrap();
+/// fs::write("proto/foo/bar.proto", "message Bar {}").unwrap();
+/// fs::write("proto/foo/root.proto", "import 'foo/bar.proto';").unwrap();
+///
+/// let includes = protox::file::discover_includes(["proto/foo/root.proto"])?;
+/// assert_eq!(includes, [std::path::Path::new("proto")]);
+/// # Ok::<(), protox::Error>(())
+/// ```
+pub fn discover_includes(
+    files: impl IntoIterator<Item = impl AsRef<Path>>,
+) -> Result<Vec<PathBuf>, Error> {
+    let roots: Vec<PathBuf> = files
+        .into_iter()
+        .map(|file| file.as_ref().to_owned())
+        .collect();
+
+    let mut discovery = Discovery {
+        includes: Vec::new(),
+        google: GoogleFileResolver::new(),
+        names: HashMap::new(),
+    };
+
+    let mut queue: VecDeque<PathBuf> = roots.iter().cloned().collect();
+    let mut visited = HashSet::new();
+    while let Some(path) = queue.pop_front() {
+        if !visited.insert(path.clone()) {
+            continue;
+        }
+
+        let name = match discovery.names.get(&path) {
+            Some(name) => name.clone(),
+            None => path.display().to_string(),
+        };
+        let file = File::open(&name, &path)?;
+        for (index, import) in file.descriptor.dependency.iter().enumerate() {
+            let import_path = discovery
+                .find_import(&name, &path, import)
+                .map_err(|err| err.into_import_error(&file, index))?;
+            if let Some(import_path) = import_path {
+                discovery.add_name(&import_path, import)?;
+                queue.push_back(import_path);
+            }
+        }
+    }
+
+    for root in &roots {
+        let parent = root.parent().unwrap_or(Path::new(""));
+        let name = match discovery
+            .includes
+            .iter()
+            .find_map(|include| root.strip_prefix(include).ok())
+        {
+            Some(name) => name.to_owned(),
+            None => {
+                discovery.includes.push(parent.to_owned());
+                root.strip_prefix(parent).unwrap().to_owned()
+            }
+        };
+        discovery.add_name(root, &name.to_string_lossy().replace('\\', "/"))?;
+    }
+
+    Ok(discovery
+        .includes
+        .into_iter()
+        .map(|include| {
+            if include.as_os_str().is_empty() {
+                PathBuf::from(".")
+            } else {
+                include
+            }
+        })
+        .collect())
+}
+
+struct Discovery {
+    /// The include paths found so far. The current directory is represented by an empty path, so that joining it
+    /// with a file name gives the same path as the file name.
+    includes: Vec<PathBuf>,
+    google: GoogleFileResolver,
+    /// The name each file found so far is imported by.
+    names: HashMap<PathBuf, String>,
+}
+
+impl Discovery {
+    /// Finds the path of an imported file, adding a new include path if necessary. Returns `None` for files provided
+    /// by [`GoogleFileResolver`].
+    fn find_import(
+        &mut self,
+        name: &str,
+        path: &Path,
+        import: &str,
+    ) -> Result<Option<PathBuf>, Error> {
+        for include in &self.includes {
+            let import_path = include.join(import);
+            if import_path.is_file() {
+                return Ok(Some(import_path));
+            }
+        }
+        if self.google.open_file(import).is_ok() {
+            return Ok(None);
+        }
+
+        let parent = path.parent().unwrap_or(Path::new(""));
+        let mut candidates = parent.ancestors().filter(|dir| dir.join(import).is_file());
+        let Some(include) = candidates.next() else {
+            let searched = parent
+                .ancestors()
+                .map(|dir| match dir.to_str() {
+                    Some("") => ".".to_owned(),
+                    _ => dir.display().to_string(),
+                })
+                .collect();
+            return Err(Error::file_not_found_in(import, searched));
+        };
+        if let Some(other) = candidates.next() {
+            return Err(Error::from_kind(ErrorKind::AmbiguousImport {
+                name: name.to_owned(),
+                import: import.to_owned(),
+                first: include.join(import),
+                second: other.join(import),
+            }));
+        }
+
+        let include = include.to_owned();
+        let import_path = include.join(import);
+        self.includes.push(include);
+        Ok(Some(import_path))
+    }
+
+    /// Records the name a file is imported by, checking it is the same as any previous import of the file.
+    fn add_name(&mut self, path: &Path, name: &str) -> Result<(), Error> {
+        match self.names.entry(path.to_owned()) {
+            hash_map::Entry::Occupied(entry) if entry.get() != name => {
+                Err(Error::from_kind(ErrorKind::InconsistentImportName {
+                    path: path.to_owned(),
+                    first: entry.get().clone(),
+                    second: name.to_owned(),
+                }))
+            }
+            hash_map::Entry::Occupied(_) => Ok(()),
+            hash_map::Entry::Vacant(entry) => {
+                entry.insert(name.to_owned());
+                Ok(())
+            }
+        }
+    }
+}
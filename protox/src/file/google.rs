@@ -2,6 +2,11 @@ use super::{File, FileResolver};
 use crate::Error;
 
 /// An implementation of [`FileResolver`] which resolves well-known imports such as `google/protobuf/descriptor.proto`.
+///
+/// If the `google-api` feature is enabled, this also resolves `google/api/annotations.proto` and
+/// `google/api/http.proto`, which define the `(google.api.http)` option used for gRPC transcoding. Similarly, the
+/// `validate` feature adds `buf/validate/validate.proto` and `validate/validate.proto`, which define the constraint
+/// options of protovalidate and protoc-gen-validate.
 #[derive(Debug, Default)]
 pub struct GoogleFileResolver {
     _priv: (),
@@ -26,7 +31,12 @@ macro_rules! include_proto {
 
 pub(crate) const ANY: &str = include_proto!("any.proto");
 pub(crate) const API: &str = include_proto!("api.proto");
-pub(crate) const DESCRIPTOR: &str = include_proto!("descriptor.proto");
+// The submodule's copy of `descriptor.proto` predates extension declarations, option retention and targets, and
+// editions features, so a newer version is bundled separately.
+pub(crate) const DESCRIPTOR: &str = include_str!(concat!(
+    env!("CARGO_MANIFEST_DIR"),
+    "/descriptor/google/protobuf/descriptor.proto"
+));
 pub(crate) const DURATION: &str = include_proto!("duration.proto");
 pub(crate) const EMPTY: &str = include_proto!("empty.proto");
 pub(crate) const FIELD_MASK: &str = include_proto!("field_mask.proto");
@@ -37,6 +47,33 @@ pub(crate) const TYPE: &str = include_proto!("type.proto");
 pub(crate) const WRAPPERS: &str = include_proto!("wrappers.proto");
 pub(crate) const COMPILER_PLUGIN: &str = include_proto!("compiler/plugin.proto");
 
+#[cfg(feature = "google-api")]
+macro_rules! include_google_api_proto {
+    ($name:literal) => {
+        include_str!(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/googleapis/google/api/",
+            $name
+        ))
+    };
+}
+
+#[cfg(feature = "google-api")]
+pub(crate) const GOOGLE_API_ANNOTATIONS: &str = include_google_api_proto!("annotations.proto");
+#[cfg(feature = "google-api")]
+pub(crate) const GOOGLE_API_HTTP: &str = include_google_api_proto!("http.proto");
+
+#[cfg(feature = "validate")]
+pub(crate) const PROTOVALIDATE: &str = include_str!(concat!(
+    env!("CARGO_MANIFEST_DIR"),
+    "/validate/buf/validate/validate.proto"
+));
+#[cfg(feature = "validate")]
+pub(crate) const PROTOC_GEN_VALIDATE: &str = include_str!(concat!(
+    env!("CARGO_MANIFEST_DIR"),
+    "/validate/validate/validate.proto"
+));
+
 impl FileResolver for GoogleFileResolver {
     fn open_file(&self, name: &str) -> Result<File, Error> {
         let source = match name {
@@ -52,9 +89,21 @@ impl FileResolver for GoogleFileResolver {
             "google/protobuf/type.proto" => TYPE,
             "google/protobuf/wrappers.proto" => WRAPPERS,
             "google/protobuf/compiler/plugin.proto" => COMPILER_PLUGIN,
+            #[cfg(feature = "google-api")]
+            "google/api/annotations.proto" => GOOGLE_API_ANNOTATIONS,
+            #[cfg(feature = "google-api")]
+            "google/api/http.proto" => GOOGLE_API_HTTP,
+            #[cfg(feature = "validate")]
+            "buf/validate/validate.proto" => PROTOVALIDATE,
+            #[cfg(feature = "validate")]
+            "validate/validate.proto" => PROTOC_GEN_VALIDATE,
             _ => return Err(Error::file_not_found(name)),
         };
 
         File::from_source(name, source)
     }
+
+    fn description(&self) -> String {
+        "built-in google files".to_owned()
+    }
 }
@@ -1,4 +1,7 @@
-use std::path::{self, Path, PathBuf};
+use std::{
+    env, fs,
+    path::{self, Path, PathBuf},
+};
 
 use crate::{error::ErrorKind, Error};
 
@@ -8,12 +11,126 @@ use super::{File, FileResolver};
 #[derive(Debug)]
 pub struct IncludeFileResolver {
     include: PathBuf,
+    follow_symlinks: bool,
+    canonicalize: bool,
+    case_sensitivity: CaseSensitivity,
+}
+
+/// Controls how an [`IncludeFileResolver`] compares file names with paths on the file system.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum CaseSensitivity {
+    /// Paths are compared case-insensitively on Windows, and case-sensitively on other platforms.
+    #[default]
+    Platform,
+    /// Paths are compared case-sensitively, and a file is only opened if the case of its name matches the file
+    /// system exactly. This prevents a file on a case-insensitive file system from being imported under several
+    /// names.
+    Sensitive,
+    /// Paths are compared ignoring ASCII case.
+    Insensitive,
 }
 
 impl IncludeFileResolver {
     /// Constructs a `IncludeFileResolver` that searches the given include path.
     pub fn new(include: PathBuf) -> Self {
-        IncludeFileResolver { include }
+        IncludeFileResolver {
+            include,
+            follow_symlinks: true,
+            canonicalize: false,
+            case_sensitivity: CaseSensitivity::Platform,
+        }
+    }
+
+    /// Sets whether files may be opened through symbolic links. Defaults to `true`.
+    ///
+    /// If disabled, opening a file is an error if the file, or any directory between it and the include path, is a
+    /// symbolic link.
+    pub fn follow_symlinks(&mut self, yes: bool) -> &mut Self {
+        self.follow_symlinks = yes;
+        self
+    }
+
+    /// Sets whether paths are canonicalized before checking if they are inside the include path. Defaults to `false`.
+    ///
+    /// By default, [`resolve_path`](FileResolver::resolve_path) compares paths lexically after normalizing `.` and `..`
+    /// components, and making relative paths absolute if the other path is absolute. Only if that fails are both
+    /// paths resolved to their real location on the file system, so a path which reaches a file through a symbolic
+    /// link is named after the path given rather than the file's real location. If enabled, paths are always
+    /// resolved to their real location first, and paths which do not exist are not resolved.
+    pub fn canonicalize(&mut self, yes: bool) -> &mut Self {
+        self.canonicalize = yes;
+        self
+    }
+
+    /// Sets how file names are compared with paths on the file system. Defaults to [`CaseSensitivity::Platform`].
+    pub fn case_sensitivity(&mut self, case_sensitivity: CaseSensitivity) -> &mut Self {
+        self.case_sensitivity = case_sensitivity;
+        self
+    }
+
+    /// Returns an error if any component of the path to the file named `name` is a symbolic link.
+    fn check_symlinks(&self, name: &str) -> Result<(), Error> {
+        let mut path = self.include.clone();
+        for component in Path::new(name).components() {
+            path.push(component);
+            match fs::symlink_metadata(&path) {
+                Ok(metadata) if metadata.file_type().is_symlink() => {
+                    return Err(Error::from_kind(ErrorKind::SymlinkNotFollowed {
+                        name: name.to_owned(),
+                        path,
+                    }))
+                }
+                Ok(_) => (),
+                // Let opening the file report the error.
+                Err(_) => break,
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns an error if the file named `name` exists, but the case of its name on the file system differs.
+    fn check_case(&self, name: &str) -> Result<(), Error> {
+        let mut path = self.include.clone();
+        let mut actual = Vec::new();
+        let mut mismatch = false;
+        for component in Path::new(name).components() {
+            let component = component.as_os_str();
+            let Ok(entries) = fs::read_dir(&path) else {
+                break;
+            };
+            let Some(entry) = entries
+                .filter_map(Result::ok)
+                .map(|entry| entry.file_name())
+                .filter(|entry| entry.eq_ignore_ascii_case(component))
+                .min_by_key(|entry| entry != component)
+            else {
+                break;
+            };
+
+            mismatch |= entry != component;
+            actual.push(entry.to_string_lossy().into_owned());
+            path.push(entry);
+        }
+
+        if mismatch {
+            Err(Error::from_kind(ErrorKind::FileNameCaseMismatch {
+                name: name.to_owned(),
+                actual: actual.join("/"),
+            }))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl CaseSensitivity {
+    fn is_insensitive(self) -> bool {
+        match self {
+            CaseSensitivity::Platform => cfg!(windows),
+            CaseSensitivity::Sensitive => false,
+            CaseSensitivity::Insensitive => true,
+        }
     }
 }
 
@@ -30,13 +147,28 @@ impl FileResolver for IncludeFileResolver {
     /// assert_eq!(resolver.resolve_path(Path::new("notincluded.proto")), None);
     /// ```
     fn resolve_path(&self, path: &Path) -> Option<String> {
-        if let Some(relative_path) = strip_prefix(path, &self.include) {
+        let ignore_case = self.case_sensitivity.is_insensitive();
+        let resolve_canonical = || {
+            let path = fs::canonicalize(path).ok()?;
+            let include = fs::canonicalize(&self.include).ok()?;
+            strip_prefix(&path, &include, ignore_case).and_then(path_to_file_name)
+        };
+        if self.canonicalize {
+            return resolve_canonical();
+        }
+
+        let (path, include) = make_comparable(path, &self.include);
+        if let Some(relative_path) =
+            strip_prefix(&normalize(&path), &normalize(&include), ignore_case)
+        {
             if let Some(name) = path_to_file_name(relative_path) {
                 return Some(name);
             }
         }
 
-        None
+        // The path may still reach a file in the include path through a symbolic link, or a different mapping of
+        // the same drive.
+        resolve_canonical()
     }
 
     /// Opens a file by its unique name.
@@ -65,32 +197,76 @@ impl FileResolver for IncludeFileResolver {
     /// assert_eq!(file.source(), Some("/* hello! */"));
     /// ```
     fn open_file(&self, name: &str) -> Result<File, Error> {
+        if !self.follow_symlinks {
+            self.check_symlinks(name)?;
+        }
+        if self.case_sensitivity == CaseSensitivity::Sensitive {
+            self.check_case(name)?;
+        }
         File::open(name, &self.include.join(name))
     }
+
+    fn description(&self) -> String {
+        format!("include path '{}'", self.include.display())
+    }
 }
 
+/// Converts a relative path to a file name, with components separated by `/`.
+///
+/// `.` components are removed, and `..` components remove the preceding component. Returns `None` if the path is
+/// absolute, is not valid UTF-8, or refers to a location outside the directory it is relative to.
 pub(crate) fn path_to_file_name(path: &Path) -> Option<String> {
-    let mut name = String::new();
+    let mut components = Vec::new();
     for component in path.components() {
         match component {
-            std::path::Component::Normal(component) => {
-                if let Some(component) = component.to_str() {
-                    if !name.is_empty() {
-                        name.push('/');
-                    }
-                    name.push_str(component);
-                } else {
-                    return None;
-                }
+            path::Component::Normal(component) => components.push(component.to_str()?),
+            path::Component::CurDir => (),
+            path::Component::ParentDir => {
+                components.pop()?;
             }
-            _ => return None,
+            path::Component::RootDir | path::Component::Prefix(_) => return None,
         }
     }
 
-    if name.is_empty() {
+    if components.is_empty() {
         None
     } else {
-        Some(name)
+        Some(components.join("/"))
+    }
+}
+
+/// Lexically normalizes a path, removing `.` components and resolving `..` components against the preceding
+/// component, in the same way as `protoc`.
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            path::Component::CurDir => (),
+            path::Component::ParentDir => match normalized.components().next_back() {
+                Some(path::Component::Normal(_)) => {
+                    normalized.pop();
+                }
+                // The parent of the root is the root.
+                Some(path::Component::RootDir) => (),
+                _ => normalized.push(component),
+            },
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+/// If exactly one of the paths is relative, makes it absolute by joining it to the current directory, so that it can
+/// be compared with the other.
+fn make_comparable(l: &Path, r: &Path) -> (PathBuf, PathBuf) {
+    let absolute = |path: &Path| match env::current_dir() {
+        Ok(current_dir) if path.is_relative() => current_dir.join(path),
+        _ => path.to_owned(),
+    };
+    if l.is_absolute() == r.is_absolute() {
+        (l.to_owned(), r.to_owned())
+    } else {
+        (absolute(l), absolute(r))
     }
 }
 
@@ -101,7 +277,7 @@ pub(crate) fn check_shadow(
 ) -> Result<(), Error> {
     // actual_path is expected to be an include path concatenated with `expected_path`
     if let Some(actual_path) = actual_path {
-        if !path_eq(actual_path, expected_path) {
+        if !path_eq(actual_path, expected_path) && !is_same_file(actual_path, expected_path) {
             return Err(Error::from_kind(ErrorKind::FileShadowed {
                 name: file.to_string(),
                 path: expected_path.to_owned(),
@@ -113,8 +289,16 @@ pub(crate) fn check_shadow(
     Ok(())
 }
 
-fn strip_prefix<'a>(path: &'a Path, prefix: &Path) -> Option<&'a Path> {
-    Some(iter_after(path.components(), prefix.components())?.as_path())
+fn strip_prefix<'a>(path: &'a Path, prefix: &Path, ignore_case: bool) -> Option<&'a Path> {
+    Some(iter_after(path.components(), prefix.components(), ignore_case)?.as_path())
+}
+
+/// Returns true if both paths exist and refer to the same file, after resolving symbolic links.
+fn is_same_file(l: &Path, r: &Path) -> bool {
+    match (fs::canonicalize(l), fs::canonicalize(r)) {
+        (Ok(l), Ok(r)) => l == r,
+        _ => false,
+    }
 }
 
 /// Naive path equality
@@ -131,7 +315,7 @@ fn path_eq(l: &Path, r: &Path) -> bool {
             (_, Some(path::Component::CurDir)) => {
                 rhs = rhs_next;
             }
-            (Some(ref l), Some(ref r)) if path_component_eq(l, r) => {
+            (Some(ref l), Some(ref r)) if path_component_eq(l, r, cfg!(windows)) => {
                 lhs = lhs_next;
                 rhs = rhs_next;
             }
@@ -140,8 +324,8 @@ fn path_eq(l: &Path, r: &Path) -> bool {
     }
 }
 
-/// Comparison of paths which ignores '.' components and is case-insensitive on windows.
-fn iter_after<'a, 'b, I, J>(mut iter: I, mut prefix: J) -> Option<I>
+/// Comparison of paths which ignores '.' components, and optionally ASCII case.
+fn iter_after<'a, 'b, I, J>(mut iter: I, mut prefix: J, ignore_case: bool) -> Option<I>
 where
     I: Iterator<Item = path::Component<'a>> + Clone,
     J: Iterator<Item = path::Component<'b>> + Clone,
@@ -157,7 +341,7 @@ where
             (_, Some(path::Component::CurDir)) => {
                 prefix = prefix_next;
             }
-            (Some(ref l), Some(ref r)) if path_component_eq(l, r) => {
+            (Some(ref l), Some(ref r)) if path_component_eq(l, r, ignore_case) => {
                 iter = path_next;
                 prefix = prefix_next;
             }
@@ -169,12 +353,12 @@ where
     }
 }
 
-#[cfg(windows)]
-fn path_component_eq(l: &path::Component, r: &path::Component) -> bool {
-    l.as_os_str().eq_ignore_ascii_case(r.as_os_str())
-}
-
-#[cfg(not(windows))]
-fn path_component_eq(l: &path::Component, r: &path::Component) -> bool {
-    l == r
+fn path_component_eq(l: &path::Component, r: &path::Component, ignore_case: bool) -> bool {
+    // Drive letters are never case-sensitive.
+    let is_prefix = matches!(l, path::Component::Prefix(_));
+    if ignore_case || is_prefix {
+        l.as_os_str().eq_ignore_ascii_case(r.as_os_str())
+    } else {
+        l == r
+    }
 }
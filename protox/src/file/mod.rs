@@ -2,28 +2,46 @@
 
 mod chain;
 mod descriptor_set;
+mod digest;
+mod discover;
 mod google;
 mod include;
+mod overlay;
+mod registry;
 #[cfg(test)]
 mod tests;
+mod trace;
 
 pub use chain::ChainFileResolver;
 pub use descriptor_set::DescriptorSetFileResolver;
+pub use digest::InputDigest;
+pub use discover::discover_includes;
 pub use google::GoogleFileResolver;
-pub use include::IncludeFileResolver;
+pub use include::{CaseSensitivity, IncludeFileResolver};
+pub use overlay::OverlayFileResolver;
 use prost_types::FileDescriptorProto;
+pub use registry::{ModuleFetcher, RegistryFileResolver};
+pub use trace::{ImportResolution, ResolutionCandidate, ResolutionOutcome};
 
 use std::{
+    collections::HashMap,
     fs,
     io::{self, Read},
     path::{Path, PathBuf},
+    process,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::{Duration, Instant},
 };
 
 use bytes::{Buf, Bytes};
 pub(crate) use include::{check_shadow, path_to_file_name};
 use prost::{DecodeError, Message};
+use protox_parse::tag;
 
-use crate::error::{Error, ErrorKind};
+use crate::{
+    compile::ranges_without_field,
+    error::{Error, ErrorKind},
+};
 
 const MAX_FILE_LEN: u64 = i32::MAX as u64;
 
@@ -31,7 +49,9 @@ const MAX_FILE_LEN: u64 = i32::MAX as u64;
 ///
 /// The main implementation is [`IncludeFileResolver`] which uses the file system, but
 /// this trait allows sourcing files from other places as well.
-pub trait FileResolver {
+///
+/// Resolvers must be [`Send`] and [`Sync`], so that a [`Compiler`](crate::Compiler) can be shared between threads.
+pub trait FileResolver: Send + Sync {
     /// Converts a file system path to a unique file name.
     fn resolve_path(&self, _path: &Path) -> Option<String> {
         None
@@ -43,6 +63,25 @@ pub trait FileResolver {
     ///
     /// If the file is not found, the implementation should return [`Error::file_not_found`].
     fn open_file(&self, name: &str) -> Result<File, Error>;
+
+    /// Returns a short, human-readable description of this resolver, such as `include path 'src'`.
+    ///
+    /// This is used to identify the resolver in an [`ImportResolution`]. The default implementation returns the
+    /// name of the type.
+    fn description(&self) -> String {
+        std::any::type_name::<Self>().to_owned()
+    }
+
+    /// Opens a file by its unique name, recording each resolver consulted in `trace`.
+    ///
+    /// The default implementation calls [`open_file`](FileResolver::open_file) and records its result. Resolvers
+    /// which delegate to several other resolvers, like [`ChainFileResolver`], should override this to record each of
+    /// them in turn.
+    fn open_file_traced(&self, name: &str, trace: &mut ImportResolution) -> Result<File, Error> {
+        let result = self.open_file(name);
+        trace.record(self.description(), &result);
+        result
+    }
 }
 
 impl<T> FileResolver for Box<T>
@@ -56,6 +95,14 @@ where
     fn open_file(&self, name: &str) -> Result<File, Error> {
         (**self).open_file(name)
     }
+
+    fn description(&self) -> String {
+        (**self).description()
+    }
+
+    fn open_file_traced(&self, name: &str, trace: &mut ImportResolution) -> Result<File, Error> {
+        (**self).open_file_traced(name, trace)
+    }
 }
 
 /// An opened protobuf source file, returned by [`FileResolver::open_file`].
@@ -65,6 +112,7 @@ pub struct File {
     pub(crate) source: Option<String>,
     pub(crate) descriptor: FileDescriptorProto,
     pub(crate) encoded: Option<Bytes>,
+    pub(crate) parse_time: Duration,
 }
 
 /// Information about a [`File`] after it has been added to a [`Compiler`](crate::Compiler) instance.
@@ -80,8 +128,8 @@ impl File {
     ///
     /// # Errors
     ///
-    /// Returns an error if there is an IO error opening the file, or it is not
-    /// a valid protobuf source file.
+    /// Returns an error if there is an IO error opening or reading the file, if it is larger than 2,147,483,647 bytes,
+    /// if it is not valid UTF-8, or if it is not a valid protobuf source file.
     ///
     /// If the file does not exist, [`Error::file_not_found()`] is returned
     ///
@@ -117,12 +165,9 @@ impl File {
     /// assert!(File::open("notfound.proto", "notfound.proto".as_ref()).unwrap_err().is_file_not_found());
     /// ```
     pub fn open(name: &str, path: &Path) -> Result<Self, Error> {
-        let map_io_err = |err: io::Error| -> Error {
+        let map_open_err = |err: io::Error| -> Error {
             match err.kind() {
                 io::ErrorKind::NotFound => Error::file_not_found(name),
-                io::ErrorKind::InvalidData => Error::from_kind(ErrorKind::FileInvalidUtf8 {
-                    name: name.to_owned(),
-                }),
                 _ => Error::from_kind(ErrorKind::OpenFile {
                     name: name.to_owned(),
                     path: path.to_owned(),
@@ -130,9 +175,8 @@ impl File {
                 }),
             }
         };
-
-        let file = fs::File::open(path).map_err(map_io_err)?;
-        let metadata = file.metadata().map_err(map_io_err)?;
+        let file = fs::File::open(path).map_err(map_open_err)?;
+        let metadata = file.metadata().map_err(map_open_err)?;
 
         if metadata.len() > MAX_FILE_LEN {
             return Err(Error::from_kind(ErrorKind::FileTooLarge {
@@ -140,18 +184,48 @@ impl File {
             }));
         }
 
-        let mut buf = String::with_capacity(metadata.len() as usize);
-        file.take(MAX_FILE_LEN)
-            .read_to_string(&mut buf)
-            .map_err(map_io_err)?;
-
-        let descriptor = protox_parse::parse(name, &buf)?;
+        let buf = read_source(name, path, Some(path), file, metadata.len())?;
+        let (descriptor, parse_time) = parse(name, &buf)?;
 
         Ok(File {
             path: Some(path.to_owned()),
             source: Some(buf),
             descriptor,
             encoded: None,
+            parse_time,
+        })
+    }
+
+    /// Read a protobuf source file from a reader, such as the standard input of the process, into a new instance of
+    /// [`File`].
+    ///
+    /// The file has no path, so errors are reported using `name`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if there is an IO error reading from `reader`, if it yields more than 2,147,483,647 bytes, if
+    /// its contents are not valid UTF-8, or if they are not a valid protobuf source file. The size limit is checked
+    /// while reading, so at most one byte past the limit is read.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use protox::file::File;
+    /// let file = File::from_reader("foo.proto", "message Foo { }".as_bytes()).unwrap();
+    /// assert_eq!(file.name(), "foo.proto");
+    /// assert_eq!(file.path(), None);
+    /// assert_eq!(file.source(), Some("message Foo { }"));
+    /// ```
+    pub fn from_reader(name: &str, reader: impl Read) -> Result<Self, Error> {
+        let buf = read_source(name, Path::new(name), None, reader, 0)?;
+        let (descriptor, parse_time) = parse(name, &buf)?;
+
+        Ok(File {
+            path: None,
+            source: Some(buf),
+            descriptor,
+            encoded: None,
+            parse_time,
         })
     }
 
@@ -187,13 +261,14 @@ impl File {
     /// });
     /// ```
     pub fn from_source(name: &str, source: &str) -> Result<Self, Error> {
-        let descriptor = protox_parse::parse(name, source)?;
+        let (descriptor, parse_time) = parse(name, source)?;
 
         Ok(File {
             path: None,
             source: Some(source.to_owned()),
             descriptor,
             encoded: None,
+            parse_time,
         })
     }
 
@@ -206,6 +281,7 @@ impl File {
             source: None,
             descriptor: file,
             encoded: None,
+            parse_time: Duration::ZERO,
         }
     }
 
@@ -226,6 +302,7 @@ impl File {
             source: None,
             descriptor: FileDescriptorProto::decode(encoded.as_ref())?,
             encoded: Some(encoded),
+            parse_time: Duration::ZERO,
         })
     }
 
@@ -250,6 +327,47 @@ impl File {
     pub fn file_descriptor_proto(&self) -> &FileDescriptorProto {
         &self.descriptor
     }
+
+    /// Changes the name of this file, for files resolved under a different name to the one imported.
+    pub(crate) fn set_name(&mut self, name: &str) {
+        self.descriptor.name = Some(name.to_owned());
+        if let Some(encoded) = &mut self.encoded {
+            // Later occurrences of a non-repeated field take precedence when decoding.
+            let mut buf = encoded.to_vec();
+            prost::encoding::string::encode(tag::file::NAME as u32, &name.to_owned(), &mut buf);
+            *encoded = Bytes::from(buf);
+        }
+    }
+
+    /// Replaces the names of any dependencies which are keys of `names` with the corresponding value.
+    pub(crate) fn rename_dependencies(&mut self, names: &HashMap<String, String>) {
+        if !self
+            .descriptor
+            .dependency
+            .iter()
+            .any(|dependency| names.contains_key(dependency))
+        {
+            return;
+        }
+
+        for dependency in &mut self.descriptor.dependency {
+            if let Some(name) = names.get(dependency) {
+                dependency.clone_from(name);
+            }
+        }
+        if let Some(encoded) = &mut self.encoded {
+            let mut buf = Vec::with_capacity(encoded.len());
+            for range in ranges_without_field(encoded, tag::file::DEPENDENCY as u32) {
+                buf.extend_from_slice(&encoded[range]);
+            }
+            prost::encoding::string::encode_repeated(
+                tag::file::DEPENDENCY as u32,
+                &self.descriptor.dependency,
+                &mut buf,
+            );
+            *encoded = Bytes::from(buf);
+        }
+    }
 }
 
 impl FileMetadata {
@@ -270,6 +388,75 @@ impl FileMetadata {
     }
 }
 
+/// Parses a source file, returning the time taken so that it can be reported separately from the time spent by
+/// the [`FileResolver`].
+fn parse(name: &str, source: &str) -> Result<(FileDescriptorProto, Duration), Error> {
+    let start = Instant::now();
+    let descriptor = protox_parse::parse(name, source)?;
+    Ok((descriptor, start.elapsed()))
+}
+
+/// Reads the contents of a source file, enforcing the maximum file length. `display_path` is used in IO errors, while
+/// `path` is recorded in UTF-8 errors.
+fn read_source(
+    name: &str,
+    display_path: &Path,
+    path: Option<&Path>,
+    reader: impl Read,
+    len_hint: u64,
+) -> Result<String, Error> {
+    // The length reported by the metadata may be zero or out of date, for example for pipes or files which are
+    // still being written, so the limit is also enforced while reading.
+    let mut buf = Vec::with_capacity(len_hint as usize);
+    reader
+        .take(MAX_FILE_LEN + 1)
+        .read_to_end(&mut buf)
+        .map_err(|err| {
+            Error::from_kind(ErrorKind::ReadFile {
+                name: name.to_owned(),
+                path: display_path.to_owned(),
+                err,
+            })
+        })?;
+    if buf.len() as u64 > MAX_FILE_LEN {
+        return Err(Error::from_kind(ErrorKind::FileTooLarge {
+            name: name.to_owned(),
+        }));
+    }
+    String::from_utf8(buf)
+        .map_err(|err| Error::invalid_utf8(name, path, err.as_bytes(), err.utf8_error()))
+}
+
+/// Creates a new temporary file or directory next to `path` using `create`, returning it along with its path.
+///
+/// The name includes the process id and a counter, and `create` must fail with [`io::ErrorKind::AlreadyExists`] if the
+/// path is taken, so concurrent writers in any thread or process never share a temporary path.
+pub(crate) fn create_temp<T>(
+    path: &Path,
+    create: impl Fn(&Path) -> io::Result<T>,
+) -> io::Result<(T, PathBuf)> {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "not a file path"))?;
+    loop {
+        let mut temp_name = std::ffi::OsString::from(".");
+        temp_name.push(file_name);
+        temp_name.push(format!(
+            ".{}.{}.tmp",
+            process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        let temp_path = path.with_file_name(temp_name);
+        match create(&temp_path) {
+            Ok(value) => return Ok((value, temp_path)),
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(err) => return Err(err),
+        }
+    }
+}
+
 impl From<FileDescriptorProto> for File {
     fn from(file: FileDescriptorProto) -> Self {
         File::from_file_descriptor_proto(file)
@@ -0,0 +1,156 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use crate::Error;
+
+use super::{File, FileResolver, ImportResolution};
+
+/// An implementation of [`FileResolver`] which layers in-memory sources over another resolver.
+///
+/// Files added to the overlay take precedence over files of the same name in the base resolver, and all other files
+/// are opened from the base resolver. This is useful for editors, which need to compile the unsaved contents of open
+/// buffers against the rest of the files on disk.
+///
+/// # Examples
+///
+/// ```
+/// # use std::{fs, path::PathBuf};
+/// # use protox::{file::{IncludeFileResolver, OverlayFileResolver}, Compiler};
+/// # let tempdir = tempfile::TempDir::new().unwrap();
+/// # let include = tempdir.path().to_owned();
+/// fs::write(include.join("dep.proto"), "message Dep {}").unwrap();
+/// fs::write(include.join("root.proto"), "message Root {}").unwrap();
+///
+/// let mut resolver = OverlayFileResolver::new(IncludeFileResolver::new(include.clone()));
+/// resolver.set_file_with_path(
+///     "root.proto",
+///     include.join("root.proto"),
+///     "import 'dep.proto'; message Root { optional Dep dep = 1; }",
+/// );
+///
+/// let mut compiler = Compiler::with_file_resolver(resolver);
+/// compiler.open_file(include.join("root.proto"))?;
+/// assert!(compiler.descriptor_pool().get_message_by_name("Root").unwrap().get_field_by_name("dep").is_some());
+/// # Ok::<(), protox::Error>(())
+/// ```
+#[derive(Debug)]
+pub struct OverlayFileResolver<R> {
+    base: R,
+    files: HashMap<String, OverlayFile>,
+}
+
+#[derive(Debug)]
+struct OverlayFile {
+    path: Option<PathBuf>,
+    source: String,
+}
+
+impl<R> OverlayFileResolver<R>
+where
+    R: FileResolver,
+{
+    /// Creates a new, empty overlay over the given resolver.
+    pub fn new(base: R) -> Self {
+        OverlayFileResolver {
+            base,
+            files: HashMap::new(),
+        }
+    }
+
+    /// Gets the resolver used for files which are not in the overlay.
+    pub fn base(&self) -> &R {
+        &self.base
+    }
+
+    /// Adds a file to the overlay, replacing any previous contents.
+    ///
+    /// The file is not backed by the file system, so [`File::path`] returns `None` for it. Use
+    /// [`set_file_with_path`](OverlayFileResolver::set_file_with_path) for unsaved changes to a file on disk.
+    pub fn set_file(&mut self, name: impl Into<String>, source: impl Into<String>) {
+        self.files.insert(
+            name.into(),
+            OverlayFile {
+                path: None,
+                source: source.into(),
+            },
+        );
+    }
+
+    /// Adds a file to the overlay, replacing any previous contents, as the contents of the file at `path`.
+    ///
+    /// The path is reported by [`File::path`], so that diagnostics refer to the same location whether or not the file
+    /// has unsaved changes, and [`resolve_path`](FileResolver::resolve_path) maps it to `name`.
+    pub fn set_file_with_path(
+        &mut self,
+        name: impl Into<String>,
+        path: impl Into<PathBuf>,
+        source: impl Into<String>,
+    ) {
+        self.files.insert(
+            name.into(),
+            OverlayFile {
+                path: Some(path.into()),
+                source: source.into(),
+            },
+        );
+    }
+
+    /// Removes a file from the overlay, so that it is opened from the base resolver again.
+    ///
+    /// Returns `true` if the file was in the overlay.
+    pub fn remove_file(&mut self, name: &str) -> bool {
+        self.files.remove(name).is_some()
+    }
+
+    /// Returns `true` if the overlay contains a file with the given name.
+    pub fn contains_file(&self, name: &str) -> bool {
+        self.files.contains_key(name)
+    }
+
+    /// Removes all files from the overlay.
+    pub fn clear(&mut self) {
+        self.files.clear();
+    }
+}
+
+impl<R> FileResolver for OverlayFileResolver<R>
+where
+    R: FileResolver,
+{
+    fn resolve_path(&self, path: &Path) -> Option<String> {
+        for (name, file) in &self.files {
+            if file.path.as_deref() == Some(path) {
+                return Some(name.clone());
+            }
+        }
+
+        self.base.resolve_path(path)
+    }
+
+    fn open_file(&self, name: &str) -> Result<File, Error> {
+        match self.files.get(name) {
+            Some(overlay) => {
+                let mut file = File::from_source(name, &overlay.source)?;
+                file.path = overlay.path.clone();
+                Ok(file)
+            }
+            None => self.base.open_file(name),
+        }
+    }
+
+    fn description(&self) -> String {
+        "overlay".to_owned()
+    }
+
+    fn open_file_traced(&self, name: &str, trace: &mut ImportResolution) -> Result<File, Error> {
+        if self.files.contains_key(name) {
+            let result = self.open_file(name);
+            trace.record(self.description(), &result);
+            result
+        } else {
+            self.base.open_file_traced(name, trace)
+        }
+    }
+}
@@ -0,0 +1,364 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::{self, Write},
+    fs,
+    path::{Path, PathBuf},
+};
+
+use sha3::{
+    digest::{ExtendableOutput, Update, XofReader},
+    Shake256,
+};
+
+use crate::{error::ErrorKind, Error};
+
+use super::{create_temp, digest::to_hex, path_to_file_name, File, FileResolver};
+
+/// Downloads the contents of a module from a schema registry, such as the [Buf Schema Registry](https://buf.build).
+///
+/// protox does not include an HTTP client, so this must be implemented using the registry's download API. See
+/// [`RegistryFileResolver`].
+pub trait ModuleFetcher: Send + Sync {
+    /// Downloads every file in the module `name` (for example `buf.build/owner/module`) at the commit identified by
+    /// the `b5` digest `digest`, returning the path and contents of each file.
+    ///
+    /// The returned files are verified against `digest` before they are used, so implementations do not need to
+    /// check them.
+    fn fetch(&self, name: &str, digest: &str) -> Result<Vec<(String, Vec<u8>)>, Error>;
+}
+
+/// An implementation of [`FileResolver`] which resolves files from modules downloaded from a schema registry.
+///
+/// Each module is pinned to a `b5` digest, as used by the Buf Schema Registry and written to `buf.lock` files. The
+/// digest has the form `b5:<hex>`, and is computed from the module's `.proto` files and the digests of its
+/// dependencies:
+///
+/// 1. A manifest lists each `.proto` file, sorted by path, with one line of the form `shake256:<hex>  <path>\n`,
+///    where `<hex>` is the 64-byte SHAKE256 hash of the file contents.
+/// 2. The manifest is hashed with SHAKE256, and formatted as `shake256:<hex>`.
+/// 3. The manifest digest and the sorted `b5` digests of the module's dependencies are joined with newlines, and the
+///    result is hashed with SHAKE256 to give the module digest.
+///
+/// Other files, such as `buf.yaml` and documentation, are not covered by the digest and are ignored. The files of a
+/// module are verified against its digest whenever they are downloaded or read from the cache, so compilation fails
+/// instead of silently using different contents.
+///
+/// If a cache directory is set, modules are stored in it under `<module name>/<hex digest>`, and are only fetched
+/// if they are missing from the cache or fail verification.
+///
+/// # Examples
+///
+/// ```
+/// # use protox::{file::{ModuleFetcher, RegistryFileResolver}, Compiler};
+/// struct Fetcher;
+///
+/// impl ModuleFetcher for Fetcher {
+///     fn fetch(&self, name: &str, digest: &str) -> Result<Vec<(String, Vec<u8>)>, protox::Error> {
+///         // Download the module from the registry here.
+///         assert_eq!(name, "buf.build/acme/weather");
+///         Ok(vec![("acme/weather/v1/weather.proto".to_owned(), b"package acme.weather.v1; message Forecast {}".to_vec())])
+///     }
+/// }
+///
+/// let digest = RegistryFileResolver::module_digest([(
+///     "acme/weather/v1/weather.proto",
+///     b"package acme.weather.v1; message Forecast {}".as_ref(),
+/// )]);
+///
+/// let mut resolver = RegistryFileResolver::new(Fetcher);
+/// resolver.add_module("buf.build/acme/weather", &digest)?;
+///
+/// let mut compiler = Compiler::with_file_resolver(resolver);
+/// compiler.open_file("acme/weather/v1/weather.proto")?;
+/// assert!(compiler.descriptor_pool().get_message_by_name("acme.weather.v1.Forecast").is_some());
+/// # Ok::<(), protox::Error>(())
+/// ```
+pub struct RegistryFileResolver {
+    fetcher: Box<dyn ModuleFetcher>,
+    cache_dir: Option<PathBuf>,
+    files: HashMap<String, ModuleFile>,
+}
+
+struct ModuleFile {
+    module: String,
+    path: Option<PathBuf>,
+    contents: Vec<u8>,
+}
+
+impl RegistryFileResolver {
+    /// Creates a new [`RegistryFileResolver`] which downloads modules using the given fetcher.
+    pub fn new<F>(fetcher: F) -> Self
+    where
+        F: ModuleFetcher + 'static,
+    {
+        RegistryFileResolver {
+            fetcher: Box::new(fetcher),
+            cache_dir: None,
+            files: HashMap::new(),
+        }
+    }
+
+    /// Sets the directory in which downloaded modules are cached.
+    ///
+    /// This should be set before any modules are added.
+    pub fn cache_dir(&mut self, path: impl Into<PathBuf>) -> &mut Self {
+        self.cache_dir = Some(path.into());
+        self
+    }
+
+    /// Computes the `b5` digest of a module without dependencies from the path and contents of each of its files, in
+    /// the format expected by [`add_module`](RegistryFileResolver::add_module).
+    pub fn module_digest<'a>(files: impl IntoIterator<Item = (&'a str, &'a [u8])>) -> String {
+        Self::module_digest_with_dependencies(files, [])
+    }
+
+    /// Computes the `b5` digest of a module from the path and contents of each of its files and the `b5` digests of
+    /// its dependencies.
+    pub fn module_digest_with_dependencies<'a>(
+        files: impl IntoIterator<Item = (&'a str, &'a [u8])>,
+        dependencies: impl IntoIterator<Item = &'a str>,
+    ) -> String {
+        let files = files
+            .into_iter()
+            .map(|(name, contents)| (name.to_owned(), contents.to_owned()))
+            .collect();
+        let dependencies: Vec<&str> = dependencies.into_iter().collect();
+        module_digest(&files, &dependencies)
+    }
+
+    /// Adds a module without dependencies pinned to the given `b5` digest, making its files available for import.
+    ///
+    /// See [`add_module_with_dependencies`](RegistryFileResolver::add_module_with_dependencies).
+    pub fn add_module(&mut self, name: &str, digest: &str) -> Result<&mut Self, Error> {
+        self.add_module_with_dependencies(name, digest, [])
+    }
+
+    /// Adds a module pinned to the given `b5` digest, making its files available for import.
+    ///
+    /// The digest of a module covers the digests of its dependencies, so these must be given to verify it. They are
+    /// listed alongside the module in a `buf.lock` file. The dependencies themselves are not added.
+    ///
+    /// The module is read from the cache directory if possible, and otherwise downloaded using the
+    /// [`ModuleFetcher`]. Only `.proto` files are made available. It is an error if two modules contain a file with
+    /// the same name.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the digest or a dependency digest is not of the form `b5:<hex>`, the module could not be
+    /// fetched or does not match the digest, or it contains a file which is already provided by another module.
+    pub fn add_module_with_dependencies<'a>(
+        &mut self,
+        name: &str,
+        digest: &str,
+        dependencies: impl IntoIterator<Item = &'a str>,
+    ) -> Result<&mut Self, Error> {
+        let dependencies: Vec<&str> = dependencies.into_iter().collect();
+        let hex = parse_digest(digest).ok_or_else(|| invalid_digest(name, digest))?;
+        if let Some(dependency) = dependencies
+            .iter()
+            .find(|dependency| parse_digest(dependency).is_none())
+        {
+            return Err(invalid_digest(name, dependency));
+        }
+
+        let cache_path = self
+            .cache_dir
+            .as_ref()
+            .map(|cache_dir| cache_dir.join(name).join(hex));
+        let cached = cache_path
+            .as_deref()
+            .and_then(read_cached_module)
+            .filter(|files| module_digest(files, &dependencies) == digest);
+
+        let files = match cached {
+            Some(files) => files,
+            None => {
+                let files: BTreeMap<String, Vec<u8>> = self
+                    .fetcher
+                    .fetch(name, digest)?
+                    .into_iter()
+                    .filter(|(file_name, _)| is_proto_file(file_name))
+                    .collect();
+                if let Some(file_name) = files.keys().find(|file_name| {
+                    path_to_file_name(Path::new(file_name)).as_deref() != Some(file_name)
+                }) {
+                    return Err(Error::from_kind(ErrorKind::InvalidFileName {
+                        name: file_name.clone(),
+                    }));
+                }
+
+                let actual = module_digest(&files, &dependencies);
+                if actual != digest {
+                    return Err(Error::from_kind(ErrorKind::ModuleDigestMismatch {
+                        module: name.to_owned(),
+                        expected: digest.to_owned(),
+                        actual,
+                    }));
+                }
+                if let Some(cache_path) = &cache_path {
+                    write_cached_module(cache_path, &files)?;
+                }
+                files
+            }
+        };
+
+        if let Some((file_name, existing)) = files
+            .keys()
+            .find_map(|file_name| Some((file_name, self.files.get(file_name)?)))
+        {
+            return Err(Error::from_kind(ErrorKind::DuplicateModuleFile {
+                name: file_name.clone(),
+                first: existing.module.clone(),
+                second: name.to_owned(),
+            }));
+        }
+        for (file_name, contents) in files {
+            let path = cache_path.as_ref().map(|dir| dir.join(&file_name));
+            self.files.insert(
+                file_name,
+                ModuleFile {
+                    module: name.to_owned(),
+                    path,
+                    contents,
+                },
+            );
+        }
+        Ok(self)
+    }
+}
+
+impl FileResolver for RegistryFileResolver {
+    fn open_file(&self, name: &str) -> Result<File, Error> {
+        let Some(module_file) = self.files.get(name) else {
+            return Err(Error::file_not_found(name));
+        };
+
+        let source = std::str::from_utf8(&module_file.contents).map_err(|err| {
+            Error::invalid_utf8(
+                name,
+                module_file.path.as_deref(),
+                &module_file.contents,
+                err,
+            )
+        })?;
+        let mut file = File::from_source(name, source)?;
+        file.path.clone_from(&module_file.path);
+        Ok(file)
+    }
+
+    fn description(&self) -> String {
+        let mut modules: Vec<&str> = self.files.values().map(|f| f.module.as_str()).collect();
+        modules.sort_unstable();
+        modules.dedup();
+        format!("registry modules [{}]", modules.join(", "))
+    }
+}
+
+impl fmt::Debug for RegistryFileResolver {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RegistryFileResolver")
+            .field("cache_dir", &self.cache_dir)
+            .finish_non_exhaustive()
+    }
+}
+
+fn invalid_digest(module: &str, digest: &str) -> Error {
+    Error::from_kind(ErrorKind::InvalidModuleDigest {
+        module: module.to_owned(),
+        digest: digest.to_owned(),
+    })
+}
+
+/// Gets the hex-encoded hash of a `b5` digest, if it is valid.
+fn parse_digest(digest: &str) -> Option<&str> {
+    digest.strip_prefix("b5:").filter(|hex| {
+        hex.len() == 128 && hex.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'))
+    })
+}
+
+fn is_proto_file(name: &str) -> bool {
+    Path::new(name).extension() == Some("proto".as_ref())
+}
+
+/// Computes the `b5` digest of a module, as described on [`RegistryFileResolver`].
+fn module_digest(files: &BTreeMap<String, Vec<u8>>, dependencies: &[&str]) -> String {
+    let mut manifest = String::new();
+    for (name, contents) in files.iter().filter(|(name, _)| is_proto_file(name)) {
+        writeln!(
+            manifest,
+            "shake256:{}  {}",
+            to_hex(&shake256(contents)),
+            name
+        )
+        .unwrap();
+    }
+
+    let mut dependencies = dependencies.to_vec();
+    dependencies.sort_unstable();
+    let mut digests = format!("shake256:{}", to_hex(&shake256(manifest.as_bytes())));
+    for dependency in dependencies {
+        digests.push('\n');
+        digests.push_str(dependency);
+    }
+    format!("b5:{}", to_hex(&shake256(digests.as_bytes())))
+}
+
+/// Computes the 64-byte SHAKE256 hash of the data, the digest function used by the Buf Schema Registry.
+fn shake256(data: &[u8]) -> [u8; 64] {
+    let mut hasher = Shake256::default();
+    hasher.update(data);
+    let mut hash = [0; 64];
+    hasher.finalize_xof().read(&mut hash);
+    hash
+}
+
+/// Reads all files in a cached module directory, returning `None` if it does not exist or cannot be read.
+fn read_cached_module(dir: &Path) -> Option<BTreeMap<String, Vec<u8>>> {
+    let mut files = BTreeMap::new();
+    let mut dirs = vec![dir.to_owned()];
+    while let Some(current) = dirs.pop() {
+        for entry in fs::read_dir(&current).ok()? {
+            let entry = entry.ok()?;
+            let path = entry.path();
+            if entry.file_type().ok()?.is_dir() {
+                dirs.push(path);
+            } else {
+                let name = path_to_file_name(path.strip_prefix(dir).ok()?)?;
+                files.insert(name, fs::read(&path).ok()?);
+            }
+        }
+    }
+    Some(files)
+}
+
+/// Writes a module to the cache, replacing any existing contents.
+///
+/// The files are written to a temporary directory first, which is renamed into place once complete.
+fn write_cached_module(dir: &Path, files: &BTreeMap<String, Vec<u8>>) -> Result<(), Error> {
+    let write_error = |path: &Path, err| {
+        Error::from_kind(ErrorKind::WriteFile {
+            path: path.to_owned(),
+            err,
+        })
+    };
+
+    if let Some(parent) = dir.parent() {
+        fs::create_dir_all(parent).map_err(|err| write_error(parent, err))?;
+    }
+    let ((), temp_dir) =
+        create_temp(dir, |path| fs::create_dir(path)).map_err(|err| write_error(dir, err))?;
+    for (name, contents) in files {
+        let path = temp_dir.join(name);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(|err| write_error(parent, err))?;
+        }
+        fs::write(&path, contents).map_err(|err| write_error(&path, err))?;
+    }
+
+    let _ = fs::remove_dir_all(dir);
+    if let Err(err) = fs::rename(&temp_dir, dir) {
+        let _ = fs::remove_dir_all(&temp_dir);
+        return Err(write_error(dir, err));
+    }
+    Ok(())
+}
@@ -1,14 +1,23 @@
 use std::{
     io::{self, Seek, Write},
     path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
 };
 
+use miette::Diagnostic;
 use prost_types::{source_code_info::Location, FileDescriptorProto, SourceCodeInfo};
 
 use crate::{file::FileResolver, Error};
 
 use super::{
-    ChainFileResolver, DescriptorSetFileResolver, File, GoogleFileResolver, IncludeFileResolver,
+    check_shadow,
+    digest::{sha256, to_hex},
+    CaseSensitivity, ChainFileResolver, DescriptorSetFileResolver, File, GoogleFileResolver,
+    IncludeFileResolver, ModuleFetcher, OverlayFileResolver, RegistryFileResolver,
 };
 
 struct EmptyFileResolver;
@@ -21,6 +30,22 @@ impl FileResolver for EmptyFileResolver {
 
 struct SingleFileResolver(File);
 
+struct TestModuleFetcher {
+    files: &'static [(&'static str, &'static str)],
+    fetches: Arc<AtomicUsize>,
+}
+
+impl ModuleFetcher for TestModuleFetcher {
+    fn fetch(&self, _: &str, _: &str) -> Result<Vec<(String, Vec<u8>)>, Error> {
+        self.fetches.fetch_add(1, Ordering::Relaxed);
+        Ok(self
+            .files
+            .iter()
+            .map(|(name, contents)| (name.to_string(), contents.as_bytes().to_vec()))
+            .collect())
+    }
+}
+
 impl FileResolver for SingleFileResolver {
     fn resolve_path(&self, path: &Path) -> Option<String> {
         if self.0.path.as_deref() == Some(path) {
@@ -55,6 +80,7 @@ fn chain_file_resolver() {
         source: Some(source.to_owned()),
         descriptor: protox_parse::parse("bar.proto", source).unwrap(),
         encoded: None,
+        parse_time: Duration::ZERO,
     }));
 
     assert_eq!(resolver.resolve_path("./notfound.proto".as_ref()), None);
@@ -70,6 +96,120 @@ fn chain_file_resolver() {
     assert_eq!(resolver.open_file("foo.proto").unwrap().name(), "foo.proto");
 }
 
+#[test]
+fn chain_file_resolver_priority() {
+    let mut resolver = ChainFileResolver::new();
+    resolver.add(SingleFileResolver(
+        File::from_source("foo.proto", "message First {}").unwrap(),
+    ));
+    resolver.insert(
+        0,
+        SingleFileResolver(File::from_source("foo.proto", "message Second {}").unwrap()),
+    );
+    let mut nested = ChainFileResolver::new();
+    nested.add(GoogleFileResolver::new());
+    nested.add(EmptyFileResolver);
+    nested.set_label(1, "empty");
+    resolver.add(nested);
+    resolver.set_label(1, "first");
+
+    assert_eq!(resolver.len(), 3);
+    assert_eq!(resolver.label(1).as_deref(), Some("first"));
+    assert_eq!(resolver.label(2).as_deref(), Some("chain"));
+    assert_eq!(resolver.label(3), None);
+    assert_eq!(
+        resolver
+            .open_file("foo.proto")
+            .unwrap()
+            .file_descriptor_proto()
+            .message_type[0]
+            .name(),
+        "Second"
+    );
+
+    let err = resolver.open_file("notfound.proto").unwrap_err();
+    assert!(err.is_file_not_found());
+    assert_eq!(err.to_string(), "file 'notfound.proto' not found");
+    assert_eq!(
+        err.help().unwrap().to_string(),
+        format!(
+            "searched {}, first, built-in google files, empty",
+            std::any::type_name::<SingleFileResolver>()
+        )
+    );
+
+    resolver.remove(0);
+    assert_eq!(resolver.iter().len(), 2);
+    assert_eq!(
+        resolver
+            .open_file("foo.proto")
+            .unwrap()
+            .file_descriptor_proto()
+            .message_type[0]
+            .name(),
+        "First"
+    );
+}
+
+#[test]
+fn overlay_file_resolver() {
+    let mut resolver = OverlayFileResolver::new(SingleFileResolver(File {
+        path: Some(PathBuf::from("./foo.proto")),
+        source: Some("message Foo {}".to_owned()),
+        descriptor: protox_parse::parse("foo.proto", "message Foo {}").unwrap(),
+        encoded: None,
+        parse_time: Duration::ZERO,
+    }));
+    assert_eq!(
+        resolver
+            .open_file("foo.proto")
+            .unwrap()
+            .file_descriptor_proto()
+            .message_type[0]
+            .name(),
+        "Foo"
+    );
+
+    resolver.set_file_with_path("foo.proto", "./foo.proto", "message Bar {}");
+    resolver.set_file("new.proto", "message New {}");
+    assert!(resolver.contains_file("foo.proto"));
+
+    let file = resolver.open_file("foo.proto").unwrap();
+    assert_eq!(file.source(), Some("message Bar {}"));
+    assert_eq!(file.path(), Some(Path::new("./foo.proto")));
+    assert_eq!(
+        resolver.resolve_path("./foo.proto".as_ref()).as_deref(),
+        Some("foo.proto")
+    );
+
+    let file = resolver.open_file("new.proto").unwrap();
+    assert_eq!(file.source(), Some("message New {}"));
+    assert_eq!(file.path(), None);
+
+    resolver.set_file("new.proto", "message {}");
+    let err = resolver.open_file("new.proto").unwrap_err();
+    assert!(err.is_parse());
+    assert_eq!(err.file(), Some("new.proto"));
+
+    assert!(resolver.remove_file("foo.proto"));
+    assert!(!resolver.remove_file("foo.proto"));
+    assert_eq!(
+        resolver
+            .open_file("foo.proto")
+            .unwrap()
+            .file_descriptor_proto()
+            .message_type[0]
+            .name(),
+        "Foo"
+    );
+
+    resolver.clear();
+    assert!(resolver
+        .open_file("new.proto")
+        .unwrap_err()
+        .is_file_not_found());
+}
+
 #[test]
 fn descriptor_set_file_resolver() {
     let mut encoded_files: Vec<u8> = vec![
@@ -234,7 +374,13 @@ fn include_resolver() {
     );
     assert_eq!(include.resolve_path(Path::new("/path/to/include")), None);
     assert_eq!(
-        include.resolve_path(Path::new("/path/to/../to/include/foo.proto")),
+        include
+            .resolve_path(Path::new("/path/to/../to/include/foo.proto"))
+            .as_deref(),
+        Some("foo.proto")
+    );
+    assert_eq!(
+        include.resolve_path(Path::new("/path/to/include/../foo.proto")),
         None
     );
     assert_eq!(include.resolve_path(Path::new("/path/to")), None);
@@ -257,8 +403,10 @@ fn include_resolver() {
         Some("dir/foo.proto")
     );
     assert_eq!(
-        include.resolve_path(Path::new("/path/to/include/dir/../foo.proto")),
-        None
+        include
+            .resolve_path(Path::new("/path/to/include/dir/../foo.proto"))
+            .as_deref(),
+        Some("foo.proto")
     );
     assert_eq!(
         include.resolve_path(&Path::new("/path/to/include").join(non_utf8_path())),
@@ -279,6 +427,281 @@ fn include_resolver() {
     );
 }
 
+#[test]
+fn include_resolver_case_sensitivity() {
+    let mut include = IncludeFileResolver::new("/path/to/include".into());
+    include.case_sensitivity(CaseSensitivity::Insensitive);
+    assert_eq!(
+        include
+            .resolve_path(Path::new("/PATH/to/Include/Dir/foo.proto"))
+            .as_deref(),
+        Some("Dir/foo.proto")
+    );
+
+    include.case_sensitivity(CaseSensitivity::Sensitive);
+    assert_eq!(
+        include.resolve_path(Path::new("/PATH/to/Include/Dir/foo.proto")),
+        None
+    );
+
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::create_dir(dir.path().join("Dir")).unwrap();
+    std::fs::write(dir.path().join("Dir/Foo.proto"), "").unwrap();
+
+    let mut include = IncludeFileResolver::new(dir.path().to_owned());
+    include.case_sensitivity(CaseSensitivity::Sensitive);
+    assert_eq!(
+        include.open_file("Dir/Foo.proto").unwrap().name(),
+        "Dir/Foo.proto"
+    );
+    assert!(include
+        .open_file("Dir/Bar.proto")
+        .unwrap_err()
+        .is_file_not_found());
+    assert_eq!(
+        include.open_file("dir/foo.proto").unwrap_err().to_string(),
+        "file 'dir/foo.proto' does not match the case of 'Dir/Foo.proto' on the file system"
+    );
+}
+
+#[test]
+#[cfg(unix)]
+fn include_resolver_symlinks() {
+    use std::os::unix::fs::symlink;
+
+    let dir = tempfile::tempdir().unwrap();
+    let include_path = dir.path().join("include");
+    std::fs::create_dir_all(include_path.join("dir")).unwrap();
+    std::fs::write(include_path.join("dir/foo.proto"), "").unwrap();
+    symlink(include_path.join("dir"), include_path.join("link")).unwrap();
+    symlink(&include_path, dir.path().join("alias")).unwrap();
+
+    let mut include = IncludeFileResolver::new(include_path.clone());
+    assert_eq!(
+        include.open_file("link/foo.proto").unwrap().name(),
+        "link/foo.proto"
+    );
+    // Paths through a symbolic link are resolved once lexical comparison fails, but keep their own name.
+    assert_eq!(
+        include
+            .resolve_path(&dir.path().join("alias/dir/foo.proto"))
+            .as_deref(),
+        Some("dir/foo.proto")
+    );
+    assert_eq!(
+        include
+            .resolve_path(&include_path.join("link/foo.proto"))
+            .as_deref(),
+        Some("link/foo.proto")
+    );
+
+    include.follow_symlinks(false);
+    assert_eq!(
+        include.open_file("dir/foo.proto").unwrap().name(),
+        "dir/foo.proto"
+    );
+    assert_eq!(
+        include.open_file("link/foo.proto").unwrap_err().to_string(),
+        format!(
+            "path '{}' to file 'link/foo.proto' is a symbolic link, which is not followed",
+            include_path.join("link").display()
+        )
+    );
+    assert!(include
+        .open_file("link/bar.proto")
+        .unwrap_err()
+        .to_string()
+        .contains("is a symbolic link"));
+    assert!(include
+        .open_file("nope/bar.proto")
+        .unwrap_err()
+        .is_file_not_found());
+
+    include.canonicalize(true);
+    assert_eq!(
+        include
+            .resolve_path(&dir.path().join("alias/dir/foo.proto"))
+            .as_deref(),
+        Some("dir/foo.proto")
+    );
+    assert_eq!(
+        include
+            .resolve_path(&include_path.join("link/foo.proto"))
+            .as_deref(),
+        Some("dir/foo.proto")
+    );
+    assert_eq!(
+        include.resolve_path(&dir.path().join("alias/dir/nope.proto")),
+        None
+    );
+
+    // A file reached through a symbolic link to its include path is not shadowed.
+    check_shadow(
+        "dir/foo.proto",
+        Some(&include_path.join("dir/foo.proto")),
+        &dir.path().join("alias/dir/foo.proto"),
+    )
+    .unwrap();
+    check_shadow(
+        "dir/foo.proto",
+        Some(&include_path.join("dir/foo.proto")),
+        &dir.path().join("other/dir/foo.proto"),
+    )
+    .unwrap_err();
+}
+
+#[test]
+fn registry_resolver() {
+    const MODULE: &[(&str, &str)] = &[
+        ("c.proto", "import \"a/b.proto\";"),
+        ("a/b.proto", "message B {}"),
+        ("buf.yaml", "version: v2"),
+    ];
+    const DIGEST: &str = "b5:71bce9518339d30309c426ab01a89fc7d95c000b3a3052c05b3fc35430bbd4044348edad1caf3e93af229871a1baa567bed84c996005bb0fe219f4d40d633359";
+
+    assert_eq!(
+        RegistryFileResolver::module_digest(
+            MODULE
+                .iter()
+                .map(|(name, contents)| (*name, contents.as_bytes()))
+        ),
+        DIGEST
+    );
+    assert_eq!(
+        RegistryFileResolver::module_digest([]),
+        "b5:fb27b3544d9e59e11c2bc5472414bdd55559b281dc0ca57ce6fca6ed431047d5803274e0b436ed73eaaa05f0f3251af9a42a040dba009ecb3c21a36ea12e54fa"
+    );
+    assert_eq!(
+        RegistryFileResolver::module_digest_with_dependencies([("d.proto", b"".as_ref())], [DIGEST]),
+        "b5:71896e959b03c388d347a97146f44b7626093af9ecabdf25ab203fa65426889d9eeb96d32fd377549c6258208e16943d84c5c17e4d3d9708906556c3c3fe526c"
+    );
+
+    let cache_dir = tempfile::tempdir().unwrap();
+    let fetches = Arc::new(AtomicUsize::new(0));
+    let new_resolver = || {
+        let mut resolver = RegistryFileResolver::new(TestModuleFetcher {
+            files: MODULE,
+            fetches: fetches.clone(),
+        });
+        resolver.cache_dir(cache_dir.path());
+        resolver
+    };
+
+    let mut resolver = new_resolver();
+    resolver.add_module("buf.build/acme/mod", DIGEST).unwrap();
+    assert_eq!(fetches.load(Ordering::Relaxed), 1);
+    let file = resolver.open_file("a/b.proto").unwrap();
+    assert_eq!(file.source(), Some("message B {}"));
+    assert_eq!(
+        file.path(),
+        Some(
+            cache_dir
+                .path()
+                .join("buf.build/acme/mod")
+                .join(&DIGEST[3..])
+                .join("a/b.proto")
+                .as_ref()
+        )
+    );
+    assert!(resolver
+        .open_file("d.proto")
+        .unwrap_err()
+        .is_file_not_found());
+    assert!(resolver
+        .open_file("buf.yaml")
+        .unwrap_err()
+        .is_file_not_found());
+
+    // The second time, the module is read from the cache.
+    let mut resolver = new_resolver();
+    resolver.add_module("buf.build/acme/mod", DIGEST).unwrap();
+    assert_eq!(fetches.load(Ordering::Relaxed), 1);
+    assert_eq!(
+        resolver.open_file("c.proto").unwrap().source(),
+        Some("import \"a/b.proto\";")
+    );
+
+    assert_eq!(
+        resolver
+            .add_module("buf.build/acme/other", DIGEST)
+            .unwrap_err()
+            .to_string(),
+        "file 'a/b.proto' is provided by both module 'buf.build/acme/mod' and module 'buf.build/acme/other'"
+    );
+
+    // Modified cache entries are fetched again.
+    std::fs::write(
+        cache_dir
+            .path()
+            .join("buf.build/acme/mod")
+            .join(&DIGEST[3..])
+            .join("a/b.proto"),
+        "message Modified {}",
+    )
+    .unwrap();
+    let mut resolver = new_resolver();
+    resolver.add_module("buf.build/acme/mod", DIGEST).unwrap();
+    assert_eq!(fetches.load(Ordering::Relaxed), 3);
+    assert_eq!(
+        resolver.open_file("a/b.proto").unwrap().source(),
+        Some("message B {}")
+    );
+
+    let digest = format!("b5:{}", "0".repeat(128));
+    assert_eq!(
+        new_resolver()
+            .add_module("buf.build/acme/mod", &digest)
+            .unwrap_err()
+            .to_string(),
+        format!(
+            "module 'buf.build/acme/mod' has digest '{}', but expected '{}'",
+            DIGEST, digest
+        )
+    );
+    assert_eq!(
+        new_resolver()
+            .add_module("buf.build/acme/mod", "b5:1234")
+            .unwrap_err()
+            .to_string(),
+        "digest 'b5:1234' of module 'buf.build/acme/mod' is invalid"
+    );
+    assert_eq!(
+        new_resolver()
+            .add_module_with_dependencies("buf.build/acme/mod", DIGEST, ["sha256:1234"])
+            .unwrap_err()
+            .to_string(),
+        "digest 'sha256:1234' of module 'buf.build/acme/mod' is invalid"
+    );
+    assert_eq!(
+        new_resolver()
+            .add_module_with_dependencies("buf.build/acme/mod", DIGEST, [DIGEST])
+            .unwrap_err()
+            .to_string(),
+        format!(
+            "module 'buf.build/acme/mod' has digest '{}', but expected '{}'",
+            RegistryFileResolver::module_digest_with_dependencies(
+                MODULE
+                    .iter()
+                    .map(|(name, contents)| (*name, contents.as_bytes())),
+                [DIGEST]
+            ),
+            DIGEST
+        )
+    );
+
+    let mut resolver = RegistryFileResolver::new(TestModuleFetcher {
+        files: &[("../escape.proto", "")],
+        fetches,
+    });
+    assert_eq!(
+        resolver
+            .add_module("buf.build/acme/mod", DIGEST)
+            .unwrap_err()
+            .to_string(),
+        "file name '../escape.proto' must be a relative path without '.' or '..' components"
+    );
+}
+
 #[test]
 fn file_open() {
     let mut tempfile = tempfile::NamedTempFile::new().unwrap();
@@ -404,3 +827,125 @@ fn file_decode_file_descriptor_proto_err() {
     let invalid = b"invalid";
     assert!(File::decode_file_descriptor_proto(invalid.as_ref()).is_err());
 }
+
+#[test]
+#[cfg(unix)]
+fn file_open_read_error() {
+    // Directories can be opened on unix, but not read.
+    let tempdir = tempfile::tempdir().unwrap();
+
+    let err = File::open("dir.proto", tempdir.path()).unwrap_err();
+    assert!(err.is_io());
+    assert_eq!(err.file(), Some("dir.proto"));
+    assert_eq!(
+        err.to_string(),
+        format!("error reading file '{}'", tempdir.path().display())
+    );
+}
+
+#[test]
+fn file_from_reader() {
+    struct FailingReader;
+
+    impl io::Read for FailingReader {
+        fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+            Err(io::Error::new(io::ErrorKind::BrokenPipe, "broken pipe"))
+        }
+    }
+
+    let file = File::from_reader("stdin/foo.proto", "syntax = 'proto3';".as_bytes()).unwrap();
+    assert_eq!(file.name(), "stdin/foo.proto");
+    assert_eq!(file.path(), None);
+    assert_eq!(file.source(), Some("syntax = 'proto3';"));
+
+    let err = File::from_reader("stdin/foo.proto", FailingReader).unwrap_err();
+    assert!(err.is_io());
+    assert_eq!(err.file(), Some("stdin/foo.proto"));
+    assert_eq!(err.to_string(), "error reading file 'stdin/foo.proto'");
+
+    let err = File::from_reader("stdin/foo.proto", b"message \xff {}".as_ref()).unwrap_err();
+    assert!(err.is_parse());
+    assert_eq!(err.file(), Some("stdin/foo.proto"));
+    assert_eq!(
+        err.to_string(),
+        "file 'stdin/foo.proto' is not valid utf-8 at byte offset 8"
+    );
+}
+
+#[test]
+fn discover_includes() {
+    let dir = tempfile::tempdir().unwrap();
+    let write = |name: &str, source: &str| {
+        let path = dir.path().join(name);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, source).unwrap();
+        path
+    };
+
+    let root = write(
+        "proto/foo/root.proto",
+        "import 'foo/bar.proto'; import 'google/protobuf/empty.proto'; import 'dep.proto';",
+    );
+    write("proto/foo/bar.proto", "import 'foo/baz.proto';");
+    write("proto/foo/baz.proto", "");
+    write("third_party/dep.proto", "");
+    let other = write("other/other.proto", "import 'foo/bar.proto';");
+
+    let err = super::discover_includes([&root]).unwrap_err();
+    assert!(err.is_file_not_found());
+    assert_eq!(err.to_string(), "import 'dep.proto' not found");
+
+    write("proto/dep.proto", "");
+    assert_eq!(
+        super::discover_includes([&root, &other]).unwrap(),
+        [dir.path().join("proto"), dir.path().join("other")]
+    );
+
+    let ambiguous = write("proto/foo/ambiguous.proto", "import 'dep.proto';");
+    write("proto/foo/dep.proto", "");
+    let err = super::discover_includes([&ambiguous]).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        format!(
+            "import 'dep.proto' in '{}' could refer to '{}' or '{}'",
+            ambiguous.display(),
+            dir.path().join("proto/foo/dep.proto").display(),
+            dir.path().join("proto/dep.proto").display(),
+        )
+    );
+
+    let inconsistent = write(
+        "proto/foo/inconsistent.proto",
+        "import 'foo/baz.proto'; import 'baz.proto';",
+    );
+    let err = super::discover_includes([&inconsistent]).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        format!(
+            "file '{}' is imported as both 'foo/baz.proto' and 'baz.proto'",
+            dir.path().join("proto/foo/baz.proto").display(),
+        )
+    );
+}
+
+#[test]
+fn sha256_known_vectors() {
+    assert_eq!(
+        to_hex(&sha256(b"")),
+        "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+    );
+    assert_eq!(
+        to_hex(&sha256(b"abc")),
+        "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+    );
+    assert_eq!(
+        to_hex(&sha256(
+            b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"
+        )),
+        "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1"
+    );
+    assert_eq!(
+        to_hex(&sha256(&[b'a'; 1_000_000])),
+        "cdc76e5c9914fb9281a1c7e284d73e67f1809a48a497200e046d39ccc7112cd0"
+    );
+}
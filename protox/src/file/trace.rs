@@ -0,0 +1,144 @@
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use super::File;
+use crate::Error;
+
+/// A record of how a single file name was resolved to a [`File`].
+///
+/// See [`Compiler::trace_imports`](crate::Compiler::trace_imports).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImportResolution {
+    name: String,
+    importer: Option<String>,
+    candidates: Vec<ResolutionCandidate>,
+}
+
+/// A resolver consulted while resolving a file name, and the result it returned.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResolutionCandidate {
+    resolver: String,
+    outcome: ResolutionOutcome,
+}
+
+/// The result of asking a single resolver to open a file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ResolutionOutcome {
+    /// The resolver returned the file, which was read from the given path, if any.
+    Found {
+        /// The path of the file on the file system, if known.
+        path: Option<PathBuf>,
+    },
+    /// The resolver did not contain the file, so the next resolver was tried.
+    NotFound,
+    /// The resolver failed with the given error message, aborting resolution.
+    Failed(String),
+}
+
+impl ImportResolution {
+    pub(crate) fn new(name: &str, importer: Option<&str>) -> Self {
+        ImportResolution {
+            name: name.to_owned(),
+            importer: importer.map(ToOwned::to_owned),
+            candidates: Vec::new(),
+        }
+    }
+
+    /// Gets the name of the file being resolved.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Gets the name of the file which imports this one, or `None` if it was opened directly with
+    /// [`Compiler::open_file`](crate::Compiler::open_file).
+    pub fn importer(&self) -> Option<&str> {
+        self.importer.as_deref()
+    }
+
+    /// Gets the resolvers consulted, in the order they were tried.
+    ///
+    /// Every candidate before the [`resolved`](ImportResolution::resolved) one was skipped because it did not
+    /// contain the file.
+    pub fn candidates(&self) -> &[ResolutionCandidate] {
+        &self.candidates
+    }
+
+    /// Gets the candidate which answered the request, if the file was found.
+    pub fn resolved(&self) -> Option<&ResolutionCandidate> {
+        self.candidates
+            .iter()
+            .find(|candidate| matches!(candidate.outcome, ResolutionOutcome::Found { .. }))
+    }
+
+    /// Gets the file system path the name resolved to, if the file was found and has a path.
+    pub fn path(&self) -> Option<&Path> {
+        match &self.resolved()?.outcome {
+            ResolutionOutcome::Found { path } => path.as_deref(),
+            _ => None,
+        }
+    }
+
+    /// Records the result of asking a resolver to open this file.
+    ///
+    /// This should be called by [`FileResolver::open_file_traced`](super::FileResolver::open_file_traced)
+    /// implementations for each resolver they consult.
+    pub fn record(&mut self, resolver: impl Into<String>, result: &Result<File, Error>) {
+        let outcome = match result {
+            Ok(file) => ResolutionOutcome::Found {
+                path: file.path().map(ToOwned::to_owned),
+            },
+            Err(err) if err.is_file_not_found() => ResolutionOutcome::NotFound,
+            Err(err) => ResolutionOutcome::Failed(err.to_string()),
+        };
+
+        self.candidates.push(ResolutionCandidate {
+            resolver: resolver.into(),
+            outcome,
+        });
+    }
+}
+
+impl ResolutionCandidate {
+    /// Gets the [`description`](super::FileResolver::description) of the resolver.
+    pub fn resolver(&self) -> &str {
+        &self.resolver
+    }
+
+    /// Gets the result returned by the resolver.
+    pub fn outcome(&self) -> &ResolutionOutcome {
+        &self.outcome
+    }
+}
+
+impl fmt::Display for ImportResolution {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "'{}'", self.name)?;
+        if let Some(importer) = &self.importer {
+            write!(f, " (imported by '{}')", importer)?;
+        }
+        if self.candidates.is_empty() {
+            return write!(f, ": no resolvers");
+        }
+        for (i, candidate) in self.candidates.iter().enumerate() {
+            f.write_str(if i == 0 { ": " } else { ", " })?;
+            write!(f, "{}", candidate)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for ResolutionCandidate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.outcome {
+            ResolutionOutcome::Found { path: Some(path) } => {
+                write!(f, "found in {} at '{}'", self.resolver, path.display())
+            }
+            ResolutionOutcome::Found { path: None } => write!(f, "found in {}", self.resolver),
+            ResolutionOutcome::NotFound => write!(f, "not found in {}", self.resolver),
+            ResolutionOutcome::Failed(err) => write!(f, "{} failed: {}", self.resolver, err),
+        }
+    }
+}
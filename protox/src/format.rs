@@ -0,0 +1,176 @@
+//! Encoding of compiled descriptors in the protobuf text format and JSON.
+//!
+//! A [`DescriptorSet`] holds a [`FileDescriptorSet`](prost_types::FileDescriptorSet) as a dynamic message, so it can
+//! be written in formats other than the binary encoding, for tools which consume `.pbtxt` or `.json` descriptor
+//! artifacts. Use [`Compiler::descriptor_set`](crate::Compiler::descriptor_set) to get the compiled files, including
+//! custom options, which are written using their extension names.
+//!
+//! The text format is always available. If the `serde` feature is enabled, [`DescriptorSet`] also implements
+//! [`Serialize`](serde::Serialize) and [`Deserialize`](serde::Deserialize) using the
+//! [canonical JSON encoding](https://protobuf.dev/programming-guides/proto3/#json) of protobuf messages, so fields are
+//! named with their JSON names, such as `messageType`, rather than their Rust names.
+//!
+//! # Examples
+//!
+//! ```
+//! # use protox::{file::{File, FileResolver}, Compiler};
+//! # struct Resolver;
+//! # impl FileResolver for Resolver {
+//! #     fn open_file(&self, name: &str) -> Result<File, protox::Error> {
+//! #         File::from_source(name, "message Foo {}")
+//! #     }
+//! # }
+//! let mut compiler = Compiler::with_file_resolver(Resolver);
+//! compiler.open_file("foo.proto")?;
+//!
+//! let text = compiler.descriptor_set().to_text_format();
+//! assert_eq!(text, "file: [{\n  name: \"foo.proto\"\n  message_type: [{\n    name: \"Foo\"\n  }]\n}]");
+//! # Ok::<(), protox::Error>(())
+//! ```
+
+use std::fmt;
+
+use prost::Message;
+use prost_reflect::{
+    text_format::FormatOptions, DescriptorPool, DynamicMessage, MessageDescriptor, ReflectMessage,
+};
+use prost_types::FileDescriptorSet;
+
+use crate::Error;
+
+const FILE_DESCRIPTOR_SET: &str = "google.protobuf.FileDescriptorSet";
+
+/// The format to write a [`FileDescriptorSet`](prost_types::FileDescriptorSet) in.
+///
+/// See [`Compiler::write_file_descriptor_set_as`](crate::Compiler::write_file_descriptor_set_as).
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum OutputFormat {
+    /// The binary protobuf encoding.
+    #[default]
+    Binary,
+    /// The protobuf text format.
+    Text,
+    /// The canonical protobuf JSON encoding. Only available if the `serde` feature is enabled.
+    #[cfg(feature = "serde")]
+    Json,
+}
+
+/// A set of file descriptors which can be written in the protobuf text format, or as JSON.
+///
+/// See the [module documentation](self) for details.
+#[derive(Clone, PartialEq)]
+pub struct DescriptorSet {
+    message: DynamicMessage,
+}
+
+impl DescriptorSet {
+    /// Creates a [`DescriptorSet`] by decoding a binary [`FileDescriptorSet`](prost_types::FileDescriptorSet).
+    ///
+    /// If `pool` defines `google.protobuf.FileDescriptorSet`, its definition is used, so any custom options defined
+    /// in the pool are written with their extension names. Otherwise, custom options are kept as unknown fields, which
+    /// are omitted from the text format and JSON.
+    pub fn decode(pool: &DescriptorPool, bytes: &[u8]) -> Result<Self, Error> {
+        let message =
+            DynamicMessage::decode(descriptor_set_message(pool), bytes).map_err(Error::new)?;
+        Ok(DescriptorSet { message })
+    }
+
+    /// Parses a [`DescriptorSet`] from the protobuf text format.
+    ///
+    /// Custom options may be set using their extension names if they are defined in `pool`, as for
+    /// [`decode`](DescriptorSet::decode).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `input` is not a valid text format `FileDescriptorSet`.
+    pub fn from_text_format(pool: &DescriptorPool, input: &str) -> Result<Self, Error> {
+        let message = DynamicMessage::parse_text_format(descriptor_set_message(pool), input)
+            .map_err(Error::new)?;
+        Ok(DescriptorSet { message })
+    }
+
+    /// Formats the descriptors in the protobuf text format, with one field per line. Repeated fields are written
+    /// using the list syntax, such as `file: [{ ... }]`.
+    pub fn to_text_format(&self) -> String {
+        self.message
+            .to_text_format_with_options(&FormatOptions::new().pretty(true))
+    }
+
+    /// Formats the descriptors as pretty-printed JSON.
+    #[cfg(feature = "serde")]
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(&self.message).expect("serializing to a string cannot fail")
+    }
+
+    /// Parses a [`DescriptorSet`] from JSON, using the definition of `google.protobuf.FileDescriptorSet` in `pool`
+    /// if it exists, as for [`decode`](DescriptorSet::decode).
+    ///
+    /// Unlike the [`Deserialize`](serde::Deserialize) implementation, this allows custom options defined in `pool`
+    /// to be set.
+    #[cfg(feature = "serde")]
+    pub fn from_json(pool: &DescriptorPool, input: &str) -> Result<Self, Error> {
+        let mut deserializer = serde_json::Deserializer::from_str(input);
+        let message = DynamicMessage::deserialize(descriptor_set_message(pool), &mut deserializer)
+            .and_then(|message| deserializer.end().map(|()| message))
+            .map_err(Error::new)?;
+        Ok(DescriptorSet { message })
+    }
+
+    /// Encodes the descriptors in the binary protobuf format, including custom options.
+    pub fn encode_to_vec(&self) -> Vec<u8> {
+        self.message.encode_to_vec()
+    }
+
+    /// Converts the descriptors into a [`FileDescriptorSet`](prost_types::FileDescriptorSet).
+    ///
+    /// Custom options are not included, since they cannot be represented by `prost_types`.
+    pub fn to_file_descriptor_set(&self) -> FileDescriptorSet {
+        self.message
+            .transcode_to()
+            .expect("message should be a FileDescriptorSet")
+    }
+}
+
+impl From<FileDescriptorSet> for DescriptorSet {
+    fn from(file_descriptor_set: FileDescriptorSet) -> Self {
+        DescriptorSet {
+            message: file_descriptor_set.transcode_to_dynamic(),
+        }
+    }
+}
+
+impl fmt::Debug for DescriptorSet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&self.message, f)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for DescriptorSet {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serde::Serialize::serialize(&self.message, serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for DescriptorSet {
+    /// Deserializes a [`DescriptorSet`] from the canonical JSON encoding, using the built-in definition of
+    /// `google.protobuf.FileDescriptorSet`. Use [`DescriptorSet::from_json`] to allow custom options.
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let message =
+            DynamicMessage::deserialize(FileDescriptorSet::default().descriptor(), deserializer)?;
+        Ok(DescriptorSet { message })
+    }
+}
+
+fn descriptor_set_message(pool: &DescriptorPool) -> MessageDescriptor {
+    pool.get_message_by_name(FILE_DESCRIPTOR_SET)
+        .unwrap_or_else(|| FileDescriptorSet::default().descriptor())
+}
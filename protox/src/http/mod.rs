@@ -0,0 +1,455 @@
+//! Validation of `(google.api.http)` annotations used for gRPC transcoding.
+//!
+//! This module is only available if the `google-api` feature is enabled, which also allows
+//! [`GoogleFileResolver`](crate::file::GoogleFileResolver) to resolve `google/api/annotations.proto` and
+//! `google/api/http.proto`.
+//!
+//! HTTP gateways such as grpc-gateway or Envoy's transcoder only report problems with
+//! [`HttpRule`](https://github.com/googleapis/googleapis/blob/master/google/api/http.proto)s when the service is
+//! deployed. Registering [`HttpRuleCheck`] with [`Compiler::add_check`](crate::Compiler::add_check) reports them while
+//! compiling instead. For each method with an `(google.api.http)` option, it checks that:
+//!
+//! - exactly one pattern (`get`, `put`, `post`, `delete`, `patch` or `custom`) is set, and `custom` patterns have a
+//!   kind,
+//! - the path follows the path template syntax, for example `/v1/{name=shelves/*}/books:list`,
+//! - each variable in the path refers to a non-repeated scalar or enum field of the request message, possibly nested
+//!   in singular message fields,
+//! - `body` is empty, `*` or the name of a top-level field of the request message, and is not set for `get` rules,
+//! - `response_body` is empty or the name of a top-level field of the response message,
+//! - rules in `additional_bindings` do not have their own `additional_bindings`.
+//!
+//! # Examples
+//!
+//! ```
+//! # use protox::{file::{ChainFileResolver, File, FileResolver, GoogleFileResolver}, http::HttpRuleCheck, Compiler};
+//! # struct Resolver;
+//! # impl FileResolver for Resolver {
+//! #     fn open_file(&self, name: &str) -> Result<File, protox::Error> {
+//! #         match name {
+//! #             "library.proto" => File::from_source(name, "
+//! syntax = 'proto3';
+//! import 'google/api/annotations.proto';
+//!
+//! message GetBookRequest { string name = 1; }
+//! message Book { string name = 1; }
+//!
+//! service Library {
+//!   rpc GetBook(GetBookRequest) returns (Book) {
+//!     option (google.api.http) = { get: '/v1/{title=shelves/*/books/*}' };
+//!   }
+//! }
+//! #             "),
+//! #             _ => Err(protox::Error::file_not_found(name)),
+//! #         }
+//! #     }
+//! # }
+//! # let mut resolver = ChainFileResolver::new();
+//! # resolver.add(Resolver);
+//! # resolver.add(GoogleFileResolver::new());
+//! let mut compiler = Compiler::with_file_resolver(resolver);
+//! compiler.add_check(HttpRuleCheck::new());
+//!
+//! let err = compiler.open_file("library.proto").unwrap_err();
+//! assert_eq!(
+//!     err.to_string(),
+//!     "path variable 'title' of method 'GetBook' refers to unknown field 'title' of message 'GetBookRequest'",
+//! );
+//! ```
+
+use prost_reflect::{
+    DynamicMessage, FileDescriptor, Kind, MessageDescriptor, MethodDescriptor, ReflectMessage,
+    Value,
+};
+use protox_parse::tag;
+
+use crate::check::{CustomCheck, Diagnostics};
+
+#[cfg(test)]
+mod tests;
+
+/// The full name of the `http` extension of `google.protobuf.MethodOptions`.
+const HTTP_EXTENSION: &str = "google.api.http";
+/// The fields of `google.api.HttpRule` which specify a method and path.
+const PATTERNS: &[&str] = &["get", "put", "post", "delete", "patch", "custom"];
+
+/// A [`CustomCheck`] which validates the `(google.api.http)` options of methods.
+///
+/// See the [module documentation](self) for details.
+#[derive(Debug, Default, Clone)]
+pub struct HttpRuleCheck {
+    _priv: (),
+}
+
+impl HttpRuleCheck {
+    /// Creates a new instance of [`HttpRuleCheck`].
+    pub fn new() -> Self {
+        Default::default()
+    }
+}
+
+impl CustomCheck for HttpRuleCheck {
+    fn check_file(&self, file: &FileDescriptor, diagnostics: &mut Diagnostics<'_>) {
+        let Some(extension) = file.parent_pool().get_extension_by_name(HTTP_EXTENSION) else {
+            return;
+        };
+
+        for service in file.services() {
+            for method in service.methods() {
+                let options = method.options();
+                if !options.has_extension(&extension) {
+                    continue;
+                }
+                let Some(rule) = options.get_extension(&extension).as_message().cloned() else {
+                    continue;
+                };
+
+                let mut path = method.path().to_vec();
+                path.extend([tag::method::OPTIONS, extension.number() as i32]);
+                let mut checker = RuleChecker {
+                    method: &method,
+                    path: &path,
+                    diagnostics,
+                };
+                checker.check_rule(&rule, false);
+                if let Some(Value::List(bindings)) =
+                    rule.get_field_by_name("additional_bindings").as_deref()
+                {
+                    for binding in bindings {
+                        if let Some(binding) = binding.as_message() {
+                            checker.check_rule(binding, true);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+struct RuleChecker<'a, 'b> {
+    method: &'a MethodDescriptor,
+    path: &'a [i32],
+    diagnostics: &'a mut Diagnostics<'b>,
+}
+
+impl RuleChecker<'_, '_> {
+    fn check_rule(&mut self, rule: &DynamicMessage, is_additional_binding: bool) {
+        if is_additional_binding && has_field(rule, "additional_bindings") {
+            self.error(
+                format!(
+                    "additional binding of method '{}' has its own additional bindings",
+                    self.method.name()
+                ),
+                "additional bindings may only be nested one level deep",
+            );
+        }
+
+        let patterns: Vec<&str> = PATTERNS
+            .iter()
+            .copied()
+            .filter(|name| has_field(rule, name))
+            .collect();
+        let (kind, template) = match patterns.as_slice() {
+            [] => {
+                self.error(
+                    format!(
+                        "HTTP rule of method '{}' does not specify a pattern",
+                        self.method.name()
+                    ),
+                    "set one of 'get', 'put', 'post', 'delete', 'patch' or 'custom'",
+                );
+                return;
+            }
+            ["custom"] => {
+                let custom = rule.get_field_by_name("custom").unwrap();
+                let custom = custom.as_message().unwrap();
+                let kind = string_field(custom, "kind");
+                if kind.is_empty() {
+                    self.error(
+                        format!(
+                            "custom HTTP pattern of method '{}' does not specify a kind",
+                            self.method.name()
+                        ),
+                        "set 'kind' to the HTTP method, such as 'HEAD', or '*' to match any method",
+                    );
+                }
+                (kind, string_field(custom, "path"))
+            }
+            [pattern] => (pattern.to_string(), string_field(rule, pattern)),
+            _ => unreachable!("pattern fields are in a oneof"),
+        };
+
+        match parse_template(&template) {
+            Ok(variables) => {
+                for variable in variables {
+                    self.check_variable(variable);
+                }
+            }
+            Err(message) => self.error(
+                format!(
+                    "invalid path template '{}' for method '{}': {}",
+                    template,
+                    self.method.name(),
+                    message
+                ),
+                "path templates have the form '/v1/{field=segments/*}:verb'",
+            ),
+        }
+
+        let body = string_field(rule, "body");
+        if !body.is_empty() {
+            if kind == "get" {
+                self.error(
+                    format!(
+                        "HTTP GET rule of method '{}' must not have a body",
+                        self.method.name()
+                    ),
+                    "remove the 'body' field, or use a different HTTP method",
+                );
+            } else if body != "*" {
+                self.check_top_level_field("body", &body, &self.method.input());
+            }
+        }
+
+        let response_body = string_field(rule, "response_body");
+        if !response_body.is_empty() {
+            self.check_top_level_field("response_body", &response_body, &self.method.output());
+        }
+    }
+
+    fn check_variable(&mut self, field_path: &str) {
+        let mut message = self.method.input();
+        let mut parts = field_path.split('.').peekable();
+        while let Some(part) = parts.next() {
+            let Some(field) = message.get_field_by_name(part) else {
+                self.error(
+                    format!(
+                        "path variable '{}' of method '{}' refers to unknown field '{}' of message '{}'",
+                        field_path,
+                        self.method.name(),
+                        part,
+                        message.full_name()
+                    ),
+                    "path variables must be the names of fields of the request message",
+                );
+                return;
+            };
+
+            if field.is_list() || field.is_map() {
+                self.error(
+                    format!(
+                        "path variable '{}' of method '{}' refers to repeated field '{}'",
+                        field_path,
+                        self.method.name(),
+                        field.full_name()
+                    ),
+                    "path variables must not refer to repeated or map fields",
+                );
+                return;
+            }
+
+            match (field.kind(), parts.peek()) {
+                (Kind::Message(nested), Some(_)) => message = nested,
+                (_, Some(_)) => {
+                    self.error(
+                        format!(
+                            "path variable '{}' of method '{}' refers to a field of '{}', which is not a message",
+                            field_path,
+                            self.method.name(),
+                            field.full_name()
+                        ),
+                        "only message fields may contain nested fields",
+                    );
+                    return;
+                }
+                (Kind::Message(_), None) => {
+                    self.error(
+                        format!(
+                            "path variable '{}' of method '{}' refers to message field '{}'",
+                            field_path,
+                            self.method.name(),
+                            field.full_name()
+                        ),
+                        "path variables must refer to scalar or enum fields",
+                    );
+                    return;
+                }
+                (_, None) => (),
+            }
+        }
+    }
+
+    fn check_top_level_field(&mut self, option: &str, name: &str, message: &MessageDescriptor) {
+        if message.get_field_by_name(name).is_none() {
+            self.error(
+                format!(
+                    "'{}' of method '{}' refers to unknown field '{}' of message '{}'",
+                    option,
+                    self.method.name(),
+                    name,
+                    message.full_name()
+                ),
+                format!(
+                    "'{}' must be empty, '*' or the name of a top-level field of '{}'",
+                    option,
+                    message.full_name()
+                ),
+            );
+        }
+    }
+
+    fn error(&mut self, message: String, help: impl Into<String>) {
+        self.diagnostics.error(self.path, message).set_help(help);
+    }
+}
+
+fn has_field(message: &DynamicMessage, name: &str) -> bool {
+    message
+        .descriptor()
+        .get_field_by_name(name)
+        .is_some_and(|field| message.has_field(&field))
+}
+
+fn string_field(message: &DynamicMessage, name: &str) -> String {
+    message
+        .get_field_by_name(name)
+        .and_then(|value| value.as_str().map(str::to_owned))
+        .unwrap_or_default()
+}
+
+/// Parses a path template, returning the field paths of its variables.
+///
+/// The grammar is defined in `google/api/http.proto`:
+///
+/// ```text
+/// Template = "/" Segments [ Verb ] ;
+/// Segments = Segment { "/" Segment } ;
+/// Segment  = "*" | "**" | LITERAL | Variable ;
+/// Variable = "{" FieldPath [ "=" Segments ] "}" ;
+/// FieldPath = IDENT { "." IDENT } ;
+/// Verb     = ":" LITERAL ;
+/// ```
+pub(crate) fn parse_template(template: &str) -> Result<Vec<&str>, String> {
+    let mut parser = TemplateParser {
+        template,
+        pos: 0,
+        variables: Vec::new(),
+    };
+    parser.parse()?;
+    Ok(parser.variables)
+}
+
+struct TemplateParser<'a> {
+    template: &'a str,
+    pos: usize,
+    variables: Vec<&'a str>,
+}
+
+impl<'a> TemplateParser<'a> {
+    fn parse(&mut self) -> Result<(), String> {
+        if !self.eat('/') {
+            return Err("the path must start with '/'".to_owned());
+        }
+        self.parse_segments(false)?;
+        if self.eat(':') && self.parse_literal().is_empty() {
+            return Err(format!("expected a verb at offset {}", self.pos));
+        }
+        match self.peek() {
+            None => Ok(()),
+            Some(ch) => Err(format!("unexpected '{}' at offset {}", ch, self.pos)),
+        }
+    }
+
+    fn parse_segments(&mut self, in_variable: bool) -> Result<(), String> {
+        loop {
+            let is_wildcard = self.parse_segment(in_variable)?;
+            if !self.eat('/') {
+                return Ok(());
+            }
+            if is_wildcard {
+                return Err("'**' must be the last segment of the path".to_owned());
+            }
+        }
+    }
+
+    /// Parses a segment, returning `true` if it is `**`.
+    fn parse_segment(&mut self, in_variable: bool) -> Result<bool, String> {
+        match self.peek() {
+            Some('*') => {
+                self.pos += 1;
+                Ok(self.eat('*'))
+            }
+            Some('{') if in_variable => Err(format!(
+                "variables may not be nested, at offset {}",
+                self.pos
+            )),
+            Some('{') => {
+                self.pos += 1;
+                self.parse_variable()?;
+                Ok(false)
+            }
+            _ => {
+                if self.parse_literal().is_empty() {
+                    Err(match self.peek() {
+                        Some(ch) => format!("expected a segment, but found '{}'", ch),
+                        None => "expected a segment, but found the end of the path".to_owned(),
+                    })
+                } else {
+                    Ok(false)
+                }
+            }
+        }
+    }
+
+    fn parse_variable(&mut self) -> Result<(), String> {
+        let start = self.pos;
+        loop {
+            let ident = self.take_while(|ch| ch.is_ascii_alphanumeric() || ch == '_');
+            if ident.is_empty() || ident.starts_with(|ch: char| ch.is_ascii_digit()) {
+                return Err(format!("expected a field name at offset {}", self.pos));
+            }
+            if !self.eat('.') {
+                break;
+            }
+        }
+        self.variables.push(&self.template[start..self.pos]);
+
+        if self.eat('=') {
+            self.parse_segments(true)?;
+        }
+        if !self.eat('}') {
+            return Err(format!(
+                "expected '}}' to close the variable at offset {}",
+                self.pos
+            ));
+        }
+        Ok(())
+    }
+
+    fn parse_literal(&mut self) -> &'a str {
+        self.take_while(|ch| {
+            !matches!(ch, '/' | '{' | '}' | ':' | '*' | '=' | '?' | '#') && !ch.is_whitespace()
+        })
+    }
+
+    fn take_while(&mut self, f: impl Fn(char) -> bool) -> &'a str {
+        let start = self.pos;
+        let len = self.template[start..]
+            .find(|ch| !f(ch))
+            .unwrap_or(self.template.len() - start);
+        self.pos += len;
+        &self.template[start..self.pos]
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.template[self.pos..].chars().next()
+    }
+
+    fn eat(&mut self, ch: char) -> bool {
+        if self.peek() == Some(ch) {
+            self.pos += ch.len_utf8();
+            true
+        } else {
+            false
+        }
+    }
+}
@@ -0,0 +1,151 @@
+use super::{parse_template, HttpRuleCheck};
+use crate::{
+    file::{ChainFileResolver, File, FileResolver, GoogleFileResolver},
+    Compiler, Error,
+};
+
+struct TestFileResolver {
+    source: String,
+}
+
+impl FileResolver for TestFileResolver {
+    fn open_file(&self, name: &str) -> Result<File, Error> {
+        if name == "root.proto" {
+            File::from_source(name, &self.source)
+        } else {
+            Err(Error::file_not_found(name))
+        }
+    }
+}
+
+fn check(rule: &str) -> Result<(), String> {
+    let source = format!(
+        "syntax = 'proto3';
+        import 'google/api/annotations.proto';
+
+        message Shelf {{ string id = 1; repeated string tags = 2; }}
+        message Request {{
+          string name = 1;
+          Shelf shelf = 2;
+          repeated string ids = 3;
+          int64 count = 4;
+        }}
+        message Response {{ Shelf shelf = 1; }}
+
+        service Library {{
+          rpc Method(Request) returns (Response) {{
+            option (google.api.http) = {{ {} }};
+          }}
+        }}",
+        rule
+    );
+
+    let mut resolver = ChainFileResolver::new();
+    resolver.add(TestFileResolver { source });
+    resolver.add(GoogleFileResolver::new());
+
+    let mut compiler = Compiler::with_file_resolver(resolver);
+    compiler.add_check(HttpRuleCheck::new());
+    match compiler.open_file("root.proto") {
+        Ok(_) => Ok(()),
+        Err(err) => Err(err.to_string()),
+    }
+}
+
+#[test]
+fn valid_rules() {
+    check("get: '/v1/{name}'").unwrap();
+    check("get: '/v1/{name=shelves/*/books/**}'").unwrap();
+    check("post: '/v1/{shelf.id}:publish' body: '*'").unwrap();
+    check("put: '/v1/{count}' body: 'shelf' response_body: 'shelf'").unwrap();
+    check("custom: { kind: 'HEAD' path: '/v1/*' }").unwrap();
+    check("get: '/v1' additional_bindings: { post: '/v2/{name}' body: '*' }").unwrap();
+}
+
+#[test]
+fn invalid_rules() {
+    assert_eq!(
+        check("body: '*'").unwrap_err(),
+        "HTTP rule of method 'Method' does not specify a pattern"
+    );
+    assert_eq!(
+        check("custom: { path: '/v1' }").unwrap_err(),
+        "custom HTTP pattern of method 'Method' does not specify a kind"
+    );
+    assert_eq!(
+        check("get: 'v1/{name}'").unwrap_err(),
+        "invalid path template 'v1/{name}' for method 'Method': the path must start with '/'"
+    );
+    assert_eq!(
+        check("get: '/v1/{missing}'").unwrap_err(),
+        "path variable 'missing' of method 'Method' refers to unknown field 'missing' of message 'Request'"
+    );
+    assert_eq!(
+        check("get: '/v1/{ids}'").unwrap_err(),
+        "path variable 'ids' of method 'Method' refers to repeated field 'Request.ids'"
+    );
+    assert_eq!(
+        check("get: '/v1/{shelf}'").unwrap_err(),
+        "path variable 'shelf' of method 'Method' refers to message field 'Request.shelf'"
+    );
+    assert_eq!(
+        check("get: '/v1/{name.id}'").unwrap_err(),
+        "path variable 'name.id' of method 'Method' refers to a field of 'Request.name', which is not a message"
+    );
+    assert_eq!(
+        check("get: '/v1/{name}' body: '*'").unwrap_err(),
+        "HTTP GET rule of method 'Method' must not have a body"
+    );
+    assert_eq!(
+        check("post: '/v1' body: 'shelf.id'").unwrap_err(),
+        "'body' of method 'Method' refers to unknown field 'shelf.id' of message 'Request'"
+    );
+    assert_eq!(
+        check("post: '/v1' response_body: 'name'").unwrap_err(),
+        "'response_body' of method 'Method' refers to unknown field 'name' of message 'Response'"
+    );
+    assert_eq!(
+        check("get: '/v1' additional_bindings: { get: '/v2' additional_bindings: { get: '/v3' } }")
+            .unwrap_err(),
+        "additional binding of method 'Method' has its own additional bindings"
+    );
+}
+
+#[test]
+fn path_templates() {
+    assert_eq!(parse_template("/v1/books").unwrap(), Vec::<&str>::new());
+    assert_eq!(
+        parse_template("/v1/{book.name=shelves/*/books/*}:get").unwrap(),
+        ["book.name"]
+    );
+    assert_eq!(parse_template("/{a}/{b=**}").unwrap(), ["a", "b"]);
+
+    assert_eq!(
+        parse_template("/v1/**/books").unwrap_err(),
+        "'**' must be the last segment of the path"
+    );
+    assert_eq!(
+        parse_template("/v1/{a={b}}").unwrap_err(),
+        "variables may not be nested, at offset 7"
+    );
+    assert_eq!(
+        parse_template("/v1/{a").unwrap_err(),
+        "expected '}' to close the variable at offset 6"
+    );
+    assert_eq!(
+        parse_template("/v1/{}").unwrap_err(),
+        "expected a field name at offset 5"
+    );
+    assert_eq!(
+        parse_template("/v1//books").unwrap_err(),
+        "expected a segment, but found '/'"
+    );
+    assert_eq!(
+        parse_template("/v1/books:").unwrap_err(),
+        "expected a verb at offset 10"
+    );
+    assert_eq!(
+        parse_template("/v1/books?a=b").unwrap_err(),
+        "unexpected '?' at offset 9"
+    );
+}
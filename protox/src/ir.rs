@@ -0,0 +1,346 @@
+//! A read-only view of the elements of a compiled file, as they were declared in the source.
+//!
+//! The parser lowers some declarations into several descriptors: a `map<K, V>` field becomes a repeated field of a
+//! generated map entry message, a group becomes both a field and a nested message, and a proto3 `optional` field is
+//! wrapped in a synthetic oneof. The types in this module undo that normalization, so tools such as code generators
+//! can special-case these elements without re-deriving them from the descriptors:
+//!
+//! * [`Message::messages`] only includes messages declared with a `message` statement. Map entries are available from
+//!   [`FieldSource::Map`], and the bodies of groups from [`FieldSource::Group`].
+//! * [`Oneof::is_synthetic`] identifies the oneofs generated for proto3 `optional` fields.
+//! * [`Field::is_packed`] applies the resolved [`repeated_field_encoding`](crate::features::FeatureSet::repeated_field_encoding)
+//!   feature, so it includes the default packing of repeated fields in proto3 files, where the `packed` option is
+//!   usually not set.
+//!
+//! The view is built from a [`FileDescriptor`], so it can be used for pre-compiled files as well as source files.
+//!
+//! # Examples
+//!
+//! ```
+//! # use protox::{file::{File, FileResolver}, ir::{self, FieldSource}, Compiler};
+//! # struct Resolver;
+//! # impl FileResolver for Resolver {
+//! #     fn open_file(&self, name: &str) -> Result<File, protox::Error> {
+//! #         File::from_source(name, "syntax = 'proto3'; message Foo { map<string, int32> counts = 1; optional int32 limit = 2; }")
+//! #     }
+//! # }
+//! let mut compiler = Compiler::with_file_resolver(Resolver);
+//! compiler.open_file("foo.proto")?;
+//!
+//! let file = ir::File::new(&compiler.descriptor_pool().get_file_by_name("foo.proto").unwrap());
+//! let message = &file.messages()[0];
+//! assert!(message.messages().is_empty());
+//!
+//! let FieldSource::Map { key, value, .. } = message.fields()[0].source() else {
+//!     panic!("expected a map field");
+//! };
+//! assert_eq!((key.name(), value.name()), ("key", "value"));
+//!
+//! assert!(message.oneofs()[0].is_synthetic());
+//! assert!(message.fields()[1].is_proto3_optional());
+//! # Ok::<(), protox::Error>(())
+//! ```
+
+use prost_reflect::{
+    ExtensionDescriptor, FieldDescriptor, FileDescriptor, Kind, MessageDescriptor, OneofDescriptor,
+};
+
+use crate::{
+    compile::is_packable,
+    features::{FeatureResolver, FeatureSet, RepeatedFieldEncoding},
+};
+
+/// The messages and extensions declared in a file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct File {
+    descriptor: FileDescriptor,
+    messages: Vec<Message>,
+    extensions: Vec<Extension>,
+}
+
+/// A message declared with a `message` statement, or the body of a group.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Message {
+    descriptor: MessageDescriptor,
+    fields: Vec<Field>,
+    oneofs: Vec<Oneof>,
+    messages: Vec<Message>,
+    extensions: Vec<Extension>,
+}
+
+/// A field of a [`Message`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Field {
+    descriptor: FieldDescriptor,
+    source: FieldSource,
+    is_packed: bool,
+}
+
+/// An extension field declared in a file or message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Extension {
+    descriptor: ExtensionDescriptor,
+    source: FieldSource,
+    is_packed: bool,
+}
+
+/// A oneof of a [`Message`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Oneof {
+    descriptor: OneofDescriptor,
+    is_synthetic: bool,
+}
+
+/// How a field was declared in the source.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum FieldSource {
+    /// A field with a scalar, message or enum type.
+    Field,
+    /// A `map<K, V>` field, whose type is the generated map entry message.
+    Map {
+        /// The generated map entry message.
+        entry: MessageDescriptor,
+        /// The `key` field of the map entry.
+        key: FieldDescriptor,
+        /// The `value` field of the map entry.
+        value: FieldDescriptor,
+    },
+    /// A group, whose type is the message declared by its body.
+    Group(Box<Message>),
+}
+
+impl File {
+    /// Builds the view of a compiled file.
+    pub fn new(file: &FileDescriptor) -> Self {
+        let features = FeatureResolver::new(file);
+        let extensions: Vec<Extension> = file
+            .extensions()
+            .map(|extension| Extension::new(extension, &features))
+            .collect();
+        let messages = file
+            .messages()
+            .filter(|message| !is_synthesized(message, &[], &extensions))
+            .map(|message| Message::new(&message, &features))
+            .collect();
+        File {
+            descriptor: file.clone(),
+            messages,
+            extensions,
+        }
+    }
+
+    /// Gets the descriptor of the file.
+    pub fn descriptor(&self) -> &FileDescriptor {
+        &self.descriptor
+    }
+
+    /// Gets the top-level messages declared in the file, excluding the bodies of groups.
+    pub fn messages(&self) -> &[Message] {
+        &self.messages
+    }
+
+    /// Gets the top-level extensions declared in the file.
+    pub fn extensions(&self) -> &[Extension] {
+        &self.extensions
+    }
+}
+
+impl Message {
+    fn new(message: &MessageDescriptor, features: &FeatureResolver) -> Self {
+        let fields: Vec<Field> = message
+            .fields()
+            .map(|field| Field::new(field, features))
+            .collect();
+        let extensions: Vec<Extension> = message
+            .child_extensions()
+            .map(|extension| Extension::new(extension, features))
+            .collect();
+        let oneofs = message
+            .oneofs()
+            .map(|oneof| Oneof {
+                is_synthetic: is_synthetic(&oneof),
+                descriptor: oneof,
+            })
+            .collect();
+        let messages = message
+            .child_messages()
+            .filter(|nested| !is_synthesized(nested, &fields, &extensions))
+            .map(|nested| Message::new(&nested, features))
+            .collect();
+        Message {
+            descriptor: message.clone(),
+            fields,
+            oneofs,
+            messages,
+            extensions,
+        }
+    }
+
+    /// Gets the descriptor of the message.
+    pub fn descriptor(&self) -> &MessageDescriptor {
+        &self.descriptor
+    }
+
+    /// Gets the fields of the message, in the order they were declared.
+    pub fn fields(&self) -> &[Field] {
+        &self.fields
+    }
+
+    /// Gets the oneofs of the message, including synthetic oneofs.
+    pub fn oneofs(&self) -> &[Oneof] {
+        &self.oneofs
+    }
+
+    /// Gets the messages declared within this message, excluding map entries and the bodies of groups.
+    pub fn messages(&self) -> &[Message] {
+        &self.messages
+    }
+
+    /// Gets the extensions declared within this message.
+    pub fn extensions(&self) -> &[Extension] {
+        &self.extensions
+    }
+}
+
+impl Field {
+    fn new(field: FieldDescriptor, features: &FeatureResolver) -> Self {
+        let source = if field.is_map() {
+            match field.kind() {
+                Kind::Message(entry) => FieldSource::Map {
+                    key: entry.map_entry_key_field(),
+                    value: entry.map_entry_value_field(),
+                    entry,
+                },
+                _ => FieldSource::Field,
+            }
+        } else {
+            group_source(
+                field.is_group(),
+                field.kind(),
+                Some(field.parent_message()),
+                features,
+            )
+        };
+        let is_packed =
+            field.is_list() && is_packable(&field.kind()) && is_packed(&features.for_field(&field));
+        Field {
+            descriptor: field,
+            source,
+            is_packed,
+        }
+    }
+
+    /// Gets the descriptor of the field.
+    pub fn descriptor(&self) -> &FieldDescriptor {
+        &self.descriptor
+    }
+
+    /// Gets how the field was declared.
+    pub fn source(&self) -> &FieldSource {
+        &self.source
+    }
+
+    /// Returns `true` if this is a proto3 `optional` field, which is the only field of a synthetic oneof.
+    pub fn is_proto3_optional(&self) -> bool {
+        self.descriptor.field_descriptor_proto().proto3_optional()
+    }
+
+    /// Returns `true` if this is a repeated field which uses packed encoding.
+    ///
+    /// Unlike [`FieldDescriptor::is_packed`], this applies the proto3 default to fields which have options but do not
+    /// set `packed`.
+    pub fn is_packed(&self) -> bool {
+        self.is_packed
+    }
+}
+
+impl Extension {
+    fn new(extension: ExtensionDescriptor, features: &FeatureResolver) -> Self {
+        let source = group_source(
+            extension.is_group(),
+            extension.kind(),
+            extension.parent_message().as_ref(),
+            features,
+        );
+        let is_packed = extension.is_list()
+            && is_packable(&extension.kind())
+            && is_packed(&features.for_extension(&extension));
+        Extension {
+            descriptor: extension,
+            source,
+            is_packed,
+        }
+    }
+
+    /// Gets the descriptor of the extension.
+    pub fn descriptor(&self) -> &ExtensionDescriptor {
+        &self.descriptor
+    }
+
+    /// Gets how the extension was declared. Extensions cannot be maps, so this is either [`FieldSource::Field`] or
+    /// [`FieldSource::Group`].
+    pub fn source(&self) -> &FieldSource {
+        &self.source
+    }
+
+    /// Returns `true` if this is a repeated extension which uses packed encoding. See [`Field::is_packed`].
+    pub fn is_packed(&self) -> bool {
+        self.is_packed
+    }
+}
+
+impl Oneof {
+    /// Gets the descriptor of the oneof.
+    pub fn descriptor(&self) -> &OneofDescriptor {
+        &self.descriptor
+    }
+
+    /// Returns `true` if this oneof was generated for a proto3 `optional` field, rather than declared in the source.
+    pub fn is_synthetic(&self) -> bool {
+        self.is_synthetic
+    }
+}
+
+/// Gets the source of a field which is not a map. The body of a group is declared in the same scope as the field, so
+/// a group type declared elsewhere in a pre-compiled file is treated as a normal message field.
+fn group_source(
+    is_group: bool,
+    kind: Kind,
+    scope: Option<&MessageDescriptor>,
+    features: &FeatureResolver,
+) -> FieldSource {
+    match kind {
+        Kind::Message(message) if is_group && message.parent_message().as_ref() == scope => {
+            FieldSource::Group(Box::new(Message::new(&message, features)))
+        }
+        _ => FieldSource::Field,
+    }
+}
+
+/// Returns `true` if a nested message was generated for a map field or group declared in the same scope.
+fn is_synthesized(message: &MessageDescriptor, fields: &[Field], extensions: &[Extension]) -> bool {
+    let sources = fields
+        .iter()
+        .map(Field::source)
+        .chain(extensions.iter().map(Extension::source));
+    for source in sources {
+        match source {
+            FieldSource::Map { entry, .. } if entry == message => return true,
+            FieldSource::Group(group) if group.descriptor() == message => return true,
+            _ => (),
+        }
+    }
+    false
+}
+
+fn is_packed(features: &FeatureSet) -> bool {
+    features.repeated_field_encoding() == RepeatedFieldEncoding::Packed
+}
+
+fn is_synthetic(oneof: &OneofDescriptor) -> bool {
+    oneof.fields().len() == 1
+        && oneof
+            .fields()
+            .all(|field| field.field_descriptor_proto().proto3_optional())
+}
@@ -0,0 +1,583 @@
+//! Conversion of JSON Schema and OpenAPI 3 documents to protobuf files.
+//!
+//! This module is only available if the `json-schema` feature is enabled.
+//!
+//! [`from_json_schema`] and [`from_openapi`] convert the named schemas in a document into a [`FileBuilder`] for a
+//! `proto3` file. The result can be rendered as `.proto` source, or passed to a [`Compiler`](crate::Compiler) to
+//! check it and produce file descriptors, in the same way as any other builder.
+//!
+//! Schemas are converted as follows:
+//!
+//! - Objects with `properties` become messages. Properties are converted to `lower_snake_case` fields, numbered from 1
+//!   in alphabetical order of their names, with a `json_name` option if needed to preserve the original name.
+//!   Properties which are `nullable`, or whose `type` includes `"null"`, become `optional` fields.
+//! - Objects with only `additionalProperties` become `map<string, _>` fields, and objects with neither become
+//!   `google.protobuf.Struct` fields. Schemas with no type become `google.protobuf.Value` fields.
+//! - String schemas with an `enum` keyword become enums, with an additional `_UNSPECIFIED` zero value.
+//! - Arrays become `repeated` fields.
+//! - `string`, `boolean`, `integer` and `number` become the corresponding scalar types, taking the `format` keyword
+//!   into account. Strings with the `byte` format become `bytes`, and the `date-time` format becomes
+//!   `google.protobuf.Timestamp`.
+//! - `$ref`s to other named schemas become references to the corresponding message or enum.
+//! - Descriptions become comments.
+//!
+//! Inline object and enum schemas are converted to nested types named after their property. Keywords which have no
+//! protobuf equivalent, such as `oneOf` or `allOf`, result in a [`SchemaError`].
+//!
+//! # Examples
+//!
+//! ```
+//! # use protox::{json_schema, file::{ChainFileResolver, GoogleFileResolver}, Compiler};
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let document = serde_json::json!({
+//!     "openapi": "3.0.0",
+//!     "components": {
+//!         "schemas": {
+//!             "Pet": {
+//!                 "type": "object",
+//!                 "properties": {
+//!                     "name": { "type": "string" },
+//!                     "status": { "type": "string", "enum": ["available", "sold"] },
+//!                 },
+//!             },
+//!         },
+//!     },
+//! });
+//!
+//! let mut file = json_schema::from_openapi("pet.proto", &document)?;
+//! file.package("pets");
+//!
+//! assert_eq!(file.to_source(), r#"syntax = "proto3";
+//!
+//! package pets;
+//!
+//! message Pet {
+//!   string name = 1;
+//!   enum Status {
+//!     STATUS_UNSPECIFIED = 0;
+//!     STATUS_AVAILABLE = 1;
+//!     STATUS_SOLD = 2;
+//!   }
+//!   Status status = 2;
+//! }
+//! "#);
+//!
+//! let mut resolver = ChainFileResolver::new();
+//! resolver.add(file);
+//! resolver.add(GoogleFileResolver::new());
+//! Compiler::with_file_resolver(resolver).open_file("pet.proto")?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::{collections::BTreeSet, iter, mem};
+
+use miette::Diagnostic;
+use prost_types::field_descriptor_proto::Label;
+use protox_parse::case::to_json_name;
+use serde_json::{Map, Value};
+use thiserror::Error;
+
+use crate::builder::{EnumBuilder, FileBuilder, MessageBuilder};
+
+#[cfg(test)]
+mod tests;
+
+/// An error returned when a schema cannot be converted to protobuf.
+#[derive(Debug, Clone, Diagnostic, Error)]
+#[error("cannot convert schema at '{pointer}': {message}")]
+pub struct SchemaError {
+    pointer: String,
+    message: String,
+}
+
+impl SchemaError {
+    /// The [JSON pointer](https://datatracker.ietf.org/doc/html/rfc6901) of the schema which could not be converted.
+    pub fn pointer(&self) -> &str {
+        &self.pointer
+    }
+
+    /// A description of the problem.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+/// Converts a JSON Schema document to a protobuf file with the given name.
+///
+/// The root schema is converted to a message named after its `title`, or the file name if it has none. Schemas under
+/// `$defs` or `definitions` are converted to top-level messages and enums.
+pub fn from_json_schema(name: &str, schema: &Value) -> Result<FileBuilder, SchemaError> {
+    let mut converter = Converter::new(name);
+
+    let root = as_object(schema, "")?;
+    if root.contains_key("properties") || root.contains_key("type") {
+        let title = match root.get("title") {
+            Some(Value::String(title)) => title.as_str(),
+            _ => {
+                let stem = name.rsplit('/').next().unwrap_or(name);
+                stem.strip_suffix(".proto").unwrap_or(stem)
+            }
+        };
+        converter.convert_definition(title, schema, "")?;
+    }
+
+    for keyword in ["$defs", "definitions"] {
+        if let Some(definitions) = root.get(keyword) {
+            converter.convert_definitions(definitions, &format!("/{}", keyword))?;
+        }
+    }
+
+    Ok(converter.finish())
+}
+
+/// Converts the schemas under `components/schemas` in an OpenAPI 3 document to a protobuf file with the given name.
+pub fn from_openapi(name: &str, document: &Value) -> Result<FileBuilder, SchemaError> {
+    let root = as_object(document, "")?;
+    match root.get("openapi") {
+        Some(Value::String(version)) if version.starts_with("3.") => (),
+        _ => return Err(error("/openapi", "expected an OpenAPI 3 document")),
+    }
+
+    let mut converter = Converter::new(name);
+    if let Some(schemas) = root
+        .get("components")
+        .and_then(|components| components.get("schemas"))
+    {
+        converter.convert_definitions(schemas, "/components/schemas")?;
+    }
+
+    Ok(converter.finish())
+}
+
+struct Converter {
+    file: FileBuilder,
+    imports: BTreeSet<&'static str>,
+}
+
+/// The protobuf type of a field, and whether it is repeated or has explicit presence.
+struct FieldType {
+    ty: String,
+    label: Option<Label>,
+}
+
+impl Converter {
+    fn new(name: &str) -> Self {
+        let mut file = FileBuilder::new(name);
+        file.syntax("proto3");
+        Converter {
+            file,
+            imports: BTreeSet::new(),
+        }
+    }
+
+    fn finish(mut self) -> FileBuilder {
+        for import in self.imports {
+            self.file.import(import);
+        }
+        self.file
+    }
+
+    fn convert_definitions(
+        &mut self,
+        definitions: &Value,
+        pointer: &str,
+    ) -> Result<(), SchemaError> {
+        for (name, schema) in as_object(definitions, pointer)? {
+            self.convert_definition(name, schema, &child_pointer(pointer, name))?;
+        }
+        Ok(())
+    }
+
+    fn convert_definition(
+        &mut self,
+        name: &str,
+        schema: &Value,
+        pointer: &str,
+    ) -> Result<(), SchemaError> {
+        let name = to_pascal_case(name, pointer)?;
+        let object = as_object(schema, pointer)?;
+        check_unsupported(object, pointer)?;
+
+        if let Some(values) = object.get("enum") {
+            let enum_ = self.file.add_enum(&name);
+            convert_enum(enum_, &name, object, values, pointer)
+        } else if is_object(object) {
+            let message = self.file.add_message(&name);
+            convert_message(&mut self.imports, message, object, pointer)
+        } else {
+            Err(error(pointer, "named schemas must be objects or enums"))
+        }
+    }
+}
+
+fn convert_message(
+    imports: &mut BTreeSet<&'static str>,
+    message: &mut MessageBuilder,
+    object: &Map<String, Value>,
+    pointer: &str,
+) -> Result<(), SchemaError> {
+    if let Some(Value::String(description)) = object.get("description") {
+        message.comment(description);
+    }
+
+    let Some(properties) = object.get("properties") else {
+        return Ok(());
+    };
+    let properties_pointer = child_pointer(pointer, "properties");
+    for (number, (property, schema)) in as_object(properties, &properties_pointer)?
+        .iter()
+        .enumerate()
+    {
+        let pointer = child_pointer(&properties_pointer, property);
+        let field_name = to_snake_case(property, &pointer)?;
+        let field_type = convert_field_type(imports, message, property, schema, &pointer)?;
+
+        let field = message.add_field(&field_name, field_type.ty, number as i32 + 1);
+        if let Some(label) = field_type.label {
+            field.label(label);
+        }
+        if to_json_name(&field_name) != *property {
+            field.option("json_name", quote(property));
+        }
+        if let Some(Value::String(description)) = schema.get("description") {
+            field.comment(description);
+        }
+    }
+    Ok(())
+}
+
+fn convert_field_type(
+    imports: &mut BTreeSet<&'static str>,
+    message: &mut MessageBuilder,
+    property: &str,
+    schema: &Value,
+    pointer: &str,
+) -> Result<FieldType, SchemaError> {
+    let object = as_object(schema, pointer)?;
+    check_unsupported(object, pointer)?;
+
+    if let Some(Value::String(reference)) = object.get("$ref") {
+        return Ok(FieldType {
+            ty: resolve_reference(reference, pointer)?,
+            label: None,
+        });
+    }
+
+    let (ty, nullable) = schema_type(object, pointer)?;
+    let label = if nullable {
+        Some(Label::Optional)
+    } else {
+        None
+    };
+
+    if let Some(values) = object.get("enum") {
+        let name = to_pascal_case(property, pointer)?;
+        convert_enum(message.add_enum(&name), &name, object, values, pointer)?;
+        return Ok(FieldType { ty: name, label });
+    }
+
+    let ty = match ty {
+        Some("string") => match object.get("format").and_then(Value::as_str) {
+            Some("byte") | Some("binary") => "bytes".to_owned(),
+            Some("date-time") => {
+                imports.insert("google/protobuf/timestamp.proto");
+                return Ok(FieldType {
+                    ty: "google.protobuf.Timestamp".to_owned(),
+                    label: None,
+                });
+            }
+            _ => "string".to_owned(),
+        },
+        Some("boolean") => "bool".to_owned(),
+        Some("integer") => match object.get("format").and_then(Value::as_str) {
+            Some("int32") => "int32".to_owned(),
+            Some("uint32") => "uint32".to_owned(),
+            Some("uint64") => "uint64".to_owned(),
+            _ => "int64".to_owned(),
+        },
+        Some("number") => match object.get("format").and_then(Value::as_str) {
+            Some("float") => "float".to_owned(),
+            _ => "double".to_owned(),
+        },
+        Some("array") => {
+            let items_pointer = child_pointer(pointer, "items");
+            let items = object
+                .get("items")
+                .ok_or_else(|| error(pointer, "arrays must have an 'items' schema"))?;
+            let item_type = convert_field_type(imports, message, property, items, &items_pointer)?;
+            if item_type.label == Some(Label::Repeated) || item_type.ty.starts_with("map<") {
+                return Err(error(
+                    &items_pointer,
+                    "nested arrays and maps are not supported",
+                ));
+            }
+            return Ok(FieldType {
+                ty: item_type.ty,
+                label: Some(Label::Repeated),
+            });
+        }
+        Some("object") | None if is_object(object) => {
+            if object.contains_key("properties") {
+                let name = to_pascal_case(property, pointer)?;
+                convert_message(imports, message.add_message(&name), object, pointer)?;
+                return Ok(FieldType {
+                    ty: name,
+                    label: None,
+                });
+            }
+
+            match object.get("additionalProperties") {
+                Some(Value::Bool(true)) | None => {
+                    imports.insert("google/protobuf/struct.proto");
+                    return Ok(FieldType {
+                        ty: "google.protobuf.Struct".to_owned(),
+                        label: None,
+                    });
+                }
+                Some(values) => {
+                    let values_pointer = child_pointer(pointer, "additionalProperties");
+                    let value_type =
+                        convert_field_type(imports, message, property, values, &values_pointer)?;
+                    if value_type.label == Some(Label::Repeated)
+                        || value_type.ty.starts_with("map<")
+                    {
+                        return Err(error(
+                            &values_pointer,
+                            "nested arrays and maps are not supported",
+                        ));
+                    }
+                    return Ok(FieldType {
+                        ty: format!("map<string, {}>", value_type.ty),
+                        label: None,
+                    });
+                }
+            }
+        }
+        None => {
+            imports.insert("google/protobuf/struct.proto");
+            return Ok(FieldType {
+                ty: "google.protobuf.Value".to_owned(),
+                label: None,
+            });
+        }
+        Some(ty) => return Err(error(pointer, &format!("unsupported type '{}'", ty))),
+    };
+
+    Ok(FieldType { ty, label })
+}
+
+fn convert_enum(
+    enum_: &mut EnumBuilder,
+    name: &str,
+    object: &Map<String, Value>,
+    values: &Value,
+    pointer: &str,
+) -> Result<(), SchemaError> {
+    if let Some(Value::String(description)) = object.get("description") {
+        enum_.comment(description);
+    }
+
+    let prefix = to_upper_snake_case(name);
+    enum_.add_value(format!("{}_UNSPECIFIED", prefix), 0);
+
+    let values_pointer = child_pointer(pointer, "enum");
+    let Value::Array(values) = values else {
+        return Err(error(&values_pointer, "expected an array"));
+    };
+    for (number, value) in values.iter().enumerate() {
+        let value_pointer = child_pointer(&values_pointer, &number.to_string());
+        match value {
+            Value::String(value) => {
+                let value_name = to_upper_snake_case(value);
+                if value_name.is_empty() {
+                    return Err(error(
+                        &value_pointer,
+                        "enum values must contain a letter or digit",
+                    ));
+                }
+                enum_.add_value(format!("{}_{}", prefix, value_name), number as i32 + 1);
+            }
+            Value::Null => (),
+            _ => {
+                return Err(error(
+                    &value_pointer,
+                    "only string enum values are supported",
+                ))
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Returns the non-null type of a schema, and whether it is nullable.
+fn schema_type<'a>(
+    object: &'a Map<String, Value>,
+    pointer: &str,
+) -> Result<(Option<&'a str>, bool), SchemaError> {
+    let nullable = object.get("nullable") == Some(&Value::Bool(true));
+    match object.get("type") {
+        None => Ok((None, nullable)),
+        Some(Value::String(ty)) => Ok((Some(ty.as_str()), nullable)),
+        Some(Value::Array(types)) => {
+            let non_null: Vec<_> = types
+                .iter()
+                .filter(|ty| ty.as_str() != Some("null"))
+                .collect();
+            let ty = match non_null[..] {
+                [Value::String(ty)] => Some(ty.as_str()),
+                [] => None,
+                _ => {
+                    return Err(error(
+                        &child_pointer(pointer, "type"),
+                        "only a single non-null type is supported",
+                    ))
+                }
+            };
+            Ok((ty, nullable || non_null.len() != types.len()))
+        }
+        Some(_) => Err(error(
+            &child_pointer(pointer, "type"),
+            "expected a string or array",
+        )),
+    }
+}
+
+fn is_object(object: &Map<String, Value>) -> bool {
+    object.get("type").and_then(Value::as_str) == Some("object")
+        || object.contains_key("properties")
+        || object.contains_key("additionalProperties")
+}
+
+fn check_unsupported(object: &Map<String, Value>, pointer: &str) -> Result<(), SchemaError> {
+    for keyword in ["oneOf", "anyOf", "allOf", "not"] {
+        if object.contains_key(keyword) {
+            return Err(error(
+                pointer,
+                &format!("the '{}' keyword is not supported", keyword),
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn resolve_reference(reference: &str, pointer: &str) -> Result<String, SchemaError> {
+    for prefix in ["#/$defs/", "#/definitions/", "#/components/schemas/"] {
+        if let Some(name) = reference.strip_prefix(prefix) {
+            if !name.contains('/') {
+                return to_pascal_case(&unescape_pointer(name), pointer);
+            }
+        }
+    }
+
+    Err(error(
+        &child_pointer(pointer, "$ref"),
+        &format!("unsupported reference '{}'", reference),
+    ))
+}
+
+fn as_object<'a>(value: &'a Value, pointer: &str) -> Result<&'a Map<String, Value>, SchemaError> {
+    value
+        .as_object()
+        .ok_or_else(|| error(pointer, "expected an object"))
+}
+
+fn error(pointer: &str, message: &str) -> SchemaError {
+    SchemaError {
+        pointer: pointer.to_owned(),
+        message: message.to_owned(),
+    }
+}
+
+fn child_pointer(pointer: &str, name: &str) -> String {
+    format!("{}/{}", pointer, name.replace('~', "~0").replace('/', "~1"))
+}
+
+fn unescape_pointer(name: &str) -> String {
+    name.replace("~1", "/").replace("~0", "~")
+}
+
+/// Splits a name into words, at non-alphanumeric characters and lowercase to uppercase transitions.
+fn words(name: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut current = String::new();
+    let mut prev_lower = false;
+    for ch in name.chars() {
+        if !ch.is_ascii_alphanumeric() {
+            if !current.is_empty() {
+                words.push(mem::take(&mut current));
+            }
+            prev_lower = false;
+            continue;
+        }
+        if ch.is_ascii_uppercase() && prev_lower {
+            words.push(mem::take(&mut current));
+        }
+        prev_lower = ch.is_ascii_lowercase() || ch.is_ascii_digit();
+        current.push(ch);
+    }
+    if !current.is_empty() {
+        words.push(current);
+    }
+    words
+}
+
+fn to_identifier(name: &str, pointer: &str, ident: String) -> Result<String, SchemaError> {
+    if ident
+        .as_bytes()
+        .first()
+        .is_some_and(u8::is_ascii_alphabetic)
+    {
+        Ok(ident)
+    } else {
+        Err(error(
+            pointer,
+            &format!("cannot convert '{}' to a valid protobuf name", name),
+        ))
+    }
+}
+
+fn to_pascal_case(name: &str, pointer: &str) -> Result<String, SchemaError> {
+    let ident = words(name)
+        .iter()
+        .map(|word| {
+            let mut chars = word.chars();
+            let first = chars.next().unwrap().to_ascii_uppercase();
+            iter::once(first).chain(chars).collect::<String>()
+        })
+        .collect();
+    to_identifier(name, pointer, ident)
+}
+
+fn to_snake_case(name: &str, pointer: &str) -> Result<String, SchemaError> {
+    let ident = words(name)
+        .iter()
+        .map(|word| word.to_ascii_lowercase())
+        .collect::<Vec<_>>()
+        .join("_");
+    to_identifier(name, pointer, ident)
+}
+
+fn to_upper_snake_case(name: &str) -> String {
+    words(name)
+        .iter()
+        .map(|word| word.to_ascii_uppercase())
+        .collect::<Vec<_>>()
+        .join("_")
+}
+
+/// Formats a string as a protobuf string literal.
+fn quote(value: &str) -> String {
+    let mut result = String::with_capacity(value.len() + 2);
+    result.push('"');
+    for ch in value.chars() {
+        match ch {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            ch if ch.is_ascii_control() => result.push_str(&format!("\\x{:02x}", ch as u8)),
+            ch => result.push(ch),
+        }
+    }
+    result.push('"');
+    result
+}
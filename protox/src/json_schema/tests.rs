@@ -0,0 +1,249 @@
+use serde_json::json;
+
+use super::{from_json_schema, from_openapi};
+use crate::{
+    file::{ChainFileResolver, GoogleFileResolver},
+    Compiler,
+};
+
+fn check(file: crate::builder::FileBuilder) -> Compiler {
+    let name = file.name().to_owned();
+    let mut resolver = ChainFileResolver::new();
+    resolver.add(file);
+    resolver.add(GoogleFileResolver::new());
+
+    let mut compiler = Compiler::with_file_resolver(resolver);
+    compiler.open_file(name).unwrap();
+    compiler
+}
+
+#[test]
+fn json_schema() {
+    let schema = json!({
+        "$schema": "https://json-schema.org/draft/2020-12/schema",
+        "title": "user",
+        "description": "A registered user.",
+        "type": "object",
+        "properties": {
+            "id": { "type": "integer", "format": "int32" },
+            "displayName": { "type": ["string", "null"], "description": "The name shown to others." },
+            "e-mail": { "type": "string" },
+            "avatar": { "type": "string", "format": "byte" },
+            "createdAt": { "type": "string", "format": "date-time" },
+            "score": { "type": "number", "format": "float" },
+            "active": { "type": "boolean" },
+            "tags": { "type": "array", "items": { "type": "string" } },
+            "labels": { "type": "object", "additionalProperties": { "type": "string" } },
+            "metadata": { "type": "object" },
+            "extra": {},
+            "address": { "$ref": "#/$defs/Address" },
+            "previousAddresses": { "type": "array", "items": { "$ref": "#/$defs/Address" } },
+            "settings": {
+                "type": "object",
+                "properties": {
+                    "theme": { "type": "string", "enum": ["light", "dark", null] },
+                },
+            },
+        },
+        "$defs": {
+            "Address": {
+                "type": "object",
+                "properties": {
+                    "street": { "type": "string" },
+                    "kind": { "$ref": "#/$defs/address_kind" },
+                },
+            },
+            "address_kind": {
+                "type": "string",
+                "enum": ["home", "workPlace"],
+            },
+        },
+    });
+
+    let file = from_json_schema("user.proto", &schema).unwrap();
+    assert_eq!(
+        file.to_source(),
+        r#"syntax = "proto3";
+
+import "google/protobuf/struct.proto";
+import "google/protobuf/timestamp.proto";
+
+// A registered user.
+message User {
+  bool active = 1;
+  Address address = 2;
+  bytes avatar = 3;
+  google.protobuf.Timestamp created_at = 4;
+  // The name shown to others.
+  optional string display_name = 5;
+  string e_mail = 6 [json_name = "e-mail"];
+  google.protobuf.Value extra = 7;
+  int32 id = 8;
+  map<string, string> labels = 9;
+  google.protobuf.Struct metadata = 10;
+  repeated Address previous_addresses = 11;
+  float score = 12;
+  message Settings {
+    enum Theme {
+      THEME_UNSPECIFIED = 0;
+      THEME_LIGHT = 1;
+      THEME_DARK = 2;
+    }
+    Theme theme = 1;
+  }
+  Settings settings = 13;
+  repeated string tags = 14;
+}
+
+message Address {
+  AddressKind kind = 1;
+  string street = 2;
+}
+
+enum AddressKind {
+  ADDRESS_KIND_UNSPECIFIED = 0;
+  ADDRESS_KIND_HOME = 1;
+  ADDRESS_KIND_WORK_PLACE = 2;
+}
+"#
+    );
+
+    let compiler = check(file);
+    let message = compiler
+        .descriptor_pool()
+        .get_message_by_name("User")
+        .unwrap();
+    assert_eq!(
+        message.get_field_by_name("e_mail").unwrap().json_name(),
+        "e-mail"
+    );
+    assert_eq!(
+        message.get_field_by_name("created_at").unwrap().json_name(),
+        "createdAt"
+    );
+    assert!(message
+        .get_field_by_name("display_name")
+        .unwrap()
+        .supports_presence());
+}
+
+#[test]
+fn json_schema_title_from_file_name() {
+    let schema = json!({ "type": "object" });
+    let file = from_json_schema("foo/bar_baz.proto", &schema).unwrap();
+    assert_eq!(
+        file.to_source(),
+        "syntax = \"proto3\";\n\nmessage BarBaz {\n}\n"
+    );
+    check(file);
+}
+
+#[test]
+fn openapi() {
+    let document = json!({
+        "openapi": "3.1.0",
+        "info": { "title": "Pets", "version": "1.0.0" },
+        "components": {
+            "schemas": {
+                "Pet": {
+                    "type": "object",
+                    "required": ["name"],
+                    "properties": {
+                        "name": { "type": "string" },
+                        "age": { "type": "integer", "nullable": true },
+                        "owner": { "$ref": "#/components/schemas/Owner" },
+                    },
+                },
+                "Owner": {
+                    "properties": {
+                        "pets": { "type": "array", "items": { "$ref": "#/components/schemas/Pet" } },
+                    },
+                },
+            },
+        },
+    });
+
+    let file = from_openapi("pets.proto", &document).unwrap();
+    assert_eq!(
+        file.to_source(),
+        r#"syntax = "proto3";
+
+message Owner {
+  repeated Pet pets = 1;
+}
+
+message Pet {
+  optional int64 age = 1;
+  string name = 2;
+  Owner owner = 3;
+}
+"#
+    );
+    check(file);
+}
+
+#[test]
+fn errors() {
+    let err = from_openapi("foo.proto", &json!({ "swagger": "2.0" })).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "cannot convert schema at '/openapi': expected an OpenAPI 3 document"
+    );
+
+    let err = from_json_schema(
+        "foo.proto",
+        &json!({
+            "type": "object",
+            "properties": {
+                "a/b": { "oneOf": [{ "type": "string" }, { "type": "integer" }] },
+            },
+        }),
+    )
+    .unwrap_err();
+    assert_eq!(err.pointer(), "/properties/a~1b");
+    assert_eq!(err.message(), "the 'oneOf' keyword is not supported");
+
+    let err = from_json_schema(
+        "foo.proto",
+        &json!({
+            "type": "object",
+            "properties": {
+                "matrix": { "type": "array", "items": { "type": "array", "items": { "type": "number" } } },
+            },
+        }),
+    )
+    .unwrap_err();
+    assert_eq!(err.pointer(), "/properties/matrix/items");
+
+    let err = from_json_schema(
+        "foo.proto",
+        &json!({
+            "type": "object",
+            "properties": {
+                "ref": { "$ref": "other.json#/Foo" },
+            },
+        }),
+    )
+    .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "cannot convert schema at '/properties/ref/$ref': unsupported reference 'other.json#/Foo'"
+    );
+
+    let err = from_json_schema(
+        "foo.proto",
+        &json!({ "$defs": { "123": { "type": "object" } } }),
+    )
+    .unwrap_err();
+    assert_eq!(
+        err.message(),
+        "cannot convert '123' to a valid protobuf name"
+    );
+
+    let err = from_json_schema(
+        "foo.proto",
+        &json!({ "$defs": { "Name": { "type": "string" } } }),
+    )
+    .unwrap_err();
+    assert_eq!(err.message(), "named schemas must be objects or enums");
+}
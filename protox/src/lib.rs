@@ -1,7 +1,8 @@
 //! A rust implementation of the protobuf compiler.
 //!
 //! For convenient compilation of protobuf source files in a single function, see
-//! [`compile()`]. For more options see [`Compiler`].
+//! [`compile()`], or [`compile_str()`] to compile a single in-memory source string. For more options see
+//! [`Compiler`].
 //!
 //! # Examples
 //!
@@ -66,20 +67,52 @@
 //!  5 │ }
 //!    ╰────
 //! ```
+//!
+//! ### Tracing
+//!
+//! If the `tracing` feature is enabled, [`tracing`](https://crates.io/crates/tracing) spans are emitted for each file
+//! as it is resolved, parsed and checked. The `check` span covers name resolution and option interpretation. Using a
+//! subscriber which records span durations gives a per-phase breakdown of where compilation time is spent.
 #![warn(missing_debug_implementations, missing_docs)]
 #![deny(unsafe_code)]
 #![doc(html_root_url = "https://docs.rs/protox/0.7.1/")]
 
+pub mod builder;
+pub mod check;
+pub mod embed;
+pub mod features;
 pub mod file;
+pub mod format;
+#[cfg(feature = "google-api")]
+pub mod http;
+pub mod ir;
+#[cfg(feature = "json-schema")]
+pub mod json_schema;
+pub mod lint;
+pub mod migrate;
+pub mod refactor;
+pub mod stats;
+pub mod symbol;
+#[cfg(feature = "test-util")]
+pub mod testing;
+#[cfg(feature = "validate")]
+pub mod validate;
+pub mod verify;
 
 mod compile;
+mod decompile;
 mod error;
 
 use std::path::Path;
 
+pub use protox_parse::fix;
 pub use {prost, prost_reflect};
 
-pub use self::compile::Compiler;
+pub use self::compile::{
+    CheckMode, CompatibilityLevel, Compiler, CompilerObserver, CompilerSnapshot, DescriptorFilter,
+    DescriptorVerification, ShadowPolicy, UnusedImport,
+};
+pub use self::decompile::decompile;
 pub use self::error::Error;
 
 /// Compiles a set of protobuf files using the given include paths.
@@ -185,3 +218,83 @@ pub fn compile(
         .open_files(files)?
         .file_descriptor_set())
 }
+
+/// Compiles a single protobuf file from an in-memory source string, using the given include paths to resolve its
+/// imports.
+///
+/// The file is parsed, its imports are resolved and the result is checked, as with [`compile()`]. The returned set
+/// contains `name` along with all of its imports, and includes source code info.
+///
+/// # Examples
+///
+/// ```
+/// # fn main() -> Result<(), protox::Error> {
+/// let file_descriptors = protox::compile_str(
+///     "foo.proto",
+///     "syntax = 'proto3'; import 'google/protobuf/empty.proto'; message Foo { google.protobuf.Empty empty = 1; }",
+///     Vec::<&str>::new(),
+/// )?;
+///
+/// assert_eq!(file_descriptors.file.len(), 2);
+/// assert_eq!(file_descriptors.file[0].name(), "google/protobuf/empty.proto");
+/// assert_eq!(file_descriptors.file[1].name(), "foo.proto");
+/// # Ok(())
+/// # }
+/// ```
+pub fn compile_str(
+    name: &str,
+    source: &str,
+    includes: impl IntoIterator<Item = impl AsRef<Path>>,
+) -> Result<prost_types::FileDescriptorSet, Error> {
+    use crate::file::{ChainFileResolver, GoogleFileResolver, IncludeFileResolver};
+
+    let mut resolver = ChainFileResolver::new();
+    for include in includes {
+        resolver.add(IncludeFileResolver::new(include.as_ref().to_owned()));
+    }
+    resolver.add(GoogleFileResolver::new());
+
+    compile_str_with_file_resolver(name, source, resolver)
+}
+
+/// Compiles a single protobuf file from an in-memory source string, using a custom [`FileResolver`](file::FileResolver)
+/// to resolve its imports.
+///
+/// See [`compile_str()`] for details.
+pub fn compile_str_with_file_resolver(
+    name: &str,
+    source: &str,
+    resolver: impl file::FileResolver + 'static,
+) -> Result<prost_types::FileDescriptorSet, Error> {
+    let file = file::File::from_source(name, source)?;
+
+    let mut chain = file::ChainFileResolver::new();
+    chain.add(SourceFileResolver { file });
+    chain.add(resolver);
+
+    Ok(Compiler::with_file_resolver(chain)
+        .include_source_info(true)
+        .include_imports(true)
+        .open_file(name)?
+        .file_descriptor_set())
+}
+
+/// Resolves a single file which has already been parsed.
+#[derive(Debug)]
+struct SourceFileResolver {
+    file: file::File,
+}
+
+impl file::FileResolver for SourceFileResolver {
+    fn open_file(&self, name: &str) -> Result<file::File, Error> {
+        if name == self.file.name() {
+            Ok(self.file.clone())
+        } else {
+            Err(Error::file_not_found(name))
+        }
+    }
+
+    fn description(&self) -> String {
+        format!("source of '{}'", self.file.name())
+    }
+}
@@ -0,0 +1,573 @@
+//! Style checks for protobuf source files.
+//!
+//! The [`Linter`] runs a configurable set of [`Rule`]s over a parsed [`File`], in the spirit of
+//! [`buf lint`](https://buf.build/docs/lint/rules). Each violation is reported as a
+//! [`LintWarning`], which implements [`miette::Diagnostic`] with [warning severity](miette::Severity::Warning).
+//!
+//! # Suppressing warnings
+//!
+//! Rules can be disabled for an entire file by adding a comment anywhere in it of the form:
+//!
+//! ```text
+//! // protox:lint:ignore-file FIELD_LOWER_SNAKE_CASE ENUM_VALUE_PREFIX
+//! ```
+//!
+//! If no rule names are given, all rules are disabled for the file.
+//!
+//! # Examples
+//!
+//! ```
+//! # use protox::{file::File, lint::{Linter, Rule}};
+//! let file = File::from_source("foo.proto", "
+//!     message foo_bar {
+//!         optional int32 Field = 1;
+//!     }
+//! ").unwrap();
+//!
+//! let warnings = Linter::new().lint_file(&file);
+//! assert_eq!(warnings.len(), 2);
+//! assert_eq!(warnings[0].rule(), Rule::MessagePascalCase);
+//! assert_eq!(warnings[1].rule(), Rule::FieldLowerSnakeCase);
+//! ```
+
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    str::FromStr,
+};
+
+use miette::{Diagnostic, LabeledSpan, NamedSource, Severity, SourceCode, SourceSpan};
+use prost_types::{
+    source_code_info::Location, DescriptorProto, EnumDescriptorProto, FileDescriptorProto,
+    ServiceDescriptorProto,
+};
+use protox_parse::tag;
+
+use crate::{error::location_span, file::File};
+
+#[cfg(test)]
+mod tests;
+
+const IGNORE_FILE_DIRECTIVE: &str = "protox:lint:ignore-file";
+
+/// A style rule which may be checked by a [`Linter`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[non_exhaustive]
+pub enum Rule {
+    /// Package names must be `lower_snake_case`, separated by dots.
+    PackageLowerSnakeCase,
+    /// The directory containing a file must match its package, e.g. `foo/bar/baz.proto` must have package `foo.bar`.
+    PackageDirectoryMatch,
+    /// Message names must be `PascalCase`.
+    MessagePascalCase,
+    /// Field names must be `lower_snake_case`.
+    FieldLowerSnakeCase,
+    /// Enum names must be `PascalCase`.
+    EnumPascalCase,
+    /// Enum value names must be `UPPER_SNAKE_CASE`.
+    EnumValueUpperSnakeCase,
+    /// Enum value names must be prefixed with the `UPPER_SNAKE_CASE` name of their enum.
+    EnumValuePrefix,
+    /// Service names must be `PascalCase`.
+    ServicePascalCase,
+    /// RPC names must be `PascalCase`.
+    RpcPascalCase,
+    /// RPC request types must be named `MethodRequest` or `ServiceMethodRequest`.
+    RpcRequestStandardName,
+    /// RPC response types must be named `MethodResponse` or `ServiceMethodResponse`.
+    RpcResponseStandardName,
+}
+
+/// Runs a configurable set of style [`Rule`]s over protobuf files.
+#[derive(Debug, Clone)]
+pub struct Linter {
+    rules: HashSet<Rule>,
+}
+
+/// A violation of a lint [`Rule`].
+pub struct LintWarning {
+    rule: Rule,
+    message: String,
+    help: Option<String>,
+    span: Option<SourceSpan>,
+    source_code: NamedSource<String>,
+}
+
+/// An error returned when parsing an unknown rule name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnknownRuleError {
+    name: String,
+}
+
+impl Rule {
+    /// All rules supported by this version of protox.
+    pub const ALL: &'static [Rule] = &[
+        Rule::PackageLowerSnakeCase,
+        Rule::PackageDirectoryMatch,
+        Rule::MessagePascalCase,
+        Rule::FieldLowerSnakeCase,
+        Rule::EnumPascalCase,
+        Rule::EnumValueUpperSnakeCase,
+        Rule::EnumValuePrefix,
+        Rule::ServicePascalCase,
+        Rule::RpcPascalCase,
+        Rule::RpcRequestStandardName,
+        Rule::RpcResponseStandardName,
+    ];
+
+    /// Returns the identifier of this rule, as used in suppression comments, e.g. `FIELD_LOWER_SNAKE_CASE`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Rule::PackageLowerSnakeCase => "PACKAGE_LOWER_SNAKE_CASE",
+            Rule::PackageDirectoryMatch => "PACKAGE_DIRECTORY_MATCH",
+            Rule::MessagePascalCase => "MESSAGE_PASCAL_CASE",
+            Rule::FieldLowerSnakeCase => "FIELD_LOWER_SNAKE_CASE",
+            Rule::EnumPascalCase => "ENUM_PASCAL_CASE",
+            Rule::EnumValueUpperSnakeCase => "ENUM_VALUE_UPPER_SNAKE_CASE",
+            Rule::EnumValuePrefix => "ENUM_VALUE_PREFIX",
+            Rule::ServicePascalCase => "SERVICE_PASCAL_CASE",
+            Rule::RpcPascalCase => "RPC_PASCAL_CASE",
+            Rule::RpcRequestStandardName => "RPC_REQUEST_STANDARD_NAME",
+            Rule::RpcResponseStandardName => "RPC_RESPONSE_STANDARD_NAME",
+        }
+    }
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Rule {
+    type Err = UnknownRuleError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Rule::ALL
+            .iter()
+            .find(|rule| rule.name() == s)
+            .copied()
+            .ok_or_else(|| UnknownRuleError { name: s.to_owned() })
+    }
+}
+
+impl fmt::Display for UnknownRuleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unknown lint rule '{}'", self.name)
+    }
+}
+
+impl std::error::Error for UnknownRuleError {}
+
+impl Default for Linter {
+    fn default() -> Self {
+        Linter {
+            rules: Rule::ALL.iter().copied().collect(),
+        }
+    }
+}
+
+impl Linter {
+    /// Creates a new [`Linter`] with all rules enabled.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Creates a new [`Linter`] with no rules enabled.
+    pub fn empty() -> Self {
+        Linter {
+            rules: HashSet::new(),
+        }
+    }
+
+    /// Enables the given rule.
+    pub fn enable(&mut self, rule: Rule) -> &mut Self {
+        self.rules.insert(rule);
+        self
+    }
+
+    /// Disables the given rule.
+    pub fn disable(&mut self, rule: Rule) -> &mut Self {
+        self.rules.remove(&rule);
+        self
+    }
+
+    /// Returns `true` if the given rule is enabled.
+    pub fn is_enabled(&self, rule: Rule) -> bool {
+        self.rules.contains(&rule)
+    }
+
+    /// Checks a file against all enabled rules.
+    ///
+    /// Warnings are returned in the order the corresponding definitions appear in the file descriptor.
+    pub fn lint_file(&self, file: &File) -> Vec<LintWarning> {
+        let descriptor = file.file_descriptor_proto();
+
+        let mut ctx = Context {
+            rules: self.rules.clone(),
+            locations: HashMap::new(),
+            path: Vec::new(),
+            source: file.source(),
+            name: file.name(),
+            warnings: Vec::new(),
+        };
+
+        if let Some(source_code_info) = &descriptor.source_code_info {
+            for location in &source_code_info.location {
+                ctx.apply_suppressions(location);
+                ctx.locations
+                    .entry(location.path.as_slice())
+                    .or_insert(location);
+            }
+        }
+
+        ctx.lint_file(descriptor);
+        ctx.warnings
+    }
+}
+
+struct Context<'a> {
+    rules: HashSet<Rule>,
+    locations: HashMap<&'a [i32], &'a Location>,
+    path: Vec<i32>,
+    source: Option<&'a str>,
+    name: &'a str,
+    warnings: Vec<LintWarning>,
+}
+
+impl<'a> Context<'a> {
+    fn apply_suppressions(&mut self, location: &Location) {
+        let comments = location
+            .leading_comments
+            .iter()
+            .chain(location.trailing_comments.iter())
+            .chain(location.leading_detached_comments.iter());
+        for comment in comments {
+            for line in comment.lines() {
+                if let Some(rest) = line.trim().strip_prefix(IGNORE_FILE_DIRECTIVE) {
+                    let mut names = rest.split_whitespace().peekable();
+                    if names.peek().is_none() {
+                        self.rules.clear();
+                    }
+                    for name in names {
+                        if let Ok(rule) = name.parse() {
+                            self.rules.remove(&rule);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn lint_file(&mut self, file: &FileDescriptorProto) {
+        if let Some(package) = &file.package {
+            self.path.push(tag::file::PACKAGE);
+            if !package.split('.').all(is_lower_snake_case) {
+                self.add(
+                    Rule::PackageLowerSnakeCase,
+                    format!("package name '{}' should be lower_snake_case", package),
+                    None,
+                );
+            }
+
+            let directory = file.name().rsplit_once('/').map_or("", |(dir, _)| dir);
+            if directory != package.replace('.', "/") {
+                self.add(
+                    Rule::PackageDirectoryMatch,
+                    format!(
+                        "files with package '{}' must be within a directory '{}' relative to the include path",
+                        package,
+                        package.replace('.', "/")
+                    ),
+                    Some(format!("the file is in the directory '{}'", directory)),
+                );
+            }
+            self.path.pop();
+        }
+
+        for (index, message) in file.message_type.iter().enumerate() {
+            self.path.extend([tag::file::MESSAGE_TYPE, index as i32]);
+            self.lint_message(message);
+            self.path.truncate(self.path.len() - 2);
+        }
+
+        for (index, enum_) in file.enum_type.iter().enumerate() {
+            self.path.extend([tag::file::ENUM_TYPE, index as i32]);
+            self.lint_enum(enum_);
+            self.path.truncate(self.path.len() - 2);
+        }
+
+        for (index, service) in file.service.iter().enumerate() {
+            self.path.extend([tag::file::SERVICE, index as i32]);
+            self.lint_service(service);
+            self.path.truncate(self.path.len() - 2);
+        }
+    }
+
+    fn lint_message(&mut self, message: &DescriptorProto) {
+        if message.options.as_ref().and_then(|o| o.map_entry) == Some(true) {
+            return;
+        }
+
+        if !is_pascal_case(message.name()) {
+            self.add_for_name(
+                Rule::MessagePascalCase,
+                format!("message name '{}' should be PascalCase", message.name()),
+                None,
+            );
+        }
+
+        for (index, field) in message.field.iter().enumerate() {
+            self.path.extend([tag::message::FIELD, index as i32]);
+            if !is_lower_snake_case(field.name()) {
+                self.add_for_name(
+                    Rule::FieldLowerSnakeCase,
+                    format!("field name '{}' should be lower_snake_case", field.name()),
+                    None,
+                );
+            }
+            self.path.truncate(self.path.len() - 2);
+        }
+
+        for (index, nested) in message.nested_type.iter().enumerate() {
+            self.path.extend([tag::message::NESTED_TYPE, index as i32]);
+            self.lint_message(nested);
+            self.path.truncate(self.path.len() - 2);
+        }
+
+        for (index, enum_) in message.enum_type.iter().enumerate() {
+            self.path.extend([tag::message::ENUM_TYPE, index as i32]);
+            self.lint_enum(enum_);
+            self.path.truncate(self.path.len() - 2);
+        }
+    }
+
+    fn lint_enum(&mut self, enum_: &EnumDescriptorProto) {
+        if !is_pascal_case(enum_.name()) {
+            self.add_for_name(
+                Rule::EnumPascalCase,
+                format!("enum name '{}' should be PascalCase", enum_.name()),
+                None,
+            );
+        }
+
+        let prefix = to_upper_snake_case(enum_.name()) + "_";
+        for (index, value) in enum_.value.iter().enumerate() {
+            self.path.extend([tag::enum_::VALUE, index as i32]);
+            if !is_upper_snake_case(value.name()) {
+                self.add_for_name(
+                    Rule::EnumValueUpperSnakeCase,
+                    format!(
+                        "enum value name '{}' should be UPPER_SNAKE_CASE",
+                        value.name()
+                    ),
+                    None,
+                );
+            }
+            if !value.name().starts_with(&prefix) {
+                self.add_for_name(
+                    Rule::EnumValuePrefix,
+                    format!(
+                        "enum value name '{}' should be prefixed with '{}'",
+                        value.name(),
+                        prefix
+                    ),
+                    None,
+                );
+            }
+            self.path.truncate(self.path.len() - 2);
+        }
+    }
+
+    fn lint_service(&mut self, service: &ServiceDescriptorProto) {
+        if !is_pascal_case(service.name()) {
+            self.add_for_name(
+                Rule::ServicePascalCase,
+                format!("service name '{}' should be PascalCase", service.name()),
+                None,
+            );
+        }
+
+        for (index, method) in service.method.iter().enumerate() {
+            self.path.extend([tag::service::METHOD, index as i32]);
+            if !is_pascal_case(method.name()) {
+                self.add_for_name(
+                    Rule::RpcPascalCase,
+                    format!("rpc name '{}' should be PascalCase", method.name()),
+                    None,
+                );
+            }
+
+            for (rule, tag, type_name, suffix) in [
+                (
+                    Rule::RpcRequestStandardName,
+                    tag::method::INPUT_TYPE,
+                    method.input_type(),
+                    "Request",
+                ),
+                (
+                    Rule::RpcResponseStandardName,
+                    tag::method::OUTPUT_TYPE,
+                    method.output_type(),
+                    "Response",
+                ),
+            ] {
+                let short_name = type_name.rsplit('.').next().unwrap_or(type_name);
+                let expected = format!("{}{}", method.name(), suffix);
+                let expected_with_service =
+                    format!("{}{}{}", service.name(), method.name(), suffix);
+                if short_name != expected && short_name != expected_with_service {
+                    self.path.push(tag);
+                    self.add(
+                        rule,
+                        format!(
+                            "rpc {} type '{}' should be named '{}' or '{}'",
+                            suffix.to_ascii_lowercase(),
+                            short_name,
+                            expected,
+                            expected_with_service
+                        ),
+                        None,
+                    );
+                    self.path.pop();
+                }
+            }
+            self.path.truncate(self.path.len() - 2);
+        }
+    }
+
+    fn add_for_name(&mut self, rule: Rule, message: String, help: Option<String>) {
+        self.path.push(tag::message::NAME);
+        self.add(rule, message, help);
+        self.path.pop();
+    }
+
+    fn add(&mut self, rule: Rule, message: String, help: Option<String>) {
+        if !self.rules.contains(&rule) {
+            return;
+        }
+
+        let span = match (self.source, self.locations.get(self.path.as_slice())) {
+            (Some(source), Some(location)) => location_span(source, &location.span),
+            _ => None,
+        };
+
+        self.warnings.push(LintWarning {
+            rule,
+            message,
+            help,
+            span,
+            source_code: NamedSource::new(self.name, self.source.unwrap_or_default().to_owned()),
+        });
+    }
+}
+
+impl LintWarning {
+    /// Returns the rule which was violated.
+    pub fn rule(&self) -> Rule {
+        self.rule
+    }
+
+    /// Returns the name of the file in which the violation occurred.
+    pub fn file(&self) -> &str {
+        self.source_code.name()
+    }
+
+    /// Returns the source code span of the offending definition, if known.
+    pub fn span(&self) -> Option<SourceSpan> {
+        self.span
+    }
+}
+
+impl fmt::Display for LintWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl fmt::Debug for LintWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:", self.file())?;
+        if let Some(span) = &self.span {
+            if let Ok(span_contents) = self.source_code.read_span(span, 0, 0) {
+                write!(
+                    f,
+                    "{}:{}: ",
+                    span_contents.line() + 1,
+                    span_contents.column() + 1
+                )?;
+            }
+        }
+        write!(f, "{} ({})", self.message, self.rule)
+    }
+}
+
+impl std::error::Error for LintWarning {}
+
+impl Diagnostic for LintWarning {
+    fn code<'a>(&'a self) -> Option<Box<dyn fmt::Display + 'a>> {
+        Some(Box::new(self.rule))
+    }
+
+    fn severity(&self) -> Option<Severity> {
+        Some(Severity::Warning)
+    }
+
+    fn help<'a>(&'a self) -> Option<Box<dyn fmt::Display + 'a>> {
+        self.help
+            .as_ref()
+            .map(|help| Box::new(help) as Box<dyn fmt::Display>)
+    }
+
+    fn source_code(&self) -> Option<&dyn SourceCode> {
+        if self.span.is_some() {
+            Some(&self.source_code)
+        } else {
+            None
+        }
+    }
+
+    fn labels(&self) -> Option<Box<dyn Iterator<Item = LabeledSpan> + '_>> {
+        self.span.map(|span| {
+            Box::new(std::iter::once(LabeledSpan::new_with_span(
+                Some("defined here".to_owned()),
+                span,
+            ))) as Box<dyn Iterator<Item = LabeledSpan>>
+        })
+    }
+}
+
+fn is_lower_snake_case(s: &str) -> bool {
+    s.as_bytes().first().is_some_and(u8::is_ascii_lowercase)
+        && s.bytes()
+            .all(|ch| ch.is_ascii_lowercase() || ch.is_ascii_digit() || ch == b'_')
+}
+
+fn is_upper_snake_case(s: &str) -> bool {
+    s.as_bytes().first().is_some_and(u8::is_ascii_uppercase)
+        && s.bytes()
+            .all(|ch| ch.is_ascii_uppercase() || ch.is_ascii_digit() || ch == b'_')
+}
+
+fn is_pascal_case(s: &str) -> bool {
+    s.as_bytes().first().is_some_and(u8::is_ascii_uppercase)
+        && s.bytes().all(|ch| ch.is_ascii_alphanumeric())
+}
+
+fn to_upper_snake_case(s: &str) -> String {
+    let mut result = String::with_capacity(s.len() + 4);
+    let mut prev_lower = false;
+    for ch in s.chars() {
+        if ch == '_' {
+            result.push('_');
+            prev_lower = false;
+            continue;
+        }
+        if ch.is_ascii_uppercase() && prev_lower {
+            result.push('_');
+        }
+        prev_lower = ch.is_ascii_lowercase() || ch.is_ascii_digit();
+        result.push(ch.to_ascii_uppercase());
+    }
+    result
+}
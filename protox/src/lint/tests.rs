@@ -0,0 +1,192 @@
+use super::*;
+
+fn lint(name: &str, source: &str) -> Vec<(Rule, String)> {
+    let file = File::from_source(name, source).unwrap();
+    Linter::new()
+        .lint_file(&file)
+        .into_iter()
+        .map(|warning| (warning.rule(), warning.to_string()))
+        .collect()
+}
+
+#[test]
+fn valid_file() {
+    assert_eq!(
+        lint(
+            "foo/bar/baz.proto",
+            "
+            syntax = 'proto3';
+            package foo.bar;
+
+            message GetThingRequest {
+                int32 thing_id = 1;
+                message Nested {}
+            }
+            message GetThingResponse {}
+
+            enum ThingKind {
+                THING_KIND_UNSPECIFIED = 0;
+                THING_KIND_BIG = 1;
+            }
+
+            service ThingService {
+                rpc GetThing(GetThingRequest) returns (GetThingResponse);
+            }
+            ",
+        ),
+        vec![]
+    );
+}
+
+#[test]
+fn package_rules() {
+    assert_eq!(
+        lint("foo.proto", "package Foo.bar;"),
+        vec![
+            (
+                Rule::PackageLowerSnakeCase,
+                "package name 'Foo.bar' should be lower_snake_case".to_owned()
+            ),
+            (
+                Rule::PackageDirectoryMatch,
+                "files with package 'Foo.bar' must be within a directory 'Foo/bar' relative to the include path".to_owned()
+            ),
+        ]
+    );
+}
+
+#[test]
+fn naming_rules() {
+    assert_eq!(
+        lint(
+            "foo.proto",
+            "
+            syntax = 'proto3';
+            message foo {
+                map<int32, string> MapField = 1;
+            }
+            enum bar_baz {
+                ZERO = 0;
+                BAR_BAZ_one = 1;
+            }
+            service s {
+                rpc get(.foo) returns (GetResponse);
+            }
+            ",
+        ),
+        vec![
+            (
+                Rule::MessagePascalCase,
+                "message name 'foo' should be PascalCase".to_owned()
+            ),
+            (
+                Rule::FieldLowerSnakeCase,
+                "field name 'MapField' should be lower_snake_case".to_owned()
+            ),
+            (
+                Rule::EnumPascalCase,
+                "enum name 'bar_baz' should be PascalCase".to_owned()
+            ),
+            (
+                Rule::EnumValuePrefix,
+                "enum value name 'ZERO' should be prefixed with 'BAR_BAZ_'".to_owned()
+            ),
+            (
+                Rule::EnumValueUpperSnakeCase,
+                "enum value name 'BAR_BAZ_one' should be UPPER_SNAKE_CASE".to_owned()
+            ),
+            (
+                Rule::ServicePascalCase,
+                "service name 's' should be PascalCase".to_owned()
+            ),
+            (
+                Rule::RpcPascalCase,
+                "rpc name 'get' should be PascalCase".to_owned()
+            ),
+            (
+                Rule::RpcRequestStandardName,
+                "rpc request type 'foo' should be named 'getRequest' or 'sgetRequest'".to_owned()
+            ),
+            (
+                Rule::RpcResponseStandardName,
+                "rpc response type 'GetResponse' should be named 'getResponse' or 'sgetResponse'"
+                    .to_owned()
+            ),
+        ]
+    );
+}
+
+#[test]
+fn ignore_file_comment() {
+    assert_eq!(
+        lint(
+            "foo.proto",
+            "
+            // protox:lint:ignore-file MESSAGE_PASCAL_CASE
+            syntax = 'proto3';
+
+            message foo {
+                int32 Bar = 1;
+            }
+            ",
+        ),
+        vec![(
+            Rule::FieldLowerSnakeCase,
+            "field name 'Bar' should be lower_snake_case".to_owned()
+        )]
+    );
+    assert_eq!(
+        lint(
+            "foo.proto",
+            "
+            syntax = 'proto3';
+
+            /* protox:lint:ignore-file */
+            message foo {
+                int32 Bar = 1;
+            }
+            ",
+        ),
+        vec![]
+    );
+}
+
+#[test]
+fn disable_rule() {
+    let file = File::from_source("foo.proto", "message foo { optional int32 Bar = 1; }").unwrap();
+
+    let warnings = Linter::new()
+        .disable(Rule::MessagePascalCase)
+        .lint_file(&file);
+    assert_eq!(warnings.len(), 1);
+    assert_eq!(warnings[0].rule(), Rule::FieldLowerSnakeCase);
+    assert_eq!(warnings[0].span(), Some((29..32).into()));
+    assert_eq!(warnings[0].severity(), Some(Severity::Warning));
+    assert_eq!(
+        format!("{:?}", warnings[0]),
+        "foo.proto:1:30: field name 'Bar' should be lower_snake_case (FIELD_LOWER_SNAKE_CASE)"
+    );
+
+    let warnings = Linter::empty()
+        .enable(Rule::MessagePascalCase)
+        .lint_file(&file);
+    assert_eq!(warnings.len(), 1);
+    assert_eq!(warnings[0].rule(), Rule::MessagePascalCase);
+}
+
+#[test]
+fn rule_names() {
+    for &rule in Rule::ALL {
+        assert_eq!(rule.name().parse(), Ok(rule));
+    }
+    assert!("NOT_A_RULE".parse::<Rule>().is_err());
+}
+
+#[test]
+fn upper_snake_case() {
+    assert_eq!(to_upper_snake_case("Foo"), "FOO");
+    assert_eq!(to_upper_snake_case("FooBar"), "FOO_BAR");
+    assert_eq!(to_upper_snake_case("foo_bar"), "FOO_BAR");
+    assert_eq!(to_upper_snake_case("HTTPMethod"), "HTTPMETHOD");
+    assert_eq!(to_upper_snake_case("Foo2Bar"), "FOO2_BAR");
+}
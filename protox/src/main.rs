@@ -1,14 +1,21 @@
-use std::{fs, path::PathBuf};
+use std::{fs, io, path::PathBuf};
 
-use clap::Parser;
-use miette::Result;
-use protox::Compiler;
+use clap::{Parser, ValueEnum};
+use miette::{IntoDiagnostic, Result};
+use protox::{format::OutputFormat, Compiler};
 
 #[derive(Debug, Parser)]
 pub struct Args {
     /// The source file(s) to compile
-    #[clap(value_name = "PROTO_FILES", required = true, value_parser)]
+    #[clap(
+        value_name = "PROTO_FILES",
+        required_unless_present = "stdin_name",
+        value_parser
+    )]
     files: Vec<PathBuf>,
+    /// If set, a source file is read from standard input and compiled with the given name.
+    #[clap(long, visible_alias = "stdin_name", value_name = "NAME")]
+    stdin_name: Option<String>,
     /// The directory in which to search for imports.
     #[clap(
         short = 'I',
@@ -19,6 +26,9 @@ pub struct Args {
         value_parser
     )]
     includes: Vec<PathBuf>,
+    /// If set, the include paths are inferred from the imports of the source files.
+    #[clap(long, visible_alias = "discover_includes", conflicts_with = "includes")]
+    discover_includes: bool,
     /// The output path to write a file descriptor set to.
     #[clap(
         short = 'o',
@@ -28,27 +38,96 @@ pub struct Args {
         value_parser
     )]
     output: Option<PathBuf>,
+    /// The format to write the file descriptor set in.
+    #[clap(
+        long,
+        visible_alias = "output_format",
+        value_name = "FORMAT",
+        value_enum,
+        default_value_t = Format::Binary,
+        requires = "output"
+    )]
+    output_format: Format,
+    /// The output path to write a Makefile-style dependency file to, listing every file read while compiling the output.
+    #[clap(
+        long,
+        visible_alias = "dependency_out",
+        value_name = "PATH",
+        requires = "output",
+        value_parser
+    )]
+    dependency_out: Option<PathBuf>,
+    /// The output path to write size and complexity metrics for the compiled files to, as JSON.
+    #[clap(long, visible_alias = "stats_out", value_name = "PATH", value_parser)]
+    stats_out: Option<PathBuf>,
     /// If set, includes source code information in the output file descriptor set.
     #[clap(long, visible_alias = "include_source_info")]
     include_source_info: bool,
     /// If set, all dependencies of the input files are output, so that the file descriptor set is self-contained.
     #[clap(long, visible_alias = "include_imports")]
     include_imports: bool,
+    /// If set, options with source retention are included in the output file descriptor set.
+    #[clap(long, visible_alias = "retain_options")]
+    retain_options: bool,
+    /// If set, imports may not use absolute paths or '..' components, or read files through symbolic links leading
+    /// outside the include paths.
+    #[clap(long)]
+    hermetic: bool,
+    /// If set, invalid UTF-8 sequences in source files are replaced with U+FFFD instead of causing an error.
+    #[clap(long, visible_alias = "lossy_utf8")]
+    lossy_utf8: bool,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum Format {
+    /// The binary protobuf encoding, as written by `protoc --descriptor_set_out`.
+    Binary,
+    /// The protobuf text format.
+    Text,
+    /// The canonical protobuf JSON encoding.
+    Json,
+}
+
+impl From<Format> for OutputFormat {
+    fn from(format: Format) -> Self {
+        match format {
+            Format::Binary => OutputFormat::Binary,
+            Format::Text => OutputFormat::Text,
+            Format::Json => OutputFormat::Json,
+        }
+    }
 }
 
 pub fn main() -> Result<()> {
     miette::set_panic_hook();
 
     let args = Args::parse();
-    let mut compiler = Compiler::new(args.includes)?;
+    let includes = if args.discover_includes {
+        protox::file::discover_includes(&args.files)?
+    } else {
+        args.includes
+    };
+    let mut compiler = Compiler::new(includes)?;
     compiler.include_imports(args.include_imports);
     compiler.include_source_info(args.include_source_info);
+    compiler.retain_options(args.retain_options);
+    compiler.hermetic(args.hermetic);
+    compiler.lossy_utf8(args.lossy_utf8);
     for file in args.files {
         compiler.open_file(file)?;
     }
+    if let Some(name) = &args.stdin_name {
+        compiler.add_reader(name, io::stdin().lock())?;
+    }
     if let Some(output) = args.output {
-        fs::write(output, compiler.encode_file_descriptor_set())
-            .map_err(|err| miette::miette!(err))?;
+        compiler.write_file_descriptor_set_as(&output, args.output_format.into())?;
+        if let Some(dependency_out) = args.dependency_out {
+            compiler.write_depfile(dependency_out, [output])?;
+        }
+    }
+    if let Some(stats_out) = args.stats_out {
+        let stats = serde_json::to_string_pretty(&compiler.stats()).into_diagnostic()?;
+        fs::write(&stats_out, stats).into_diagnostic()?;
     }
     Ok(())
 }
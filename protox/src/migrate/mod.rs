@@ -0,0 +1,451 @@
+//! Migration of proto2 files to proto3.
+//!
+//! [`to_proto3`] rewrites a compiled proto2 file as proto3 source, formatted in the same way as
+//! [`decompile`](crate::decompile). The following constructs are migrated automatically:
+//!
+//! * `required` labels are removed.
+//! * Groups are converted to a nested message and a field of that type, with the same name and number.
+//! * Default values are removed, and recorded in a comment on the field instead.
+//! * Singular scalar and enum fields are marked `optional`, so they keep tracking field presence.
+//! * Repeated scalar fields are marked `[packed = false]`, unless set explicitly, so their encoding does not change.
+//!
+//! Each automatic change which may affect the behaviour of existing code is recorded as a [`MigrationNote`]. So is
+//! each construct which has no equivalent in proto3, such as extension ranges, which are left unchanged for the
+//! user to resolve by hand.
+//!
+//! The parser does not yet support editions, so files can only be migrated to proto3.
+//!
+//! # Examples
+//!
+//! ```
+//! # use protox::{file::{File, FileResolver}, migrate::{self, MigrationNoteKind}, Compiler};
+//! # struct Resolver;
+//! # impl FileResolver for Resolver {
+//! #     fn open_file(&self, name: &str) -> Result<File, protox::Error> {
+//! #         File::from_source(name, "package pkg; message Foo { required int32 id = 1; }")
+//! #     }
+//! # }
+//! let mut compiler = Compiler::with_file_resolver(Resolver);
+//! compiler.open_file("foo.proto")?;
+//!
+//! let file = compiler.descriptor_pool().get_file_by_name("foo.proto").unwrap();
+//! let migration = migrate::to_proto3(&file);
+//! assert_eq!(migration.source(), r#"syntax = "proto3";
+//!
+//! package pkg;
+//!
+//! message Foo {
+//!   optional int32 id = 1;
+//! }
+//! "#);
+//! assert_eq!(migration.notes()[0].kind(), MigrationNoteKind::RequiredRemoved);
+//! assert!(migration.is_complete());
+//! # Ok::<(), protox::Error>(())
+//! ```
+
+use std::fmt;
+
+use prost_reflect::{DynamicMessage, FileDescriptor, Syntax};
+use prost_types::{
+    field_descriptor_proto::{Label, Type},
+    source_code_info::Location,
+    DescriptorProto, EnumDescriptorProto, FieldDescriptorProto, FieldOptions, FileDescriptorProto,
+    ServiceDescriptorProto,
+};
+use protox_parse::tag;
+
+use crate::{
+    decompile::{decompile_proto, format_default_value},
+    symbol::{find_options, join_name},
+};
+
+#[cfg(test)]
+mod tests;
+
+/// The result of migrating a file, computed by [`to_proto3`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Migration {
+    source: String,
+    notes: Vec<MigrationNote>,
+}
+
+/// A change made while migrating a file which may affect existing code, or a construct which could not be migrated.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MigrationNote {
+    kind: MigrationNoteKind,
+    name: String,
+    message: String,
+}
+
+/// The kind of a [`MigrationNote`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum MigrationNoteKind {
+    /// A `required` field was made optional, so the application must check it is set.
+    RequiredRemoved,
+    /// A group was converted to a message field, which has a different wire encoding.
+    GroupConverted,
+    /// The default value of a field was removed, so an unset field now reads as the zero value of its type.
+    DefaultRemoved,
+    /// An enum does not have zero as its first value, which is required in proto3.
+    EnumZeroValue,
+    /// A message declares extension ranges, which are not allowed in proto3.
+    ExtensionRange,
+    /// An extension does not extend one of the options messages, which is not allowed in proto3.
+    Extension,
+    /// A message uses the message set wire format, which is not supported in proto3.
+    MessageSet,
+    /// A field uses a closed enum declared in another proto2 file, which proto3 messages cannot use.
+    ClosedEnum,
+    /// A definition sets custom options, which are not preserved in the migrated source.
+    CustomOptions,
+}
+
+/// Rewrites a compiled proto2 file as proto3 source.
+///
+/// The file should be taken from a pool built by a [`Compiler`](crate::Compiler), so that type names are resolved and
+/// options are interpreted. If it includes source code info, comments are preserved. A file which already uses
+/// proto3 is formatted without any changes.
+///
+/// See the [module documentation](self) for details.
+pub fn to_proto3(file: &FileDescriptor) -> Migration {
+    let mut proto = file.file_descriptor_proto().clone();
+    if file.syntax() == Syntax::Proto3 {
+        return Migration {
+            source: decompile_proto(&proto),
+            notes: Vec::new(),
+        };
+    }
+
+    let mut migrator = Migrator {
+        file,
+        notes: Vec::new(),
+        comments: Vec::new(),
+    };
+    migrator.check_custom_options(file.name(), Some(file.options()));
+
+    let package = proto.package().to_owned();
+    for (index, message) in proto.message_type.iter_mut().enumerate() {
+        let name = join_name(&package, message.name());
+        migrator.migrate_message(message, &name, vec![tag::file::MESSAGE_TYPE, index as i32]);
+    }
+    for enum_ in &proto.enum_type {
+        migrator.check_enum(enum_, &package);
+    }
+    for (index, extension) in proto.extension.iter_mut().enumerate() {
+        migrator.migrate_extension(
+            extension,
+            &package,
+            vec![tag::file::EXTENSION, index as i32],
+        );
+    }
+    for service in &proto.service {
+        migrator.check_service(service, &package);
+    }
+
+    proto.syntax = Some("proto3".to_owned());
+    add_comments(&mut proto, migrator.comments);
+    Migration {
+        source: decompile_proto(&proto),
+        notes: migrator.notes,
+    }
+}
+
+struct Migrator<'a> {
+    file: &'a FileDescriptor,
+    notes: Vec<MigrationNote>,
+    /// Comments to add to the element at each path.
+    comments: Vec<(Vec<i32>, String)>,
+}
+
+impl<'a> Migrator<'a> {
+    fn migrate_message(&mut self, message: &mut DescriptorProto, name: &str, path: Vec<i32>) {
+        self.check_custom_options(name, find_options(self.file.parent_pool(), name));
+        if message
+            .options
+            .as_ref()
+            .is_some_and(|options| options.message_set_wire_format())
+        {
+            self.add_note(
+                MigrationNoteKind::MessageSet,
+                name,
+                format!("message '{}' uses the message set wire format, which is not supported in proto3", name),
+            );
+        }
+        if !message.extension_range.is_empty() {
+            self.add_note(
+                MigrationNoteKind::ExtensionRange,
+                name,
+                format!(
+                    "message '{}' declares extension ranges, which are not allowed in proto3",
+                    name
+                ),
+            );
+        }
+
+        for (index, field) in message.field.iter_mut().enumerate() {
+            let field_name = join_name(name, field.name());
+            self.migrate_field(
+                field,
+                &field_name,
+                child_path(&path, tag::message::FIELD, index),
+            );
+        }
+        for oneof in &message.oneof_decl {
+            let oneof_name = join_name(name, oneof.name());
+            self.check_custom_options(
+                &oneof_name,
+                find_options(self.file.parent_pool(), &oneof_name),
+            );
+        }
+        for (index, nested) in message.nested_type.iter_mut().enumerate() {
+            // Map entries are written using the map syntax, which is the same in proto3.
+            if nested
+                .options
+                .as_ref()
+                .is_some_and(|options| options.map_entry())
+            {
+                continue;
+            }
+            let nested_name = join_name(name, nested.name());
+            self.migrate_message(
+                nested,
+                &nested_name,
+                child_path(&path, tag::message::NESTED_TYPE, index),
+            );
+        }
+        for enum_ in &message.enum_type {
+            self.check_enum(enum_, name);
+        }
+        for (index, extension) in message.extension.iter_mut().enumerate() {
+            self.migrate_extension(
+                extension,
+                name,
+                child_path(&path, tag::message::EXTENSION, index),
+            );
+        }
+    }
+
+    fn migrate_field(&mut self, field: &mut FieldDescriptorProto, name: &str, path: Vec<i32>) {
+        self.check_custom_options(name, find_options(self.file.parent_pool(), name));
+
+        if field.label() == Label::Required {
+            field.set_label(Label::Optional);
+            self.add_note(
+                MigrationNoteKind::RequiredRemoved,
+                name,
+                format!("required field '{}' is now optional, so the application must check that it is set", name),
+            );
+        }
+        if field.r#type() == Type::Group {
+            field.set_type(Type::Message);
+            self.add_note(
+                MigrationNoteKind::GroupConverted,
+                name,
+                format!("group '{}' was converted to a message field, which has a different wire encoding", name),
+            );
+        }
+        if let Some(value) = format_default_value(field) {
+            field.default_value = None;
+            self.comments
+                .push((path, format!(" Default value in proto2: {}\n", value)));
+            self.add_note(
+                MigrationNoteKind::DefaultRemoved,
+                name,
+                format!("the default value of field '{}' ({}) was removed, so an unset field now reads as the zero value of its type", name, value),
+            );
+        }
+
+        let is_scalar = field.r#type() != Type::Message;
+        match field.label() {
+            Label::Optional
+                if is_scalar && field.oneof_index.is_none() && field.extendee.is_none() =>
+            {
+                field.proto3_optional = Some(true);
+            }
+            Label::Repeated
+                if is_scalar && !matches!(field.r#type(), Type::String | Type::Bytes) =>
+            {
+                let options = field.options.get_or_insert_with(FieldOptions::default);
+                if options.packed.is_none() {
+                    options.packed = Some(false);
+                }
+            }
+            _ => (),
+        }
+
+        if field.r#type() == Type::Enum {
+            let enum_name = field
+                .type_name()
+                .strip_prefix('.')
+                .unwrap_or(field.type_name());
+            if let Some(enum_) = self.file.parent_pool().get_enum_by_name(enum_name) {
+                let enum_file = enum_.parent_file();
+                if enum_file != *self.file && enum_file.syntax() == Syntax::Proto2 {
+                    self.add_note(
+                        MigrationNoteKind::ClosedEnum,
+                        name,
+                        format!(
+                            "field '{}' uses the enum '{}' declared in proto2 file '{}', but proto3 messages cannot use closed enums",
+                            name,
+                            enum_name,
+                            enum_file.name()
+                        ),
+                    );
+                }
+            }
+        }
+    }
+
+    fn migrate_extension(
+        &mut self,
+        extension: &mut FieldDescriptorProto,
+        scope: &str,
+        path: Vec<i32>,
+    ) {
+        let name = join_name(scope, extension.name());
+        let extendee = extension
+            .extendee()
+            .strip_prefix('.')
+            .unwrap_or(extension.extendee());
+        let is_options = extendee
+            .strip_prefix("google.protobuf.")
+            .is_some_and(|name| name.ends_with("Options"));
+        if !is_options {
+            let message = format!(
+                "extension '{}' extends '{}', but proto3 only allows extensions of options messages",
+                name, extendee
+            );
+            self.add_note(MigrationNoteKind::Extension, &name, message);
+        }
+        self.migrate_field(extension, &name, path);
+    }
+
+    fn check_enum(&mut self, enum_: &EnumDescriptorProto, scope: &str) {
+        let name = join_name(scope, enum_.name());
+        self.check_custom_options(&name, find_options(self.file.parent_pool(), &name));
+        if enum_.value.first().is_some_and(|value| value.number() != 0) {
+            self.add_note(
+                MigrationNoteKind::EnumZeroValue,
+                &name,
+                format!("the first value of enum '{}' must be zero in proto3", name),
+            );
+        }
+        // Like in C++, enum values are siblings of their enum type.
+        for value in &enum_.value {
+            let value_name = join_name(scope, value.name());
+            self.check_custom_options(
+                &value_name,
+                find_options(self.file.parent_pool(), &value_name),
+            );
+        }
+    }
+
+    fn check_service(&mut self, service: &ServiceDescriptorProto, scope: &str) {
+        let name = join_name(scope, service.name());
+        self.check_custom_options(&name, find_options(self.file.parent_pool(), &name));
+        for method in &service.method {
+            let method_name = join_name(&name, method.name());
+            self.check_custom_options(
+                &method_name,
+                find_options(self.file.parent_pool(), &method_name),
+            );
+        }
+    }
+
+    fn check_custom_options(&mut self, name: &str, options: Option<DynamicMessage>) {
+        if options.is_some_and(|options| options.extensions().next().is_some()) {
+            self.add_note(
+                MigrationNoteKind::CustomOptions,
+                name,
+                format!("the custom options of '{}' are not preserved in the migrated source, and must be copied by hand", name),
+            );
+        }
+    }
+
+    fn add_note(&mut self, kind: MigrationNoteKind, name: &str, message: String) {
+        self.notes.push(MigrationNote {
+            kind,
+            name: name.to_owned(),
+            message,
+        });
+    }
+}
+
+impl Migration {
+    /// Gets the migrated source of the file.
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// Gets the changes which may affect existing code, and the constructs which could not be migrated, in the order
+    /// of the definitions they apply to.
+    pub fn notes(&self) -> &[MigrationNote] {
+        &self.notes
+    }
+
+    /// Returns `true` if every construct in the file was migrated, so the source can be used without changes.
+    pub fn is_complete(&self) -> bool {
+        self.notes.iter().all(|note| note.kind().is_migrated())
+    }
+}
+
+impl MigrationNote {
+    /// Gets the kind of this note.
+    pub fn kind(&self) -> MigrationNoteKind {
+        self.kind
+    }
+
+    /// Gets the fully-qualified name of the definition this note applies to, or the file name for file options.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl fmt::Display for MigrationNote {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl MigrationNoteKind {
+    /// Returns `true` if the construct was migrated automatically, or `false` if it must be resolved by hand.
+    pub fn is_migrated(&self) -> bool {
+        matches!(
+            self,
+            MigrationNoteKind::RequiredRemoved
+                | MigrationNoteKind::GroupConverted
+                | MigrationNoteKind::DefaultRemoved
+        )
+    }
+}
+
+fn child_path(path: &[i32], tag: i32, index: usize) -> Vec<i32> {
+    let mut path = path.to_vec();
+    path.extend([tag, index as i32]);
+    path
+}
+
+/// Appends comments to the leading comments of the elements at the given paths.
+fn add_comments(file: &mut FileDescriptorProto, comments: Vec<(Vec<i32>, String)>) {
+    let locations = &mut file
+        .source_code_info
+        .get_or_insert_with(Default::default)
+        .location;
+    for (path, comment) in comments {
+        match locations.iter_mut().find(|location| location.path == path) {
+            Some(location) => {
+                let leading = location.leading_comments.get_or_insert_with(String::new);
+                if !leading.is_empty() && !leading.ends_with('\n') {
+                    leading.push('\n');
+                }
+                leading.push_str(&comment);
+            }
+            None => locations.push(Location {
+                path,
+                leading_comments: Some(comment),
+                ..Default::default()
+            }),
+        }
+    }
+}
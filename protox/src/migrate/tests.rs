@@ -0,0 +1,166 @@
+use super::{to_proto3, Migration, MigrationNoteKind};
+use crate::{
+    file::{ChainFileResolver, File, FileResolver, GoogleFileResolver},
+    Compiler, Error,
+};
+
+struct TestFileResolver {
+    files: Vec<(&'static str, &'static str)>,
+}
+
+impl FileResolver for TestFileResolver {
+    fn open_file(&self, name: &str) -> Result<File, Error> {
+        match self.files.iter().find(|(file, _)| *file == name) {
+            Some((_, source)) => File::from_source(name, source),
+            None => Err(Error::file_not_found(name)),
+        }
+    }
+}
+
+/// Compiles the files and migrates the last one.
+fn check(files: &[(&'static str, &'static str)]) -> Migration {
+    let mut resolver = ChainFileResolver::new();
+    resolver.add(TestFileResolver {
+        files: files.to_vec(),
+    });
+    resolver.add(GoogleFileResolver::new());
+
+    let mut compiler = Compiler::with_file_resolver(resolver);
+    compiler.include_source_info(true);
+    let (name, _) = files.last().unwrap();
+    compiler.open_file(name).unwrap();
+
+    let file = compiler.descriptor_pool().get_file_by_name(name).unwrap();
+    to_proto3(&file)
+}
+
+fn notes(migration: &Migration) -> Vec<(MigrationNoteKind, &str)> {
+    migration
+        .notes()
+        .iter()
+        .map(|note| (note.kind(), note.name()))
+        .collect()
+}
+
+#[test]
+fn migrate_to_proto3() {
+    let migration = check(&[(
+        "root.proto",
+        "syntax = 'proto2';
+        package pkg;
+
+        // A message.
+        message Foo {
+            required int32 id = 1;
+            // The count.
+            optional uint32 count = 2 [default = 5];
+            optional string name = 3 [default = 'foo'];
+            repeated int32 values = 4;
+            repeated int32 packed_values = 5 [packed = true];
+            optional Foo child = 6;
+            repeated group Item = 7 {
+                optional string key = 1;
+            }
+            oneof kind {
+                int32 number = 8;
+            }
+            map<string, int32> counts = 9;
+        }",
+    )]);
+
+    assert_eq!(
+        migration.source(),
+        r#"syntax = "proto3";
+
+package pkg;
+
+// A message.
+message Foo {
+  message Item {
+    optional string key = 1;
+  }
+  optional int32 id = 1;
+  // The count.
+  // Default value in proto2: 5
+  optional uint32 count = 2;
+  // Default value in proto2: "foo"
+  optional string name = 3;
+  repeated int32 values = 4 [packed = false];
+  repeated int32 packed_values = 5 [packed = true];
+  .pkg.Foo child = 6;
+  repeated .pkg.Foo.Item item = 7;
+  oneof kind {
+    int32 number = 8;
+  }
+  map<string, int32> counts = 9;
+}
+"#
+    );
+    assert_eq!(
+        notes(&migration),
+        [
+            (MigrationNoteKind::RequiredRemoved, "pkg.Foo.id"),
+            (MigrationNoteKind::DefaultRemoved, "pkg.Foo.count"),
+            (MigrationNoteKind::DefaultRemoved, "pkg.Foo.name"),
+            (MigrationNoteKind::GroupConverted, "pkg.Foo.item"),
+        ]
+    );
+    assert!(migration.is_complete());
+    crate::compile_str("root.proto", migration.source(), [] as [&str; 0]).unwrap();
+}
+
+#[test]
+fn migrate_reports_unsupported_constructs() {
+    let migration = check(&[
+        (
+            "dep.proto",
+            "syntax = 'proto2';
+            package dep;
+            enum Closed { CLOSED_UNSPECIFIED = 0; }",
+        ),
+        (
+            "root.proto",
+            "syntax = 'proto2';
+            package pkg;
+            import 'dep.proto';
+            import 'google/protobuf/descriptor.proto';
+            message Foo {
+                option (label) = 'foo';
+                optional dep.Closed closed = 1;
+                extensions 100 to 200;
+            }
+            enum Kind { KIND_ONE = 1; }
+            extend Foo { optional int32 bar = 100; }
+            extend google.protobuf.MessageOptions { optional string label = 5000; }",
+        ),
+    ]);
+
+    assert_eq!(
+        notes(&migration),
+        [
+            (MigrationNoteKind::CustomOptions, "pkg.Foo"),
+            (MigrationNoteKind::ExtensionRange, "pkg.Foo"),
+            (MigrationNoteKind::ClosedEnum, "pkg.Foo.closed"),
+            (MigrationNoteKind::EnumZeroValue, "pkg.Kind"),
+            (MigrationNoteKind::Extension, "pkg.bar"),
+        ]
+    );
+    assert!(!migration.is_complete());
+    assert_eq!(
+        migration.notes()[2].to_string(),
+        "field 'pkg.Foo.closed' uses the enum 'dep.Closed' declared in proto2 file 'dep.proto', but proto3 messages cannot use closed enums"
+    );
+}
+
+#[test]
+fn migrate_proto3_file() {
+    let migration = check(&[(
+        "root.proto",
+        "syntax = 'proto3'; message Foo { optional int32 a = 1; }",
+    )]);
+    assert_eq!(
+        migration.source(),
+        "syntax = \"proto3\";\n\nmessage Foo {\n  optional int32 a = 1;\n}\n"
+    );
+    assert!(migration.notes().is_empty());
+}
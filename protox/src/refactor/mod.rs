@@ -0,0 +1,463 @@
+//! Refactorings of the protobuf source files added to a [`Compiler`].
+//!
+//! [`rename`] computes the changes needed to rename a message, enum, field, extension, oneof, service, method or
+//! package across every file which has been compiled. Unlike a textual search and replace, it uses the same scoping
+//! rules as the compiler, so only the names which actually refer to the renamed definition are changed, and any
+//! reference which would resolve to a different definition after the rename is written in its fully-qualified form
+//! instead.
+//!
+//! # Examples
+//!
+//! ```
+//! # use protox::{file::{File, FileResolver}, Compiler};
+//! # struct Resolver;
+//! # impl FileResolver for Resolver {
+//! #     fn open_file(&self, name: &str) -> Result<File, protox::Error> {
+//! #         File::from_source(name, "package pkg; message Foo {} message Bar { optional Foo foo = 1; }")
+//! #     }
+//! # }
+//! let mut compiler = Compiler::with_file_resolver(Resolver);
+//! compiler.open_file("root.proto")?;
+//!
+//! let rename = protox::refactor::rename(&compiler, "pkg.Foo", "Baz")?;
+//! let (file, fix) = rename.files().next().unwrap();
+//! assert_eq!(file, "root.proto");
+//! assert_eq!(
+//!     fix.apply("package pkg; message Foo {} message Bar { optional Foo foo = 1; }"),
+//!     "package pkg; message Baz {} message Bar { optional Baz foo = 1; }",
+//! );
+//! # Ok::<(), protox::Error>(())
+//! ```
+
+use std::{
+    collections::{BTreeMap, HashSet},
+    ops::Range,
+};
+
+use prost_reflect::{DescriptorPool, FileDescriptor, Kind, MessageDescriptor};
+use protox_parse::{tag, tokens::TokenKind};
+
+use crate::{
+    compile::{find_location, source_references, tokens, SourceReference},
+    error::ErrorKind,
+    fix::{Edit, Fix},
+    symbol::{self, join_name, rename_prefix, ReferenceKind, SymbolKind, SymbolTable},
+    Compiler, Error,
+};
+
+#[cfg(test)]
+mod tests;
+
+/// The changes to each source file needed to rename a symbol, computed by [`rename`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Rename {
+    fixes: BTreeMap<String, Fix>,
+}
+
+impl Rename {
+    /// Gets the files which must change, ordered by name, with the edits to apply to each of them.
+    ///
+    /// The edits in each [`Fix`] are ordered by position and do not overlap, so [`Fix::apply`] can be used to update
+    /// the source of the file.
+    pub fn files(&self) -> impl Iterator<Item = (&str, &Fix)> {
+        self.fixes.iter().map(|(name, fix)| (name.as_str(), fix))
+    }
+
+    /// Gets the edits to apply to the given file, which are empty if it does not need to change.
+    pub fn edits(&self, file: &str) -> &[Edit] {
+        self.fixes.get(file).map(Fix::edits).unwrap_or_default()
+    }
+
+    /// Returns `true` if no file needs to change, which is the case if the new name is the same as the old one.
+    pub fn is_empty(&self) -> bool {
+        self.fixes.is_empty()
+    }
+}
+
+/// Computes the edits needed to rename the symbol with the given fully-qualified name in every file added to the
+/// compiler.
+///
+/// The `new_name` is a single identifier, which replaces the last component of the name, so the symbol stays in
+/// the same scope. For a package, the last component of the given package prefix is renamed, so renaming `foo.v1`
+/// to `v2` moves every file in `foo.v1` and its sub-packages into `foo.v2`.
+///
+/// The edits cover:
+///
+/// * the declaration of the symbol, or each `package` statement for a package;
+/// * the types of fields and extensions, including map values, the extendees of extensions, and the input and output
+///   types of methods, which refer to the symbol or a definition within it;
+/// * the names of options which set the renamed field, or an extension within the renamed scope.
+///
+/// References are changed by replacing only the renamed component of the name as it is written. If the result would
+/// resolve to a different definition, for example because the new name is shadowed in that scope, the reference is
+/// replaced by its fully-qualified name. Likewise, any other reference which would be shadowed by the new name is
+/// fully qualified, so every reference resolves to the same definition after the rename.
+///
+/// The sources of the files are needed to compute the edits, so this should be used with a compiler which
+/// [retains sources](Compiler::retain_sources).
+///
+/// # Errors
+///
+/// Returns an error if:
+///
+/// * the symbol is not defined, or the new name is not a valid identifier or is already defined in the same scope;
+/// * the symbol is an enum value, or a group, map entry message or other definition which is not declared by name in
+///   the source;
+/// * the source of a file which needs to change is not available;
+/// * an aggregate option value, such as `option (foo) = { bar: 1 }`, may refer to the renamed symbol, since names
+///   within these values are not updated.
+pub fn rename(compiler: &Compiler, symbol: &str, new_name: &str) -> Result<Rename, Error> {
+    let name = symbol.strip_prefix('.').unwrap_or(symbol);
+    let pool = compiler.descriptor_pool();
+    let symbols = SymbolTable::new(&pool);
+
+    let invalid = |reason: String| {
+        Error::from_kind(ErrorKind::InvalidRename {
+            name: name.to_owned(),
+            new_name: new_name.to_owned(),
+            reason,
+        })
+    };
+
+    let Some(target) = symbols.get(name) else {
+        return Err(invalid("it is not defined".to_owned()));
+    };
+    if !is_identifier(new_name) {
+        return Err(invalid("the new name is not a valid identifier".to_owned()));
+    }
+    if target.kind() == SymbolKind::EnumValue {
+        return Err(invalid("renaming enum values is not supported".to_owned()));
+    }
+
+    let new_full_name = match name.rsplit_once('.') {
+        Some((parent, _)) => join_name(parent, new_name),
+        None => new_name.to_owned(),
+    };
+    if new_full_name == name {
+        return Ok(Rename::default());
+    }
+    if let Some(existing) = symbols.get(&new_full_name) {
+        return Err(invalid(format!(
+            "'{}' is already defined in file '{}'",
+            new_full_name,
+            existing.file()
+        )));
+    }
+
+    let mut renamer = Renamer {
+        compiler,
+        pool: &pool,
+        name,
+        new_name,
+        kind: target.kind(),
+        renamed: symbols.renamed(name, &new_full_name),
+        new_full_name,
+        edits: BTreeMap::new(),
+    };
+    renamer.add_declaration().map_err(invalid)?;
+    for file in pool.files() {
+        renamer.add_references(&file).map_err(invalid)?;
+    }
+
+    let message = format!("rename '{}' to '{}'", name, renamer.new_full_name);
+    Ok(Rename {
+        fixes: renamer
+            .edits
+            .into_iter()
+            .map(|(file, edits)| {
+                let fix = Fix::new(message.clone(), edits.into_values().collect());
+                (file, fix)
+            })
+            .collect(),
+    })
+}
+
+struct Renamer<'a> {
+    compiler: &'a Compiler,
+    pool: &'a DescriptorPool,
+    /// The fully-qualified name of the symbol being renamed.
+    name: &'a str,
+    /// The new last component of the name.
+    new_name: &'a str,
+    new_full_name: String,
+    kind: SymbolKind,
+    /// The symbols of all files, with the symbol already renamed.
+    renamed: SymbolTable,
+    /// The edits to each file, keyed by their start offset.
+    edits: BTreeMap<String, BTreeMap<usize, Edit>>,
+}
+
+impl<'a> Renamer<'a> {
+    fn add_declaration(&mut self) -> Result<(), String> {
+        if self.kind == SymbolKind::Package {
+            // Each file in the package or one of its sub-packages declares it, so each package statement is renamed.
+            let index = self.name.matches('.').count();
+            for file in self.pool.files() {
+                if rename_prefix(file.package_name(), self.name, self.new_name).is_none() {
+                    continue;
+                }
+                let source = self.source(&file)?;
+                let Some(span) =
+                    find_location(file.file_descriptor_proto(), source, &[tag::file::PACKAGE])
+                else {
+                    continue;
+                };
+                if let Some((_, span)) = tokens(file.name(), source, span)
+                    .filter(|(kind, _)| matches!(kind, TokenKind::Ident(_)))
+                    .nth(index + 1)
+                {
+                    self.edit(file.name(), span, self.new_name.to_owned());
+                }
+            }
+            return Ok(());
+        }
+
+        let (file, path) = self.declaration()?;
+        let source = self.source(&file)?;
+        let Some(span) = find_location(
+            file.file_descriptor_proto(),
+            source,
+            &[path.as_slice(), &[tag::message::NAME]].concat(),
+        ) else {
+            return Err(format!(
+                "it is not declared by name in file '{}'",
+                file.name()
+            ));
+        };
+        self.edit(file.name(), span, self.new_name.to_owned());
+        Ok(())
+    }
+
+    /// Finds the file declaring the symbol, and the path of its declaration.
+    fn declaration(&self) -> Result<(FileDescriptor, Vec<i32>), String> {
+        let (parent, name) = self.name.rsplit_once('.').unwrap_or(("", self.name));
+        let declaration = match self.kind {
+            SymbolKind::Message => self.pool.get_message_by_name(self.name).map(|message| {
+                if message.is_map_entry() {
+                    return Err("map entry messages are generated by the compiler".to_owned());
+                }
+                let is_group = self
+                    .pool
+                    .all_messages()
+                    .flat_map(|message| message.fields().collect::<Vec<_>>())
+                    .map(|field| (field.is_group(), field.kind()))
+                    .chain(
+                        self.pool
+                            .all_extensions()
+                            .map(|extension| (extension.is_group(), extension.kind())),
+                    )
+                    .any(|(is_group, kind)| is_group && kind.as_message() == Some(&message));
+                if is_group {
+                    return Err("renaming groups is not supported".to_owned());
+                }
+                Ok((message.parent_file(), message.path().to_vec()))
+            }),
+            SymbolKind::Enum => self
+                .pool
+                .get_enum_by_name(self.name)
+                .map(|enum_| Ok((enum_.parent_file(), enum_.path().to_vec()))),
+            SymbolKind::Extension => self.pool.get_extension_by_name(self.name).map(|extension| {
+                if extension.is_group() {
+                    return Err("renaming groups is not supported".to_owned());
+                }
+                Ok((extension.parent_file(), extension.path().to_vec()))
+            }),
+            SymbolKind::Field => self
+                .pool
+                .get_message_by_name(parent)
+                .and_then(|message| message.get_field_by_name(name))
+                .map(|field| {
+                    if field.is_group() {
+                        return Err("renaming groups is not supported".to_owned());
+                    }
+                    Ok((field.parent_file(), field.path().to_vec()))
+                }),
+            SymbolKind::Oneof => self.pool.get_message_by_name(parent).and_then(|message| {
+                message
+                    .oneofs()
+                    .find(|oneof| oneof.name() == name)
+                    .map(|oneof| Ok((oneof.parent_file(), oneof.path().to_vec())))
+            }),
+            SymbolKind::Service => self
+                .pool
+                .get_service_by_name(self.name)
+                .map(|service| Ok((service.parent_file(), service.path().to_vec()))),
+            SymbolKind::Method => self.pool.get_service_by_name(parent).and_then(|service| {
+                service
+                    .methods()
+                    .find(|method| method.name() == name)
+                    .map(|method| Ok((method.parent_file(), method.path().to_vec())))
+            }),
+            SymbolKind::Package | SymbolKind::EnumValue => None,
+        };
+        declaration.unwrap_or_else(|| Err("it is not defined".to_owned()))
+    }
+
+    /// Updates the type names and option names in a file which refer to the renamed symbol, and qualifies any which
+    /// would be shadowed by it.
+    fn add_references(&mut self, file: &FileDescriptor) -> Result<(), String> {
+        let Some(source) = self.compiler.source(file.name()) else {
+            // Without the source, only the names which need to change can be found.
+            let is_affected = symbol::file_type_references(file)
+                .iter()
+                .any(|reference| self.rename(reference.target().full_name()).is_some());
+            if is_affected {
+                return Err(format!(
+                    "the source of file '{}' is not available",
+                    file.name()
+                ));
+            }
+            return Ok(());
+        };
+
+        let references = source_references(file, source);
+        for aggregate in &references.aggregates {
+            let value: Vec<_> = tokens(file.name(), source, aggregate.span.clone())
+                .map(|(kind, _)| kind)
+                .collect();
+            self.check_aggregate(file, &aggregate.message, &value)?;
+        }
+        for reference in references.references {
+            self.add_reference(file.name(), source, reference);
+        }
+        Ok(())
+    }
+
+    /// Checks that an aggregate option value of the given message type cannot refer to the renamed symbol, since the
+    /// text format value is not updated.
+    fn check_aggregate(
+        &self,
+        file: &FileDescriptor,
+        message: &MessageDescriptor,
+        value: &[TokenKind<'_>],
+    ) -> Result<(), String> {
+        let (parent, name) = self.name.rsplit_once('.').unwrap_or(("", self.name));
+        let mut in_brackets = false;
+        let mut is_affected = false;
+        for token in value {
+            match token {
+                TokenKind::Punct('[') => in_brackets = true,
+                TokenKind::Punct(']') => in_brackets = false,
+                // Extension names and type URLs are written in brackets, and field names outside them.
+                TokenKind::Ident(ident) if in_brackets == (self.kind != SymbolKind::Field) => {
+                    is_affected |= *ident == name;
+                }
+                _ => (),
+            }
+        }
+        if is_affected && (self.kind != SymbolKind::Field || reaches_message(message, parent)) {
+            return Err(format!(
+                "it may be referenced by an aggregate option value in file '{}', which cannot be updated",
+                file.name()
+            ));
+        }
+        Ok(())
+    }
+
+    /// Renames the component of a reference which refers to the renamed symbol, or qualifies the reference if it
+    /// would not resolve to the same definition after the rename.
+    fn add_reference(&mut self, file: &str, source: &str, reference: SourceReference) {
+        if reference.kind == ReferenceKind::OptionField {
+            // Fields are always named by a single identifier in their own message.
+            if reference.target == self.name {
+                self.edit(file, reference.span, self.new_name.to_owned());
+            }
+            return;
+        }
+
+        let mut is_absolute = false;
+        let mut parts = Vec::new();
+        for (kind, span) in tokens(file, source, reference.span.clone()) {
+            match kind {
+                TokenKind::Ident(ident) => parts.push((span, ident)),
+                TokenKind::Punct('.') if parts.is_empty() => is_absolute = true,
+                TokenKind::Punct('.') => (),
+                _ => return,
+            }
+        }
+
+        let new_target = self.rename(&reference.target);
+        let target = new_target.as_deref().unwrap_or(&reference.target);
+        let scope = self
+            .rename(&reference.scope)
+            .unwrap_or_else(|| reference.scope.clone());
+
+        // The name as written is a suffix of the fully-qualified name of its target.
+        let mut names: Vec<&str> = parts.iter().map(|&(_, name)| name).collect();
+        let mut edit = None;
+        if new_target.is_some() {
+            let index = self.name.matches('.').count();
+            let first = (reference.target.matches('.').count() + 1).checked_sub(names.len());
+            if let Some(i) = first.and_then(|first| index.checked_sub(first)) {
+                names[i] = self.new_name;
+                edit = Some(parts[i].0.clone());
+            }
+        }
+
+        let name = if is_absolute {
+            format!(".{}", names.join("."))
+        } else {
+            names.join(".")
+        };
+        let resolved = if reference.kind == ReferenceKind::OptionExtension {
+            self.renamed.resolve_name(&scope, &name, |symbol| {
+                symbol.kind() == SymbolKind::Extension
+            })
+        } else {
+            self.renamed.resolve_type_name(&scope, &name)
+        };
+
+        if resolved.is_some_and(|symbol| symbol.full_name() == target) {
+            if let Some(span) = edit {
+                self.edit(file, span, self.new_name.to_owned());
+            }
+        } else {
+            self.edit(file, reference.span, format!(".{}", target));
+        }
+    }
+
+    fn rename(&self, full_name: &str) -> Option<String> {
+        rename_prefix(full_name, self.name, &self.new_full_name)
+    }
+
+    fn source(&self, file: &FileDescriptor) -> Result<&'a str, String> {
+        self.compiler
+            .source(file.name())
+            .ok_or_else(|| format!("the source of file '{}' is not available", file.name()))
+    }
+
+    fn edit(&mut self, file: &str, span: Range<usize>, replacement: String) {
+        self.edits
+            .entry(file.to_owned())
+            .or_default()
+            .insert(span.start, Edit::new(span, replacement));
+    }
+}
+
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Returns `true` if a value of type `message` may contain a field of the message with the given name.
+fn reaches_message(message: &MessageDescriptor, name: &str) -> bool {
+    let mut visited = HashSet::new();
+    let mut stack = vec![message.clone()];
+    while let Some(message) = stack.pop() {
+        if message.full_name() == name {
+            return true;
+        }
+        if !visited.insert(message.full_name().to_owned()) {
+            continue;
+        }
+        for field in message.fields() {
+            if let Kind::Message(child) = field.kind() {
+                stack.push(child);
+            }
+        }
+    }
+    false
+}
@@ -0,0 +1,264 @@
+use super::rename;
+use crate::{
+    file::{ChainFileResolver, File, FileResolver, GoogleFileResolver},
+    Compiler, Error,
+};
+
+struct TestFileResolver {
+    files: Vec<(&'static str, &'static str)>,
+}
+
+impl FileResolver for TestFileResolver {
+    fn open_file(&self, name: &str) -> Result<File, Error> {
+        match self.files.iter().find(|(file, _)| *file == name) {
+            Some((_, source)) => File::from_source(name, source),
+            None => Err(Error::file_not_found(name)),
+        }
+    }
+}
+
+/// Compiles the files and renames a symbol, returning the updated source of each file which changed.
+fn check(
+    files: &[(&'static str, &'static str)],
+    symbol: &str,
+    new_name: &str,
+) -> Result<Vec<(String, String)>, String> {
+    let mut resolver = ChainFileResolver::new();
+    resolver.add(TestFileResolver {
+        files: files.to_vec(),
+    });
+    resolver.add(GoogleFileResolver::new());
+
+    let mut compiler = Compiler::with_file_resolver(resolver);
+    compiler
+        .open_files(files.iter().map(|(name, _)| name))
+        .unwrap();
+
+    let rename = rename(&compiler, symbol, new_name).map_err(|err| err.to_string())?;
+    Ok(rename
+        .files()
+        .map(|(name, fix)| {
+            let (_, source) = files.iter().find(|(file, _)| *file == name).unwrap();
+            (name.to_owned(), fix.apply(source))
+        })
+        .collect())
+}
+
+fn updated(files: &[(&str, &str)]) -> Vec<(String, String)> {
+    files
+        .iter()
+        .map(|(name, source)| (name.to_string(), source.to_string()))
+        .collect()
+}
+
+#[test]
+fn rename_message() {
+    assert_eq!(
+        check(
+            &[
+                (
+                    "a.proto",
+                    "syntax = 'proto3';
+                    package pkg;
+                    message Foo { message Inner {} }",
+                ),
+                (
+                    "b.proto",
+                    "syntax = 'proto3';
+                    package pkg.sub;
+                    import 'a.proto';
+                    message Bar {
+                        pkg.Foo foo = 1;
+                        .pkg.Foo.Inner inner = 2;
+                        map<string, Foo.Inner> map = 3;
+                    }
+                    service Service {
+                        rpc Get(pkg.Foo) returns (Foo);
+                    }",
+                ),
+            ],
+            "pkg.Foo",
+            "Baz",
+        ),
+        Ok(updated(&[
+            (
+                "a.proto",
+                "syntax = 'proto3';
+                    package pkg;
+                    message Baz { message Inner {} }",
+            ),
+            (
+                "b.proto",
+                "syntax = 'proto3';
+                    package pkg.sub;
+                    import 'a.proto';
+                    message Bar {
+                        pkg.Baz foo = 1;
+                        .pkg.Baz.Inner inner = 2;
+                        map<string, Baz.Inner> map = 3;
+                    }
+                    service Service {
+                        rpc Get(pkg.Baz) returns (Baz);
+                    }",
+            ),
+        ]))
+    );
+}
+
+#[test]
+fn rename_package() {
+    assert_eq!(
+        check(
+            &[
+                ("a.proto", "package foo.v1; message Foo {}"),
+                (
+                    "b.proto",
+                    "package foo.v1.sub;
+                    import 'a.proto';
+                    message Bar { optional foo.v1.Foo foo = 1; optional v1.Foo foo2 = 2; }",
+                ),
+                (
+                    "c.proto",
+                    "package other; import 'a.proto'; message Baz { optional .foo.v1.Foo foo = 1; }",
+                ),
+            ],
+            "foo.v1",
+            "v2",
+        ),
+        Ok(updated(&[
+            ("a.proto", "package foo.v2; message Foo {}"),
+            (
+                "b.proto",
+                "package foo.v2.sub;
+                    import 'a.proto';
+                    message Bar { optional foo.v2.Foo foo = 1; optional v2.Foo foo2 = 2; }",
+            ),
+            (
+                "c.proto",
+                "package other; import 'a.proto'; message Baz { optional .foo.v2.Foo foo = 1; }",
+            ),
+        ]))
+    );
+}
+
+#[test]
+fn rename_field_and_extension_in_options() {
+    let files = [(
+        "root.proto",
+        "syntax = 'proto2';
+        package pkg;
+        import 'google/protobuf/descriptor.proto';
+        message Opts { optional int32 old = 1; }
+        extend google.protobuf.MessageOptions { optional Opts opts = 5000; }
+        extend google.protobuf.FieldOptions { optional Opts field_opts = 5000; }
+        message Foo {
+            option (opts).old = 1;
+            optional int32 bar = 1 [(pkg.field_opts).old = 2];
+        }",
+    )];
+
+    assert_eq!(
+        check(&files, "pkg.Opts.old", "new"),
+        Ok(updated(&[(
+            "root.proto",
+            "syntax = 'proto2';
+        package pkg;
+        import 'google/protobuf/descriptor.proto';
+        message Opts { optional int32 new = 1; }
+        extend google.protobuf.MessageOptions { optional Opts opts = 5000; }
+        extend google.protobuf.FieldOptions { optional Opts field_opts = 5000; }
+        message Foo {
+            option (opts).new = 1;
+            optional int32 bar = 1 [(pkg.field_opts).new = 2];
+        }",
+        )]))
+    );
+    assert_eq!(
+        check(&files, "pkg.field_opts", "settings"),
+        Ok(updated(&[(
+            "root.proto",
+            "syntax = 'proto2';
+        package pkg;
+        import 'google/protobuf/descriptor.proto';
+        message Opts { optional int32 old = 1; }
+        extend google.protobuf.MessageOptions { optional Opts opts = 5000; }
+        extend google.protobuf.FieldOptions { optional Opts settings = 5000; }
+        message Foo {
+            option (opts).old = 1;
+            optional int32 bar = 1 [(pkg.settings).old = 2];
+        }",
+        )]))
+    );
+}
+
+#[test]
+fn rename_qualifies_shadowed_references() {
+    // After renaming, 'Bar' inside 'Outer' would refer to 'pkg.Outer.Bar', so the reference to 'pkg.Bar' is
+    // qualified.
+    assert_eq!(
+        check(
+            &[(
+                "root.proto",
+                "package pkg;
+                message Bar {}
+                message Outer {
+                    message Inner {}
+                    optional Bar bar = 1;
+                    optional Inner inner = 2;
+                }",
+            )],
+            "pkg.Outer.Inner",
+            "Bar",
+        ),
+        Ok(updated(&[(
+            "root.proto",
+            "package pkg;
+                message Bar {}
+                message Outer {
+                    message Bar {}
+                    optional .pkg.Bar bar = 1;
+                    optional Bar inner = 2;
+                }",
+        )]))
+    );
+}
+
+#[test]
+fn rename_errors() {
+    let files = [(
+        "root.proto",
+        "syntax = 'proto2';
+        package pkg;
+        import 'google/protobuf/descriptor.proto';
+        message Foo { optional int32 a = 1; }
+        message Bar {}
+        enum Kind { KIND_UNSPECIFIED = 0; }
+        extend google.protobuf.FileOptions { optional Foo foo = 5000; }
+        option (foo) = { a: 1 };",
+    )];
+
+    assert_eq!(
+        check(&files, "pkg.Missing", "Other"),
+        Err("cannot rename 'pkg.Missing' to 'Other': it is not defined".to_owned())
+    );
+    assert_eq!(
+        check(&files, "pkg.Foo", "Bar"),
+        Err(
+            "cannot rename 'pkg.Foo' to 'Bar': 'pkg.Bar' is already defined in file 'root.proto'"
+                .to_owned()
+        )
+    );
+    assert_eq!(
+        check(&files, "pkg.Foo", "1Foo"),
+        Err("cannot rename 'pkg.Foo' to '1Foo': the new name is not a valid identifier".to_owned())
+    );
+    assert_eq!(
+        check(&files, "pkg.KIND_UNSPECIFIED", "KIND_UNKNOWN"),
+        Err("cannot rename 'pkg.KIND_UNSPECIFIED' to 'KIND_UNKNOWN': renaming enum values is not supported".to_owned())
+    );
+    assert_eq!(
+        check(&files, "pkg.Foo.a", "b"),
+        Err("cannot rename 'pkg.Foo.a' to 'b': it may be referenced by an aggregate option value in file 'root.proto', which cannot be updated".to_owned())
+    );
+    assert_eq!(check(&files, "pkg.Foo", "Foo"), Ok(Vec::new()));
+}
@@ -0,0 +1,484 @@
+//! Size, complexity and timing metrics for compiled files.
+//!
+//! See [`Compiler::stats`](crate::Compiler::stats) and [`Compiler::compile_with_report`](crate::Compiler::compile_with_report).
+
+use std::{collections::HashMap, time::Duration};
+
+use prost::Message;
+use prost_reflect::{FileDescriptor, MessageDescriptor};
+use protox_parse::tag;
+
+use crate::compile::find_unused_imports;
+
+/// Metrics describing a set of compiled files.
+///
+/// # Examples
+///
+/// ```
+/// # use protox::{file::{File, FileResolver}, Compiler};
+/// # struct Resolver;
+/// # impl FileResolver for Resolver {
+/// #     fn open_file(&self, name: &str) -> Result<File, protox::Error> {
+/// #         File::from_source(name, "message Foo { message Bar { optional int32 a = 1; } optional Bar bar = 1; }")
+/// #     }
+/// # }
+/// # fn main() -> Result<(), protox::Error> {
+/// let mut compiler = Compiler::with_file_resolver(Resolver);
+/// compiler.open_file("root.proto")?;
+///
+/// let stats = compiler.stats();
+/// assert_eq!(stats.messages(), 2);
+/// assert_eq!(stats.fields(), 2);
+/// assert_eq!(stats.max_nesting_depth(), 2);
+/// assert_eq!(stats.files()[0].largest_message(), Some(("Foo", 1)));
+/// # Ok(())
+/// # }
+/// ```
+///
+/// If the `serde` feature is enabled, `Stats` implements [`Serialize`](serde::Serialize), so the metrics for each file
+/// can be written as JSON.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Stats {
+    files: Vec<FileStats>,
+}
+
+/// An estimate of the memory used by the files added to a [`Compiler`](crate::Compiler).
+///
+/// See [`Compiler::memory_usage`](crate::Compiler::memory_usage). Sizes are measured in bytes of encoded data, which
+/// closely tracks, but is smaller than, the memory used by the decoded descriptors.
+///
+/// # Examples
+///
+/// ```
+/// # use protox::{file::{File, FileResolver}, Compiler};
+/// # struct Resolver;
+/// # impl FileResolver for Resolver {
+/// #     fn open_file(&self, name: &str) -> Result<File, protox::Error> {
+/// #         File::from_source(name, "message Foo { optional int32 a = 1; }")
+/// #     }
+/// # }
+/// # fn main() -> Result<(), protox::Error> {
+/// let mut compiler = Compiler::with_file_resolver(Resolver);
+/// compiler.open_file("root.proto")?;
+/// assert_eq!(compiler.memory_usage().sources(), "root.proto".len() + 37);
+///
+/// compiler.retain_sources(false);
+/// assert_eq!(compiler.memory_usage().sources(), 0);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryUsage {
+    files: usize,
+    descriptors: usize,
+    source_info: usize,
+    sources: usize,
+}
+
+/// The time spent compiling each file, returned by [`Compiler::compile_with_report`](crate::Compiler::compile_with_report).
+///
+/// # Examples
+///
+/// ```
+/// # use protox::{file::{File, FileResolver}, Compiler};
+/// # struct Resolver;
+/// # impl FileResolver for Resolver {
+/// #     fn open_file(&self, name: &str) -> Result<File, protox::Error> {
+/// #         match name {
+/// #             "root.proto" => File::from_source(name, "import 'dep.proto'; message Foo { optional Bar bar = 1; }"),
+/// #             _ => File::from_source(name, "message Bar {}"),
+/// #         }
+/// #     }
+/// # }
+/// # fn main() -> Result<(), protox::Error> {
+/// let mut compiler = Compiler::with_file_resolver(Resolver);
+/// let (file_descriptor_set, report) = compiler.compile_with_report(["root.proto"])?;
+///
+/// assert_eq!(file_descriptor_set.file.len(), 1);
+/// assert_eq!(report.files()[0].name(), "root.proto");
+/// assert_eq!(report.files()[1].name(), "dep.proto");
+/// assert!(report.resolve_time() + report.parse_time() <= report.total_time());
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CompileReport {
+    files: Vec<FileTimings>,
+    indices: HashMap<String, usize>,
+    total_time: Duration,
+}
+
+/// The time spent compiling a single file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileTimings {
+    name: String,
+    resolve_time: Duration,
+    parse_time: Duration,
+    check_time: Duration,
+}
+
+/// Metrics describing a single file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct FileStats {
+    name: String,
+    messages: usize,
+    fields: usize,
+    extensions: usize,
+    enums: usize,
+    services: usize,
+    methods: usize,
+    encoded_size: usize,
+    source_info_size: usize,
+    max_nesting_depth: usize,
+    largest_message: Option<LargestMessage>,
+    unused_imports: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+struct LargestMessage {
+    name: String,
+    fields: usize,
+}
+
+impl Stats {
+    /// Computes metrics for the given files.
+    pub fn new(files: impl IntoIterator<Item = FileDescriptor>) -> Self {
+        Stats {
+            files: files
+                .into_iter()
+                .map(|file| FileStats::new(&file))
+                .collect(),
+        }
+    }
+
+    /// Gets the metrics for each file.
+    pub fn files(&self) -> &[FileStats] {
+        &self.files
+    }
+
+    /// Gets the total number of messages, including nested messages and map entries, in all files.
+    pub fn messages(&self) -> usize {
+        self.files.iter().map(FileStats::messages).sum()
+    }
+
+    /// Gets the total number of message fields in all files.
+    pub fn fields(&self) -> usize {
+        self.files.iter().map(FileStats::fields).sum()
+    }
+
+    /// Gets the total number of extensions in all files.
+    pub fn extensions(&self) -> usize {
+        self.files.iter().map(FileStats::extensions).sum()
+    }
+
+    /// Gets the total number of enums in all files.
+    pub fn enums(&self) -> usize {
+        self.files.iter().map(FileStats::enums).sum()
+    }
+
+    /// Gets the total number of services in all files.
+    pub fn services(&self) -> usize {
+        self.files.iter().map(FileStats::services).sum()
+    }
+
+    /// Gets the total number of methods in all files.
+    pub fn methods(&self) -> usize {
+        self.files.iter().map(FileStats::methods).sum()
+    }
+
+    /// Gets the total encoded size in bytes of all file descriptors, excluding source code info.
+    pub fn encoded_size(&self) -> usize {
+        self.files.iter().map(FileStats::encoded_size).sum()
+    }
+
+    /// Gets the total size in bytes which source code info adds to the encoded file descriptors.
+    pub fn source_info_size(&self) -> usize {
+        self.files.iter().map(FileStats::source_info_size).sum()
+    }
+
+    /// Gets the deepest nesting of messages in any file.
+    pub fn max_nesting_depth(&self) -> usize {
+        self.files
+            .iter()
+            .map(FileStats::max_nesting_depth)
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Gets the file and full name of the message with the most fields, along with its number of fields.
+    pub fn largest_message(&self) -> Option<(&str, &str, usize)> {
+        self.files
+            .iter()
+            .filter_map(|file| {
+                file.largest_message()
+                    .map(|(message, fields)| (file.name(), message, fields))
+            })
+            .fold(None, |largest, message| match largest {
+                Some(largest) if largest.2 >= message.2 => Some(largest),
+                _ => Some(message),
+            })
+    }
+}
+
+impl FileStats {
+    fn new(file: &FileDescriptor) -> Self {
+        let file_proto = file.file_descriptor_proto();
+        let source_info_size = file_proto.source_code_info.as_ref().map_or(0, |info| {
+            prost::encoding::message::encoded_len(tag::file::SOURCE_CODE_INFO as u32, info)
+        });
+
+        let mut stats = FileStats {
+            name: file.name().to_owned(),
+            messages: 0,
+            fields: 0,
+            extensions: file.extensions().len(),
+            enums: file.enums().len(),
+            services: file.services().len(),
+            methods: file.services().map(|service| service.methods().len()).sum(),
+            encoded_size: file.encode_to_vec().len() - source_info_size,
+            source_info_size,
+            max_nesting_depth: 0,
+            largest_message: None,
+            unused_imports: find_unused_imports(file, None)
+                .into_iter()
+                .map(|import| import.name().to_owned())
+                .collect(),
+        };
+
+        let mut messages: Vec<(MessageDescriptor, usize)> =
+            file.messages().map(|message| (message, 1)).collect();
+        messages.reverse();
+        while let Some((message, depth)) = messages.pop() {
+            let fields = message.fields().len();
+            stats.messages += 1;
+            stats.fields += fields;
+            stats.extensions += message.child_extensions().len();
+            stats.enums += message.child_enums().len();
+            stats.max_nesting_depth = stats.max_nesting_depth.max(depth);
+            if stats
+                .largest_message
+                .as_ref()
+                .map_or(true, |largest| fields > largest.fields)
+            {
+                stats.largest_message = Some(LargestMessage {
+                    name: message.full_name().to_owned(),
+                    fields,
+                });
+            }
+
+            let start = messages.len();
+            messages.extend(message.child_messages().map(|child| (child, depth + 1)));
+            messages[start..].reverse();
+        }
+
+        stats
+    }
+
+    /// Gets the name of the file.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Gets the number of messages, including nested messages and map entries, in the file.
+    pub fn messages(&self) -> usize {
+        self.messages
+    }
+
+    /// Gets the number of message fields in the file.
+    pub fn fields(&self) -> usize {
+        self.fields
+    }
+
+    /// Gets the number of extensions in the file, including those nested in messages.
+    pub fn extensions(&self) -> usize {
+        self.extensions
+    }
+
+    /// Gets the number of enums in the file, including those nested in messages.
+    pub fn enums(&self) -> usize {
+        self.enums
+    }
+
+    /// Gets the number of services in the file.
+    pub fn services(&self) -> usize {
+        self.services
+    }
+
+    /// Gets the number of methods in all services in the file.
+    pub fn methods(&self) -> usize {
+        self.methods
+    }
+
+    /// Gets the encoded size in bytes of the file descriptor, excluding source code info.
+    pub fn encoded_size(&self) -> usize {
+        self.encoded_size
+    }
+
+    /// Gets the size in bytes which source code info adds to the encoded file descriptor.
+    pub fn source_info_size(&self) -> usize {
+        self.source_info_size
+    }
+
+    /// Gets the deepest nesting of messages in the file, where top-level messages have a depth of one.
+    pub fn max_nesting_depth(&self) -> usize {
+        self.max_nesting_depth
+    }
+
+    /// Gets the full name of the message with the most fields, along with its number of fields.
+    ///
+    /// If several messages have the same number of fields, the first one declared is returned.
+    pub fn largest_message(&self) -> Option<(&str, usize)> {
+        self.largest_message
+            .as_ref()
+            .map(|largest| (largest.name.as_str(), largest.fields))
+    }
+
+    /// Gets the names of imported files which do not declare any name used by this file.
+    ///
+    /// See [`Compiler::unused_imports`](crate::Compiler::unused_imports).
+    pub fn unused_imports(&self) -> &[String] {
+        &self.unused_imports
+    }
+}
+
+impl MemoryUsage {
+    pub(crate) fn new<'a>(
+        files: impl IntoIterator<Item = FileDescriptor>,
+        sources: impl IntoIterator<Item = (&'a str, &'a str)>,
+    ) -> Self {
+        let mut usage = MemoryUsage {
+            files: 0,
+            descriptors: 0,
+            source_info: 0,
+            sources: sources
+                .into_iter()
+                .map(|(name, source)| name.len() + source.len())
+                .sum(),
+        };
+        for file in files {
+            let file_proto = file.file_descriptor_proto();
+            let source_info = file_proto.source_code_info.as_ref().map_or(0, |info| {
+                prost::encoding::message::encoded_len(tag::file::SOURCE_CODE_INFO as u32, info)
+            });
+            usage.files += 1;
+            usage.descriptors += file_proto.encoded_len() - source_info;
+            usage.source_info += source_info;
+        }
+        usage
+    }
+
+    /// Gets the number of files, including imports and files added from file descriptor sets.
+    pub fn files(&self) -> usize {
+        self.files
+    }
+
+    /// Gets the encoded size in bytes of the file descriptors of all files, excluding source code info.
+    pub fn descriptors(&self) -> usize {
+        self.descriptors
+    }
+
+    /// Gets the encoded size in bytes of the source code info of all files.
+    pub fn source_info(&self) -> usize {
+        self.source_info
+    }
+
+    /// Gets the size in bytes of the source text, and the names, of the files whose sources are kept for error
+    /// reporting.
+    pub fn sources(&self) -> usize {
+        self.sources
+    }
+
+    /// Gets the sum of all sizes.
+    pub fn total(&self) -> usize {
+        self.descriptors + self.source_info + self.sources
+    }
+}
+
+impl CompileReport {
+    /// Gets the timings of each file, in the order the files were first resolved.
+    ///
+    /// Files which were already added to the [`Compiler`](crate::Compiler) before compiling are not included.
+    pub fn files(&self) -> &[FileTimings] {
+        &self.files
+    }
+
+    /// Gets the total time spent by [`FileResolver`](crate::file::FileResolver)s opening and reading files.
+    pub fn resolve_time(&self) -> Duration {
+        self.files.iter().map(FileTimings::resolve_time).sum()
+    }
+
+    /// Gets the total time spent parsing source files.
+    pub fn parse_time(&self) -> Duration {
+        self.files.iter().map(FileTimings::parse_time).sum()
+    }
+
+    /// Gets the total time spent checking files and adding them to the descriptor pool.
+    pub fn check_time(&self) -> Duration {
+        self.files.iter().map(FileTimings::check_time).sum()
+    }
+
+    /// Gets the time taken to compile all files, including any time not attributed to a single file.
+    pub fn total_time(&self) -> Duration {
+        self.total_time
+    }
+
+    pub(crate) fn file_mut(&mut self, name: &str) -> &mut FileTimings {
+        let index = match self.indices.get(name) {
+            Some(&index) => index,
+            None => {
+                self.indices.insert(name.to_owned(), self.files.len());
+                self.files.push(FileTimings {
+                    name: name.to_owned(),
+                    resolve_time: Duration::ZERO,
+                    parse_time: Duration::ZERO,
+                    check_time: Duration::ZERO,
+                });
+                self.files.len() - 1
+            }
+        };
+        &mut self.files[index]
+    }
+
+    pub(crate) fn set_total_time(&mut self, total_time: Duration) {
+        self.total_time = total_time;
+    }
+}
+
+impl FileTimings {
+    /// Gets the name of the file.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Gets the time spent by the [`FileResolver`](crate::file::FileResolver) to open and read the file, not
+    /// including the time spent parsing it.
+    pub fn resolve_time(&self) -> Duration {
+        self.resolve_time
+    }
+
+    /// Gets the time spent parsing the file, or zero if it was not resolved from source.
+    pub fn parse_time(&self) -> Duration {
+        self.parse_time
+    }
+
+    /// Gets the time spent checking the file, including any [custom checks](crate::Compiler::add_check), and adding
+    /// it to the descriptor pool.
+    pub fn check_time(&self) -> Duration {
+        self.check_time
+    }
+
+    pub(crate) fn add_resolve_time(&mut self, time: Duration) {
+        self.resolve_time += time;
+    }
+
+    pub(crate) fn add_parse_time(&mut self, time: Duration) {
+        self.parse_time += time;
+    }
+
+    pub(crate) fn add_check_time(&mut self, time: Duration) {
+        self.check_time += time;
+    }
+}
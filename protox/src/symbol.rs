@@ -0,0 +1,918 @@
+//! Types describing named protobuf definitions and the references between them.
+
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fmt, iter,
+    ops::Bound,
+};
+
+use miette::SourceSpan;
+use prost_reflect::{
+    DescriptorPool, DynamicMessage, EnumDescriptor, ExtensionDescriptor, FieldDescriptor,
+    FileDescriptor, Kind, MessageDescriptor, ReflectMessage, Value,
+};
+use prost_types::{DescriptorProto, FileDescriptorProto};
+
+/// The kind of definition a [`Symbol`] refers to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum SymbolKind {
+    /// A message type.
+    Message,
+    /// An enum type.
+    Enum,
+    /// A package, or a prefix of a package name.
+    Package,
+    /// A service.
+    Service,
+    /// An extension field.
+    Extension,
+    /// A message field.
+    Field,
+    /// A oneof, including synthetic oneofs generated for proto3 `optional` fields.
+    Oneof,
+    /// A service method.
+    Method,
+    /// An enum value. Like in C++, enum values are siblings of their enum type, rather than children of it.
+    EnumValue,
+}
+
+/// A named definition in a protobuf file.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Symbol {
+    pub(crate) full_name: String,
+    pub(crate) kind: SymbolKind,
+    pub(crate) file: String,
+}
+
+/// A table of every fully-qualified name defined in a set of files.
+///
+/// This contains the same names, with the same scoping rules, as are used to resolve type names while checking files.
+/// It can be created from a [`DescriptorPool`] with [`SymbolTable::new`], or from a [`Compiler`](crate::Compiler)
+/// with [`Compiler::symbol_table`](crate::Compiler::symbol_table).
+///
+/// # Examples
+///
+/// ```
+/// # use protox::{file::{File, FileResolver}, symbol::SymbolKind, Compiler};
+/// # struct Resolver;
+/// # impl FileResolver for Resolver {
+/// #     fn open_file(&self, name: &str) -> Result<File, protox::Error> {
+/// #         File::from_source(name, "package foo.bar; message Outer { message Inner {} } enum Kind { KIND_UNSPECIFIED = 0; }")
+/// #     }
+/// # }
+/// # fn main() -> Result<(), protox::Error> {
+/// let mut compiler = Compiler::with_file_resolver(Resolver);
+/// compiler.open_file("root.proto")?;
+/// let symbols = compiler.symbol_table();
+///
+/// assert_eq!(symbols.get("foo.bar").unwrap().kind(), SymbolKind::Package);
+/// assert_eq!(symbols.get("foo.bar.Outer.Inner").unwrap().file(), "root.proto");
+///
+/// let children: Vec<&str> = symbols.children("foo.bar").map(|s| s.full_name()).collect();
+/// assert_eq!(children, ["foo.bar.KIND_UNSPECIFIED", "foo.bar.Kind", "foo.bar.Outer"]);
+///
+/// let resolved = symbols.resolve_type_name("foo.bar.Outer", "Inner").unwrap();
+/// assert_eq!(resolved.full_name(), "foo.bar.Outer.Inner");
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct SymbolTable {
+    symbols: BTreeMap<String, Symbol>,
+    /// The names of the symbols declared by each file, other than packages.
+    files: HashMap<String, Vec<String>>,
+    /// The files declaring each package, in the order they were added.
+    packages: BTreeMap<String, Vec<String>>,
+}
+
+/// The namespaces whose names were changed by [`SymbolTable::update_file`] or [`SymbolTable::remove_file`].
+///
+/// A namespace is a package, or the fully-qualified name of a message, enum or service, and the empty string is the
+/// namespace of top-level names in files without a package. A namespace changes if a name directly within it is
+/// added, removed, or now refers to a different kind of definition. References in other files which only look up
+/// names in unchanged namespaces resolve to the same symbols as before, so they do not need to be checked again.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SymbolChanges {
+    namespaces: BTreeSet<String>,
+}
+
+/// The kind of element making a [`TypeReference`] or [`Location`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ReferenceKind {
+    /// The type of a message field or extension field.
+    FieldType,
+    /// The message extended by an extension field.
+    Extendee,
+    /// The input type of a service method.
+    MethodInput,
+    /// The output type of a service method.
+    MethodOutput,
+    /// An extension named in parentheses in an option, such as `foo` in `option (foo).bar = 1;`.
+    OptionExtension,
+    /// A field named in an option, such as `bar` in `option (foo).bar = 1;`.
+    OptionField,
+}
+
+/// A resolved reference from a field, extension or method to the type it names.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct TypeReference {
+    pub(crate) file: String,
+    pub(crate) referrer: String,
+    pub(crate) kind: ReferenceKind,
+    pub(crate) target: Symbol,
+}
+
+/// A name in the source of a compiled file which refers to a definition.
+///
+/// See [`Compiler::references`](crate::Compiler::references).
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Location {
+    pub(crate) file: String,
+    pub(crate) span: SourceSpan,
+    pub(crate) kind: ReferenceKind,
+}
+
+/// A definition generated by the compiler rather than declared in the source, and the field it was generated for.
+///
+/// Map fields generate a nested `XxxEntry` message, and proto3 `optional` fields generate a oneof named `_xxx`.
+/// Like `protoc`, no source code info is emitted for these definitions, so this links them back to the field which
+/// does have a location.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SyntheticOrigin {
+    pub(crate) synthetic: Symbol,
+    pub(crate) field: String,
+}
+
+impl SymbolKind {
+    fn as_str(&self) -> &'static str {
+        match self {
+            SymbolKind::Message => "message",
+            SymbolKind::Enum => "enum",
+            SymbolKind::Package => "package",
+            SymbolKind::Service => "service",
+            SymbolKind::Extension => "extension",
+            SymbolKind::Field => "field",
+            SymbolKind::Oneof => "oneof",
+            SymbolKind::Method => "method",
+            SymbolKind::EnumValue => "enum value",
+        }
+    }
+
+    /// Returns true if names may be defined within a symbol of this kind.
+    fn is_parent(&self) -> bool {
+        matches!(
+            self,
+            SymbolKind::Package | SymbolKind::Message | SymbolKind::Enum | SymbolKind::Service
+        )
+    }
+}
+
+impl fmt::Display for SymbolKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl Symbol {
+    /// Gets the fully-qualified name of this symbol, without a leading period.
+    pub fn full_name(&self) -> &str {
+        &self.full_name
+    }
+
+    /// Gets the kind of definition this symbol refers to.
+    pub fn kind(&self) -> SymbolKind {
+        self.kind
+    }
+
+    /// Gets the name of the file which declares this symbol.
+    pub fn file(&self) -> &str {
+        &self.file
+    }
+
+    fn from_message(message: &MessageDescriptor) -> Self {
+        Symbol {
+            full_name: message.full_name().to_owned(),
+            kind: SymbolKind::Message,
+            file: message.parent_file().name().to_owned(),
+        }
+    }
+
+    fn from_enum(enum_: &EnumDescriptor) -> Self {
+        Symbol {
+            full_name: enum_.full_name().to_owned(),
+            kind: SymbolKind::Enum,
+            file: enum_.parent_file().name().to_owned(),
+        }
+    }
+
+    fn from_kind(kind: Kind) -> Option<Self> {
+        match kind {
+            Kind::Message(message) => Some(Symbol::from_message(&message)),
+            Kind::Enum(enum_) => Some(Symbol::from_enum(&enum_)),
+            _ => None,
+        }
+    }
+}
+
+impl SymbolTable {
+    /// Collects the names defined by all files in the given pool.
+    ///
+    /// If a package is declared by several files, its symbol refers to the first of them.
+    pub fn new(pool: &DescriptorPool) -> Self {
+        let mut table = SymbolTable::default();
+        for file in pool.files() {
+            table.add_file(&file);
+        }
+        table
+    }
+
+    /// Looks up a symbol by its fully-qualified name, without a leading period.
+    pub fn get(&self, full_name: &str) -> Option<&Symbol> {
+        self.symbols.get(full_name)
+    }
+
+    /// Gets the symbols defined directly within the given namespace, ordered by name.
+    ///
+    /// The namespace may be a package or the name of a message, enum or service. Passing an empty string lists
+    /// the top-level symbols.
+    pub fn children<'a>(&'a self, namespace: &str) -> impl Iterator<Item = &'a Symbol> + 'a {
+        let prefix = if namespace.is_empty() {
+            String::new()
+        } else {
+            format!("{}.", namespace)
+        };
+
+        let prefix_len = prefix.len();
+        self.symbols
+            .range::<str, _>((Bound::Included(prefix.as_str()), Bound::Unbounded))
+            .map(|(_, symbol)| symbol)
+            .take_while(move |symbol| symbol.full_name.starts_with(&prefix))
+            .filter(move |symbol| !symbol.full_name[prefix_len..].contains('.'))
+    }
+
+    /// Gets all symbols in the table, ordered by name.
+    pub fn iter(&self) -> impl Iterator<Item = &'_ Symbol> {
+        self.symbols.values()
+    }
+
+    /// Resolves a type name, as written in a field, extension or method, to the message or enum it refers to.
+    ///
+    /// The `scope` is the fully-qualified name of the element containing the reference: for a field this is its
+    /// message, and for a method it is the package of the file. Names with a leading period are fully-qualified.
+    /// Otherwise, this uses the same rules as the checker: the first component of the name is looked up in each
+    /// enclosing scope from the innermost outwards, and once it is found the rest of the name must be defined
+    /// within it.
+    ///
+    /// Returns `None` if the name does not refer to a message or enum. Unlike the checker, this does not verify
+    /// that the file containing the symbol is imported by the file making the reference.
+    pub fn resolve_type_name(&self, scope: &str, name: &str) -> Option<&Symbol> {
+        self.resolve_name(scope, name, |symbol| {
+            matches!(symbol.kind, SymbolKind::Message | SymbolKind::Enum)
+        })
+    }
+
+    /// Resolves a name relative to `scope` with the same rules as [`resolve_type_name`](SymbolTable::resolve_type_name),
+    /// to a symbol accepted by `is_target`.
+    pub(crate) fn resolve_name(
+        &self,
+        scope: &str,
+        name: &str,
+        is_target: impl Fn(&Symbol) -> bool,
+    ) -> Option<&Symbol> {
+        if let Some(full_name) = name.strip_prefix('.') {
+            return self.get(full_name).filter(|symbol| is_target(symbol));
+        }
+
+        let first_part = name.split('.').next().unwrap_or_default();
+        let candidate_parents = iter::once(scope)
+            .chain(scope.rmatch_indices('.').map(|(i, _)| &scope[..i]))
+            .chain(iter::once(""))
+            .filter(|parent| !scope.is_empty() || parent.is_empty());
+        for parent in candidate_parents {
+            let join = |name: &str| {
+                if parent.is_empty() {
+                    name.to_owned()
+                } else {
+                    format!("{}.{}", parent, name)
+                }
+            };
+
+            if first_part.len() == name.len() {
+                if let Some(symbol) = self.get(&join(name)).filter(|symbol| is_target(symbol)) {
+                    return Some(symbol);
+                }
+            } else if self
+                .get(&join(first_part))
+                .is_some_and(|symbol| symbol.kind.is_parent())
+            {
+                // The first component determines the scope, even if the full name is not found within it.
+                return self.get(&join(name)).filter(|symbol| is_target(symbol));
+            }
+        }
+
+        None
+    }
+
+    /// Adds the names defined by a file, replacing the names of any file with the same name which was added before.
+    ///
+    /// This allows a table to be kept up to date as files are edited and compiled again, without collecting the names
+    /// of every other file. The returned [`SymbolChanges`] identify the references in other files which may now
+    /// resolve differently. An updated file is
+    /// treated as the last file added, so a package it shares with other files then refers to the first of those.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use prost_reflect::DescriptorPool;
+    /// # use prost_types::{DescriptorProto, FileDescriptorProto};
+    /// # use protox::symbol::SymbolTable;
+    /// # fn main() -> Result<(), prost_reflect::DescriptorError> {
+    /// fn compile(messages: &[&str]) -> Result<DescriptorPool, prost_reflect::DescriptorError> {
+    ///     let mut pool = DescriptorPool::new();
+    ///     pool.add_file_descriptor_proto(FileDescriptorProto {
+    ///         name: Some("dep.proto".to_owned()),
+    ///         package: Some("foo".to_owned()),
+    ///         message_type: messages
+    ///             .iter()
+    ///             .map(|name| DescriptorProto {
+    ///                 name: Some(name.to_string()),
+    ///                 ..Default::default()
+    ///             })
+    ///             .collect(),
+    ///         ..Default::default()
+    ///     })?;
+    ///     Ok(pool)
+    /// }
+    ///
+    /// let mut symbols = SymbolTable::new(&compile(&["Dep"])?);
+    ///
+    /// let edited = compile(&["Dep", "Added"])?;
+    /// let changes = symbols.update_file(&edited.get_file_by_name("dep.proto").unwrap());
+    /// assert_eq!(symbols.get("foo.Added").unwrap().file(), "dep.proto");
+    /// assert_eq!(changes.namespaces().collect::<Vec<_>>(), ["foo"]);
+    ///
+    /// assert!(changes.affects("foo.Root", "Added"));
+    /// assert!(!changes.affects("bar.Root", "baz.Other"));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn update_file(&mut self, file: &FileDescriptor) -> SymbolChanges {
+        let mut added = SymbolTable::default();
+        added.add_file(file);
+
+        let mut names = self.file_names(file.name());
+        names.extend(added.symbols.into_keys());
+        let before = self.kinds(names);
+        self.remove_symbols(file.name());
+        self.add_file(file);
+        self.changes(before)
+    }
+
+    /// Removes the names defined by a file.
+    ///
+    /// If a package was also declared by another file, it is kept and refers to that file instead. See
+    /// [`update_file`](SymbolTable::update_file).
+    pub fn remove_file(&mut self, name: &str) -> SymbolChanges {
+        let before = self.kinds(self.file_names(name));
+        self.remove_symbols(name);
+        self.changes(before)
+    }
+
+    /// Returns a copy of this table in which `name`, and every name defined within it, is replaced by `new_name`.
+    pub(crate) fn renamed(&self, name: &str, new_name: &str) -> SymbolTable {
+        let mut table = SymbolTable::default();
+        for symbol in self.symbols.values() {
+            let full_name = rename_prefix(&symbol.full_name, name, new_name)
+                .unwrap_or_else(|| symbol.full_name.clone());
+            if symbol.kind == SymbolKind::Package {
+                for file in &self.packages[&symbol.full_name] {
+                    table.add_package_name(&full_name, file);
+                }
+            } else {
+                table.add_name(full_name, symbol.kind, &symbol.file);
+            }
+        }
+        table
+    }
+
+    /// Adds the packages, messages and enums defined by a file which has not yet been added to a pool.
+    pub(crate) fn add_file_proto(&mut self, file: &FileDescriptorProto) {
+        fn add_message(
+            table: &mut SymbolTable,
+            file: &str,
+            scope: &str,
+            message: &DescriptorProto,
+        ) {
+            let full_name = join_name(scope, message.name());
+            for nested in &message.nested_type {
+                add_message(table, file, &full_name, nested);
+            }
+            for enum_ in &message.enum_type {
+                table.add_name(join_name(&full_name, enum_.name()), SymbolKind::Enum, file);
+            }
+            table.add_name(full_name, SymbolKind::Message, file);
+        }
+
+        let package = file.package();
+        if !package.is_empty() {
+            for (i, _) in package
+                .match_indices('.')
+                .chain(iter::once((package.len(), "")))
+            {
+                self.add_package_name(&package[..i], file.name());
+            }
+        }
+        for message in &file.message_type {
+            add_message(self, file.name(), package, message);
+        }
+        for enum_ in &file.enum_type {
+            self.add_name(
+                join_name(package, enum_.name()),
+                SymbolKind::Enum,
+                file.name(),
+            );
+        }
+    }
+
+    /// Gets the fully-qualified name a type name which cannot be resolved would refer to if it were defined.
+    ///
+    /// Like [`resolve_type_name`](SymbolTable::resolve_type_name), if the first component of the name is found in an
+    /// enclosing scope, the name is relative to it. Otherwise, a name with several components is assumed to be
+    /// fully-qualified, and a single identifier is assumed to be defined in `package`.
+    pub(crate) fn unresolved_type_name(&self, package: &str, scope: &str, name: &str) -> String {
+        if let Some(full_name) = name.strip_prefix('.') {
+            return full_name.to_owned();
+        }
+
+        let first_part = name.split('.').next().unwrap_or_default();
+        if first_part.len() == name.len() {
+            return join_name(package, name);
+        }
+
+        let candidate_parents = iter::once(scope)
+            .chain(scope.rmatch_indices('.').map(|(i, _)| &scope[..i]))
+            .filter(|parent| !parent.is_empty());
+        for parent in candidate_parents {
+            if self
+                .get(&join_name(parent, first_part))
+                .is_some_and(|symbol| symbol.kind.is_parent())
+            {
+                return join_name(parent, name);
+            }
+        }
+        name.to_owned()
+    }
+
+    fn add_name(&mut self, full_name: String, kind: SymbolKind, file: &str) {
+        self.files
+            .entry(file.to_owned())
+            .or_default()
+            .push(full_name.clone());
+        self.symbols.insert(
+            full_name.clone(),
+            Symbol {
+                full_name,
+                kind,
+                file: file.to_owned(),
+            },
+        );
+    }
+
+    fn add_file(&mut self, file: &FileDescriptor) {
+        let package = file.package_name();
+        if !package.is_empty() {
+            for (i, _) in package.match_indices('.') {
+                self.add_package(&package[..i], file);
+            }
+            self.add_package(package, file);
+        }
+
+        for message in file.messages() {
+            self.add_message(file, &message);
+        }
+        for enum_ in file.enums() {
+            self.add_enum(file, &enum_);
+        }
+        for extension in file.extensions() {
+            self.add(extension.full_name(), SymbolKind::Extension, file);
+        }
+        for service in file.services() {
+            self.add(service.full_name(), SymbolKind::Service, file);
+            for method in service.methods() {
+                self.add(method.full_name(), SymbolKind::Method, file);
+            }
+        }
+    }
+
+    fn add_message(&mut self, file: &FileDescriptor, message: &MessageDescriptor) {
+        self.add(message.full_name(), SymbolKind::Message, file);
+        for field in message.fields() {
+            self.add(field.full_name(), SymbolKind::Field, file);
+        }
+        for oneof in message.oneofs() {
+            self.add(oneof.full_name(), SymbolKind::Oneof, file);
+        }
+        for child in message.child_messages() {
+            self.add_message(file, &child);
+        }
+        for enum_ in message.child_enums() {
+            self.add_enum(file, &enum_);
+        }
+        for extension in message.child_extensions() {
+            self.add(extension.full_name(), SymbolKind::Extension, file);
+        }
+    }
+
+    fn add_enum(&mut self, file: &FileDescriptor, enum_: &EnumDescriptor) {
+        self.add(enum_.full_name(), SymbolKind::Enum, file);
+        for value in enum_.values() {
+            self.add(value.full_name(), SymbolKind::EnumValue, file);
+        }
+    }
+
+    fn add_package_name(&mut self, name: &str, file: &str) {
+        let files = self.packages.entry(name.to_owned()).or_default();
+        if !files.iter().any(|existing| existing == file) {
+            files.push(file.to_owned());
+        }
+        self.symbols
+            .entry(name.to_owned())
+            .or_insert_with(|| Symbol {
+                full_name: name.to_owned(),
+                kind: SymbolKind::Package,
+                file: file.to_owned(),
+            });
+    }
+
+    fn add_package(&mut self, name: &str, file: &FileDescriptor) {
+        self.add_package_name(name, file.name());
+    }
+
+    fn add(&mut self, full_name: &str, kind: SymbolKind, file: &FileDescriptor) {
+        self.add_name(full_name.to_owned(), kind, file.name());
+    }
+
+    /// Gets the names declared by a file, including the packages it declares.
+    fn file_names(&self, file: &str) -> BTreeSet<String> {
+        let names = self.files.get(file).into_iter().flatten();
+        let packages = self
+            .packages
+            .iter()
+            .filter(|(_, files)| files.iter().any(|existing| existing == file))
+            .map(|(package, _)| package);
+        names.chain(packages).cloned().collect()
+    }
+
+    /// Gets the current kind of each name, or `None` if it is not defined.
+    fn kinds(&self, names: BTreeSet<String>) -> BTreeMap<String, Option<SymbolKind>> {
+        names
+            .into_iter()
+            .map(|name| {
+                let kind = self.symbols.get(&name).map(|symbol| symbol.kind);
+                (name, kind)
+            })
+            .collect()
+    }
+
+    /// Gets the namespaces of the names whose kind differs from `before`.
+    fn changes(&self, before: BTreeMap<String, Option<SymbolKind>>) -> SymbolChanges {
+        SymbolChanges {
+            namespaces: before
+                .iter()
+                .filter(|(name, kind)| self.symbols.get(*name).map(|symbol| symbol.kind) != **kind)
+                .map(|(name, _)| namespace(name).to_owned())
+                .collect(),
+        }
+    }
+
+    fn remove_symbols(&mut self, file: &str) {
+        for name in self.files.remove(file).into_iter().flatten() {
+            if self
+                .symbols
+                .get(&name)
+                .is_some_and(|symbol| symbol.file == file)
+            {
+                self.symbols.remove(&name);
+            }
+        }
+
+        let symbols = &mut self.symbols;
+        self.packages.retain(|package, files| {
+            files.retain(|existing| existing != file);
+            match files.first() {
+                Some(first) => {
+                    if let Some(symbol) = symbols.get_mut(package) {
+                        if symbol.file == file {
+                            symbol.file.clone_from(first);
+                        }
+                    }
+                    true
+                }
+                None => {
+                    symbols.remove(package);
+                    false
+                }
+            }
+        });
+    }
+}
+
+impl SymbolChanges {
+    /// Returns `true` if no names were changed.
+    pub fn is_empty(&self) -> bool {
+        self.namespaces.is_empty()
+    }
+
+    /// Gets the namespaces which were changed, ordered by name.
+    pub fn namespaces(&self) -> impl Iterator<Item = &'_ str> {
+        self.namespaces.iter().map(String::as_str)
+    }
+
+    /// Returns `true` if resolving the type name `name` relative to `scope`, as by
+    /// [`SymbolTable::resolve_type_name`], may give a different result after these changes.
+    pub fn affects(&self, scope: &str, name: &str) -> bool {
+        if let Some(full_name) = name.strip_prefix('.') {
+            return self.namespaces.contains(namespace(full_name));
+        }
+
+        let prefix = name.rsplit_once('.').map(|(prefix, _)| prefix);
+        iter::once(scope)
+            .chain(scope.rmatch_indices('.').map(|(i, _)| &scope[..i]))
+            .chain(iter::once(""))
+            .any(|parent| {
+                self.namespaces.contains(parent)
+                    || prefix
+                        .is_some_and(|prefix| self.namespaces.contains(&join_name(parent, prefix)))
+            })
+    }
+}
+
+impl TypeReference {
+    /// Gets the name of the file containing the referencing element.
+    pub fn file(&self) -> &str {
+        &self.file
+    }
+
+    /// Gets the fully-qualified name of the referencing field, extension or method.
+    pub fn referrer(&self) -> &str {
+        &self.referrer
+    }
+
+    /// Gets the kind of reference.
+    pub fn kind(&self) -> ReferenceKind {
+        self.kind
+    }
+
+    /// Gets the symbol the type name resolved to.
+    pub fn target(&self) -> &Symbol {
+        &self.target
+    }
+}
+
+impl Location {
+    /// Gets the name of the file containing the reference.
+    pub fn file(&self) -> &str {
+        &self.file
+    }
+
+    /// Gets the span of the name as written in the source. For an extension used in an option, this excludes the
+    /// parentheses around its name.
+    pub fn span(&self) -> SourceSpan {
+        self.span
+    }
+
+    /// Gets the kind of element making the reference.
+    pub fn kind(&self) -> ReferenceKind {
+        self.kind
+    }
+}
+
+impl SyntheticOrigin {
+    /// Gets the generated map entry message or oneof.
+    pub fn synthetic(&self) -> &Symbol {
+        &self.synthetic
+    }
+
+    /// Gets the fully-qualified name of the field the definition was generated for.
+    pub fn field(&self) -> &str {
+        &self.field
+    }
+}
+
+/// Collects the type references made by all files in the pool, in file order.
+pub(crate) fn type_references(pool: &DescriptorPool) -> Vec<TypeReference> {
+    let mut references = Vec::new();
+    for file in pool.files() {
+        add_file_references(&mut references, &file);
+    }
+    references
+}
+
+/// Collects the type references made by a single file.
+pub(crate) fn file_type_references(file: &FileDescriptor) -> Vec<TypeReference> {
+    let mut references = Vec::new();
+    add_file_references(&mut references, file);
+    references
+}
+
+fn add_file_references(references: &mut Vec<TypeReference>, file: &FileDescriptor) {
+    for message in file.messages() {
+        add_message_references(references, file, &message);
+    }
+    for extension in file.extensions() {
+        add_extension_references(references, file, &extension);
+    }
+    for service in file.services() {
+        for method in service.methods() {
+            for (kind, target) in [
+                (ReferenceKind::MethodInput, method.input()),
+                (ReferenceKind::MethodOutput, method.output()),
+            ] {
+                references.push(TypeReference {
+                    file: file.name().to_owned(),
+                    referrer: method.full_name().to_owned(),
+                    kind,
+                    target: Symbol::from_message(&target),
+                });
+            }
+        }
+    }
+}
+
+fn add_message_references(
+    references: &mut Vec<TypeReference>,
+    file: &FileDescriptor,
+    message: &MessageDescriptor,
+) {
+    for field in message.fields() {
+        add_field_references(references, file, &field);
+    }
+    for extension in message.child_extensions() {
+        add_extension_references(references, file, &extension);
+    }
+    for child in message.child_messages() {
+        add_message_references(references, file, &child);
+    }
+}
+
+fn add_field_references(
+    references: &mut Vec<TypeReference>,
+    file: &FileDescriptor,
+    field: &FieldDescriptor,
+) {
+    if let Some(target) = Symbol::from_kind(field.kind()) {
+        references.push(TypeReference {
+            file: file.name().to_owned(),
+            referrer: field.full_name().to_owned(),
+            kind: ReferenceKind::FieldType,
+            target,
+        });
+    }
+}
+
+fn add_extension_references(
+    references: &mut Vec<TypeReference>,
+    file: &FileDescriptor,
+    extension: &ExtensionDescriptor,
+) {
+    references.push(TypeReference {
+        file: file.name().to_owned(),
+        referrer: extension.full_name().to_owned(),
+        kind: ReferenceKind::Extendee,
+        target: Symbol::from_message(&extension.containing_message()),
+    });
+    if let Some(target) = Symbol::from_kind(extension.kind()) {
+        references.push(TypeReference {
+            file: file.name().to_owned(),
+            referrer: extension.full_name().to_owned(),
+            kind: ReferenceKind::FieldType,
+            target,
+        });
+    }
+}
+
+/// Gets the options of the message, field, oneof, enum, enum value, extension, service or method with the given
+/// fully-qualified name.
+pub(crate) fn find_options(pool: &DescriptorPool, full_name: &str) -> Option<DynamicMessage> {
+    let full_name = full_name.strip_prefix('.').unwrap_or(full_name);
+    if let Some(message) = pool.get_message_by_name(full_name) {
+        return Some(message.options());
+    }
+    if let Some(enum_) = pool.get_enum_by_name(full_name) {
+        return Some(enum_.options());
+    }
+    if let Some(extension) = pool.get_extension_by_name(full_name) {
+        return Some(extension.options());
+    }
+    if let Some(service) = pool.get_service_by_name(full_name) {
+        return Some(service.options());
+    }
+
+    let (parent, name) = full_name.rsplit_once('.').unwrap_or(("", full_name));
+    if let Some(message) = pool.get_message_by_name(parent) {
+        if let Some(field) = message.get_field_by_name(name) {
+            return Some(field.options());
+        }
+        if let Some(oneof) = message.oneofs().find(|oneof| oneof.name() == name) {
+            return Some(oneof.options());
+        }
+    }
+    if let Some(service) = pool.get_service_by_name(parent) {
+        if let Some(method) = service.methods().find(|method| method.name() == name) {
+            return Some(method.options());
+        }
+    }
+    pool.all_enums()
+        .filter_map(|enum_| enum_.get_value_by_name(name))
+        .find(|value| value.full_name() == full_name)
+        .map(|value| value.options())
+}
+
+/// Gets the value of an option, which may be a field of the options message such as `deprecated`, or the full name of
+/// an extension, optionally in parentheses.
+pub(crate) fn find_option_value(options: &DynamicMessage, option: &str) -> Option<Value> {
+    let option = option
+        .strip_prefix('(')
+        .and_then(|option| option.strip_suffix(')'))
+        .unwrap_or(option);
+    let option = option.strip_prefix('.').unwrap_or(option);
+
+    let descriptor = options.descriptor();
+    if let Some(field) = descriptor.get_field_by_name(option) {
+        return options
+            .has_field(&field)
+            .then(|| options.get_field(&field).into_owned());
+    }
+    let extension = descriptor.parent_pool().get_extension_by_name(option)?;
+    options
+        .has_extension(&extension)
+        .then(|| options.get_extension(&extension).into_owned())
+}
+
+/// Collects the map entry messages and synthetic oneofs generated in all files in the pool, in file order.
+pub(crate) fn synthetic_origins(pool: &DescriptorPool) -> Vec<SyntheticOrigin> {
+    let mut origins = Vec::new();
+    for file in pool.files() {
+        for message in file.messages() {
+            add_synthetic_origins(&mut origins, &message);
+        }
+    }
+    origins
+}
+
+fn add_synthetic_origins(origins: &mut Vec<SyntheticOrigin>, message: &MessageDescriptor) {
+    for field in message.fields() {
+        if let (true, Kind::Message(entry)) = (field.is_map(), field.kind()) {
+            origins.push(SyntheticOrigin {
+                synthetic: Symbol::from_message(&entry),
+                field: field.full_name().to_owned(),
+            });
+        }
+    }
+    for field in message.fields() {
+        if let (true, Some(oneof)) = (
+            field.field_descriptor_proto().proto3_optional(),
+            field.containing_oneof(),
+        ) {
+            origins.push(SyntheticOrigin {
+                synthetic: Symbol {
+                    full_name: oneof.full_name().to_owned(),
+                    kind: SymbolKind::Oneof,
+                    file: message.parent_file().name().to_owned(),
+                },
+                field: field.full_name().to_owned(),
+            });
+        }
+    }
+    for child in message.child_messages() {
+        add_synthetic_origins(origins, &child);
+    }
+}
+
+/// If `full_name` is `name` or is defined within it, returns the name it would have if `name` were replaced by
+/// `new_name`.
+pub(crate) fn rename_prefix(full_name: &str, name: &str, new_name: &str) -> Option<String> {
+    let rest = full_name.strip_prefix(name)?;
+    if rest.is_empty() || rest.starts_with('.') {
+        Some(format!("{}{}", new_name, rest))
+    } else {
+        None
+    }
+}
+
+/// Gets the namespace containing a fully-qualified name.
+fn namespace(full_name: &str) -> &str {
+    full_name
+        .rsplit_once('.')
+        .map_or("", |(namespace, _)| namespace)
+}
+
+pub(crate) fn join_name(scope: &str, name: &str) -> String {
+    if scope.is_empty() {
+        name.to_owned()
+    } else {
+        format!("{}.{}", scope, name)
+    }
+}
@@ -0,0 +1,106 @@
+//! Utilities for testing tools built on protox.
+//!
+//! This module is only available if the `test-util` feature is enabled.
+//!
+//! Each function takes a list of files as `(name, source)` pairs, which are served from memory by an
+//! [`OverlayFileResolver`], with the well-known types available through [`GoogleFileResolver`]. Every file in the
+//! list is opened in order, so a test can list the dependencies of a file before it, or just the one file under test.
+//!
+//! Errors are compared using their [`Debug`](std::fmt::Debug) representation, of the form
+//! `file.proto:line:column: message`, which makes a compact golden value for negative tests. The full error is
+//! returned as well, so the [`Diagnostic`](miette::Diagnostic) labels and help text can also be checked.
+//!
+//! # Examples
+//!
+//! ```
+//! use protox::testing::{assert_compiles, assert_error_matches};
+//!
+//! let compiler = assert_compiles(&[
+//!     ("dep.proto", "package dep; message Dep {}"),
+//!     ("root.proto", "import 'dep.proto'; message Root { optional dep.Dep dep = 1; }"),
+//! ]);
+//! assert!(compiler.descriptor_pool().get_message_by_name("Root").is_some());
+//!
+//! assert_error_matches(
+//!     &[("root.proto", "message Root {\n  optional Missing field = 1;\n}\n")],
+//!     "root.proto:2:12: name 'Missing' is not defined",
+//! );
+//! ```
+
+#[cfg(test)]
+mod tests;
+
+use crate::{
+    file::{GoogleFileResolver, OverlayFileResolver},
+    Compiler, Error,
+};
+
+/// Creates a [`Compiler`] which resolves the given files from memory, without opening any of them.
+///
+/// Source info is included in the output, so that it can be compared with golden descriptors.
+pub fn compiler(files: &[(&str, &str)]) -> Compiler {
+    let mut resolver = OverlayFileResolver::new(GoogleFileResolver::new());
+    for &(name, source) in files {
+        resolver.set_file(name, source);
+    }
+
+    let mut compiler = Compiler::with_file_resolver(resolver);
+    compiler.include_source_info(true);
+    compiler
+}
+
+/// Compiles the given files, returning the first error.
+pub fn compile(files: &[(&str, &str)]) -> Result<Compiler, Error> {
+    let mut compiler = compiler(files);
+    for &(name, _) in files {
+        compiler.open_file(name)?;
+    }
+    Ok(compiler)
+}
+
+/// Compiles the given files, returning the compiler so that the results and any warnings can be inspected.
+///
+/// # Panics
+///
+/// Panics if any of the files fails to compile.
+#[track_caller]
+pub fn assert_compiles(files: &[(&str, &str)]) -> Compiler {
+    match compile(files) {
+        Ok(compiler) => compiler,
+        Err(err) => panic!("expected files to compile, but got error: {:?}", err),
+    }
+}
+
+/// Compiles the given files, returning the first error.
+///
+/// # Panics
+///
+/// Panics if all of the files compile successfully.
+#[track_caller]
+pub fn assert_error(files: &[(&str, &str)]) -> Error {
+    match compile(files) {
+        Ok(_) => panic!("expected an error, but the files compiled successfully"),
+        Err(err) => err,
+    }
+}
+
+/// Compiles the given files, and checks that the first error matches `expected`.
+///
+/// `expected` is compared with the error's [`Debug`](std::fmt::Debug) representation, such as
+/// `root.proto:2:12: name 'Missing' is not defined`, or, if it has no location, with its message.
+///
+/// # Panics
+///
+/// Panics if all of the files compile successfully, or if the error does not match.
+#[track_caller]
+pub fn assert_error_matches(files: &[(&str, &str)], expected: &str) -> Error {
+    let err = assert_error(files);
+    let actual = format!("{:?}", err);
+    if actual != expected && err.to_string() != expected {
+        panic!(
+            "error does not match\n  expected: {}\n    actual: {}",
+            expected, actual
+        );
+    }
+    err
+}
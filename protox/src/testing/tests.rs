@@ -0,0 +1,64 @@
+use super::{assert_compiles, assert_error, assert_error_matches, compile};
+
+#[test]
+fn compiles_with_imports() {
+    let compiler = assert_compiles(&[
+        (
+            "dep.proto",
+            "syntax = 'proto3'; package dep; import 'google/protobuf/timestamp.proto'; message Dep { google.protobuf.Timestamp time = 1; }",
+        ),
+        (
+            "root.proto",
+            "syntax = 'proto3'; import 'dep.proto'; message Root { dep.Dep dep = 1; }",
+        ),
+    ]);
+
+    let names: Vec<_> = compiler
+        .file_descriptor_set()
+        .file
+        .into_iter()
+        .map(|file| file.name().to_owned())
+        .collect();
+    assert_eq!(names, ["dep.proto", "root.proto"]);
+}
+
+#[test]
+fn error_matches_debug_or_message() {
+    let files = &[
+        ("dep.proto", "message Dep {}"),
+        ("root.proto", "import 'dep.proto';\nmessage Dep {}\n"),
+    ];
+    let err = assert_error_matches(
+        files,
+        "root.proto:2:9: name 'Dep' is already defined in file 'dep.proto'",
+    );
+    assert_eq!(err.file(), Some("root.proto"));
+    assert_error_matches(files, "name 'Dep' is already defined in file 'dep.proto'");
+
+    assert_error_matches(
+        &[("root.proto", "import 'missing.proto';")],
+        "import 'missing.proto' not found",
+    );
+}
+
+#[test]
+#[should_panic(expected = "error does not match")]
+fn error_does_not_match() {
+    assert_error_matches(&[("root.proto", "message {}")], "root.proto:1:1: unknown");
+}
+
+#[test]
+#[should_panic(expected = "expected an error")]
+fn error_not_found() {
+    assert_error(&[("root.proto", "message Foo {}")]);
+}
+
+#[test]
+fn compile_returns_first_error() {
+    let err = compile(&[
+        ("a.proto", "message A { optional B b = 1; }"),
+        ("b.proto", "message B { optional A a = 1; }"),
+    ])
+    .unwrap_err();
+    assert_eq!(err.file(), Some("a.proto"));
+}
@@ -0,0 +1,359 @@
+//! Validation of protovalidate and protoc-gen-validate constraint options.
+//!
+//! This module is only available if the `validate` feature is enabled, which also allows
+//! [`GoogleFileResolver`](crate::file::GoogleFileResolver) to resolve `buf/validate/validate.proto`, used by
+//! [protovalidate](https://github.com/bufbuild/protovalidate), and the legacy `validate/validate.proto`, used by
+//! [protoc-gen-validate](https://github.com/bufbuild/protoc-gen-validate).
+//!
+//! Both libraries only report invalid constraints when validation code is generated or run. Registering
+//! [`ValidateCheck`] with [`Compiler::add_check`](crate::Compiler::add_check) reports them while compiling instead. It
+//! checks that:
+//!
+//! - the rules set in `(buf.validate.field)` and `(validate.rules)` options match the type of the field, for example
+//!   that `string` rules are only used on `string` fields or `google.protobuf.StringValue` wrappers, and `repeated`
+//!   and `map` rules are only used on repeated and map fields,
+//! - the same holds for the `items` of `repeated` rules, and the `keys` and `values` of `map` rules,
+//! - minimum lengths and counts, such as `min_len` or `min_items`, are not greater than the corresponding maximum,
+//! - the `oneof` rules of `(buf.validate.message)` options refer to fields of the message.
+//!
+//! # Examples
+//!
+//! ```
+//! # use protox::{file::{ChainFileResolver, File, FileResolver, GoogleFileResolver}, validate::ValidateCheck, Compiler};
+//! # struct Resolver;
+//! # impl FileResolver for Resolver {
+//! #     fn open_file(&self, name: &str) -> Result<File, protox::Error> {
+//! #         match name {
+//! #             "user.proto" => File::from_source(name, "
+//! syntax = 'proto3';
+//! import 'buf/validate/validate.proto';
+//!
+//! message User {
+//!   int64 id = 1 [(buf.validate.field).string.min_len = 1];
+//! }
+//! #             "),
+//! #             _ => Err(protox::Error::file_not_found(name)),
+//! #         }
+//! #     }
+//! # }
+//! # let mut resolver = ChainFileResolver::new();
+//! # resolver.add(Resolver);
+//! # resolver.add(GoogleFileResolver::new());
+//! let mut compiler = Compiler::with_file_resolver(resolver);
+//! compiler.add_check(ValidateCheck::new());
+//!
+//! let err = compiler.open_file("user.proto").unwrap_err();
+//! assert_eq!(
+//!     err.to_string(),
+//!     "'string' rules cannot be used on field 'User.id' of type 'int64'",
+//! );
+//! ```
+
+use prost_reflect::{
+    DynamicMessage, ExtensionDescriptor, FieldDescriptor, FileDescriptor, Kind, MessageDescriptor,
+    ReflectMessage, Value,
+};
+use protox_parse::tag;
+
+use crate::check::{CustomCheck, Diagnostics};
+
+#[cfg(test)]
+mod tests;
+
+/// The full names of the field constraint extensions of protovalidate and protoc-gen-validate.
+const FIELD_EXTENSIONS: &[&str] = &["buf.validate.field", "validate.rules"];
+/// The full name of the message constraint extension of protovalidate.
+const MESSAGE_EXTENSION: &str = "buf.validate.message";
+/// Pairs of rules whose first value must not be greater than the second.
+const MIN_MAX_RULES: &[(&str, &str)] = &[
+    ("min_len", "max_len"),
+    ("min_bytes", "max_bytes"),
+    ("min_items", "max_items"),
+    ("min_pairs", "max_pairs"),
+];
+
+/// A [`CustomCheck`] which validates protovalidate and protoc-gen-validate constraint options.
+///
+/// See the [module documentation](self) for details.
+#[derive(Debug, Default, Clone)]
+pub struct ValidateCheck {
+    _priv: (),
+}
+
+/// The shape of the values constrained by a set of rules.
+enum Target {
+    Single(Kind),
+    List(Kind),
+    Map(Kind, Kind),
+}
+
+impl ValidateCheck {
+    /// Creates a new instance of [`ValidateCheck`].
+    pub fn new() -> Self {
+        Default::default()
+    }
+}
+
+impl CustomCheck for ValidateCheck {
+    fn check_file(&self, file: &FileDescriptor, diagnostics: &mut Diagnostics<'_>) {
+        let pool = file.parent_pool();
+        let field_extensions: Vec<ExtensionDescriptor> = FIELD_EXTENSIONS
+            .iter()
+            .filter_map(|name| pool.get_extension_by_name(name))
+            .collect();
+        let message_extension = pool.get_extension_by_name(MESSAGE_EXTENSION);
+        if field_extensions.is_empty() && message_extension.is_none() {
+            return;
+        }
+
+        let mut messages: Vec<_> = file.messages().collect();
+        while let Some(message) = messages.pop() {
+            if let Some(extension) = &message_extension {
+                check_message_rules(&message, extension, diagnostics);
+            }
+
+            for field in message.fields() {
+                let options = field.options();
+                for extension in &field_extensions {
+                    if !options.has_extension(extension) {
+                        continue;
+                    }
+                    if let Some(rules) = options.get_extension(extension).as_message() {
+                        let mut path = field.path().to_vec();
+                        path.extend([tag::field::OPTIONS, extension.number() as i32]);
+                        let mut checker = RuleChecker {
+                            field: &field,
+                            path: &path,
+                            diagnostics,
+                        };
+                        checker.check_rules(rules, Target::of(&field), None);
+                    }
+                }
+            }
+
+            messages.extend(message.child_messages());
+        }
+    }
+}
+
+fn check_message_rules(
+    message: &MessageDescriptor,
+    extension: &ExtensionDescriptor,
+    diagnostics: &mut Diagnostics<'_>,
+) {
+    let options = message.options();
+    if !options.has_extension(extension) {
+        return;
+    }
+    let Some(rules) = options.get_extension(extension).as_message().cloned() else {
+        return;
+    };
+    let Some(Value::List(oneofs)) = rules.get_field_by_name("oneof").as_deref().cloned() else {
+        return;
+    };
+
+    let mut path = message.path().to_vec();
+    path.extend([tag::message::OPTIONS, extension.number() as i32]);
+    for oneof in oneofs.iter().filter_map(Value::as_message) {
+        let Some(Value::List(fields)) = oneof.get_field_by_name("fields").as_deref().cloned()
+        else {
+            continue;
+        };
+        for name in fields.iter().filter_map(Value::as_str) {
+            if message.get_field_by_name(name).is_none() {
+                diagnostics
+                    .error(
+                        &path,
+                        format!(
+                            "oneof rule of message '{}' refers to unknown field '{}'",
+                            message.full_name(),
+                            name
+                        ),
+                    )
+                    .set_help("the fields of a oneof rule must be names of fields of the message");
+            }
+        }
+    }
+}
+
+impl Target {
+    fn of(field: &FieldDescriptor) -> Self {
+        match field.kind() {
+            Kind::Message(entry) if field.is_map() => Target::Map(
+                entry.map_entry_key_field().kind(),
+                entry.map_entry_value_field().kind(),
+            ),
+            kind if field.is_list() => Target::List(kind),
+            kind => Target::Single(kind),
+        }
+    }
+
+    /// Gets the name of the rules which may be applied to this target.
+    fn rules_name(&self) -> Option<&'static str> {
+        match self {
+            Target::Single(kind) => match kind {
+                Kind::Message(message) => match message.full_name() {
+                    "google.protobuf.Any" => Some("any"),
+                    "google.protobuf.Duration" => Some("duration"),
+                    "google.protobuf.FieldMask" => Some("field_mask"),
+                    "google.protobuf.Timestamp" => Some("timestamp"),
+                    "google.protobuf.DoubleValue" => Some("double"),
+                    "google.protobuf.FloatValue" => Some("float"),
+                    "google.protobuf.Int64Value" => Some("int64"),
+                    "google.protobuf.UInt64Value" => Some("uint64"),
+                    "google.protobuf.Int32Value" => Some("int32"),
+                    "google.protobuf.UInt32Value" => Some("uint32"),
+                    "google.protobuf.BoolValue" => Some("bool"),
+                    "google.protobuf.StringValue" => Some("string"),
+                    "google.protobuf.BytesValue" => Some("bytes"),
+                    _ => None,
+                },
+                Kind::Enum(_) => Some("enum"),
+                kind => Some(scalar_name(kind)),
+            },
+            Target::List(_) => Some("repeated"),
+            Target::Map(_, _) => Some("map"),
+        }
+    }
+
+    fn type_name(&self) -> String {
+        match self {
+            Target::Single(kind) => kind_name(kind),
+            Target::List(kind) => format!("repeated {}", kind_name(kind)),
+            Target::Map(key, value) => format!("map<{}, {}>", kind_name(key), kind_name(value)),
+        }
+    }
+}
+
+struct RuleChecker<'a, 'b> {
+    field: &'a FieldDescriptor,
+    path: &'a [i32],
+    diagnostics: &'a mut Diagnostics<'b>,
+}
+
+impl RuleChecker<'_, '_> {
+    /// Checks the rules for a field, or for the items, keys or values of a field if `part` is set.
+    fn check_rules(&mut self, rules: &DynamicMessage, target: Target, part: Option<&str>) {
+        let Some(type_oneof) = rules
+            .descriptor()
+            .oneofs()
+            .find(|oneof| oneof.name() == "type")
+        else {
+            return;
+        };
+        let Some(rule) = type_oneof.fields().find(|field| rules.has_field(field)) else {
+            return;
+        };
+
+        let description = match part {
+            Some(part) => format!("the {} of field '{}'", part, self.field.full_name()),
+            None => format!("field '{}'", self.field.full_name()),
+        };
+
+        let expected = target.rules_name();
+        if expected != Some(rule.name()) {
+            let help = match expected {
+                Some(expected) => format!("use '{}' rules instead", expected),
+                None => "only rules which apply to all fields, such as 'required', may be used on this field"
+                    .to_owned(),
+            };
+            self.diagnostics
+                .error(
+                    self.path,
+                    format!(
+                        "'{}' rules cannot be used on {} of type '{}'",
+                        rule.name(),
+                        description,
+                        target.type_name()
+                    ),
+                )
+                .set_help(help);
+            return;
+        }
+
+        let rule_value = rules.get_field(&rule);
+        let Some(rule_value) = rule_value.as_message() else {
+            return;
+        };
+        for (min, max) in MIN_MAX_RULES {
+            if let (Some(min_value), Some(max_value)) =
+                (get_u64(rule_value, min), get_u64(rule_value, max))
+            {
+                if min_value > max_value {
+                    self.diagnostics.error(
+                        self.path,
+                        format!(
+                            "'{}' ({}) is greater than '{}' ({}) for {}",
+                            min, min_value, max, max_value, description
+                        ),
+                    );
+                }
+            }
+        }
+
+        match target {
+            Target::List(kind) => {
+                if let Some(items) = get_message(rule_value, "items") {
+                    self.check_rules(&items, Target::Single(kind), Some("items"));
+                }
+            }
+            Target::Map(key, value) => {
+                if let Some(keys) = get_message(rule_value, "keys") {
+                    self.check_rules(&keys, Target::Single(key), Some("keys"));
+                }
+                if let Some(values) = get_message(rule_value, "values") {
+                    self.check_rules(&values, Target::Single(value), Some("values"));
+                }
+            }
+            Target::Single(_) => (),
+        }
+    }
+}
+
+fn get_u64(message: &DynamicMessage, name: &str) -> Option<u64> {
+    let field = message.descriptor().get_field_by_name(name)?;
+    if message.has_field(&field) {
+        message.get_field(&field).as_u64()
+    } else {
+        None
+    }
+}
+
+fn get_message(message: &DynamicMessage, name: &str) -> Option<DynamicMessage> {
+    let field = message.descriptor().get_field_by_name(name)?;
+    if message.has_field(&field) {
+        message.get_field(&field).as_message().cloned()
+    } else {
+        None
+    }
+}
+
+fn kind_name(kind: &Kind) -> String {
+    match kind {
+        Kind::Message(message) => message.full_name().to_owned(),
+        Kind::Enum(enum_) => enum_.full_name().to_owned(),
+        kind => scalar_name(kind).to_owned(),
+    }
+}
+
+fn scalar_name(kind: &Kind) -> &'static str {
+    match kind {
+        Kind::Double => "double",
+        Kind::Float => "float",
+        Kind::Int32 => "int32",
+        Kind::Int64 => "int64",
+        Kind::Uint32 => "uint32",
+        Kind::Uint64 => "uint64",
+        Kind::Sint32 => "sint32",
+        Kind::Sint64 => "sint64",
+        Kind::Fixed32 => "fixed32",
+        Kind::Fixed64 => "fixed64",
+        Kind::Sfixed32 => "sfixed32",
+        Kind::Sfixed64 => "sfixed64",
+        Kind::Bool => "bool",
+        Kind::String => "string",
+        Kind::Bytes => "bytes",
+        Kind::Message(_) => "message",
+        Kind::Enum(_) => "enum",
+    }
+}
@@ -0,0 +1,135 @@
+use super::ValidateCheck;
+use crate::{
+    file::{ChainFileResolver, File, FileResolver, GoogleFileResolver},
+    Compiler, Error,
+};
+
+struct TestFileResolver {
+    source: String,
+}
+
+impl FileResolver for TestFileResolver {
+    fn open_file(&self, name: &str) -> Result<File, Error> {
+        if name == "root.proto" {
+            File::from_source(name, &self.source)
+        } else {
+            Err(Error::file_not_found(name))
+        }
+    }
+}
+
+fn check(import: &str, body: &str) -> Result<(), String> {
+    let source = format!(
+        "syntax = 'proto3';
+        import '{}';
+        import 'google/protobuf/duration.proto';
+        import 'google/protobuf/field_mask.proto';
+        import 'google/protobuf/wrappers.proto';
+
+        enum Status {{ STATUS_UNSPECIFIED = 0; }}
+
+        message Foo {{
+          {}
+        }}",
+        import, body
+    );
+
+    let mut resolver = ChainFileResolver::new();
+    resolver.add(TestFileResolver { source });
+    resolver.add(GoogleFileResolver::new());
+
+    let mut compiler = Compiler::with_file_resolver(resolver);
+    compiler.add_check(ValidateCheck::new());
+    match compiler.open_file("root.proto") {
+        Ok(_) => Ok(()),
+        Err(err) => Err(err.to_string()),
+    }
+}
+
+fn protovalidate(body: &str) -> Result<(), String> {
+    check("buf/validate/validate.proto", body)
+}
+
+fn pgv(body: &str) -> Result<(), String> {
+    check("validate/validate.proto", body)
+}
+
+#[test]
+fn valid_constraints() {
+    protovalidate(
+        "string name = 1 [(buf.validate.field).string = { min_len: 1, max_len: 10 }];
+        int32 count = 2 [(buf.validate.field).int32.gt = 0];
+        Status status = 3 [(buf.validate.field).enum.defined_only = true];
+        repeated string tags = 4 [(buf.validate.field).repeated = { max_items: 5, items: { string: { min_len: 1 } } }];
+        map<string, int64> counts = 5 [(buf.validate.field).map = { keys: { string: { min_len: 1 } }, values: { int64: { gte: 0 } } }];
+        google.protobuf.Duration timeout = 6 [(buf.validate.field).duration.gt = { seconds: 1 }];
+        google.protobuf.StringValue nickname = 7 [(buf.validate.field).string.max_len = 20];
+        Foo parent = 8 [(buf.validate.field).required = true];
+        google.protobuf.FieldMask mask = 9 [(buf.validate.field).field_mask.in = 'a'];
+        option (buf.validate.message).oneof = { fields: ['name', 'count'] };",
+    )
+    .unwrap();
+
+    pgv(
+        "string name = 1 [(validate.rules).string = { min_len: 1, max_len: 10 }];
+        repeated uint32 ids = 2 [(validate.rules).repeated.items.uint32.lt = 100];
+        Foo parent = 3 [(validate.rules).message.required = true];",
+    )
+    .unwrap();
+}
+
+#[test]
+fn invalid_constraints() {
+    assert_eq!(
+        protovalidate("int64 id = 1 [(buf.validate.field).string.min_len = 1];").unwrap_err(),
+        "'string' rules cannot be used on field 'Foo.id' of type 'int64'"
+    );
+    assert_eq!(
+        protovalidate("repeated string tags = 1 [(buf.validate.field).string.min_len = 1];")
+            .unwrap_err(),
+        "'string' rules cannot be used on field 'Foo.tags' of type 'repeated string'"
+    );
+    assert_eq!(
+        protovalidate(
+            "repeated string tags = 1 [(buf.validate.field).repeated.items.int32.gt = 1];"
+        )
+        .unwrap_err(),
+        "'int32' rules cannot be used on the items of field 'Foo.tags' of type 'string'"
+    );
+    assert_eq!(
+        protovalidate(
+            "map<string, Status> m = 1 [(buf.validate.field).map.values.string.min_len = 1];"
+        )
+        .unwrap_err(),
+        "'string' rules cannot be used on the values of field 'Foo.m' of type 'Status'"
+    );
+    assert_eq!(
+        protovalidate("Foo parent = 1 [(buf.validate.field).string.min_len = 1];").unwrap_err(),
+        "'string' rules cannot be used on field 'Foo.parent' of type 'Foo'"
+    );
+    assert_eq!(
+        protovalidate(
+            "string name = 1 [(buf.validate.field).string = { min_len: 5, max_len: 1 }];"
+        )
+        .unwrap_err(),
+        "'min_len' (5) is greater than 'max_len' (1) for field 'Foo.name'"
+    );
+    assert_eq!(
+        protovalidate(
+            "string name = 1;
+            option (buf.validate.message).oneof = { fields: ['name', 'missing'] };"
+        )
+        .unwrap_err(),
+        "oneof rule of message 'Foo' refers to unknown field 'missing'"
+    );
+
+    assert_eq!(
+        pgv("bool flag = 1 [(validate.rules).int32.gt = 1];").unwrap_err(),
+        "'int32' rules cannot be used on field 'Foo.flag' of type 'bool'"
+    );
+    assert_eq!(
+        pgv("repeated int32 ids = 1 [(validate.rules).repeated = { min_items: 3, max_items: 2 }];")
+            .unwrap_err(),
+        "'min_items' (3) is greater than 'max_items' (2) for field 'Foo.ids'"
+    );
+}
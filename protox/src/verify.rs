@@ -0,0 +1,340 @@
+//! Utilities for checking that protox produces the same output as `protoc`.
+//!
+//! See the documentation for [`compare_with_protoc()`] for details.
+
+use std::{
+    env, fmt, fs,
+    path::{Path, PathBuf},
+    process::Command,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use prost_reflect::{DescriptorPool, DynamicMessage, MapKey, ReflectMessage, Value};
+use prost_types::{source_code_info::Location, FileDescriptorSet};
+
+use crate::{Compiler, Error};
+
+/// A difference between the file descriptors produced by `protoc` and by protox.
+///
+/// Returned by [`compare_with_protoc()`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Divergence {
+    file: String,
+    path: String,
+    expected: Option<String>,
+    actual: Option<String>,
+}
+
+impl Divergence {
+    /// Gets the name of the file whose descriptors differ.
+    pub fn file(&self) -> &str {
+        &self.file
+    }
+
+    /// Gets the path to the differing field within the file's [`FileDescriptorProto`](prost_types::FileDescriptorProto),
+    /// such as `message_type[0].field[1].json_name`.
+    ///
+    /// This is empty if the whole file is missing from one of the outputs.
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Gets the value produced by `protoc`, formatted in the protobuf text format, or `None` if it is not set.
+    pub fn expected(&self) -> Option<&str> {
+        self.expected.as_deref()
+    }
+
+    /// Gets the value produced by protox, formatted in the protobuf text format, or `None` if it is not set.
+    pub fn actual(&self) -> Option<&str> {
+        self.actual.as_deref()
+    }
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.file)?;
+        if !self.path.is_empty() {
+            write!(f, ": {}", self.path)?;
+        }
+        write!(
+            f,
+            ": protoc produced {}, but protox produced {}",
+            self.expected.as_deref().unwrap_or("nothing"),
+            self.actual.as_deref().unwrap_or("nothing"),
+        )
+    }
+}
+
+/// Compiles a set of protobuf files with both `protoc` and protox, and returns the differences between the
+/// resulting file descriptors.
+///
+/// The files are compiled with imports and source info included, as with [`compile()`](crate::compile()) and
+/// `protoc --include_imports --include_source_info`. Extension options are compared too.
+///
+/// Some differences are not considered significant, and are not reported:
+///
+/// - The order of [`Location`](prost_types::source_code_info::Location)s in the source code info.
+/// - The formatting of floating-point default values, such as `1e3` and `1000`.
+///
+/// # Errors
+///
+/// Returns an error if either compiler fails. The error from protox is returned as-is, while a failure of
+/// `protoc` is reported with the contents of its standard error output.
+///
+/// # Examples
+///
+/// ```no_run
+/// # use protox::verify::compare_with_protoc;
+/// # fn main() -> Result<(), protox::Error> {
+/// let divergences = compare_with_protoc("protoc", ["root.proto"], ["."])?;
+/// for divergence in &divergences {
+///     eprintln!("{}", divergence);
+/// }
+/// assert!(divergences.is_empty());
+/// # Ok(())
+/// # }
+/// ```
+pub fn compare_with_protoc(
+    protoc: impl AsRef<Path>,
+    files: impl IntoIterator<Item = impl AsRef<Path>>,
+    includes: impl IntoIterator<Item = impl AsRef<Path>>,
+) -> Result<Vec<Divergence>, Error> {
+    let files: Vec<PathBuf> = files.into_iter().map(|f| f.as_ref().to_owned()).collect();
+    let includes: Vec<PathBuf> = includes
+        .into_iter()
+        .map(|i| i.as_ref().to_owned())
+        .collect();
+
+    let actual = Compiler::new(&includes)?
+        .include_imports(true)
+        .include_source_info(true)
+        .open_files(&files)?
+        .encode_file_descriptor_set();
+    let expected = run_protoc(protoc.as_ref(), &files, &includes)?;
+
+    // Decode both outputs using the descriptors produced by protox, so that any extension options are known.
+    let pool = DescriptorPool::decode(actual.as_slice()).map_err(Error::new)?;
+    let set_desc = pool
+        .get_message_by_name(FileDescriptorSet::default().descriptor().full_name())
+        .unwrap_or_else(|| FileDescriptorSet::default().descriptor());
+
+    let expected =
+        DynamicMessage::decode(set_desc.clone(), expected.as_slice()).map_err(Error::new)?;
+    let actual = DynamicMessage::decode(set_desc, actual.as_slice()).map_err(Error::new)?;
+
+    let expected_files = normalized_files(&expected);
+    let actual_files = normalized_files(&actual);
+
+    let mut divergences = Vec::new();
+    for (name, expected_file) in &expected_files {
+        match actual_files.iter().find(|(n, _)| n == name) {
+            Some((_, actual_file)) => {
+                let mut ctx = DiffContext {
+                    file: name,
+                    path: String::new(),
+                    divergences: &mut divergences,
+                };
+                ctx.diff_messages(expected_file, actual_file);
+            }
+            None => divergences.push(Divergence {
+                file: name.clone(),
+                path: String::new(),
+                expected: Some(format!("file '{}'", name)),
+                actual: None,
+            }),
+        }
+    }
+    for (name, _) in &actual_files {
+        if !expected_files.iter().any(|(n, _)| n == name) {
+            divergences.push(Divergence {
+                file: name.clone(),
+                path: String::new(),
+                expected: None,
+                actual: Some(format!("file '{}'", name)),
+            });
+        }
+    }
+
+    Ok(divergences)
+}
+
+fn run_protoc(protoc: &Path, files: &[PathBuf], includes: &[PathBuf]) -> Result<Vec<u8>, Error> {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+    let output_path = env::temp_dir().join(format!(
+        "protox-verify-{}-{}.bin",
+        std::process::id(),
+        COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+
+    let mut command = Command::new(protoc);
+    for include in includes {
+        command.arg("--proto_path").arg(include);
+    }
+    let output = command
+        .arg("--include_imports")
+        .arg("--include_source_info")
+        .arg(format!("--descriptor_set_out={}", output_path.display()))
+        .args(files)
+        .output()?;
+
+    if !output.status.success() {
+        let _ = fs::remove_file(&output_path);
+        return Err(Error::new(format!(
+            "protoc failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+
+    let bytes = fs::read(&output_path);
+    let _ = fs::remove_file(&output_path);
+    Ok(bytes?)
+}
+
+/// Gets the files of a file descriptor set by name, with source locations in a consistent order.
+fn normalized_files(file_set: &DynamicMessage) -> Vec<(String, DynamicMessage)> {
+    let files = match file_set.get_field_by_name("file").as_deref() {
+        Some(Value::List(files)) => files.clone(),
+        _ => return Vec::new(),
+    };
+
+    files
+        .into_iter()
+        .filter_map(|file| match file {
+            Value::Message(file) => Some(file),
+            _ => None,
+        })
+        .map(|mut file| {
+            let name = file
+                .get_field_by_name("name")
+                .and_then(|name| name.as_str().map(ToOwned::to_owned))
+                .unwrap_or_default();
+
+            if let Some(Value::Message(source_code_info)) =
+                file.get_field_by_name_mut("source_code_info")
+            {
+                if let Some(Value::List(locations)) =
+                    source_code_info.get_field_by_name_mut("location")
+                {
+                    locations.sort_by_cached_key(|location| {
+                        let location = location
+                            .as_message()
+                            .and_then(|location| location.transcode_to::<Location>().ok())
+                            .unwrap_or_default();
+                        (location.path, location.span)
+                    });
+                }
+            }
+
+            (name, file)
+        })
+        .collect()
+}
+
+struct DiffContext<'a> {
+    file: &'a str,
+    path: String,
+    divergences: &'a mut Vec<Divergence>,
+}
+
+impl<'a> DiffContext<'a> {
+    fn diff_messages(&mut self, expected: &DynamicMessage, actual: &DynamicMessage) {
+        for field in expected.descriptor().fields() {
+            let expected_value = expected
+                .has_field(&field)
+                .then(|| expected.get_field(&field));
+            let actual_value = actual.has_field(&field).then(|| actual.get_field(&field));
+
+            if field.name() == "default_value" && is_float_default(expected, actual) {
+                continue;
+            }
+
+            self.with_path(field.name(), |ctx| {
+                ctx.diff_values(expected_value.as_deref(), actual_value.as_deref())
+            });
+        }
+
+        let mut extensions: Vec<_> = expected.extensions().map(|(ext, _)| ext).collect();
+        for (extension, _) in actual.extensions() {
+            if !extensions.contains(&extension) {
+                extensions.push(extension);
+            }
+        }
+        for extension in extensions {
+            let expected_value = expected
+                .has_extension(&extension)
+                .then(|| expected.get_extension(&extension));
+            let actual_value = actual
+                .has_extension(&extension)
+                .then(|| actual.get_extension(&extension));
+
+            self.with_path(&format!("[{}]", extension.full_name()), |ctx| {
+                ctx.diff_values(expected_value.as_deref(), actual_value.as_deref())
+            });
+        }
+    }
+
+    fn diff_values(&mut self, expected: Option<&Value>, actual: Option<&Value>) {
+        match (expected, actual) {
+            (Some(Value::Message(expected)), Some(Value::Message(actual))) => {
+                self.diff_messages(expected, actual)
+            }
+            (Some(Value::List(expected)), Some(Value::List(actual))) => {
+                for index in 0..expected.len().max(actual.len()) {
+                    let path = self.path.clone();
+                    self.path.push_str(&format!("[{}]", index));
+                    self.diff_values(expected.get(index), actual.get(index));
+                    self.path = path;
+                }
+            }
+            (Some(Value::Map(expected)), Some(Value::Map(actual))) => {
+                let mut keys: Vec<&MapKey> = expected.keys().collect();
+                keys.extend(actual.keys().filter(|key| !expected.contains_key(key)));
+                for key in keys {
+                    let path = self.path.clone();
+                    self.path
+                        .push_str(&format!("[{}]", Value::from(key.clone())));
+                    self.diff_values(expected.get(key), actual.get(key));
+                    self.path = path;
+                }
+            }
+            (expected, actual) => {
+                if expected != actual {
+                    self.divergences.push(Divergence {
+                        file: self.file.to_owned(),
+                        path: self.path.clone(),
+                        expected: expected.map(|value| value.to_string()),
+                        actual: actual.map(|value| value.to_string()),
+                    });
+                }
+            }
+        }
+    }
+
+    fn with_path(&mut self, name: &str, f: impl FnOnce(&mut Self)) {
+        let len = self.path.len();
+        if !self.path.is_empty() {
+            self.path.push('.');
+        }
+        self.path.push_str(name);
+        f(self);
+        self.path.truncate(len);
+    }
+}
+
+/// Returns true if both messages are fields with equal floating-point default values, which may be formatted
+/// differently.
+fn is_float_default(expected: &DynamicMessage, actual: &DynamicMessage) -> bool {
+    let parse = |message: &DynamicMessage| {
+        message
+            .get_field_by_name("default_value")
+            .and_then(|value| value.as_str().and_then(|s| s.parse::<f64>().ok()))
+    };
+
+    match (parse(expected), parse(actual)) {
+        (Some(expected), Some(actual)) => {
+            expected == actual || (expected.is_nan() && actual.is_nan())
+        }
+        _ => false,
+    }
+}
@@ -4,8 +4,9 @@ use std::{
     process::{Command, Stdio},
 };
 
+use prost::Message;
 use prost_reflect::{DescriptorPool, DynamicMessage, SerializeOptions, Value};
-use prost_types::{field_descriptor_proto::Type, source_code_info::Location};
+use prost_types::{field_descriptor_proto::Type, source_code_info::Location, FileDescriptorProto};
 use tempfile::TempDir;
 
 fn test_data_dir() -> PathBuf {
@@ -50,7 +51,64 @@ fn to_yaml(message: &DynamicMessage) -> String {
     String::from_utf8(serializer.into_inner().unwrap()).unwrap()
 }
 
+/// Compares the encoded bytes of each non-google file with the output of protoc, using
+/// `Compiler::byte_compatible`.
+fn compare_bytes(name: &str) {
+    let files = [
+        "google/protobuf/descriptor.proto".to_owned(),
+        format!("{}.proto", name),
+    ];
+
+    let expected = protoc_output(&files);
+    let actual = protox::Compiler::new([test_data_dir(), google_proto_dir(), google_src_dir()])
+        .unwrap()
+        .include_imports(true)
+        .include_source_info(true)
+        .byte_compatible(true)
+        .open_files(&files)
+        .unwrap()
+        .encode_file_descriptor_set();
+
+    let expected = split_files(&expected);
+    let actual = split_files(&actual);
+    assert_eq!(
+        expected.iter().map(|(name, _)| name).collect::<Vec<_>>(),
+        actual.iter().map(|(name, _)| name).collect::<Vec<_>>()
+    );
+    for ((name, expected), (_, actual)) in expected.iter().zip(&actual) {
+        assert!(
+            expected == actual,
+            "encoding of '{name}' differs from protoc"
+        );
+    }
+}
+
+/// Splits an encoded `FileDescriptorSet` into the name and encoding of each file, excluding
+/// `google/protobuf` files.
+fn split_files(mut buf: &[u8]) -> Vec<(String, &[u8])> {
+    let mut files = Vec::new();
+    while !buf.is_empty() {
+        let (tag, wire_type) = prost::encoding::decode_key(&mut buf).unwrap();
+        assert_eq!(
+            (tag, wire_type),
+            (1, prost::encoding::WireType::LengthDelimited)
+        );
+        let len = prost::encoding::decode_varint(&mut buf).unwrap() as usize;
+        let (file, rest) = buf.split_at(len);
+        let name = FileDescriptorProto::decode(file).unwrap().name().to_owned();
+        if !name.starts_with("google/protobuf/") {
+            files.push((name, file));
+        }
+        buf = rest;
+    }
+    files
+}
+
 fn protoc(files: &[String]) -> DynamicMessage {
+    decode_file_descriptor(protoc_output(files))
+}
+
+fn protoc_output(files: &[String]) -> Vec<u8> {
     let tempdir = TempDir::new().unwrap();
     let result = tempdir.path().join("desc.bin");
     let output = Command::new(prost_build::protoc_from_env())
@@ -73,9 +131,7 @@ fn protoc(files: &[String]) -> DynamicMessage {
             String::from_utf8_lossy(&output.stderr)
         );
     }
-    let bytes = fs::read(result).unwrap();
-
-    decode_file_descriptor(bytes)
+    fs::read(result).unwrap()
 }
 
 fn protox(files: &[String]) -> DynamicMessage {
@@ -202,9 +258,13 @@ macro_rules! compare {
 compare!(empty_file);
 compare!(empty_file_with_comment);
 compare!(field_defaults);
+compare!(field_defaults_float_format);
+compare!(field_defaults_imported_enum);
 compare!(generate_map_entry_message);
 compare!(generate_group_message);
+compare!(generate_group_message_nested);
 compare!(generate_synthetic_oneof_ordering);
+compare!(generate_synthetic_oneof_name_conflict);
 compare!(generate_synthetic_oneof);
 compare!(generated_message_ordering);
 compare!(multiple_extends);
@@ -213,10 +273,46 @@ compare!(option_merge_message);
 compare!(custom_json_name);
 compare!(reserved_ranges);
 compare!(oneof_group_field);
+compare!(oneof_options);
 compare!(service);
+compare!(service_options);
 compare!(option_group_field);
 compare!(message_name_field_name_conflict);
 compare!(package_name_field_name_conflict);
+compare!(message_set);
+compare!(option_source_paths);
+compare!(comment_attachment);
+compare!(file_options);
+compare!(standard_options);
+
+macro_rules! compare_bytes {
+    ($name:ident) => {
+        mod $name {
+            #[test]
+            fn bytes() {
+                super::compare_bytes(stringify!($name));
+            }
+        }
+    };
+}
+
+compare_bytes!(comment_attachment);
+compare_bytes!(custom_json_name);
+compare_bytes!(field_defaults_imported_enum);
+compare_bytes!(file_options);
+compare_bytes!(generate_group_message);
+compare_bytes!(generate_group_message_nested);
+compare_bytes!(generate_map_entry_message);
+compare_bytes!(generate_synthetic_oneof);
+compare_bytes!(generated_message_ordering);
+compare_bytes!(multiple_extends);
+compare_bytes!(name_resolution);
+compare_bytes!(oneof_options);
+compare_bytes!(option_source_paths);
+compare_bytes!(reserved_ranges);
+compare_bytes!(service);
+compare_bytes!(service_options);
+compare_bytes!(standard_options);
 
 #[test]
 fn google_protobuf_any() {
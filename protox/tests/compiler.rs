@@ -1,16 +1,30 @@
-use std::{env, fs, io, path::PathBuf};
+use std::{
+    env, fs, io,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
 
 use insta::assert_yaml_snapshot;
 use miette::{Diagnostic, JSONReportHandler};
 use prost::Message;
-use prost_reflect::{DescriptorPool, Value};
+use prost_reflect::{DescriptorPool, FileDescriptor, Value};
 use prost_types::{
-    source_code_info::Location, FileDescriptorProto, FileDescriptorSet, SourceCodeInfo,
+    field_descriptor_proto::{Label, Type},
+    source_code_info::Location,
+    DescriptorProto, EnumDescriptorProto, EnumValueDescriptorProto, FieldDescriptorProto,
+    FileDescriptorProto, FileDescriptorSet, SourceCodeInfo,
 };
 use protox::{
+    check::{CustomCheck, Diagnostics},
     compile,
     file::{ChainFileResolver, DescriptorSetFileResolver, File, FileResolver, GoogleFileResolver},
-    Compiler, Error,
+    fix::Edit,
+    ir::{self, FieldSource},
+    symbol::{ReferenceKind, SymbolKind, SymbolTable},
+    CheckMode, CompatibilityLevel, Compiler, CompilerObserver, DescriptorFilter,
+    DescriptorVerification, Error,
 };
 use tempfile::TempDir;
 
@@ -358,8 +372,70 @@ fn error_invalid_utf8() {
 
     assert!(err.is_parse());
     assert_eq!(err.file(), Some("foo.proto"));
-    assert_eq!(err.to_string(), "file 'foo.proto' is not valid utf-8");
-    assert_eq!(format!("{:?}", err), "file 'foo.proto' is not valid utf-8");
+    assert_eq!(
+        err.to_string(),
+        "file 'foo.proto' is not valid utf-8 at byte offset 8"
+    );
+    assert_eq!(
+        format!("{:?}", err),
+        "foo.proto:1:9: file 'foo.proto' is not valid utf-8 at byte offset 8"
+    );
+    let label = err.labels().unwrap().next().unwrap();
+    assert_eq!(label.label(), Some("invalid utf-8 sequence"));
+    assert_eq!((label.offset(), label.len()), (8, 3));
+}
+
+#[test]
+fn lossy_utf8() {
+    let dir = TempDir::new().unwrap();
+    fs::write(
+        dir.path().join("dep.proto"),
+        b"// \xFF\xFE comment\nmessage Dep { optional string s = 1 [default = \"\xC3\"]; }",
+    )
+    .unwrap();
+    fs::write(
+        dir.path().join("root.proto"),
+        b"import 'dep.proto'; message Root { optional Dep dep = 1; optional Missing missing = 2; }",
+    )
+    .unwrap();
+
+    let mut compiler = Compiler::new([dir.path()]).unwrap();
+    let err = compiler.open_file("root.proto").unwrap_err();
+    assert_eq!(err.file(), Some("dep.proto"));
+    assert_eq!(
+        err.to_string(),
+        "file 'dep.proto' is not valid utf-8 at byte offset 3"
+    );
+
+    let mut compiler = Compiler::new([dir.path()]).unwrap();
+    compiler.lossy_utf8(true);
+    let err = compiler.open_file("root.proto").unwrap_err();
+    assert_eq!(err.file(), Some("root.proto"));
+    assert_eq!(err.to_string(), "name 'Missing' is not defined");
+    assert_eq!(
+        compiler.check_warnings()[0].to_string(),
+        "file 'dep.proto' is not valid utf-8 at byte offset 3; invalid sequences were replaced with U+FFFD"
+    );
+
+    let dep = compiler
+        .descriptor_pool()
+        .get_message_by_name("Dep")
+        .unwrap();
+    assert_eq!(
+        dep.get_field_by_name("s")
+            .unwrap()
+            .field_descriptor_proto()
+            .default_value(),
+        "\u{FFFD}"
+    );
+    assert_eq!(
+        compiler
+            .files()
+            .find(|file| file.name() == "dep.proto")
+            .unwrap()
+            .path(),
+        Some(dir.path().join("dep.proto").as_path())
+    );
 }
 
 #[test]
@@ -382,3 +458,4028 @@ fn name_resolution_incorrect() {
     );
     assert_eq!(format!("{}", error.help().unwrap()), "The innermost scope is searched first in name resolution. Consider using a leading '.' (i.e., '.foo.Foo') to start from the outermost scope.");
 }
+
+#[test]
+fn type_references() {
+    let compiler = check(&[
+        (
+            "dep.proto",
+            "
+            syntax = 'proto3';
+            package dep;
+
+            message Dep {}
+            enum Kind { KIND_UNSPECIFIED = 0; }
+        ",
+        ),
+        (
+            "root.proto",
+            "
+            syntax = 'proto2';
+            package root;
+            import 'dep.proto';
+
+            message Foo {
+                optional dep.Dep dep = 1;
+                optional dep.Kind kind = 2;
+                optional int32 num = 3;
+                extensions 10 to 20;
+            }
+
+            extend Foo {
+                optional Foo foo_ext = 10;
+            }
+
+            service Service {
+                rpc Method(Foo) returns (dep.Dep);
+            }
+        ",
+        ),
+    ])
+    .unwrap();
+
+    let references: Vec<_> = compiler
+        .type_references()
+        .into_iter()
+        .map(|reference| {
+            (
+                reference.file().to_owned(),
+                reference.referrer().to_owned(),
+                reference.kind(),
+                reference.target().full_name().to_owned(),
+                reference.target().kind(),
+                reference.target().file().to_owned(),
+            )
+        })
+        .collect();
+
+    let reference = |referrer: &str, kind, target: &str, target_kind, target_file: &str| {
+        (
+            "root.proto".to_owned(),
+            referrer.to_owned(),
+            kind,
+            target.to_owned(),
+            target_kind,
+            target_file.to_owned(),
+        )
+    };
+    assert_eq!(
+        references,
+        vec![
+            reference(
+                "root.Foo.dep",
+                ReferenceKind::FieldType,
+                "dep.Dep",
+                SymbolKind::Message,
+                "dep.proto"
+            ),
+            reference(
+                "root.Foo.kind",
+                ReferenceKind::FieldType,
+                "dep.Kind",
+                SymbolKind::Enum,
+                "dep.proto"
+            ),
+            reference(
+                "root.foo_ext",
+                ReferenceKind::Extendee,
+                "root.Foo",
+                SymbolKind::Message,
+                "root.proto"
+            ),
+            reference(
+                "root.foo_ext",
+                ReferenceKind::FieldType,
+                "root.Foo",
+                SymbolKind::Message,
+                "root.proto"
+            ),
+            reference(
+                "root.Service.Method",
+                ReferenceKind::MethodInput,
+                "root.Foo",
+                SymbolKind::Message,
+                "root.proto"
+            ),
+            reference(
+                "root.Service.Method",
+                ReferenceKind::MethodOutput,
+                "dep.Dep",
+                SymbolKind::Message,
+                "dep.proto"
+            ),
+        ]
+    );
+}
+
+#[test]
+fn unused_imports() {
+    let mut resolver = ChainFileResolver::new();
+    resolver.add(TestFileResolver {
+        files: &[
+            ("unused.proto", "message Unused {}"),
+            ("used.proto", "message Used {}"),
+            ("public.proto", "import public 'used.proto';"),
+            (
+                "option.proto",
+                "import 'google/protobuf/descriptor.proto';
+                extend google.protobuf.MessageOptions { optional int32 opt = 1000; }",
+            ),
+            (
+                "root.proto",
+                "import 'public.proto';
+import 'unused.proto';
+import 'option.proto';
+
+message Foo {
+    option (opt) = 1;
+    optional Used used = 1;
+}",
+            ),
+        ],
+    });
+    resolver.add(GoogleFileResolver::new());
+
+    let mut compiler = Compiler::with_file_resolver(resolver);
+    compiler.open_file("root.proto").unwrap();
+
+    let unused_imports = compiler.unused_imports();
+    assert_eq!(unused_imports.len(), 1);
+    assert_eq!(unused_imports[0].file(), "root.proto");
+    assert_eq!(unused_imports[0].name(), "unused.proto");
+    assert_eq!(unused_imports[0].span(), Some((23..45).into()));
+    assert_eq!(unused_imports[0].removal_span(), Some((23..46).into()));
+    let fix = unused_imports[0].fix().unwrap();
+    assert_eq!(fix.message(), "remove unused import");
+    assert_eq!(fix.edits(), [Edit::delete(23..46)]);
+    assert_eq!(
+        format!("{:?}", unused_imports[0]),
+        "root.proto:2:1: import 'unused.proto' is unused"
+    );
+
+    let mut resolver = ChainFileResolver::new();
+    resolver.add(TestFileResolver {
+        files: &[
+            ("dep.proto", "message Dep {}"),
+            ("root.proto", "message Foo {} import 'dep.proto';"),
+        ],
+    });
+    let error = Compiler::with_file_resolver(resolver)
+        .deny_unused_imports(true)
+        .open_file("root.proto")
+        .unwrap_err();
+    assert_eq!(error.file(), Some("root.proto"));
+    assert_eq!(
+        format!("{:?}", error),
+        "root.proto:1:16: import 'dep.proto' is unused"
+    );
+    assert_eq!(
+        error.fixes()[0].apply("message Foo {} import 'dep.proto';"),
+        "message Foo {} "
+    );
+    assert_yaml_snapshot!(error_to_json(&error));
+}
+
+#[test]
+fn write_file_descriptor_set() {
+    let tempdir = TempDir::new().unwrap();
+    fs::write(tempdir.path().join("dep.proto"), "message Dep {}").unwrap();
+    fs::write(
+        tempdir.path().join("root.proto"),
+        "import 'dep.proto'; import 'google/protobuf/any.proto'; message Root { optional Dep dep = 1; optional google.protobuf.Any any = 2; }",
+    )
+    .unwrap();
+
+    let mut compiler = Compiler::new([tempdir.path()]).unwrap();
+    compiler.include_imports(true);
+    compiler.open_file("root.proto").unwrap();
+
+    let output = tempdir.path().join("out.bin");
+    compiler.write_file_descriptor_set(&output).unwrap();
+    assert_eq!(
+        fs::read(&output).unwrap(),
+        compiler.encode_file_descriptor_set()
+    );
+
+    std::thread::scope(|scope| {
+        for _ in 0..4 {
+            scope.spawn(|| compiler.write_file_descriptor_set(&output).unwrap());
+        }
+    });
+    assert_eq!(
+        fs::read(&output).unwrap(),
+        compiler.encode_file_descriptor_set()
+    );
+
+    let mut entries: Vec<_> = fs::read_dir(tempdir.path())
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name())
+        .collect();
+    entries.sort();
+    assert_eq!(entries, ["dep.proto", "out.bin", "root.proto"]);
+
+    let error = compiler
+        .write_file_descriptor_set(tempdir.path().join("missing/out.bin"))
+        .unwrap_err();
+    assert!(error.is_io());
+    assert!(error.to_string().starts_with("error writing file '"));
+}
+
+#[test]
+fn depfile() {
+    struct PathFileResolver {
+        dir: PathBuf,
+    }
+
+    impl FileResolver for PathFileResolver {
+        fn open_file(&self, name: &str) -> Result<File, Error> {
+            File::open(name, &self.dir.join(name))
+        }
+    }
+
+    let tempdir = TempDir::new().unwrap();
+    let dir = tempdir.path().join("my protos");
+    fs::create_dir(&dir).unwrap();
+    fs::write(dir.join("dep.proto"), "message Dep {}").unwrap();
+    fs::write(
+        dir.join("root.proto"),
+        "import 'dep.proto'; import 'google/protobuf/any.proto'; message Root { optional Dep dep = 1; optional google.protobuf.Any any = 2; }",
+    )
+    .unwrap();
+
+    let mut resolver = ChainFileResolver::new();
+    resolver.add(PathFileResolver { dir: dir.clone() });
+    resolver.add(GoogleFileResolver::new());
+    let mut compiler = Compiler::with_file_resolver(resolver);
+    compiler.open_file("root.proto").unwrap();
+
+    let escaped_dir = dir.display().to_string().replace(' ', "\\ ");
+    assert_eq!(
+        compiler.depfile(["out/a.bin", "out/$b.bin"]),
+        format!(
+            "out/a.bin out/$$b.bin: \\\n  {dir}/dep.proto \\\n  {dir}/root.proto\n",
+            dir = escaped_dir
+        )
+    );
+
+    let path = tempdir.path().join("out.d");
+    compiler.write_depfile(&path, ["out.bin"]).unwrap();
+    assert_eq!(
+        fs::read_to_string(&path).unwrap(),
+        compiler.depfile(["out.bin"])
+    );
+}
+
+#[test]
+fn import_trace() {
+    use protox::file::ResolutionOutcome;
+
+    let tempdir = TempDir::new().unwrap();
+    let first = tempdir.path().join("first");
+    let second = tempdir.path().join("second");
+    fs::create_dir(&first).unwrap();
+    fs::create_dir(&second).unwrap();
+    fs::write(first.join("dep.proto"), "message Dep {}").unwrap();
+    fs::write(second.join("dep.proto"), "message Dep {}").unwrap();
+    fs::write(second.join("root.proto"), "import 'dep.proto';").unwrap();
+
+    let mut compiler = Compiler::new([&first, &second]).unwrap();
+    compiler.trace_imports(true);
+    compiler.open_file("root.proto").unwrap();
+
+    let trace = compiler.import_trace();
+    assert_eq!(trace.len(), 2);
+
+    assert_eq!(trace[0].name(), "root.proto");
+    assert_eq!(trace[0].importer(), None);
+    assert_eq!(trace[0].candidates().len(), 2);
+    assert_eq!(
+        trace[0].candidates()[0].outcome(),
+        &ResolutionOutcome::NotFound
+    );
+    assert_eq!(
+        trace[0].resolved().unwrap().resolver(),
+        format!("include path '{}'", second.display())
+    );
+    assert_eq!(trace[0].path(), Some(second.join("root.proto").as_path()));
+
+    assert_eq!(trace[1].name(), "dep.proto");
+    assert_eq!(trace[1].importer(), Some("root.proto"));
+    assert_eq!(trace[1].candidates().len(), 1);
+    assert_eq!(trace[1].path(), Some(first.join("dep.proto").as_path()));
+    assert_eq!(
+        trace[1].to_string(),
+        format!(
+            "'dep.proto' (imported by 'root.proto'): found in include path '{}' at '{}'",
+            first.display(),
+            first.join("dep.proto").display()
+        )
+    );
+
+    let mut compiler = Compiler::new([&first]).unwrap();
+    compiler.trace_imports(true);
+    compiler.open_file("google/protobuf/any.proto").unwrap();
+    assert_eq!(
+        compiler.import_trace()[0].to_string(),
+        format!(
+            "'google/protobuf/any.proto': not found in include path '{}', found in built-in google files",
+            first.display()
+        )
+    );
+}
+
+#[test]
+fn symbol_table() {
+    const SOURCE: &str = "
+        syntax = 'proto2';
+        package foo.bar;
+
+        message Baz {}
+
+        message Outer {
+            message Baz {}
+
+            message Inner {
+                optional Baz a = 1;
+                optional bar.Baz b = 2;
+                optional .foo.bar.Baz c = 3;
+                optional Outer.Baz d = 4;
+                optional Kind e = 5;
+            }
+
+            optional int32 Kind = 1;
+            optional Kind kind = 2;
+            oneof choice { Inner inner = 3; }
+            extensions 100 to 200;
+        }
+
+        enum Kind { KIND_UNSPECIFIED = 0; }
+
+        service Svc { rpc Call(Outer.Inner) returns (Baz); }
+
+        extend Outer { optional Kind ext = 100; }
+    ";
+
+    let mut compiler = Compiler::with_file_resolver(TestFileResolver {
+        files: &[("root.proto", SOURCE)],
+    });
+    compiler.open_file("root.proto").unwrap();
+
+    let symbols = compiler.symbol_table();
+    assert_eq!(
+        SymbolTable::new(&compiler.descriptor_pool()).iter().count(),
+        symbols.iter().count()
+    );
+
+    for (name, kind) in [
+        ("foo", SymbolKind::Package),
+        ("foo.bar", SymbolKind::Package),
+        ("foo.bar.Outer", SymbolKind::Message),
+        ("foo.bar.Outer.Kind", SymbolKind::Field),
+        ("foo.bar.Outer.choice", SymbolKind::Oneof),
+        ("foo.bar.Kind", SymbolKind::Enum),
+        ("foo.bar.KIND_UNSPECIFIED", SymbolKind::EnumValue),
+        ("foo.bar.Svc", SymbolKind::Service),
+        ("foo.bar.Svc.Call", SymbolKind::Method),
+        ("foo.bar.ext", SymbolKind::Extension),
+    ] {
+        let symbol = symbols.get(name).unwrap();
+        assert_eq!(symbol.kind(), kind, "{}", name);
+        assert_eq!(symbol.file(), "root.proto");
+    }
+    assert!(symbols.get("foo.bar.Kind.KIND_UNSPECIFIED").is_none());
+
+    let children: Vec<&str> = symbols.children("").map(|s| s.full_name()).collect();
+    assert_eq!(children, ["foo"]);
+    let children: Vec<&str> = symbols
+        .children("foo.bar.Outer")
+        .map(|s| s.full_name())
+        .collect();
+    assert_eq!(
+        children,
+        [
+            "foo.bar.Outer.Baz",
+            "foo.bar.Outer.Inner",
+            "foo.bar.Outer.Kind",
+            "foo.bar.Outer.choice",
+            "foo.bar.Outer.inner",
+            "foo.bar.Outer.kind",
+        ]
+    );
+
+    // Unresolved type names should resolve to the same types as chosen by the checker.
+    let unresolved = protox_parse::parse("root.proto", SOURCE).unwrap();
+    let pool = compiler.descriptor_pool();
+    let mut messages: Vec<_> = unresolved
+        .message_type
+        .iter()
+        .map(|message| (format!("foo.bar.{}", message.name()), message))
+        .collect();
+    let mut checked = 0;
+    while let Some((full_name, message)) = messages.pop() {
+        let message_desc = pool.get_message_by_name(&full_name).unwrap();
+        for field in &message.field {
+            if field.type_name().is_empty() {
+                continue;
+            }
+
+            let resolved = symbols
+                .resolve_type_name(&full_name, field.type_name())
+                .unwrap();
+            let expected = match message_desc.get_field_by_name(field.name()).unwrap().kind() {
+                prost_reflect::Kind::Message(message) => message.full_name().to_owned(),
+                prost_reflect::Kind::Enum(enum_) => enum_.full_name().to_owned(),
+                _ => unreachable!(),
+            };
+            assert_eq!(resolved.full_name(), expected, "{}", field.name());
+            checked += 1;
+        }
+        messages.extend(
+            message
+                .nested_type
+                .iter()
+                .map(|nested| (format!("{}.{}", full_name, nested.name()), nested)),
+        );
+    }
+    assert_eq!(checked, 7);
+
+    assert_eq!(
+        symbols
+            .resolve_type_name("foo.bar.Outer.Inner", "Kind")
+            .unwrap()
+            .full_name(),
+        "foo.bar.Kind"
+    );
+    assert!(symbols
+        .resolve_type_name("foo.bar.Outer", "foo.Baz")
+        .is_none());
+    assert!(symbols.resolve_type_name("", "Baz").is_none());
+    assert_eq!(
+        symbols.resolve_type_name("", "foo.bar.Baz").unwrap().kind(),
+        SymbolKind::Message
+    );
+}
+
+#[test]
+fn symbol_table_update_file() {
+    fn compile(files: &'static [(&'static str, &'static str)]) -> Compiler {
+        let mut compiler = Compiler::with_file_resolver(TestFileResolver { files });
+        for (name, _) in files {
+            compiler.open_file(name).unwrap();
+        }
+        compiler
+    }
+
+    let compiler = compile(&[
+        ("a.proto", "package foo; message A { message Nested {} }"),
+        ("b.proto", "package foo.bar; message B {}"),
+        ("c.proto", "package foo; message C {}"),
+    ]);
+    let mut symbols = compiler.symbol_table();
+
+    // Editing a file without changing its names affects no references.
+    let edited = compile(&[(
+        "a.proto",
+        "package foo; message A { message Nested {} optional int32 x = 1; }",
+    )]);
+    let changes = symbols.update_file(
+        &edited
+            .descriptor_pool()
+            .get_file_by_name("a.proto")
+            .unwrap(),
+    );
+    assert_eq!(changes.namespaces().collect::<Vec<_>>(), ["foo.A"]);
+    assert!(changes.affects("foo.bar.B", "A.x"));
+    assert!(!changes.affects("foo.bar.B", "bar.B"));
+    assert!(!changes.affects("foo.bar.B", "B"));
+
+    let edited = compile(&[(
+        "a.proto",
+        "package foo; message A {} enum Nested { N = 0; }",
+    )]);
+    let changes = symbols.update_file(
+        &edited
+            .descriptor_pool()
+            .get_file_by_name("a.proto")
+            .unwrap(),
+    );
+    assert_eq!(changes.namespaces().collect::<Vec<_>>(), ["foo", "foo.A"]);
+    assert_eq!(symbols.get("foo.Nested").unwrap().kind(), SymbolKind::Enum);
+    assert!(symbols.get("foo.A.Nested").is_none());
+    assert!(symbols.get("foo.A.x").is_none());
+    assert!(changes.affects("foo.bar.B", "Nested"));
+    assert!(changes.affects("other", ".foo.A.Nested"));
+    assert!(!changes.affects("other", ".foo.bar.B"));
+
+    // A package declared by another file is kept.
+    let changes = symbols.remove_file("a.proto");
+    assert_eq!(changes.namespaces().collect::<Vec<_>>(), ["foo"]);
+    assert_eq!(symbols.get("foo").unwrap().file(), "b.proto");
+    assert!(symbols.get("foo.A").is_none());
+    assert!(symbols.get("foo.C").is_some());
+
+    symbols.remove_file("b.proto");
+    assert_eq!(symbols.get("foo").unwrap().file(), "c.proto");
+    assert!(symbols.get("foo.bar").is_none());
+
+    let changes = symbols.remove_file("c.proto");
+    assert_eq!(changes.namespaces().collect::<Vec<_>>(), ["", "foo"]);
+    assert_eq!(symbols.iter().count(), 0);
+    assert!(symbols.remove_file("c.proto").is_empty());
+}
+
+#[test]
+fn ir_view() {
+    let mut compiler = Compiler::with_file_resolver(TestFileResolver {
+        files: &[
+            (
+                "proto2.proto",
+                "
+                syntax = 'proto2';
+                package pkg;
+
+                message Foo {
+                    message Nested {}
+                    map<string, Nested> items = 1;
+                    optional group Body = 2 { optional int32 a = 1; }
+                    oneof choice { int32 b = 3; }
+                    extensions 10 to 20;
+                }
+
+                extend Foo { optional group Ext = 10 { optional int32 c = 1; } }
+                ",
+            ),
+            (
+                "proto3.proto",
+                "syntax = 'proto3'; message Bar { optional int32 x = 1; int32 y = 2; repeated int32 z = 3 [deprecated = true]; repeated int32 w = 4 [packed = false]; }",
+            ),
+        ],
+    });
+    compiler.open_file("proto2.proto").unwrap();
+    compiler.open_file("proto3.proto").unwrap();
+    let pool = compiler.descriptor_pool();
+
+    let file = ir::File::new(&pool.get_file_by_name("proto2.proto").unwrap());
+    let names: Vec<&str> = file
+        .messages()
+        .iter()
+        .map(|message| message.descriptor().name())
+        .collect();
+    assert_eq!(names, ["Foo"]);
+
+    let foo = &file.messages()[0];
+    let names: Vec<&str> = foo
+        .messages()
+        .iter()
+        .map(|message| message.descriptor().name())
+        .collect();
+    assert_eq!(names, ["Nested"]);
+
+    match foo.fields()[0].source() {
+        FieldSource::Map { entry, key, value } => {
+            assert_eq!(entry.full_name(), "pkg.Foo.ItemsEntry");
+            assert_eq!(key.kind(), prost_reflect::Kind::String);
+            assert_eq!(value.kind().as_message().unwrap().name(), "Nested");
+        }
+        source => panic!("unexpected source {:?}", source),
+    }
+    match foo.fields()[1].source() {
+        FieldSource::Group(body) => {
+            assert_eq!(body.descriptor().full_name(), "pkg.Foo.Body");
+            assert_eq!(body.fields()[0].descriptor().name(), "a");
+        }
+        source => panic!("unexpected source {:?}", source),
+    }
+    assert_eq!(foo.fields()[2].source(), &FieldSource::Field);
+    assert!(!foo.oneofs()[0].is_synthetic());
+
+    match file.extensions()[0].source() {
+        FieldSource::Group(body) => assert_eq!(body.descriptor().full_name(), "pkg.Ext"),
+        source => panic!("unexpected source {:?}", source),
+    }
+
+    let file = ir::File::new(&pool.get_file_by_name("proto3.proto").unwrap());
+    let bar = &file.messages()[0];
+    assert_eq!(bar.oneofs().len(), 1);
+    assert!(bar.oneofs()[0].is_synthetic());
+    assert!(bar.fields()[0].is_proto3_optional());
+    assert!(!bar.fields()[1].is_proto3_optional());
+    assert!(!bar.fields()[1].is_packed());
+    assert!(bar.fields()[2].is_packed());
+    assert!(!bar.fields()[3].is_packed());
+}
+
+#[test]
+fn add_file_descriptor_set() {
+    let mut resolver = ChainFileResolver::new();
+    resolver.add(TestFileResolver {
+        files: &[
+            (
+                "validate/validate.proto",
+                "
+                syntax = 'proto2';
+                package validate;
+                import 'google/protobuf/descriptor.proto';
+                extend google.protobuf.FieldOptions { optional bool required = 1071; }
+                ",
+            ),
+            (
+                "google/protobuf/timestamp.proto",
+                "
+                syntax = 'proto3';
+                package google.protobuf;
+                import 'validate/validate.proto';
+                message Timestamp {
+                    int64 seconds = 1 [(validate.required) = true];
+                    int64 vendored = 3;
+                }
+                ",
+            ),
+        ],
+    });
+    resolver.add(GoogleFileResolver::new());
+    let mut vendored = Compiler::with_file_resolver(resolver);
+    vendored.include_imports(true);
+    vendored
+        .open_file("google/protobuf/timestamp.proto")
+        .unwrap();
+    let encoded = vendored.encode_file_descriptor_set();
+
+    let tempdir = TempDir::new().unwrap();
+    fs::write(
+        tempdir.path().join("root.proto"),
+        "syntax = 'proto3'; import 'google/protobuf/timestamp.proto'; message Root { google.protobuf.Timestamp time = 1; }",
+    )
+    .unwrap();
+
+    let mut compiler = Compiler::new([tempdir.path()]).unwrap();
+    compiler
+        .add_encoded_file_descriptor_set(encoded.as_slice())
+        .unwrap();
+    compiler.open_file("root.proto").unwrap();
+
+    let pool = compiler.descriptor_pool();
+    let timestamp = pool
+        .get_message_by_name("google.protobuf.Timestamp")
+        .unwrap();
+    assert!(timestamp.get_field_by_name("vendored").is_some());
+    let required = pool.get_extension_by_name("validate.required").unwrap();
+    assert_eq!(
+        timestamp
+            .get_field_by_name("seconds")
+            .unwrap()
+            .options()
+            .get_extension(&required)
+            .as_ref(),
+        &Value::Bool(true)
+    );
+
+    let names: Vec<_> = compiler.files().map(|file| file.name()).collect();
+    assert_eq!(
+        names,
+        [
+            "google/protobuf/descriptor.proto",
+            "validate/validate.proto",
+            "google/protobuf/timestamp.proto",
+            "root.proto"
+        ]
+    );
+
+    assert!(Compiler::new([tempdir.path()])
+        .unwrap()
+        .add_encoded_file_descriptor_set([0xff].as_slice())
+        .is_err());
+}
+
+#[test]
+fn compile_str() {
+    let file_descriptor_set = protox::compile_str_with_file_resolver(
+        "root.proto",
+        "import 'dep.proto'; message Foo { optional Bar bar = 1; }",
+        TestFileResolver {
+            files: &[("dep.proto", "message Bar {}")],
+        },
+    )
+    .unwrap();
+
+    let names: Vec<_> = file_descriptor_set.file.iter().map(|f| f.name()).collect();
+    assert_eq!(names, ["dep.proto", "root.proto"]);
+    assert_eq!(
+        file_descriptor_set.file[1].message_type[0].field[0].type_name(),
+        ".Bar"
+    );
+    assert!(file_descriptor_set.file[1].source_code_info.is_some());
+
+    let err = protox::compile_str(
+        "root.proto",
+        "message Foo { optional Bar bar = 1; }",
+        [] as [&str; 0],
+    )
+    .unwrap_err();
+    assert_eq!(err.to_string(), "name 'Bar' is not defined");
+
+    let err =
+        protox::compile_str("root.proto", "import 'dep.proto';", [] as [&str; 0]).unwrap_err();
+    assert_eq!(err.to_string(), "import 'dep.proto' not found");
+}
+
+/// Resolves files from owned sources, falling back to the bundled well-known types.
+struct SourceFileResolver {
+    files: Vec<(String, String)>,
+}
+
+impl SourceFileResolver {
+    fn new(files: &[(&str, &str)]) -> Self {
+        SourceFileResolver {
+            files: files
+                .iter()
+                .map(|(name, source)| (name.to_string(), source.to_string()))
+                .collect(),
+        }
+    }
+}
+
+impl FileResolver for SourceFileResolver {
+    fn open_file(&self, name: &str) -> Result<File, Error> {
+        match self.files.iter().find(|(file, _)| file == name) {
+            Some((_, source)) => File::from_source(name, source),
+            None => GoogleFileResolver::new().open_file(name),
+        }
+    }
+}
+
+#[test]
+fn option_retention() {
+    let mut compiler = Compiler::with_file_resolver(SourceFileResolver::new(&[(
+        "root.proto",
+        "
+            import 'google/protobuf/descriptor.proto';
+
+            extend google.protobuf.MessageOptions {
+                optional int32 source = 1001 [retention = RETENTION_SOURCE];
+                optional int32 runtime = 1002 [retention = RETENTION_RUNTIME];
+            }
+
+            message Foo {
+                option (source) = 1;
+                option (runtime) = 2;
+            }
+        ",
+    )]));
+    compiler.open_file("root.proto").unwrap();
+
+    let get_options = |encoded: Vec<u8>| {
+        let pool = DescriptorPool::decode(encoded.as_slice()).unwrap();
+        let options = pool.get_message_by_name("Foo").unwrap().options();
+        let source = pool.get_extension_by_name("source").unwrap();
+        let runtime = pool.get_extension_by_name("runtime").unwrap();
+        (
+            options.has_extension(&source),
+            options.has_extension(&runtime),
+        )
+    };
+
+    compiler.include_imports(true);
+    assert_eq!(
+        get_options(compiler.encode_file_descriptor_set()),
+        (false, true)
+    );
+    assert_eq!(
+        get_options(compiler.file_descriptor_set().encode_to_vec()),
+        (false, false)
+    );
+
+    compiler.include_source_info(true);
+    assert_eq!(
+        get_options(compiler.encode_file_descriptor_set()),
+        (false, true)
+    );
+
+    compiler.retain_options(true);
+    assert_eq!(
+        get_options(compiler.encode_file_descriptor_set()),
+        (true, true)
+    );
+
+    let pool = compiler.descriptor_pool();
+    let source = pool.get_extension_by_name("source").unwrap();
+    assert_eq!(
+        pool.get_message_by_name("Foo")
+            .unwrap()
+            .options()
+            .get_extension(&source)
+            .as_ref(),
+        &Value::I32(1)
+    );
+}
+
+#[test]
+fn option_targets() {
+    const DEPENDENCY: (&str, &str) = (
+        "dep.proto",
+        "
+        import 'google/protobuf/descriptor.proto';
+
+        message Rule {
+            optional int32 min = 1 [targets = TARGET_TYPE_FIELD];
+            optional int32 max = 2 [targets = TARGET_TYPE_MESSAGE];
+        }
+
+        extend google.protobuf.MessageOptions {
+            optional Rule message_rule = 1001;
+        }
+
+        extend google.protobuf.FieldOptions {
+            optional Rule rule = 1001;
+            optional int32 any = 1002 [targets = TARGET_TYPE_FIELD, targets = TARGET_TYPE_ONEOF];
+        }
+    ",
+    );
+
+    let mut compiler = Compiler::with_file_resolver(SourceFileResolver::new(&[
+        DEPENDENCY,
+        (
+            "valid.proto",
+            "
+                import 'dep.proto';
+
+                message Foo {
+                    option (message_rule).max = 1;
+
+                    optional int32 bar = 1 [(any) = 1, (rule).min = 1];
+                }
+            ",
+        ),
+    ]));
+    compiler.open_file("valid.proto").unwrap();
+
+    let mut compiler = Compiler::with_file_resolver(SourceFileResolver::new(&[
+        DEPENDENCY,
+        (
+            "invalid.proto",
+            "import 'dep.proto';\nmessage Foo {\n  optional int32 bar = 1 [(rule).max = 1];\n}\n",
+        ),
+    ]));
+    let err = compiler.open_file("invalid.proto").unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "option '(rule).max' cannot be set on an entity of type 'field'"
+    );
+    assert_eq!(err.file(), Some("invalid.proto"));
+    assert_eq!(
+        format!("{:?}", err),
+        "invalid.proto:3:27: option '(rule).max' cannot be set on an entity of type 'field'"
+    );
+
+    let mut compiler = Compiler::with_file_resolver(SourceFileResolver::new(&[
+        DEPENDENCY,
+        (
+            "invalid.proto",
+            "import 'dep.proto';\nmessage Foo {\n  option (message_rule).min = 1;\n}\n",
+        ),
+    ]));
+    let err = compiler.open_file("invalid.proto").unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "option '(message_rule).min' cannot be set on an entity of type 'message'"
+    );
+}
+
+#[test]
+fn extension_declarations() {
+    const EXTENDEE: (&str, &str) = (
+        "extendee.proto",
+        "
+        syntax = 'proto2';
+        package foo;
+        import 'google/protobuf/descriptor.proto';
+
+        message Extendee {
+            extensions 100 to 199 [
+                declaration = { number: 100, full_name: '.bar.value', type: 'int32' },
+                declaration = { number: 101, full_name: '.bar.values', type: '.foo.Extendee', repeated: true },
+                declaration = { number: 102, reserved: true }
+            ];
+            extensions 200 to 299 [verification = DECLARATION];
+            extensions 300 to 399;
+        }
+    ",
+    );
+
+    fn check_extension(source: &str) -> Result<(), Error> {
+        let mut compiler = Compiler::with_file_resolver(SourceFileResolver::new(&[
+            EXTENDEE,
+            ("root.proto", source),
+        ]));
+        compiler.open_file("root.proto")?;
+        Ok(())
+    }
+
+    check_extension(
+        "
+        syntax = 'proto2';
+        package bar;
+        import 'extendee.proto';
+
+        extend foo.Extendee {
+            optional int32 value = 100;
+            repeated foo.Extendee values = 101;
+            optional string undeclared = 300;
+        }
+    ",
+    )
+    .unwrap();
+
+    let err = check_extension(
+        "syntax = 'proto2';\npackage bar;\nimport 'extendee.proto';\nextend foo.Extendee {\n  optional int64 value = 100;\n}\n",
+    )
+    .unwrap_err();
+    assert_eq!(
+        format!("{:?}", err),
+        "root.proto:5:3: extension field 100 of 'foo.Extendee' is expected to be type 'int32', not 'int64'"
+    );
+
+    let err = check_extension(
+        "package bar; import 'extendee.proto'; extend foo.Extendee { optional int32 other = 100; }",
+    )
+    .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "extension field 100 of 'foo.Extendee' is expected to have name '.bar.value', not '.bar.other'"
+    );
+
+    let err = check_extension(
+        "package bar; import 'extendee.proto'; extend foo.Extendee { optional foo.Extendee values = 101; }",
+    )
+    .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "extension field 101 of 'foo.Extendee' is expected to be repeated"
+    );
+
+    let err = check_extension(
+        "package bar; import 'extendee.proto'; extend foo.Extendee { optional int32 value = 103; }",
+    )
+    .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "missing extension declaration for field 'bar.value' with number 103 in extendee message 'foo.Extendee'"
+    );
+
+    let err = check_extension(
+        "package bar; import 'extendee.proto'; extend foo.Extendee { optional int32 value = 200; }",
+    )
+    .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "missing extension declaration for field 'bar.value' with number 200 in extendee message 'foo.Extendee'"
+    );
+
+    let err = check_extension(
+        "package bar; import 'extendee.proto'; extend foo.Extendee { optional int32 value = 102; }",
+    )
+    .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "cannot use number 102 for extension field 'bar.value', as it is reserved in the extension declarations for message 'foo.Extendee'"
+    );
+}
+
+#[test]
+fn invalid_extension_declarations() {
+    fn check_declarations(options: &str) -> String {
+        let source = format!(
+            "import 'google/protobuf/descriptor.proto';\nmessage Foo {{\n  extensions 1 to 10 [{}];\n}}\n",
+            options
+        );
+        let mut compiler =
+            Compiler::with_file_resolver(SourceFileResolver::new(&[("root.proto", &source)]));
+        compiler.open_file("root.proto").unwrap_err().to_string()
+    }
+
+    assert_eq!(
+        check_declarations("declaration = { number: 11, full_name: '.a', type: 'int32' }"),
+        "extension declaration number 11 is not in the extension range"
+    );
+    assert_eq!(
+        check_declarations(
+            "declaration = { number: 1, full_name: '.a', type: 'int32' }, declaration = { number: 1, full_name: '.b', type: 'int32' }"
+        ),
+        "extension declaration number 1 is declared multiple times"
+    );
+    assert_eq!(
+        check_declarations(
+            "declaration = { number: 1, full_name: '.a', type: 'int32' }, declaration = { number: 2, full_name: '.a', type: 'int32' }"
+        ),
+        "extension name '.a' is declared multiple times"
+    );
+    assert_eq!(
+        check_declarations("declaration = { number: 1, full_name: '.a' }"),
+        "extension declaration #0 should have both 'full_name' and 'type' set"
+    );
+    assert_eq!(
+        check_declarations("declaration = { number: 1, full_name: 'a', type: 'int32' }"),
+        "extension declaration name 'a' must be fully-qualified with a leading '.'"
+    );
+    assert_eq!(
+        check_declarations(
+            "declaration = { number: 1, full_name: '.a', type: 'int32' }, verification = UNVERIFIED"
+        ),
+        "cannot mark the extension range as UNVERIFIED when it has extensions declared"
+    );
+}
+
+#[test]
+fn message_set() {
+    let err = check(&[(
+        "root.proto",
+        "message Set {\n  option message_set_wire_format = true;\n  optional int32 a = 1;\n}\n",
+    )])
+    .unwrap_err();
+    assert_eq!(
+        format!("{:?}", err),
+        "root.proto:3:18: message sets cannot have fields, only extensions"
+    );
+
+    let err = check(&[(
+        "root.proto",
+        "syntax = 'proto3';\nmessage Set {\n  option message_set_wire_format = true;\n}\n",
+    )])
+    .unwrap_err();
+    assert_eq!(
+        format!("{:?}", err),
+        "root.proto:2:9: message sets are not supported in proto3"
+    );
+
+    let err = check(&[
+        (
+            "dep.proto",
+            "message Set {\n  option message_set_wire_format = true;\n  extensions 4 to max;\n}\n",
+        ),
+        (
+            "root.proto",
+            "import 'dep.proto';\nextend Set {\n  optional int32 a = 4;\n}\n",
+        ),
+    ])
+    .unwrap_err();
+    assert_eq!(
+        format!("{:?}", err),
+        "root.proto:3:12: extensions of message sets must be optional messages"
+    );
+
+    let err = check(&[
+        (
+            "dep.proto",
+            "message Set {\n  option message_set_wire_format = true;\n  extensions 4 to max;\n}\n",
+        ),
+        (
+            "root.proto",
+            "import 'dep.proto';\nextend Set {\n  repeated Set a = 4;\n}\n",
+        ),
+    ])
+    .unwrap_err();
+    assert_eq!(
+        format!("{:?}", err),
+        "root.proto:3:12: extensions of message sets must be optional messages"
+    );
+}
+
+#[test]
+fn json_name_conflicts() {
+    let err = check(&[(
+        "root.proto",
+        "syntax = 'proto3';\nmessage A {\n  int32 foo_bar = 1;\n  int32 fooBar = 2;\n}\n",
+    )])
+    .unwrap_err();
+    assert_eq!(
+        format!("{:?}", err),
+        "root.proto:4:9: the default JSON name of field 'fooBar' ('fooBar') conflicts with the default JSON name of field 'foo_bar'"
+    );
+
+    let err = check(&[(
+        "root.proto",
+        "message A {\n  optional int32 a = 1 [json_name = 'x'];\n  optional int32 b = 2 [json_name = 'x'];\n}\n",
+    )])
+    .unwrap_err();
+    assert_eq!(
+        format!("{:?}", err),
+        "root.proto:3:18: the custom JSON name of field 'b' ('x') conflicts with the custom JSON name of field 'a'"
+    );
+
+    let err = check(&[(
+        "root.proto",
+        "syntax = 'proto3';\nmessage A {\n  int32 a = 1 [json_name = 'b'];\n  message B {\n    int32 b = 1;\n    int32 c = 2 [json_name = 'b'];\n  }\n}\n",
+    )])
+    .unwrap_err();
+    assert_eq!(
+        format!("{:?}", err),
+        "root.proto:6:11: the custom JSON name of field 'c' ('b') conflicts with the default JSON name of field 'b'"
+    );
+
+    let err = check(&[(
+        "root.proto",
+        "message A {\n  optional int32 a = 1 [json_name = '[foo]'];\n}\n",
+    )])
+    .unwrap_err();
+    assert_eq!(
+        format!("{:?}", err),
+        "root.proto:2:25: the custom JSON name of field 'a' ('[foo]') is invalid: JSON names may not start with '[' and end with ']'"
+    );
+
+    // Names which differ only in case do not conflict in proto2.
+    check(&[(
+        "root.proto",
+        "message A {\n  optional int32 foo = 1;\n  optional int32 b = 2 [json_name = 'Foo'];\n}\n",
+    )])
+    .unwrap();
+}
+
+#[test]
+fn compatibility_level() {
+    const PROTO3_OPTIONAL: (&str, &str) = (
+        "root.proto",
+        "syntax = 'proto3';\nmessage Foo {\n  optional int32 a = 1;\n}\n",
+    );
+
+    let mut compiler = Compiler::with_file_resolver(TestFileResolver {
+        files: &[PROTO3_OPTIONAL],
+    });
+    compiler.compatibility_level(CompatibilityLevel::Protoc3_12);
+    let err = compiler.open_file("root.proto").unwrap_err();
+    assert_eq!(
+        format!("{:?}", err),
+        "root.proto:3:3: field 'a' is a proto3 optional field, which requires --experimental_allow_proto3_optional"
+    );
+
+    let mut compiler = Compiler::with_file_resolver(TestFileResolver {
+        files: &[PROTO3_OPTIONAL],
+    });
+    compiler.compatibility_level(CompatibilityLevel::Protoc21);
+    compiler.open_file("root.proto").unwrap();
+    let message = compiler
+        .descriptor_pool()
+        .get_message_by_name("Foo")
+        .unwrap();
+    assert_eq!(message.oneofs().next().unwrap().name(), "_a");
+
+    // Options were not checked against their targets before protoc 22.
+    const TARGETS: &[(&str, &str)] = &[
+        (
+            "dep.proto",
+            "import 'google/protobuf/descriptor.proto';\nextend google.protobuf.MessageOptions {\n  optional int32 rule = 1001 [targets = TARGET_TYPE_FIELD];\n}\n",
+        ),
+        (
+            "root.proto",
+            "import 'dep.proto';\nmessage Foo {\n  option (rule) = 1;\n}\n",
+        ),
+    ];
+    let mut compiler = Compiler::with_file_resolver(SourceFileResolver::new(TARGETS));
+    compiler.compatibility_level(CompatibilityLevel::Protoc21);
+    compiler.open_file("root.proto").unwrap();
+
+    let mut compiler = Compiler::with_file_resolver(SourceFileResolver::new(TARGETS));
+    compiler.compatibility_level(CompatibilityLevel::Protoc25);
+    compiler.open_file("root.proto").unwrap_err();
+}
+
+struct NoEmptyMessages;
+
+impl CustomCheck for NoEmptyMessages {
+    fn check_file(&self, file: &FileDescriptor, diagnostics: &mut Diagnostics<'_>) {
+        for message in file.messages() {
+            if message.fields().len() == 0 {
+                diagnostics
+                    .error(
+                        message.path(),
+                        format!("message '{}' is empty", message.name()),
+                    )
+                    .set_help("add a field");
+            } else if message.fields().len() == 1 {
+                diagnostics.warning(
+                    message.path(),
+                    format!("message '{}' has a single field", message.name()),
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn custom_check() {
+    let mut compiler = Compiler::with_file_resolver(TestFileResolver {
+        files: &[
+            ("dep.proto", "message Empty {}\n"),
+            (
+                "root.proto",
+                "import 'dep.proto';\nmessage Foo {\n  optional Empty a = 1;\n}\n",
+            ),
+            (
+                "empty.proto",
+                "message Baz {\n  optional int32 a = 1;\n}\nmessage Bar {}\n",
+            ),
+        ],
+    });
+    compiler.add_check(NoEmptyMessages);
+
+    // Imported files are not checked.
+    compiler.open_file("root.proto").unwrap();
+    assert_eq!(compiler.check_warnings().len(), 1);
+    assert_eq!(
+        format!("{:?}", compiler.check_warnings()[0]),
+        "root.proto:2:1: message 'Foo' has a single field"
+    );
+
+    let err = compiler.open_file("empty.proto").unwrap_err();
+    assert_eq!(err.file(), Some("empty.proto"));
+    assert_eq!(
+        format!("{:?}", err),
+        "empty.proto:4:1: message 'Bar' is empty"
+    );
+    assert_eq!(err.help().unwrap().to_string(), "add a field");
+    assert_eq!(compiler.check_warnings().len(), 1);
+}
+
+#[test]
+fn stats() {
+    let mut compiler = Compiler::with_file_resolver(TestFileResolver {
+        files: &[
+            ("dep.proto", "message Dep {}\n"),
+            ("unused.proto", "message Unused {}\n"),
+            (
+                "root.proto",
+                "import 'dep.proto';\nimport 'unused.proto';\n// Comment\nmessage Foo {\n  message Bar {\n    message Baz {}\n    optional Dep a = 1;\n  }\n  optional int32 a = 1;\n  optional int32 b = 2;\n  extend Foo {\n    optional int32 c = 10;\n  }\n  extensions 10 to 20;\n}\nenum E { A = 1; }\nservice S {\n  rpc M(Foo) returns (Foo);\n}\n",
+            ),
+        ],
+    });
+    compiler.include_source_info(true);
+    compiler.open_file("root.proto").unwrap();
+
+    let stats = compiler.stats();
+    assert_eq!(stats.files().len(), 1);
+    let file = &stats.files()[0];
+    assert_eq!(file.name(), "root.proto");
+    assert_eq!(file.messages(), 3);
+    assert_eq!(file.fields(), 3);
+    assert_eq!(file.extensions(), 1);
+    assert_eq!(file.enums(), 1);
+    assert_eq!(file.services(), 1);
+    assert_eq!(file.methods(), 1);
+    assert_eq!(file.max_nesting_depth(), 3);
+    assert_eq!(file.largest_message(), Some(("Foo", 2)));
+    assert_eq!(file.unused_imports(), ["unused.proto"]);
+
+    assert_eq!(
+        compiler.file_descriptor_set().file[0].encoded_len(),
+        file.encoded_size() + file.source_info_size()
+    );
+
+    compiler.include_imports(true);
+    let stats = compiler.stats();
+    assert_eq!(stats.files().len(), 3);
+    assert_eq!(stats.messages(), 5);
+    assert_eq!(stats.largest_message(), Some(("root.proto", "Foo", 2)));
+
+    #[cfg(feature = "serde")]
+    {
+        let json = serde_json::to_value(&stats).unwrap();
+        assert_eq!(json["files"][2]["name"], "root.proto");
+        assert_eq!(json["files"][2]["messages"], 3);
+        assert_eq!(json["files"][2]["unused_imports"][0], "unused.proto");
+        assert_eq!(json["files"][2]["largest_message"]["name"], "Foo");
+        assert_eq!(json["files"][2]["largest_message"]["fields"], 2);
+    }
+}
+
+#[test]
+fn hermetic() {
+    let dir = tempfile::tempdir().unwrap();
+    let include = dir.path().join("include");
+    fs::create_dir(&include).unwrap();
+    fs::write(dir.path().join("outside.proto"), "message Outside {}\n").unwrap();
+    fs::write(
+        include.join("root.proto"),
+        "import 'vendor.proto';\nmessage Foo { optional Vendor a = 1; }\n",
+    )
+    .unwrap();
+
+    // Imports in source files are validated by the parser, but pre-compiled files may import any name.
+    let vendor = FileDescriptorSet {
+        file: vec![FileDescriptorProto {
+            name: Some("vendor.proto".to_owned()),
+            dependency: vec!["../outside.proto".to_owned()],
+            message_type: vec![prost_types::DescriptorProto {
+                name: Some("Vendor".to_owned()),
+                ..Default::default()
+            }],
+            ..Default::default()
+        }],
+    };
+
+    Compiler::new([&include])
+        .unwrap()
+        .add_file_descriptor_set(vendor.clone())
+        .open_file("root.proto")
+        .unwrap();
+
+    let err = Compiler::new([&include])
+        .unwrap()
+        .hermetic(true)
+        .add_file_descriptor_set(vendor)
+        .open_file("root.proto")
+        .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "file name '../outside.proto' must be a relative path without '.' or '..' components"
+    );
+    assert_eq!(err.file(), Some("../outside.proto"));
+
+    #[cfg(unix)]
+    {
+        std::os::unix::fs::symlink(dir.path().join("outside.proto"), include.join("link.proto"))
+            .unwrap();
+        fs::write(
+            include.join("linked.proto"),
+            "import 'link.proto';\nmessage Foo { optional Outside a = 1; }\n",
+        )
+        .unwrap();
+
+        Compiler::new([&include])
+            .unwrap()
+            .open_file("linked.proto")
+            .unwrap();
+
+        let err = Compiler::new([&include])
+            .unwrap()
+            .hermetic(true)
+            .open_file("linked.proto")
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "file 'link.proto' resolves to '{}', which is outside the include paths",
+                include.join("link.proto").display()
+            )
+        );
+    }
+
+    fs::write(include.join("dep.proto"), "message Dep {}\n").unwrap();
+    fs::write(include.join("ok.proto"), "import 'dep.proto';\n").unwrap();
+    Compiler::new([&include])
+        .unwrap()
+        .hermetic(true)
+        .open_file(include.join("ok.proto"))
+        .unwrap();
+}
+
+#[test]
+fn from_protoc_args() {
+    let dir = TempDir::new().unwrap();
+    let include = dir.path().join("include");
+    fs::create_dir(&include).unwrap();
+    fs::write(
+        include.join("root.proto"),
+        "import 'vendor.proto';\nmessage Root { optional Vendor vendor = 1; }\n",
+    )
+    .unwrap();
+
+    let vendor = FileDescriptorSet {
+        file: vec![FileDescriptorProto {
+            name: Some("vendor.proto".to_owned()),
+            message_type: vec![prost_types::DescriptorProto {
+                name: Some("Vendor".to_owned()),
+                ..Default::default()
+            }],
+            ..Default::default()
+        }],
+    };
+    let vendor_path = dir.path().join("vendor.bin");
+    fs::write(&vendor_path, vendor.encode_to_vec()).unwrap();
+
+    let args = [
+        format!("--proto_path={}", include.display()),
+        format!("--descriptor_set_in={}", vendor_path.display()),
+        "--include_imports".to_owned(),
+        "root.proto".to_owned(),
+    ];
+    let compiler = Compiler::from_protoc_args(&args).unwrap();
+    let files = compiler.file_descriptor_set().file;
+    assert_eq!(files.len(), 2);
+    assert_eq!(files[0].name(), "vendor.proto");
+    assert_eq!(files[1].name(), "root.proto");
+
+    let err = Compiler::from_protoc_args(&[
+        "-I".to_owned(),
+        include.display().to_string(),
+        "--descriptor_set_in".to_owned(),
+        dir.path().join("missing.bin").display().to_string(),
+        "root.proto".to_owned(),
+    ])
+    .unwrap_err();
+    assert!(err.is_io());
+
+    let err = Compiler::from_protoc_args(&["--cpp_out=gen".to_owned()]).unwrap_err();
+    assert_eq!(err.to_string(), "unknown protoc argument '--cpp_out=gen'");
+}
+
+#[test]
+fn uninterpreted_options() {
+    let resolver = TestFileResolver {
+        files: &[(
+            "root.proto",
+            "syntax = 'proto3';\n\
+            package pkg;\n\
+            option java_package = 'com.example';\n\
+            message Foo {\n\
+              repeated int32 values = 1 [packed = false, (unknown.opt).a = 5];\n\
+              Bar bar = 2;\n\
+            }\n\
+            message Bar {}\n",
+        )],
+    };
+
+    let err = Compiler::with_file_resolver(TestFileResolver {
+        files: resolver.files,
+    })
+    .open_file("root.proto")
+    .unwrap_err();
+    assert_eq!(err.to_string(), "name 'unknown.opt' is not defined");
+
+    let mut compiler = Compiler::with_file_resolver(resolver);
+    compiler
+        .interpret_options(false)
+        .include_source_info(true)
+        .open_file("root.proto")
+        .unwrap();
+
+    let file = &compiler.file_descriptor_set().file[0];
+    let file_options = file.options.as_ref().unwrap();
+    assert_eq!(file_options.java_package, None);
+    assert_eq!(file_options.uninterpreted_option.len(), 1);
+    assert_eq!(
+        file_options.uninterpreted_option[0].name[0].name_part,
+        "java_package"
+    );
+    assert_eq!(
+        file_options.uninterpreted_option[0].string_value(),
+        b"com.example"
+    );
+
+    let field = &file.message_type[0].field[0];
+    let field_options = field.options.as_ref().unwrap();
+    assert_eq!(field_options.packed, None);
+    let names: Vec<_> = field_options
+        .uninterpreted_option
+        .iter()
+        .map(|option| {
+            option
+                .name
+                .iter()
+                .map(|part| part.name_part.as_str())
+                .collect::<Vec<_>>()
+        })
+        .collect();
+    assert_eq!(names, [vec!["packed"], vec!["unknown.opt", "a"]]);
+    assert_eq!(field.json_name(), "values");
+    assert_eq!(file.message_type[0].field[1].type_name(), ".pkg.Bar");
+    assert!(file
+        .source_code_info
+        .as_ref()
+        .unwrap()
+        .location
+        .iter()
+        .any(|location| location.path == [4, 0, 2, 0, 8, 999, 1]));
+
+    assert_eq!(
+        FileDescriptorSet::decode(compiler.encode_file_descriptor_set().as_slice()).unwrap(),
+        compiler.file_descriptor_set()
+    );
+    assert!(compiler
+        .descriptor_pool()
+        .get_message_by_name("pkg.Foo")
+        .unwrap()
+        .get_field(1)
+        .unwrap()
+        .is_packed());
+}
+
+#[test]
+fn syntax_mixing() {
+    const DEP: (&str, &str) = (
+        "dep.proto",
+        "syntax = 'proto2';\npackage dep;\nenum Closed {\n  A = 0;\n}\nmessage Ext {\n  extensions 1 to 10;\n}\n",
+    );
+
+    let err = check(&[
+        DEP,
+        (
+            "root.proto",
+            "syntax = 'proto3';\nimport 'dep.proto';\nmessage Foo {\n  dep.Closed a = 1;\n}\n",
+        ),
+    ])
+    .unwrap_err();
+    assert_eq!(
+        format!("{:?}", err),
+        "root.proto:4:3: enum 'dep.Closed' is not a proto3 enum, but is used by field 'a' of proto3 message 'Foo'"
+    );
+    let related: Vec<_> = err.related().unwrap().collect();
+    assert_eq!(
+        related[0].to_string(),
+        "enum 'dep.Closed' is defined in proto2 file 'dep.proto'"
+    );
+    let label = related[0].labels().unwrap().next().unwrap();
+    assert_eq!(&DEP.1[label.offset()..][..label.len()], "Closed");
+
+    let err = check(&[
+        DEP,
+        (
+            "root.proto",
+            "syntax = 'proto3';\nimport 'dep.proto';\nmessage Foo {\n  message Bar {\n    map<string, dep.Closed> a = 1;\n  }\n}\n",
+        ),
+    ])
+    .unwrap_err();
+    assert_eq!(
+        format!("{:?}", err),
+        "root.proto:5:5: enum 'dep.Closed' is not a proto3 enum, but is used by field 'a' of proto3 message 'Foo.Bar'"
+    );
+
+    let err = check(&[
+        DEP,
+        (
+            "root.proto",
+            "syntax = 'proto3';\nimport 'dep.proto';\nextend dep.Ext {\n  string a = 1;\n}\n",
+        ),
+    ])
+    .unwrap_err();
+    assert_eq!(
+        format!("{:?}", err),
+        "root.proto:3:8: extending 'dep.Ext' is not allowed in proto3"
+    );
+    assert!(err
+        .help()
+        .unwrap()
+        .to_string()
+        .contains("proto2 syntax or editions"));
+
+    // Extensions nested in messages are checked too, and the label points at the extendee.
+    const NESTED: (&str, &str) = (
+        "root.proto",
+        "syntax = 'proto3';\nimport 'dep.proto';\nmessage Foo {\n  extend dep.Ext {\n    string a = 1;\n  }\n}\n",
+    );
+    let err = check(&[DEP, NESTED]).unwrap_err();
+    let label = err.labels().unwrap().next().unwrap();
+    assert_eq!(&NESTED.1[label.offset()..][..label.len()], "dep.Ext");
+
+    // Like protoc, options messages declared in the 'proto2' package may be extended.
+    check(&[
+        (
+            "descriptor.proto",
+            "syntax = 'proto2';\npackage proto2;\nmessage FieldOptions {\n  extensions 1000 to max;\n}\n",
+        ),
+        (
+            "root.proto",
+            "syntax = 'proto3';\nimport 'descriptor.proto';\nextend proto2.FieldOptions {\n  string a = 1001;\n}\n",
+        ),
+    ])
+    .unwrap();
+
+    // Proto2 messages may use open enums, and proto3 files may define options.
+    check(&[
+        (
+            "dep.proto",
+            "syntax = 'proto3';\npackage dep;\nenum Open {\n  A = 0;\n}\n",
+        ),
+        (
+            "root.proto",
+            "syntax = 'proto2';\nimport 'dep.proto';\nmessage Foo {\n  optional dep.Open a = 1;\n}\n",
+        ),
+    ])
+    .unwrap();
+    protox::compile_str(
+        "root.proto",
+        "syntax = 'proto3';\nimport 'google/protobuf/descriptor.proto';\nextend google.protobuf.FieldOptions {\n  string a = 1001;\n}\n",
+        Vec::<PathBuf>::new(),
+    )
+    .unwrap();
+}
+
+#[test]
+fn invalid_oneofs() {
+    use prost_types::{
+        field_descriptor_proto::{Label, Type},
+        DescriptorProto, FieldDescriptorProto, OneofDescriptorProto,
+    };
+
+    fn field(
+        name: &str,
+        number: i32,
+        label: Label,
+        oneof_index: Option<i32>,
+    ) -> FieldDescriptorProto {
+        FieldDescriptorProto {
+            name: Some(name.to_owned()),
+            number: Some(number),
+            label: Some(label as i32),
+            r#type: Some(Type::Int32 as i32),
+            oneof_index,
+            ..Default::default()
+        }
+    }
+
+    fn compile(fields: Vec<FieldDescriptorProto>, oneofs: &[&str]) -> Result<(), Error> {
+        let file = FileDescriptorProto {
+            name: Some("root.proto".to_owned()),
+            message_type: vec![DescriptorProto {
+                name: Some("Foo".to_owned()),
+                field: fields,
+                oneof_decl: oneofs
+                    .iter()
+                    .map(|name| OneofDescriptorProto {
+                        name: Some(name.to_string()),
+                        ..Default::default()
+                    })
+                    .collect(),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let mut compiler =
+            Compiler::with_file_resolver(DescriptorSetFileResolver::new(FileDescriptorSet {
+                file: vec![file],
+            }));
+        compiler.open_file("root.proto").map(drop)
+    }
+
+    compile(
+        vec![
+            field("a", 1, Label::Optional, Some(0)),
+            field("b", 2, Label::Optional, Some(0)),
+            field("c", 3, Label::Optional, None),
+        ],
+        &["x"],
+    )
+    .unwrap();
+    // Fields are consecutive if they are declared together, regardless of their numbers.
+    compile(
+        vec![
+            field("a", 1, Label::Optional, Some(0)),
+            field("b", 3, Label::Optional, Some(0)),
+            field("c", 2, Label::Optional, None),
+        ],
+        &["x"],
+    )
+    .unwrap();
+
+    assert_eq!(
+        compile(vec![field("a", 1, Label::Optional, None)], &["x"])
+            .unwrap_err()
+            .to_string(),
+        "oneof 'x' must have at least one field"
+    );
+    assert_eq!(
+        compile(vec![field("a", 1, Label::Repeated, Some(0))], &["x"])
+            .unwrap_err()
+            .to_string(),
+        "field 'a' in a oneof must be optional"
+    );
+    assert_eq!(
+        compile(
+            vec![
+                field("a", 1, Label::Optional, Some(0)),
+                field("b", 2, Label::Optional, None),
+                field("c", 3, Label::Optional, Some(0)),
+            ],
+            &["x"],
+        )
+        .unwrap_err()
+        .to_string(),
+        "fields in oneof 'x' must be defined consecutively"
+    );
+    assert_eq!(
+        compile(
+            vec![
+                field("a", 1, Label::Optional, Some(0)),
+                field("b", 2, Label::Optional, Some(1)),
+                field("c", 3, Label::Optional, Some(0)),
+            ],
+            &["x", "y"],
+        )
+        .unwrap_err()
+        .to_string(),
+        "fields in oneof 'x' must be defined consecutively"
+    );
+
+    let err = check(&[(
+        "root.proto",
+        "message Foo {\n  oneof x {\n    oneof y {\n      int32 a = 1;\n    }\n  }\n}\n",
+    )])
+    .unwrap_err();
+    assert_eq!(
+        format!("{:?}", err),
+        "root.proto:3:5: oneofs cannot be nested"
+    );
+}
+
+#[test]
+fn import_rewrites() {
+    let mut resolver = ChainFileResolver::new();
+    resolver.add(TestFileResolver {
+        files: &[
+            (
+                "root.proto",
+                "import 'company/foo.proto';
+                import 'company/api/bar.proto';
+                import 'companyx/baz.proto';
+                message Root {
+                    optional Foo foo = 1;
+                    optional Bar bar = 2;
+                    optional Baz baz = 3;
+                }",
+            ),
+            ("third_party/company/foo.proto", "message Foo {}"),
+            ("vendor/api/bar.proto", "message Bar {}"),
+            ("companyx/baz.proto", "message Baz {}"),
+        ],
+    });
+
+    let mut compiler = Compiler::with_file_resolver(resolver);
+    compiler
+        .add_import_rewrite("company/", "third_party/company")
+        .add_import_rewrite("company/api", "vendor/api/")
+        .include_imports(true)
+        .open_file("root.proto")
+        .unwrap();
+    assert_eq!(
+        compiler
+            .files()
+            .map(|file| file.name().to_owned())
+            .collect::<Vec<_>>(),
+        [
+            "company/foo.proto",
+            "company/api/bar.proto",
+            "companyx/baz.proto",
+            "root.proto"
+        ]
+    );
+    let pool = compiler.descriptor_pool();
+    assert_eq!(
+        pool.get_message_by_name("Foo")
+            .unwrap()
+            .parent_file()
+            .name(),
+        "company/foo.proto"
+    );
+
+    // Pre-compiled files are renamed too.
+    let mut compiler = Compiler::with_file_resolver(
+        DescriptorSetFileResolver::decode(
+            FileDescriptorSet {
+                file: vec![
+                    FileDescriptorProto {
+                        name: Some("real/dep.proto".to_owned()),
+                        ..Default::default()
+                    },
+                    FileDescriptorProto {
+                        name: Some("root.proto".to_owned()),
+                        dependency: vec!["virtual/dep.proto".to_owned()],
+                        ..Default::default()
+                    },
+                ],
+            }
+            .encode_to_vec()
+            .as_slice(),
+        )
+        .unwrap(),
+    );
+    compiler
+        .add_import_rewrite("virtual", "real")
+        .include_imports(true)
+        .open_file("root.proto")
+        .unwrap();
+    assert_eq!(
+        compiler.file_descriptor_set().file[0].name(),
+        "virtual/dep.proto"
+    );
+
+    let mut compiler = Compiler::with_file_resolver(TestFileResolver {
+        files: &[("root.proto", "import 'company/missing.proto';")],
+    });
+    let error = compiler
+        .add_import_rewrite("company", "third_party/company")
+        .open_file("root.proto")
+        .unwrap_err();
+    assert!(error.is_file_not_found());
+}
+
+#[test]
+fn enum_value_names() {
+    // Enum values are siblings of their enum, so they conflict with values of other enums in the same scope.
+    const SCOPE: (&str, &str) = (
+        "root.proto",
+        "package pkg;\nenum E1 {\n  FOO = 0;\n}\nenum E2 {\n  FOO = 0;\n}\n",
+    );
+    let err = check(&[SCOPE]).unwrap_err();
+    assert_eq!(
+        format!("{:?}", err),
+        "root.proto:6:3: name 'pkg.FOO' is defined twice"
+    );
+    assert_eq!(
+        err.help().unwrap().to_string(),
+        "enum values use C++ scoping rules, meaning that enum values are siblings of their type, not children of it, so 'FOO' must be unique within 'pkg', not just within 'E2'"
+    );
+    let labels: Vec<_> = err.labels().unwrap().collect();
+    assert_eq!(labels[0].offset(), SCOPE.1.find("FOO").unwrap());
+
+    let err = check(&[(
+        "root.proto",
+        "message Foo {\n  enum E1 {\n    E2 = 0;\n  }\n  enum E2 {\n    A = 0;\n  }\n}\n",
+    )])
+    .unwrap_err();
+    assert_eq!(
+        format!("{:?}", err),
+        "root.proto:3:5: name 'Foo.E2' is defined twice"
+    );
+    assert!(err.help().unwrap().to_string().contains("within 'Foo'"));
+
+    // In proto3, values with the same name after removing the enum name prefix must have the same number.
+    const PREFIX: (&str, &str) = (
+        "root.proto",
+        "syntax = 'proto3';\nenum FooBar {\n  option allow_alias = true;\n  FOO_BAR_BAZ = 0;\n  Baz = 1;\n  QUX = 2;\n  qux = 2;\n}\n",
+    );
+    let err = check(&[PREFIX]).unwrap_err();
+    assert_eq!(
+        format!("{:?}", err),
+        "root.proto:5:3: enum value 'Baz' has the same name as 'FOO_BAR_BAZ' if you ignore case and strip out the enum name prefix"
+    );
+
+    let mut compiler = Compiler::with_file_resolver(TestFileResolver { files: &[PREFIX] });
+    compiler
+        .allow_enum_value_name_conflicts(true)
+        .open_file("root.proto")
+        .unwrap();
+    assert_eq!(compiler.check_warnings().len(), 1);
+    assert_eq!(
+        format!("{:?}", compiler.check_warnings()[0]),
+        "root.proto:5:3: enum value 'Baz' has the same name as 'FOO_BAR_BAZ' if you ignore case and strip out the enum name prefix"
+    );
+
+    // In proto2, conflicts are only warnings.
+    let compiler = check(&[(
+        "root.proto",
+        "syntax = 'proto2';\nenum Foo {\n  FOO_BAR = 0;\n  BAR = 1;\n}\n",
+    )])
+    .unwrap();
+    assert_eq!(compiler.check_warnings().len(), 1);
+    assert_eq!(
+        format!("{:?}", compiler.check_warnings()[0]),
+        "root.proto:4:3: enum value 'BAR' has the same name as 'FOO_BAR' if you ignore case and strip out the enum name prefix"
+    );
+
+    // The prefix is only removed if something is left, and distinct names after conversion do not conflict.
+    check(&[(
+        "root.proto",
+        "syntax = 'proto3';\nenum Bar {\n  BAR = 0;\n  B_A_R = 1;\n  _ = 2;\n  BAR_ = 3;\n}\n",
+    )])
+    .unwrap_err();
+    let compiler = check(&[(
+        "root.proto",
+        "syntax = 'proto3';\nenum Bar {\n  BAR = 0;\n  B_A_R = 1;\n  BAR_X = 2;\n}\n",
+    )])
+    .unwrap();
+    assert!(compiler.check_warnings().is_empty());
+}
+
+#[test]
+fn encode_file_descriptor_set_to() {
+    let mut resolver = ChainFileResolver::new();
+    resolver.add(TestFileResolver {
+        files: &[
+            ("dep.proto", "message Dep {}"),
+            (
+                "root.proto",
+                "import 'google/protobuf/descriptor.proto';
+                import 'dep.proto';
+                extend google.protobuf.MessageOptions { optional int32 ext = 1001; }
+                // Comment
+                message Root {
+                    option (ext) = 1;
+                    optional Dep dep = 1 [deprecated = true];
+                }",
+            ),
+        ],
+    });
+    resolver.add(GoogleFileResolver::new());
+
+    let mut compiler = Compiler::with_file_resolver(resolver);
+    compiler.open_file("root.proto").unwrap();
+
+    for (include_imports, include_source_info) in
+        [(false, false), (false, true), (true, false), (true, true)]
+    {
+        compiler
+            .include_imports(include_imports)
+            .include_source_info(include_source_info);
+
+        let mut output = Vec::new();
+        compiler.encode_file_descriptor_set_to(&mut output).unwrap();
+        assert_eq!(
+            FileDescriptorSet::decode(output.as_slice()).unwrap(),
+            compiler.file_descriptor_set()
+        );
+
+        if include_imports {
+            let pool = DescriptorPool::decode(output.as_slice()).unwrap();
+            let ext = pool.get_extension_by_name("ext").unwrap();
+            assert_eq!(
+                pool.get_message_by_name("Root")
+                    .unwrap()
+                    .options()
+                    .get_extension(&ext)
+                    .as_ref(),
+                &Value::I32(1)
+            );
+        }
+    }
+}
+
+#[test]
+fn encode_file() {
+    let mut resolver = ChainFileResolver::new();
+    resolver.add(TestFileResolver {
+        files: &[
+            ("dep.proto", "message Dep {}"),
+            (
+                "root.proto",
+                "import 'google/protobuf/descriptor.proto';
+                import 'dep.proto';
+                extend google.protobuf.MessageOptions { optional int32 ext = 1001; }
+                // Comment
+                message Root {
+                    option (ext) = 1;
+                    optional Dep dep = 1 [deprecated = true];
+                }",
+            ),
+        ],
+    });
+    resolver.add(GoogleFileResolver::new());
+
+    let mut compiler = Compiler::with_file_resolver(resolver);
+    compiler
+        .include_imports(true)
+        .open_file("root.proto")
+        .unwrap();
+
+    for include_source_info in [false, true] {
+        compiler.include_source_info(include_source_info);
+
+        // Each file is encoded exactly as it appears in the file descriptor set.
+        let mut set = Vec::new();
+        for file in compiler.files() {
+            let encoded = compiler.encode_file(file.name()).unwrap();
+            prost::encoding::bytes::encode(1, &encoded, &mut set);
+        }
+        assert_eq!(set, compiler.encode_file_descriptor_set());
+    }
+
+    assert_eq!(compiler.encode_file("missing.proto"), None);
+}
+
+#[test]
+fn duplicate_names() {
+    const FILES: &[(&str, &str)] = &[
+        ("a/common.proto", "package common;\nmessage Common {}\n"),
+        ("b/common.proto", "package common;\nmessage Common {}\n"),
+        (
+            "root.proto",
+            "import 'b/common.proto';\nmessage Root {\n  optional common.Common c = 1;\n}\n",
+        ),
+        (
+            "other.proto",
+            "package common;\n\nmessage Common {\n  optional int32 a = 1;\n}\n",
+        ),
+    ];
+
+    // Both definitions are reported, even though the files are unrelated.
+    let mut compiler = Compiler::with_file_resolver(TestFileResolver { files: FILES });
+    compiler.open_file("a/common.proto").unwrap();
+    let err = compiler.open_file("other.proto").unwrap_err();
+    assert_eq!(
+        format!("{:?}", err),
+        "other.proto:3:9: name 'common.Common' is already defined in file 'a/common.proto'"
+    );
+    let related: Vec<_> = err.related().unwrap().collect();
+    assert_eq!(
+        related[0].to_string(),
+        "'common.Common' is first defined in file 'a/common.proto'"
+    );
+    let labels: Vec<_> = related[0].labels().unwrap().collect();
+    assert_eq!(labels[0].offset(), FILES[0].1.find("Common").unwrap());
+
+    // The first definition may come from a file descriptor set, without source.
+    let mut compiler = Compiler::with_file_resolver(TestFileResolver { files: FILES });
+    compiler.add_file_descriptor_set(FileDescriptorSet {
+        file: vec![FileDescriptorProto {
+            name: Some("vendored.proto".to_owned()),
+            package: Some("common".to_owned()),
+            message_type: vec![prost_types::DescriptorProto {
+                name: Some("Common".to_owned()),
+                ..Default::default()
+            }],
+            ..Default::default()
+        }],
+    });
+    compiler.open_file("vendored.proto").unwrap();
+    let err = compiler.open_file("a/common.proto").unwrap_err();
+    assert_eq!(
+        format!("{:?}", err),
+        "a/common.proto:2:9: name 'common.Common' is already defined in file 'vendored.proto'"
+    );
+
+    // Identical files may be allowed, in which case imports refer to the first one.
+    let mut compiler = Compiler::with_file_resolver(TestFileResolver { files: FILES });
+    compiler.open_file("a/common.proto").unwrap();
+    compiler.open_file("b/common.proto").unwrap_err();
+    compiler
+        .allow_identical_redefinitions(true)
+        .open_files(["b/common.proto", "root.proto"])
+        .unwrap();
+    let files = compiler.file_descriptor_set().file;
+    assert_eq!(files.len(), 2);
+    assert_eq!(files[1].dependency, ["a/common.proto"]);
+    compiler.open_file("other.proto").unwrap_err();
+}
+
+#[test]
+fn filter_imports() {
+    let mut resolver = ChainFileResolver::new();
+    resolver.add(TestFileResolver {
+        files: &[
+            ("dep.proto", "message Dep {}"),
+            (
+                "root.proto",
+                "import 'dep.proto';
+                import 'google/protobuf/timestamp.proto';
+                message Root {
+                    optional Dep dep = 1;
+                    optional google.protobuf.Timestamp time = 2;
+                }",
+            ),
+        ],
+    });
+    resolver.add(GoogleFileResolver::new());
+
+    let mut compiler = Compiler::with_file_resolver(resolver);
+    compiler
+        .include_imports(true)
+        .open_file("root.proto")
+        .unwrap();
+    let names = |compiler: &Compiler| -> Vec<String> {
+        compiler
+            .file_descriptor_set()
+            .file
+            .iter()
+            .map(|file| file.name().to_owned())
+            .collect()
+    };
+    assert_eq!(
+        names(&compiler),
+        ["dep.proto", "google/protobuf/timestamp.proto", "root.proto"]
+    );
+
+    compiler.exclude_google_imports(true);
+    assert_eq!(names(&compiler), ["dep.proto", "root.proto"]);
+    assert_eq!(
+        FileDescriptorSet::decode(compiler.encode_file_descriptor_set().as_slice())
+            .unwrap()
+            .file
+            .len(),
+        2
+    );
+    assert_eq!(compiler.stats().files().len(), 2);
+
+    // Files added explicitly are always included.
+    compiler.filter_imports(|_| false);
+    assert_eq!(names(&compiler), ["root.proto"]);
+    compiler.open_file("dep.proto").unwrap();
+    assert_eq!(names(&compiler), ["dep.proto", "root.proto"]);
+
+    compiler.exclude_google_imports(false);
+    assert_eq!(
+        names(&compiler),
+        ["dep.proto", "google/protobuf/timestamp.proto", "root.proto"]
+    );
+}
+
+#[test]
+fn retain_sources() {
+    const FILES: &[(&str, &str)] = &[
+        ("a.proto", "package common;\nmessage Common {}\n"),
+        ("b.proto", "package common;\n\nmessage Common {}\n"),
+    ];
+
+    let mut compiler = Compiler::with_file_resolver(TestFileResolver { files: FILES });
+    compiler.open_file("a.proto").unwrap();
+    let usage = compiler.memory_usage();
+    assert_eq!(usage.files(), 1);
+    assert_eq!(usage.sources(), "a.proto".len() + FILES[0].1.len());
+    assert!(usage.descriptors() > 0);
+    assert_eq!(
+        usage.total(),
+        usage.descriptors() + usage.source_info() + usage.sources()
+    );
+
+    // Without the source of the first file, errors only point at the second definition.
+    compiler.retain_sources(false);
+    assert_eq!(compiler.memory_usage().sources(), 0);
+    let err = compiler.open_file("b.proto").unwrap_err();
+    assert_eq!(
+        format!("{:?}", err),
+        "b.proto:3:9: name 'common.Common' is already defined in file 'a.proto'"
+    );
+    let related: Vec<_> = err.related().unwrap().collect();
+    assert!(related[0]
+        .labels()
+        .map_or(true, |mut labels| labels.next().is_none()));
+
+    // Identical files are compared by their descriptors instead.
+    compiler
+        .allow_identical_redefinitions(true)
+        .open_file("b.proto")
+        .unwrap();
+    assert_eq!(compiler.memory_usage().sources(), 0);
+}
+
+#[test]
+fn duplicate_options() {
+    fn check(files: &'static [(&'static str, &'static str)]) -> Result<Compiler, Error> {
+        let mut resolver = ChainFileResolver::new();
+        resolver.add(TestFileResolver { files });
+        resolver.add(GoogleFileResolver::new());
+
+        let mut compiler = Compiler::with_file_resolver(resolver);
+        compiler.open_file(files[0].0)?;
+        Ok(compiler)
+    }
+
+    const NESTED: (&str, &str) = (
+        "root.proto",
+        "import 'google/protobuf/descriptor.proto';\nmessage Foo {\n  optional Bar bar = 1;\n  optional int32 a = 2;\n}\nmessage Bar {\n  optional int32 a = 1;\n}\nextend google.protobuf.MessageOptions {\n  optional Foo foo = 1000;\n}\nmessage Msg {\n  option (foo).bar.a = 1;\n  option (foo).a = 2;\n  option (foo).bar.a = 3;\n}\n",
+    );
+    let err = check(&[NESTED]).unwrap_err();
+    assert_eq!(
+        format!("{:?}", err),
+        "root.proto:15:3: option field '(foo).bar.a' has already been set"
+    );
+    let labels: Vec<_> = err.labels().unwrap().collect();
+    assert_eq!(labels[0].label(), Some("first set here"));
+    assert_eq!(
+        labels[0].offset(),
+        NESTED.1.find("option (foo).bar.a = 1").unwrap()
+    );
+    assert_eq!(labels[1].label(), Some("set again here"));
+    assert_eq!(
+        labels[1].offset(),
+        NESTED.1.find("option (foo).bar.a = 3").unwrap()
+    );
+
+    // Setting a message value conflicts with earlier settings of its fields.
+    const AGGREGATE: (&str, &str) = (
+        "root.proto",
+        "import 'google/protobuf/descriptor.proto';\nmessage Foo {\n  optional int32 a = 1;\n}\nextend google.protobuf.FieldOptions {\n  optional Foo foo = 1000;\n}\nmessage Msg {\n  optional int32 f = 1 [(foo).a = 1, (foo) = { a: 2 }];\n}\n",
+    );
+    let err = check(&[AGGREGATE]).unwrap_err();
+    assert_eq!(err.to_string(), "option field '(foo)' has already been set");
+    let labels: Vec<_> = err.labels().unwrap().collect();
+    assert_eq!(labels[0].offset(), AGGREGATE.1.find("(foo).a").unwrap());
+    assert_eq!(labels[1].offset(), AGGREGATE.1.find("(foo) = {").unwrap());
+
+    // Built-in options are also reported.
+    let err = check(&[(
+        "root.proto",
+        "option java_package = 'foo';\noption java_package = 'bar';\n",
+    )])
+    .unwrap_err();
+    assert_eq!(
+        format!("{:?}", err),
+        "root.proto:2:1: option field 'java_package' has already been set"
+    );
+    assert_eq!(err.labels().unwrap().count(), 2);
+}
+
+#[test]
+fn dependents() {
+    let compiler = check(&[
+        ("base.proto", ""),
+        ("other.proto", ""),
+        ("middle.proto", "import 'base.proto';"),
+        ("public.proto", "import public 'other.proto';"),
+        (
+            "root.proto",
+            "import 'middle.proto'; import 'public.proto';",
+        ),
+    ])
+    .unwrap();
+
+    assert_eq!(
+        compiler.dependents("base.proto"),
+        ["middle.proto", "root.proto"]
+    );
+    assert_eq!(
+        compiler.dependents("other.proto"),
+        ["public.proto", "root.proto"]
+    );
+    assert_eq!(compiler.dependents("middle.proto"), ["root.proto"]);
+    assert!(compiler.dependents("root.proto").is_empty());
+    assert!(compiler.dependents("missing.proto").is_empty());
+}
+
+#[test]
+fn invalid_file_option_values() {
+    let err = check(&[("root.proto", "option optimize_for = FOO;")]).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "expected a value of type 'google.protobuf.FileOptions.OptimizeMode' for option 'optimize_for', but found 'FOO'"
+    );
+
+    let err = check(&[("root.proto", "option java_package = 1;")]).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "expected a value of type 'string' for option 'java_package', but found '1'"
+    );
+
+    let err = check(&[("root.proto", "option cc_enable_arenas = 'true';")]).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "expected a value of type 'bool' for option 'cc_enable_arenas', but found '\"true\"'"
+    );
+}
+
+#[test]
+fn invalid_imported_enum_defaults() {
+    const DEP: (&str, &str) = (
+        "dep.proto",
+        "package dep;\nenum E { A = 0; }\nmessage M { enum N { X = 0; } }\n",
+    );
+
+    let err = check(&[
+        DEP,
+        (
+            "root.proto",
+            "import 'dep.proto';\nmessage R { optional dep.E f = 1 [default = B]; }\n",
+        ),
+    ])
+    .unwrap_err();
+    assert_eq!(
+        format!("{:?}", err),
+        "root.proto:2:45: invalid default value 'B' for type 'dep.E'"
+    );
+
+    // Values are looked up in the enum itself, not in the scope which contains it.
+    let err = check(&[
+        DEP,
+        (
+            "root.proto",
+            "import 'dep.proto';\nmessage R { optional dep.M.N f = 1 [default = A]; }\n",
+        ),
+    ])
+    .unwrap_err();
+    assert_eq!(
+        format!("{:?}", err),
+        "root.proto:2:47: invalid default value 'A' for type 'dep.M.N'"
+    );
+}
+
+#[test]
+fn invalid_map_value_types() {
+    const MISSING: (&str, &str) = (
+        "root.proto",
+        "message Foo {\n  message Bar {\n    map<string, Missing> m = 1;\n  }\n}\n",
+    );
+    let err = check(&[MISSING]).unwrap_err();
+    assert_eq!(
+        format!("{:?}", err),
+        "root.proto:3:17: name 'Missing' is not defined"
+    );
+    let span = err.labels().unwrap().next().unwrap();
+    assert_eq!(span.offset(), MISSING.1.find("Missing").unwrap());
+    assert_eq!(span.len(), "Missing".len());
+
+    // Types from files imported by a public import are visible, but not types from other indirect imports.
+    const NOT_IMPORTED: (&str, &str) = (
+        "root.proto",
+        "import 'public.proto';\nmessage Foo {\n  map<int32, dep.Dep> dep = 1;\n  map<int32, other.Other> other = 2;\n}\n",
+    );
+    let err = check(&[
+        ("dep.proto", "package dep; message Dep {}"),
+        ("other.proto", "package other; message Other {}"),
+        (
+            "public.proto",
+            "import public 'dep.proto'; import 'other.proto';",
+        ),
+        NOT_IMPORTED,
+    ])
+    .unwrap_err();
+    assert_eq!(
+        format!("{:?}", err),
+        "root.proto:4:14: name 'other.Other' is not defined"
+    );
+    assert_eq!(
+        err.help().unwrap().to_string(),
+        "'other.Other' is defined in 'other.proto', which is not imported by 'root.proto'"
+    );
+}
+
+#[test]
+fn compile_with_report() {
+    let mut compiler = Compiler::with_file_resolver(TestFileResolver {
+        files: &[
+            ("base.proto", "message Base {}"),
+            (
+                "dep.proto",
+                "import 'base.proto'; message Dep { optional Base base = 1; }",
+            ),
+            (
+                "root.proto",
+                "import 'dep.proto'; message Root { optional Dep dep = 1; }",
+            ),
+            ("other.proto", "import 'dep.proto'; message Other {}"),
+            (
+                "invalid.proto",
+                "message Invalid { optional Missing missing = 1; }",
+            ),
+        ],
+    });
+
+    let (file_descriptor_set, report) = compiler.compile_with_report(["root.proto"]).unwrap();
+    assert_eq!(file_descriptor_set, compiler.file_descriptor_set());
+    assert_eq!(
+        report
+            .files()
+            .iter()
+            .map(|file| file.name())
+            .collect::<Vec<_>>(),
+        ["root.proto", "dep.proto", "base.proto"]
+    );
+    assert_eq!(
+        report.resolve_time(),
+        report.files().iter().map(|file| file.resolve_time()).sum()
+    );
+    assert_eq!(
+        report.parse_time(),
+        report.files().iter().map(|file| file.parse_time()).sum()
+    );
+    assert!(report
+        .files()
+        .iter()
+        .all(|file| file.parse_time() > Duration::ZERO));
+    assert_eq!(
+        report.check_time(),
+        report.files().iter().map(|file| file.check_time()).sum()
+    );
+    assert!(
+        report.resolve_time() + report.parse_time() + report.check_time() <= report.total_time()
+    );
+
+    let (file_descriptor_set, report) = compiler.compile_with_report(["other.proto"]).unwrap();
+    assert_eq!(file_descriptor_set.file.len(), 2);
+    assert_eq!(report.files().len(), 1);
+    assert_eq!(report.files()[0].name(), "other.proto");
+
+    compiler.compile_with_report(["invalid.proto"]).unwrap_err();
+    compiler.open_file("root.proto").unwrap();
+}
+
+#[test]
+fn allow_missing_weak_imports() {
+    let err = check(&[("root.proto", "import weak 'missing.proto'; message Root {}")]).unwrap_err();
+    assert!(err.is_file_not_found());
+    assert_eq!(err.to_string(), "import 'missing.proto' not found");
+
+    let mut compiler = Compiler::with_file_resolver(TestFileResolver {
+        files: &[
+            ("dep.proto", "import weak 'missing.proto'; message Dep {}"),
+            (
+                "root.proto",
+                "import 'dep.proto'; import weak 'missing.proto'; message Root { optional Dep dep = 1; }",
+            ),
+            (
+                "invalid.proto",
+                "import weak 'missing.proto'; message Invalid { optional Missing missing = 1; }",
+            ),
+            ("nested.proto", "import weak 'dep.proto'; import 'missing.proto';"),
+        ],
+    });
+    compiler
+        .allow_missing_weak_imports(true)
+        .include_imports(true)
+        .deny_unused_imports(true)
+        .open_file("root.proto")
+        .unwrap();
+
+    let files = compiler.file_descriptor_set().file;
+    assert_eq!(
+        files.iter().map(|file| file.name()).collect::<Vec<_>>(),
+        ["dep.proto", "root.proto"]
+    );
+    assert_eq!(files[1].dependency, ["dep.proto", "missing.proto"]);
+    assert_eq!(files[1].weak_dependency, [1]);
+    assert_eq!(
+        compiler
+            .check_warnings()
+            .iter()
+            .map(|warning| warning.to_string())
+            .collect::<Vec<_>>(),
+        ["weak import 'missing.proto' not found"]
+    );
+
+    let err = compiler.open_file("invalid.proto").unwrap_err();
+    assert_eq!(err.to_string(), "name 'Missing' is not defined");
+
+    let err = compiler.open_file("nested.proto").unwrap_err();
+    assert_eq!(err.to_string(), "import 'missing.proto' not found");
+}
+
+#[test]
+fn allow_missing_imports() {
+    let mut compiler = Compiler::with_file_resolver(TestFileResolver {
+        files: &[
+            (
+                "root.proto",
+                "package pkg;\nimport 'dep.proto';\nimport 'missing.proto';\nimport 'other.proto';\nmessage Root {\n  optional Local a = 1;\n  optional Missing b = 2;\n  optional foo.Foo c = 3;\n  optional Kind d = 4 [default = KIND_ONE];\n}\nextend .foo.Foo {\n  optional int32 ext = 100;\n}\nservice S {\n  rpc M(Root) returns (bar.Bar);\n}\n",
+            ),
+            ("dep.proto", "package pkg;\nmessage Local {}\n"),
+            (
+                "second.proto",
+                "import 'missing.proto';\nmessage Second {\n  optional pkg.Missing a = 1;\n  optional pkg.Other b = 2;\n  optional int32 c = 3;\n}\n",
+            ),
+        ],
+    });
+
+    let err = compiler.open_file("root.proto").unwrap_err();
+    assert_eq!(err.to_string(), "import 'missing.proto' not found");
+
+    compiler
+        .allow_missing_imports(true)
+        .include_imports(true)
+        .open_file("root.proto")
+        .unwrap();
+
+    let files = compiler.file_descriptor_set().file;
+    assert_eq!(
+        files.iter().map(|file| file.name()).collect::<Vec<_>>(),
+        ["dep.proto", "root.proto"]
+    );
+    let root = &files[1];
+    assert_eq!(
+        root.dependency,
+        ["dep.proto", "missing.proto", "other.proto"]
+    );
+    assert_eq!(
+        root.message_type[0]
+            .field
+            .iter()
+            .map(|field| field.type_name())
+            .collect::<Vec<_>>(),
+        [".pkg.Local", ".pkg.Missing", ".foo.Foo", ".pkg.Kind"]
+    );
+    assert_eq!(root.extension[0].extendee(), ".foo.Foo");
+    // There are more packages than missing imports, so the reference to 'bar.Bar' is removed.
+    assert!(root.service[0].method.is_empty());
+
+    let pool = compiler.descriptor_pool();
+    let kind = pool.get_enum_by_name("pkg.Kind").unwrap();
+    assert_eq!(kind.parent_file().name(), "missing.proto");
+    assert_eq!(kind.values().next().unwrap().name(), "KIND_ONE");
+    let foo = pool.get_message_by_name("foo.Foo").unwrap();
+    assert_eq!(foo.parent_file().name(), "other.proto");
+    assert_eq!(foo.extensions().len(), 1);
+
+    let warnings: Vec<_> = compiler
+        .check_warnings()
+        .iter()
+        .map(|warning| format!("{:?}", warning))
+        .collect();
+    assert_eq!(
+        warnings,
+        [
+            "root.proto:import 'missing.proto' not found",
+            "root.proto:import 'other.proto' not found",
+            "root.proto:7:12: name 'Missing' is not defined",
+            "root.proto:8:12: name 'foo.Foo' is not defined",
+            "root.proto:9:12: name 'Kind' is not defined",
+            "root.proto:11:8: name '.foo.Foo' is not defined",
+            "root.proto:15:24: name 'bar.Bar' is not defined",
+        ]
+    );
+    assert_eq!(
+        compiler.check_warnings()[2].help().unwrap().to_string(),
+        "using a placeholder for 'pkg.Missing' in missing import 'missing.proto'"
+    );
+
+    // Placeholder types were only created for the first importer, so other references are removed.
+    compiler.open_file("second.proto").unwrap();
+    let second = compiler
+        .descriptor_pool()
+        .get_message_by_name("Second")
+        .unwrap();
+    assert_eq!(
+        second
+            .fields()
+            .map(|field| field.name().to_owned())
+            .collect::<Vec<_>>(),
+        ["a", "c"]
+    );
+    let warning = compiler.check_warnings().last().unwrap();
+    assert_eq!(
+        format!("{:?}", warning),
+        "second.proto:4:12: 'pkg.Other' resolves to 'pkg.Other', which is not defined"
+    );
+}
+
+#[test]
+fn compile_on_threads() {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<Compiler>();
+
+    let compiler = Mutex::new(Compiler::with_file_resolver(TestFileResolver {
+        files: &[
+            ("a.proto", "message A {}"),
+            ("b.proto", "message B {}"),
+            ("c.proto", "message C {}"),
+        ],
+    }));
+    thread::scope(|scope| {
+        for name in ["a.proto", "b.proto", "c.proto"] {
+            let compiler = &compiler;
+            scope.spawn(move || {
+                compiler.lock().unwrap().open_file(name).unwrap();
+            });
+        }
+    });
+
+    let compiler = compiler.into_inner().unwrap();
+    let mut names: Vec<_> = compiler.files().map(|file| file.name()).collect();
+    names.sort();
+    assert_eq!(names, ["a.proto", "b.proto", "c.proto"]);
+}
+
+#[test]
+fn option_values() {
+    let mut compiler = Compiler::with_file_resolver(SourceFileResolver::new(&[(
+        "root.proto",
+        "
+            package pkg;
+            import 'google/protobuf/descriptor.proto';
+
+            extend google.protobuf.EnumValueOptions {
+                optional string label = 1001;
+            }
+            extend google.protobuf.MethodOptions {
+                optional int32 timeout = 1002;
+            }
+
+            message Msg {
+                oneof choice {
+                    option (oneof_tag) = 3;
+                    int32 a = 1;
+                }
+            }
+            extend google.protobuf.OneofOptions {
+                optional int32 oneof_tag = 1003;
+            }
+
+            enum Enum {
+                option allow_alias = true;
+                ZERO = 0 [(label) = 'zero'];
+                ALIAS = 0;
+            }
+
+            service Service {
+                rpc Call(Msg) returns (Msg) {
+                    option (timeout) = 30;
+                    option idempotency_level = NO_SIDE_EFFECTS;
+                }
+            }
+        ",
+    )]));
+    compiler.open_file("root.proto").unwrap();
+
+    assert_eq!(
+        compiler.option_value("pkg.ZERO", "(pkg.label)"),
+        Some(Value::String("zero".to_owned()))
+    );
+    assert_eq!(compiler.option_value("pkg.ALIAS", "(pkg.label)"), None);
+    assert_eq!(
+        compiler.option_value(".pkg.Enum", "allow_alias"),
+        Some(Value::Bool(true))
+    );
+    assert_eq!(
+        compiler.option_value("pkg.Msg.choice", ".pkg.oneof_tag"),
+        Some(Value::I32(3))
+    );
+    assert_eq!(
+        compiler.option_value("pkg.Service.Call", "pkg.timeout"),
+        Some(Value::I32(30))
+    );
+    assert_eq!(
+        compiler.option_value("pkg.Service.Call", "idempotency_level"),
+        Some(Value::EnumNumber(1))
+    );
+    assert_eq!(compiler.option_value("pkg.Service.Call", "unknown"), None);
+    assert!(compiler.options_of("pkg.Service").is_some());
+    assert!(compiler.options_of("pkg.Missing").is_none());
+    assert!(compiler.options_of("pkg.Enum.ZERO").is_none());
+}
+
+#[test]
+fn standard_options_round_trip() {
+    let test_data_dir =
+        PathBuf::from(env::var_os("CARGO_MANIFEST_DIR").unwrap()).join("tests/data");
+    let files = ["file_options.proto", "standard_options.proto"];
+    let mut compiler = Compiler::new([&test_data_dir]).unwrap();
+    compiler.open_files(files).unwrap();
+    let expected = compiler.file_descriptor_set();
+
+    let mut round_trip = Compiler::new([&test_data_dir]).unwrap();
+    round_trip
+        .add_file_descriptor_set(expected.clone())
+        .open_files(files)
+        .unwrap();
+    assert_eq!(round_trip.file_descriptor_set(), expected);
+
+    let mut round_trip = Compiler::new([&test_data_dir]).unwrap();
+    round_trip
+        .add_encoded_file_descriptor_set(compiler.encode_file_descriptor_set().as_slice())
+        .unwrap()
+        .open_files(files)
+        .unwrap();
+    assert_eq!(
+        round_trip.encode_file_descriptor_set(),
+        compiler.encode_file_descriptor_set()
+    );
+
+    let options = expected.file[0].options.as_ref().unwrap();
+    assert_eq!(options.php_generic_services, Some(true));
+    assert_eq!(options.java_generic_services, Some(false));
+    assert_eq!(options.deprecated, Some(true));
+}
+
+#[test]
+fn invalid_standard_option_values() {
+    let err = check(&[(
+        "root.proto",
+        "message Foo { optional int32 a = 1 [jstype = JS_STRING]; }",
+    )])
+    .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "jstype is only allowed on int64, uint64, sint64, fixed64 or sfixed64 fields, but 'a' has type 'int32'"
+    );
+    assert_eq!(
+        format!("{:?}", err),
+        "root.proto:1:37: jstype is only allowed on int64, uint64, sint64, fixed64 or sfixed64 fields, but 'a' has type 'int32'"
+    );
+
+    let err = check(&[(
+        "root.proto",
+        "message Foo { extensions 1; } extend Foo { optional string a = 1 [lazy = true]; }",
+    )])
+    .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "'lazy = true' can only be specified for message fields, but 'a' has type 'string'"
+    );
+
+    let err = check(&[(
+        "root.proto",
+        "message Foo { optional int32 a = 1 [packed = true]; }",
+    )])
+    .unwrap_err();
+    assert_eq!(
+        format!("{:?}", err),
+        "root.proto:1:37: 'packed = true' can only be specified for repeated fields, but 'a' is not repeated"
+    );
+    let err = check(&[(
+        "root.proto",
+        "message Foo { repeated string a = 1 [packed = true]; }",
+    )])
+    .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "'packed = true' can only be specified for repeated primitive fields, but 'a' has type 'string'"
+    );
+    check(&[(
+        "root.proto",
+        "enum E { A = 0; } message Foo { repeated E a = 1 [packed = true]; repeated string b = 2 [packed = false]; }",
+    )])
+    .unwrap();
+
+    let err = check(&[(
+        "root.proto",
+        "message Foo { optional int32 a = 1 [ctype = CORD]; }",
+    )])
+    .unwrap_err();
+    assert_eq!(
+        format!("{:?}", err),
+        "root.proto:1:37: ctype is only allowed on string or bytes fields, but 'a' has type 'int32'"
+    );
+    let err = check(&[(
+        "root.proto",
+        "message Foo { extensions 1; } extend Foo { optional bytes a = 1 [ctype = CORD]; }",
+    )])
+    .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "extension 'a' specifies 'ctype = CORD', which is not supported for extensions"
+    );
+    let compiler = check(&[(
+        "root.proto",
+        "message Foo { extensions 1; optional bytes a = 2 [ctype = CORD]; } extend Foo { repeated string b = 1 [ctype = STRING_PIECE]; }",
+    )])
+    .unwrap();
+    let file = compiler.file_descriptor_set().file.pop().unwrap();
+    assert_eq!(
+        file.message_type[0].field[0]
+            .options
+            .as_ref()
+            .unwrap()
+            .ctype(),
+        prost_types::field_options::CType::Cord
+    );
+    assert_eq!(
+        file.extension[0].options.as_ref().unwrap().ctype(),
+        prost_types::field_options::CType::StringPiece
+    );
+
+    let err = check(&[
+        ("dep.proto", "option optimize_for = LITE_RUNTIME;"),
+        ("root.proto", "import 'dep.proto';"),
+    ])
+    .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "files which do not use 'optimize_for = LITE_RUNTIME' cannot import 'dep.proto', which does"
+    );
+    check(&[
+        ("dep.proto", "option optimize_for = LITE_RUNTIME;"),
+        (
+            "root.proto",
+            "import 'dep.proto'; option optimize_for = LITE_RUNTIME;",
+        ),
+    ])
+    .unwrap();
+
+    // Pre-compiled files are checked in the same way.
+    let mut compiler = Compiler::with_file_resolver(TestFileResolver { files: &[] });
+    compiler.add_file_descriptor_set(FileDescriptorSet {
+        file: vec![FileDescriptorProto {
+            name: Some("vendored.proto".to_owned()),
+            message_type: vec![prost_types::DescriptorProto {
+                name: Some("Foo".to_owned()),
+                field: vec![prost_types::FieldDescriptorProto {
+                    name: Some("a".to_owned()),
+                    number: Some(1),
+                    label: Some(prost_types::field_descriptor_proto::Label::Optional as i32),
+                    r#type: Some(prost_types::field_descriptor_proto::Type::Bool as i32),
+                    options: Some(prost_types::FieldOptions {
+                        jstype: Some(prost_types::field_options::JsType::JsNumber as i32),
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }],
+            ..Default::default()
+        }],
+    });
+    let err = compiler.open_file("vendored.proto").unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "jstype is only allowed on int64, uint64, sint64, fixed64 or sfixed64 fields, but 'a' has type 'bool'"
+    );
+}
+
+#[test]
+fn failed_check_is_not_added() {
+    let mut compiler = Compiler::with_file_resolver(TestFileResolver {
+        files: &[
+            ("dep.proto", "message Dep {}"),
+            (
+                "root.proto",
+                "import 'dep.proto'; message A { optional string s = 1 [packed = true]; }",
+            ),
+        ],
+    });
+    compiler.open_file("dep.proto").unwrap();
+
+    let err = compiler.open_file("root.proto").unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "'packed = true' can only be specified for repeated fields, but 's' is not repeated"
+    );
+
+    assert!(compiler
+        .descriptor_pool()
+        .get_message_by_name("A")
+        .is_none());
+    assert!(compiler
+        .descriptor_pool()
+        .get_message_by_name("Dep")
+        .is_some());
+    assert_eq!(compiler.files().count(), 1);
+    assert!(compiler.dependents("dep.proto").is_empty());
+
+    compiler.open_file("root.proto").unwrap_err();
+}
+
+#[test]
+fn synthetic_origins() {
+    let mut compiler = check(&[(
+        "root.proto",
+        "
+            syntax = 'proto3';
+            package pkg;
+
+            message Foo {
+                map<string, Foo> values = 1;
+                optional int32 count = 2;
+                message Nested {
+                    map<int32, int32> ids = 1;
+                }
+            }
+        ",
+    )])
+    .unwrap();
+
+    let origins: Vec<_> = compiler
+        .synthetic_origins()
+        .iter()
+        .map(|origin| {
+            (
+                origin.synthetic().full_name().to_owned(),
+                origin.synthetic().kind(),
+                origin.synthetic().file().to_owned(),
+                origin.field().to_owned(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        origins,
+        [
+            (
+                "pkg.Foo.ValuesEntry".to_owned(),
+                SymbolKind::Message,
+                "root.proto".to_owned(),
+                "pkg.Foo.values".to_owned()
+            ),
+            (
+                "pkg.Foo._count".to_owned(),
+                SymbolKind::Oneof,
+                "root.proto".to_owned(),
+                "pkg.Foo.count".to_owned()
+            ),
+            (
+                "pkg.Foo.Nested.IdsEntry".to_owned(),
+                SymbolKind::Message,
+                "root.proto".to_owned(),
+                "pkg.Foo.Nested.ids".to_owned()
+            ),
+        ]
+    );
+
+    // Like protoc, no locations are generated for the map entry messages or the synthetic oneof.
+    compiler.include_source_info(true);
+    let file = &compiler.file_descriptor_set().file[0];
+    let locations = &file.source_code_info.as_ref().unwrap().location;
+    let message = &file.message_type[0];
+    assert_eq!(message.nested_type[0].name(), "ValuesEntry");
+    assert_eq!(message.oneof_decl[0].name(), "_count");
+    for location in locations {
+        assert!(
+            !location.path.starts_with(&[4, 0, 3, 0]),
+            "unexpected location for map entry: {:?}",
+            location
+        );
+        assert!(
+            !location.path.starts_with(&[4, 0, 8, 0]),
+            "unexpected location for synthetic oneof: {:?}",
+            location
+        );
+        assert!(!location.path.starts_with(&[4, 0, 3, 1, 3, 0]));
+    }
+}
+
+#[test]
+fn embed_to_out_dir() {
+    let tempdir = TempDir::new().unwrap();
+    fs::write(tempdir.path().join("dep.proto"), "message Dep {}").unwrap();
+    fs::write(
+        tempdir.path().join("root.proto"),
+        "import 'dep.proto'; message Root { optional Dep dep = 1; }",
+    )
+    .unwrap();
+    let out_dir = tempdir.path().join("out");
+    fs::create_dir(&out_dir).unwrap();
+
+    // No other test reads OUT_DIR, so setting it here is not racy.
+    env::remove_var("OUT_DIR");
+    let err =
+        protox::embed::compile_to_out_dir(["root.proto"], [tempdir.path()], "fds.bin").unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "OUT_DIR is not set, this must be called from a build script"
+    );
+
+    env::set_var("OUT_DIR", &out_dir);
+    let path =
+        protox::embed::compile_to_out_dir(["root.proto"], [tempdir.path()], "fds.bin").unwrap();
+    env::remove_var("OUT_DIR");
+    assert_eq!(path, out_dir.join("fds.bin"));
+
+    let pool = DescriptorPool::decode(fs::read(&path).unwrap().as_slice()).unwrap();
+    assert!(pool.get_message_by_name("Root").is_some());
+    assert!(pool.get_message_by_name("Dep").is_some());
+    assert!(pool
+        .files()
+        .all(|file| file.file_descriptor_proto().source_code_info.is_none()));
+}
+
+#[test]
+fn oneof_options() {
+    let mut compiler = Compiler::with_file_resolver(SourceFileResolver::new(&[(
+        "root.proto",
+        "
+            import 'google/protobuf/descriptor.proto';
+
+            extend google.protobuf.OneofOptions {
+                optional int32 oneof_opt = 1000;
+            }
+            extend google.protobuf.FieldOptions {
+                optional int32 field_opt = 1000;
+            }
+
+            message Foo {
+                oneof choice {
+                    option (oneof_opt) = 5;
+                    int32 a = 1;
+                }
+            }
+        ",
+    )]));
+    compiler.open_file("root.proto").unwrap();
+    assert_eq!(
+        compiler.option_value("Foo.choice", "(oneof_opt)"),
+        Some(Value::I32(5))
+    );
+
+    let err = check(&[(
+        "root.proto",
+        "message Foo { oneof x { option foo = 1; int32 a = 1; } }",
+    )])
+    .unwrap_err();
+    assert_eq!(
+        format!("{:?}", err),
+        "root.proto:1:25: option field 'foo' is not defined"
+    );
+
+    let err = check(&[(
+        "root.proto",
+        "message Foo { oneof x { option (unknown) = 1; int32 a = 1; } }",
+    )])
+    .unwrap_err();
+    assert_eq!(
+        format!("{:?}", err),
+        "root.proto:1:25: name 'unknown' is not defined"
+    );
+
+    let mut compiler = Compiler::with_file_resolver(SourceFileResolver::new(&[(
+        "root.proto",
+        "
+            import 'google/protobuf/descriptor.proto';
+
+            extend google.protobuf.FieldOptions {
+                optional int32 field_opt = 1000;
+            }
+
+            message Foo {
+                oneof choice {
+                    option (field_opt) = 5;
+                    int32 a = 1;
+                }
+            }
+        ",
+    )]));
+    let err = compiler.open_file("root.proto").unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "expected an extension to type 'google.protobuf.OneofOptions', but 'field_opt' extends 'google.protobuf.FieldOptions'"
+    );
+}
+
+#[test]
+fn lenient_check_mode() {
+    let files = &[(
+        "root.proto",
+        "message Foo {\n  optional Missing a = 1;\n  optional int32 b = 2;\n  optional int32 c = 2;\n  oneof o { Missing d = 4; }\n}\nmessage Bar {\n  optional int32 x = 1 [jstype = JS_STRING];\n}\nservice S {\n  rpc M(Missing) returns (Bar);\n  rpc N(Bar) returns (Bar);\n}\n",
+    )];
+
+    let err = Compiler::with_file_resolver(TestFileResolver { files })
+        .open_file("root.proto")
+        .unwrap_err();
+    assert_eq!(err.file(), Some("root.proto"));
+
+    let mut compiler = Compiler::with_file_resolver(TestFileResolver { files });
+    compiler
+        .check_mode(CheckMode::Lenient)
+        .include_source_info(true)
+        .open_file("root.proto")
+        .unwrap();
+
+    let errors: Vec<_> = compiler
+        .check_errors()
+        .iter()
+        .map(|err| format!("{:?}", err))
+        .collect();
+    assert_eq!(
+        errors,
+        [
+            "root.proto:2:12: name 'Missing' is not defined",
+            "root.proto:4:22: field number '2' is already used",
+            "root.proto:5:13: name 'Missing' is not defined",
+            "root.proto:11:9: name 'Missing' is not defined",
+            "root.proto:8:25: jstype is only allowed on int64, uint64, sint64, fixed64 or sfixed64 fields, but 'x' has type 'int32'",
+            "root.proto:5:9: oneof 'o' must have at least one field",
+        ]
+    );
+
+    let file = &compiler.file_descriptor_set().file[0];
+    let foo = &file.message_type[0];
+    assert_eq!(foo.field.len(), 1);
+    assert_eq!(foo.field[0].name(), "b");
+    assert_eq!(file.message_type[1].field[0].name(), "x");
+    assert_eq!(file.service[0].method.len(), 1);
+    assert_eq!(file.service[0].method[0].name(), "N");
+
+    // Source locations of the remaining elements are renumbered.
+    let locations = &file.source_code_info.as_ref().unwrap().location;
+    let b = locations
+        .iter()
+        .find(|location| location.path == [4, 0, 2, 0])
+        .unwrap();
+    assert_eq!(b.span, [2, 2, 23]);
+    assert!(!locations
+        .iter()
+        .any(|location| location.path.starts_with(&[4, 0, 2, 1])));
+}
+
+#[test]
+fn add_reader() {
+    let mut compiler = Compiler::with_file_resolver(TestFileResolver {
+        files: &[("dep.proto", "package dep;\nmessage Dep {}\n")],
+    });
+    compiler
+        .add_reader(
+            "piped/root.proto",
+            "import 'dep.proto';\nmessage Root {\n  optional dep.Dep a = 1;\n}\n".as_bytes(),
+        )
+        .unwrap();
+
+    let files = compiler.file_descriptor_set().file;
+    assert_eq!(files.len(), 1);
+    assert_eq!(files[0].name(), "piped/root.proto");
+    assert_eq!(files[0].dependency, ["dep.proto"]);
+
+    let err = compiler
+        .add_reader(
+            "piped/invalid.proto",
+            "message Invalid {\n  optional Missing a = 1;\n}\n".as_bytes(),
+        )
+        .unwrap_err();
+    assert_eq!(err.file(), Some("piped/invalid.proto"));
+    assert_eq!(
+        format!("{:?}", err),
+        "piped/invalid.proto:2:12: name 'Missing' is not defined"
+    );
+}
+
+#[test]
+fn duplicate_extension_numbers() {
+    const FILES: &[(&str, &str)] = &[
+        (
+            "base.proto",
+            "package pkg;\nmessage Base {\n  extensions 10 to 20;\n  extensions 100 to 200;\n}\n",
+        ),
+        (
+            "a.proto",
+            "package a;\nimport 'base.proto';\nextend pkg.Base {\n  optional int32 a = 100;\n}\n",
+        ),
+        (
+            "b.proto",
+            "package b;\nimport 'base.proto';\nmessage B {\n  extend pkg.Base {\n    optional int32 b = 100;\n  }\n}\n",
+        ),
+        (
+            "c.proto",
+            "package c;\nimport 'base.proto';\nextend pkg.Base {\n  optional int32 c = 10;\n  optional int32 d = 10;\n}\n",
+        ),
+    ];
+
+    // The files do not import each other, but both extensions are reported.
+    let mut compiler = Compiler::with_file_resolver(TestFileResolver { files: FILES });
+    compiler.open_file("a.proto").unwrap();
+    let err = compiler.open_file("b.proto").unwrap_err();
+    assert_eq!(
+        format!("{:?}", err),
+        "b.proto:5:24: extension number '100' of 'pkg.Base' is already used by extension 'a.a' in file 'a.proto'"
+    );
+    let related: Vec<_> = err.related().unwrap().collect();
+    assert_eq!(
+        related[0].to_string(),
+        "extension 'a.a' is defined in file 'a.proto'"
+    );
+    let labels: Vec<_> = related[0].labels().unwrap().collect();
+    assert_eq!(labels[0].offset(), FILES[1].1.find("100").unwrap());
+    assert_eq!(
+        related[1].to_string(),
+        "'pkg.Base' declares extensions 100 to 200 in file 'base.proto'"
+    );
+    let labels: Vec<_> = related[1].labels().unwrap().collect();
+    assert_eq!(labels[0].offset(), FILES[0].1.find("100 to 200").unwrap());
+
+    // Within a file, the second extension is reported.
+    let err = compiler.open_file("c.proto").unwrap_err();
+    assert_eq!(
+        format!("{:?}", err),
+        "c.proto:5:22: extension number '10' of 'pkg.Base' is already used by extension 'c.c' in file 'c.proto'"
+    );
+    let related: Vec<_> = err.related().unwrap().collect();
+    assert_eq!(
+        related[1].to_string(),
+        "'pkg.Base' declares extensions 10 to 20 in file 'base.proto'"
+    );
+}
+
+#[test]
+fn check_file_only() {
+    let mut compiler = Compiler::with_file_resolver(TestFileResolver {
+        files: &[
+            ("dep.proto", "package dep;\nmessage Dep {}\n"),
+            ("unused.proto", "package unused;\nmessage Unused {}\n"),
+            (
+                "root.proto",
+                "import 'dep.proto';\nimport 'unused.proto';\nmessage Root {\n  optional dep.Dep a = 1;\n}\n",
+            ),
+            (
+                "invalid.proto",
+                "import 'dep.proto';\nmessage Invalid {\n  optional dep.Missing a = 1;\n}\n",
+            ),
+        ],
+    });
+    compiler.open_file("dep.proto").unwrap();
+
+    let diagnostics = compiler.check_file_only("root.proto").unwrap();
+    assert_eq!(diagnostics.file_descriptor_proto().name(), "root.proto");
+    assert_eq!(
+        diagnostics.file_descriptor_proto().message_type[0].field[0].type_name(),
+        ".dep.Dep"
+    );
+    assert_eq!(diagnostics.unused_imports().len(), 1);
+    assert_eq!(diagnostics.unused_imports()[0].name(), "unused.proto");
+    assert!(diagnostics.warnings().is_empty());
+    assert!(diagnostics.errors().is_empty());
+
+    // Neither the file nor its new import were added.
+    assert_eq!(compiler.files().count(), 1);
+    assert!(compiler.unused_imports().is_empty());
+    assert!(compiler
+        .descriptor_pool()
+        .get_file_by_name("root.proto")
+        .is_none());
+    assert!(compiler
+        .descriptor_pool()
+        .get_file_by_name("unused.proto")
+        .is_none());
+
+    let err = compiler.check_file_only("invalid.proto").unwrap_err();
+    assert_eq!(
+        format!("{:?}", err),
+        "invalid.proto:3:12: 'dep.Missing' resolves to 'dep.Missing', which is not defined"
+    );
+    assert!(compiler
+        .descriptor_pool()
+        .get_file_by_name("invalid.proto")
+        .is_none());
+
+    // The file can be checked again, and then compiled.
+    compiler.check_file_only("root.proto").unwrap();
+    compiler.open_file("root.proto").unwrap();
+    assert_eq!(compiler.unused_imports().len(), 1);
+
+    let err = compiler.check_file_only("root.proto").unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "file 'root.proto' has already been compiled"
+    );
+}
+
+#[test]
+fn descriptor_set_formats() {
+    let mut compiler = Compiler::with_file_resolver(SourceFileResolver::new(&[(
+        "root.proto",
+        "import 'google/protobuf/descriptor.proto';\nextend google.protobuf.MessageOptions {\n  optional string tag = 5000;\n}\nmessage Foo {\n  option (tag) = 'x';\n  optional int32 a_b = 1;\n}\n",
+    )]));
+    compiler.open_file("root.proto").unwrap();
+
+    let descriptor_set = compiler.descriptor_set();
+    assert_eq!(
+        descriptor_set.encode_to_vec(),
+        compiler.encode_file_descriptor_set()
+    );
+    assert_eq!(
+        descriptor_set.to_file_descriptor_set(),
+        compiler.file_descriptor_set()
+    );
+
+    let text = descriptor_set.to_text_format();
+    assert!(text.contains("options {\n      [tag]: \"x\"\n    }"));
+    let parsed =
+        protox::format::DescriptorSet::from_text_format(&compiler.descriptor_pool(), &text)
+            .unwrap();
+    assert_eq!(parsed, descriptor_set);
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("out.pbtxt");
+    compiler
+        .write_file_descriptor_set_as(&path, protox::format::OutputFormat::Text)
+        .unwrap();
+    assert_eq!(fs::read_to_string(&path).unwrap(), text);
+
+    #[cfg(feature = "serde")]
+    {
+        let json = descriptor_set.to_json();
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(
+            value["file"][0]["messageType"][0]["field"][0]["jsonName"],
+            "aB"
+        );
+        assert_eq!(value["file"][0]["messageType"][0]["options"]["[tag]"], "x");
+
+        let parsed =
+            protox::format::DescriptorSet::from_json(&compiler.descriptor_pool(), &json).unwrap();
+        assert_eq!(parsed, descriptor_set);
+
+        // Without the pool, custom options cannot be deserialized.
+        assert!(serde_json::from_str::<protox::format::DescriptorSet>(&json).is_err());
+    }
+}
+
+#[test]
+fn references() {
+    const FILES: &[(&str, &str)] = &[
+        (
+            "dep.proto",
+            "syntax = 'proto2';
+            package dep;
+            import 'google/protobuf/descriptor.proto';
+            message Dep { optional int32 value = 1; }
+            extend google.protobuf.FieldOptions { optional Dep dep = 5000; }",
+        ),
+        (
+            "root.proto",
+            "syntax = 'proto2';
+            package root;
+            import 'dep.proto';
+            message Foo {
+                optional dep.Dep a = 1 [(dep.dep).value = 2];
+                map<string, .dep.Dep> b = 2;
+            }
+            service Service {
+                rpc Get(dep.Dep) returns (Foo);
+            }",
+        ),
+    ];
+
+    let mut resolver = ChainFileResolver::new();
+    resolver.add(TestFileResolver { files: FILES });
+    resolver.add(GoogleFileResolver::new());
+
+    let mut compiler = Compiler::with_file_resolver(resolver);
+    compiler.open_file("root.proto").unwrap();
+
+    let references = |name: &str| -> Vec<(&str, &str, ReferenceKind)> {
+        compiler
+            .references(name)
+            .into_iter()
+            .filter_map(|location| {
+                // References made by imported well-known types are not checked.
+                let (file, source) = FILES.iter().find(|(file, _)| *file == location.file())?;
+                let span = location.span();
+                Some((
+                    *file,
+                    &source[span.offset()..span.offset() + span.len()],
+                    location.kind(),
+                ))
+            })
+            .collect()
+    };
+
+    assert_eq!(
+        references(".dep.Dep"),
+        [
+            ("dep.proto", "Dep", ReferenceKind::FieldType),
+            ("root.proto", "dep.Dep", ReferenceKind::FieldType),
+            ("root.proto", ".dep.Dep", ReferenceKind::FieldType),
+            ("root.proto", "dep.Dep", ReferenceKind::MethodInput),
+        ]
+    );
+    assert_eq!(
+        references("dep.dep"),
+        [("root.proto", "dep.dep", ReferenceKind::OptionExtension)]
+    );
+    assert_eq!(
+        references("dep.Dep.value"),
+        [("root.proto", "value", ReferenceKind::OptionField)]
+    );
+    assert_eq!(
+        references("google.protobuf.FieldOptions"),
+        [(
+            "dep.proto",
+            "google.protobuf.FieldOptions",
+            ReferenceKind::Extendee
+        )]
+    );
+    assert_eq!(references("root.Missing"), []);
+}
+
+#[test]
+fn filtered_file_descriptor_set() {
+    const FILES: &[(&str, &str)] = &[
+        ("common.proto", "package common; message Id {}"),
+        ("unused.proto", "package unused; message Unused {}"),
+        (
+            "foo.proto",
+            "package foo;
+            import 'unused.proto';
+            import public 'common.proto';
+            message Foo {}",
+        ),
+        (
+            "foo_v1.proto",
+            "package foo.v1;
+            import 'foo.proto';
+            message Foo { optional foo.Foo foo = 1; }",
+        ),
+        (
+            "bar.proto",
+            "package bar;
+            import 'unused.proto';
+            import 'common.proto';
+            message Bar { optional common.Id id = 1; }
+            message Other {}",
+        ),
+    ];
+
+    let mut compiler = Compiler::with_file_resolver(TestFileResolver { files: FILES });
+    compiler.include_source_info(true);
+    compiler.open_files(["foo_v1.proto", "bar.proto"]).unwrap();
+
+    let filtered = |names: &[&str]| -> Result<Vec<FileDescriptorProto>, String> {
+        let mut filter = DescriptorFilter::new();
+        for name in names {
+            filter.include(*name);
+        }
+        compiler
+            .filtered_file_descriptor_set(&filter)
+            .map(|set| set.file)
+            .map_err(|err| err.to_string())
+    };
+    let file_names = |files: &[FileDescriptorProto]| -> Vec<String> {
+        files.iter().map(|file| file.name().to_owned()).collect()
+    };
+
+    let files = filtered(&["foo"]).unwrap();
+    assert_eq!(
+        file_names(&files),
+        ["common.proto", "foo.proto", "foo_v1.proto"]
+    );
+    let foo = &files[1];
+    assert_eq!(foo.dependency, ["common.proto"]);
+    assert_eq!(foo.public_dependency, [0]);
+    let import_paths: Vec<&[i32]> = foo
+        .source_code_info
+        .as_ref()
+        .unwrap()
+        .location
+        .iter()
+        .map(|location| location.path.as_slice())
+        .filter(|path| matches!(path, [3 | 10, ..]))
+        .collect();
+    assert_eq!(import_paths, [&[3, 0][..], &[10, 0][..]]);
+
+    let files = filtered(&[".bar.Bar"]).unwrap();
+    assert_eq!(file_names(&files), ["common.proto", "bar.proto"]);
+    assert_eq!(files[1].dependency, ["common.proto"]);
+
+    assert_eq!(
+        file_names(&filtered(&["foo.v1.Foo.foo", "common"]).unwrap()),
+        ["common.proto", "foo.proto", "foo_v1.proto"]
+    );
+    assert_eq!(
+        filtered(&["foo.Missing"]),
+        Err("cannot filter descriptors by 'foo.Missing': it is not defined".to_owned())
+    );
+}
+
+#[test]
+fn filtered_file_descriptor_set_prune_types() {
+    const FILES: &[(&str, &str)] = &[
+        (
+            "common.proto",
+            "package common;
+            message Id {}
+            message Unused {}
+            enum Kind { KIND_UNSPECIFIED = 0; }",
+        ),
+        (
+            "opts.proto",
+            "syntax = 'proto2';
+            package opts;
+            import 'google/protobuf/descriptor.proto';
+            extend google.protobuf.MessageOptions { optional string label = 5000; }",
+        ),
+        ("fwd.proto", "package fwd; import public 'common.proto';"),
+        (
+            "api.proto",
+            "package api;
+            import 'fwd.proto';
+            import 'opts.proto';
+            message Other { optional common.Unused unused = 1; }
+            message Request {
+                option (opts.label) = 'request';
+                optional common.Id id = 1;
+                message Nested { optional common.Kind kind = 1; }
+            }
+            service Api { rpc Get(Request) returns (Request); }",
+        ),
+    ];
+
+    let mut resolver = ChainFileResolver::new();
+    resolver.add(TestFileResolver { files: FILES });
+    resolver.add(GoogleFileResolver::new());
+
+    let mut compiler = Compiler::with_file_resolver(resolver);
+    compiler.include_source_info(true);
+    compiler.open_file("api.proto").unwrap();
+
+    let filtered = |filter: &DescriptorFilter| -> Vec<FileDescriptorProto> {
+        let set = compiler.filtered_file_descriptor_set(filter).unwrap();
+        DescriptorPool::from_file_descriptor_set(set.clone()).unwrap();
+        set.file
+    };
+    let definitions = |file: &FileDescriptorProto| -> Vec<String> {
+        file.message_type
+            .iter()
+            .map(|message| message.name().to_owned())
+            .chain(file.enum_type.iter().map(|enum_| enum_.name().to_owned()))
+            .chain(file.service.iter().map(|service| service.name().to_owned()))
+            .chain(
+                file.extension
+                    .iter()
+                    .map(|extension| extension.name().to_owned()),
+            )
+            .collect()
+    };
+
+    let files = filtered(DescriptorFilter::new().include("api.Api").prune_types(true));
+    let summary: Vec<_> = files
+        .iter()
+        .map(|file| (file.name(), file.dependency.clone(), definitions(file)))
+        .collect();
+    assert_eq!(
+        summary,
+        [
+            (
+                "common.proto",
+                vec![],
+                vec!["Id".to_owned(), "Kind".to_owned()]
+            ),
+            ("fwd.proto", vec!["common.proto".to_owned()], vec![]),
+            (
+                "api.proto",
+                vec!["fwd.proto".to_owned()],
+                vec!["Request".to_owned(), "Api".to_owned()]
+            ),
+        ]
+    );
+    let api = &files[2];
+    assert!(
+        api.source_code_info
+            .as_ref()
+            .unwrap()
+            .location
+            .iter()
+            .all(|location| !location.path.starts_with(&[4, 1])
+                && !location.path.starts_with(&[3, 1]))
+    );
+
+    let files = filtered(
+        DescriptorFilter::new()
+            .include("api.Request")
+            .prune_types(true)
+            .keep_custom_options(true),
+    );
+    let api = files.last().unwrap();
+    assert_eq!(api.dependency, ["fwd.proto", "opts.proto"]);
+    assert_eq!(definitions(api), ["Request"]);
+    let opts = files
+        .iter()
+        .find(|file| file.name() == "opts.proto")
+        .unwrap();
+    assert_eq!(definitions(opts), ["label"]);
+
+    let files = filtered(
+        DescriptorFilter::new()
+            .include("common.KIND_UNSPECIFIED")
+            .prune_types(true),
+    );
+    assert_eq!(files.len(), 1);
+    assert_eq!(definitions(&files[0]), ["Kind"]);
+}
+
+#[derive(Default)]
+struct RecordingObserver {
+    events: Arc<Mutex<Vec<String>>>,
+}
+
+impl CompilerObserver for RecordingObserver {
+    fn on_file_resolved(&self, name: &str, _path: Option<&Path>) {
+        self.events.lock().unwrap().push(format!("resolved {name}"));
+    }
+
+    fn on_file_parsed(&self, file: &FileDescriptorProto) {
+        self.events
+            .lock()
+            .unwrap()
+            .push(format!("parsed {}", file.name()));
+    }
+
+    fn on_file_checked(&self, file: &FileDescriptor) {
+        self.events
+            .lock()
+            .unwrap()
+            .push(format!("checked {}", file.name()));
+    }
+
+    fn on_error(&self, error: &Error) {
+        self.events.lock().unwrap().push(format!("error {error}"));
+    }
+}
+
+#[test]
+fn observer() {
+    let observer = RecordingObserver::default();
+    let events = observer.events.clone();
+    let mut compiler = Compiler::with_file_resolver(TestFileResolver {
+        files: &[
+            ("dep.proto", "syntax = 'proto3'; message Dep {}"),
+            (
+                "root.proto",
+                "syntax = 'proto3'; import 'dep.proto'; message Root { Dep dep = 1; }",
+            ),
+            (
+                "invalid.proto",
+                "syntax = 'proto3'; message Foo { Bar bar = 1; }",
+            ),
+        ],
+    });
+    compiler.add_observer(observer);
+
+    compiler.open_file("root.proto").unwrap();
+    assert_eq!(
+        *events.lock().unwrap(),
+        [
+            "resolved root.proto",
+            "parsed root.proto",
+            "resolved dep.proto",
+            "parsed dep.proto",
+            "checked dep.proto",
+            "checked root.proto",
+        ]
+    );
+
+    events.lock().unwrap().clear();
+    compiler.open_file("invalid.proto").unwrap_err();
+    assert_eq!(
+        *events.lock().unwrap(),
+        [
+            "resolved invalid.proto",
+            "parsed invalid.proto",
+            "error name 'Bar' is not defined",
+        ]
+    );
+
+    events.lock().unwrap().clear();
+    compiler.check_mode(CheckMode::Lenient);
+    compiler.open_file("invalid.proto").unwrap();
+    assert_eq!(
+        *events.lock().unwrap(),
+        [
+            "resolved invalid.proto",
+            "parsed invalid.proto",
+            "error name 'Bar' is not defined",
+            "checked invalid.proto",
+        ]
+    );
+}
+
+#[test]
+fn byte_compatible() {
+    let tempdir = tempfile::tempdir().unwrap();
+    fs::write(
+        tempdir.path().join("root.proto"),
+        "syntax = 'proto2';
+        import 'google/protobuf/descriptor.proto';
+        message Rule {
+            optional string name = 1;
+            optional int32 weight = 2;
+        }
+        extend google.protobuf.MessageOptions {
+            optional Rule rule = 50000;
+            optional int32 first = 50001;
+        }
+        message Foo {
+            option (first) = 1;
+            option (rule).name = 'a';
+            option deprecated = true;
+            option (rule).weight = 2;
+            optional group Bar = 1 [deprecated = true] {
+                optional int32 a = 1;
+            }
+        }",
+    )
+    .unwrap();
+
+    let mut compiler = Compiler::new([tempdir.path()]).unwrap();
+    compiler
+        .include_source_info(true)
+        .byte_compatible(true)
+        .open_file("root.proto")
+        .unwrap();
+    let encoded = compiler.encode_file("root.proto").unwrap();
+    let file = FileDescriptorProto::decode(encoded.as_slice()).unwrap();
+
+    let paths: Vec<_> = file
+        .source_code_info
+        .unwrap()
+        .location
+        .into_iter()
+        .map(|location| location.path)
+        .skip_while(|path| path != &[4, 1])
+        .collect();
+    assert_eq!(
+        paths,
+        [
+            vec![4, 1],
+            vec![4, 1, 1],
+            vec![4, 1, 7],
+            vec![4, 1, 7, 50001],
+            vec![4, 1, 7],
+            vec![4, 1, 7, 50000, 1],
+            vec![4, 1, 7],
+            vec![4, 1, 7, 3],
+            vec![4, 1, 7],
+            vec![4, 1, 7, 50000, 2],
+            vec![4, 1, 2, 0],
+            vec![4, 1, 2, 0, 4],
+            vec![4, 1, 2, 0, 5],
+            vec![4, 1, 2, 0, 1],
+            vec![4, 1, 2, 0, 3],
+            vec![4, 1, 2, 0, 8],
+            vec![4, 1, 2, 0, 8, 3],
+            vec![4, 1, 3, 0],
+            vec![4, 1, 3, 0, 1],
+            vec![4, 1, 2, 0, 6],
+            vec![4, 1, 3, 0, 2, 0],
+            vec![4, 1, 3, 0, 2, 0, 4],
+            vec![4, 1, 3, 0, 2, 0, 5],
+            vec![4, 1, 3, 0, 2, 0, 1],
+            vec![4, 1, 3, 0, 2, 0, 3],
+        ]
+    );
+
+    // The standard options, followed by one entry for each custom option statement in source order.
+    let options: &[u8] = &[
+        0x3a, 0x13, 0x18, 0x01, 0x88, 0xb5, 0x18, 0x01, 0x82, 0xb5, 0x18, 0x03, 0x0a, 0x01, b'a',
+        0x82, 0xb5, 0x18, 0x02, 0x10, 0x02,
+    ];
+    assert!(encoded
+        .windows(options.len())
+        .any(|window| window == options));
+
+    compiler.byte_compatible(false);
+    let encoded = compiler.encode_file("root.proto").unwrap();
+    assert!(!encoded
+        .windows(options.len())
+        .any(|window| window == options));
+}
+
+#[test]
+fn descriptor_verification() {
+    fn field(name: &str, number: i32, ty: Type, type_name: Option<&str>) -> FieldDescriptorProto {
+        FieldDescriptorProto {
+            name: Some(name.to_owned()),
+            number: Some(number),
+            label: Some(Label::Optional as i32),
+            r#type: Some(ty as i32),
+            type_name: type_name.map(ToOwned::to_owned),
+            ..Default::default()
+        }
+    }
+
+    fn compile(mode: DescriptorVerification, file: FileDescriptorProto) -> Result<(), Error> {
+        let mut compiler = Compiler::with_file_resolver(TestFileResolver {
+            files: &[(
+                "root.proto",
+                "import 'dep.proto'; message Root { optional Dep dep = 1; }",
+            )],
+        });
+        compiler.descriptor_verification(mode);
+        compiler.add_file_descriptor_set(FileDescriptorSet {
+            file: vec![FileDescriptorProto {
+                name: Some("dep.proto".to_owned()),
+                message_type: [
+                    vec![DescriptorProto {
+                        name: Some("Dep".to_owned()),
+                        ..Default::default()
+                    }],
+                    file.message_type,
+                ]
+                .concat(),
+                ..file
+            }],
+        });
+        compiler.open_file("root.proto").map(drop)
+    }
+
+    // Returns the results in strict and trusting modes.
+    let check = |file: FileDescriptorProto| {
+        let strict = compile(DescriptorVerification::Strict, file.clone());
+        let trusted = compile(DescriptorVerification::TrustInput, file);
+        (
+            strict.map_err(|err| err.to_string()),
+            trusted.map_err(|err| err.to_string()),
+        )
+    };
+    let message = |fields: Vec<FieldDescriptorProto>| DescriptorProto {
+        name: Some("Foo".to_owned()),
+        field: fields,
+        ..Default::default()
+    };
+
+    // Checked by the descriptor pool in both modes.
+    assert_eq!(
+        check(FileDescriptorProto {
+            message_type: vec![message(vec![
+                field("a", 1, Type::Int32, None),
+                field("b", 1, Type::Int32, None),
+            ])],
+            ..Default::default()
+        }),
+        (
+            Err("field number '1' is already used".to_owned()),
+            Err("field number '1' is already used".to_owned())
+        )
+    );
+    assert_eq!(
+        check(FileDescriptorProto {
+            message_type: vec![message(vec![field(
+                "a",
+                1,
+                Type::Message,
+                Some(".Missing")
+            )])],
+            ..Default::default()
+        }),
+        (
+            Err("name '.Missing' is not defined".to_owned()),
+            Err("name '.Missing' is not defined".to_owned())
+        )
+    );
+
+    // Only checked in strict mode.
+    let mut required = field("a", 1, Type::Int32, None);
+    required.label = Some(Label::Required as i32);
+    assert_eq!(
+        check(FileDescriptorProto {
+            syntax: Some("proto3".to_owned()),
+            message_type: vec![message(vec![required])],
+            ..Default::default()
+        }),
+        (
+            Err("required field 'Foo.a' is not allowed in proto3".to_owned()),
+            Ok(())
+        )
+    );
+    assert_eq!(
+        check(FileDescriptorProto {
+            message_type: vec![DescriptorProto {
+                reserved_name: vec!["a".to_owned()],
+                ..message(vec![field("a", 1, Type::Int32, None)])
+            }],
+            ..Default::default()
+        }),
+        (
+            Err("field name 'a' is reserved in message 'Foo'".to_owned()),
+            Ok(())
+        )
+    );
+    assert_eq!(
+        check(FileDescriptorProto {
+            message_type: vec![message(vec![field("a-b", 1, Type::Int32, None)])],
+            ..Default::default()
+        }),
+        (Err("field name 'Foo.a-b' is not valid".to_owned()), Ok(()))
+    );
+    assert_eq!(
+        check(FileDescriptorProto {
+            enum_type: vec![EnumDescriptorProto {
+                name: Some("Kind".to_owned()),
+                value: vec![EnumValueDescriptorProto {
+                    name: Some("KIND_UNSPECIFIED".to_owned()),
+                    number: Some(0),
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }],
+            message_type: vec![message(vec![field("a", 1, Type::Message, Some(".Kind"))])],
+            ..Default::default()
+        }),
+        (
+            Err("field 'Foo.a' refers to 'Kind', which is not a message type".to_owned()),
+            Ok(())
+        )
+    );
+    let err = compile(
+        DescriptorVerification::Strict,
+        FileDescriptorProto {
+            syntax: Some("proto4".to_owned()),
+            ..Default::default()
+        },
+    )
+    .unwrap_err();
+    assert_eq!(err.file(), Some("dep.proto"));
+    assert_eq!(err.to_string(), "unknown syntax 'proto4'");
+
+    let mut compiler = Compiler::with_file_resolver(TestFileResolver {
+        files: &[(
+            "root.proto",
+            "import 'dep.proto'; message Root { optional Dep dep = 1; }",
+        )],
+    });
+    compiler.check_mode(CheckMode::Lenient);
+    compiler.add_file_descriptor_set(FileDescriptorSet {
+        file: vec![FileDescriptorProto {
+            name: Some("dep.proto".to_owned()),
+            message_type: vec![
+                DescriptorProto {
+                    name: Some("Dep".to_owned()),
+                    ..Default::default()
+                },
+                message(vec![field("a-b", 1, Type::Int32, None)]),
+            ],
+            ..Default::default()
+        }],
+    });
+    compiler.open_file("root.proto").unwrap();
+    assert_eq!(
+        compiler
+            .check_errors()
+            .iter()
+            .map(|err| format!("{:?}", err))
+            .collect::<Vec<_>>(),
+        ["dep.proto:field name 'Foo.a-b' is not valid"]
+    );
+
+    // The output of protox is accepted in strict mode, including optional extensions in proto3 files.
+    let test_data_dir =
+        PathBuf::from(env::var_os("CARGO_MANIFEST_DIR").unwrap()).join("tests/data");
+    let mut compiler = Compiler::new([&test_data_dir]).unwrap();
+    compiler.include_imports(true);
+    compiler.open_file("service_options.proto").unwrap();
+    let file_descriptor_set = compiler.file_descriptor_set();
+    assert!(file_descriptor_set
+        .file
+        .iter()
+        .flat_map(|file| &file.extension)
+        .any(|extension| extension.proto3_optional()));
+
+    let mut compiler = Compiler::new([&test_data_dir]).unwrap();
+    compiler.descriptor_verification(DescriptorVerification::Strict);
+    compiler.add_file_descriptor_set(file_descriptor_set);
+    compiler.open_file("service_options.proto").unwrap();
+}
+
+#[test]
+fn snapshot_restore() {
+    let mut compiler = Compiler::with_file_resolver(TestFileResolver {
+        files: &[
+            ("dep.proto", "package dep;\nmessage Dep {}\n"),
+            ("unused.proto", "package unused;\nmessage Unused {}\n"),
+            (
+                "root.proto",
+                "import 'dep.proto';\nimport 'unused.proto';\nmessage Root {\n  optional dep.Dep a = 1;\n}\n",
+            ),
+        ],
+    });
+    compiler.open_file("root.proto").unwrap();
+    let snapshot = compiler.snapshot();
+
+    for _ in 0..2 {
+        compiler.open_file("dep.proto").unwrap();
+        compiler
+            .add_reader(
+                "edited.proto",
+                "import 'unused.proto';\nmessage Edited {}\n".as_bytes(),
+            )
+            .unwrap();
+        assert_eq!(compiler.files().count(), 4);
+        assert_eq!(compiler.unused_imports().len(), 2);
+        assert!(compiler
+            .file_descriptor_set()
+            .file
+            .iter()
+            .any(|file| file.name() == "dep.proto"));
+
+        compiler.restore(&snapshot);
+        assert_eq!(compiler.files().count(), 3);
+        assert_eq!(compiler.unused_imports().len(), 1);
+        assert!(compiler
+            .descriptor_pool()
+            .get_message_by_name("Edited")
+            .is_none());
+        assert_eq!(
+            compiler
+                .file_descriptor_set()
+                .file
+                .iter()
+                .map(|file| file.name())
+                .collect::<Vec<_>>(),
+            ["root.proto"]
+        );
+    }
+}
+
+#[test]
+fn input_digests() {
+    let tempdir = TempDir::new().unwrap();
+    fs::write(
+        tempdir.path().join("root.proto"),
+        "import 'dep.proto'; message Root { optional Dep dep = 1; }",
+    )
+    .unwrap();
+    let dep = FileDescriptorProto {
+        name: Some("dep.proto".to_owned()),
+        message_type: vec![DescriptorProto {
+            name: Some("Dep".to_owned()),
+            ..Default::default()
+        }],
+        ..Default::default()
+    };
+
+    let mut compiler = Compiler::new([tempdir.path()]).unwrap();
+    compiler.add_file_descriptor_set(FileDescriptorSet {
+        file: vec![dep.clone()],
+    });
+    compiler.open_file("root.proto").unwrap();
+    let snapshot = compiler.snapshot();
+    compiler
+        .add_reader("other.proto", "message Dep {}".as_bytes())
+        .unwrap_err();
+
+    let digests = compiler.input_digests();
+    assert_eq!(
+        digests
+            .iter()
+            .map(|digest| (digest.name(), digest.path()))
+            .collect::<Vec<_>>(),
+        [
+            (
+                "root.proto",
+                Some(tempdir.path().join("root.proto").as_path())
+            ),
+            ("dep.proto", None),
+            ("other.proto", None),
+        ]
+    );
+    assert_eq!(
+        digests[0].digest(),
+        "sha256:a8604a40531b0a167fed4eb74d1d5ce49b69e86dc146ab01730119f821704521"
+    );
+    assert_eq!(
+        digests[2].digest(),
+        "sha256:cb10a160fb99b3201009ece626aacc596156fb8119d64499a14e81d89d10859c"
+    );
+    // Pre-compiled files are hashed by their encoded descriptor.
+    assert_ne!(digests[1].sha256(), digests[2].sha256());
+
+    compiler.restore(&snapshot);
+    assert_eq!(compiler.input_digests().len(), 2);
+}
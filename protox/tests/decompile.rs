@@ -0,0 +1,270 @@
+use std::{collections::BTreeMap, env, fs, path::PathBuf};
+
+use prost_reflect::FileDescriptor;
+use prost_types::{FileDescriptorProto, FileDescriptorSet};
+use protox::{
+    decompile,
+    file::{ChainFileResolver, DescriptorSetFileResolver, File, FileResolver, GoogleFileResolver},
+    Compiler, Error,
+};
+
+fn test_data_dir() -> PathBuf {
+    PathBuf::from(env::var_os("CARGO_MANIFEST_DIR").unwrap()).join("tests/data")
+}
+
+struct SourceResolver {
+    name: String,
+    source: String,
+}
+
+impl FileResolver for SourceResolver {
+    fn open_file(&self, name: &str) -> Result<File, Error> {
+        if name == self.name {
+            File::from_source(name, &self.source)
+        } else {
+            Err(Error::file_not_found(name))
+        }
+    }
+}
+
+type Comments = BTreeMap<Vec<i32>, (Option<String>, Option<String>, Vec<String>)>;
+
+fn comments(file: &FileDescriptorProto) -> Comments {
+    file.source_code_info
+        .iter()
+        .flat_map(|info| &info.location)
+        .filter(|location| {
+            location.leading_comments.is_some()
+                || location.trailing_comments.is_some()
+                || !location.leading_detached_comments.is_empty()
+        })
+        .map(|location| {
+            (
+                location.path.clone(),
+                (
+                    location.leading_comments.clone(),
+                    location.trailing_comments.clone(),
+                    location.leading_detached_comments.clone(),
+                ),
+            )
+        })
+        .collect()
+}
+
+fn without_source_info(mut file: FileDescriptorProto) -> FileDescriptorProto {
+    file.source_code_info = None;
+    file
+}
+
+/// Decompiles a file, and compiles the output again with the same imports.
+fn round_trip(file: &FileDescriptor) -> FileDescriptor {
+    let source = decompile(file);
+    let set = FileDescriptorSet {
+        file: file
+            .parent_pool()
+            .file_descriptor_protos()
+            .cloned()
+            .collect(),
+    };
+
+    let mut resolver = ChainFileResolver::new();
+    resolver.add(SourceResolver {
+        name: file.name().to_owned(),
+        source: source.clone(),
+    });
+    resolver.add(DescriptorSetFileResolver::new(set));
+
+    let mut compiler = Compiler::with_file_resolver(resolver);
+    compiler.include_source_info(true);
+    if let Err(err) = compiler.open_file(file.name()) {
+        panic!("failed to compile decompiled file: {:?}\n{}", err, source);
+    }
+
+    compiler
+        .descriptor_pool()
+        .get_file_by_name(file.name())
+        .unwrap()
+}
+
+fn compile(name: &str, source: &str) -> FileDescriptor {
+    let mut resolver = ChainFileResolver::new();
+    resolver.add(SourceResolver {
+        name: name.to_owned(),
+        source: source.to_owned(),
+    });
+    resolver.add(GoogleFileResolver::new());
+
+    let mut compiler = Compiler::with_file_resolver(resolver);
+    compiler.include_source_info(true);
+    compiler.open_file(name).unwrap();
+    compiler.descriptor_pool().get_file_by_name(name).unwrap()
+}
+
+/// Checks that a file compiles to an equivalent descriptor after decompiling it. Custom options are not preserved by
+/// [`FileDescriptorProto`], so they are compared by decompiling the result again.
+fn assert_round_trip(file: &FileDescriptor) {
+    let actual = round_trip(file);
+    similar_asserts::assert_eq!(
+        without_source_info(file.file_descriptor_proto().clone()),
+        without_source_info(actual.file_descriptor_proto().clone()),
+        "{}",
+        file.name()
+    );
+    similar_asserts::assert_eq!(
+        comments(file.file_descriptor_proto()),
+        comments(actual.file_descriptor_proto()),
+        "{}",
+        file.name()
+    );
+    similar_asserts::assert_eq!(decompile(file), decompile(&actual), "{}", file.name());
+}
+
+#[test]
+fn round_trip_test_data() {
+    let mut count = 0;
+    for entry in fs::read_dir(test_data_dir()).unwrap() {
+        let path = entry.unwrap().path();
+        if path.extension() != Some("proto".as_ref()) {
+            continue;
+        }
+        let name = path.file_name().unwrap().to_str().unwrap().to_owned();
+
+        let mut compiler = Compiler::new([test_data_dir()]).unwrap();
+        compiler.include_source_info(true);
+        if compiler.open_file(&name).is_err() {
+            continue;
+        }
+        let file = compiler.descriptor_pool().get_file_by_name(&name).unwrap();
+
+        assert_round_trip(&file);
+        count += 1;
+    }
+    assert!(count > 10);
+}
+
+#[test]
+fn round_trip_comments() {
+    let source = r#"// Detached comment.
+
+// Syntax comment.
+syntax = "proto2"; // Trailing syntax comment.
+
+package foo; // Package comment.
+
+import "google/protobuf/descriptor.proto";
+
+option java_package = "com.example";
+
+// Message comment.
+message Foo {
+  // Trailing message comment
+  // over two lines.
+
+  option deprecated = true;
+
+  /* Field comment. */
+  optional int32 a = 1 [default = -5, json_name = "A"];
+  oneof kind {
+    // Oneof field comment.
+    string b = 2;
+    group C = 3 {
+      optional bytes d = 1 [default = "\001\xff"];
+    }
+  }
+  map<string, Foo> e = 4;
+  message Nested {}
+  repeated Nested f = 5 [packed = false];
+
+  extensions 100 to 199, 1000 to max;
+  reserved 6, 8 to 10;
+  reserved "g";
+
+  extend Foo {
+    optional float h = 100 [default = inf];
+  }
+}
+
+enum Kind {
+  option allow_alias = true;
+  KIND_UNSPECIFIED = 0; // Enum value comment.
+  KIND_DEFAULT = 0 [deprecated = true];
+  reserved 2 to 3, 10 to max;
+  reserved "KIND_OLD";
+}
+
+extend google.protobuf.FileOptions {
+  optional Kind kind = 1000;
+}
+
+service Service {
+  option deprecated = true;
+  rpc Call(stream Foo) returns (Foo);
+  rpc Stream(Foo) returns (stream Foo) {
+    option idempotency_level = NO_SIDE_EFFECTS;
+  }
+}
+"#;
+
+    let file = compile("foo.proto", source);
+
+    let decompiled = decompile(&file);
+    assert!(decompiled.contains("// Trailing message comment\n  // over two lines.\n\n"));
+    assert!(decompiled.contains("KIND_UNSPECIFIED = 0; // Enum value comment.\n"));
+    assert!(decompiled.contains("    group C = 3 {\n"));
+    assert!(decompiled.contains("map<string, .foo.Foo> e = 4;"));
+
+    assert_round_trip(&file);
+}
+
+#[test]
+fn round_trip_custom_options() {
+    let source = r#"syntax = "proto3";
+
+package foo;
+
+import "google/protobuf/descriptor.proto";
+
+option (file_tag) = "file";
+
+message Rule {
+  int32 min = 1;
+  repeated string names = 2;
+}
+
+extend google.protobuf.FileOptions {
+  string file_tag = 1000;
+}
+
+extend google.protobuf.MessageOptions {
+  Rule rule = 1000;
+}
+
+extend google.protobuf.FieldOptions {
+  repeated int32 tags = 1000;
+}
+
+extend google.protobuf.EnumValueOptions {
+  bool hidden = 1000;
+}
+
+message Foo {
+  option (rule) = { min: 1 names: "a" names: "b" };
+
+  int32 a = 1 [deprecated = true, (tags) = 1, (tags) = 2];
+}
+
+enum Kind {
+  KIND_UNSPECIFIED = 0 [(hidden) = true];
+}
+"#;
+
+    let file = compile("foo.proto", source);
+
+    let decompiled = decompile(&file);
+    assert!(decompiled.contains("option (foo.file_tag) = \"file\";\n"));
+    assert!(decompiled.contains("option (foo.rule) = { min:1,names:[\"a\",\"b\"] };\n"));
+    assert!(decompiled.contains("a = 1 [deprecated = true, (foo.tags) = 1, (foo.tags) = 2];\n"));
+    assert!(decompiled.contains("KIND_UNSPECIFIED = 0 [(foo.hidden) = true];\n"));
+
+    assert_round_trip(&file);
+}
@@ -0,0 +1,47 @@
+use std::{env, fs, path::PathBuf};
+
+use protox::verify::compare_with_protoc;
+
+fn test_data_dir() -> PathBuf {
+    PathBuf::from(env::var_os("CARGO_MANIFEST_DIR").unwrap()).join("tests/data")
+}
+
+fn google_src_dir() -> PathBuf {
+    PathBuf::from(env::var_os("CARGO_MANIFEST_DIR").unwrap()).join("protobuf/src")
+}
+
+/// Runs every file in the test data directory through both protox and the `protoc` found by
+/// `prost-build`, and reports all differences between the outputs.
+///
+/// This requires a local installation of `protoc`, so it is not run by default.
+#[test]
+#[ignore]
+fn protoc_conformance() {
+    let mut names: Vec<String> = fs::read_dir(test_data_dir())
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+        .filter(|name| name.ends_with(".proto"))
+        // Files which are expected to fail compilation.
+        .filter(|name| name != "name_resolution_incorrect.proto")
+        .collect();
+    names.sort();
+
+    let mut failures = Vec::new();
+    for name in &names {
+        match compare_with_protoc(
+            prost_build::protoc_from_env(),
+            [test_data_dir().join(name)],
+            [test_data_dir(), google_src_dir()],
+        ) {
+            Ok(divergences) => failures.extend(divergences.iter().map(ToString::to_string)),
+            Err(err) => failures.push(format!("{}: {}", name, err)),
+        }
+    }
+
+    assert!(
+        failures.is_empty(),
+        "{} divergences from protoc:\n{}",
+        failures.len(),
+        failures.join("\n")
+    );
+}